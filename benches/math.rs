@@ -0,0 +1,55 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use oxide_engine::types::{
+    geometry::{Aabb, Frustum},
+    matrices::Matrix4f,
+    quaternion::Quat,
+    vectors::{Vec3f, Vec4f},
+};
+
+// about what a scene with a few thousand entities does every frame
+const COUNT: usize = 4096;
+
+fn matrices() -> Vec<Matrix4f> {
+    (0..COUNT)
+        .map(|i| {
+            let i = i as f32;
+            Matrix4f::translation(Vec3f::new([i, -i, i * 0.5]))
+                * Quat::from_axis_angle(Vec3f::new([1.0, 2.0, 3.0]), i * 0.01).to_matrix()
+                * Matrix4f::scale(Vec3f::new([1.0 + i * 0.001, 1.0, 2.0]))
+        })
+        .collect()
+}
+
+fn matrix_multiply(c: &mut Criterion) {
+    let models = matrices();
+    let view_projection = Matrix4f::perspective(1.0, 16.0 / 9.0, 0.1) * Matrix4f::translation(Vec3f::new([0.0, -2.0, -10.0]));
+    c.bench_function("matrix multiply", |b| {
+        b.iter(|| models.iter().map(|x| black_box(view_projection) * *x).fold(0.0, |sum, x| sum + x.0[3][3]))
+    });
+    c.bench_function("matrix vec4", |b| {
+        b.iter(|| models.iter().map(|x| *x * black_box(Vec4f::new([1.0, 2.0, 3.0, 1.0]))).fold(0.0, |sum, x| sum + x.w))
+    });
+}
+
+fn quaternion_rotation(c: &mut Criterion) {
+    let rotations: Vec<Quat> = (0..COUNT).map(|i| Quat::from_axis_angle(Vec3f::new([0.0, 1.0, 1.0]), i as f32 * 0.01)).collect();
+    c.bench_function("quaternion rotate vec3", |b| {
+        b.iter(|| rotations.iter().map(|x| black_box(Vec3f::new([1.0, 0.0, 0.0])) * *x).fold(0.0, |sum, x| sum + x.z))
+    });
+}
+
+fn frustum_culling(c: &mut Criterion) {
+    let frustum = Frustum::from_matrix(&Matrix4f::perspective_finite(1.0, 16.0 / 9.0, 0.1, 500.0));
+    let boxes: Vec<Aabb> = (0..COUNT)
+        .map(|i| {
+            let center = Vec3f::new([(i % 64) as f32 * 4.0 - 128.0, 0.0, -((i / 64) as f32) * 4.0]);
+            Aabb::new(center - 0.5, center + 0.5)
+        })
+        .collect();
+    c.bench_function("frustum aabb", |b| b.iter(|| boxes.iter().filter(|x| black_box(&frustum).intersects_aabb(x)).count()));
+}
+
+criterion_group!(benches, matrix_multiply, quaternion_rotation, frustum_culling);
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+use oxide_engine::{
+    asset_descriptions::{AssetDescriptions, MaterialDescription, ModelDescription, ShaderDescription},
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    engine_error::EngineError,
+    run,
+    state::State,
+    types::{
+        camera::{Camera, ProjectionKind},
+        material::RenderingType,
+        model::ModelComponent,
+        position::Position,
+        quaternion::Quat,
+        shader::ShaderType,
+        transform::Transform,
+        vectors::{Vec3d, Vec3f, Vec3i},
+    },
+};
+
+const MODEL_NAME: &str = "monkey.gltf";
+const MATERIAL_NAME: &str = "debug_normals";
+
+// draws the model's normals by swapping every mesh to the debug material
+struct DebugNormalsSystem {}
+
+impl System for DebugNormalsSystem {
+    fn on_start(&self, _world: &World, assets: &mut AssetLibrary, _state: &mut State) {
+        let material = match assets.materials.iter().find(|(_, v)| v.name == MATERIAL_NAME) {
+            Some((uuid, _)) => *uuid,
+            None => return,
+        };
+        if let Some(model) = assets.models.values_mut().find(|v| v.name == MODEL_NAME) {
+            model.visit_primitives_mut(|(_, x)| *x = material);
+        }
+    }
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+}
+
+fn main() -> Result<(), EngineError> {
+    let mut world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::new(Vec3i::new([0, 0, 0]), Vec3d::new([0.0, 0.0, -3.0])),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.entities.borrow_mut().spawn((
+        ModelComponent::new(MODEL_NAME),
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.add_system(DebugNormalsSystem {});
+
+    run(
+        world,
+        AssetDescriptions {
+            shaders: vec![
+                ShaderDescription {
+                    name: "perspective".to_string(),
+                    shader_type: ShaderType::Vertex,
+                    source: None,
+                },
+                ShaderDescription {
+                    name: "debug_normals".to_string(),
+                    shader_type: ShaderType::Geometry,
+                    source: Some("debug_normals.geom".to_string()),
+                },
+                ShaderDescription {
+                    name: "vertex_color".to_string(),
+                    shader_type: ShaderType::Fragment,
+                    source: None,
+                },
+            ],
+            textures: vec![],
+            models: vec![ModelDescription {
+                name: MODEL_NAME.to_string(),
+                optimize: true,
+                lod_distances: vec![],
+            }],
+            materials: vec![MaterialDescription {
+                name: MATERIAL_NAME.to_string(),
+                vertex: "perspective".to_string(),
+                fragment: "vertex_color".to_string(),
+                attachments: vec![],
+                paramaters: None,
+                rendering_type: RenderingType::Fill,
+                transparent: false,
+                depth: None,
+                geometry: Some("debug_normals".to_string()),
+                tessellation: None,
+            }],
+            ui_elements: vec![],
+            fonts: vec![],
+        },
+    )
+}
@@ -0,0 +1,182 @@
+use oxide_engine::{
+    asset_descriptions::{
+        AssetDescriptions, AttachmentDescription, FontDescription, MaterialDescription, ModelDescription,
+        ShaderDescription, UiElementDescription,
+    },
+    asset_library::AssetLibrary,
+    asset_loading::AssetEvent,
+    ecs::{System, World},
+    engine_error::EngineError,
+    run,
+    state::State,
+    types::{
+        camera::{Camera, ProjectionKind},
+        material::{MaterialParameters, RenderingType},
+        model::ModelComponent,
+        position::Position,
+        quaternion::Quat,
+        shader::ShaderType,
+        transform::Transform,
+        vectors::{Vec2f, Vec3f, Vec4f},
+    },
+    ui::{
+        ui_layout::{Anchor, UiElementType, UiLayoutKind, UiSpriteMode},
+        ui_text::UiText,
+    },
+};
+
+const MODEL_NAME: &str = "sponza.gltf";
+const LOADING_BAR: &str = "loading_bar";
+const LOADING_TEXT: &str = "loading_text";
+const FONT_NAME: &str = "roboto.ttf";
+const BAR_WIDTH: f32 = 1.6;
+const BAR_HEIGHT: f32 = 0.05;
+
+struct LoadingScreen {}
+
+impl System for LoadingScreen {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let progress = state.asset_loading_progress();
+        let ready = state.asset_loading.events().contains(&AssetEvent::AssetsReady);
+        if let Some(text) = assets.ui.values_mut().find(|x| x.name == LOADING_TEXT) {
+            match ready {
+                true => text.set_text(""),
+                false => text.set_text(&format!("Loading {}%", (progress * 100.0) as u32)),
+            }
+        }
+
+        let Some(bar) = assets.ui.values_mut().find(|x| x.name == LOADING_BAR) else {
+            return;
+        };
+
+        if ready {
+            bar.set_size(0.0, 0.0);
+        } else if !state.asset_loading.is_ready() {
+            bar.set_size(BAR_WIDTH * progress, BAR_HEIGHT);
+        }
+    }
+}
+
+fn main() -> Result<(), EngineError> {
+    let mut world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.entities.borrow_mut().spawn((
+        ModelComponent::new(MODEL_NAME),
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.add_system(LoadingScreen {});
+    // F3 shows frame times and counts
+    #[cfg(feature = "dev_tools")]
+    world.add_system(oxide_engine::diagnostics::DiagnosticsOverlay::new(FONT_NAME, LOADING_TEXT));
+
+    run(
+        world,
+        AssetDescriptions {
+            shaders: vec![
+                ShaderDescription {
+                    name: "ui_vertex".to_string(),
+                    shader_type: ShaderType::UiVertex,
+                    source: None,
+                },
+                ShaderDescription {
+                    name: "ui_fragment".to_string(),
+                    shader_type: ShaderType::UiFragment,
+                    source: None,
+                },
+            ],
+            textures: vec![],
+            models: vec![ModelDescription {
+                name: MODEL_NAME.to_string(),
+                optimize: true,
+                lod_distances: vec![],
+            }],
+            materials: vec![
+                MaterialDescription {
+                    name: LOADING_BAR.to_string(),
+                    vertex: "ui_vertex".to_string(),
+                    fragment: "ui_fragment".to_string(),
+                    attachments: vec![],
+                    paramaters: Some(MaterialParameters {
+                        diffuse_color: Vec3f::new([0.9, 0.9, 0.9]).into(),
+                        ..Default::default()
+                    }),
+                    rendering_type: RenderingType::Fill,
+                    transparent: false,
+                    depth: None,
+                    geometry: None,
+                    tessellation: None,
+                },
+                MaterialDescription {
+                    name: LOADING_TEXT.to_string(),
+                    vertex: "ui_vertex".to_string(),
+                    fragment: "ui_fragment".to_string(),
+                    attachments: vec![AttachmentDescription::Texture(FONT_NAME.to_string())],
+                    paramaters: None,
+                    rendering_type: RenderingType::Fill,
+                    transparent: true,
+                    depth: None,
+                    geometry: None,
+                    tessellation: None,
+                },
+            ],
+            ui_elements: vec![
+                UiElementDescription {
+                    element_type: UiElementType::None,
+                    name: LOADING_BAR.to_string(),
+                    material: LOADING_BAR.to_string(),
+                    position: Vec2f::new([0.0, 0.0]),
+                    screen_anchor: Anchor::Center,
+                    width: 0.0,
+                    height: BAR_HEIGHT,
+                    anchors: None,
+                    layout: UiLayoutKind::Free,
+                    sprite_mode: UiSpriteMode::Stretch,
+                    focusable: false,
+                    children: vec![],
+                },
+                UiElementDescription {
+                    element_type: UiElementType::Text(UiText {
+                        content: "Loading".to_string(),
+                        font: FONT_NAME.to_string(),
+                        size: 0.06,
+                        color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+                    }),
+                    name: LOADING_TEXT.to_string(),
+                    material: LOADING_TEXT.to_string(),
+                    position: Vec2f::new([0.0, -0.1]),
+                    screen_anchor: Anchor::Center,
+                    width: BAR_WIDTH,
+                    height: 0.1,
+                    anchors: None,
+                    layout: UiLayoutKind::Free,
+                    sprite_mode: UiSpriteMode::Stretch,
+                    focusable: false,
+                    children: vec![],
+                },
+            ],
+            fonts: vec![FontDescription {
+                name: FONT_NAME.to_string(),
+                size: 48.0,
+                characters: None,
+            }],
+        },
+    )
+}
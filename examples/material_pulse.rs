@@ -0,0 +1,66 @@
+use oxide_engine::{
+    asset_descriptions::AssetDescriptions,
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    engine_error::EngineError,
+    run,
+    state::State,
+    types::{
+        camera::{Camera, ProjectionKind},
+        material::MaterialParameters,
+        position::Position,
+        quaternion::Quat,
+        transform::Transform,
+        vectors::Vec3f,
+    },
+};
+
+const MATERIAL_NAME: &str = "pulse";
+
+struct PulseSystem {}
+
+impl System for PulseSystem {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let brightness = (state.time_struct.elapsed.sin() * 0.5 + 0.5) as f32;
+        let parameters = MaterialParameters {
+            diffuse_color: Vec3f::new([brightness, 0.2, 1.0 - brightness]).into(),
+            ..Default::default()
+        };
+
+        if let Some(material) = assets.material_mut_by_name(MATERIAL_NAME) {
+            material.set_parameters(state, parameters);
+        }
+    }
+}
+
+fn main() -> Result<(), EngineError> {
+    let mut world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.add_system(PulseSystem {});
+
+    run(
+        world,
+        AssetDescriptions {
+            shaders: vec![],
+            textures: vec![],
+            models: vec![],
+            materials: vec![],
+            ui_elements: vec![],
+            fonts: vec![],
+        },
+    )
+}
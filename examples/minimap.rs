@@ -0,0 +1,100 @@
+use std::cell::Cell;
+
+use oxide_engine::{
+    asset_descriptions::AssetDescriptions,
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    engine_error::EngineError,
+    run,
+    state::State,
+    types::{
+        camera::{Camera, ProjectionKind},
+        position::Position,
+        quaternion::Quat,
+        texture::Texture,
+        transform::Transform,
+        vectors::Vec3f,
+    },
+    uuid::Uuid,
+};
+
+const MINIMAP_SIZE: u32 = 256;
+const REDRAW_INTERVAL: u32 = 30;
+
+fn draw_minimap(world: &World) -> Vec<u8> {
+    let mut pixels = vec![0; (MINIMAP_SIZE * MINIMAP_SIZE * 4) as usize];
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[20, 40, 20, 255]);
+    }
+
+    let entities = world.entities.borrow();
+    for (_, transform) in entities.query::<&Transform>().iter() {
+        let position: Vec3f = (transform.position - Position::default()).into();
+        let x = (position.x + MINIMAP_SIZE as f32 / 2.0) as i64;
+        let y = (position.z + MINIMAP_SIZE as f32 / 2.0) as i64;
+        if (0..MINIMAP_SIZE as i64).contains(&x) && (0..MINIMAP_SIZE as i64).contains(&y) {
+            let id = ((y as u32 * MINIMAP_SIZE + x as u32) * 4) as usize;
+            pixels[id..id + 4].copy_from_slice(&[255, 255, 255, 255]);
+        }
+    }
+
+    pixels
+}
+
+struct MinimapSystem {
+    texture: Uuid,
+    frame: Cell<u32>,
+}
+
+impl System for MinimapSystem {
+    fn on_start(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let mut texture = Texture::from_rgba8("minimap", MINIMAP_SIZE, MINIMAP_SIZE, draw_minimap(world));
+        texture.load(state);
+        assets.textures.insert(self.texture, texture);
+    }
+
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        self.frame.set(self.frame.get() + 1);
+        if !self.frame.get().is_multiple_of(REDRAW_INTERVAL) {
+            return;
+        }
+
+        let pixels = draw_minimap(world);
+        if let Some(texture) = assets.textures.get_mut(&self.texture) {
+            texture.update_region(state, 0, 0, MINIMAP_SIZE, MINIMAP_SIZE, &pixels);
+        }
+    }
+}
+
+fn main() -> Result<(), EngineError> {
+    let mut world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.add_system(MinimapSystem {
+        texture: Uuid::new_v4(),
+        frame: Cell::new(0),
+    });
+
+    run(
+        world,
+        AssetDescriptions {
+            shaders: vec![],
+            textures: vec![],
+            models: vec![],
+            materials: vec![],
+            ui_elements: vec![],
+            fonts: vec![],
+        },
+    )
+}
@@ -0,0 +1,114 @@
+use oxide_engine::{
+    asset_descriptions::{
+        AssetDescriptions, AttachmentDescription, MaterialDescription, ShaderDescription, TextureDescription,
+        UiElementDescription,
+    },
+    ecs::World,
+    engine_error::EngineError,
+    run,
+    types::{
+        camera::{Camera, ProjectionKind},
+        material::RenderingType,
+        position::Position,
+        quaternion::Quat,
+        shader::ShaderType,
+        texture::TextureKind,
+        transform::Transform,
+        vectors::{Vec2f, Vec3f},
+    },
+    ui::ui_layout::{Anchor, UiElementType, UiLayoutKind, UiSpriteMode},
+};
+
+// a 64x64 frame with 16 pixel borders
+const PANEL_TEXTURE: &str = "panel.png";
+const PANEL_MATERIAL: &str = "panel";
+const BORDER: f32 = 16.0;
+
+// the same sliced texture on a wide, a tall and a panel too small for its borders,
+// next to a stretched one for comparison
+fn panel(name: &str, position: [f32; 2], width: f32, height: f32, sprite_mode: UiSpriteMode) -> UiElementDescription {
+    UiElementDescription {
+        element_type: UiElementType::None,
+        name: name.to_string(),
+        material: PANEL_MATERIAL.to_string(),
+        position: Vec2f::new(position),
+        screen_anchor: Anchor::Center,
+        width,
+        height,
+        anchors: None,
+        layout: UiLayoutKind::Free,
+        sprite_mode,
+        focusable: false,
+        children: vec![],
+    }
+}
+
+fn main() -> Result<(), EngineError> {
+    let world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+
+    let sliced = UiSpriteMode::NineSlice {
+        left: BORDER,
+        right: BORDER,
+        top: BORDER,
+        bottom: BORDER,
+    };
+
+    run(
+        world,
+        AssetDescriptions {
+            shaders: vec![
+                ShaderDescription {
+                    name: "ui_vertex".to_string(),
+                    shader_type: ShaderType::UiVertex,
+                    source: None,
+                },
+                ShaderDescription {
+                    name: "ui_fragment".to_string(),
+                    shader_type: ShaderType::UiFragment,
+                    source: None,
+                },
+            ],
+            textures: vec![TextureDescription {
+                name: PANEL_TEXTURE.to_string(),
+                linear: false,
+                generate_mips: false,
+                kind: TextureKind::D2,
+                faces: vec![],
+                storage: None,
+            }],
+            models: vec![],
+            materials: vec![MaterialDescription {
+                name: PANEL_MATERIAL.to_string(),
+                vertex: "ui_vertex".to_string(),
+                fragment: "ui_fragment".to_string(),
+                attachments: vec![AttachmentDescription::Texture(PANEL_TEXTURE.to_string())],
+                paramaters: None,
+                rendering_type: RenderingType::Fill,
+                transparent: true,
+                depth: None,
+                geometry: None,
+                tessellation: None,
+            }],
+            ui_elements: vec![
+                panel("wide", [-0.4, 0.4], 0.9, 0.2, sliced),
+                panel("tall", [0.6, 0.0], 0.2, 0.8, sliced),
+                panel("tiny", [-0.4, 0.0], 0.01, 0.01, sliced),
+                panel("stretched", [-0.4, -0.4], 0.9, 0.2, UiSpriteMode::Stretch),
+            ],
+            fonts: vec![],
+        },
+    )
+}
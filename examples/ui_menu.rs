@@ -0,0 +1,160 @@
+use oxide_engine::{
+    asset_descriptions::{AssetDescriptions, MaterialDescription, ShaderDescription, UiElementDescription},
+    asset_library::AssetLibrary,
+    ecs::{Callback, World},
+    engine_error::EngineError,
+    log::info,
+    run,
+    state::State,
+    types::{
+        camera::{Camera, ProjectionKind},
+        material::{MaterialParameters, RenderingType},
+        position::Position,
+        quaternion::Quat,
+        shader::ShaderType,
+        transform::Transform,
+        vectors::{Vec2f, Vec3f},
+    },
+    ui::ui_layout::{Anchor, UiElement, UiElementType, UiLayoutKind, UiSpriteMode},
+    uuid::Uuid,
+};
+
+const BUTTON_MATERIAL: &str = "button";
+const MENU_PANEL: &str = "menu_panel";
+
+// spawns a panel stacking two item buttons and a close button, once
+struct OpenMenu {
+    item: Uuid,
+    close: Uuid,
+}
+
+impl Callback for OpenMenu {
+    fn action(&self, _world: &World, assets: &mut AssetLibrary, _state: &mut State) {
+        if assets.ui.values().any(|x| x.name == MENU_PANEL) {
+            return;
+        }
+        let Some((material, _)) = assets.material_by_name(BUTTON_MATERIAL) else {
+            return;
+        };
+
+        let mut panel = UiElement::new(
+            MENU_PANEL,
+            UiElementType::None,
+            material,
+            Anchor::Center,
+            Vec2f::new([0.0, 0.0]),
+            0.4,
+            0.5,
+        );
+        panel.layout = UiLayoutKind::VerticalStack { spacing: 0.05 };
+        let panel = assets.ui_add(panel);
+
+        for (name, callback) in [("item_a", self.item), ("item_b", self.item), ("close", self.close)] {
+            let mut button = UiElement::new(
+                name,
+                UiElementType::Button(callback),
+                material,
+                Anchor::Center,
+                Vec2f::new([0.0, 0.0]),
+                0.4,
+                0.1,
+            );
+            button.parent = Some(panel);
+            button.focusable = true;
+            assets.ui_add(button);
+        }
+    }
+}
+
+struct CloseMenu {}
+
+impl Callback for CloseMenu {
+    fn action(&self, _world: &World, assets: &mut AssetLibrary, _state: &mut State) {
+        let panel = assets
+            .ui
+            .iter()
+            .find(|(_, x)| x.name == MENU_PANEL)
+            .map(|(uuid, _)| *uuid);
+        if let Some(panel) = panel {
+            assets.ui_remove(panel);
+        }
+    }
+}
+
+struct Item {}
+
+impl Callback for Item {
+    fn action(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {
+        info!("Item picked");
+    }
+}
+
+fn main() -> Result<(), EngineError> {
+    let mut world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    let item = world.add_callback(Item {});
+    let close = world.add_callback(CloseMenu {});
+    let open = world.add_callback(OpenMenu { item, close });
+
+    run(
+        world,
+        AssetDescriptions {
+            shaders: vec![
+                ShaderDescription {
+                    name: "ui_vertex".to_string(),
+                    shader_type: ShaderType::UiVertex,
+                    source: None,
+                },
+                ShaderDescription {
+                    name: "ui_fragment".to_string(),
+                    shader_type: ShaderType::UiFragment,
+                    source: None,
+                },
+            ],
+            textures: vec![],
+            models: vec![],
+            materials: vec![MaterialDescription {
+                name: BUTTON_MATERIAL.to_string(),
+                vertex: "ui_vertex".to_string(),
+                fragment: "ui_fragment".to_string(),
+                attachments: vec![],
+                paramaters: Some(MaterialParameters {
+                    diffuse_color: Vec3f::new([0.3, 0.3, 0.35]).into(),
+                    ..Default::default()
+                }),
+                rendering_type: RenderingType::Fill,
+                transparent: false,
+                depth: None,
+                geometry: None,
+                tessellation: None,
+            }],
+            ui_elements: vec![UiElementDescription {
+                element_type: UiElementType::Button(open),
+                name: "menu".to_string(),
+                material: BUTTON_MATERIAL.to_string(),
+                position: Vec2f::new([0.15, -0.1]),
+                screen_anchor: Anchor::UpLeft,
+                width: 0.2,
+                height: 0.1,
+                anchors: None,
+                layout: UiLayoutKind::Free,
+                sprite_mode: UiSpriteMode::Stretch,
+                focusable: true,
+                children: vec![],
+            }],
+            fonts: vec![],
+        },
+    )
+}
@@ -0,0 +1,72 @@
+use oxide_engine::{
+    asset_descriptions::AssetDescriptions,
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    engine_config::{AssetPaths, EngineConfig, FullscreenMode},
+    engine_error::EngineError,
+    run_with_config,
+    state::State,
+    types::{
+        camera::{Camera, ProjectionKind},
+        position::Position,
+        quaternion::Quat,
+        transform::Transform,
+        vectors::Vec3f,
+    },
+};
+use winit::keyboard::{Key, NamedKey};
+
+// alt+enter switches between a window and borderless fullscreen on the monitor the window is on
+struct FullscreenToggle {}
+
+impl System for FullscreenToggle {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let alt = state.input.key_down.contains(&Key::Named(NamedKey::Alt));
+        if alt && state.input.key_pressed.contains(&Key::Named(NamedKey::Enter)) {
+            let mode = match state.window.fullscreen_mode() {
+                FullscreenMode::Windowed => FullscreenMode::Borderless(None),
+                _ => FullscreenMode::Windowed,
+            };
+            state.set_fullscreen(mode);
+        }
+    }
+}
+
+fn main() -> Result<(), EngineError> {
+    let mut world = World::new();
+    world.entities.borrow_mut().spawn((
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport: None,
+            active: true,
+            priority: 0,
+        },
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ),
+    ));
+    world.add_system(FullscreenToggle {});
+
+    run_with_config(
+        world,
+        AssetDescriptions {
+            shaders: vec![],
+            textures: vec![],
+            models: vec![],
+            materials: vec![],
+            ui_elements: vec![],
+            fonts: vec![],
+        },
+        EngineConfig {
+            window_title: String::from("Window config"),
+            initial_size: Some((1600, 900)),
+            resizable: false,
+            asset_paths: AssetPaths { packs: vec!["window_config.data".into()], ..Default::default() },
+            ..Default::default()
+        },
+    )
+}
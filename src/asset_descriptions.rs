@@ -1,29 +1,63 @@
 use std::collections::HashMap;
 
-use crate::{asset_library::AssetLibrary, types::{material::{Attachment, Material, MaterialParameters, RenderingType}, model::Model, shader::{Shader, ShaderType}, texture::Texture, vectors::Vec2f}, ui::ui_layout::{Anchor, UiElement, UiElementType}};
+use crate::{asset_library::AssetLibrary, engine_config::AssetPaths, engine_error::EngineError, types::{font::{default_font_size, Font, DEFAULT_CHARACTERS}, material::{Attachment, DepthSettings, Material, MaterialParameters, RenderingType}, model::{default_optimize, Model}, shader::{Shader, ShaderType}, texture::{default_generate_mips, Texture, TextureKind}, vectors::Vec2f}, ui::ui_layout::{Anchor, UiAnchors, UiElement, UiElementType, UiLayoutKind, UiSpriteMode}};
+use log::error;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShaderDescription {
     pub name: String,
-    pub shader_type: ShaderType
+    pub shader_type: ShaderType,
+    // glsl source in AssetPaths::shaders, compiled when building the pack with dev_tools
+    #[serde(default)]
+    pub source: Option<String>
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TextureDescription {
     pub name: String,
+    #[serde(default)]
+    pub linear: bool,
+    #[serde(default = "default_generate_mips")]
+    pub generate_mips: bool,
+    #[serde(default)]
+    pub kind: TextureKind,
+    // cubemap face images, a single entry is read as a cross or strip layout
+    #[serde(default)]
+    pub faces: Vec<String>,
+    // width and height of a storage texture for compute shaders to write, no file is read for it
+    #[serde(default)]
+    pub storage: Option<(u32, u32)>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModelDescription {
     pub name: String,
+    #[serde(default = "default_optimize")]
+    pub optimize: bool,
+    // where LOD1, LOD2, ... of nodes named like Rock_LOD1 start
+    #[serde(default)]
+    pub lod_distances: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FontDescription {
+    // ttf or otf file in AssetPaths::fonts, the baked atlas becomes a texture of the same name
+    pub name: String,
+    // pixel size the glyphs are rasterized at
+    #[serde(default = "default_font_size")]
+    pub size: f32,
+    // characters to bake, printable ascii and latin-1 when not set
+    #[serde(default)]
+    pub characters: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum AttachmentDescription {
     Texture(String),
     DefaultTexture,
+    CubeTexture(String),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,7 +67,16 @@ pub struct MaterialDescription {
     pub fragment: String,
     pub attachments: Vec<AttachmentDescription>,
     pub paramaters: Option<MaterialParameters>,
-    pub rendering_type: RenderingType
+    pub rendering_type: RenderingType,
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default)]
+    pub depth: Option<DepthSettings>,
+    #[serde(default)]
+    pub geometry: Option<String>,
+    // control and evaluation shader names
+    #[serde(default)]
+    pub tessellation: Option<(String, String)>
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,6 +88,18 @@ pub struct UiElementDescription {
     pub screen_anchor: Anchor,
     pub width: f32,
     pub height: f32,
+    // replaces screen_anchor, position, width and height when set
+    #[serde(default)]
+    pub anchors: Option<UiAnchors>,
+    #[serde(default)]
+    pub layout: UiLayoutKind,
+    #[serde(default)]
+    pub sprite_mode: UiSpriteMode,
+    #[serde(default)]
+    pub focusable: bool,
+    // positioned inside this element's rect
+    #[serde(default)]
+    pub children: Vec<UiElementDescription>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,23 +108,121 @@ pub struct AssetDescriptions {
     pub textures: Vec<TextureDescription>,
     pub models: Vec<ModelDescription>,
     pub materials: Vec<MaterialDescription>,
-    pub ui_elements: Vec<UiElementDescription>
+    pub ui_elements: Vec<UiElementDescription>,
+    #[serde(default)]
+    pub fonts: Vec<FontDescription>
+}
+
+#[cfg(feature = "dev_tools")]
+fn load_shader(description: &ShaderDescription, paths: &AssetPaths) -> Result<Shader, EngineError> {
+    match description.source.as_ref() {
+        Some(source) => match crate::types::shader_compiler::compile_glsl(source, paths) {
+            Ok(words) => Ok(Shader::from_words(description.name.clone(), description.shader_type, words)),
+            Err(e) => Err(EngineError::ShaderCompile { name: description.name.clone(), message: e.to_string() }),
+        },
+        None => Shader::new(description.name.clone(), description.shader_type, paths)
+    }
+}
+
+#[cfg(not(feature = "dev_tools"))]
+fn load_shader(description: &ShaderDescription, paths: &AssetPaths) -> Result<Shader, EngineError> {
+    if description.source.is_some() {
+        log::warn!("GLSL sources need the dev_tools feature, loading {} from spir-v", description.name);
+    }
+    Shader::new(description.name.clone(), description.shader_type, paths)
+}
+
+fn resolve_attachment(description: &AttachmentDescription, textures: &HashMap<Uuid, Texture>) -> Attachment {
+    match description {
+        AttachmentDescription::Texture(name) => match textures.iter().find(|(_, v)| v.name == *name) {
+            Some((texture_uuid, _)) => Attachment::Texture(*texture_uuid),
+            None => {
+                error!("Texture {} not found, using the default texture", name);
+                Attachment::DefaultTexture
+            }
+        },
+        AttachmentDescription::DefaultTexture => Attachment::DefaultTexture,
+        AttachmentDescription::CubeTexture(name) => {
+            let texture_uuid = *textures.iter().find(|(_, v)| v.name == *name && v.kind == TextureKind::Cube)
+                .expect("Cube texture not found").0;
+            Attachment::Texture(texture_uuid)
+        }
+    }
+}
+
+fn insert_ui_element(
+    description: &UiElementDescription,
+    parent: Option<Uuid>,
+    materials: &HashMap<Uuid, Material>,
+    ui: &mut HashMap<Uuid, UiElement>
+) -> Uuid {
+    let material_uuid = materials.iter().find(|(_, material)| material.name == description.material)
+        .expect("Material not found").0;
+    let uuid = Uuid::new_v4();
+    let anchors = description.anchors.unwrap_or_else(|| {
+        UiAnchors::from_anchor(description.screen_anchor, description.position, description.width, description.height)
+    });
+    let mut element = UiElement::with_anchors(&description.name, description.element_type.clone(), *material_uuid, anchors);
+    element.layout = description.layout;
+    element.sprite_mode = description.sprite_mode;
+    element.focusable = description.focusable;
+    element.parent = parent;
+    element.children = description.children.iter().map(|x| insert_ui_element(x, Some(uuid), materials, ui)).collect();
+    ui.insert(uuid, element);
+    uuid
 }
 
 impl AssetDescriptions {
-    pub fn generate_library(&self) -> AssetLibrary {
+    // fails on shaders that can't be read or compiled, other missing assets are logged and skipped
+    pub fn generate_library(&self, paths: &AssetPaths) -> Result<AssetLibrary, EngineError> {
         let shaders: HashMap<Uuid, Shader> = {
             let mut map = HashMap::new();
             for shader_description in self.shaders.iter() {
-                map.insert(Uuid::new_v4(), Shader::new(shader_description.name.clone(), shader_description.shader_type));
+                map.insert(Uuid::new_v4(), load_shader(shader_description, paths)?);
             }
             map
         };
         
+        let mut fonts: HashMap<Uuid, Font> = HashMap::new();
+        let mut font_atlases: Vec<Texture> = Vec::new();
+        for font_description in self.fonts.iter() {
+            let characters = font_description.characters.as_deref().unwrap_or(DEFAULT_CHARACTERS);
+            match Font::bake(font_description.name.clone(), font_description.size, characters, paths) {
+                Ok((font, atlas)) => {
+                    fonts.insert(Uuid::new_v4(), font);
+                    font_atlases.push(atlas);
+                },
+                Err(e) => error!("{}", e),
+            }
+        }
+
         let textures: HashMap<Uuid, Texture> = {
             let mut map = HashMap::new();
+            for atlas in font_atlases {
+                map.insert(Uuid::new_v4(), atlas);
+            }
             for texture_description in self.textures.iter() {
-                map.insert(Uuid::new_v4(), Texture::new(texture_description.name.clone()));
+                if let Some((width, height)) = texture_description.storage {
+                    map.insert(Uuid::new_v4(), Texture::new_storage(&texture_description.name, width, height));
+                    continue;
+                }
+                let texture = match texture_description.kind {
+                    TextureKind::D2 => match Texture::new(texture_description.name.clone(), !texture_description.linear, texture_description.generate_mips, paths) {
+                        Ok(val) => val,
+                        Err(e) => {
+                            error!("{}", e);
+                            continue;
+                        }
+                    },
+                    TextureKind::Cube => Texture::new_cube(
+                        texture_description.name.clone(),
+                        &texture_description.faces,
+                        !texture_description.linear,
+                        texture_description.generate_mips,
+                        paths
+                    ).unwrap_or_else(|e| panic!("Invalid cubemap {}: {}", texture_description.name, e))
+                };
+                map.insert(Uuid::new_v4(), texture);
             }
             map
         };
@@ -77,7 +230,10 @@ impl AssetDescriptions {
         let models: HashMap<Uuid, Model> = {
             let mut map = HashMap::new();
             for model_description in self.models.iter() {
-                map.insert(Uuid::new_v4(), Model::new(model_description.name.clone()));
+                let mut model = Model::new(model_description.name.clone());
+                model.optimize = model_description.optimize;
+                model.lod_distances.clone_from(&model_description.lod_distances);
+                map.insert(Uuid::new_v4(), model);
             }
             map
         };
@@ -85,27 +241,31 @@ impl AssetDescriptions {
         let materials: HashMap<Uuid, Material> = {
             let mut map = HashMap::new();
             for material_description in self.materials.iter() {
-                let vertex_uuid = shaders.iter().find(|(_, shader)| shader.name == material_description.vertex)
-                    .expect("Vertex shader not found").0;
+                let (vertex_uuid, vertex_shader) = shaders.iter().find(|(_, shader)| shader.name == material_description.vertex)
+                    .expect("Vertex shader not found");
+                let depth = match material_description.depth {
+                    Some(val) => val,
+                    None if matches!(vertex_shader.shader_type, ShaderType::UiVertex) => DepthSettings::ui(),
+                    None => DepthSettings::default()
+                };
                 let fragment_uuid = shaders.iter().find(|(_, shader)| shader.name == material_description.fragment)
                     .expect("Vertex shader not found").0;
-                map.insert(Uuid::new_v4(), Material::new(
+                let find_shader = |name: &String| *shaders.iter().find(|(_, shader)| shader.name == *name)
+                    .unwrap_or_else(|| panic!("Shader {} not found", name)).0;
+                let mut material = Material::new(
                     material_description.name.clone(), 
                     *vertex_uuid, 
                     *fragment_uuid, 
-                    material_description.attachments.iter().map(|x|
-                        match x {
-                            AttachmentDescription::Texture(name) => {
-                                let texture_uuid = *textures.iter().find(|(_, v)| v.name == *name).expect("Textre not found").0;
-                                Attachment::Texture(texture_uuid)
-                            },
-                            AttachmentDescription::DefaultTexture => Attachment::DefaultTexture
-                        }
-                    ).collect(),
+                    material_description.attachments.iter().map(|x| resolve_attachment(x, &textures)).collect(),
                     material_description.paramaters.clone(),
-                    material_description.rendering_type
-                )
+                    material_description.rendering_type,
+                    material_description.transparent,
+                    depth
                 );
+                material.geometry_shader = material_description.geometry.as_ref().map(find_shader);
+                material.tessellation_shaders = material_description.tessellation.as_ref()
+                    .map(|(control, evaluation)| (find_shader(control), find_shader(evaluation)));
+                map.insert(Uuid::new_v4(), material);
             }
             map
         };
@@ -113,23 +273,89 @@ impl AssetDescriptions {
         let ui: HashMap<Uuid, UiElement> = {
             let mut map = HashMap::new();
             for ui_element_desc in self.ui_elements.iter() {
-                let material_uuid = materials.iter().find(|(_, material)| material.name == ui_element_desc.material)
-                    .expect("Material not found").0;
-                map.insert(
-                    Uuid::new_v4(),
-                    UiElement::new(&ui_element_desc.name, ui_element_desc.element_type, *material_uuid, ui_element_desc.screen_anchor, ui_element_desc.position, ui_element_desc.width, ui_element_desc.height)
-                );
+                insert_ui_element(ui_element_desc, None, &materials, &mut map);
             }
             map
         };
 
-        AssetLibrary {
-            shaders,
-            textures,
-            models,
-            materials,
-            meshes: HashMap::new(),
-            ui
-        }
+        Ok(AssetLibrary::new(shaders, textures, models, materials, HashMap::new(), ui, fonts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{engine_config::AssetPaths, engine_error::EngineError, types::{material::Attachment, shader::ShaderType, texture::TextureKind}};
+
+    use super::{resolve_attachment, AssetDescriptions, AttachmentDescription, ShaderDescription, TextureDescription};
+
+    #[test]
+    fn test_material_with_missing_texture() {
+        let descriptions = AssetDescriptions {
+            shaders: vec![],
+            textures: vec![TextureDescription {
+                name: "missing.png".to_string(),
+                linear: false,
+                generate_mips: true,
+                kind: TextureKind::D2,
+                faces: vec![],
+                storage: None,
+            }],
+            models: vec![],
+            materials: vec![],
+            ui_elements: vec![],
+            fonts: vec![],
+        };
+        let library = descriptions.generate_library(&AssetPaths::default()).unwrap();
+        assert!(library.textures.is_empty());
+
+        let attachment = resolve_attachment(&AttachmentDescription::Texture("missing.png".to_string()), &library.textures);
+        assert!(matches!(attachment, Attachment::DefaultTexture));
+    }
+
+    #[test]
+    fn test_storage_texture_and_compute_shader() {
+        let descriptions = AssetDescriptions {
+            shaders: vec![ShaderDescription {
+                name: "gradient".to_string(),
+                shader_type: ShaderType::Compute,
+                source: None,
+            }],
+            textures: vec![TextureDescription {
+                name: "gradient".to_string(),
+                linear: true,
+                generate_mips: false,
+                kind: TextureKind::D2,
+                faces: vec![],
+                storage: Some((64, 32)),
+            }],
+            models: vec![],
+            materials: vec![],
+            ui_elements: vec![],
+            fonts: vec![],
+        };
+        let library = descriptions.generate_library(&AssetPaths::default()).unwrap();
+        assert!(library.shader_by_name("gradient").is_some());
+
+        let (_, texture) = library.texture_by_name("gradient").unwrap();
+        assert!(texture.storage);
+        assert_eq!((texture.width, texture.height), (64, 32));
+    }
+
+    #[test]
+    fn test_missing_shader_file() {
+        let descriptions = AssetDescriptions {
+            shaders: vec![ShaderDescription {
+                name: "missing".to_string(),
+                shader_type: ShaderType::Fragment,
+                source: None,
+            }],
+            textures: vec![],
+            models: vec![],
+            materials: vec![],
+            ui_elements: vec![],
+            fonts: vec![],
+        };
+        let error = descriptions.generate_library(&AssetPaths::default()).unwrap_err();
+        assert!(matches!(&error, EngineError::MissingShader { name, .. } if name == "missing"));
     }
 }
@@ -1,11 +1,129 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::{cell::RefCell, collections::HashMap, fmt::{self, Debug}};
 
+use log::warn;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{types::{material::Material, mesh::Mesh, model::Model, shader::Shader, texture::Texture}, ui::ui_layout::UiElement};
+use crate::{asset_pack::{AssetPackError, PackSections}, types::{font::Font, material::{Attachment, Material}, mesh::Mesh, model::Model, shader::Shader, texture::Texture}, ui::ui_layout::UiElement};
 
-#[derive(Debug, Serialize, Deserialize)]
+trait NamedAsset {
+    fn asset_name(&self) -> &str;
+}
+
+macro_rules! named_asset {
+    ($($asset:ty),*) => {
+        $(impl NamedAsset for $asset {
+            fn asset_name(&self) -> &str {
+                &self.name
+            }
+        })*
+    };
+}
+
+named_asset!(Shader, Texture, Model, Material, Mesh, Font);
+
+#[derive(Debug, Default)]
+struct NameIndex {
+    // size of the map when the index was built, a different size means it was mutated
+    len: usize,
+    uuids: HashMap<String, Uuid>,
+}
+
+impl NameIndex {
+    fn build<T: NamedAsset>(map: &HashMap<Uuid, T>) -> NameIndex {
+        let mut uuids = HashMap::new();
+        for (uuid, asset) in map.iter() {
+            uuids.entry(asset.asset_name().to_string()).or_insert(*uuid);
+        }
+        NameIndex { len: map.len(), uuids }
+    }
+}
+
+#[derive(Debug, Default)]
+struct NameIndices {
+    shaders: RefCell<Option<NameIndex>>,
+    textures: RefCell<Option<NameIndex>>,
+    models: RefCell<Option<NameIndex>>,
+    materials: RefCell<Option<NameIndex>>,
+    meshes: RefCell<Option<NameIndex>>,
+    fonts: RefCell<Option<NameIndex>>,
+}
+
+fn find_by_name<'a, T: NamedAsset>(
+    map: &'a HashMap<Uuid, T>,
+    index: &RefCell<Option<NameIndex>>,
+    name: &str
+) -> Option<(Uuid, &'a T)> {
+    let mut index = index.borrow_mut();
+    if index.as_ref().is_none_or(|x| x.len != map.len()) {
+        *index = Some(NameIndex::build(map));
+    }
+
+    let uuid = *index.as_ref().unwrap().uuids.get(name)?;
+    match map.get(&uuid) {
+        Some(asset) if asset.asset_name() == name => Some((uuid, asset)),
+        // the entry was replaced or renamed since the index was built
+        _ => {
+            let rebuilt = NameIndex::build(map);
+            let uuid = rebuilt.uuids.get(name).copied();
+            *index = Some(rebuilt);
+            let uuid = uuid?;
+            map.get(&uuid).map(|x| (uuid, x))
+        }
+    }
+}
+
+fn duplicate_names<'a>(category: &'static str, names: impl Iterator<Item = &'a str>) -> Vec<DuplicateName> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for name in names {
+        *counts.entry(name).or_insert(0) += 1;
+    }
+
+    let mut duplicates: Vec<DuplicateName> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(name, count)| DuplicateName { category, name: name.to_string(), count })
+        .collect();
+    duplicates.sort_by(|a, b| a.name.cmp(&b.name));
+    duplicates
+}
+
+// maps the assets of `incoming` onto the ones of `existing` with the same name
+fn overridden<T: NamedAsset>(
+    category: &str,
+    existing: &HashMap<Uuid, T>,
+    incoming: &HashMap<Uuid, T>,
+    remap: &mut HashMap<Uuid, Uuid>
+) {
+    let names: HashMap<&str, Uuid> = existing.iter().map(|(uuid, x)| (x.asset_name(), *uuid)).collect();
+    for (uuid, asset) in incoming.iter() {
+        if let Some(existing) = names.get(asset.asset_name()) {
+            warn!("{} '{}' is in more than one mounted pack, using the last one", category, asset.asset_name());
+            remap.insert(*uuid, *existing);
+        }
+    }
+}
+
+fn insert_remapped<T>(target: &mut HashMap<Uuid, T>, incoming: HashMap<Uuid, T>, remap: &HashMap<Uuid, Uuid>) {
+    for (uuid, asset) in incoming {
+        target.insert(remap.get(&uuid).copied().unwrap_or(uuid), asset);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateName {
+    pub category: &'static str,
+    pub name: String,
+    pub count: usize,
+}
+
+impl fmt::Display for DuplicateName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} share the name '{}', lookups by name will only find one of them", self.count, self.category, self.name)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct AssetLibrary {
     pub shaders: HashMap<Uuid, Shader>,
     pub textures: HashMap<Uuid, Texture>,
@@ -13,4 +131,305 @@ pub struct AssetLibrary {
     pub materials: HashMap<Uuid, Material>,
     pub meshes: HashMap<Uuid, Mesh>,
     pub ui: HashMap<Uuid, UiElement>,
+    #[serde(default)]
+    pub fonts: HashMap<Uuid, Font>,
+    #[serde(skip)]
+    names: NameIndices,
+    // texture and mesh sections still waiting in the asset pack
+    #[serde(skip)]
+    pub(crate) sections: PackSections,
+}
+
+impl AssetLibrary {
+    pub fn new(
+        shaders: HashMap<Uuid, Shader>,
+        textures: HashMap<Uuid, Texture>,
+        models: HashMap<Uuid, Model>,
+        materials: HashMap<Uuid, Material>,
+        meshes: HashMap<Uuid, Mesh>,
+        ui: HashMap<Uuid, UiElement>,
+        fonts: HashMap<Uuid, Font>
+    ) -> AssetLibrary {
+        AssetLibrary {
+            shaders,
+            textures,
+            models,
+            materials,
+            meshes,
+            ui,
+            fonts,
+            names: NameIndices::default(),
+            sections: PackSections::default()
+        }
+    }
+
+    pub fn shader_by_name(&self, name: &str) -> Option<(Uuid, &Shader)> {
+        find_by_name(&self.shaders, &self.names.shaders, name)
+    }
+
+    pub fn texture_by_name(&self, name: &str) -> Option<(Uuid, &Texture)> {
+        find_by_name(&self.textures, &self.names.textures, name)
+    }
+
+    pub fn model_by_name(&self, name: &str) -> Option<(Uuid, &Model)> {
+        find_by_name(&self.models, &self.names.models, name)
+    }
+
+    pub fn material_by_name(&self, name: &str) -> Option<(Uuid, &Material)> {
+        find_by_name(&self.materials, &self.names.materials, name)
+    }
+
+    pub fn mesh_by_name(&self, name: &str) -> Option<(Uuid, &Mesh)> {
+        find_by_name(&self.meshes, &self.names.meshes, name)
+    }
+
+    pub fn font_by_name(&self, name: &str) -> Option<(Uuid, &Font)> {
+        find_by_name(&self.fonts, &self.names.fonts, name)
+    }
+
+    pub fn material_mut_by_name(&mut self, name: &str) -> Option<&mut Material> {
+        let (uuid, _) = self.material_by_name(name)?;
+        self.materials.get_mut(&uuid)
+    }
+
+    // the element shows up once UiMeshBuilder laid it out, a parent that exists gets it appended to its children
+    pub fn ui_add(&mut self, element: UiElement) -> Uuid {
+        let uuid = Uuid::new_v4();
+        if let Some(parent) = element.parent.and_then(|x| self.ui.get_mut(&x)) {
+            parent.children.push(uuid);
+            parent.mark_dirty();
+        }
+        self.ui.insert(uuid, element);
+        uuid
+    }
+
+    // removes the element with all of its children, their meshes are kept alive by the renderer
+    // until the frames drawing them have retired
+    pub fn ui_remove(&mut self, uuid: Uuid) -> Option<UiElement> {
+        let element = self.ui.remove(&uuid)?;
+        if let Some(parent) = element.parent.and_then(|x| self.ui.get_mut(&x)) {
+            parent.children.retain(|x| *x != uuid);
+            parent.mark_dirty();
+        }
+
+        let mut children = element.children.clone();
+        while let Some(child) = children.pop() {
+            if let Some(child) = self.ui.remove(&child) {
+                children.extend(child.children);
+            }
+        }
+        Some(element)
+    }
+
+    // hides or shows the element together with its children, no mesh is rebuilt
+    pub fn ui_set_visible(&mut self, uuid: Uuid, visible: bool) {
+        if let Some(element) = self.ui.get_mut(&uuid) {
+            element.visible = visible;
+        }
+    }
+
+    // the indices notice inserts and removals on their own, renaming an entry
+    // in place or swapping entries without changing the map size needs this
+    pub fn invalidate_names(&self) {
+        self.names.shaders.replace(None);
+        self.names.textures.replace(None);
+        self.names.models.replace(None);
+        self.names.materials.replace(None);
+        self.names.meshes.replace(None);
+        self.names.fonts.replace(None);
+    }
+
+    // mounts another pack on top of this one, its assets replace the ones here with the same name but keep
+    // their uuids so everything referencing them in either pack stays valid, both packs' textures and
+    // meshes are read in full since they're matched by name
+    pub fn merge(&mut self, mut other: AssetLibrary) -> Result<(), AssetPackError> {
+        self.load_pending_textures()?;
+        self.load_pending_meshes()?;
+        other.load_pending_textures()?;
+        other.load_pending_meshes()?;
+
+        let mut remap = HashMap::new();
+        overridden("shader", &self.shaders, &other.shaders, &mut remap);
+        overridden("texture", &self.textures, &other.textures, &mut remap);
+        overridden("model", &self.models, &other.models, &mut remap);
+        overridden("material", &self.materials, &other.materials, &mut remap);
+        overridden("mesh", &self.meshes, &other.meshes, &mut remap);
+        overridden("font", &self.fonts, &other.fonts, &mut remap);
+
+        let remapped = |uuid: &mut Uuid| {
+            if let Some(target) = remap.get(uuid) {
+                *uuid = *target;
+            }
+        };
+        for material in other.materials.values_mut() {
+            remapped(&mut material.vertex_shader);
+            remapped(&mut material.fragment_shader);
+            for attachment in material.attachments.iter_mut() {
+                if let Attachment::Texture(uuid) = attachment {
+                    remapped(uuid);
+                }
+            }
+        }
+        for model in other.models.values_mut() {
+            model.visit_primitives_mut(|(mesh, material)| {
+                remapped(mesh);
+                remapped(material);
+            });
+        }
+        for element in other.ui.values_mut() {
+            remapped(&mut element.material);
+        }
+
+        insert_remapped(&mut self.shaders, other.shaders, &remap);
+        insert_remapped(&mut self.textures, other.textures, &remap);
+        insert_remapped(&mut self.models, other.models, &remap);
+        insert_remapped(&mut self.materials, other.materials, &remap);
+        insert_remapped(&mut self.meshes, other.meshes, &remap);
+        insert_remapped(&mut self.fonts, other.fonts, &remap);
+        self.ui.extend(other.ui);
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<DuplicateName>> {
+        let mut duplicates = Vec::new();
+        duplicates.append(&mut duplicate_names("shaders", self.shaders.values().map(|x| x.name.as_str())));
+        duplicates.append(&mut duplicate_names("textures", self.textures.values().map(|x| x.name.as_str())));
+        duplicates.append(&mut duplicate_names("models", self.models.values().map(|x| x.name.as_str())));
+        duplicates.append(&mut duplicate_names("materials", self.materials.values().map(|x| x.name.as_str())));
+        duplicates.append(&mut duplicate_names("meshes", self.meshes.values().map(|x| x.name.as_str())));
+        duplicates.append(&mut duplicate_names("fonts", self.fonts.values().map(|x| x.name.as_str())));
+
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(duplicates)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{
+        asset_pack,
+        types::{
+            material::{Attachment, DepthSettings, Material, RenderingType},
+            model::Model,
+            shader::{Shader, ShaderType},
+            texture::Texture,
+        },
+        ui::ui_layout::UiElement,
+    };
+
+    use super::{AssetLibrary, DuplicateName};
+
+    // a pack with a "lit" shader and a material drawing `textures`, as it comes out of the pack file
+    fn pack(textures: &[(&str, u32)], material: &str) -> (AssetLibrary, Uuid) {
+        let mut assets = AssetLibrary::default();
+        let shader = Uuid::new_v4();
+        assets.shaders.insert(shader, Shader::from_words("lit".to_string(), ShaderType::Fragment, vec![]));
+        let mut attachments = Vec::new();
+        for (name, size) in textures {
+            let uuid = Uuid::new_v4();
+            assets.textures.insert(uuid, Texture::from_rgba8(name, *size, *size, vec![0; (size * size * 4) as usize]));
+            attachments.push(Attachment::Texture(uuid));
+        }
+        let material = Material::new(
+            material.to_string(),
+            shader,
+            shader,
+            attachments,
+            None,
+            RenderingType::Fill,
+            false,
+            DepthSettings::default(),
+        );
+        assets.materials.insert(Uuid::new_v4(), material);
+        (asset_pack::decode(asset_pack::encode(&assets).unwrap()).unwrap(), shader)
+    }
+
+    #[test]
+    fn test_lookup_follows_mutations() {
+        let mut assets = AssetLibrary::default();
+        let ship = Uuid::new_v4();
+        assets.models.insert(ship, Model::new("ship.gltf".to_string()));
+        assert_eq!(assets.model_by_name("ship.gltf").map(|x| x.0), Some(ship));
+        assert!(assets.model_by_name("rock.obj").is_none());
+
+        let rock = Uuid::new_v4();
+        assets.models.insert(rock, Model::new("rock.obj".to_string()));
+        assert_eq!(assets.model_by_name("rock.obj").map(|x| x.0), Some(rock));
+
+        assets.models.get_mut(&ship).unwrap().name = "hull.gltf".to_string();
+        assets.invalidate_names();
+        assert!(assets.model_by_name("ship.gltf").is_none());
+        assert_eq!(assets.model_by_name("hull.gltf").map(|x| x.0), Some(ship));
+
+        assets.models.remove(&rock);
+        assert!(assets.model_by_name("rock.obj").is_none());
+    }
+
+    #[test]
+    fn test_validate_reports_duplicates() {
+        let mut assets = AssetLibrary::default();
+        assert!(assets.validate().is_ok());
+
+        for name in ["ship.gltf", "ship.gltf", "rock.obj"] {
+            assets.models.insert(Uuid::new_v4(), Model::new(name.to_string()));
+        }
+        assert_eq!(
+            assets.validate(),
+            Err(vec![DuplicateName {
+                category: "models",
+                name: "ship.gltf".to_string(),
+                count: 2,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_later_pack_overrides() {
+        let (mut assets, shader) = pack(&[("stone", 1)], "rock");
+        let (dlc, _) = pack(&[("stone", 2), ("moss", 1)], "mossy");
+        assets.merge(dlc).unwrap();
+
+        assert_eq!(assets.shaders.len(), 1);
+        assert_eq!(assets.textures.len(), 2);
+        assert_eq!(assets.materials.len(), 2);
+        let (stone, texture) = assets.texture_by_name("stone").unwrap();
+        assert_eq!(texture.width, 2);
+        let (moss, _) = assets.texture_by_name("moss").unwrap();
+
+        // both packs' materials point at the stone that's left and the first pack's shader
+        let rock = assets.material_by_name("rock").unwrap().1;
+        assert!(matches!(rock.attachments[..], [Attachment::Texture(x)] if x == stone));
+        let mossy = assets.material_by_name("mossy").unwrap().1;
+        assert_eq!((mossy.vertex_shader, mossy.fragment_shader), (shader, shader));
+        assert!(matches!(mossy.attachments[..], [Attachment::Texture(x), Attachment::Texture(y)] if x == stone && y == moss));
+        assert!(assets.validate().is_ok());
+    }
+
+    #[test]
+    fn test_ui_add_and_remove() {
+        let mut assets = AssetLibrary::default();
+        let panel = assets.ui_add(UiElement::default());
+        let other = assets.ui_add(UiElement::default());
+        let child_of = |parent| {
+            let mut element = UiElement::default();
+            element.parent = Some(parent);
+            element
+        };
+        let button = assets.ui_add(child_of(panel));
+        let label = assets.ui_add(child_of(button));
+        assert_eq!(assets.ui[&panel].children, vec![button]);
+        assert_eq!(assets.ui[&button].children, vec![label]);
+
+        assert!(assets.ui_remove(button).is_some());
+        assert!(assets.ui[&panel].children.is_empty());
+        assert!(!assets.ui.contains_key(&label));
+        assert!(assets.ui_remove(button).is_none());
+        assert!(assets.ui.contains_key(&other));
+        assert_eq!(assets.ui.len(), 2);
+    }
 }
@@ -0,0 +1,347 @@
+use std::{
+    any::Any,
+    collections::HashSet,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use log::error;
+use uuid::Uuid;
+use vulkano::{device::Device, memory::allocator::StandardMemoryAllocator};
+
+use crate::{
+    state::State,
+    types::{
+        mesh::{MeshUpload, UploadedMesh},
+        texture::{TextureUpload, UploadedTexture},
+    },
+    vulkan::{context::VulkanContext, memory::MemoryAllocators, transfer::TransferManager},
+};
+
+pub const DEFAULT_UPLOAD_WORKERS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetEvent {
+    // every upload queued while the systems were starting is resident
+    AssetsReady,
+}
+
+// what a worker thread needs to create and fill GPU resources
+#[derive(Clone)]
+pub struct UploadContext {
+    pub device: Arc<Device>,
+    pub transfer: Arc<TransferManager>,
+    pub memory_allocator: Arc<StandardMemoryAllocator>,
+}
+
+impl UploadContext {
+    pub fn new(state: &State) -> UploadContext {
+        UploadContext {
+            device: state.vulkan_context.device.clone(),
+            transfer: state.memory_allocators.transfer.clone(),
+            memory_allocator: state.memory_allocators.standard_memory_allocator.clone(),
+        }
+    }
+}
+
+pub enum UploadJob {
+    Mesh(MeshUpload),
+    Texture(TextureUpload),
+}
+
+impl UploadJob {
+    fn uuid(&self) -> Uuid {
+        match self {
+            UploadJob::Mesh(upload) => upload.uuid,
+            UploadJob::Texture(upload) => upload.uuid,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UploadError {
+    pub uuid: Uuid,
+    pub message: String,
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to upload asset {}: {}", self.uuid, self.message)
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "upload panicked".to_string(),
+        },
+    }
+}
+
+// a panicking upload is reported like a failed one instead of taking its worker down
+fn catch_panic<T>(uuid: Uuid, upload: impl FnOnce() -> Result<T, UploadError>) -> Result<T, UploadError> {
+    panic::catch_unwind(AssertUnwindSafe(upload))
+        .unwrap_or_else(|payload| Err(UploadError { uuid, message: panic_message(payload) }))
+}
+
+// threads taking jobs from one shared queue, `handler` returns false once its results can't be delivered
+struct WorkerPool<J> {
+    work_send: Option<crossbeam_channel::Sender<J>>,
+    work_recv: crossbeam_channel::Receiver<J>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<J: Send + 'static> WorkerPool<J> {
+    fn new(count: usize, handler: impl Fn(J) -> bool + Clone + Send + 'static) -> WorkerPool<J> {
+        let (work_send, work_recv) = crossbeam_channel::unbounded();
+        let workers = (0..count.max(1))
+            .map(|_| {
+                let work_recv: crossbeam_channel::Receiver<J> = work_recv.clone();
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    while let Ok(job) = work_recv.recv() {
+                        if !handler(job) {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        WorkerPool { work_send: Some(work_send), work_recv, workers }
+    }
+
+    // false when the pool was shut down
+    fn send(&self, job: J) -> bool {
+        self.work_send.as_ref().is_some_and(|x| x.send(job).is_ok())
+    }
+
+}
+
+impl<J> WorkerPool<J> {
+    // drops the queued jobs and waits for the ones already running
+    fn shutdown(&mut self) {
+        self.work_send = None;
+        while self.work_recv.try_recv().is_ok() {}
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("Upload worker panicked");
+            }
+        }
+    }
+}
+
+impl<J> Drop for WorkerPool<J> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[derive(Debug, Default)]
+struct LoadingProgress {
+    submitted: usize,
+    completed: usize,
+    ready: bool,
+    pending_events: Vec<AssetEvent>,
+    events: Vec<AssetEvent>,
+}
+
+impl LoadingProgress {
+    fn submit(&mut self) {
+        self.submitted += 1;
+    }
+
+    fn complete(&mut self, count: usize) {
+        self.completed += count;
+        self.check_ready();
+    }
+
+    fn check_ready(&mut self) {
+        if !self.ready && self.completed >= self.submitted {
+            self.ready = true;
+            self.pending_events.push(AssetEvent::AssetsReady);
+        }
+    }
+
+    fn fraction(&self) -> f32 {
+        if self.submitted == 0 {
+            1.0
+        } else {
+            self.completed as f32 / self.submitted as f32
+        }
+    }
+
+    // events raised during a frame are visible to every system for the whole next frame
+    fn end_frame(&mut self) {
+        self.events = std::mem::take(&mut self.pending_events);
+    }
+}
+
+pub struct AssetLoading {
+    workers: WorkerPool<UploadJob>,
+    mesh_recv: Receiver<Result<UploadedMesh, UploadError>>,
+    texture_recv: Receiver<Result<UploadedTexture, UploadError>>,
+    uploading: HashSet<Uuid>,
+    progress: LoadingProgress,
+}
+
+impl AssetLoading {
+    pub fn new(context: &VulkanContext, memory_allocators: &MemoryAllocators, worker_count: usize) -> AssetLoading {
+        let (mesh_send, mesh_recv) = mpsc::channel();
+        let (texture_send, texture_recv) = mpsc::channel();
+        let upload_context = UploadContext {
+            device: context.device.clone(),
+            transfer: memory_allocators.transfer.clone(),
+            memory_allocator: Arc::new(StandardMemoryAllocator::new_default(context.device.clone())),
+        };
+        let workers = WorkerPool::new(worker_count, move |job: UploadJob| {
+            let uuid = job.uuid();
+            match job {
+                UploadJob::Mesh(upload) => mesh_send.send(catch_panic(uuid, || upload.run(&upload_context))).is_ok(),
+                UploadJob::Texture(upload) => {
+                    texture_send.send(catch_panic(uuid, || Ok(upload.run(&upload_context)))).is_ok()
+                }
+            }
+        });
+
+        AssetLoading {
+            workers,
+            mesh_recv,
+            texture_recv,
+            uploading: HashSet::new(),
+            progress: LoadingProgress::default(),
+        }
+    }
+
+    pub fn submit(&mut self, job: UploadJob) {
+        let uuid = job.uuid();
+        if !self.workers.send(job) {
+            error!("Upload workers were shut down, asset {} won't be uploaded", uuid);
+            return;
+        }
+        self.uploading.insert(uuid);
+        self.progress.submit();
+    }
+
+    // stops the workers, uploads still queued are dropped
+    pub fn shutdown(&mut self) {
+        self.workers.shutdown();
+    }
+
+    pub fn is_uploading(&self, uuid: &Uuid) -> bool {
+        self.uploading.contains(uuid)
+    }
+
+    // failed uploads count as finished too, it's up to the caller to submit them again
+    pub fn finished_meshes(&mut self) -> Vec<Result<UploadedMesh, UploadError>> {
+        let finished: Vec<_> = self.mesh_recv.try_iter().collect();
+        for result in finished.iter() {
+            self.uploading.remove(&result.as_ref().map_or_else(|x| x.uuid, |x| x.uuid));
+        }
+        self.progress.complete(finished.len());
+        finished
+    }
+
+    pub fn finished_textures(&mut self) -> Vec<Result<UploadedTexture, UploadError>> {
+        let finished: Vec<_> = self.texture_recv.try_iter().collect();
+        for result in finished.iter() {
+            self.uploading.remove(&result.as_ref().map_or_else(|x| x.uuid, |x| x.uuid));
+        }
+        self.progress.complete(finished.len());
+        finished
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.progress.fraction()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.progress.ready
+    }
+
+    pub fn events(&self) -> &[AssetEvent] {
+        &self.progress.events
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.progress.check_ready();
+        self.progress.end_frame();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use uuid::Uuid;
+
+    use super::{catch_panic, AssetEvent, LoadingProgress, WorkerPool};
+
+    #[test]
+    fn test_progress_and_ready_event() {
+        let mut progress = LoadingProgress::default();
+        progress.submit();
+        progress.submit();
+        assert_eq!(progress.fraction(), 0.0);
+
+        progress.complete(1);
+        progress.end_frame();
+        assert_eq!(progress.fraction(), 0.5);
+        assert!(progress.events.is_empty());
+
+        progress.complete(1);
+        assert!(progress.ready);
+        assert!(progress.events.is_empty());
+        progress.end_frame();
+        assert_eq!(progress.events, vec![AssetEvent::AssetsReady]);
+        progress.end_frame();
+        assert!(progress.events.is_empty());
+
+        progress.submit();
+        progress.complete(1);
+        progress.end_frame();
+        assert!(progress.events.is_empty());
+    }
+
+    #[test]
+    fn test_ready_without_uploads() {
+        let mut progress = LoadingProgress::default();
+        assert_eq!(progress.fraction(), 1.0);
+        progress.check_ready();
+        progress.end_frame();
+        assert_eq!(progress.events, vec![AssetEvent::AssetsReady]);
+    }
+
+    #[test]
+    fn test_pool_completes_every_upload() {
+        let (result_send, result_recv) = mpsc::channel();
+        let mut pool = WorkerPool::new(4, move |job: u32| result_send.send(job * 2).is_ok());
+        for job in 0..256 {
+            assert!(pool.send(job));
+        }
+
+        let mut results: Vec<u32> = result_recv.iter().take(256).collect();
+        results.sort();
+        assert_eq!(results, (0..256).map(|x| x * 2).collect::<Vec<_>>());
+
+        pool.shutdown();
+        assert!(!pool.send(0));
+    }
+
+    #[test]
+    fn test_panics_become_errors() {
+        let uuid = Uuid::new_v4();
+        let result: Result<(), _> = catch_panic(uuid, || panic!("out of device memory"));
+        let error = result.unwrap_err();
+        assert_eq!(error.uuid, uuid);
+        assert_eq!(error.message, "out of device memory");
+        assert_eq!(catch_panic(uuid, || Ok(1)).unwrap(), 1);
+    }
+}
@@ -0,0 +1,808 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    asset_library::AssetLibrary,
+    types::{
+        font::Font, material::Material, mesh::Mesh, model::Model, shader::Shader, texture::Texture, vectors::Vec2f,
+    },
+    ui::{
+        ui_layout::{Anchor, UiElement, UiElementType, UiLayoutKind, UiSpriteMode},
+        ui_mesh::UiMesh,
+    },
+};
+
+pub const ASSET_PACK_PATH: &str = "assets.data";
+pub const FORMAT_VERSION: u32 = 6;
+
+const MAGIC: [u8; 4] = *b"OXPK";
+// magic, version, index hash, asset counts, index length
+const HEADER_SIZE: usize = 4 + 4 + 8 + 6 * 4 + 4;
+const V1_HEADER_SIZE: usize = 4 + 4 + 8 + 6 * 4;
+#[cfg(feature = "compression")]
+const COMPRESSION_LEVEL: i32 = 3;
+
+// upgrades a whole pack file from the paired version to a newer one
+pub type Migration = fn(Vec<u8>) -> Result<Vec<u8>, AssetPackError>;
+
+const MIGRATIONS: &[(u32, Migration)] = &[
+    (1, migrate_monolithic),
+    (4, migrate_fontless_core),
+    (5, migrate_anchorless_ui),
+];
+
+#[derive(Debug)]
+pub enum AssetPackError {
+    Io { path: String, error: io::Error },
+    Truncated,
+    NotAPack,
+    UnsupportedVersion { found: u32, expected: u32 },
+    Checksum { expected: u64, found: u64 },
+    CountMismatch { category: &'static str, expected: u32, found: u32 },
+    CompressionUnsupported,
+    Compression(io::Error),
+    Encode(rmp_serde::encode::Error),
+    Decode(rmp_serde::decode::Error),
+}
+
+impl fmt::Display for AssetPackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetPackError::Io { path, error } => write!(f, "failed to access asset pack {}: {}", path, error),
+            AssetPackError::Truncated => write!(f, "asset pack is truncated — re-run with dev_tools"),
+            AssetPackError::NotAPack => {
+                write!(f, "asset pack has no header, it is corrupted or older than format versioning — re-run with dev_tools")
+            }
+            AssetPackError::UnsupportedVersion { found, expected } => write!(
+                f,
+                "asset pack was built with engine format {}, this build expects {} — re-run with dev_tools",
+                found, expected
+            ),
+            AssetPackError::Checksum { expected, found } => write!(
+                f,
+                "asset pack content hash is {:016x} but the pack says {:016x}, the file is corrupted — re-run with dev_tools",
+                found, expected
+            ),
+            AssetPackError::CountMismatch { category, expected, found } => write!(
+                f,
+                "asset pack header lists {} {} but {} were found — re-run with dev_tools",
+                expected, category, found
+            ),
+            AssetPackError::CompressionUnsupported => {
+                write!(f, "asset pack is compressed but this build lacks the compression feature")
+            }
+            AssetPackError::Compression(e) => write!(f, "failed to (de)compress asset pack section: {}", e),
+            AssetPackError::Encode(e) => write!(f, "failed to encode asset pack: {}", e),
+            AssetPackError::Decode(e) => write!(f, "failed to decode asset pack: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetCounts {
+    pub shaders: u32,
+    pub textures: u32,
+    pub models: u32,
+    pub materials: u32,
+    pub meshes: u32,
+    pub ui: u32,
+}
+
+impl AssetCounts {
+    pub fn of(assets: &AssetLibrary) -> AssetCounts {
+        AssetCounts {
+            shaders: assets.shaders.len() as u32,
+            textures: (assets.textures.len() + assets.sections.textures.len()) as u32,
+            models: assets.models.len() as u32,
+            materials: assets.materials.len() as u32,
+            meshes: (assets.meshes.len() + assets.sections.meshes.len()) as u32,
+            ui: assets.ui.len() as u32,
+        }
+    }
+
+    fn as_array(&self) -> [(&'static str, u32); 6] {
+        [
+            ("shaders", self.shaders),
+            ("textures", self.textures),
+            ("models", self.models),
+            ("materials", self.materials),
+            ("meshes", self.meshes),
+            ("ui elements", self.ui),
+        ]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> AssetCounts {
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        AssetCounts {
+            shaders: u32_at(0),
+            textures: u32_at(4),
+            models: u32_at(8),
+            materials: u32_at(12),
+            meshes: u32_at(16),
+            ui: u32_at(20),
+        }
+    }
+
+    fn check(&self, found: &AssetCounts) -> Result<(), AssetPackError> {
+        for ((category, expected), (_, found)) in self.as_array().into_iter().zip(found.as_array()) {
+            if expected != found {
+                return Err(AssetPackError::CountMismatch { category, expected, found });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetPackHeader {
+    pub version: u32,
+    pub hash: u64,
+    pub counts: AssetCounts,
+    pub index_len: u32,
+}
+
+impl AssetPackHeader {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.hash.to_le_bytes());
+        for (_, count) in self.counts.as_array() {
+            bytes.extend_from_slice(&count.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.index_len.to_le_bytes());
+    }
+
+    fn version(bytes: &[u8]) -> Result<u32, AssetPackError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(AssetPackError::NotAPack);
+        }
+        if bytes.len() < 8 {
+            return Err(AssetPackError::Truncated);
+        }
+        Ok(u32::from_le_bytes(bytes[4..8].try_into().unwrap()))
+    }
+
+    fn read(bytes: &[u8]) -> Result<AssetPackHeader, AssetPackError> {
+        let version = AssetPackHeader::version(bytes)?;
+        if bytes.len() < HEADER_SIZE {
+            return Err(AssetPackError::Truncated);
+        }
+
+        Ok(AssetPackHeader {
+            version,
+            hash: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            counts: AssetCounts::from_bytes(&bytes[16..40]),
+            index_len: u32::from_le_bytes(bytes[40..44].try_into().unwrap()),
+        })
+    }
+}
+
+// where a section lives, relative to the end of the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct SectionEntry {
+    offset: u64,
+    length: u64,
+    hash: u64,
+    compressed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PackIndex {
+    core: SectionEntry,
+    textures: Vec<(Uuid, SectionEntry)>,
+    meshes: Vec<(Uuid, SectionEntry)>,
+}
+
+type CoreSection = (
+    HashMap<Uuid, Shader>,
+    HashMap<Uuid, Model>,
+    HashMap<Uuid, Material>,
+    HashMap<Uuid, UiElement>,
+    HashMap<Uuid, Font>,
+);
+
+type FontlessCoreSection = (
+    HashMap<Uuid, Shader>,
+    HashMap<Uuid, Model>,
+    HashMap<Uuid, Material>,
+    HashMap<Uuid, LegacyUiElement>,
+);
+
+type AnchorlessCoreSection = (
+    HashMap<Uuid, Shader>,
+    HashMap<Uuid, Model>,
+    HashMap<Uuid, Material>,
+    HashMap<Uuid, LegacyUiElement>,
+    HashMap<Uuid, Font>,
+);
+
+// ui elements up to format 5, centered on an anchor point with a size instead of UiAnchors
+#[derive(Debug, Serialize, Deserialize)]
+struct LegacyUiElement {
+    element_type: UiElementType,
+    name: String,
+    material: Uuid,
+    position: Vec2f,
+    screen_anchor: Anchor,
+    width: f32,
+    height: f32,
+    mesh: Option<UiMesh>,
+    #[serde(default)]
+    layout: UiLayoutKind,
+    #[serde(default)]
+    parent: Option<Uuid>,
+    #[serde(default)]
+    children: Vec<Uuid>,
+    #[serde(default)]
+    sprite_mode: UiSpriteMode,
+    #[serde(default = "legacy_visible")]
+    visible: bool,
+    #[serde(default = "legacy_opacity")]
+    opacity: f32,
+    #[serde(default)]
+    clip_children: bool,
+    #[serde(default)]
+    focusable: bool,
+}
+
+fn legacy_visible() -> bool {
+    true
+}
+
+fn legacy_opacity() -> f32 {
+    1.0
+}
+
+impl LegacyUiElement {
+    fn upgrade(self) -> UiElement {
+        let mut element = UiElement::new(
+            &self.name,
+            self.element_type,
+            self.material,
+            self.screen_anchor,
+            self.position,
+            self.width,
+            self.height,
+        );
+        element.mesh = self.mesh;
+        element.layout = self.layout;
+        element.parent = self.parent;
+        element.children = self.children;
+        element.sprite_mode = self.sprite_mode;
+        element.visible = self.visible;
+        element.set_opacity(self.opacity);
+        element.clip_children = self.clip_children;
+        element.focusable = self.focusable;
+        element
+    }
+}
+
+fn upgrade_ui(ui: HashMap<Uuid, LegacyUiElement>) -> HashMap<Uuid, UiElement> {
+    ui.into_iter().map(|(uuid, element)| (uuid, element.upgrade())).collect()
+}
+
+// the whole library as format 1 stored it
+#[derive(Deserialize)]
+struct MonolithicLibrary {
+    shaders: HashMap<Uuid, Shader>,
+    textures: HashMap<Uuid, Texture>,
+    models: HashMap<Uuid, Model>,
+    materials: HashMap<Uuid, Material>,
+    meshes: HashMap<Uuid, Mesh>,
+    ui: HashMap<Uuid, LegacyUiElement>,
+    #[serde(default)]
+    fonts: HashMap<Uuid, Font>,
+}
+
+#[derive(Debug, Clone)]
+enum SectionSource {
+    File(String),
+    // packs upgraded by a migration are kept in memory
+    Memory(Arc<Vec<u8>>),
+}
+
+// texture and mesh sections that haven't been deserialized yet
+#[derive(Debug, Default)]
+pub struct PackSections {
+    source: Option<SectionSource>,
+    start: u64,
+    textures: Vec<(Uuid, SectionEntry)>,
+    meshes: Vec<(Uuid, SectionEntry)>,
+}
+
+impl PackSections {
+    pub fn pending_textures(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn pending_meshes(&self) -> usize {
+        self.meshes.len()
+    }
+
+    fn read_all<T: DeserializeOwned>(&self, entries: &[(Uuid, SectionEntry)]) -> Result<Vec<(Uuid, T)>, AssetPackError> {
+        let source = match self.source.as_ref() {
+            Some(val) => val,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut assets = Vec::with_capacity(entries.len());
+        match source {
+            SectionSource::File(path) => {
+                let io_error = |error| AssetPackError::Io { path: path.clone(), error };
+                let mut file = fs::File::open(path).map_err(io_error)?;
+                for (uuid, entry) in entries {
+                    let mut bytes = vec![0; entry.length as usize];
+                    file.seek(SeekFrom::Start(self.start + entry.offset)).map_err(io_error)?;
+                    file.read_exact(&mut bytes).map_err(|error| match error.kind() {
+                        io::ErrorKind::UnexpectedEof => AssetPackError::Truncated,
+                        _ => io_error(error),
+                    })?;
+                    assets.push((*uuid, decode_section(&bytes, entry)?));
+                }
+            }
+            SectionSource::Memory(pack) => {
+                for (uuid, entry) in entries {
+                    let start = (self.start + entry.offset) as usize;
+                    let bytes = pack.get(start..start + entry.length as usize).ok_or(AssetPackError::Truncated)?;
+                    assets.push((*uuid, decode_section(bytes, entry)?));
+                }
+            }
+        }
+        Ok(assets)
+    }
+}
+
+impl AssetLibrary {
+    // deserializes texture sections that were left in the pack on load
+    pub fn load_pending_textures(&mut self) -> Result<(), AssetPackError> {
+        let entries = std::mem::take(&mut self.sections.textures);
+        let textures: Vec<(Uuid, Texture)> = self.sections.read_all(&entries)?;
+        self.textures.extend(textures);
+        Ok(())
+    }
+
+    // deserializes mesh sections that were left in the pack on load
+    pub fn load_pending_meshes(&mut self) -> Result<(), AssetPackError> {
+        let entries = std::mem::take(&mut self.sections.meshes);
+        let meshes: Vec<(Uuid, Mesh)> = self.sections.read_all(&entries)?;
+        self.meshes.extend(meshes);
+        Ok(())
+    }
+}
+
+// 64-bit FNV-1a, stable across builds unlike the std hashers
+fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(feature = "compression")]
+fn compress(bytes: Vec<u8>) -> Result<(Vec<u8>, bool), AssetPackError> {
+    let compressed = zstd::bulk::compress(&bytes, COMPRESSION_LEVEL).map_err(AssetPackError::Compression)?;
+    Ok((compressed, true))
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(bytes: Vec<u8>) -> Result<(Vec<u8>, bool), AssetPackError> {
+    Ok((bytes, false))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, AssetPackError> {
+    zstd::stream::decode_all(bytes).map_err(AssetPackError::Compression)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_bytes: &[u8]) -> Result<Vec<u8>, AssetPackError> {
+    Err(AssetPackError::CompressionUnsupported)
+}
+
+fn decode_section<T: DeserializeOwned>(bytes: &[u8], entry: &SectionEntry) -> Result<T, AssetPackError> {
+    let hash = content_hash(bytes);
+    if hash != entry.hash {
+        return Err(AssetPackError::Checksum { expected: entry.hash, found: hash });
+    }
+
+    if entry.compressed {
+        rmp_serde::from_slice(&decompress(bytes)?).map_err(AssetPackError::Decode)
+    } else {
+        rmp_serde::from_slice(bytes).map_err(AssetPackError::Decode)
+    }
+}
+
+struct SectionWriter {
+    bytes: Vec<u8>,
+}
+
+impl SectionWriter {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<SectionEntry, AssetPackError> {
+        let (bytes, compressed) = compress(rmp_serde::to_vec(value).map_err(AssetPackError::Encode)?)?;
+        let entry = SectionEntry {
+            offset: self.bytes.len() as u64,
+            length: bytes.len() as u64,
+            hash: content_hash(&bytes),
+            compressed,
+        };
+        self.bytes.extend_from_slice(&bytes);
+        Ok(entry)
+    }
+}
+
+pub fn encode(assets: &AssetLibrary) -> Result<Vec<u8>, AssetPackError> {
+    if assets.sections.pending_textures() > 0 || assets.sections.pending_meshes() > 0 {
+        log::warn!("Encoding an asset library with unloaded sections, they won't be included");
+    }
+
+    let mut sections = SectionWriter { bytes: Vec::new() };
+    let core = sections.push(&(&assets.shaders, &assets.models, &assets.materials, &assets.ui, &assets.fonts))?;
+    let mut textures = Vec::with_capacity(assets.textures.len());
+    for (uuid, texture) in assets.textures.iter() {
+        textures.push((*uuid, sections.push(texture)?));
+    }
+    let mut meshes = Vec::with_capacity(assets.meshes.len());
+    for (uuid, mesh) in assets.meshes.iter() {
+        meshes.push((*uuid, sections.push(mesh)?));
+    }
+
+    let index = rmp_serde::to_vec(&PackIndex { core, textures, meshes }).map_err(AssetPackError::Encode)?;
+    let header = AssetPackHeader {
+        version: FORMAT_VERSION,
+        hash: content_hash(&index),
+        counts: AssetCounts::of(assets),
+        index_len: index.len() as u32,
+    };
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + index.len() + sections.bytes.len());
+    header.write(&mut bytes);
+    bytes.extend_from_slice(&index);
+    bytes.extend_from_slice(&sections.bytes);
+    Ok(bytes)
+}
+
+fn read_index(bytes: &[u8]) -> Result<(AssetPackHeader, PackIndex), AssetPackError> {
+    let header = AssetPackHeader::read(bytes)?;
+    let index_end = HEADER_SIZE + header.index_len as usize;
+    let index = bytes.get(HEADER_SIZE..index_end).ok_or(AssetPackError::Truncated)?;
+    let hash = content_hash(index);
+    if hash != header.hash {
+        return Err(AssetPackError::Checksum { expected: header.hash, found: hash });
+    }
+
+    let index: PackIndex = rmp_serde::from_slice(index).map_err(AssetPackError::Decode)?;
+    Ok((header, index))
+}
+
+// reads the core section and leaves textures and meshes to be loaded on demand
+fn open(header: AssetPackHeader, index: PackIndex, core: &[u8], source: SectionSource) -> Result<AssetLibrary, AssetPackError> {
+    let (shaders, models, materials, ui, fonts): CoreSection = decode_section(core, &index.core)?;
+    let mut assets = AssetLibrary::new(shaders, HashMap::new(), models, materials, HashMap::new(), ui, fonts);
+    assets.sections = PackSections {
+        source: Some(source),
+        start: HEADER_SIZE as u64 + header.index_len as u64,
+        textures: index.textures,
+        meshes: index.meshes,
+    };
+
+    header.counts.check(&AssetCounts::of(&assets))?;
+    Ok(assets)
+}
+
+fn migrate(mut bytes: Vec<u8>, migrations: &[(u32, Migration)]) -> Result<Vec<u8>, AssetPackError> {
+    let mut version = AssetPackHeader::version(&bytes)?;
+    if version > FORMAT_VERSION {
+        return Err(AssetPackError::UnsupportedVersion { found: version, expected: FORMAT_VERSION });
+    }
+
+    while version < FORMAT_VERSION {
+        let migration = migrations
+            .iter()
+            .find(|(from, _)| *from == version)
+            .ok_or(AssetPackError::UnsupportedVersion { found: version, expected: FORMAT_VERSION })?;
+        bytes = (migration.1)(bytes)?;
+
+        let migrated = AssetPackHeader::version(&bytes)?;
+        if migrated <= version {
+            return Err(AssetPackError::UnsupportedVersion { found: version, expected: FORMAT_VERSION });
+        }
+        version = migrated;
+    }
+    Ok(bytes)
+}
+
+// format 1 stored the whole library as a single message after the header
+fn migrate_monolithic(bytes: Vec<u8>) -> Result<Vec<u8>, AssetPackError> {
+    let body = bytes.get(V1_HEADER_SIZE..).ok_or(AssetPackError::Truncated)?;
+    let expected = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+    let hash = content_hash(body);
+    if hash != expected {
+        return Err(AssetPackError::Checksum { expected, found: hash });
+    }
+
+    let library: MonolithicLibrary = rmp_serde::from_slice(body).map_err(AssetPackError::Decode)?;
+    let assets = AssetLibrary::new(
+        library.shaders,
+        library.textures,
+        library.models,
+        library.materials,
+        library.meshes,
+        upgrade_ui(library.ui),
+        library.fonts,
+    );
+    AssetCounts::from_bytes(&bytes[16..40]).check(&AssetCounts::of(&assets))?;
+    encode(&assets)
+}
+
+// decodes the core section of a sectioned pack as `C` and encodes the library `upgrade` makes of it
+fn migrate_core<C: DeserializeOwned>(bytes: Vec<u8>, upgrade: impl FnOnce(C) -> AssetLibrary) -> Result<Vec<u8>, AssetPackError> {
+    let (header, index) = read_index(&bytes)?;
+    let start = HEADER_SIZE + header.index_len as usize;
+    let core_start = start + index.core.offset as usize;
+    let core = bytes.get(core_start..core_start + index.core.length as usize).ok_or(AssetPackError::Truncated)?;
+    let mut assets = upgrade(decode_section(core, &index.core)?);
+
+    assets.sections = PackSections {
+        source: Some(SectionSource::Memory(Arc::new(bytes))),
+        start: start as u64,
+        textures: index.textures,
+        meshes: index.meshes,
+    };
+    header.counts.check(&AssetCounts::of(&assets))?;
+    assets.load_pending_textures()?;
+    assets.load_pending_meshes()?;
+    encode(&assets)
+}
+
+// format 4 had no fonts in the core section
+fn migrate_fontless_core(bytes: Vec<u8>) -> Result<Vec<u8>, AssetPackError> {
+    migrate_core(bytes, |(shaders, models, materials, ui): FontlessCoreSection| {
+        AssetLibrary::new(shaders, HashMap::new(), models, materials, HashMap::new(), upgrade_ui(ui), HashMap::new())
+    })
+}
+
+// format 5 placed ui elements with an anchor point, a position and a size
+fn migrate_anchorless_ui(bytes: Vec<u8>) -> Result<Vec<u8>, AssetPackError> {
+    migrate_core(bytes, |(shaders, models, materials, ui, fonts): AnchorlessCoreSection| {
+        AssetLibrary::new(shaders, HashMap::new(), models, materials, HashMap::new(), upgrade_ui(ui), fonts)
+    })
+}
+
+fn decode_with(bytes: Vec<u8>, migrations: &[(u32, Migration)]) -> Result<AssetLibrary, AssetPackError> {
+    let bytes = Arc::new(migrate(bytes, migrations)?);
+    let (header, index) = read_index(&bytes)?;
+    let start = HEADER_SIZE + header.index_len as usize + index.core.offset as usize;
+    let core = bytes.get(start..start + index.core.length as usize).ok_or(AssetPackError::Truncated)?;
+    open(header, index, core, SectionSource::Memory(bytes.clone()))
+}
+
+pub fn decode(bytes: Vec<u8>) -> Result<AssetLibrary, AssetPackError> {
+    decode_with(bytes, MIGRATIONS)
+}
+
+pub fn save(path: &str, assets: &AssetLibrary) -> Result<(), AssetPackError> {
+    let bytes = encode(assets)?;
+    fs::write(path, bytes).map_err(|error| AssetPackError::Io { path: path.to_string(), error })
+}
+
+// only the header, index and core section are read here, see PackSections
+pub fn load(path: &str) -> Result<AssetLibrary, AssetPackError> {
+    let io_error = |error| AssetPackError::Io { path: path.to_string(), error };
+    let mut file = fs::File::open(path).map_err(io_error)?;
+    let mut prefix = Vec::with_capacity(HEADER_SIZE);
+    (&mut file).take(HEADER_SIZE as u64).read_to_end(&mut prefix).map_err(io_error)?;
+
+    if AssetPackHeader::version(&prefix)? != FORMAT_VERSION {
+        let bytes = fs::read(path).map_err(io_error)?;
+        return decode(bytes);
+    }
+
+    let header = AssetPackHeader::read(&prefix)?;
+    let mut index = vec![0; header.index_len as usize];
+    file.read_exact(&mut index).map_err(|_| AssetPackError::Truncated)?;
+    prefix.extend_from_slice(&index);
+    let (header, index) = read_index(&prefix)?;
+
+    let mut core = vec![0; index.core.length as usize];
+    file.seek(SeekFrom::Current(index.core.offset as i64)).map_err(io_error)?;
+    file.read_exact(&mut core).map_err(|_| AssetPackError::Truncated)?;
+    open(header, index, &core, SectionSource::File(path.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+    use uuid::Uuid;
+
+    use std::collections::HashMap;
+
+    use crate::{
+        asset_library::AssetLibrary,
+        rendering::VertexData,
+        types::{font::{Font, Glyph}, mesh::{IndexData, Mesh}, model::Model, vectors::Vec2f},
+        ui::ui_layout::{Anchor, UiAnchors, UiElementType, UiLayoutKind, UiSpriteMode},
+    };
+
+    use super::{
+        content_hash, decode, decode_with, encode, AssetCounts, AssetPackError, AssetPackHeader, LegacyUiElement,
+        Migration, PackIndex, SectionWriter, FORMAT_VERSION, HEADER_SIZE,
+    };
+
+    fn library() -> AssetLibrary {
+        let mut assets = AssetLibrary::default();
+        assets.models.insert(Uuid::new_v4(), Model::new("ship.gltf".to_string()));
+        assets.meshes.insert(Uuid::new_v4(), Mesh::new("ship0", vec![VertexData::zeroed(); 3], vec![0, 1, 2]));
+        assets
+    }
+
+    fn font() -> Font {
+        let glyph = Glyph {
+            uv_min: Vec2f::new([0.0, 0.0]),
+            uv_max: Vec2f::new([0.5, 0.5]),
+            size: Vec2f::new([0.4, 0.6]),
+            offset: Vec2f::new([0.0, 0.0]),
+            advance: 0.5,
+        };
+        Font {
+            name: "mono.ttf".to_string(),
+            atlas: "mono.ttf".to_string(),
+            ascent: 0.8,
+            line_height: 1.2,
+            glyphs: HashMap::from([('A', glyph), ('V', glyph)]),
+            kerning: HashMap::from([(('A', 'V'), -0.1)]),
+            replacement: glyph,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_with_lazy_meshes() {
+        let mut assets = decode(encode(&library()).unwrap()).unwrap();
+        assert!(assets.model_by_name("ship.gltf").is_some());
+        assert!(assets.meshes.is_empty());
+        assert_eq!(assets.sections.pending_meshes(), 1);
+
+        assets.load_pending_meshes().unwrap();
+        assert_eq!(assets.sections.pending_meshes(), 0);
+        assert_eq!(assets.mesh_by_name("ship0").unwrap().1.indices, IndexData::U16(vec![0, 1, 2]));
+        assert_eq!(assets.mesh_by_name("ship0").unwrap().1.aabb, library().meshes.values().next().unwrap().aabb);
+    }
+
+    #[test]
+    fn test_round_trip_with_fonts() {
+        let mut original = library();
+        original.fonts.insert(Uuid::new_v4(), font());
+        let assets = decode(encode(&original).unwrap()).unwrap();
+        let (_, font) = assets.font_by_name("mono.ttf").unwrap();
+        assert_eq!(font.kerning('A', 'V'), -0.1);
+        assert_eq!(font.glyph('V'), &font.glyphs[&'A']);
+    }
+
+    #[test]
+    fn test_rejects_damaged_packs() {
+        let bytes = encode(&library()).unwrap();
+        assert!(matches!(decode(bytes[..HEADER_SIZE - 1].to_vec()), Err(AssetPackError::Truncated)));
+        assert!(matches!(decode(bytes[HEADER_SIZE..].to_vec()), Err(AssetPackError::NotAPack)));
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        let mut assets = decode(corrupted).unwrap();
+        assert!(matches!(assets.load_pending_meshes(), Err(AssetPackError::Checksum { .. })));
+
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            decode(newer),
+            Err(AssetPackError::UnsupportedVersion { found, expected }) if found == FORMAT_VERSION + 1 && expected == FORMAT_VERSION
+        ));
+
+        let mut miscounted = bytes.clone();
+        miscounted[24..28].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(decode(miscounted), Err(AssetPackError::CountMismatch { category: "models", .. })));
+    }
+
+    #[test]
+    fn test_migrates_monolithic_packs() {
+        let assets = library();
+        let body = rmp_serde::to_vec(&assets).unwrap();
+        let mut older = Vec::new();
+        older.extend_from_slice(b"OXPK");
+        older.extend_from_slice(&1u32.to_le_bytes());
+        older.extend_from_slice(&content_hash(&body).to_le_bytes());
+        for count in [0u32, 0, 1, 0, 1, 0] {
+            older.extend_from_slice(&count.to_le_bytes());
+        }
+        older.extend_from_slice(&body);
+
+        let no_migrations: &[(u32, Migration)] = &[];
+        assert!(matches!(
+            decode_with(older.clone(), no_migrations),
+            Err(AssetPackError::UnsupportedVersion { found: 1, .. })
+        ));
+
+        let mut migrated = decode(older).unwrap();
+        migrated.load_pending_meshes().unwrap();
+        assert!(migrated.model_by_name("ship.gltf").is_some());
+        assert!(migrated.mesh_by_name("ship0").is_some());
+    }
+
+    #[test]
+    fn test_migrates_packs_without_fonts() {
+        let assets = library();
+        let mut sections = SectionWriter { bytes: Vec::new() };
+        let core = sections.push(&(&assets.shaders, &assets.models, &assets.materials, &assets.ui)).unwrap();
+        let meshes = assets.meshes.iter().map(|(uuid, mesh)| (*uuid, sections.push(mesh).unwrap())).collect();
+        let index = rmp_serde::to_vec(&PackIndex { core, textures: vec![], meshes }).unwrap();
+
+        let mut older = Vec::new();
+        AssetPackHeader {
+            version: 4,
+            hash: content_hash(&index),
+            counts: AssetCounts::of(&assets),
+            index_len: index.len() as u32,
+        }
+        .write(&mut older);
+        older.extend_from_slice(&index);
+        older.extend_from_slice(&sections.bytes);
+
+        let mut migrated = decode(older).unwrap();
+        migrated.load_pending_meshes().unwrap();
+        assert!(migrated.fonts.is_empty());
+        assert!(migrated.model_by_name("ship.gltf").is_some());
+        assert!(migrated.mesh_by_name("ship0").is_some());
+    }
+
+    #[test]
+    fn test_migrates_anchorless_ui() {
+        let assets = library();
+        let uuid = Uuid::new_v4();
+        let legacy = LegacyUiElement {
+            element_type: UiElementType::None,
+            name: "bar".to_string(),
+            material: Uuid::nil(),
+            position: Vec2f::new([0.1, -0.2]),
+            screen_anchor: Anchor::UpLeft,
+            width: 0.4,
+            height: 0.1,
+            mesh: None,
+            layout: UiLayoutKind::VerticalStack { spacing: 0.01 },
+            parent: None,
+            children: vec![],
+            sprite_mode: UiSpriteMode::Stretch,
+            visible: false,
+            opacity: 0.5,
+            clip_children: true,
+            focusable: false,
+        };
+        let ui = HashMap::from([(uuid, legacy)]);
+        let mut sections = SectionWriter { bytes: Vec::new() };
+        let core = sections.push(&(&assets.shaders, &assets.models, &assets.materials, &ui, &assets.fonts)).unwrap();
+        let index = rmp_serde::to_vec(&PackIndex { core, textures: vec![], meshes: vec![] }).unwrap();
+
+        let mut older = Vec::new();
+        let mut counts = AssetCounts::of(&assets);
+        counts.meshes = 0;
+        counts.ui = 1;
+        AssetPackHeader { version: 5, hash: content_hash(&index), counts, index_len: index.len() as u32 }.write(&mut older);
+        older.extend_from_slice(&index);
+        older.extend_from_slice(&sections.bytes);
+
+        let migrated = decode(older).unwrap();
+        let element = &migrated.ui[&uuid];
+        let anchors = element.anchors();
+        assert_eq!(anchors, UiAnchors::from_anchor(Anchor::UpLeft, Vec2f::new([0.1, -0.2]), 0.4, 0.1));
+        assert_eq!(anchors.anchor_min, Vec2f::new([0.0, 0.0]));
+        assert!((anchors.offset_min.y - 0.15).abs() < 1e-5 && (anchors.offset_max.x - 0.3).abs() < 1e-5);
+        assert_eq!(element.layout, UiLayoutKind::VerticalStack { spacing: 0.01 });
+        assert!(!element.visible && element.clip_children);
+        assert_eq!(element.opacity(), 0.5);
+    }
+}
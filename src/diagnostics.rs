@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+#[cfg(feature = "dev_tools")]
+use std::cell::Cell;
+
+#[cfg(feature = "dev_tools")]
+use uuid::Uuid;
+#[cfg(feature = "dev_tools")]
+use winit::keyboard::{Key, NamedKey};
+
+use crate::{ecs::World, state::State};
+#[cfg(feature = "dev_tools")]
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::System,
+    types::vectors::{Vec2f, Vec4f},
+    ui::{
+        ui_layout::{UiAnchors, UiElement, UiElementType},
+        ui_text::UiText,
+    },
+};
+
+// systems listed by the overlay, the slowest first
+const SHOWN_SYSTEMS: usize = 5;
+
+// what the last frame cost, filled in every frame with or without a window
+#[derive(Debug, Default, Clone)]
+pub struct Diagnostics {
+    pub frame: u64,
+    // unscaled seconds since the start
+    pub elapsed: f64,
+    // seconds, smoothed over the last few dozen frames
+    pub frame_time: f64,
+    pub fps: f64,
+    // on_update of every system in the order they ran
+    pub system_times: Vec<(&'static str, Duration)>,
+    // of the last frame drawn, zero headless
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub entity_count: u32,
+    // candidate pairs the physics broadphase found in the last fixed step
+    pub pair_count: u32,
+}
+
+// the type name without its path, generic arguments included
+fn short_name(name: &str) -> &str {
+    let path = name.split('<').next().unwrap_or(name);
+    match path.rfind("::") {
+        Some(i) => &name[i + 2..],
+        None => name,
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Diagnostics {
+    // columns of to_csv_row, the systems as they ran this frame
+    pub fn csv_header(&self) -> String {
+        let mut header = String::from("frame,elapsed,frame_time_ms,fps,draw_calls,triangles,entities,pairs");
+        for (name, _) in self.system_times.iter() {
+            header.push_str(&format!(",{}_ms", short_name(name).replace([',', '"'], "_")));
+        }
+        header
+    }
+
+    // one line of a performance trace, times in milliseconds
+    pub fn to_csv_row(&self) -> String {
+        let mut row = format!(
+            "{},{:.4},{:.4},{:.2},{},{},{},{}",
+            self.frame,
+            self.elapsed,
+            self.frame_time * 1000.0,
+            self.fps,
+            self.draw_calls,
+            self.triangles,
+            self.entity_count,
+            self.pair_count
+        );
+        for (_, time) in self.system_times.iter() {
+            row.push_str(&format!(",{:.4}", millis(*time)));
+        }
+        row
+    }
+
+    // what the overlay shows
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{:.0} fps {:.2} ms\ndraws {} triangles {}\nentities {} pairs {}",
+            self.fps,
+            self.frame_time * 1000.0,
+            self.draw_calls,
+            self.triangles,
+            self.entity_count,
+            self.pair_count
+        );
+        let mut systems: Vec<&(&'static str, Duration)> = self.system_times.iter().collect();
+        systems.sort_by_key(|x| std::cmp::Reverse(x.1));
+        for (name, time) in systems.into_iter().take(SHOWN_SYSTEMS) {
+            summary.push_str(&format!("\n{} {:.2} ms", short_name(name), millis(*time)));
+        }
+        summary
+    }
+}
+
+// after the update, the system times were recorded while it ran
+pub(crate) fn record_frame(world: &World, state: &mut State) {
+    let stats = state.renderer.get().map(|x| x.last_frame_stats).unwrap_or_default();
+    let entity_count = world.entities.borrow().len();
+    let time = &state.time_struct;
+    let diagnostics = &mut state.diagnostics;
+    diagnostics.frame = time.frame_count;
+    diagnostics.elapsed = time.unscaled_elapsed;
+    diagnostics.frame_time = time.smoothed_delta;
+    diagnostics.fps = if time.smoothed_delta > 0.0 { 1.0 / time.smoothed_delta } else { 0.0 };
+    diagnostics.draw_calls = stats.draws;
+    diagnostics.triangles = stats.triangles;
+    diagnostics.entity_count = entity_count;
+    diagnostics.pair_count = state.pair_count;
+}
+
+// state.diagnostics as text in the top left corner, `toggle` shows and hides it, `material` is a text
+// material with the atlas of `font` attached, the text changes a few times a second so it stays readable
+// and the ui mesh isn't rebuilt every frame
+#[cfg(feature = "dev_tools")]
+pub struct DiagnosticsOverlay {
+    pub toggle: Key,
+    pub font: String,
+    pub material: String,
+    // like UiText::size
+    pub size: f32,
+    pub color: Vec4f,
+    pub anchors: UiAnchors,
+    // seconds between refreshes
+    pub refresh_interval: f64,
+    element: Cell<Option<Uuid>>,
+    last_refresh: Cell<f64>,
+}
+
+#[cfg(feature = "dev_tools")]
+impl DiagnosticsOverlay {
+    pub fn new(font: &str, material: &str) -> DiagnosticsOverlay {
+        DiagnosticsOverlay {
+            toggle: Key::Named(NamedKey::F3),
+            font: font.to_string(),
+            material: material.to_string(),
+            size: 0.04,
+            color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+            anchors: UiAnchors {
+                anchor_min: Vec2f::new([0.0, 0.0]),
+                anchor_max: Vec2f::new([0.0, 0.0]),
+                offset_min: Vec2f::new([0.02, 0.02]),
+                offset_max: Vec2f::new([0.8, 0.5]),
+            },
+            refresh_interval: 0.25,
+            element: Cell::new(None),
+            last_refresh: Cell::new(f64::NEG_INFINITY),
+        }
+    }
+
+    fn spawn(&self, assets: &mut AssetLibrary) -> Option<Uuid> {
+        let Some((material, _)) = assets.material_by_name(&self.material) else {
+            log::error!("Diagnostics overlay material {} not found", self.material);
+            return None;
+        };
+        let text = UiText { content: String::new(), font: self.font.clone(), size: self.size, color: self.color };
+        let mut element = UiElement::with_anchors("diagnostics_overlay", UiElementType::Text(text), material, self.anchors);
+        element.overlay = true;
+        element.visible = false;
+        Some(assets.ui_add(element))
+    }
+}
+
+#[cfg(feature = "dev_tools")]
+impl System for DiagnosticsOverlay {
+    fn on_start(&self, _world: &World, assets: &mut AssetLibrary, _state: &mut State) {
+        self.element.set(self.spawn(assets));
+    }
+
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let Some(element) = self.element.get().and_then(|x| assets.ui.get_mut(&x)) else {
+            return;
+        };
+        if state.input.key_pressed.contains(&self.toggle) {
+            element.visible = !element.visible;
+            self.last_refresh.set(f64::NEG_INFINITY);
+        }
+        let now = state.time_struct.unscaled_elapsed;
+        if !element.visible || now - self.last_refresh.get() < self.refresh_interval {
+            return;
+        }
+        self.last_refresh.set(now);
+        element.set_text(&state.diagnostics.summary());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{short_name, Diagnostics};
+
+    #[test]
+    fn test_csv_row_matches_header() {
+        let diagnostics = Diagnostics {
+            frame: 120,
+            elapsed: 2.0,
+            frame_time: 0.016,
+            fps: 60.0,
+            system_times: vec![
+                ("oxide_engine::types::transform::TransformUpdater", Duration::from_micros(250)),
+                ("oxide_engine::rendering::RendererHandler", Duration::from_millis(3)),
+            ],
+            draw_calls: 40,
+            triangles: 12000,
+            entity_count: 300,
+            pair_count: 7,
+        };
+        assert_eq!(
+            diagnostics.csv_header(),
+            "frame,elapsed,frame_time_ms,fps,draw_calls,triangles,entities,pairs,TransformUpdater_ms,RendererHandler_ms"
+        );
+        assert_eq!(diagnostics.to_csv_row(), "120,2.0000,16.0000,60.00,40,12000,300,7,0.2500,3.0000");
+
+        // slowest system first
+        let summary = diagnostics.summary();
+        assert!(summary.starts_with("60 fps 16.00 ms\n"));
+        assert!(summary.ends_with("RendererHandler 3.00 ms\nTransformUpdater 0.25 ms"));
+    }
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name("game::systems::Spawner"), "Spawner");
+        assert_eq!(short_name("game::Pool<game::Bullet>"), "Pool<game::Bullet>");
+        assert_eq!(short_name("Spawner"), "Spawner");
+    }
+}
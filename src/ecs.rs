@@ -1,13 +1,22 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, time::Instant};
 
 use log::trace;
 use uuid::Uuid;
+use winit::event::Event;
 
-use crate::{asset_library::AssetLibrary, state::State};
+use crate::{asset_library::AssetLibrary, events::RawEventHandler, state::State};
 
 pub trait System {
     fn on_start(&self, world: &World, assets: &mut AssetLibrary, state: &mut State);
     fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State);
+    // called every PhysicsSettings::timestep of scaled time, zero or more times before on_update
+    fn on_fixed_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    // called once when the window is closed or run_headless is out of frames, before anything is dropped
+    fn on_exit(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    // how state.diagnostics lists the system
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 pub trait Callback {
@@ -18,6 +27,7 @@ pub struct World {
     pub entities: RefCell<hecs::World>,
     pub systems: Vec<Box<dyn System>>,
     pub callbacks: HashMap<Uuid, Box<dyn Callback>>,
+    pub raw_event_handlers: Vec<RawEventHandler>,
 }
 
 impl World {
@@ -25,7 +35,8 @@ impl World {
         World {
             entities: RefCell::new(hecs::World::new()),
             systems: Vec::new(),
-            callbacks: HashMap::new()
+            callbacks: HashMap::new(),
+            raw_event_handlers: Vec::new()
         }
     }
 
@@ -39,6 +50,14 @@ impl World {
         uuid
     }
 
+    // for winit events the engine doesn't handle itself, see RawEventHandler
+    pub fn add_raw_event_handler<F>(&mut self, handler: F)
+    where
+        F: 'static + FnMut(&Event<()>, &mut World, &mut State) -> bool,
+    {
+        self.raw_event_handlers.push(Box::new(handler));
+    }
+
     pub fn start(&mut self, assets: &mut AssetLibrary, state: &mut State) {
         for (i, system) in self.systems.iter().enumerate() {
             trace!("{}", i);
@@ -47,9 +66,26 @@ impl World {
     }
 
     pub fn update(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        // systems reading the diagnostics see the whole previous frame
+        let mut times = Vec::with_capacity(self.systems.len());
         for (i, system) in self.systems.iter().enumerate() {
             trace!("{}", i);
+            let start = Instant::now();
             system.on_update(self, assets, state);
+            times.push((system.name(), start.elapsed()));
+        }
+        state.diagnostics.system_times = times;
+    }
+
+    pub fn fixed_update(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        for system in self.systems.iter() {
+            system.on_fixed_update(self, assets, state);
+        }
+    }
+
+    pub fn exit(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        for system in self.systems.iter() {
+            system.on_exit(self, assets, state);
         }
     }
 }
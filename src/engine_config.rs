@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+
+use vulkano::{image::{SampleCount, SampleCounts}, swapchain::PresentMode};
+use winit::{keyboard::{Key, NamedKey}, window::Icon};
+
+use crate::{asset_pack::ASSET_PACK_PATH, frame_pacer::BACKGROUND_FRAME_RATE};
+
+// position in Window::monitors()
+pub type MonitorIndex = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    // a window covering the whole monitor, the one the window is on without an index
+    Borderless(Option<MonitorIndex>),
+    // takes the monitor the window is on over at its native resolution and highest refresh rate
+    Exclusive,
+}
+
+// environment variable that overrides EngineConfig::gpu, "integrated", "discrete", a device index or part of a name
+pub const GPU_ENV_VAR: &str = "OXIDE_GPU";
+
+// which vulkan device run_with_config renders with, devices that can't render to the window are never picked
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum GpuPreference {
+    // discrete, then integrated, then anything else
+    #[default]
+    Default,
+    // integrated first, for battery life on laptops
+    PreferIntegrated,
+    PreferDiscrete,
+    // the first device whose name contains this, ignoring case
+    ByName(String),
+    // position in the list of devices logged at startup
+    ByIndex(usize),
+}
+
+impl GpuPreference {
+    // the value of GPU_ENV_VAR, an empty one leaves the config alone
+    pub fn parse(value: &str) -> Option<GpuPreference> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        Some(match value.to_lowercase().as_str() {
+            "default" => GpuPreference::Default,
+            "integrated" => GpuPreference::PreferIntegrated,
+            "discrete" => GpuPreference::PreferDiscrete,
+            _ => match value.parse() {
+                Ok(index) => GpuPreference::ByIndex(index),
+                Err(_) => GpuPreference::ByName(value.to_string()),
+            },
+        })
+    }
+
+    // the config's preference unless GPU_ENV_VAR says otherwise
+    pub fn with_env_override(&self) -> GpuPreference {
+        std::env::var(GPU_ENV_VAR).ok().and_then(|x| GpuPreference::parse(&x)).unwrap_or_else(|| self.clone())
+    }
+}
+
+// how much of vulkan's validation run_with_config turns on, messages go to the log under the "vulkan" target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValidationMode {
+    #[default]
+    Off,
+    // VK_LAYER_KHRONOS_validation when it's installed, errors and warnings
+    Standard,
+    // also the info and verbose messages of the layers and the driver
+    Verbose,
+}
+
+// where assets are read from, relative paths start at the working directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetPaths {
+    // mounted in order, assets in a later pack replace the ones with the same name in earlier packs,
+    // with dev_tools the first one is written from the asset descriptions instead of read
+    pub packs: Vec<PathBuf>,
+    pub meshes: PathBuf,
+    pub textures: PathBuf,
+    pub fonts: PathBuf,
+    // glsl sources compiled with dev_tools
+    pub shaders: PathBuf,
+    // compiled spir-v
+    pub shader_binaries: PathBuf,
+}
+
+impl AssetPaths {
+    // the usual layout under a project directory, for running from somewhere else
+    pub fn with_root(root: impl AsRef<Path>) -> AssetPaths {
+        let root = root.as_ref();
+        let assets = root.join("assets");
+        AssetPaths {
+            packs: vec![root.join(ASSET_PACK_PATH)],
+            meshes: assets.join("meshes"),
+            textures: assets.join("textures"),
+            fonts: assets.join("fonts"),
+            shaders: assets.join("shaders"),
+            shader_binaries: assets.join("shaders").join("bin"),
+        }
+    }
+}
+
+impl Default for AssetPaths {
+    fn default() -> Self {
+        AssetPaths::with_root("")
+    }
+}
+
+// how run_with_config sets the engine up, sizes are in logical pixels
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub window_title: String,
+    // the platform picks a size without one
+    pub initial_size: Option<(u32, u32)>,
+    pub min_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    pub fullscreen: FullscreenMode,
+    // any image the image crate reads
+    pub icon: Option<PathBuf>,
+    pub asset_paths: AssetPaths,
+    // samples of the default render graph's color pass, lowered to what the device supports
+    pub msaa: SampleCount,
+    // falls back to Fifo, which every device has, when the surface doesn't support it
+    pub present_mode: PresentMode,
+    // keeps updating at the full rate while the window is unfocused or covered, for servers and music players
+    pub run_in_background: bool,
+    // updates per second otherwise, nothing is drawn while the window is minimized or covered either way
+    pub background_frame_rate: f32,
+    // requests an exit when pressed, Escape in dev_tools builds and nothing otherwise
+    pub quit_key: Option<Key>,
+    // overridden by the OXIDE_GPU environment variable
+    pub gpu: GpuPreference,
+    // slows every call down, a missing validation layer only logs a warning
+    pub vulkan_validation: ValidationMode,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            window_title: String::from("Oxide Engine"),
+            initial_size: None,
+            min_size: None,
+            resizable: true,
+            fullscreen: FullscreenMode::Windowed,
+            icon: None,
+            asset_paths: AssetPaths::default(),
+            msaa: SampleCount::Sample8,
+            present_mode: PresentMode::Fifo,
+            run_in_background: false,
+            background_frame_rate: BACKGROUND_FRAME_RATE,
+            quit_key: cfg!(feature = "dev_tools").then_some(Key::Named(NamedKey::Escape)),
+            gpu: GpuPreference::Default,
+            vulkan_validation: ValidationMode::Off,
+        }
+    }
+}
+
+// how run_headless drives the world without a window
+#[derive(Debug, Clone, Copy)]
+pub struct HeadlessConfig {
+    // runs until the process ends without a limit
+    pub max_frames: Option<u64>,
+    // seconds every frame advances the clock by, physics steps follow it like they follow real time
+    pub fixed_delta: f64,
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        HeadlessConfig { max_frames: None, fixed_delta: 1.0 / 60.0 }
+    }
+}
+
+// the most samples up to `requested` in `supported`, one sample always works
+pub fn supported_samples(requested: SampleCount, supported: SampleCounts) -> SampleCount {
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|x| (*x as u32) <= (requested as u32) && supported.contains_enum(*x))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+pub fn load_icon(path: &Path) -> Result<Icon, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?.into_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::image::{SampleCount, SampleCounts};
+
+    use super::{supported_samples, GpuPreference};
+
+    #[test]
+    fn test_msaa_lowered_to_supported() {
+        let supported = SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_2 | SampleCounts::SAMPLE_4;
+        assert_eq!(supported_samples(SampleCount::Sample8, supported), SampleCount::Sample4);
+        assert_eq!(supported_samples(SampleCount::Sample2, supported), SampleCount::Sample2);
+        assert_eq!(supported_samples(SampleCount::Sample1, supported), SampleCount::Sample1);
+        assert_eq!(supported_samples(SampleCount::Sample8, SampleCounts::SAMPLE_1), SampleCount::Sample1);
+    }
+
+    #[test]
+    fn test_parse_gpu_preference() {
+        assert_eq!(GpuPreference::parse("Integrated"), Some(GpuPreference::PreferIntegrated));
+        assert_eq!(GpuPreference::parse(" discrete "), Some(GpuPreference::PreferDiscrete));
+        assert_eq!(GpuPreference::parse("1"), Some(GpuPreference::ByIndex(1)));
+        assert_eq!(GpuPreference::parse("RTX 3060"), Some(GpuPreference::ByName(String::from("RTX 3060"))));
+        assert_eq!(GpuPreference::parse(""), None);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{error::Error, fmt, io};
+
+use winit::error::{EventLoopError, OsError};
+
+use crate::{asset_pack::AssetPackError, rendering::renderer_graph::RenderGraphError};
+
+// what stopped run from starting the engine, or the event loop from running
+#[derive(Debug)]
+pub enum EngineError {
+    AssetPack(AssetPackError),
+    EventLoop(EventLoopError),
+    WindowCreation(OsError),
+    // the vulkan library, instance or device
+    VulkanInit { source: Box<dyn Error> },
+    // no device with a graphics queue that can present to the window and the features the engine needs
+    NoSuitableDevice,
+    // the device GpuPreference::ByName or ByIndex asked for isn't there or can't be used, with every device found
+    GpuUnavailable { requested: String, candidates: Vec<String> },
+    // the window surface or its swapchain
+    SurfaceCreation { source: Box<dyn Error> },
+    RenderGraph(RenderGraphError),
+    // the compiled spir-v isn't there
+    MissingShader { name: String, path: String, error: io::Error },
+    // glsl that doesn't compile or spir-v the device rejects
+    ShaderCompile { name: String, message: String },
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::AssetPack(e) => write!(f, "{}", e),
+            EngineError::EventLoop(e) => write!(f, "event loop failed: {}", e),
+            EngineError::WindowCreation(e) => write!(f, "failed to create the window: {}", e),
+            EngineError::VulkanInit { source } => write!(f, "failed to initialize vulkan: {}", source),
+            EngineError::NoSuitableDevice => {
+                write!(f, "no vulkan device can render to this window, check the graphics drivers")
+            }
+            EngineError::GpuUnavailable { requested, candidates } => {
+                write!(f, "the requested gpu {} can't be used, devices found:", requested)?;
+                for candidate in candidates {
+                    write!(f, "\n  {}", candidate)?;
+                }
+                Ok(())
+            }
+            EngineError::SurfaceCreation { source } => write!(f, "failed to create the window surface: {}", source),
+            EngineError::RenderGraph(e) => write!(f, "invalid render graph: {:?}", e),
+            EngineError::MissingShader { name, path, error } => {
+                write!(f, "failed to read shader {} from {}: {}", name, path, error)
+            }
+            EngineError::ShaderCompile { name, message } => write!(f, "failed to compile shader {}: {}", name, message),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::EventLoop(e) => Some(e),
+            EngineError::WindowCreation(e) => Some(e),
+            EngineError::VulkanInit { source } | EngineError::SurfaceCreation { source } => Some(source.as_ref()),
+            EngineError::MissingShader { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<AssetPackError> for EngineError {
+    fn from(value: AssetPackError) -> Self {
+        EngineError::AssetPack(value)
+    }
+}
+
+impl From<RenderGraphError> for EngineError {
+    fn from(value: RenderGraphError) -> Self {
+        EngineError::RenderGraph(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asset_pack::{self, AssetPackError};
+
+    use super::EngineError;
+
+    #[test]
+    fn test_missing_asset_pack() {
+        let error = EngineError::from(asset_pack::load("definitely_missing.data").unwrap_err());
+        assert!(matches!(error, EngineError::AssetPack(AssetPackError::Io { .. })));
+        assert!(error.to_string().contains("definitely_missing.data"));
+    }
+}
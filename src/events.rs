@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use winit::event::Event;
+
+use crate::{ecs::World, state::State};
+
+// window events the engine turns into something systems can read from `state.events`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineEvent {
+    // dragged onto the window and let go, one event per file
+    FileDropped(PathBuf),
+}
+
+// sees every event the event loop gets as it arrives, before the engine handles it, so `state.input` doesn't
+// include it yet, systems run once the events of a frame are all in and the input manager forgets the presses
+// and releases after them, returning true consumes the event so later handlers and the engine never see it,
+// run_headless has no event loop and never calls them
+pub type RawEventHandler = Box<dyn FnMut(&Event<()>, &mut World, &mut State) -> bool>;
+
+// runs the handlers in the order they were added until one consumes `event`
+pub(crate) fn dispatch_raw_event(event: &Event<()>, world: &mut World, state: &mut State) -> bool {
+    // taken out so the handlers can have the world, ones added meanwhile go after them
+    let mut handlers = std::mem::take(&mut world.raw_event_handlers);
+    let consumed = handlers.iter_mut().any(|handler| handler(event, world, state));
+    handlers.append(&mut world.raw_event_handlers);
+    world.raw_event_handlers = handlers;
+    consumed
+}
@@ -0,0 +1,160 @@
+use std::time::{Duration, Instant};
+
+// default frame rate while the window is unfocused or covered
+pub const BACKGROUND_FRAME_RATE: f32 = 10.0;
+// fixed steps run in one frame at most, the rest of a long stall is dropped
+pub const MAX_FIXED_STEPS: usize = 8;
+
+pub fn effective_frame_rate(
+    target_frame_rate: Option<f32>,
+    background_frame_rate: f32,
+    foreground: bool,
+    run_when_unfocused: bool,
+) -> Option<f32> {
+    if foreground || run_when_unfocused {
+        return target_frame_rate;
+    }
+
+    match target_frame_rate {
+        Some(val) => Some(val.min(background_frame_rate)),
+        None => Some(background_frame_rate)
+    }
+}
+
+pub struct FramePacer {
+    last_frame: Option<Instant>
+}
+
+impl FramePacer {
+    pub fn new() -> FramePacer {
+        FramePacer { last_frame: None }
+    }
+
+    pub fn wait_until(&mut self, now: Instant, frame_rate: Option<f32>) -> Option<Instant> {
+        let (frame_rate, last_frame) = match (frame_rate, self.last_frame) {
+            (Some(frame_rate), Some(last_frame)) if frame_rate > 0.0 => (frame_rate, last_frame),
+            _ => {
+                self.last_frame = Some(now);
+                return None;
+            }
+        };
+
+        let frame_time = Duration::from_secs_f32(1.0 / frame_rate);
+        let deadline = last_frame + frame_time;
+        if now < deadline {
+            return Some(deadline);
+        }
+
+        // stepping from the deadline avoids drift, falling too far behind resets the schedule
+        self.last_frame = if now - deadline < frame_time { Some(deadline) } else { Some(now) };
+        None
+    }
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// scaled time not yet simulated by fixed steps
+#[derive(Debug, Default)]
+pub struct FixedTimestep {
+    accumulator: f64,
+    resumed: bool,
+}
+
+impl FixedTimestep {
+    pub fn new() -> FixedTimestep {
+        FixedTimestep { accumulator: 0.0, resumed: false }
+    }
+
+    // how many fixed steps of `timestep` to run for a frame of `delta_time`
+    pub fn advance(&mut self, delta_time: f64, time_scale: f32, timestep: f64) -> usize {
+        self.accumulator += delta_time * time_scale as f64;
+        if self.resumed {
+            self.accumulator = self.accumulator.min(timestep);
+            self.resumed = false;
+        }
+        let steps = (self.accumulator / timestep).floor() as usize;
+        self.accumulator -= steps as f64 * timestep;
+        if steps > MAX_FIXED_STEPS {
+            self.accumulator = 0.0;
+            return MAX_FIXED_STEPS;
+        }
+        steps
+    }
+
+    // the next frame runs one fixed step at most, so coming back from the background doesn't fast-forward physics
+    pub fn resume(&mut self) {
+        self.resumed = true;
+    }
+
+    // how far between the last fixed step and the next one the frame is, from 0 to 1
+    pub fn alpha(&self, timestep: f64) -> f32 {
+        (self.accumulator / timestep).clamp(0.0, 1.0) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{effective_frame_rate, FixedTimestep, FramePacer, BACKGROUND_FRAME_RATE, MAX_FIXED_STEPS};
+
+    #[test]
+    fn test_uncapped_never_waits() {
+        let mut pacer = FramePacer::new();
+        let now = Instant::now();
+        assert_eq!(pacer.wait_until(now, None), None);
+        assert_eq!(pacer.wait_until(now, None), None);
+    }
+
+    #[test]
+    fn test_capped_waits_for_deadline() {
+        let mut pacer = FramePacer::new();
+        let start = Instant::now();
+        assert_eq!(pacer.wait_until(start, Some(64.0)), None);
+
+        let early = start + Duration::from_millis(5);
+        assert_eq!(pacer.wait_until(early, Some(64.0)), Some(start + Duration::from_micros(15625)));
+
+        let late = start + Duration::from_millis(16);
+        assert_eq!(pacer.wait_until(late, Some(64.0)), None);
+        assert_eq!(pacer.wait_until(late, Some(64.0)), Some(start + Duration::from_micros(31250)));
+
+        let stalled = start + Duration::from_secs(1);
+        assert_eq!(pacer.wait_until(stalled, Some(64.0)), None);
+        assert_eq!(pacer.wait_until(stalled, Some(64.0)), Some(stalled + Duration::from_micros(15625)));
+    }
+
+    #[test]
+    fn test_background_rate() {
+        assert_eq!(effective_frame_rate(Some(144.0), BACKGROUND_FRAME_RATE, true, false), Some(144.0));
+        assert_eq!(effective_frame_rate(Some(144.0), BACKGROUND_FRAME_RATE, false, false), Some(BACKGROUND_FRAME_RATE));
+        assert_eq!(effective_frame_rate(None, BACKGROUND_FRAME_RATE, false, false), Some(BACKGROUND_FRAME_RATE));
+        assert_eq!(effective_frame_rate(None, BACKGROUND_FRAME_RATE, false, true), None);
+        assert_eq!(effective_frame_rate(Some(5.0), 30.0, false, false), Some(5.0));
+    }
+
+    #[test]
+    fn test_fixed_steps_follow_scaled_time() {
+        let mut fixed = FixedTimestep::new();
+        assert_eq!(fixed.advance(0.125, 1.0, 0.25), 0);
+        assert_eq!(fixed.alpha(0.25), 0.5);
+        assert_eq!(fixed.advance(0.75, 1.0, 0.25), 3);
+
+        // paused, nothing moves and the blend stays put
+        assert_eq!(fixed.advance(1.0, 0.0, 0.25), 0);
+        assert_eq!(fixed.alpha(0.25), 0.5);
+        assert_eq!(fixed.advance(0.25, 0.5, 0.25), 1);
+
+        assert_eq!(fixed.advance(10.0, 1.0, 0.25), MAX_FIXED_STEPS);
+        assert_eq!(fixed.alpha(0.25), 0.0);
+
+        // only the frame right after resuming is held back
+        fixed.resume();
+        assert_eq!(fixed.advance(1.0, 1.0, 0.25), 1);
+        assert_eq!(fixed.advance(1.0, 1.0, 0.25), 4);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use log::{debug, error, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use uuid::Uuid;
+use vulkano::shader::spirv::bytes_to_words;
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    engine_config::AssetPaths,
+    rendering::recreate_pipelines,
+    state::State,
+    types::{
+        mesh::load_model,
+        shader::validate_materials,
+        texture::{Texture, TextureKind},
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ReloadTarget {
+    Texture(String),
+    Shader(String),
+    Model(String),
+    Named(String),
+}
+
+// maps a changed file to the asset built from it
+fn classify(paths: &AssetPaths, path: &Path) -> Option<ReloadTarget> {
+    if let Ok(rest) = path.strip_prefix(&paths.shader_binaries) {
+        if rest.extension()? != "spv" {
+            return None;
+        }
+        return Some(ReloadTarget::Shader(rest.file_stem()?.to_str()?.to_string()));
+    }
+    if let Ok(rest) = path.strip_prefix(&paths.meshes) {
+        return match rest.extension()?.to_str()? {
+            "obj" | "gltf" | "glb" => Some(ReloadTarget::Model(rest.to_str()?.replace('\\', "/"))),
+            _ => None,
+        };
+    }
+    let rest = path.strip_prefix(&paths.textures).ok()?;
+    Some(ReloadTarget::Texture(rest.to_str()?.replace('\\', "/")))
+}
+
+fn reload_texture(assets: &mut AssetLibrary, state: &State, uuid: Uuid, paths: &AssetPaths) -> bool {
+    let old = &assets.textures[&uuid];
+    if state.asset_loading.is_uploading(&uuid) {
+        warn!("Texture {} is still uploading, skipping reload", old.name);
+        return false;
+    }
+    if old.storage || old.kind != TextureKind::D2 {
+        warn!("Texture {} can't be hot reloaded", old.name);
+        return false;
+    }
+
+    let mut texture = match Texture::new(old.name.clone(), old.srgb, old.generate_mips, paths) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("{}", e);
+            return false;
+        }
+    };
+    texture.load(state);
+    assets.textures.insert(uuid, texture);
+    true
+}
+
+fn reload_shader(assets: &mut AssetLibrary, state: &State, uuid: Uuid, paths: &AssetPaths) -> bool {
+    let shader = assets.shaders.get_mut(&uuid).unwrap();
+    let path = paths.shader_binaries.join(format!("{}.spv", shader.name));
+    let words = match fs::read(&path).ok().and_then(|x| bytes_to_words(&x).ok().map(|x| x.to_vec())) {
+        Some(val) => val,
+        None => {
+            error!("Failed to read shader {}", path.display());
+            return false;
+        }
+    };
+
+    let previous = std::mem::replace(&mut shader.source, words);
+    if let Err(e) = shader.try_load(&state.vulkan_context) {
+        error!("Failed to load shader {}: {}", shader.name, e);
+        shader.source = previous;
+        return false;
+    }
+    true
+}
+
+// mesh uuids in node order, shared meshes only once
+fn unique_meshes(meshes_and_materials: Vec<(Uuid, Uuid)>) -> Vec<Uuid> {
+    let mut seen = HashSet::new();
+    meshes_and_materials.into_iter().map(|(mesh, _)| mesh).filter(|x| seen.insert(*x)).collect()
+}
+
+fn reload_model(assets: &mut AssetLibrary, state: &State, uuid: Uuid, paths: &AssetPaths) -> bool {
+    let model_name = assets.models[&uuid].name.clone();
+    let loaded = match load_model(&model_name, assets, paths) {
+        Ok(val) => val,
+        Err(_) => {
+            error!("Failed to reload model {}", model_name);
+            return false;
+        }
+    };
+
+    // new geometry is queued on the old meshes so they keep drawing until the upload finishes
+    let old = unique_meshes(assets.models[&uuid].meshes_and_materials());
+    let model = assets.models.get_mut(&uuid).unwrap();
+    model.nodes = loaded.nodes;
+    model.skins = loaded.skins;
+    model.animations = loaded.animations;
+    let new = unique_meshes(model.meshes_and_materials());
+
+    let mut remap = HashMap::new();
+    for (id, mesh_uuid) in new.into_iter().enumerate() {
+        match old.get(id) {
+            Some(old_mesh) if assets.meshes.contains_key(old_mesh) => {
+                let mesh = assets.meshes.remove(&mesh_uuid).unwrap();
+                let target = assets.meshes.get_mut(old_mesh).unwrap();
+                target.skin = mesh.skin;
+                target.load(state, mesh.vertices, mesh.indices.to_u32());
+                remap.insert(mesh_uuid, *old_mesh);
+            }
+            _ => {
+                let mesh = assets.meshes.get_mut(&mesh_uuid).unwrap();
+                mesh.load_immidiate(state, mesh.vertices.clone(), mesh.indices.to_u32());
+            }
+        }
+    }
+    assets.models.get_mut(&uuid).unwrap().visit_primitives_mut(|(mesh, _)| {
+        if let Some(old_mesh) = remap.get(mesh) {
+            *mesh = *old_mesh;
+        }
+    });
+
+    for (_, texture) in assets.textures.iter_mut().filter(|(uuid, x)| !x.is_loaded() && !state.asset_loading.is_uploading(uuid)) {
+        texture.load(state);
+    }
+    for (_, material) in assets.materials.iter_mut().filter(|(_, x)| x.parameters.is_some() && x.parameter_buffers.is_none()) {
+        material.load(state);
+    }
+    true
+}
+
+fn find_by_name(assets: &AssetLibrary, name: &str) -> Option<ReloadTarget> {
+    if assets.texture_by_name(name).is_some() {
+        Some(ReloadTarget::Texture(name.to_string()))
+    } else if assets.shader_by_name(name).is_some() {
+        Some(ReloadTarget::Shader(name.to_string()))
+    } else if assets.model_by_name(name).is_some() {
+        Some(ReloadTarget::Model(name.to_string()))
+    } else {
+        None
+    }
+}
+
+pub struct AssetHotReload {
+    paths: AssetPaths,
+    // the same directories as the watcher reports them
+    watched: AssetPaths,
+    events: Receiver<notify::Result<notify::Event>>,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl AssetHotReload {
+    pub fn new(paths: &AssetPaths) -> AssetHotReload {
+        let (sender, events) = mpsc::channel();
+        let canonical = |x: &PathBuf| fs::canonicalize(x).unwrap_or_else(|_| x.clone());
+        let watched = AssetPaths {
+            packs: Vec::new(),
+            meshes: canonical(&paths.meshes),
+            textures: canonical(&paths.textures),
+            fonts: canonical(&paths.fonts),
+            shaders: canonical(&paths.shaders),
+            shader_binaries: canonical(&paths.shader_binaries),
+        };
+        let watcher = notify::recommended_watcher(sender).map(|mut watcher| {
+            for dir in [&watched.textures, &watched.shader_binaries, &watched.meshes] {
+                if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+                    warn!("Not watching {} for changes: {}", dir.display(), e);
+                }
+            }
+            watcher
+        });
+        if let Err(e) = watcher.as_ref() {
+            error!("Asset hot reload disabled: {}", e);
+        }
+
+        AssetHotReload {
+            paths: paths.clone(),
+            watched,
+            events,
+            _watcher: watcher.ok(),
+        }
+    }
+
+    fn reload(&self, target: ReloadTarget, assets: &mut AssetLibrary, state: &mut State) -> bool {
+        debug!("Reloading {:?}", target);
+        match target {
+            ReloadTarget::Texture(name) => match assets.texture_by_name(&name) {
+                Some((uuid, _)) => reload_texture(assets, state, uuid, &self.paths),
+                None => false,
+            },
+            ReloadTarget::Shader(name) => match assets.shader_by_name(&name) {
+                Some((uuid, _)) => reload_shader(assets, state, uuid, &self.paths),
+                None => false,
+            },
+            ReloadTarget::Model(name) => match assets.model_by_name(&name) {
+                Some((uuid, _)) => reload_model(assets, state, uuid, &self.paths),
+                None => false,
+            },
+            ReloadTarget::Named(name) => {
+                if let Some(material) = assets.material_mut_by_name(&name) {
+                    material.load(state);
+                    return true;
+                }
+                match find_by_name(assets, &name) {
+                    Some(target) => self.reload(target, assets, state),
+                    None => {
+                        warn!("No asset named {} to reload", name);
+                        false
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for AssetHotReload {
+    fn default() -> Self {
+        Self::new(&AssetPaths::default())
+    }
+}
+
+impl System for AssetHotReload {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    // runs as a regular system, so reloads always happen between frames
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let mut targets = HashSet::new();
+        while let Ok(event) = self.events.try_recv() {
+            let event = match event {
+                Ok(val) => val,
+                Err(e) => {
+                    error!("{}", e);
+                    continue;
+                }
+            };
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                targets.extend(event.paths.iter().filter_map(|x| classify(&self.watched, x)));
+            }
+        }
+        targets.extend(state.asset_reload_requests.drain(..).map(ReloadTarget::Named));
+        if targets.is_empty() {
+            return;
+        }
+
+        let mut reloaded = false;
+        for target in targets {
+            reloaded |= self.reload(target, assets, state);
+        }
+
+        if reloaded {
+            state.renderer.pipelines.clear();
+            validate_materials(assets, state);
+            recreate_pipelines(assets, state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::engine_config::AssetPaths;
+
+    use super::{classify, ReloadTarget};
+
+    #[test]
+    fn test_classify_changed_files() {
+        let assets = &AssetPaths::with_root("/game");
+        assert_eq!(
+            classify(assets, Path::new("/game/assets/textures/ship/hull.png")),
+            Some(ReloadTarget::Texture("ship/hull.png".to_string()))
+        );
+        assert_eq!(
+            classify(assets, Path::new("/game/assets/shaders/bin/lit.spv")),
+            Some(ReloadTarget::Shader("lit".to_string()))
+        );
+        assert_eq!(
+            classify(assets, Path::new("/game/assets/meshes/ship.gltf")),
+            Some(ReloadTarget::Model("ship.gltf".to_string()))
+        );
+        assert_eq!(classify(assets, Path::new("/game/assets/shaders/lit.frag")), None);
+        assert_eq!(classify(assets, Path::new("/game/assets.data")), None);
+    }
+}
@@ -58,13 +58,12 @@ impl InputManager {
     }
 
     pub fn get_mouse_delta(&self) -> Vec2f {
-        if self.prev_mouse_pos.is_none() {
-            Vec2f::new([0.0, 0.0])
-        } else {
-            Vec2f::new([
-                self.mouse_pos.x - self.prev_mouse_pos.unwrap().x,
-                self.mouse_pos.y - self.prev_mouse_pos.unwrap().y,
-            ])
+        match self.prev_mouse_pos {
+            None => Vec2f::new([0.0, 0.0]),
+            Some(prev_mouse_pos) => Vec2f::new([
+                self.mouse_pos.x - prev_mouse_pos.x,
+                self.mouse_pos.y - prev_mouse_pos.y,
+            ]),
         }
     }
 
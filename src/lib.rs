@@ -1,5 +1,7 @@
 pub mod asset_library;
 pub mod asset_descriptions;
+pub mod asset_pack;
+pub mod asset_loading;
 pub mod ecs;
 pub mod input;
 pub mod rendering;
@@ -10,20 +12,42 @@ pub mod vulkan;
 pub mod ui;
 pub mod physics;
 pub mod assets;
+pub mod frame_pacer;
+pub mod engine_config;
+pub mod diagnostics;
+pub mod engine_error;
+pub mod events;
+pub mod time;
+#[cfg(feature = "dev_tools")]
+pub mod hot_reload;
 
-use std::fs;
 use std::time::Instant;
 
 use asset_descriptions::AssetDescriptions;
+use asset_library::AssetLibrary;
+use asset_loading::{AssetLoading, DEFAULT_UPLOAD_WORKERS};
+use diagnostics::Diagnostics;
 use ecs::World;
+use engine_config::{AssetPaths, EngineConfig, HeadlessConfig};
+use engine_error::EngineError;
+use events::{dispatch_raw_event, EngineEvent};
+use frame_pacer::{effective_frame_rate, FixedTimestep, FramePacer, BACKGROUND_FRAME_RATE};
 use input::{InputManager, InputManagerUpdater};
 use log::trace;
-use physics::collision_handler::CollisionHandler;
-use physics::rigidbody::RigidbodyHandler;
+use physics::{character_controller::CharacterControllerSystem, debug_draw::PhysicsDebugRenderer, joint::JointSolver, settings::PhysicsSettings};
+#[cfg(not(feature = "rapier"))]
+use physics::{collision_handler::CollisionHandler, rigidbody::RigidbodyHandler};
+#[cfg(feature = "rapier")]
+use physics::rapier::RapierPhysics;
+use rendering::particles::ParticleUpdater;
+use rendering::{debug_lines::DebugLines, picking::PickingHandler};
 use rendering::{EventLoop, Renderer, RendererHandler, Window};
-use state::State;
+use state::{State, Windowed};
+use time::Time;
+use types::animation::AnimationSystem;
 use types::camera::CameraUpdater;
-use types::material::MaterialLoader;
+use types::camera_controller::{FollowCameraSystem, OrbitCameraSystem};
+use types::material::{MaterialLoader, MaterialUpdater};
 use types::mesh::{DynamicMeshMaterialLoader, MeshBufferLoader};
 use types::model::ModelComponentUuidLoader;
 use types::shader::ShaderLoader;
@@ -31,14 +55,15 @@ use types::texture::{DefaultTextureLoader, TextureLoader};
 use types::transform::TransformUpdater;
 
 use types::vectors::Vec2f;
-use ui::ui_layout::{UiHandler, UiMeshBuilder};
+use ui::{ui_focus::UiFocus, ui_layout::{UiHandler, UiMeshBuilder}};
 use vulkan::context::VulkanContext;
 use vulkan::memory::MemoryAllocators;
+use vulkano::device::Features;
 use winit::event::DeviceEvent::MouseMotion;
 use winit::event::MouseScrollDelta;
 use winit::event::WindowEvent::KeyboardInput;
 use winit::event::{ElementState, Event, KeyEvent, WindowEvent::{self, MouseInput}};
-use winit::event_loop::ControlFlow;
+use winit::event_loop::{ActiveEventLoop, ControlFlow};
 
 pub use winit;
 pub use vulkano;
@@ -47,152 +72,368 @@ pub use log;
 pub use uuid;
 pub use image;
 
-pub fn run(mut world: World, asset_descriptions: AssetDescriptions) {
+pub fn run(world: World, asset_descriptions: AssetDescriptions) -> Result<(), EngineError> {
+    run_with_config(world, asset_descriptions, EngineConfig::default())
+}
+
+// everything that can fail is set up before the event loop starts, so errors come back before the window shows
+pub fn run_with_config(mut world: World, asset_descriptions: AssetDescriptions, config: EngineConfig) -> Result<(), EngineError> {
     env_logger::init();
-    let timer = Instant::now();
+    let paths = &config.asset_paths;
+    let pack_path = |index: usize| paths.packs.get(index).map(|x| x.to_string_lossy().into_owned());
 
     let mut assets = if cfg!(feature = "dev_tools") {
         log::debug!("Recreating asset pack...");
-        let mut assets = asset_descriptions.generate_library();
-        types::mesh::load_model_meshes(&mut assets);
-        let _ = std::fs::write("assets.data", rmp_serde::to_vec(&assets).unwrap());
+        let mut assets = asset_descriptions.generate_library(paths)?;
+        types::mesh::load_model_meshes(&mut assets, paths);
+        if let Some(Err(e)) = pack_path(0).map(|x| asset_pack::save(&x, &assets)) {
+            log::error!("{}", e);
+        }
         assets
     } else {
-        rmp_serde::from_slice(fs::read("assets.data").unwrap().as_slice()).unwrap()
+        match pack_path(0) {
+            Some(path) => {
+                let pack_timer = Instant::now();
+                let assets = asset_pack::load(&path)?;
+                log::debug!("Opened asset pack {} in {:.1?}", path, pack_timer.elapsed());
+                assets
+            }
+            None => AssetLibrary::default(),
+        }
     };
+    for path in (1..paths.packs.len()).filter_map(pack_path) {
+        log::debug!("Mounting asset pack {}", path);
+        assets.merge(asset_pack::load(&path)?)?;
+    }
+    if let Err(duplicates) = assets.validate() {
+        for duplicate in duplicates {
+            log::error!("{}", duplicate);
+        }
+    }
         
-    let event_loop = EventLoop::new();
-    let window = Window::new(&event_loop);
-    let vulkan_context = VulkanContext::new(&window);
+    let event_loop = EventLoop::new()?;
+    let window = Window::new(&event_loop, &config)?;
+    let shader_features = assets.shaders.values()
+        .fold(Features::empty(), |features, shader| features.union(&shader.shader_type.required_features()));
+    let vulkan_context = VulkanContext::new(&window, &config, shader_features)?;
+    for shader in assets.shaders.values_mut() {
+        shader.load(&vulkan_context)?;
+    }
     let memory_allocators = MemoryAllocators::new(&vulkan_context);
-    let renderer = Renderer::new(&vulkan_context, &memory_allocators, &window) ;
-    let mut state = State {
+    let renderer = Renderer::new(&vulkan_context, &memory_allocators, &window, &config)?;
+    let asset_loading = AssetLoading::new(&vulkan_context, &memory_allocators, DEFAULT_UPLOAD_WORKERS);
+    let mut state = new_state(
+        Windowed::new(window),
+        Windowed::new(vulkan_context),
+        Windowed::new(memory_allocators),
+        Windowed::new(renderer),
+        Windowed::new(asset_loading),
+    );
+    state.asset_paths = config.asset_paths.clone();
+    state.quit_key = config.quit_key.clone();
+    state.run_when_unfocused = config.run_in_background;
+    state.background_frame_rate = config.background_frame_rate;
+    add_engine_systems(&mut world, &state);
+    world.start(&mut assets, &mut state);
+    if state.exit_requested {
+        trace!("Exit requested during start!");
+        world.exit(&mut assets, &mut state);
+        return Ok(());
+    }
+
+    let mut frame_pacer = FramePacer::new();
+    let mut last_frame = Instant::now();
+
+    event_loop.event_loop.set_control_flow(ControlFlow::Poll);
+    #[allow(deprecated)]
+    event_loop
+        .event_loop
+        .run(move |event, elwt| {
+            if dispatch_raw_event(&event, &mut world, &mut state) {
+                return;
+            }
+
+            match event {
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested, ..
+                } => {
+                    trace!("Close requested!");
+                    state.request_exit();
+                    exit_if_requested(&mut world, &mut assets, &mut state, elwt);
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Focused(value), ..
+                } => {
+                    let was_background = state.in_background();
+                    state.focused = value;
+                    if was_background && !state.in_background() {
+                        state.fixed_timestep.resume();
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Occluded(value), ..
+                } => {
+                    let was_background = state.in_background();
+                    state.occluded = value;
+                    if was_background && !state.in_background() {
+                        state.fixed_timestep.resume();
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::DroppedFile(path), ..
+                } => {
+                    state.events.push(EngineEvent::FileDropped(path));
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(_), ..
+                } => {
+                    trace!("Resizing!");
+                    state.renderer.window_resized = true;
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ScaleFactorChanged { .. }, ..
+                } => {
+                    state.renderer.ui_scale_changed = true;
+                }
+                Event::WindowEvent {
+                    event: KeyboardInput {
+                        event: KeyEvent {
+                                    logical_key: key_code,
+                                    state: ElementState::Pressed, ..
+                        }, .. 
+                    }, ..
+                } => {
+                    state.input.process_key_press(key_code);
+                }
+                Event::WindowEvent {
+                    event: KeyboardInput {
+                            event: KeyEvent {
+                                    logical_key: key_code,
+                                    state: ElementState::Released, ..
+                        }, ..
+                    }, ..
+                } => {
+                    state.input.process_key_release(key_code);
+                }
+                Event::WindowEvent {
+                    event: MouseInput {
+                        device_id: _,
+                        state: ElementState::Pressed,
+                        button
+                    }, ..
+                } => {
+                    state.input.process_button_press(button);
+                }
+                Event::WindowEvent {
+                    event: MouseInput {
+                        device_id: _,
+                        state: ElementState::Released,
+                        button
+                    }, ..
+                } => {
+                    state.input.process_button_release(button);
+                }
+                Event::DeviceEvent {
+                    event: MouseMotion { delta: (x, y) }, ..
+                } => {
+                    state.input.mouse_motion(Vec2f::new([x as f32, y as f32]));
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseWheel { 
+                        delta, 
+                        .. 
+                    }, 
+                    ..
+                } => {
+                    let y = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(val) => val.y as f32
+                    };
+                    state.input.scroll_delta = y;
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::CursorMoved {
+                            device_id: _,
+                            position,
+                            ..
+                        },
+                    ..
+                } => {
+                    let logical_position = position.to_logical::<i32>(state.window.window_handle.scale_factor());
+                    let x = logical_position.x as f32;
+                    let y = logical_position.y as f32;
+                    state.input.cursor_position = Vec2f::new([x, y]);
+                }
+                Event::AboutToWait => {
+                    if elwt.exiting() {
+                        return;
+                    }
+                    let frame_rate = effective_frame_rate(
+                        state.target_frame_rate,
+                        state.background_frame_rate,
+                        !state.in_background(),
+                        state.run_when_unfocused,
+                    );
+                    if let Some(deadline) = frame_pacer.wait_until(Instant::now(), frame_rate) {
+                        elwt.set_control_flow(ControlFlow::WaitUntil(deadline));
+                        return;
+                    }
+                    elwt.set_control_flow(ControlFlow::Poll);
+
+                    let now = Instant::now();
+                    let frame_interval = frame_rate.filter(|x| *x > 0.0).map_or(0.0, |x| 1.0 / x as f64);
+                    state.time_struct.advance_paced((now - last_frame).as_secs_f64(), frame_interval);
+                    last_frame = now;
+                    state.vulkan_context.set_debug_frame(state.time_struct.frame_count);
+
+                    tick(&mut world, &mut assets, &mut state);
+                    state.asset_loading.end_frame();
+                    state.events.clear();
+                    exit_if_requested(&mut world, &mut assets, &mut state, elwt);
+                }
+                _ => (),
+            }
+        })
+        .map_err(EngineError::EventLoop)
+}
+
+// drives `world` without a window or a device for tests and dedicated servers, every frame advances the clock by
+// `fixed_delta`, systems that draw, upload assets or read the window aren't added and the windowed parts of the
+// state panic when used
+pub fn run_headless(mut world: World, mut assets: AssetLibrary, config: HeadlessConfig) -> World {
+    let _ = env_logger::try_init();
+    let mut state = headless_state();
+    add_engine_systems(&mut world, &state);
+    world.start(&mut assets, &mut state);
+
+    let mut frame = 0;
+    while !state.exit_requested && config.max_frames.is_none_or(|x| frame < x) {
+        state.time_struct.advance(config.fixed_delta);
+        tick(&mut world, &mut assets, &mut state);
+        // the renderer clears them after drawing otherwise
+        state.debug_lines.clear();
+        frame += 1;
+    }
+
+    world.exit(&mut assets, &mut state);
+    world
+}
+
+// without any of the windowed parts, for run_headless and tests
+fn headless_state() -> State {
+    new_state(
+        Windowed::headless(),
+        Windowed::headless(),
+        Windowed::headless(),
+        Windowed::headless(),
+        Windowed::headless(),
+    )
+}
+
+fn new_state(
+    window: Windowed<Window>,
+    vulkan_context: Windowed<VulkanContext>,
+    memory_allocators: Windowed<MemoryAllocators>,
+    renderer: Windowed<Renderer>,
+    asset_loading: Windowed<AssetLoading>,
+) -> State {
+    State {
         window,
         input: InputManager::new(),
         vulkan_context,
         memory_allocators,
         renderer,
-        time: 0.0,
-        delta_time: 0.0,
-        physics_time_scale: 1.0
-    };
+        time_struct: Time::new(),
+        physics_time_scale: 1.0,
+        fixed_timestep: FixedTimestep::new(),
+        physics: PhysicsSettings::default(),
+        trigger_events: Vec::new(),
+        contacts: Vec::new(),
+        pair_count: 0,
+        debug_lines: DebugLines::default(),
+        events: Vec::new(),
+        asset_paths: AssetPaths::default(),
+        target_frame_rate: None,
+        focused: true,
+        occluded: false,
+        run_when_unfocused: true,
+        background_frame_rate: BACKGROUND_FRAME_RATE,
+        asset_reload_requests: Vec::new(),
+        asset_loading,
+        diagnostics: Diagnostics::default(),
+        exit_requested: false,
+        quit_key: None,
+        ui_focus: UiFocus::default()
+    }
+}
 
+// the engine's own systems, after the ones added before run, the headless ones in the same order as with a window
+fn add_engine_systems(world: &mut World, state: &State) {
+    let windowed = !state.is_headless();
     world.add_system(DynamicMeshMaterialLoader {});
     world.add_system(ModelComponentUuidLoader {});
-    world.add_system(UiMeshBuilder {});
+    if windowed {
+        world.add_system(UiMeshBuilder {});
+    }
 
     world.add_system(TransformUpdater {});
-    world.add_system(CameraUpdater {});
+    if windowed {
+        world.add_system(AnimationSystem::new(&state.memory_allocators));
+    }
+    world.add_system(OrbitCameraSystem::new());
+    world.add_system(FollowCameraSystem {});
+    if windowed {
+        world.add_system(CameraUpdater {});
 
-    world.add_system(MaterialLoader {});
-    world.add_system(ShaderLoader {});
-    world.add_system(TextureLoader {});
-    world.add_system(MeshBufferLoader::new(&mut state));
+        world.add_system(MaterialLoader {});
+        world.add_system(MaterialUpdater {});
+        world.add_system(ShaderLoader {});
+        world.add_system(TextureLoader {});
+        world.add_system(MeshBufferLoader {});
 
-    world.add_system(RendererHandler {});
-    world.add_system(DefaultTextureLoader {});
+        world.add_system(RendererHandler {});
+        #[cfg(feature = "dev_tools")]
+        world.add_system(hot_reload::AssetHotReload::new(&state.asset_paths));
+        world.add_system(PickingHandler {});
+        world.add_system(DefaultTextureLoader {});
+    }
+    #[cfg(not(feature = "rapier"))]
     world.add_system(RigidbodyHandler {});
-    world.add_system(CollisionHandler {});
-    world.add_system(UiHandler {});
+    world.add_system(ParticleUpdater {});
+    #[cfg(not(feature = "rapier"))]
+    world.add_system(CollisionHandler::new());
+    #[cfg(feature = "rapier")]
+    world.add_system(RapierPhysics::new());
+    world.add_system(JointSolver {});
+    world.add_system(CharacterControllerSystem {});
+    if windowed {
+        world.add_system(PhysicsDebugRenderer {});
+    }
+    world.add_system(UiHandler::new());
     world.add_system(InputManagerUpdater {});
+}
 
-    world.start(&mut assets, &mut state);
-
-    event_loop.event_loop.set_control_flow(ControlFlow::Poll);
-    #[allow(deprecated)]
-    event_loop
-        .event_loop
-        .run(move |event, elwt| match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested, ..
-            } => {
-                trace!("Close requested!");
-                elwt.exit();
-            }
-            Event::WindowEvent {
-                event: WindowEvent::Resized(_), ..
-            } => {
-                trace!("Resizing!");
-                state.renderer.window_resized = true;
-            }
-            Event::WindowEvent {
-                event: KeyboardInput {
-                    event: KeyEvent {
-                                logical_key: key_code,
-                                state: ElementState::Pressed, ..
-                    }, .. 
-                }, ..
-            } => {
-                state.input.process_key_press(key_code);
-            }
-            Event::WindowEvent {
-                event: KeyboardInput {
-                        event: KeyEvent {
-                                logical_key: key_code,
-                                state: ElementState::Released, ..
-                    }, ..
-                }, ..
-            } => {
-                state.input.process_key_release(key_code);
-            }
-            Event::WindowEvent {
-                event: MouseInput {
-                    device_id: _,
-                    state: ElementState::Pressed,
-                    button
-                }, ..
-            } => {
-                state.input.process_button_press(button);
-            }
-            Event::WindowEvent {
-                event: MouseInput {
-                    device_id: _,
-                    state: ElementState::Released,
-                    button
-                }, ..
-            } => {
-                state.input.process_button_release(button);
-            }
-            Event::DeviceEvent {
-                event: MouseMotion { delta: (x, y) }, ..
-            } => {
-                state.input.mouse_motion(Vec2f::new([x as f32, y as f32]));
-            }
-            Event::WindowEvent {
-                event: WindowEvent::MouseWheel { 
-                    delta, 
-                    .. 
-                }, 
-                ..
-            } => {
-                let y = match delta {
-                    MouseScrollDelta::LineDelta(_, y) => y,
-                    MouseScrollDelta::PixelDelta(val) => val.y as f32
-                };
-                state.input.scroll_delta = y;
-            }
-            Event::WindowEvent {
-                event:
-                    WindowEvent::CursorMoved {
-                        device_id: _,
-                        position,
-                        ..
-                    },
-                ..
-            } => {
-                let logical_position = position.to_logical::<i32>(state.window.window_handle.scale_factor());
-                let x = logical_position.x as f32;
-                let y = logical_position.y as f32;
-                state.input.cursor_position = Vec2f::new([x, y]);
-            }
-            Event::AboutToWait => {
-                let current_time = (timer.elapsed().as_millis() as f64) / 1000.0;
-                state.delta_time = current_time - state.time;
-                state.time = current_time;
+// the on_exit hooks run once, however many times it's asked for
+fn exit_if_requested(world: &mut World, assets: &mut AssetLibrary, state: &mut State, elwt: &ActiveEventLoop) {
+    if state.exit_requested && !elwt.exiting() {
+        trace!("Exiting!");
+        world.exit(assets, state);
+        elwt.exit();
+    }
+}
 
-                world.update(&mut assets, &mut state);
-            }
-            _ => (),
-        })
-        .unwrap();
+// one frame of `state.time_struct.delta`, with as many fixed steps as it adds up to
+fn tick(world: &mut World, assets: &mut AssetLibrary, state: &mut State) {
+    if state.quit_key.as_ref().is_some_and(|x| state.input.key_pressed.contains(x)) {
+        state.request_exit();
+    }
+    let physics_delta_time = state.physics.physics_delta_time(state.time_struct.delta);
+    let steps = state.fixed_timestep.advance(physics_delta_time, state.physics_time_scale, state.physics.timestep);
+    state.trigger_events.clear();
+    for _ in 0..steps {
+        world.fixed_update(assets, state);
+    }
+    world.update(assets, state);
+    diagnostics::record_frame(world, state);
 }
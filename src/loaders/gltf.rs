@@ -1,19 +1,223 @@
 use std::{collections::HashMap, path::Path};
 
-use gltf::accessor::DataType;
-use log::debug;
+use gltf::{accessor::DataType, image::Format, material::AlphaMode};
+use log::{debug, error};
 use uuid::Uuid;
 
-use crate::{asset_library::AssetLibrary, rendering::VertexData, types::{material::{Attachment, Material, MaterialParameters, RenderingType}, mesh::Mesh, quaternion::Quat, texture::Texture, vectors::{Vec2f, Vec3f, Vec4f}}};
+use crate::{asset_library::AssetLibrary, engine_config::AssetPaths, rendering::{SkinVertexData, VertexData}, types::{animation::{AnimationChannel, AnimationClip, ChannelProperty, Interpolation, Skin}, material::{Attachment, DepthSettings, Material, MaterialParameters, RenderingType}, matrices::Matrix4f, mesh::Mesh, model::{Model, ModelNode}, quaternion::Quat, texture::{texture_attachment, Texture}, vectors::{GpuVec3f, Vec2f, Vec3f, Vec4f}}};
+
+// `indices` maps gltf node indices to their position in Model::node_transforms
+fn load_node(node: gltf::Node, primitives: &HashMap<usize, Vec<(Uuid, Uuid)>>, indices: &mut HashMap<usize, usize>) -> ModelNode {
+    indices.insert(node.index(), indices.len());
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let mut model_node = ModelNode::new(
+        match node.name() {
+            Some(val) => val.to_string(),
+            None => node.index().to_string()
+        },
+        (Vec3f::new(translation), Quat::new_sl(rotation), Vec3f::new(scale)),
+        node.mesh().and_then(|x| primitives.get(&x.index())).cloned().unwrap_or_default()
+    );
+    model_node.skin = node.skin().map(|x| x.index());
+    model_node.children = node.children().map(|x| load_node(x, primitives, indices)).collect();
+    model_node
+}
+
+// copy of the material drawn with the skinned vertex shader, shared by every skinned primitive using it
+fn skinned_material(
+    material: Uuid,
+    vertex_shader: Uuid,
+    variants: &mut HashMap<Uuid, Uuid>,
+    assets: &mut AssetLibrary
+) -> Uuid {
+    if let Some(uuid) = variants.get(&material) {
+        return *uuid;
+    }
+
+    let base = assets.materials.get(&material).unwrap();
+    let variant = Material::new(
+        format!("{}.skinned", base.name),
+        vertex_shader,
+        base.fragment_shader,
+        base.attachments.clone(),
+        base.parameters.clone(),
+        base.rendering_type,
+        base.transparent,
+        base.depth
+    );
+    let uuid = Uuid::new_v4();
+    assets.materials.insert(uuid, variant);
+    variants.insert(material, uuid);
+    uuid
+}
+
+fn load_skin(skin: gltf::Skin, buffers: &[gltf::buffer::Data], indices: &HashMap<usize, usize>) -> Skin {
+    let joints: Vec<usize> = skin.joints().map(|x| match indices.get(&x.index()) {
+        Some(val) => *val,
+        None => {
+            error!("Joint {} isn't part of the scene", x.index());
+            0
+        }
+    }).collect();
+
+    let inverse_bind_matrices = match skin.reader(|x| Some(&buffers[x.index()])).read_inverse_bind_matrices() {
+        Some(val) => val.map(Matrix4f).collect(),
+        None => vec![Matrix4f::indentity(); joints.len()]
+    };
+    Skin { joints, inverse_bind_matrices }
+}
+
+// cubic spline samplers are played back linearly through their keyframe values, morph targets aren't supported
+fn load_animation(
+    id: usize,
+    animation: gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+    indices: &HashMap<usize, usize>
+) -> AnimationClip {
+    use gltf::animation::{util::ReadOutputs, Interpolation as GltfInterpolation};
+
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let Some(node) = indices.get(&channel.target().node().index()) else {
+            continue;
+        };
+        let reader = channel.reader(|x| Some(&buffers[x.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+
+        let (property, values): (ChannelProperty, Vec<[f32; 4]>) = match outputs {
+            ReadOutputs::Translations(val) => (ChannelProperty::Translation, val.map(|[x, y, z]| [x, y, z, 0.0]).collect()),
+            ReadOutputs::Rotations(val) => (ChannelProperty::Rotation, val.into_f32().collect()),
+            ReadOutputs::Scales(val) => (ChannelProperty::Scale, val.map(|[x, y, z]| [x, y, z, 0.0]).collect()),
+            ReadOutputs::MorphTargetWeights(_) => continue
+        };
+        let (interpolation, values) = match channel.sampler().interpolation() {
+            GltfInterpolation::Linear => (Interpolation::Linear, values),
+            GltfInterpolation::Step => (Interpolation::Step, values),
+            GltfInterpolation::CubicSpline => (Interpolation::Linear, values.into_iter().skip(1).step_by(3).collect())
+        };
+
+        channels.push(AnimationChannel {
+            node: *node,
+            property,
+            interpolation,
+            times: inputs.collect(),
+            values
+        });
+    }
 
+    AnimationClip {
+        name: match animation.name() {
+            Some(val) => val.to_string(),
+            None => id.to_string()
+        },
+        duration: channels.iter().filter_map(|x| x.times.last()).fold(0.0, |a, b| b.max(a)),
+        channels
+    }
+}
+
+fn to_rgba8(image: gltf::image::Data) -> Option<Vec<u8>> {
+    let channels = match image.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => return Some(image.pixels),
+        _ => return None
+    };
+
+    Some(image.pixels.chunks_exact(channels).flat_map(|x| match *x {
+        [l] => [l, l, l, 255],
+        [l, a] => [l, l, l, a],
+        [r, g, b] => [r, g, b, 255],
+        _ => unreachable!()
+    }).collect())
+}
+
+// image files are read from {model}/ under AssetPaths::textures, images stored in the buffers or in data uris are decoded here
+fn image_attachment(
+    model_name: &str,
+    document: &gltf::Document,
+    texture_index: usize,
+    buffers: &[gltf::buffer::Data],
+    assets: &mut AssetLibrary,
+    srgb: bool,
+    paths: &AssetPaths
+) -> Attachment {
+    let image = document.textures().nth(texture_index).unwrap().source();
+    let source = match image.source() {
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+            let name = format!("{}/{}", model_name, uri.replace('\\', "/"));
+            return texture_attachment(&mut assets.textures, name, srgb, paths);
+        }
+        source => source
+    };
+
+    let name = format!("{}/{}", model_name, match image.name() {
+        Some(val) => val.to_string(),
+        None => image.index().to_string()
+    });
+    // gltf refuses every uri without a base, even data uris, files were handled above so the base is never read
+    let data = match gltf::image::Data::from_source(source, Some(Path::new("")), buffers) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Failed to decode embedded image {}: {}", name, e);
+            return Attachment::DefaultTexture;
+        }
+    };
+    let (width, height, format) = (data.width, data.height, data.format);
+    let Some(pixels) = to_rgba8(data) else {
+        error!("Embedded image {} has unsupported format {:?}", name, format);
+        return Attachment::DefaultTexture;
+    };
+
+    let mut texture = Texture::from_rgba8(&name, width, height, pixels);
+    texture.srgb = srgb;
+    texture.generate_mips = true;
+    let uuid = Uuid::new_v4();
+    assets.textures.insert(uuid, texture);
+    Attachment::Texture(uuid)
+}
+
+// reads both .gltf and .glb files
 #[allow(clippy::result_unit_err)]
 pub fn load_gltf(
     model_name: String,
-    assets: &mut AssetLibrary
-) -> Result<Vec<(Uuid, Uuid)>, ()> {
-    let mut meshes_and_materials = Vec::new();
-    let document = gltf::Gltf::open(format!("assets/meshes/{}.gltf", model_name)).unwrap();
-    let buffers = gltf::import_buffers(&document, Some(Path::new("assets/meshes/")), None).unwrap();
+    extension: &str,
+    assets: &mut AssetLibrary,
+    paths: &AssetPaths
+) -> Result<Model, ()> {
+    let path = paths.meshes.join(format!("{}.{}", model_name, extension));
+    let document = match gltf::Gltf::open(&path) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Failed to open {}: {}", path.display(), e);
+            return Err(());
+        }
+    };
+    load_gltf_document(model_name, document, Some(paths.meshes.as_path()), assets, paths)
+}
+
+fn load_gltf_document(
+    model_name: String,
+    mut document: gltf::Gltf,
+    base: Option<&Path>,
+    assets: &mut AssetLibrary,
+    paths: &AssetPaths
+) -> Result<Model, ()> {
+    let blob = document.blob.take();
+    let buffers = match gltf::import_buffers(&document, base, blob) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Failed to read buffers of {}: {}", model_name, e);
+            return Err(());
+        }
+    };
+
+    let vertex_shader = assets.shader_by_name("perspective").expect("\"perspective\" shader needed").0;
+    let fragment_shader = assets.shader_by_name("lit").expect("\"lit\" shader needed").0;
+    let skinned_shader = assets.shader_by_name("skinned_perspective").map(|(uuid, _)| uuid);
+    let mut skinned_materials = HashMap::new();
 
     let mut materials = HashMap::new();
     for (id, material) in document.materials().enumerate() {
@@ -26,83 +230,58 @@ pub fn load_gltf(
         }).replace('\\', "/");
         materials.insert(material.index().unwrap(), uuid);
 
-        let color_index = match material.pbr_metallic_roughness().base_color_texture() {
-            Some(val) => {
-                val.texture().index() as i32
-            },
-            None => -1
+        // attachments follow the "lit" shader: color, normal, metallic-roughness, emissive, occlusion,
+        // only color and emissive hold sRGB data
+        let pbr = material.pbr_metallic_roughness();
+        let mut attachment = |texture_index: Option<usize>, srgb: bool| match texture_index {
+            Some(val) => (image_attachment(&model_name, &document, val, &buffers, assets, srgb, paths), 1),
+            None => (Attachment::DefaultTexture, 0)
         };
-        
-        let normal_index = match material.normal_texture() {
-            Some(val) => {
-                val.texture().index() as i32
-            },
-            None => -1
-        };
-        
-        let mut use_color = 0;
-        let mut use_normal = 0;
-
-        let color_texture = if color_index >= 0 {
-            use_color = 1;
-            
-            let color_uri = match document.images().nth(document.textures().nth(color_index as usize).unwrap().source().index()).unwrap().source() {
-                gltf::image::Source::View { .. } => "",
-                gltf::image::Source::Uri { uri, .. } => uri
-            }.replace('\\', "/");
-            let name = format!("{}/{}", model_name, color_uri);
-            let uuid = Uuid::new_v4();
-            assets.textures.insert(uuid, Texture::new(name));
-
-            Attachment::Texture(uuid)
-        } else {
-            Attachment::DefaultTexture
-        };
-        
-        let normal_texture = if normal_index >= 0 {
-            use_normal = 1;
-
-            let normal_uri = match document.images().nth(document.textures().nth(normal_index as usize).unwrap().source().index()).unwrap().source() {
-                gltf::image::Source::View { .. } => "",
-                gltf::image::Source::Uri { uri, .. } => uri
-            }.replace('\\', "/");
-            let name = format!("{}/{}", model_name, normal_uri);
-            let uuid = Uuid::new_v4();
-            assets.textures.insert(uuid, Texture::new(name));
-
-            Attachment::Texture(uuid)
-        } else {
-            Attachment::DefaultTexture
+        let (color_texture, use_color) = attachment(pbr.base_color_texture().map(|x| x.texture().index()), true);
+        let (normal_texture, use_normal) = attachment(material.normal_texture().map(|x| x.texture().index()), false);
+        let (metallic_roughness_texture, use_metallic_roughness) =
+            attachment(pbr.metallic_roughness_texture().map(|x| x.texture().index()), false);
+        let (emissive_texture, use_emissive) = attachment(material.emissive_texture().map(|x| x.texture().index()), true);
+        let (occlusion_texture, use_occlusion) = attachment(material.occlusion_texture().map(|x| x.texture().index()), false);
+
+        // nothing culls back faces, so double sided materials need no special handling
+        let (transparent, alpha_cutoff) = match material.alpha_mode() {
+            AlphaMode::Opaque => (false, 0.0),
+            AlphaMode::Mask => (false, material.alpha_cutoff().unwrap_or(0.5)),
+            AlphaMode::Blend => (true, 0.0)
         };
 
         let mat = Material::new(
             name, 
-            *assets.shaders.iter().find(|(_, v)| v.name.as_str() == "perspective").expect("\"perspective\" shader needed").0,
-            *assets.shaders.iter().find(|(_, v)| v.name.as_str() == "lit").expect("\"lit\" shader needed").0,
-            vec![color_texture, normal_texture],
+            vertex_shader,
+            fragment_shader,
+            vec![color_texture, normal_texture, metallic_roughness_texture, emissive_texture, occlusion_texture],
             Some(
                 MaterialParameters {
-                    diffuse_color: Vec4f::new(material.pbr_metallic_roughness().base_color_factor()).into(),
+                    diffuse_color: Vec3f::from(Vec4f::new(pbr.base_color_factor())).into(),
                     use_diffuse_texture: use_color,
-                    use_normal_texture: use_normal
+                    use_normal_texture: use_normal,
+                    metallic_factor: pbr.metallic_factor(),
+                    roughness_factor: pbr.roughness_factor(),
+                    emissive_factor: GpuVec3f::new(material.emissive_factor()),
+                    use_metallic_roughness_texture: use_metallic_roughness,
+                    use_emissive_texture: use_emissive,
+                    use_occlusion_texture: use_occlusion,
+                    alpha_cutoff
                 }
             ),
-            RenderingType::Fill
+            RenderingType::Fill,
+            transparent,
+            DepthSettings::default()
         );
 
         assets.materials.insert(uuid, mat);
     }
     
-    for (n_id, node) in document.nodes().enumerate() {
-        let mesh = match node.mesh() {
-            Some(val) => val,
-            None => continue
-        };
-
-        let position = Vec3f::new(node.transform().decomposed().0);
-        let rotation = Quat::new_sl(node.transform().decomposed().1);
-        let scale = Vec3f::new(node.transform().decomposed().2);
-        
+    // meshes stay in node space, nodes sharing a mesh share its primitives
+    let mut primitives = HashMap::new();
+    for (m_id, mesh) in document.meshes().enumerate() {
+        let mut mesh_primitives = Vec::new();
         for (prim_id, prim) in mesh.primitives().enumerate() {
             let mat = match prim.material().index() {
                 Some(val) => {
@@ -112,17 +291,13 @@ pub fn load_gltf(
                     let uuid = Uuid::new_v4();
                     let material = Material::new(
                         format!("Material{}", prim_id), 
-                        *assets.shaders.iter().find(|(_, v)| v.name.as_str() == "perspective").expect("\"perspective\" shader needed").0,
-                        *assets.shaders.iter().find(|(_, v)| v.name.as_str() == "lit").expect("\"lit\" shader needed").0,
-                        vec![Attachment::DefaultTexture, Attachment::DefaultTexture],
-                        Some(
-                            MaterialParameters {
-                                diffuse_color: Vec3f::new([1.0, 1.0, 1.0]),
-                                use_diffuse_texture: 0,
-                                use_normal_texture: 0
-                            }
-                        ),
-                        RenderingType::Fill
+                        vertex_shader,
+                        fragment_shader,
+                        vec![Attachment::DefaultTexture; 5],
+                        Some(MaterialParameters::default()),
+                        RenderingType::Fill,
+                        false,
+                        DepthSettings::default()
                     );
                     assets.materials.insert(uuid, material);
                     uuid
@@ -241,14 +416,11 @@ pub fn load_gltf(
             };
 
             let vertices: Vec<VertexData> = (0..len).map(|i| {
-                let tang = *tangent.get(i).unwrap_or(&Vec4f::new([0.0, 1.0, 0.0, 1.0]));
-                let tang3 = Vec3f::new([tang.x, tang.y, tang.z]) * rotation;
-                let tang = Vec4f::new([tang3.x, tang3.y, tang3.z, tang.w]);
                 VertexData {
-                    position: *positions.get(i).unwrap() * scale * rotation + position,
-                    normal: *normals.get(i).unwrap_or(&Vec3f::new([0.0, 1.0, 0.0])) * rotation,
-                    uv: *uvs.get(i).unwrap_or(&Vec2f::new([0.0, 0.0])),
-                    tangent: tang 
+                    position: (*positions.get(i).unwrap()).into(),
+                    normal: (*normals.get(i).unwrap_or(&Vec3f::new([0.0, 1.0, 0.0]))).into(),
+                    uv: (*uvs.get(i).unwrap_or(&Vec2f::new([0.0, 0.0]))).into(),
+                    tangent: *tangent.get(i).unwrap_or(&Vec4f::new([0.0, 1.0, 0.0, 1.0]))
                 }
             }).collect();
 
@@ -294,25 +466,138 @@ pub fn load_gltf(
             };
 
             let name = format!("{}.{}.{}", model_name, {
-                match node.name() {
+                match mesh.name() {
                     Some(val) => val.to_string(),
-                    None => n_id.to_string()
+                    None => m_id.to_string()
                 }
             }, prim_id);
             debug!("Loading mesh {} with material {}...", name, material_name);
 
+            let reader = prim.reader(|x| Some(&buffers[x.index()]));
+            let skin = match (reader.read_joints(0), reader.read_weights(0)) {
+                (Some(joints), Some(weights)) => Some(
+                    joints.into_u16().zip(weights.into_f32()).map(|(joints, weights)| SkinVertexData {
+                        joints: joints.map(|x| x as u32),
+                        weights
+                    }).collect::<Vec<_>>()
+                ),
+                _ => None
+            };
+
             let uuid = Uuid::new_v4();
-            assets.meshes.insert(uuid, Mesh::new(&name, vertices, indices));
+            let (mesh, mat) = match (skin, skinned_shader) {
+                (Some(skin), Some(shader)) if skin.len() == len => (
+                    Mesh::new_skinned(&name, vertices, indices, skin),
+                    skinned_material(mat, shader, &mut skinned_materials, assets)
+                ),
+                (Some(_), _) => {
+                    error!("{} is skinned but can't be animated, a \"skinned_perspective\" shader and a joint per vertex are needed", name);
+                    (Mesh::new(&name, vertices, indices), mat)
+                }
+                (None, _) => (Mesh::new(&name, vertices, indices), mat)
+            };
+            assets.meshes.insert(uuid, mesh);
 
-            meshes_and_materials.push(
+            mesh_primitives.push(
                 (
                     uuid,
                     mat
                 ) 
             );
         }
+        primitives.insert(mesh.index(), mesh_primitives);
     }
 
+    let roots: Vec<gltf::Node> = match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: Vec<usize> = document.nodes().flat_map(|x| x.children()).map(|x| x.index()).collect();
+            document.nodes().filter(|x| !children.contains(&x.index())).collect()
+        }
+    };
+
+    let mut model = Model::new(model_name);
+    let mut indices = HashMap::new();
+    model.nodes = roots.into_iter().map(|x| load_node(x, &primitives, &mut indices)).collect();
+    model.skins = document.skins().map(|x| load_skin(x, &buffers, &indices)).collect();
+    model.animations = document.animations().enumerate().map(|(id, x)| load_animation(id, x, &buffers, &indices)).collect();
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use gltf::image::{Data, Format};
+    use uuid::Uuid;
 
-    Ok(meshes_and_materials)
+    use crate::{asset_library::AssetLibrary, engine_config::AssetPaths, types::{material::Attachment, shader::{Shader, ShaderType}, vectors::Vec3f}};
+
+    use super::{load_gltf_document, to_rgba8};
+
+    #[test]
+    fn test_embedded_texture_glb() {
+        let mut assets = AssetLibrary::default();
+        for (name, shader_type) in [("perspective", ShaderType::Vertex), ("lit", ShaderType::Fragment)] {
+            assets.shaders.insert(Uuid::new_v4(), Shader::from_words(name.to_string(), shader_type, vec![]));
+        }
+
+        let document = gltf::Gltf::from_slice(include_bytes!("fixtures/embedded_texture.glb")).unwrap();
+        let model = load_gltf_document("painted".to_string(), document, None, &mut assets, &AssetPaths::default()).unwrap();
+        let nodes = &model.nodes;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].name, "triangle");
+        let meshes_and_materials = &nodes[0].mesh_primitives;
+        assert_eq!(meshes_and_materials.len(), 1);
+        assert_eq!(assets.meshes[&meshes_and_materials[0].0].indices.len(), 3);
+
+        let material = &assets.materials[&meshes_and_materials[0].1];
+        let Attachment::Texture(uuid) = material.attachments[0] else {
+            panic!("embedded image wasn't turned into a texture");
+        };
+        let texture = &assets.textures[&uuid];
+        assert_eq!(texture.name, "painted/albedo");
+        assert_eq!((texture.width, texture.height), (2, 2));
+        assert_eq!(texture.image_data[..8], [255, 0, 0, 255, 0, 255, 0, 255]);
+        assert!(texture.srgb);
+    }
+
+    #[test]
+    fn test_pbr_material_import() {
+        let mut assets = AssetLibrary::default();
+        for (name, shader_type) in [("perspective", ShaderType::Vertex), ("lit", ShaderType::Fragment)] {
+            assets.shaders.insert(Uuid::new_v4(), Shader::from_words(name.to_string(), shader_type, vec![]));
+        }
+
+        let document = gltf::Gltf::from_slice(include_bytes!("fixtures/pbr_materials.gltf")).unwrap();
+        load_gltf_document("pbr".to_string(), document, None, &mut assets, &AssetPaths::default()).unwrap();
+
+        let (_, brushed) = assets.material_by_name("pbr.brushed").unwrap();
+        let parameters = brushed.parameters.as_ref().unwrap();
+        assert_eq!((parameters.metallic_factor, parameters.roughness_factor), (0.25, 0.75));
+        assert_eq!(*parameters.emissive_factor, Vec3f::new([1.0, 0.5, 0.0]));
+        assert_eq!(parameters.alpha_cutoff, 0.3);
+        assert_eq!(
+            (parameters.use_diffuse_texture, parameters.use_metallic_roughness_texture, parameters.use_emissive_texture, parameters.use_occlusion_texture),
+            (0, 1, 1, 1)
+        );
+        assert!(!brushed.transparent);
+
+        let srgb: Vec<Option<bool>> = brushed.attachments.iter().map(|x| match x {
+            Attachment::Texture(uuid) => Some(assets.textures[uuid].srgb),
+            Attachment::DefaultTexture => None
+        }).collect();
+        assert_eq!(srgb, vec![None, None, Some(false), Some(true), Some(false)]);
+
+        let (_, glass) = assets.material_by_name("pbr.glass").unwrap();
+        assert!(glass.transparent);
+        assert_eq!(glass.parameters.as_ref().unwrap().alpha_cutoff, 0.0);
+    }
+
+    #[test]
+    fn test_expands_to_rgba8() {
+        let image = Data { pixels: vec![10, 20, 30, 40, 50, 60], format: Format::R8G8B8, width: 2, height: 1 };
+        assert_eq!(to_rgba8(image), Some(vec![10, 20, 30, 255, 40, 50, 60, 255]));
+
+        let image = Data { pixels: vec![0; 16], format: Format::R16G16B16A16, width: 1, height: 1 };
+        assert_eq!(to_rgba8(image), None);
+    }
 }
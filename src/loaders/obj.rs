@@ -3,15 +3,16 @@ use std::collections::{HashMap, HashSet};
 use log::{debug, error};
 use uuid::Uuid;
 
-use crate::{asset_library::AssetLibrary, rendering::VertexData, types::{material::{Attachment, Material, MaterialParameters, RenderingType}, mesh::Mesh, texture::Texture, vectors::{Vec2f, Vec3f, Vec4f}}};
+use crate::{asset_library::AssetLibrary, engine_config::AssetPaths, rendering::VertexData, types::{material::{Attachment, DepthSettings, Material, MaterialParameters, RenderingType}, mesh::Mesh, model::{identity_transform, Model, ModelNode}, texture::texture_attachment, vectors::{GpuVec3f, Vec2f, Vec3f, Vec4f}}};
 
 #[allow(clippy::result_unit_err)]
 pub fn load_obj(
     model_name: String,
-    assets: &mut AssetLibrary
-) -> Result<Vec<(Uuid, Uuid)>, ()> {
+    assets: &mut AssetLibrary,
+    paths: &AssetPaths
+) -> Result<Model, ()> {
     let mut meshes_and_materials = Vec::new();
-        let obj = tobj::load_obj(format!("assets/meshes/{}.obj", model_name), &tobj::GPU_LOAD_OPTIONS);
+        let obj = tobj::load_obj(paths.meshes.join(format!("{}.obj", model_name)), &tobj::GPU_LOAD_OPTIONS);
         let (meshes, materials) = match obj {
             Ok(val) => val,
             Err(_) => {
@@ -28,6 +29,9 @@ pub fn load_obj(
             }
         };
 
+        let vertex_shader = assets.shader_by_name("perspective").expect("\"perspective\" shader needed").0;
+        let fragment_shader = assets.shader_by_name("lit").expect("\"lit\" shader needed").0;
+
         let mut material_map = HashMap::new();
         for material in materials.iter() {
             let uuid = Uuid::new_v4();
@@ -38,16 +42,14 @@ pub fn load_obj(
                 uuid,
                 Material::new(
                     name.clone(),
-                    *assets.shaders.iter().find(|(_, v)| v.name.as_str() == "perspective").expect("\"perspective\" shader needed").0,
-                    *assets.shaders.iter().find(|(_, v)| v.name.as_str() == "lit").expect("\"lit\" shader needed").0,
+                    vertex_shader,
+                    fragment_shader,
                     vec![
             {
                 match &material.diffuse_texture {
                     Some(val) => {
                         let name = format!("{}/{}", model_name, val).replace('\\', "/");
-                        let uuid = Uuid::new_v4();
-                        assets.textures.insert(uuid, Texture::new(name));
-                        Attachment::Texture(uuid)
+                        texture_attachment(&mut assets.textures, name, true, paths)
                     },
                     None => Attachment::DefaultTexture
                 }
@@ -56,18 +58,20 @@ pub fn load_obj(
                 match &material.normal_texture {
                     Some(val) => {
                         let name = format!("{}/{}", model_name, val).replace('\\', "/");
-                        let uuid = Uuid::new_v4();
-                        assets.textures.insert(uuid, Texture::new(name));
-                        Attachment::Texture(uuid)
+                        texture_attachment(&mut assets.textures, name, false, paths)
                     },
                     None => Attachment::DefaultTexture
                 }
             },
+            // metallic-roughness, emissive and occlusion aren't part of .mtl
+            Attachment::DefaultTexture,
+            Attachment::DefaultTexture,
+            Attachment::DefaultTexture,
                     ],
                     Some(MaterialParameters {
                         diffuse_color: match material.diffuse {
-                            Some(col) => Vec3f::new(col),
-                            None => Vec3f::new([1.0, 0.0, 1.0])
+                            Some(col) => GpuVec3f::new(col),
+                            None => GpuVec3f::new([1.0, 0.0, 1.0])
                         },
                         use_normal_texture: match &material.normal_texture {
                             Some(_) => 1,
@@ -76,9 +80,12 @@ pub fn load_obj(
                         use_diffuse_texture: match &material.diffuse_texture {
                             Some(_) => 1,
                             None => 0
-                        }
+                        },
+                        ..Default::default()
                     }),
-            RenderingType::Fill
+            RenderingType::Fill,
+            false,
+            DepthSettings::default()
             )
                 );
         }
@@ -131,9 +138,9 @@ pub fn load_obj(
 
                 vertices.push(
                     VertexData {
-                        position: Vec3f::new([pos[3*i],pos[3*i+1],pos[3*i+2]]),
-                        normal,
-                        uv,
+                        position: GpuVec3f::new([pos[3*i],pos[3*i+1],pos[3*i+2]]),
+                        normal: normal.into(),
+                        uv: uv.into(),
                         tangent: Vec4f::new([0.0, 1.0, 0.0, 1.0])
                 }
                 );
@@ -150,5 +157,7 @@ pub fn load_obj(
             );
         }
 
-        Ok(meshes_and_materials)
+        let mut model = Model::new(model_name.clone());
+        model.nodes.push(ModelNode::new(model_name, identity_transform(), meshes_and_materials));
+        Ok(model)
 }
@@ -1,3 +1,13 @@
 pub mod collider;
 pub mod rigidbody;
 pub mod collision_handler;
+pub mod settings;
+pub mod broadphase;
+pub mod character_controller;
+pub mod joint;
+pub mod debug_draw;
+pub mod heightfield;
+pub mod ccd;
+pub mod query;
+#[cfg(feature = "rapier")]
+pub mod rapier;
\ No newline at end of file
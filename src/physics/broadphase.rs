@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::types::{position::Position, vectors::{Vec3d, Vec3i}};
+
+// world-space bounds, relative to the chunk of `center`
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub center: Position,
+    pub half_extents: Vec3d,
+}
+
+impl Aabb {
+    // touching counts, across chunks too
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        let offset = Vec3d::from(other.center - self.center);
+        offset.x.abs() <= self.half_extents.x + other.half_extents.x
+            && offset.y.abs() <= self.half_extents.y + other.half_extents.y
+            && offset.z.abs() <= self.half_extents.z + other.half_extents.z
+    }
+}
+
+// bounds covering more cells than this are kept out of the grid
+pub const MAX_GRID_CELLS: u64 = 4096;
+
+// (chunk, cell in the chunk)
+type CellKey = ([i64; 3], [i64; 3]);
+
+// uniform grid of cubes `cell_size` wide, hashed so only cells that hold something take memory,
+// bounds crossing into another chunk are only found from the chunk their center is in
+#[derive(Debug)]
+pub struct SpatialGrid {
+    cell_size: f64,
+    cells: HashMap<CellKey, Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f64) -> SpatialGrid {
+        SpatialGrid { cell_size, cells: HashMap::new() }
+    }
+
+    fn range(&self, aabb: &Aabb) -> ([i64; 3], [i64; 3]) {
+        let min = aabb.center.position - aabb.half_extents;
+        let max = aabb.center.position + aabb.half_extents;
+        let cell = |x: f64| (x / self.cell_size).floor() as i64;
+        ([cell(min.x), cell(min.y), cell(min.z)], [cell(max.x), cell(max.y), cell(max.z)])
+    }
+
+    fn keys(&self, aabb: &Aabb) -> impl Iterator<Item = CellKey> {
+        let chunk = [aabb.center.chunk.x, aabb.center.chunk.y, aabb.center.chunk.z];
+        let (min, max) = self.range(aabb);
+        (min[0]..=max[0]).flat_map(move |x| {
+            (min[1]..=max[1]).flat_map(move |y| (min[2]..=max[2]).map(move |z| (chunk, [x, y, z])))
+        })
+    }
+
+    // how many cells inserting `aabb` would fill
+    pub fn cell_count(&self, aabb: &Aabb) -> u64 {
+        let (min, max) = self.range(aabb);
+        (0..3).map(|i| (max[i] - min[i] + 1) as u64).product()
+    }
+
+    pub fn insert(&mut self, index: usize, aabb: &Aabb) {
+        for key in self.keys(aabb).collect::<Vec<_>>() {
+            self.cells.entry(key).or_default().push(index);
+        }
+    }
+
+    // indices of everything sharing a cell with `aabb`, sorted and without repeats
+    pub fn query(&self, aabb: &Aabb) -> Vec<usize> {
+        let mut found: Vec<usize> = self.keys(aabb).filter_map(|x| self.cells.get(&x)).flatten().copied().collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    // bounds of the cells holding something
+    pub fn cells(&self) -> Vec<Aabb> {
+        let half = self.cell_size / 2.0;
+        self.cells.keys()
+            .map(|(chunk, cell)| {
+                let center = Vec3d::new(cell.map(|x| x as f64 * self.cell_size + half));
+                Aabb { center: Position::new(Vec3i::new(*chunk), center), half_extents: Vec3d::new([half, half, half]) }
+            })
+            .collect()
+    }
+
+    // how many pairs `pairs` finds
+    pub fn pair_count(&self) -> usize {
+        self.pairs().len()
+    }
+
+    // every pair of indices sharing at least one cell as (larger, smaller), sorted and without repeats
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for indices in self.cells.values() {
+            for (i, a) in indices.iter().enumerate() {
+                for b in indices[i + 1..].iter() {
+                    pairs.push((*a.max(b), *a.min(b)));
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{position::Position, vectors::Vec3d};
+
+    use super::{Aabb, SpatialGrid};
+
+    fn aabb(x: f64, half: f64) -> Aabb {
+        Aabb { center: Position::from(Vec3d::new([x, 0.5, 0.5])), half_extents: Vec3d::new([half, 0.1, 0.1]) }
+    }
+
+    #[test]
+    fn test_pairs_spanning_cells_are_reported_once() {
+        let mut grid = SpatialGrid::new(1.0);
+        // 0 spans cells -1 to 1, 1 and 2 sit in cell 0, 3 is far away
+        grid.insert(0, &aabb(0.5, 1.2));
+        grid.insert(1, &aabb(0.2, 0.1));
+        grid.insert(2, &aabb(0.8, 0.1));
+        grid.insert(3, &aabb(10.5, 0.1));
+
+        assert_eq!(grid.pairs(), vec![(1, 0), (2, 0), (2, 1)]);
+        assert_eq!(grid.pair_count(), 3);
+        assert_eq!(grid.query(&aabb(1.5, 0.1)), vec![0]);
+        assert_eq!(grid.query(&aabb(-5.0, 0.1)), Vec::<usize>::new());
+        assert_eq!(grid.cell_count(&aabb(0.5, 1.2)), 3);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::types::{position::Position, transform::Transform, vectors::Vec3d};
+
+use super::{
+    broadphase::{Aabb, SpatialGrid},
+    collider::{Collider, ColliderShape},
+    rigidbody::{BodyType, Rigidbody},
+};
+
+// how far past the first touch a swept body is left, within the contact slop so the collision handler
+// finds the contact and stops the body there without pushing it back out
+const OVERSHOOT: f64 = 0.0005;
+
+fn components(vec: Vec3d) -> [f64; 3] {
+    [vec.x, vec.y, vec.z]
+}
+
+// fraction of `motion` a sphere starting at `start` from the shape's center travels before it touches
+// the shape, none if it misses or already touched it at the start, that's left to the collision handler
+fn time_of_impact(radius: f64, start: Vec3d, motion: Vec3d, shape: &ColliderShape) -> Option<f64> {
+    match shape {
+        ColliderShape::Sphere(other) => {
+            // |start + motion * t| = radius + other
+            let reach = radius + other;
+            let (a, b, c) = (motion.dot(motion), start.dot(motion), start.dot(start) - reach * reach);
+            let discriminant = b * b - a * c;
+            if c <= 0.0 || b >= 0.0 || discriminant < 0.0 {
+                return None;
+            }
+            Some((-b - discriminant.sqrt()) / a).filter(|x| *x <= 1.0)
+        }
+        ColliderShape::Box(half) => {
+            // the slabs of the box grown by the radius, a little early around the corners
+            let (start, motion, half) = (components(start), components(motion), components(*half));
+            let (mut near, mut far) = (0.0f64, 1.0f64);
+            for i in 0..3 {
+                let (low, high) = (-half[i] - radius, half[i] + radius);
+                if motion[i].abs() < 1e-12 {
+                    if start[i] < low || start[i] > high {
+                        return None;
+                    }
+                    continue;
+                }
+                let (a, b) = ((low - start[i]) / motion[i], (high - start[i]) / motion[i]);
+                near = near.max(a.min(b));
+                far = far.min(a.max(b));
+                if near > far {
+                    return None;
+                }
+            }
+            (near > 0.0).then_some(near)
+        }
+        ColliderShape::Heightfield(field) => {
+            // the center is a radius from the surface's plane before the ray through it hits
+            let length = motion.length();
+            let direction = motion / length;
+            let (distance, normal) = field.raycast(start, direction, f64::INFINITY)?;
+            let closing = -direction.dot(normal);
+            if closing <= 0.0 {
+                return None;
+            }
+            Some(((distance - radius / closing) / length).max(0.0)).filter(|x| *x <= 1.0)
+        }
+    }
+}
+
+// moves every dynamic body with Rigidbody::ccd that went further than its radius this step back to
+// where it first touched a solid collider on the way, boxes are swept as the largest sphere inside them
+pub(super) fn sweep(entities: &hecs::World, cell_size: f64) {
+    let mut query = entities.query::<(&Transform, Option<&Rigidbody>, &Collider)>();
+    let bodies = query.iter().collect::<Vec<_>>();
+
+    let swept: Vec<(usize, Vec3d, f64)> = bodies.iter()
+        .enumerate()
+        .filter_map(|(i, (_, (transform, rigidbody, collider)))| {
+            let rigidbody = rigidbody.filter(|x| x.ccd && x.body_type == BodyType::Dynamic && !collider.is_trigger)?;
+            let radius = match collider.shape {
+                ColliderShape::Sphere(radius) => radius,
+                ColliderShape::Box(half) => half.x.min(half.y).min(half.z),
+                ColliderShape::Heightfield(_) => return None,
+            };
+            let motion = Vec3d::from(transform.position - rigidbody.previous_position()?);
+            (motion.length() > radius).then_some((i, motion, radius))
+        })
+        .collect();
+    if swept.is_empty() {
+        return;
+    }
+
+    // heightfields kept out of the grid like in candidate_pairs
+    let mut grid = SpatialGrid::new(cell_size);
+    let mut fields = Vec::new();
+    for (i, (_, (transform, _, collider))) in bodies.iter().enumerate() {
+        if matches!(collider.shape, ColliderShape::Heightfield(_)) {
+            fields.push(i);
+        } else {
+            grid.insert(i, &collider.aabb(transform));
+        }
+    }
+
+    let mut moves = Vec::new();
+    for (i, motion, radius) in swept {
+        let (entity, (transform, _, collider)) = bodies[i];
+        let end = collider.center(transform);
+        let start = end - Position::from(motion);
+        let half = Vec3d::new([motion.x.abs(), motion.y.abs(), motion.z.abs()]) / 2.0;
+        let path = Aabb { center: end - Position::from(motion / 2.0), half_extents: half + Vec3d::new([radius, radius, radius]) };
+
+        let candidates = grid.query(&path).into_iter().chain(fields.iter().copied().filter(|x| {
+            let (_, (transform, _, collider)) = bodies[*x];
+            collider.aabb(transform).overlaps(&path)
+        }));
+        let hit = candidates
+            .filter(|x| *x != i)
+            .filter_map(|x| {
+                let (_, (other_transform, _, other)) = bodies[x];
+                if other.is_trigger || !collider.interacts(other) {
+                    return None;
+                }
+                time_of_impact(radius, Vec3d::from(start - other.center(other_transform)), motion, &other.shape)
+            })
+            .min_by(|a, b| a.total_cmp(b));
+
+        if let Some(time) = hit {
+            let travelled = (time + OVERSHOOT / motion.length()).min(1.0);
+            moves.push((entity, motion * (travelled - 1.0)));
+        }
+    }
+    drop(query);
+
+    for (entity, offset) in moves {
+        entities.get::<&mut Transform>(entity).unwrap().position += offset.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{physics::collider::ColliderShape, types::vectors::Vec3d};
+
+    use super::time_of_impact;
+
+    #[test]
+    fn test_time_of_impact() {
+        let motion = Vec3d::new([10.0, 0.0, 0.0]);
+        let start = Vec3d::new([-5.0, 0.0, 0.0]);
+
+        let time = time_of_impact(0.5, start, motion, &ColliderShape::Sphere(1.0)).unwrap();
+        assert!((time - 0.35).abs() < 1e-9);
+        let time = time_of_impact(0.5, start, motion, &ColliderShape::Box(Vec3d::new([0.05, 1.0, 1.0]))).unwrap();
+        assert!((time - 0.445).abs() < 1e-9);
+
+        // passes beside it, stops short of it, already touching it
+        assert!(time_of_impact(0.5, Vec3d::new([-5.0, 2.0, 0.0]), motion, &ColliderShape::Sphere(1.0)).is_none());
+        assert!(time_of_impact(0.5, start, motion * 0.1, &ColliderShape::Sphere(1.0)).is_none());
+        assert!(time_of_impact(0.5, Vec3d::new([-1.2, 0.0, 0.0]), motion, &ColliderShape::Sphere(1.0)).is_none());
+    }
+}
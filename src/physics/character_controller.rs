@@ -0,0 +1,333 @@
+use crate::{
+    ecs::System,
+    types::{position::Position, transform::Transform, vectors::{Vec3d, Vec3f}},
+};
+
+use super::collider::{overlap, Collider, ColliderShape};
+
+// penetrations resolved per substep, a corner between a floor and two walls needs three
+const MAX_ITERATIONS: usize = 4;
+// spheres along the capsule's axis tried against a heightfield
+const HEIGHTFIELD_SAMPLES: usize = 8;
+
+// an upright capsule moved by its velocity every fixed step, sliding along whatever it runs into
+// instead of bouncing, the transform's position is the middle of the capsule and its rotation is ignored
+#[derive(Debug, Clone)]
+pub struct CharacterController {
+    pub radius: f64,
+    // from the bottom of the capsule to the top
+    pub height: f64,
+    // steepest ground that can be stood on, in radians, anything steeper is a wall
+    pub max_slope: f32,
+    // highest ledge walked onto without jumping, steeper slopes are climbed up to this height too
+    pub step_height: f64,
+    // how close a surface has to be to count as touching
+    pub skin_width: f64,
+    // layers of the colliders it runs into
+    pub mask: u32,
+    // desired motion in units per second, gravity included
+    pub velocity: Vec3f,
+    ground_normal: Option<Vec3f>,
+    walls: Vec<Vec3f>,
+    previous: Option<Position>,
+}
+
+// normal pointing out of the obstacle, how deep the capsule is in it
+// and how high above the bottom of the capsule they touch
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    normal: Vec3d,
+    depth: f64,
+    height: f64,
+}
+
+// what one move did, relative to where it started
+#[derive(Debug, Clone)]
+pub(super) struct Movement {
+    pub offset: Vec3d,
+    pub ground_normal: Option<Vec3d>,
+    pub walls: Vec<Vec3d>,
+}
+
+impl CharacterController {
+    pub fn new(radius: f64, height: f64) -> CharacterController {
+        CharacterController {
+            radius,
+            height,
+            max_slope: 45f32.to_radians(),
+            step_height: 0.3,
+            skin_width: 0.02,
+            mask: u32::MAX,
+            velocity: Vec3f::new([0.0, 0.0, 0.0]),
+            ground_normal: None,
+            walls: Vec::new(),
+            previous: None,
+        }
+    }
+
+    // standing on walkable ground after the last step
+    pub fn grounded(&self) -> bool {
+        self.ground_normal.is_some()
+    }
+
+    pub fn ground_normal(&self) -> Option<Vec3f> {
+        self.ground_normal
+    }
+
+    // normals of the walls and too steep slopes it ran into during the last step
+    pub fn walls(&self) -> &[Vec3f] {
+        &self.walls
+    }
+
+    // `transform` as it's drawn `alpha` of the way from the last fixed step to the current one
+    pub fn interpolated(&self, transform: &Transform, alpha: f32) -> Transform {
+        let Some(previous) = self.previous else {
+            return transform.clone();
+        };
+        let offset = Vec3d::from(transform.position - previous) * alpha as f64;
+        Transform { position: previous + Position::from(offset), ..transform.clone() }
+    }
+
+    fn walkable(&self, normal: Vec3d) -> bool {
+        normal.y >= self.max_slope.cos() as f64
+    }
+
+    // upward facing and touching low enough to be climbed like a stair
+    fn step(&self, contact: &Contact) -> bool {
+        contact.normal.y > 0.0 && contact.height <= self.step_height
+    }
+
+    fn is_wall(&self, contact: &Contact) -> bool {
+        !self.walkable(contact.normal) && !self.step(contact) && contact.normal.y > -1e-3
+    }
+
+    // which way a contact pushes: straight up off ground and steps so standing on a slope doesn't slide,
+    // sideways off walls so running into them doesn't climb, and along the normal off ceilings
+    fn push_direction(&self, contact: &Contact) -> Vec3d {
+        if self.walkable(contact.normal) || self.step(contact) {
+            return Vec3d::new([0.0, 1.0, 0.0]);
+        }
+        let flat = Vec3d::new([contact.normal.x, 0.0, contact.normal.z]);
+        if contact.normal.y > -1e-3 && flat.length() > 1e-9 {
+            flat / flat.length()
+        } else {
+            contact.normal
+        }
+    }
+
+    fn contact(&self, position: Vec3d, center: Vec3d, shape: &ColliderShape) -> Option<Contact> {
+        let half = (self.height / 2.0 - self.radius).max(0.0);
+        let bottom = position - Vec3d::new([0.0, half, 0.0]);
+        let segment = |t: f64| bottom + Vec3d::new([0.0, 2.0 * half * t, 0.0]);
+
+        // the point of the capsule's axis closest to the obstacle
+        let t = match shape {
+            ColliderShape::Sphere(_) if half > 0.0 => ((center.y - bottom.y) / (2.0 * half)).clamp(0.0, 1.0),
+            ColliderShape::Sphere(_) => 0.0,
+            ColliderShape::Box(size) => {
+                // signed distance to the box, convex so a ternary search finds its minimum along the axis
+                let distance = |t: f64| {
+                    let offset = segment(t) - center;
+                    let outside = Vec3d::new([offset.x.abs() - size.x, offset.y.abs() - size.y, offset.z.abs() - size.z]);
+                    let clamped = Vec3d::new([outside.x.max(0.0), outside.y.max(0.0), outside.z.max(0.0)]);
+                    clamped.length() + outside.x.max(outside.y).max(outside.z).min(0.0)
+                };
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..40 {
+                    let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+                    if distance(a) <= distance(b) { high = b } else { low = a }
+                }
+                (low + high) / 2.0
+            }
+            // the deepest of a few spheres along the axis, terrain is rarely convex
+            ColliderShape::Heightfield(_) => {
+                let depth = |t: f64| overlap(&ColliderShape::Sphere(self.radius), shape, center - segment(t)).map_or(f64::MIN, |x| x.1);
+                (0..=HEIGHTFIELD_SAMPLES)
+                    .map(|x| x as f64 / HEIGHTFIELD_SAMPLES as f64)
+                    .max_by(|a, b| depth(*a).total_cmp(&depth(*b)))
+                    .unwrap()
+            }
+        };
+        let point = segment(t);
+        let (normal, depth) = overlap(&ColliderShape::Sphere(self.radius), shape, center - point)?;
+        let normal = Vec3d::new([0.0, 0.0, 0.0]) - normal;
+        let height = point.y - normal.y * self.radius - (position.y - self.height / 2.0);
+        Some(Contact { normal, depth, height })
+    }
+
+    // pushes the capsule out of the obstacles, adding what it was pushed by to `contacts`
+    fn depenetrate(&self, mut position: Vec3d, obstacles: &[(Vec3d, &ColliderShape)], contacts: &mut Vec<Contact>) -> Vec3d {
+        for _ in 0..MAX_ITERATIONS {
+            let deepest = obstacles.iter()
+                .filter_map(|(center, shape)| self.contact(position, *center, shape))
+                .filter(|x| x.depth > 1e-9)
+                .max_by(|a, b| a.depth.total_cmp(&b.depth));
+            let Some(contact) = deepest else {
+                break;
+            };
+            let push = self.push_direction(&contact);
+            position += push * (contact.depth / contact.normal.dot(push).max(1e-3));
+            contacts.push(contact);
+        }
+        position
+    }
+
+    // the most upright walkable surface within skin_width below
+    fn ground(&self, position: Vec3d, obstacles: &[(Vec3d, &ColliderShape)]) -> Option<Vec3d> {
+        let probe = position - Vec3d::new([0.0, self.skin_width, 0.0]);
+        obstacles.iter()
+            .filter_map(|(center, shape)| self.contact(probe, *center, shape))
+            .map(|x| x.normal)
+            .filter(|x| self.walkable(*x))
+            .max_by(|a, b| a.y.total_cmp(&b.y))
+    }
+
+    // `obstacles` are centers relative to the capsule's and shapes, moves in substeps of half the radius
+    // so nothing thinner is tunneled through
+    pub(super) fn move_and_slide(&self, obstacles: &[(Vec3d, &ColliderShape)], motion: Vec3d) -> Movement {
+        let substeps = (motion.length() / (self.radius / 2.0)).ceil().max(1.0) as usize;
+        let mut step = motion / substeps as f64;
+        let mut position = Vec3d::new([0.0, 0.0, 0.0]);
+        let mut walls = Vec::new();
+
+        for _ in 0..substeps {
+            let mut contacts = Vec::new();
+            position = self.depenetrate(position + step, obstacles, &mut contacts);
+
+            // what's left of the motion slides along everything it hit
+            for contact in contacts {
+                let push = self.push_direction(&contact);
+                step = step - push * step.dot(push).min(0.0);
+                if self.is_wall(&contact) {
+                    walls.push(contact.normal);
+                }
+            }
+        }
+
+        Movement { offset: position, ground_normal: self.ground(position, obstacles), walls }
+    }
+}
+
+pub struct CharacterControllerSystem {}
+
+impl System for CharacterControllerSystem {
+    fn on_start(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    fn on_update(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    // after the rigidbodies moved, so it stands on moving platforms where they are now
+    fn on_fixed_update(&self, world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
+        let entities = world.entities.borrow_mut();
+        let colliders: Vec<(Position, ColliderShape, u32)> = entities
+            .query::<(&Transform, &Collider)>()
+            .without::<&CharacterController>()
+            .iter()
+            .filter(|(_, (_, collider))| !collider.is_trigger)
+            .map(|(_, (transform, collider))| (collider.center(transform), collider.shape.clone(), collider.layer))
+            .collect();
+
+        for (_, (controller, transform)) in entities.query::<(&mut CharacterController, &mut Transform)>().iter() {
+            let motion = controller.velocity.to_vec3d() * state.physics.timestep;
+            let reach = motion.length() + controller.height + controller.skin_width;
+            let obstacles: Vec<(Vec3d, &ColliderShape)> = colliders.iter()
+                .filter(|(_, _, layer)| controller.mask & (1 << layer) != 0)
+                .map(|(center, shape, _)| (Vec3d::from(*center - transform.position), shape))
+                .filter(|(offset, shape)| {
+                    let bound = match shape {
+                        ColliderShape::Sphere(radius) => *radius,
+                        ColliderShape::Box(half) => half.length(),
+                        ColliderShape::Heightfield(field) => field.bounding_radius(),
+                    };
+                    offset.length() - bound < reach
+                })
+                .collect();
+
+            let movement = controller.move_and_slide(&obstacles, motion);
+            controller.previous = Some(transform.position);
+            transform.position += movement.offset.into();
+            controller.ground_normal = movement.ground_normal.map(|x| x.to_vec3f());
+            controller.walls = movement.walls.iter().map(|x| x.to_vec3f()).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{physics::collider::ColliderShape, types::vectors::Vec3d};
+
+    use super::CharacterController;
+
+    // a 2 m tall capsule standing on a floor whose top is at y = -1, walked by `motion` 60 times,
+    // returns where it ends up and whether it's grounded
+    fn walk(controller: &CharacterController, obstacles: &[(Vec3d, ColliderShape)], motion: [f64; 3]) -> (Vec3d, bool, usize) {
+        let floor = (Vec3d::new([0.0, -2.0, 0.0]), ColliderShape::Box(Vec3d::new([50.0, 1.0, 50.0])));
+        let mut position = Vec3d::new([0.0, 0.0, 0.0]);
+        let mut grounded = false;
+        let mut walls = 0;
+        for _ in 0..60 {
+            let relative: Vec<(Vec3d, &ColliderShape)> = obstacles.iter().chain([&floor])
+                .map(|(center, shape)| (*center - position, shape))
+                .collect();
+            let movement = controller.move_and_slide(&relative, Vec3d::new(motion));
+            position += movement.offset;
+            grounded = movement.ground_normal.is_some();
+            walls = movement.walls.len();
+        }
+        (position, grounded, walls)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.02
+    }
+
+    #[test]
+    fn test_slides_along_wall_at_45_degrees() {
+        let controller = CharacterController::new(0.5, 2.0);
+        // a wall with its face at x = 1
+        let wall = (Vec3d::new([1.5, 0.0, 0.0]), ColliderShape::Box(Vec3d::new([0.5, 5.0, 50.0])));
+
+        let (position, grounded, walls) = walk(&controller, &[wall], [0.05, -0.05, 0.05]);
+        assert!(close(position.x, 0.5) && position.x <= 0.5 + 1e-6);
+        // none of the motion along the wall is lost
+        assert!(close(position.z, 3.0));
+        assert!(close(position.y, 0.0));
+        assert!(grounded && walls > 0);
+    }
+
+    #[test]
+    fn test_stops_at_slope_steeper_than_limit() {
+        let controller = CharacterController::new(0.5, 2.0);
+        // a big ball rising almost straight up from the floor at x = 1
+        let steep = (Vec3d::new([11.0, -1.0, 0.0]), ColliderShape::Sphere(10.0));
+        let (position, grounded, walls) = walk(&controller, &[steep], [0.05, -0.05, 0.0]);
+        assert!(position.x < 0.55);
+        assert!(close(position.y, 0.0));
+        assert!(grounded && walls > 0);
+
+        // a gentle hill rising above the floor is walked up
+        let hill = (Vec3d::new([0.0, -100.5, 0.0]), ColliderShape::Sphere(100.0));
+        let (position, grounded, walls) = walk(&controller, &[hill], [0.05, -0.05, 0.0]);
+        assert!(close(position.x, 3.0));
+        // the bottom of the capsule rests on the hill right below its center
+        let resting = (100.5f64 * 100.5 - 3.0 * 3.0).sqrt() - 100.5 + 0.5;
+        assert!(close(position.y, resting));
+        assert!(grounded && walls == 0);
+    }
+
+    #[test]
+    fn test_steps_onto_low_ledges_only() {
+        let controller = CharacterController::new(0.5, 2.0);
+        // ledges starting at x = 1.5, 0.25 and 0.6 high
+        let ledge = |height: f64| (Vec3d::new([6.5, -1.0 + height / 2.0, 0.0]), ColliderShape::Box(Vec3d::new([5.0, height / 2.0, 50.0])));
+
+        let (position, grounded, _) = walk(&controller, &[ledge(0.25)], [0.05, -0.05, 0.0]);
+        assert!(close(position.x, 3.0));
+        assert!(close(position.y, 0.25));
+        assert!(grounded);
+
+        let (position, _, walls) = walk(&controller, &[ledge(0.6)], [0.05, -0.05, 0.0]);
+        assert!(close(position.x, 1.0));
+        assert!(close(position.y, 0.0));
+        assert!(walls > 0);
+    }
+}
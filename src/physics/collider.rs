@@ -1,42 +1,304 @@
+use arrayvec::ArrayVec;
 use hecs::Entity;
 
-use crate::types::{transform::Transform, vectors::Vec3d};
+use crate::types::{position::Position, transform::Transform, vectors::{Vec3d, Vec3f}};
 
-use super::rigidbody::Rigidbody;
+use super::{broadphase::Aabb, heightfield::Heightfield, rigidbody::Rigidbody};
 
 #[derive(Debug, Clone)]
-pub enum Collider {
-    Sphere(f64)
+pub enum ColliderShape {
+    Sphere(f64),
+    // half extents, axis aligned in world space whatever the rotation
+    Box(Vec3d),
+    // static whatever rigidbody it's on, doesn't turn with the entity either
+    Heightfield(Heightfield),
+}
+
+// an entity with a collider but no rigidbody is static
+#[derive(Debug, Clone)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    // overlaps are reported as TriggerEvents and nothing is pushed
+    pub is_trigger: bool,
+    // from the entity's position, turned with its rotation
+    pub offset: Vec3f,
+    // bit index, see PhysicsSettings::layer for names
+    pub layer: u32,
+    // layers this collider collides with and triggers on, both colliders have to accept each other
+    pub mask: u32,
+}
+
+impl Collider {
+    pub fn new(shape: ColliderShape) -> Collider {
+        Collider {
+            shape,
+            is_trigger: false,
+            offset: Vec3f::new([0.0, 0.0, 0.0]),
+            layer: 0,
+            mask: u32::MAX,
+        }
+    }
+
+    pub fn interacts(&self, other: &Collider) -> bool {
+        self.mask & (1 << other.layer) != 0 && other.mask & (1 << self.layer) != 0
+    }
+
+    pub fn center(&self, transform: &Transform) -> Position {
+        let offset = self.offset * transform.rotation;
+        transform.position + Position::from(Vec3d::new([offset.x as f64, offset.y as f64, offset.z as f64]))
+    }
+
+    pub fn aabb(&self, transform: &Transform) -> Aabb {
+        let (center, half_extents) = match &self.shape {
+            ColliderShape::Sphere(radius) => (Vec3d::new([0.0, 0.0, 0.0]), Vec3d::new([*radius, *radius, *radius])),
+            ColliderShape::Box(half) => (Vec3d::new([0.0, 0.0, 0.0]), *half),
+            ColliderShape::Heightfield(field) => field.bounds(),
+        };
+        Aabb { center: self.center(transform) + Position::from(center), half_extents }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContactPoint {
+    // from a's position
+    pub point: Vec3d,
+    pub depth: f64,
+    // the same for the same corner of the same faces touching on the next step
+    pub feature: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContactManifold {
+    pub points: ArrayVec<ContactPoint, 4>,
+    // from a towards b
+    pub normal: Vec3d,
+}
+
+impl ContactManifold {
+    pub fn depth(&self) -> f64 {
+        self.points.iter().map(|x| x.depth).fold(0.0, f64::max)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Collision {
     pub entity_a: Entity,
     pub entity_b: Entity,
+    // separating the two in one go, split by their inverse masses
     pub move_a: Vec3d,
     pub move_b: Vec3d,
+    pub manifold: ContactManifold,
+}
+
+fn finite(vec: Vec3d) -> bool {
+    vec.x.is_finite() && vec.y.is_finite() && vec.z.is_finite()
+}
+
+impl Collision {
+    // false once a NaN or infinite position got into either body
+    pub fn is_finite(&self) -> bool {
+        finite(self.move_a) && finite(self.move_b) && finite(self.manifold.normal)
+            && self.manifold.points.iter().all(|x| finite(x.point) && x.depth.is_finite())
+    }
+}
+
+fn inverse_mass(rigidbody: Option<&Rigidbody>, collider: &Collider) -> f64 {
+    if matches!(collider.shape, ColliderShape::Heightfield(_)) {
+        return 0.0;
+    }
+    rigidbody.map_or(0.0, |x| x.inverse_mass() as f64)
+}
+
+fn flip(found: Option<(Vec3d, f64)>) -> Option<(Vec3d, f64)> {
+    found.map(|(normal, depth)| (Vec3d::new([0.0, 0.0, 0.0]) - normal, depth))
+}
+
+fn axis(index: usize, sign: f64) -> Vec3d {
+    let mut val = [0.0; 3];
+    val[index] = if sign < 0.0 { -1.0 } else { 1.0 };
+    Vec3d::new(val)
+}
+
+fn components(vec: Vec3d) -> [f64; 3] {
+    [vec.x, vec.y, vec.z]
+}
+
+// `point` is the sphere's center relative to the box's, the normal points from the box towards the sphere
+fn sphere_to_box(radius: f64, half: Vec3d, point: Vec3d) -> Option<(Vec3d, f64)> {
+    let closest = Vec3d::new([
+        point.x.clamp(-half.x, half.x),
+        point.y.clamp(-half.y, half.y),
+        point.z.clamp(-half.z, half.z),
+    ]);
+    let delta = point - closest;
+    let dst = delta.length();
+    if dst > 0.0 {
+        return (dst <= radius).then(|| (delta / dst, radius - dst));
+    }
+
+    // the center is inside, out through the nearest face
+    let (point, half) = (components(point), components(half));
+    let index = (0..3).min_by(|a, b| (half[*a] - point[*a].abs()).total_cmp(&(half[*b] - point[*b].abs()))).unwrap();
+    Some((axis(index, point[index]), radius + half[index] - point[index].abs()))
+}
+
+// normal from a towards b and how deep they overlap, `offset` is b's center relative to a's
+pub fn overlap(a: &ColliderShape, b: &ColliderShape, offset: Vec3d) -> Option<(Vec3d, f64)> {
+    match (a, b) {
+        (ColliderShape::Sphere(a_r), ColliderShape::Sphere(b_r)) => {
+            let dst = offset.length();
+            let depth = a_r + b_r - dst;
+            if depth < 0.0 {
+                return None;
+            }
+            let normal = if dst > 0.0 { offset / dst } else { axis(1, 1.0) };
+            Some((normal, depth))
+        }
+        (ColliderShape::Sphere(a_r), ColliderShape::Box(b_half)) => {
+            sphere_to_box(*a_r, *b_half, Vec3d::new([0.0, 0.0, 0.0]) - offset).map(|(normal, depth)| {
+                (Vec3d::new([0.0, 0.0, 0.0]) - normal, depth)
+            })
+        }
+        (ColliderShape::Box(a_half), ColliderShape::Sphere(b_r)) => sphere_to_box(*b_r, *a_half, offset),
+        (ColliderShape::Box(a_half), ColliderShape::Box(b_half)) => {
+            let (offset, a_half, b_half) = (components(offset), components(*a_half), components(*b_half));
+            let depths = [0, 1, 2].map(|i| a_half[i] + b_half[i] - offset[i].abs());
+            if depths.iter().any(|x| *x < 0.0) {
+                return None;
+            }
+            let index = (0..3).min_by(|a, b| depths[*a].total_cmp(&depths[*b])).unwrap();
+            Some((axis(index, offset[index]), depths[index]))
+        }
+        (ColliderShape::Heightfield(field), ColliderShape::Sphere(b_r)) => field.sphere(offset, *b_r),
+        (ColliderShape::Sphere(a_r), ColliderShape::Heightfield(field)) => {
+            flip(field.sphere(Vec3d::new([0.0, 0.0, 0.0]) - offset, *a_r))
+        }
+        (ColliderShape::Heightfield(field), ColliderShape::Box(b_half)) => {
+            field.box_contacts(offset, *b_half).map(|(normal, points)| (normal, points[0].depth))
+        }
+        (ColliderShape::Box(a_half), ColliderShape::Heightfield(field)) => {
+            flip(field.box_contacts(Vec3d::new([0.0, 0.0, 0.0]) - offset, *a_half).map(|(normal, points)| (normal, points[0].depth)))
+        }
+        (ColliderShape::Heightfield(_), ColliderShape::Heightfield(_)) => None,
+    }
+}
+
+// where a and b touch from a's center, `offset`, `normal` and `depth` as given by `overlap`,
+// one point for spheres, the corners of the overlap of the touching faces for two boxes
+// and the deepest corners and samples for a box on a heightfield
+fn contacts(a: &ColliderShape, b: &ColliderShape, offset: Vec3d, normal: Vec3d, depth: f64) -> ArrayVec<ContactPoint, 4> {
+    let mut points = ArrayVec::new();
+    match (a, b) {
+        (ColliderShape::Sphere(a_r), _) => points.push(ContactPoint { point: normal * (a_r - depth / 2.0), depth, feature: 0 }),
+        (_, ColliderShape::Sphere(b_r)) => points.push(ContactPoint { point: offset - normal * (b_r - depth / 2.0), depth, feature: 0 }),
+        (ColliderShape::Box(a_half), ColliderShape::Box(b_half)) => {
+            // b's face clipped to a's, half way through along the normal
+            let (offset, normal, a_half, b_half) = (components(offset), components(normal), components(*a_half), components(*b_half));
+            let axis = (0..3).find(|i| normal[*i] != 0.0).unwrap();
+            let range = |i: usize| [(-a_half[i]).max(offset[i] - b_half[i]), a_half[i].min(offset[i] + b_half[i])];
+            let (j, k) = ((axis + 1) % 3, (axis + 2) % 3);
+            let face = (axis * 2 + (normal[axis] > 0.0) as usize) as u32;
+            for corner in 0..4 {
+                let mut point = [0.0; 3];
+                point[axis] = normal[axis] * (a_half[axis] - depth / 2.0);
+                point[j] = range(j)[corner & 1];
+                point[k] = range(k)[corner >> 1];
+                points.push(ContactPoint { point: Vec3d::new(point), depth, feature: face * 4 + corner as u32 });
+            }
+        }
+        (ColliderShape::Heightfield(field), ColliderShape::Box(b_half)) => {
+            if let Some((_, found)) = field.box_contacts(offset, *b_half) {
+                points = found;
+            }
+        }
+        (ColliderShape::Box(a_half), ColliderShape::Heightfield(field)) => {
+            // found from the heightfield's center
+            if let Some((_, found)) = field.box_contacts(Vec3d::new([0.0, 0.0, 0.0]) - offset, *a_half) {
+                points.extend(found.into_iter().map(|x| ContactPoint { point: x.point + offset, ..x }));
+            }
+        }
+        (ColliderShape::Heightfield(_), ColliderShape::Heightfield(_)) => {}
+    }
+    points
 }
 
-pub fn sphere_to_sphere(
-    a: (Entity, &Transform, &Rigidbody, f64), 
-    b: (Entity, &Transform, &Rigidbody, f64)
+// pushes solid colliders apart by their inverse masses, two bodies that can't be pushed stay where they are
+pub fn collide(
+    a: (Entity, &Transform, Option<&Rigidbody>, &Collider),
+    b: (Entity, &Transform, Option<&Rigidbody>, &Collider)
 ) -> Option<Collision> {
-    let dst_centers = (a.1.position - b.1.position).length();
-    let dst = dst_centers - a.3 - b.3;
-
-    if dst <= 0.0 {
-        let total_mass = a.2.mass + b.2.mass;
-        let move_a_norm: Vec3d = ((a.1.position - b.1.position) / dst_centers).into();
-        let move_b_norm: Vec3d = ((b.1.position - a.1.position) / dst_centers).into();
-        let move_a = move_a_norm * (b.2.mass / total_mass) as f64 * -dst;
-        let move_b = move_b_norm * (a.2.mass / total_mass) as f64 * -dst;
-
-        return Some(Collision {
-            entity_a: a.0,
-            entity_b: b.0,
-            move_a,
-            move_b
-        });
-    }
-    None
+    let inverse_a = inverse_mass(a.2, a.3);
+    let inverse_b = inverse_mass(b.2, b.3);
+    let total_inverse = inverse_a + inverse_b;
+    if a.3.is_trigger || b.3.is_trigger || total_inverse <= 0.0 {
+        return None;
+    }
+
+    let offset: Vec3d = (b.3.center(b.1) - a.3.center(a.1)).into();
+    let (normal, depth) = overlap(&a.3.shape, &b.3.shape, offset)?;
+    let center = Vec3d::from(a.3.center(a.1) - a.1.position);
+    let mut points = contacts(&a.3.shape, &b.3.shape, offset, normal, depth);
+    for contact in points.iter_mut() {
+        contact.point += center;
+    }
+
+    Some(Collision {
+        entity_a: a.0,
+        entity_b: b.0,
+        move_a: normal * (inverse_a / total_inverse) * -depth,
+        move_b: normal * (inverse_b / total_inverse) * depth,
+        manifold: ContactManifold { points, normal },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::vectors::Vec3d;
+
+    use super::{contacts, overlap, ColliderShape};
+
+    #[test]
+    fn test_sphere_to_box_normals() {
+        let sphere = ColliderShape::Sphere(0.5);
+        let cube = ColliderShape::Box(Vec3d::new([1.0, 1.0, 1.0]));
+
+        // box above the sphere
+        let (normal, depth) = overlap(&sphere, &cube, Vec3d::new([0.0, 1.25, 0.0])).unwrap();
+        assert_eq!(normal, Vec3d::new([0.0, 1.0, 0.0]));
+        assert!((depth - 0.25).abs() < 1e-9);
+        let (normal, _) = overlap(&cube, &sphere, Vec3d::new([0.0, -1.25, 0.0])).unwrap();
+        assert_eq!(normal, Vec3d::new([0.0, -1.0, 0.0]));
+        // center inside the box, out through the nearest face
+        let (normal, depth) = overlap(&cube, &sphere, Vec3d::new([0.0, 0.0, 0.75])).unwrap();
+        assert_eq!(normal, Vec3d::new([0.0, 0.0, 1.0]));
+        assert!((depth - 0.75).abs() < 1e-9);
+
+        assert!(overlap(&sphere, &cube, Vec3d::new([1.4, 1.4, 0.0])).is_none());
+        assert!(overlap(&cube, &cube, Vec3d::new([1.5, 2.5, 0.0])).is_none());
+    }
+
+    #[test]
+    fn test_box_contacts_are_corners_of_overlap() {
+        let floor = ColliderShape::Box(Vec3d::new([10.0, 1.0, 10.0]));
+        let crate_box = ColliderShape::Box(Vec3d::new([0.5, 0.5, 0.5]));
+        let offset = Vec3d::new([3.0, 1.4, -2.0]);
+        let (normal, depth) = overlap(&floor, &crate_box, offset).unwrap();
+        assert_eq!(normal, Vec3d::new([0.0, 1.0, 0.0]));
+        let points = contacts(&floor, &crate_box, offset, normal, depth);
+        assert_eq!(points.len(), 4);
+        for (x, z) in [(2.5, -2.5), (2.5, -1.5), (3.5, -2.5), (3.5, -1.5)] {
+            assert!(points.iter().any(|p| (p.point - Vec3d::new([x, 0.95, z])).length() < 1e-9));
+        }
+        // every corner keeps its id
+        let mut features = points.iter().map(|x| x.feature).collect::<Vec<_>>();
+        features.dedup();
+        assert_eq!(features.len(), 4);
+
+        // hanging over the edge, clipped to the floor
+        let offset = Vec3d::new([10.25, 1.4, 0.0]);
+        let (normal, depth) = overlap(&floor, &crate_box, offset).unwrap();
+        let points = contacts(&floor, &crate_box, offset, normal, depth);
+        assert!(points.iter().all(|p| p.point.x >= 9.75 && p.point.x <= 10.0));
+        assert!(points.iter().all(|p| (p.depth - 0.1).abs() < 1e-9));
+    }
 }
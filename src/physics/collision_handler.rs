@@ -1,10 +1,76 @@
-use log::debug;
+use std::{cell::{Cell, RefCell}, collections::{BTreeSet, HashMap}};
 
-use crate::{ecs::System, types::transform::Transform};
+use hecs::Entity;
+use log::{debug, warn};
 
-use super::{collider::{sphere_to_sphere, Collider}, rigidbody::Rigidbody};
+use crate::{ecs::System, types::{quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}}};
 
-pub struct CollisionHandler {}
+use super::{broadphase::{SpatialGrid, MAX_GRID_CELLS}, ccd::sweep, collider::{collide, overlap, Collider, ColliderShape, Collision}, rigidbody::Rigidbody};
+
+// passes over all contacts every step, each one pushes a stack a little further apart
+const SOLVER_ITERATIONS: usize = 8;
+// overlap left between resting bodies so they still touch on the next step
+const CONTACT_SLOP: f64 = 0.001;
+
+// `trigger` is the entity whose collider is a trigger, when both are either one can be,
+// an exit can name entities that were despawned since the last frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerEvent {
+    Enter { trigger: Entity, other: Entity },
+    Stay { trigger: Entity, other: Entity },
+    Exit { trigger: Entity, other: Entity },
+}
+
+// (entity a, entity b, contact feature)
+type ContactKey = (Entity, Entity, u32);
+
+// the (trigger, other) pairs that overlapped last frame, the collisions resolved by the last step
+// and the impulses their contact points ended with
+pub struct CollisionHandler {
+    overlaps: RefCell<BTreeSet<(Entity, Entity)>>,
+    contacts: RefCell<Vec<Collision>>,
+    impulses: RefCell<HashMap<ContactKey, f32>>,
+    // candidate pairs the broadphase found in the last step
+    pair_count: Cell<usize>,
+}
+
+impl CollisionHandler {
+    pub fn new() -> CollisionHandler {
+        CollisionHandler {
+            overlaps: RefCell::new(BTreeSet::new()),
+            contacts: RefCell::new(Vec::new()),
+            impulses: RefCell::new(HashMap::new()),
+            pair_count: Cell::new(0),
+        }
+    }
+
+    // resolves this frame's collisions and diffs the trigger overlaps against the last frame's,
+    // bodies with ccd are first moved back to where they hit something on the way
+    pub(super) fn step(&self, entities: &hecs::World, cell_size: f64) -> Vec<TriggerEvent> {
+        sweep(entities, cell_size);
+        let (overlaps, contacts, pair_count) = resolve_collisions(entities, cell_size, &mut self.impulses.borrow_mut());
+        self.contacts.replace(contacts);
+        self.pair_count.set(pair_count);
+        let previous = self.overlaps.replace(overlaps);
+        let overlaps = self.overlaps.borrow();
+
+        let exits = previous.difference(&overlaps).map(|(trigger, other)| TriggerEvent::Exit { trigger: *trigger, other: *other });
+        let rest = overlaps.iter().map(|(trigger, other)| {
+            if previous.contains(&(*trigger, *other)) {
+                TriggerEvent::Stay { trigger: *trigger, other: *other }
+            } else {
+                TriggerEvent::Enter { trigger: *trigger, other: *other }
+            }
+        });
+        exits.chain(rest).collect()
+    }
+}
+
+impl Default for CollisionHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl System for CollisionHandler {
     fn on_start(
@@ -17,40 +83,586 @@ impl System for CollisionHandler {
 
     fn on_update(
         &self,
-        world: &crate::ecs::World,
+        _world: &crate::ecs::World,
         _assets: &mut crate::asset_library::AssetLibrary,
         _state: &mut crate::state::State,
+    ) {
+    }
+
+    // after RigidbodyHandler moved the bodies, the events of every step this frame are kept
+    fn on_fixed_update(
+        &self,
+        world: &crate::ecs::World,
+        _assets: &mut crate::asset_library::AssetLibrary,
+        state: &mut crate::state::State,
     ) {
         let entities = world.entities.borrow_mut();
-        let mut collisions = Vec::new();
-        
-        {
-            let mut query = entities.query::<(&Transform, &Rigidbody, &Collider)>();
-            let vec = query.iter().collect::<Vec<_>>();
-
-            for (a, (ta, ra, ca)) in vec.iter() {
-                for (b, (tb, rb, cb)) in vec.iter() {
-                    if a <= b { continue; }
-
-                    match (ca, cb) {
-                        (Collider::Sphere(a_r), Collider::Sphere(b_r)) => {
-                            if let Some(collision) = sphere_to_sphere((*a, ta, ra, *a_r), (*b, tb, rb, *b_r)) {
-                                collisions.push(collision);
-                            }
-                        }
-                    }
+        let events = self.step(&entities, state.physics.broadphase_cell_size);
+        state.trigger_events.extend(events);
+        state.contacts.clone_from(&self.contacts.borrow());
+        state.pair_count = self.pair_count.get() as u32;
+    }
+}
+
+type Body<'a> = (Entity, (&'a Transform, Option<&'a Rigidbody>, &'a Collider));
+
+// pairs of indices into `bodies` whose bounds share a grid cell, as (a, b) with a's entity after b's,
+// in the order a double loop over `bodies` would find them, heightfields and other huge colliders would fill
+// far too many cells so they're paired with everything over their whole footprint instead
+fn candidate_pairs(bodies: &[Body], cell_size: f64) -> Vec<(usize, usize)> {
+    let mut grid = SpatialGrid::new(cell_size);
+    let mut large = Vec::new();
+    let bounds: Vec<_> = bodies.iter().map(|(_, (transform, _, collider))| collider.aabb(transform)).collect();
+    for (i, (_, (_, _, collider))) in bodies.iter().enumerate() {
+        if matches!(collider.shape, ColliderShape::Heightfield(_)) || grid.cell_count(&bounds[i]) > MAX_GRID_CELLS {
+            large.push(i);
+        } else {
+            grid.insert(i, &bounds[i]);
+        }
+    }
+    let mut pairs = grid.pairs();
+    for i in large {
+        pairs.extend((0..bodies.len()).filter(|x| *x != i && bounds[i].overlaps(&bounds[*x])).map(|x| (i, x)));
+    }
+    let mut pairs: Vec<(usize, usize)> = pairs.into_iter()
+        .map(|(i, j)| if bodies[i].0 > bodies[j].0 { (i, j) } else { (j, i) })
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+// a rigidbody touched by a collision, how far it has been pushed so far
+struct SolverBody {
+    rigidbody: Rigidbody,
+    rotation: Quat,
+    moved: Vec3d,
+}
+
+impl SolverBody {
+    fn load(entities: &hecs::World, entity: Entity) -> Option<SolverBody> {
+        if entities.get::<&Collider>(entity).is_ok_and(|x| matches!(x.shape, ColliderShape::Heightfield(_))) {
+            return None;
+        }
+        let rigidbody = entities.get::<&Rigidbody>(entity).ok().map(|x| Rigidbody::clone(&x))?;
+        let rotation = entities.get::<&Transform>(entity).unwrap().rotation;
+        Some(SolverBody { rigidbody, rotation, moved: Vec3d::new([0.0, 0.0, 0.0]) })
+    }
+}
+
+// a contact point from each entity's center of mass, the impulse pushing b away from a along the normal
+struct SolverPoint {
+    a: Entity,
+    b: Entity,
+    arm_a: Vec3f,
+    arm_b: Vec3f,
+    normal: Vec3f,
+    impulse: f32,
+    key: ContactKey,
+}
+
+// how little the point `arm` from the body's center of mass resists an impulse along `normal`
+fn give(body: Option<&SolverBody>, arm: Vec3f, normal: Vec3f) -> f32 {
+    body.map_or(0.0, |x| {
+        x.rigidbody.inverse_mass() + x.rigidbody.inverse_inertia(x.rotation, arm.cross(normal)).cross(arm).dot(normal)
+    })
+}
+
+fn apply_impulse(bodies: &mut HashMap<Entity, SolverBody>, point: &SolverPoint, amount: f32) {
+    let impulse = point.normal * amount;
+    if let Some(body) = bodies.get_mut(&point.a) {
+        body.rigidbody.velocity -= impulse * body.rigidbody.inverse_mass();
+        body.rigidbody.angular_velocity -= body.rigidbody.inverse_inertia(body.rotation, point.arm_a.cross(impulse));
+    }
+    if let Some(body) = bodies.get_mut(&point.b) {
+        body.rigidbody.velocity += impulse * body.rigidbody.inverse_mass();
+        body.rigidbody.angular_velocity += body.rigidbody.inverse_inertia(body.rotation, point.arm_b.cross(impulse));
+    }
+}
+
+// pushes overlapping bodies apart and stops them moving into each other, kinematic and static bodies
+// and colliders without a rigidbody have infinite mass so only the other body moves,
+// returns the (trigger, other) pairs that overlap, the collisions and how many candidate pairs were tested
+fn resolve_collisions(
+    entities: &hecs::World,
+    cell_size: f64,
+    impulses: &mut HashMap<ContactKey, f32>
+) -> (BTreeSet<(Entity, Entity)>, Vec<Collision>, usize) {
+    let mut collisions = Vec::new();
+    let mut overlaps = BTreeSet::new();
+    let pair_count;
+
+    {
+        let mut query = entities.query::<(&Transform, Option<&Rigidbody>, &Collider)>();
+        let vec = query.iter().collect::<Vec<_>>();
+
+        let pairs = candidate_pairs(&vec, cell_size);
+        pair_count = pairs.len();
+        for (i, j) in pairs {
+            let (a, (ta, ra, ca)) = &vec[i];
+            let (b, (tb, rb, cb)) = &vec[j];
+            if !ca.interacts(cb) {
+                continue;
+            }
+            if ca.is_trigger || cb.is_trigger {
+                let offset: Vec3d = (cb.center(tb) - ca.center(ta)).into();
+                if overlap(&ca.shape, &cb.shape, offset).is_some() {
+                    overlaps.insert(if ca.is_trigger { (*a, *b) } else { (*b, *a) });
                 }
+            } else if let Some(collision) = collide((*a, ta, *ra, ca), (*b, tb, *rb, cb)) {
+                collisions.push(collision);
             }
         }
+    }
 
+    collisions.retain(|x| {
+        if !x.is_finite() {
+            warn!("skipped a collision between {} and {} with non-finite vectors", x.entity_a.id(), x.entity_b.id());
+        }
+        x.is_finite()
+    });
+
+    // pushed apart a little at a time so bodies stacked on each other settle, what's left of the overlap
+    // is the overlap minus how far the two already moved apart along the normal
+    let mut bodies: HashMap<Entity, SolverBody> = HashMap::new();
+    for collision in collisions.iter() {
+        for entity in [collision.entity_a, collision.entity_b] {
+            if let Some(body) = SolverBody::load(entities, entity) {
+                bodies.entry(entity).or_insert(body);
+            }
+        }
+    }
+    let inverse_mass = |bodies: &HashMap<Entity, SolverBody>, entity: Entity| bodies.get(&entity).map_or(0.0, |x| x.rigidbody.inverse_mass() as f64);
+    let moved = |bodies: &HashMap<Entity, SolverBody>, entity: Entity| bodies.get(&entity).map_or(Vec3d::new([0.0, 0.0, 0.0]), |x| x.moved);
+    for _ in 0..SOLVER_ITERATIONS {
         for collision in collisions.iter() {
-            let mut a = entities.query_one::<&mut Transform>(collision.entity_a).unwrap();
-            let mut b = entities.query_one::<&mut Transform>(collision.entity_b).unwrap();
+            let (a, b) = (collision.entity_a, collision.entity_b);
+            let normal = collision.manifold.normal;
+            let apart = (moved(&bodies, b) - moved(&bodies, a)).dot(normal);
+            let left = collision.manifold.depth() - CONTACT_SLOP - apart;
+            let (inverse_a, inverse_b) = (inverse_mass(&bodies, a), inverse_mass(&bodies, b));
+            if left <= 0.0 || inverse_a + inverse_b <= 0.0 {
+                continue;
+            }
+            if let Some(body) = bodies.get_mut(&a) {
+                body.moved -= normal * (left * inverse_a / (inverse_a + inverse_b));
+            }
+            if let Some(body) = bodies.get_mut(&b) {
+                body.moved += normal * (left * inverse_b / (inverse_a + inverse_b));
+            }
+        }
+    }
+
+    // inelastic, only the velocity along the normal that closes the gap is removed, every point's impulse
+    // is summed over the iterations and starts from where it ended on the last step
+    let mut points = Vec::new();
+    for collision in collisions.iter() {
+        let (a, b) = (collision.entity_a, collision.entity_b);
+        let between = Vec3d::from(
+            entities.get::<&Transform>(b).unwrap().position - entities.get::<&Transform>(a).unwrap().position
+        );
+        let center = |entity: Entity| {
+            bodies.get(&entity).map_or(Vec3f::new([0.0, 0.0, 0.0]), |x| x.rigidbody.center_of_mass * x.rotation)
+        };
+        for contact in collision.manifold.points.iter() {
+            let key = (a, b, contact.feature);
+            points.push(SolverPoint {
+                a,
+                b,
+                arm_a: contact.point.to_vec3f() - center(a),
+                arm_b: (contact.point - between).to_vec3f() - center(b),
+                normal: collision.manifold.normal.to_vec3f(),
+                impulse: impulses.get(&key).copied().unwrap_or(0.0),
+                key,
+            });
+        }
+    }
+    for point in points.iter() {
+        apply_impulse(&mut bodies, point, point.impulse);
+    }
+    for _ in 0..SOLVER_ITERATIONS {
+        for point in points.iter_mut() {
+            let velocity = |body: Option<&SolverBody>, arm: Vec3f| {
+                body.map_or(Vec3f::new([0.0, 0.0, 0.0]), |x| x.rigidbody.velocity + x.rigidbody.angular_velocity.cross(arm))
+            };
+            let closing = (velocity(bodies.get(&point.b), point.arm_b) - velocity(bodies.get(&point.a), point.arm_a)).dot(point.normal);
+            let total = give(bodies.get(&point.a), point.arm_a, point.normal) + give(bodies.get(&point.b), point.arm_b, point.normal);
+            if total <= 0.0 {
+                continue;
+            }
+            // never pulls the bodies together
+            let impulse = (point.impulse - closing / total).max(0.0);
+            apply_impulse(&mut bodies, point, impulse - point.impulse);
+            point.impulse = impulse;
+        }
+    }
+    impulses.clear();
+    impulses.extend(points.iter().map(|x| (x.key, x.impulse)));
+
+    for (entity, body) in bodies.iter() {
+        entities.get::<&mut Transform>(*entity).unwrap().position += body.moved.into();
+        let mut rigidbody = entities.get::<&mut Rigidbody>(*entity).unwrap();
+        rigidbody.velocity = body.rigidbody.velocity;
+        rigidbody.angular_velocity = body.rigidbody.angular_velocity;
+    }
+    for collision in collisions.iter() {
+        debug!("{} {} {:?} {:?}", collision.entity_a.id(), collision.entity_b.id(), collision.move_a, collision.move_b);
+    }
+
+    (overlaps, collisions, pair_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        frame_pacer::FixedTimestep,
+        physics::{
+            collider::{collide, Collider, ColliderShape},
+            heightfield::Heightfield,
+            rigidbody::{integrate, BodyType, Rigidbody},
+            settings::PhysicsSettings,
+        },
+        types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+    };
+
+    use super::{candidate_pairs, CollisionHandler, TriggerEvent, CONTACT_SLOP};
+
+    fn transform(y: f64) -> Transform {
+        Transform::new(Position::from(Vec3d::new([0.0, y, 0.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))
+    }
+
+    fn sphere(radius: f64) -> Collider {
+        Collider::new(ColliderShape::Sphere(radius))
+    }
+
+    // the rigidbody and collision handlers over five seconds at 60 fps, with every trigger event sent
+    fn simulate(entities: &mut hecs::World, handler: &CollisionHandler) -> Vec<TriggerEvent> {
+        let settings = PhysicsSettings::default();
+        let mut events = Vec::new();
+        for _ in 0..300 {
+            for (_, (rigidbody, transform)) in entities.query_mut::<(&mut Rigidbody, &mut Transform)>() {
+                integrate(rigidbody, transform, &settings, 1.0 / 60.0);
+            }
+            events.extend(handler.step(entities, PhysicsSettings::default().broadphase_cell_size));
+        }
+        events
+    }
+
+    fn height(entities: &hecs::World, entity: hecs::Entity) -> f64 {
+        entities.get::<&Transform>(entity).unwrap().position.position.y
+    }
+
+    #[test]
+    fn test_sphere_rests_on_static_floor() {
+        let mut entities = hecs::World::new();
+        // a huge sphere whose top is at y = 0
+        let floor = entities.spawn((transform(-1000.0), sphere(1000.0)));
+        let mut static_body = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        static_body.body_type = BodyType::Static;
+        let static_floor = entities.spawn((transform(-1000.0), static_body, sphere(1000.0)));
+        let ball = entities.spawn((
+            transform(2.0),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+            sphere(0.5),
+        ));
+
+        simulate(&mut entities, &CollisionHandler::new());
 
-            a.get().unwrap().position += collision.move_a.into();
-            b.get().unwrap().position += collision.move_b.into();
+        assert_eq!(height(&entities, floor), -1000.0);
+        assert_eq!(height(&entities, static_floor), -1000.0);
+        assert!((height(&entities, ball) - 0.5).abs() < 0.01);
+        assert!(entities.get::<&Rigidbody>(ball).unwrap().velocity.y.abs() < 0.2);
+    }
+
+    #[test]
+    fn test_kinematic_body_pushes_but_is_not_pushed() {
+        let mut entities = hecs::World::new();
+        let mut platform = Rigidbody::new(1.0, Vec3f::new([0.0, 1.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        platform.body_type = BodyType::Kinematic;
+        platform.add_force(Vec3f::new([0.0, -100.0, 0.0]));
+        let platform = entities.spawn((transform(-1000.0), platform, sphere(1000.0)));
+        let ball = entities.spawn((
+            transform(0.5),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+            sphere(0.5),
+        ));
+
+        simulate(&mut entities, &CollisionHandler::new());
+
+        // rose at its own velocity, ignoring gravity, forces and the ball on top
+        assert!((height(&entities, platform) + 995.0).abs() < 1e-3);
+        assert!((height(&entities, ball) - 5.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_sphere_passes_through_trigger() {
+        let mut entities = hecs::World::new();
+        let mut zone = Collider::new(ColliderShape::Box(Vec3d::new([1.0, 1.0, 1.0])));
+        zone.is_trigger = true;
+        let zone = entities.spawn((transform(0.0), zone));
+        let mut rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, -3.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        rigidbody.use_gravity = false;
+        let ball = entities.spawn((transform(3.0), rigidbody, sphere(0.5)));
+
+        let events = simulate(&mut entities, &CollisionHandler::new());
+
+        let enters = events.iter().filter(|x| matches!(x, TriggerEvent::Enter { .. })).count();
+        let stays = events.iter().filter(|x| matches!(x, TriggerEvent::Stay { .. })).count();
+        assert_eq!(enters, 1);
+        assert!(stays > 0);
+        assert_eq!(events.last(), Some(&TriggerEvent::Exit { trigger: zone, other: ball }));
+        assert_eq!(events.len(), stays + 2);
+        // went straight through
+        assert!((height(&entities, ball) + 12.0).abs() < 1e-3);
+        assert_eq!(entities.get::<&Rigidbody>(ball).unwrap().velocity.y, -3.0);
+    }
 
-            debug!("{} {} {:?} {:?}", collision.entity_a.id(), collision.entity_b.id(), collision.move_a, collision.move_b);
+    #[test]
+    fn test_trigger_exit_on_despawn() {
+        let mut entities = hecs::World::new();
+        let mut zone = sphere(1.0);
+        zone.is_trigger = true;
+        let zone = entities.spawn((transform(0.0), zone));
+        let ball = entities.spawn((transform(0.0), sphere(0.5)));
+        let handler = CollisionHandler::new();
+
+        assert_eq!(handler.step(&entities, 4.0), vec![TriggerEvent::Enter { trigger: zone, other: ball }]);
+        entities.despawn(ball).unwrap();
+        assert_eq!(handler.step(&entities, 4.0), vec![TriggerEvent::Exit { trigger: zone, other: ball }]);
+        assert!(handler.step(&entities, 4.0).is_empty());
+    }
+
+    #[test]
+    fn test_coincident_spheres_separate() {
+        let mut entities = hecs::World::new();
+        let rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        let a = entities.spawn((transform(1.0), rigidbody.clone(), sphere(0.5)));
+        let b = entities.spawn((transform(1.0), rigidbody, sphere(0.5)));
+
+        CollisionHandler::new().step(&entities, 4.0);
+
+        let position = |entity| Vec3d::from(entities.get::<&Transform>(entity).unwrap().position);
+        let (a, b) = (position(a), position(b));
+        assert!([a.x, a.y, a.z, b.x, b.y, b.z].iter().all(|x| x.is_finite()));
+        assert!(((a - b).length() - (1.0 - CONTACT_SLOP)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_box_stack_stays_put() {
+        let mut entities = hecs::World::new();
+        entities.spawn((transform(-0.5), Collider::new(ColliderShape::Box(Vec3d::new([5.0, 0.5, 5.0])))));
+        let crate_box = Collider::new(ColliderShape::Box(Vec3d::new([0.5, 0.5, 0.5])));
+        let boxes = [0.5, 1.5, 2.5].map(|y| {
+            (entities.spawn((transform(y), Rigidbody::with_collider_inertia(1.0, &crate_box), crate_box.clone())), y)
+        });
+        let handler = CollisionHandler::new();
+
+        // ten seconds
+        for _ in 0..2 {
+            simulate(&mut entities, &handler);
+            for (entity, y) in boxes {
+                let position = Vec3d::from(entities.get::<&Transform>(entity).unwrap().position);
+                assert!((position - Vec3d::new([0.0, y, 0.0])).length() < 0.01);
+            }
         }
     }
+
+    #[test]
+    fn test_sphere_rolls_down_heightfield() {
+        let mut entities = hecs::World::new();
+        // one cell 10 wide, from 5 high at -x down to 0 at +x
+        let slope = Heightfield::new(vec![5.0, 0.0, 5.0, 0.0], (2, 2), 10.0);
+        entities.spawn((transform(0.0), Collider::new(ColliderShape::Heightfield(slope))));
+        let normal = Vec3d::new([1.0, 2.0, 0.0]).normalize();
+        let on_surface = Vec3d::new([0.0, 2.5, 0.0]);
+        let start = Vec3d::new([-3.0, 4.0, 0.0]) + normal * 0.5;
+        let ball = entities.spawn((
+            Transform::new(Position::from(start), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0])),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+            sphere(0.5),
+        ));
+        let handler = CollisionHandler::new();
+
+        let mut x = start.x;
+        for _ in 0..60 {
+            for (_, (rigidbody, transform)) in entities.query_mut::<(&mut Rigidbody, &mut Transform)>() {
+                integrate(rigidbody, transform, &PhysicsSettings::default(), 1.0 / 60.0);
+            }
+            handler.step(&entities, PhysicsSettings::default().broadphase_cell_size);
+
+            // resting on the slope all the way down
+            let position = Vec3d::from(entities.get::<&Transform>(ball).unwrap().position);
+            assert!(((position - on_surface).dot(normal) - 0.5).abs() < 0.01);
+            assert!(position.x >= x);
+            x = position.x;
+        }
+        assert!(x > -2.0);
+    }
+
+    #[test]
+    fn test_fast_sphere_stopped_by_thin_wall() {
+        let fire = |ccd: bool| {
+            let mut entities = hecs::World::new();
+            // 0.1 thick
+            entities.spawn((transform(0.0), Collider::new(ColliderShape::Box(Vec3d::new([0.05, 5.0, 5.0])))));
+            let mut rigidbody = Rigidbody::new(0.01, Vec3f::new([200.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+            rigidbody.use_gravity = false;
+            rigidbody.ccd = ccd;
+            let start = Transform::new(Position::from(Vec3d::new([-2.0, 0.0, 0.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+            let bullet = entities.spawn((start, rigidbody, sphere(0.05)));
+
+            let handler = CollisionHandler::new();
+            for _ in 0..60 {
+                for (_, (rigidbody, transform)) in entities.query_mut::<(&mut Rigidbody, &mut Transform)>() {
+                    integrate(rigidbody, transform, &PhysicsSettings::default(), 1.0 / 60.0);
+                }
+                handler.step(&entities, PhysicsSettings::default().broadphase_cell_size);
+            }
+            let x = entities.get::<&Transform>(bullet).unwrap().position.position.x;
+            let velocity = entities.get::<&Rigidbody>(bullet).unwrap().velocity.x;
+            (x, velocity)
+        };
+
+        // 3.3 m a step jumps right over it
+        assert!(fire(false).0 > 100.0);
+        let (x, velocity) = fire(true);
+        assert!(x < -0.09 && x > -0.11);
+        assert!(velocity.abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_non_finite_collision_is_skipped() {
+        let mut entities = hecs::World::new();
+        let rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        let broken = Transform::new(Position::from(Vec3d::new([f64::NAN, 0.0, 0.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+        entities.spawn((broken, rigidbody.clone(), sphere(0.5)));
+        let ball = entities.spawn((transform(0.0), rigidbody, sphere(0.5)));
+
+        CollisionHandler::new().step(&entities, 4.0);
+
+        assert_eq!(height(&entities, ball), 0.0);
+        assert_eq!(entities.get::<&Rigidbody>(ball).unwrap().velocity, Vec3f::new([0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_broadphase_matches_brute_force() {
+        let mut entities = hecs::World::new();
+        // 2000 spheres scattered through a 60 m cube by a fixed lcg
+        let mut seed: u64 = 7;
+        let mut random = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        for _ in 0..2000 {
+            let position = Vec3d::new([random() * 60.0, random() * 60.0, random() * 60.0]);
+            let transform = Transform::new(Position::from(position), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+            let rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+            entities.spawn((transform, rigidbody, sphere(0.5 + random())));
+        }
+        let mut query = entities.query::<(&Transform, Option<&Rigidbody>, &Collider)>();
+        let bodies = query.iter().collect::<Vec<_>>();
+
+        let mut brute_force = Vec::new();
+        for (i, a) in bodies.iter().enumerate() {
+            for (j, b) in bodies.iter().enumerate() {
+                if a.0 > b.0 {
+                    brute_force.push((i, j));
+                }
+            }
+        }
+        let grid = candidate_pairs(&bodies, 4.0);
+        assert!(grid.len() * 100 < brute_force.len());
+
+        let collisions = |pairs: &[(usize, usize)]| {
+            pairs.iter()
+                .filter_map(|(i, j)| {
+                    let (a, (ta, ra, ca)) = bodies[*i];
+                    let (b, (tb, rb, cb)) = bodies[*j];
+                    collide((a, ta, ra, ca), (b, tb, rb, cb))
+                })
+                .map(|x| (x.entity_a, x.entity_b, x.move_a, x.move_b))
+                .collect::<Vec<_>>()
+        };
+        let expected = collisions(&brute_force);
+        assert!(!expected.is_empty());
+        assert_eq!(collisions(&grid), expected);
+    }
+
+    #[test]
+    fn test_masked_out_pairs_are_ignored() {
+        let mut settings = PhysicsSettings::default();
+        let bullets = settings.add_layer("bullets").unwrap();
+        let mask = settings.mask(&["default"]).unwrap();
+        let mut entities = hecs::World::new();
+        let bullet = || {
+            let mut collider = sphere(0.5);
+            collider.layer = bullets;
+            collider.mask = mask;
+            collider
+        };
+        let rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        let a = entities.spawn((transform(0.0), rigidbody.clone(), bullet()));
+        let b = entities.spawn((transform(0.5), rigidbody.clone(), bullet()));
+        let mut zone = sphere(2.0);
+        zone.is_trigger = true;
+        zone.mask = mask;
+        entities.spawn((transform(0.0), zone));
+
+        let handler = CollisionHandler::new();
+        assert!(handler.step(&entities, 4.0).is_empty());
+        assert_eq!(height(&entities, a), 0.0);
+        assert_eq!(height(&entities, b), 0.5);
+        // the broadphase still pairs them, only the masks keep them apart
+        assert_eq!(handler.pair_count.get(), 3);
+
+        // a default layer body still hits them
+        let c = entities.spawn((transform(-0.5), rigidbody, sphere(0.5)));
+        assert_eq!(handler.step(&entities, 4.0).len(), 1);
+        assert!(height(&entities, c) < -0.5);
+    }
+
+    #[test]
+    fn test_fixed_steps_are_independent_of_render_rate() {
+        // positions after every fixed step of a ball bouncing off a floor and a spinning crate
+        let run = |frame_time: f64| {
+            let settings = PhysicsSettings::default();
+            let mut entities = hecs::World::new();
+            entities.spawn((transform(-1000.0), sphere(1000.0)));
+            let ball = entities.spawn((
+                transform(3.0),
+                Rigidbody::new(1.0, Vec3f::new([0.5, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+                sphere(0.5),
+            ));
+            let crate_box = Collider::new(ColliderShape::Box(Vec3d::new([0.5, 0.5, 0.5])));
+            let mut spinning = Rigidbody::with_collider_inertia(2.0, &crate_box);
+            spinning.angular_velocity = Vec3f::new([0.0, 1.0, 0.0]);
+            let spinning = entities.spawn((transform(1.0), spinning, crate_box));
+
+            let handler = CollisionHandler::new();
+            let mut fixed = FixedTimestep::new();
+            let mut steps = Vec::new();
+            let mut time = 0.0;
+            while time < 3.0 {
+                for _ in 0..fixed.advance(frame_time, 1.0, settings.timestep) {
+                    for (_, (rigidbody, transform)) in entities.query_mut::<(&mut Rigidbody, &mut Transform)>() {
+                        integrate(rigidbody, transform, &settings, settings.timestep as f32);
+                    }
+                    handler.step(&entities, settings.broadphase_cell_size);
+                    let pose = |entity| {
+                        let transform = entities.get::<&Transform>(entity).unwrap();
+                        (transform.position, transform.rotation)
+                    };
+                    steps.push((pose(ball), pose(spinning)));
+                }
+                time += frame_time;
+            }
+            steps
+        };
+
+        let slow = run(1.0 / 30.0);
+        let fast = run(1.0 / 144.0);
+        assert!(slow.len() > 170 && fast.len() > 170);
+        let count = slow.len().min(fast.len());
+        assert!(slow[..count] == fast[..count]);
+    }
 }
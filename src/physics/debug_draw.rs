@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use crate::{
+    ecs::System,
+    rendering::debug_lines::DebugLines,
+    types::{position::Position, transform::Transform, vectors::{Vec3d, Vec4f}},
+};
+
+use super::{
+    broadphase::SpatialGrid,
+    character_controller::CharacterController,
+    collider::{Collider, ColliderShape, Collision},
+    rigidbody::{BodyType, Rigidbody},
+    settings::PhysicsSettings,
+};
+
+const TRIGGER_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+const COLLIDING_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+const DYNAMIC_COLOR: [f32; 4] = [0.2, 1.0, 0.3, 1.0];
+// static and kinematic bodies and colliders without a rigidbody
+const FIXED_COLOR: [f32; 4] = [0.5, 0.6, 0.7, 1.0];
+const CHARACTER_COLOR: [f32; 4] = [0.3, 0.7, 1.0, 1.0];
+const CONTACT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const CELL_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+
+// half the width of the cross marking a contact point and the length of its normal
+const CONTACT_SIZE: f64 = 0.05;
+const NORMAL_LENGTH: f64 = 0.5;
+
+// triggers first, then anything that collided in the last step
+fn collider_color(collider: &Collider, rigidbody: Option<&Rigidbody>, colliding: bool) -> Vec4f {
+    let color = if collider.is_trigger {
+        TRIGGER_COLOR
+    } else if colliding {
+        COLLIDING_COLOR
+    } else if rigidbody.is_some_and(|x| x.body_type == BodyType::Dynamic) {
+        DYNAMIC_COLOR
+    } else {
+        FIXED_COLOR
+    };
+    Vec4f::new(color)
+}
+
+// wireframes of the colliders and character controllers, every point of the `contacts` as a cross with their normal
+// and with debug_draw_broadphase the grid cells the colliders are in
+pub(super) fn draw_physics(entities: &hecs::World, contacts: &[Collision], settings: &PhysicsSettings, lines: &mut DebugLines) {
+    let colliding: HashSet<hecs::Entity> = contacts.iter().flat_map(|x| [x.entity_a, x.entity_b]).collect();
+    let mut grid = SpatialGrid::new(settings.broadphase_cell_size);
+
+    for (i, (entity, (transform, collider, rigidbody))) in entities.query::<(&Transform, &Collider, Option<&Rigidbody>)>().iter().enumerate() {
+        let color = collider_color(collider, rigidbody, colliding.contains(&entity));
+        let center = collider.center(transform);
+        match &collider.shape {
+            ColliderShape::Sphere(radius) => lines.wire_sphere(center, *radius, color),
+            ColliderShape::Box(half_extents) => lines.wire_box(center, *half_extents, color),
+            ColliderShape::Heightfield(field) => {
+                for (start, end) in field.edges() {
+                    lines.line(center + Position::from(start), center + Position::from(end), color);
+                }
+            }
+        }
+        // heightfields aren't in the grid, see candidate_pairs
+        if settings.debug_draw_broadphase && !matches!(collider.shape, ColliderShape::Heightfield(_)) {
+            grid.insert(i, &collider.aabb(transform));
+        }
+    }
+
+    for (_, (transform, controller)) in entities.query::<(&Transform, &CharacterController)>().iter() {
+        lines.wire_capsule(transform.position, controller.radius, controller.height, Vec4f::new(CHARACTER_COLOR));
+    }
+
+    for contact in contacts {
+        let Ok(transform) = entities.get::<&Transform>(contact.entity_a) else {
+            continue;
+        };
+        for contact_point in &contact.manifold.points {
+            let point = transform.position + Position::from(contact_point.point);
+            for axis in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+                let arm = Position::from(Vec3d::new(axis) * CONTACT_SIZE);
+                lines.line(point - arm, point + arm, Vec4f::new(CONTACT_COLOR));
+            }
+            lines.line(point, point + Position::from(contact.manifold.normal * NORMAL_LENGTH), Vec4f::new(CONTACT_COLOR));
+        }
+    }
+
+    for cell in grid.cells() {
+        lines.wire_box(cell.center, cell.half_extents, Vec4f::new(CELL_COLOR));
+    }
+}
+
+pub struct PhysicsDebugRenderer {}
+
+impl System for PhysicsDebugRenderer {
+    fn on_start(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    // the fixed step poses, not blended like the meshes
+    fn on_update(&self, world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
+        if !state.physics.debug_draw {
+            return;
+        }
+        let entities = world.entities.borrow();
+        draw_physics(&entities, &state.contacts, &state.physics, &mut state.debug_lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        physics::{
+            character_controller::CharacterController,
+            collider::{collide, Collider, ColliderShape},
+            rigidbody::Rigidbody,
+            settings::PhysicsSettings,
+        },
+        rendering::debug_lines::DebugLines,
+        types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f, Vec4f}},
+    };
+
+    use super::{draw_physics, CELL_COLOR, COLLIDING_COLOR, CONTACT_COLOR, FIXED_COLOR, TRIGGER_COLOR};
+
+    fn transform(position: [f64; 3]) -> Transform {
+        Transform::new(Position::from(Vec3d::new(position)), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))
+    }
+
+    fn count(lines: &DebugLines, color: [f32; 4]) -> usize {
+        lines.lines().iter().filter(|x| x.2 == Vec4f::new(color)).count()
+    }
+
+    #[test]
+    fn test_colliders_colored_by_state() {
+        let mut entities = hecs::World::new();
+        let floor_collider = Collider::new(ColliderShape::Box(Vec3d::new([5.0, 0.5, 5.0])));
+        let floor = entities.spawn((transform([0.0, -0.5, 0.0]), floor_collider.clone()));
+        let ball_collider = Collider::new(ColliderShape::Sphere(0.5));
+        let ball_body = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        let ball = entities.spawn((transform([0.0, 0.4, 0.0]), ball_body.clone(), ball_collider.clone()));
+        let mut zone = Collider::new(ColliderShape::Sphere(1.0));
+        zone.is_trigger = true;
+        entities.spawn((transform([10.0, 0.0, 0.0]), zone));
+        entities.spawn((transform([-10.0, 1.0, 0.0]), CharacterController::new(0.5, 2.0)));
+
+        let contact = {
+            let (floor_transform, ball_transform) = (entities.get::<&Transform>(floor).unwrap(), entities.get::<&Transform>(ball).unwrap());
+            collide((floor, &*floor_transform, None, &floor_collider), (ball, &*ball_transform, Some(&ball_body), &ball_collider)).unwrap()
+        };
+
+        let mut settings = PhysicsSettings::default();
+        let mut lines = DebugLines::default();
+        draw_physics(&entities, &[], &settings, &mut lines);
+        assert_eq!(count(&lines, COLLIDING_COLOR), 0);
+        assert_eq!(count(&lines, FIXED_COLOR), 12);
+        let trigger_lines = count(&lines, TRIGGER_COLOR);
+        assert!(trigger_lines > 0);
+
+        lines.clear();
+        draw_physics(&entities, &[contact], &settings, &mut lines);
+        assert_eq!(count(&lines, FIXED_COLOR), 0);
+        assert_eq!(count(&lines, COLLIDING_COLOR), 12 + trigger_lines);
+        assert_eq!(count(&lines, TRIGGER_COLOR), trigger_lines);
+        // a cross and the normal up out of the floor
+        assert_eq!(count(&lines, CONTACT_COLOR), 4);
+        let (start, end, _) = lines.lines().iter().rev().find(|x| x.2 == Vec4f::new(CONTACT_COLOR)).unwrap();
+        assert!((Vec3d::from(*end - *start) - Vec3d::new([0.0, 0.5, 0.0])).length() < 1e-9);
+        assert_eq!(count(&lines, CELL_COLOR), 0);
+
+        settings.debug_draw_broadphase = true;
+        lines.clear();
+        draw_physics(&entities, &[], &settings, &mut lines);
+        let cells = count(&lines, CELL_COLOR);
+        assert!(cells > 0 && cells.is_multiple_of(12));
+    }
+}
@@ -0,0 +1,387 @@
+use arrayvec::ArrayVec;
+
+use crate::types::{
+    mesh::Mesh,
+    quaternion::Quat,
+    texture::{f16_to_f32, Texture, TextureFormat},
+    vectors::{Vec3d, Vec3f},
+};
+
+use super::collider::ContactPoint;
+
+type Triangle = [Vec3d; 3];
+
+// static terrain, `resolution.0` samples along x in each of the `resolution.1` rows along z, `cell_size` apart
+// and centered on the collider's center, every cell between four samples is split into two triangles
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    pub heights: Vec<f32>,
+    pub resolution: (u32, u32),
+    pub cell_size: f32,
+}
+
+// the point of the triangle closest to `point`, by which of its corners, edges or face is closest
+fn closest_on_triangle(point: Vec3d, [a, b, c]: Triangle) -> Vec3d {
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = point - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = point - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let total = va + vb + vc;
+    a + ab * (vb / total) + ac * (vc / total)
+}
+
+// up out of the surface
+fn triangle_normal([a, b, c]: Triangle) -> Vec3d {
+    let normal = (b - a).cross(c - a).normalize();
+    if normal.y < 0.0 {
+        Vec3d::new([0.0, 0.0, 0.0]) - normal
+    } else {
+        normal
+    }
+}
+
+// normal out of the triangle towards the sphere and how deep the sphere is in,
+// a sphere that sank below the triangle is pushed back up through it
+fn sphere_triangle(center: Vec3d, radius: f64, triangle: Triangle) -> Option<(Vec3d, f64)> {
+    let normal = triangle_normal(triangle);
+    let height = (center - triangle[0]).dot(normal);
+    let closest = closest_on_triangle(center, triangle);
+    if height < 0.0 {
+        let under = (center - normal * height - closest).length() < 1e-9;
+        return under.then_some((normal, radius - height));
+    }
+    let delta = center - closest;
+    let dst = delta.length();
+    if dst > radius {
+        return None;
+    }
+    Some((if dst > 1e-9 { delta / dst } else { normal }, radius - dst))
+}
+
+// distance along a normalized `direction` to the triangle, hit from either side
+fn ray_triangle(origin: Vec3d, direction: Vec3d, [a, b, c]: Triangle) -> Option<f64> {
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(ac);
+    let det = ab.dot(p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) / det;
+    let q = to_origin.cross(ab);
+    let v = direction.dot(q) / det;
+    // a little past the edges so rays along the diagonal hit either triangle
+    if u < -1e-9 || v < -1e-9 || u + v > 1.0 + 1e-9 {
+        return None;
+    }
+    let distance = ac.dot(q) / det;
+    (distance >= 0.0).then_some(distance)
+}
+
+impl Heightfield {
+    pub fn new(heights: Vec<f32>, resolution: (u32, u32), cell_size: f32) -> Heightfield {
+        if heights.len() != (resolution.0 * resolution.1) as usize {
+            panic!("Heightfield resolution doesn't match the height count!");
+        }
+        Heightfield { heights, resolution, cell_size }
+    }
+
+    // a grid mesh's vertices put on the nearest sample, the highest where several share one, centered on
+    // the middle of the mesh's bounds so put that in Collider::offset for meshes not centered on their origin
+    pub fn from_mesh(mesh: &Mesh) -> Heightfield {
+        let (min, max) = (mesh.aabb.min, mesh.aabb.max);
+        let tolerance = (max.x - min.x).max(max.z - min.z) * 1e-4;
+        let mut columns: Vec<f32> = mesh.vertices.iter().map(|x| x.position.x).collect();
+        columns.sort_by(f32::total_cmp);
+        columns.dedup_by(|a, b| (*a - *b).abs() <= tolerance);
+
+        let columns = columns.len().max(2) as u32;
+        let cell_size = ((max.x - min.x) / (columns - 1) as f32).max(f32::EPSILON);
+        let rows = (((max.z - min.z) / cell_size).round() as u32 + 1).max(2);
+        let mut heights = vec![f32::NEG_INFINITY; (columns * rows) as usize];
+        for vertex in mesh.vertices.iter() {
+            let i = (((vertex.position.x - min.x) / cell_size).round() as u32).min(columns - 1);
+            let j = (((vertex.position.z - min.z) / cell_size).round() as u32).min(rows - 1);
+            let height = &mut heights[(j * columns + i) as usize];
+            *height = height.max(vertex.position.y);
+        }
+        // samples no vertex landed on
+        for height in heights.iter_mut().filter(|x| x.is_infinite()) {
+            *height = min.y;
+        }
+        Heightfield::new(heights, (columns, rows), cell_size)
+    }
+
+    // the red channel of every pixel from 0 to 1 a unit apart, rows of the image along z,
+    // scale `heights` and `cell_size` to size the terrain, cubemaps use their first face
+    pub fn from_image(texture: &Texture) -> Heightfield {
+        let pixel_size = match texture.format {
+            TextureFormat::Rgba8 => 4,
+            TextureFormat::Rgba16F => 8,
+        };
+        let heights = texture.image_data
+            .chunks_exact(pixel_size)
+            .take((texture.width * texture.height) as usize)
+            .map(|x| match texture.format {
+                TextureFormat::Rgba8 => x[0] as f32 / 255.0,
+                TextureFormat::Rgba16F => f16_to_f32(u16::from_le_bytes([x[0], x[1]])),
+            })
+            .collect();
+        Heightfield::new(heights, (texture.width, texture.height), 1.0)
+    }
+
+    // x and z of the first sample, at -x -z
+    fn origin(&self) -> (f64, f64) {
+        let half = |count: u32| -(count.saturating_sub(1) as f64) * self.cell_size as f64 / 2.0;
+        (half(self.resolution.0), half(self.resolution.1))
+    }
+
+    pub(super) fn sample(&self, i: u32, j: u32) -> Vec3d {
+        let (x, z) = self.origin();
+        let cell = self.cell_size as f64;
+        let height = self.heights[(j * self.resolution.0 + i) as usize] as f64;
+        Vec3d::new([x + i as f64 * cell, height, z + j as f64 * cell])
+    }
+
+    // center from the collider's center and half extents of the whole footprint and every height
+    pub fn bounds(&self) -> (Vec3d, Vec3d) {
+        let low = self.heights.iter().copied().fold(f32::INFINITY, f32::min) as f64;
+        let high = self.heights.iter().copied().fold(f32::NEG_INFINITY, f32::max) as f64;
+        let (x, z) = self.origin();
+        (Vec3d::new([0.0, (low + high) / 2.0, 0.0]), Vec3d::new([-x, (high - low) / 2.0, -z]))
+    }
+
+    pub fn bounding_radius(&self) -> f64 {
+        let (center, half) = self.bounds();
+        Vec3d::new([half.x, center.y.abs() + half.y, half.z]).length()
+    }
+
+    // indices from `low` to `high` along one axis, `ceil` for the first sample in the range
+    // and floor for the first cell, clamped to `last`
+    fn span(&self, low: f64, high: f64, origin: f64, last: i64, ceil: bool) -> impl Iterator<Item = u32> + Clone {
+        let cell = self.cell_size as f64;
+        let first = if ceil { ((low - origin) / cell).ceil() } else { ((low - origin) / cell).floor() };
+        let end = ((high - origin) / cell).floor() as i64;
+        (first.max(0.0) as i64..=end.min(last)).map(|x| x as u32)
+    }
+
+    // (column, row) of the cells whose footprint overlaps `min` to `max` along x and z
+    fn cells(&self, min: Vec3d, max: Vec3d) -> impl Iterator<Item = (u32, u32)> {
+        let (x, z) = self.origin();
+        let columns = self.span(min.x, max.x, x, self.resolution.0 as i64 - 2, false);
+        self.span(min.z, max.z, z, self.resolution.1 as i64 - 2, false)
+            .flat_map(move |j| columns.clone().map(move |i| (i, j)))
+    }
+
+    // (column, row) of the samples from `min` to `max` along x and z
+    fn samples(&self, min: Vec3d, max: Vec3d) -> impl Iterator<Item = (u32, u32)> {
+        let (x, z) = self.origin();
+        let columns = self.span(min.x, max.x, x, self.resolution.0 as i64 - 1, true);
+        self.span(min.z, max.z, z, self.resolution.1 as i64 - 1, true)
+            .flat_map(move |j| columns.clone().map(move |i| (i, j)))
+    }
+
+    // split along the diagonal from the -x -z sample to the +x +z one
+    fn triangles(&self, i: u32, j: u32) -> [Triangle; 2] {
+        let (a, b) = (self.sample(i, j), self.sample(i + 1, j));
+        let (c, d) = (self.sample(i, j + 1), self.sample(i + 1, j + 1));
+        [[a, c, d], [a, d, b]]
+    }
+
+    // height and normal of the surface at `x` and `z`, none outside the footprint
+    fn surface(&self, x: f64, z: f64) -> Option<(f64, Vec3d)> {
+        let (origin_x, origin_z) = self.origin();
+        let cell = self.cell_size as f64;
+        let (u, v) = ((x - origin_x) / cell, (z - origin_z) / cell);
+        let (columns, rows) = self.resolution;
+        if columns < 2 || rows < 2 || !(0.0..=(columns - 1) as f64).contains(&u) || !(0.0..=(rows - 1) as f64).contains(&v) {
+            return None;
+        }
+        let (i, j) = ((u as u32).min(columns - 2), (v as u32).min(rows - 2));
+        let [upper, lower] = self.triangles(i, j);
+        let triangle = if v - j as f64 >= u - i as f64 { upper } else { lower };
+        let normal = triangle_normal(triangle);
+        let height = triangle[0].y - ((x - triangle[0].x) * normal.x + (z - triangle[0].z) * normal.z) / normal.y;
+        Some((height, normal))
+    }
+
+    // normal out of the surface and how deep a sphere at `center` from the heightfield's center is in it,
+    // the deepest of the triangles under the sphere
+    pub(super) fn sphere(&self, center: Vec3d, radius: f64) -> Option<(Vec3d, f64)> {
+        let reach = Vec3d::new([radius, radius, radius]);
+        self.cells(center - reach, center + reach)
+            .flat_map(|(i, j)| self.triangles(i, j))
+            .filter_map(|x| sphere_triangle(center, radius, x))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // an axis aligned box at `center` from the heightfield's center, its corners under the surface and the
+    // samples poking up into it, the deepest four from the deepest and the normal of the deepest
+    pub(super) fn box_contacts(&self, center: Vec3d, half: Vec3d) -> Option<(Vec3d, ArrayVec<ContactPoint, 4>)> {
+        let mut found = Vec::new();
+        for corner in 0..8 {
+            let sign = |bit: u32| if corner & bit != 0 { 1.0 } else { -1.0 };
+            let point = center + Vec3d::new([half.x * sign(1), half.y * sign(2), half.z * sign(4)]);
+            let Some((height, normal)) = self.surface(point.x, point.z) else {
+                continue;
+            };
+            let depth = (height - point.y) * normal.y;
+            if depth >= 0.0 {
+                found.push((normal, ContactPoint { point: point + normal * (depth / 2.0), depth, feature: corner }));
+            }
+        }
+
+        let up = Vec3d::new([0.0, 1.0, 0.0]);
+        let (bottom, top) = (center.y - half.y, center.y + half.y);
+        for (i, j) in self.samples(center - half, center + half) {
+            let sample = self.sample(i, j);
+            let depth = sample.y - bottom;
+            if depth >= 0.0 && sample.y <= top {
+                let feature = 8 + j * self.resolution.0 + i;
+                found.push((up, ContactPoint { point: sample - up * (depth / 2.0), depth, feature }));
+            }
+        }
+
+        found.sort_by(|a, b| b.1.depth.total_cmp(&a.1.depth));
+        let normal = found.first()?.0;
+        Some((normal, found.into_iter().take(4).map(|x| x.1).collect()))
+    }
+
+    // whether a box at `center` from the heightfield's center turned by `rotation` has a corner under the
+    // surface or a sample poking up into it
+    pub(super) fn oriented_box(&self, center: Vec3d, half: Vec3d, rotation: Quat) -> bool {
+        let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].map(|x| (Vec3f::new(x) * rotation).to_vec3d());
+        let under = (0..8).any(|corner| {
+            let sign = |bit: u32| if corner & bit != 0 { 1.0 } else { -1.0 };
+            let point = center + axes[0] * (half.x * sign(1)) + axes[1] * (half.y * sign(2)) + axes[2] * (half.z * sign(4));
+            self.surface(point.x, point.z).is_some_and(|(height, _)| point.y <= height)
+        });
+        let reach = half.length();
+        let reach = Vec3d::new([reach, reach, reach]);
+        under || self.samples(center - reach, center + reach).any(|(i, j)| {
+            let local = self.sample(i, j) - center;
+            local.dot(axes[0]).abs() <= half.x && local.dot(axes[1]).abs() <= half.y && local.dot(axes[2]).abs() <= half.z
+        })
+    }
+
+    // distance along `direction` from `origin`, both from the heightfield's center, to where the ray
+    // first hits the surface within `max_distance`, and the surface's normal there
+    pub fn raycast(&self, origin: Vec3d, direction: Vec3d, max_distance: f64) -> Option<(f64, Vec3d)> {
+        let direction = direction.normalize();
+        // nothing further can be over the footprint
+        let end = origin + direction * max_distance.min(origin.length() + self.bounding_radius());
+        let min = Vec3d::new([origin.x.min(end.x), origin.y.min(end.y), origin.z.min(end.z)]);
+        let max = Vec3d::new([origin.x.max(end.x), origin.y.max(end.y), origin.z.max(end.z)]);
+        self.cells(min, max)
+            .flat_map(|(i, j)| self.triangles(i, j))
+            .filter_map(|x| ray_triangle(origin, direction, x).map(|distance| (distance, triangle_normal(x))))
+            .filter(|x| x.0 <= max_distance)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    // lines between neighbouring samples
+    pub(super) fn edges(&self) -> Vec<(Vec3d, Vec3d)> {
+        let (columns, rows) = self.resolution;
+        let mut edges = Vec::new();
+        for j in 0..rows {
+            for i in 0..columns {
+                if i + 1 < columns {
+                    edges.push((self.sample(i, j), self.sample(i + 1, j)));
+                }
+                if j + 1 < rows {
+                    edges.push((self.sample(i, j), self.sample(i, j + 1)));
+                }
+            }
+        }
+        edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use crate::{
+        rendering::VertexData,
+        types::{mesh::Mesh, texture::Texture, vectors::{Vec3d, Vec3f}},
+    };
+
+    use super::Heightfield;
+
+    // a ridge along z, 1 high in the middle column
+    fn ridge() -> Heightfield {
+        Heightfield::new(vec![0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0], (3, 3), 2.0)
+    }
+
+    #[test]
+    fn test_raycast_hits_surface() {
+        let field = ridge();
+        let down = Vec3d::new([0.0, -1.0, 0.0]);
+
+        let (distance, normal) = field.raycast(Vec3d::new([0.0, 5.0, 0.5]), down, f64::INFINITY).unwrap();
+        assert!((distance - 4.0).abs() < 1e-9);
+        assert!(normal.y > 0.0);
+        // half way down the slope at x = -1
+        let (distance, normal) = field.raycast(Vec3d::new([-1.0, 5.0, -1.0]), down, f64::INFINITY).unwrap();
+        assert!((distance - 4.5).abs() < 1e-9);
+        assert!((normal - Vec3d::new([-1.0, 2.0, 0.0]).normalize()).length() < 1e-9);
+
+        assert!(field.raycast(Vec3d::new([0.0, 5.0, 0.0]), down, 3.0).is_none());
+        assert!(field.raycast(Vec3d::new([3.0, 5.0, 0.0]), down, f64::INFINITY).is_none());
+        // from the side into the ridge
+        let (distance, _) = field.raycast(Vec3d::new([-5.0, 0.25, 0.0]), Vec3d::new([1.0, 0.0, 0.0]), f64::INFINITY).unwrap();
+        assert!((distance - 3.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_from_mesh_and_image() {
+        let field = ridge();
+        let vertices = (0..3)
+            .flat_map(|j| (0..3).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let mut vertex = VertexData::zeroed();
+                *vertex.position = Vec3f::new([i as f32 * 2.0 - 2.0, field.heights[j * 3 + i], j as f32 * 2.0 - 2.0]);
+                vertex
+            })
+            .collect();
+        let indices = (0..2).flat_map(|j| (0..2).flat_map(move |i| {
+            let a = j * 3 + i;
+            [a, a + 3, a + 4, a, a + 4, a + 1]
+        }));
+        let mesh = Mesh::new("terrain", vertices, indices.collect());
+
+        let from_mesh = Heightfield::from_mesh(&mesh);
+        assert_eq!(from_mesh.resolution, (3, 3));
+        assert_eq!(from_mesh.cell_size, 2.0);
+        assert_eq!(from_mesh.heights, field.heights);
+
+        let mut texture = Texture::new_storage("heights", 2, 1);
+        texture.image_data = vec![0, 0, 0, 255, 255, 0, 0, 255];
+        let from_image = Heightfield::from_image(&texture);
+        assert_eq!(from_image.resolution, (2, 1));
+        assert_eq!(from_image.heights, vec![0.0, 1.0]);
+    }
+}
@@ -0,0 +1,420 @@
+use hecs::Entity;
+
+use crate::{
+    ecs::System,
+    types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+};
+
+use super::rigidbody::{rotate, Rigidbody};
+
+// passes over all joints every fixed step, chains need more than one to settle
+const SOLVER_ITERATIONS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum JointKind {
+    // keeps `anchor_a` on this entity `rest_length` away from `anchor_b` on the other one,
+    // anchors are in each entity's own space, `stiffness` from 0 to 1 is how much of the error is fixed every pass
+    Distance { anchor_a: Vec3f, anchor_b: Vec3f, rest_length: f64, stiffness: f32 },
+    // both entities share `anchor` and only turn about `axis`, both in this entity's space,
+    // `limits` are the lowest and highest angle in radians the other entity turns to about the axis,
+    // from how the two were turned when the joint was first solved
+    Hinge { anchor: Vec3f, axis: Vec3f, limits: Option<(f32, f32)> },
+}
+
+// connects the entity it's on to `other`, removed once `other` is despawned
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub other: Entity,
+    pub kind: JointKind,
+    hinge: Option<HingeFrame>,
+}
+
+// the hinge as seen from the other entity, and a direction across the axis on each side to measure the angle with
+#[derive(Debug, Clone, Copy)]
+struct HingeFrame {
+    anchor_b: Vec3f,
+    axis_b: Vec3f,
+    reference_a: Vec3f,
+    reference_b: Vec3f,
+}
+
+impl Joint {
+    pub fn new(other: Entity, kind: JointKind) -> Joint {
+        Joint { other, kind, hinge: None }
+    }
+}
+
+// an end of a joint, copied out of its transform and rigidbody and written back once the joint is solved
+struct Body {
+    position: Position,
+    rotation: Quat,
+    velocity: Vec3f,
+    angular_velocity: Vec3f,
+    // none for entities without one, which never move
+    rigidbody: Option<Rigidbody>,
+}
+
+impl Body {
+    fn load(entities: &hecs::World, entity: Entity) -> Option<Body> {
+        let transform = entities.get::<&Transform>(entity).ok()?;
+        let rigidbody = entities.get::<&Rigidbody>(entity).ok().map(|x| Rigidbody::clone(&x));
+        Some(Body {
+            position: transform.position,
+            rotation: transform.rotation,
+            velocity: rigidbody.as_ref().map_or(Vec3f::new([0.0, 0.0, 0.0]), |x| x.velocity),
+            angular_velocity: rigidbody.as_ref().map_or(Vec3f::new([0.0, 0.0, 0.0]), |x| x.angular_velocity),
+            rigidbody,
+        })
+    }
+
+    // only dynamic bodies get moved
+    fn store(&self, entities: &hecs::World, entity: Entity) {
+        if self.inverse_mass() <= 0.0 {
+            return;
+        }
+        if let Ok(mut transform) = entities.get::<&mut Transform>(entity) {
+            transform.position = self.position;
+            transform.rotation = self.rotation;
+        }
+        if let Ok(mut rigidbody) = entities.get::<&mut Rigidbody>(entity) {
+            rigidbody.velocity = self.velocity;
+            rigidbody.angular_velocity = self.angular_velocity;
+        }
+    }
+
+    // the pose the current fixed step started from, the one the joint was set up in
+    fn start_pose(&self) -> (Position, Quat) {
+        self.rigidbody.as_ref().and_then(|x| x.previous_pose()).unwrap_or((self.position, self.rotation))
+    }
+
+    fn inverse_mass(&self) -> f32 {
+        self.rigidbody.as_ref().map_or(0.0, |x| x.inverse_mass())
+    }
+
+    fn inverse_inertia(&self, vec: Vec3f) -> Vec3f {
+        self.rigidbody.as_ref().map_or(Vec3f::new([0.0, 0.0, 0.0]), |x| x.inverse_inertia(self.rotation, vec))
+    }
+
+    // how easily the point `arm` away from the center gets moved along `direction`
+    fn give(&self, arm: Vec3f, direction: Vec3f) -> f32 {
+        let turn = arm.cross(direction);
+        self.inverse_mass() + self.inverse_inertia(turn).dot(turn)
+    }
+
+    // how easily it gets turned about `axis`
+    fn angular_give(&self, axis: Vec3f) -> f32 {
+        self.inverse_inertia(axis).dot(axis)
+    }
+
+    fn point_velocity(&self, arm: Vec3f) -> Vec3f {
+        self.velocity + self.angular_velocity.cross(arm)
+    }
+
+    // moves the point `arm` away from the center like an impulse would change its velocity
+    fn nudge(&mut self, arm: Vec3f, correction: Vec3f) {
+        let offset = correction * self.inverse_mass();
+        self.position += Position::from(offset.to_vec3d());
+        self.rotation = rotate(self.rotation, self.inverse_inertia(arm.cross(correction)));
+    }
+
+    fn push(&mut self, arm: Vec3f, impulse: Vec3f) {
+        self.velocity += impulse * self.inverse_mass();
+        self.angular_velocity += self.inverse_inertia(arm.cross(impulse));
+    }
+
+    fn turn(&mut self, correction: Vec3f) {
+        self.rotation = rotate(self.rotation, self.inverse_inertia(correction));
+    }
+
+    fn twist(&mut self, impulse: Vec3f) {
+        self.angular_velocity += self.inverse_inertia(impulse);
+    }
+}
+
+// any unit vector at a right angle to `axis`
+fn across(axis: Vec3f) -> Vec3f {
+    let other = if axis.x.abs() < 0.9 { Vec3f::new([1.0, 0.0, 0.0]) } else { Vec3f::new([0.0, 1.0, 0.0]) };
+    axis.cross(other).normalize()
+}
+
+// world space offsets of the anchors from their body's center, and from a's anchor to b's
+fn anchors(a: &Body, b: &Body, anchor_a: Vec3f, anchor_b: Vec3f) -> (Vec3f, Vec3f, Vec3f) {
+    let arm_a = anchor_a * a.rotation;
+    let arm_b = anchor_b * b.rotation;
+    let between = Vec3d::from(b.position - a.position).to_vec3f() + arm_b - arm_a;
+    (arm_a, arm_b, between)
+}
+
+// moves the anchors `error` closer along `direction` and stops them moving apart along it, `direction` points from a to b
+fn pull(a: &mut Body, b: &mut Body, arms: (Vec3f, Vec3f), direction: Vec3f, error: f32, stiffness: f32) {
+    let total = a.give(arms.0, direction) + b.give(arms.1, direction);
+    if total <= 0.0 {
+        return;
+    }
+    let correction = direction * (error * stiffness / total);
+    a.nudge(arms.0, correction);
+    b.nudge(arms.1, correction * -1.0);
+
+    let speed = (b.point_velocity(arms.1) - a.point_velocity(arms.0)).dot(direction);
+    let impulse = direction * (speed * stiffness / total);
+    a.push(arms.0, impulse);
+    b.push(arms.1, impulse * -1.0);
+}
+
+// turns b by `angle` relative to a, split by how easily each of them turns
+fn turn_apart(a: &mut Body, b: &mut Body, angle: Vec3f) {
+    let size = angle.length();
+    if size <= 0.0 {
+        return;
+    }
+    let total = a.angular_give(angle / size) + b.angular_give(angle / size);
+    if total <= 0.0 {
+        return;
+    }
+    let correction = angle / total;
+    b.turn(correction);
+    a.turn(correction * -1.0);
+}
+
+// changes b's angular velocity by `change` relative to a's
+fn twist_apart(a: &mut Body, b: &mut Body, change: Vec3f) {
+    let size = change.length();
+    if size <= 0.0 {
+        return;
+    }
+    let total = a.angular_give(change / size) + b.angular_give(change / size);
+    if total <= 0.0 {
+        return;
+    }
+    let impulse = change / total;
+    b.twist(impulse);
+    a.twist(impulse * -1.0);
+}
+
+fn solve_distance(a: &mut Body, b: &mut Body, anchor_a: Vec3f, anchor_b: Vec3f, rest_length: f64, stiffness: f32) {
+    let (arm_a, arm_b, between) = anchors(a, b, anchor_a, anchor_b);
+    let length = between.length();
+    if length <= 0.0 {
+        return;
+    }
+    pull(a, b, (arm_a, arm_b), between / length, length - rest_length as f32, stiffness);
+}
+
+fn solve_hinge(a: &mut Body, b: &mut Body, frame: &HingeFrame, anchor: Vec3f, axis: Vec3f, limits: Option<(f32, f32)>) {
+    // the anchors stay together
+    let (arm_a, arm_b, between) = anchors(a, b, anchor, frame.anchor_b);
+    let length = between.length();
+    if length > 0.0 {
+        pull(a, b, (arm_a, arm_b), between / length, length, 1.0);
+    }
+    let (arm_a, arm_b, _) = anchors(a, b, anchor, frame.anchor_b);
+    let drift = b.point_velocity(arm_b) - a.point_velocity(arm_a);
+    let speed = drift.length();
+    if speed > 0.0 {
+        pull(a, b, (arm_a, arm_b), drift / speed, 0.0, 1.0);
+    }
+
+    // the axes line up and they only spin about them
+    let axis_a = (axis * a.rotation).normalize();
+    let axis_b = (frame.axis_b * b.rotation).normalize();
+    turn_apart(a, b, axis_b.cross(axis_a));
+    let axis_a = (axis * a.rotation).normalize();
+    let spin = b.angular_velocity - a.angular_velocity;
+    twist_apart(a, b, (spin - axis_a * spin.dot(axis_a)) * -1.0);
+
+    let Some((min, max)) = limits else {
+        return;
+    };
+    let reference_a = frame.reference_a * a.rotation;
+    let reference_b = frame.reference_b * b.rotation;
+    let angle = axis_a.dot(reference_a.cross(reference_b)).atan2(reference_a.dot(reference_b));
+    let past = if angle > max { angle - max } else if angle < min { angle - min } else { 0.0 };
+    if past == 0.0 {
+        return;
+    }
+    turn_apart(a, b, axis_a * -past);
+    let spin = (b.angular_velocity - a.angular_velocity).dot(axis_a);
+    if spin * past > 0.0 {
+        twist_apart(a, b, axis_a * -spin);
+    }
+}
+
+// the hinge as the bodies are turned right now, `anchor` and `axis` in a's space
+fn hinge_frame(a: &Body, b: &Body, anchor: Vec3f, axis: Vec3f) -> HingeFrame {
+    let ((position_a, rotation_a), (position_b, rotation_b)) = (a.start_pose(), b.start_pose());
+    let anchor_world = Vec3d::from(position_a - position_b).to_vec3f() + anchor * rotation_a;
+    let reference_a = across(axis.normalize());
+    HingeFrame {
+        anchor_b: anchor_world * rotation_b.inv(),
+        axis_b: (axis.normalize() * rotation_a) * rotation_b.inv(),
+        reference_a,
+        reference_b: (reference_a * rotation_a) * rotation_b.inv(),
+    }
+}
+
+fn solve(joint: &mut Joint, a: &mut Body, b: &mut Body) {
+    match joint.kind {
+        JointKind::Distance { anchor_a, anchor_b, rest_length, stiffness } => {
+            solve_distance(a, b, anchor_a, anchor_b, rest_length, stiffness.clamp(0.0, 1.0));
+        }
+        JointKind::Hinge { anchor, axis, limits } => {
+            let frame = *joint.hinge.get_or_insert_with(|| hinge_frame(a, b, anchor, axis));
+            solve_hinge(a, b, &frame, anchor, axis, limits);
+        }
+    }
+}
+
+// drops joints to despawned entities, then moves the bodies back together
+pub(super) fn solve_joints(entities: &mut hecs::World, iterations: usize) {
+    let broken: Vec<Entity> = entities.query::<&Joint>().iter()
+        .filter(|(_, joint)| !entities.contains(joint.other))
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in broken {
+        let _ = entities.remove_one::<Joint>(entity);
+    }
+
+    let joints: Vec<Entity> = entities.query::<&Joint>().iter().map(|(entity, _)| entity).collect();
+    for _ in 0..iterations {
+        for entity in joints.iter() {
+            let mut joint = Joint::clone(&entities.get::<&Joint>(*entity).unwrap());
+            if joint.other == *entity {
+                continue;
+            }
+            let (Some(mut a), Some(mut b)) = (Body::load(entities, *entity), Body::load(entities, joint.other)) else {
+                continue;
+            };
+            solve(&mut joint, &mut a, &mut b);
+            a.store(entities, *entity);
+            b.store(entities, joint.other);
+            *entities.get::<&mut Joint>(*entity).unwrap() = joint;
+        }
+    }
+}
+
+pub struct JointSolver {}
+
+impl System for JointSolver {
+    fn on_start(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    fn on_update(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    // after collisions, so joints win over the pushes those made
+    fn on_fixed_update(&self, world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {
+        let mut entities = world.entities.borrow_mut();
+        solve_joints(&mut entities, SOLVER_ITERATIONS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        physics::{
+            rigidbody::{integrate, BodyType, Rigidbody},
+            settings::PhysicsSettings,
+        },
+        types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+    };
+
+    use super::{solve_joints, Joint, JointKind, SOLVER_ITERATIONS};
+
+    fn transform(position: [f64; 3]) -> Transform {
+        Transform::new(Position::from(Vec3d::new(position)), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))
+    }
+
+    fn step(entities: &mut hecs::World) {
+        for (_, (rigidbody, transform)) in entities.query::<(&mut Rigidbody, &mut Transform)>().iter() {
+            integrate(rigidbody, transform, &PhysicsSettings::default(), 1.0 / 60.0);
+        }
+        solve_joints(entities, SOLVER_ITERATIONS);
+    }
+
+    #[test]
+    fn test_pendulum_keeps_its_length() {
+        let mut entities = hecs::World::new();
+        let pivot = entities.spawn((transform([0.0, 2.0, 0.0]),));
+        let joint = Joint::new(pivot, JointKind::Distance {
+            anchor_a: Vec3f::new([0.0, 0.0, 0.0]),
+            anchor_b: Vec3f::new([0.0, 0.0, 0.0]),
+            rest_length: 1.5,
+            stiffness: 1.0,
+        });
+        let bob = entities.spawn((
+            transform([1.5, 2.0, 0.0]),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+            joint,
+        ));
+
+        let mut lowest: f64 = 2.0;
+        let mut leftmost: f64 = 1.5;
+        for _ in 0..600 {
+            step(&mut entities);
+            let position = entities.get::<&Transform>(bob).unwrap().position;
+            let offset = Vec3d::from(position - Position::from(Vec3d::new([0.0, 2.0, 0.0])));
+            assert!((offset.length() - 1.5).abs() < 1e-3);
+            lowest = lowest.min(offset.y);
+            leftmost = leftmost.min(offset.x);
+        }
+        // it swung through the bottom and up the other side
+        assert!(lowest < -1.45);
+        assert!(leftmost < -1.0);
+        assert_eq!(entities.get::<&Transform>(pivot).unwrap().position, Position::from(Vec3d::new([0.0, 2.0, 0.0])));
+    }
+
+    #[test]
+    fn test_hinge_holds_axis_and_limits() {
+        let mut entities = hecs::World::new();
+        let mut frame = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        frame.body_type = BodyType::Kinematic;
+        let frame = entities.spawn((transform([0.0, 0.0, 0.0]), frame));
+        // a door hanging off the frame on a vertical hinge, pushed open
+        let joint = Joint::new(frame, JointKind::Hinge {
+            anchor: Vec3f::new([-0.5, 0.0, 0.0]),
+            axis: Vec3f::new([0.0, 1.0, 0.0]),
+            limits: Some((-1.0, 1.0)),
+        });
+        let door = entities.spawn((
+            transform([0.5, 0.0, 0.0]),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 2.0, 0.0])),
+            joint,
+        ));
+
+        for _ in 0..120 {
+            step(&mut entities);
+            let transform = entities.get::<&Transform>(door).unwrap();
+            let hinge = Vec3d::from(transform.position) + (Vec3f::new([-0.5, 0.0, 0.0]) * transform.rotation).to_vec3d();
+            assert!(hinge.length() < 1e-2);
+            assert!((Vec3f::new([0.0, 1.0, 0.0]) * transform.rotation).y > 0.999);
+        }
+        // opened until the limit, a radian about the hinge
+        let position = entities.get::<&Transform>(door).unwrap().position;
+        let offset = Vec3d::from(position);
+        let angle = (-offset.z).atan2(offset.x);
+        assert!((angle - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_joint_removed_with_other_end() {
+        let mut entities = hecs::World::new();
+        let pivot = entities.spawn((transform([0.0, 0.0, 0.0]),));
+        let joint = Joint::new(pivot, JointKind::Distance {
+            anchor_a: Vec3f::new([0.0, 0.0, 0.0]),
+            anchor_b: Vec3f::new([0.0, 0.0, 0.0]),
+            rest_length: 1.0,
+            stiffness: 1.0,
+        });
+        let bob = entities.spawn((
+            transform([1.0, 0.0, 0.0]),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+            joint,
+        ));
+
+        step(&mut entities);
+        assert!(entities.get::<&Joint>(bob).is_ok());
+        entities.despawn(pivot).unwrap();
+        step(&mut entities);
+        assert!(entities.get::<&Joint>(bob).is_err());
+        // falls freely from then on
+        assert!(entities.get::<&Rigidbody>(bob).unwrap().velocity.y < -0.25);
+    }
+}
@@ -0,0 +1,226 @@
+use hecs::Entity;
+
+use crate::types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}};
+
+use super::{
+    broadphase::{Aabb, SpatialGrid, MAX_GRID_CELLS},
+    collider::{overlap, Collider, ColliderShape},
+    settings::PhysicsSettings,
+};
+
+// which colliders a query finds
+#[derive(Debug, Clone, Copy)]
+pub struct QueryFilter {
+    // the layers to look in, see PhysicsSettings::mask
+    pub mask: u32,
+    pub include_triggers: bool,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        QueryFilter { mask: u32::MAX, include_triggers: false }
+    }
+}
+
+enum QueryShape {
+    Sphere(f64),
+    Box(Vec3d, Quat),
+}
+
+// the world space axes of a box turned by `rotation`
+fn box_axes(rotation: Quat) -> [Vec3d; 3] {
+    [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].map(|x| (Vec3f::new(x) * rotation).to_vec3d())
+}
+
+// separating axis test of a box turned by `rotation` and an axis aligned one `offset` from it
+fn boxes_overlap(half: Vec3d, rotation: Quat, other: Vec3d, offset: Vec3d) -> bool {
+    let axes = box_axes(rotation);
+    let world = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].map(Vec3d::new);
+    let mut tests = axes.to_vec();
+    tests.extend(world);
+    for a in axes {
+        for b in world {
+            let axis = a.cross(b);
+            // parallel edges, already covered by the face axes
+            if axis.length() > 1e-9 {
+                tests.push(axis.normalize());
+            }
+        }
+    }
+    let reach = |half: Vec3d, axes: &[Vec3d; 3], test: Vec3d| {
+        half.x * axes[0].dot(test).abs() + half.y * axes[1].dot(test).abs() + half.z * axes[2].dot(test).abs()
+    };
+    tests.into_iter().all(|x| offset.dot(x).abs() <= reach(half, &axes, x) + reach(other, &world, x))
+}
+
+// `offset` is the collider's center relative to the query's
+fn touches(shape: &QueryShape, target: &ColliderShape, offset: Vec3d) -> bool {
+    match (shape, target) {
+        (QueryShape::Sphere(radius), _) => overlap(&ColliderShape::Sphere(*radius), target, offset).is_some(),
+        (QueryShape::Box(half, rotation), ColliderShape::Sphere(_)) => {
+            let local = (offset.to_vec3f() * rotation.inv()).to_vec3d();
+            overlap(&ColliderShape::Box(*half), target, local).is_some()
+        }
+        (QueryShape::Box(half, rotation), ColliderShape::Box(other)) => boxes_overlap(*half, *rotation, *other, offset),
+        (QueryShape::Box(half, rotation), ColliderShape::Heightfield(field)) => {
+            field.oriented_box(Vec3d::new([0.0, 0.0, 0.0]) - offset, *half, *rotation)
+        }
+    }
+}
+
+// everything under a heightfield is inside it, its bounds only reach down to the lowest height
+fn under_or_overlaps(field: &Aabb, bounds: &Aabb) -> bool {
+    let offset = Vec3d::from(bounds.center - field.center);
+    offset.x.abs() <= field.half_extents.x + bounds.half_extents.x
+        && offset.z.abs() <= field.half_extents.z + bounds.half_extents.z
+        && offset.y - bounds.half_extents.y <= field.half_extents.y
+}
+
+// the colliders where they are now in a broadphase grid, made again once they may have moved,
+// every query finds the colliders it touches exactly, sorted by entity id
+pub struct PhysicsQuery<'a> {
+    entities: &'a hecs::World,
+    colliders: Vec<(Entity, Aabb)>,
+    grid: SpatialGrid,
+    // heightfields and huge colliders, kept out of the grid like in candidate_pairs
+    large: Vec<usize>,
+}
+
+impl<'a> PhysicsQuery<'a> {
+    pub fn new(entities: &'a hecs::World, settings: &PhysicsSettings) -> PhysicsQuery<'a> {
+        let mut grid = SpatialGrid::new(settings.broadphase_cell_size);
+        let mut large = Vec::new();
+        let mut colliders = Vec::new();
+        for (i, (entity, (transform, collider))) in entities.query::<(&Transform, &Collider)>().iter().enumerate() {
+            let aabb = collider.aabb(transform);
+            if matches!(collider.shape, ColliderShape::Heightfield(_)) || grid.cell_count(&aabb) > MAX_GRID_CELLS {
+                large.push(i);
+            } else {
+                grid.insert(i, &aabb);
+            }
+            colliders.push((entity, aabb));
+        }
+        PhysicsQuery { entities, colliders, grid, large }
+    }
+
+    pub fn overlap_sphere(&self, center: Position, radius: f64, filter: &QueryFilter) -> Vec<Entity> {
+        let bounds = Aabb { center, half_extents: Vec3d::new([radius, radius, radius]) };
+        self.overlap(center, &bounds, &QueryShape::Sphere(radius), filter)
+    }
+
+    // a box turned by `rotation`
+    pub fn overlap_box(&self, center: Position, half_extents: Vec3d, rotation: Quat, filter: &QueryFilter) -> Vec<Entity> {
+        let axes = box_axes(rotation);
+        let extent = |along: fn(Vec3d) -> f64| {
+            half_extents.x * along(axes[0]).abs() + half_extents.y * along(axes[1]).abs() + half_extents.z * along(axes[2]).abs()
+        };
+        let half = Vec3d::new([extent(|x| x.x), extent(|x| x.y), extent(|x| x.z)]);
+        let bounds = Aabb { center, half_extents: half };
+        self.overlap(center, &bounds, &QueryShape::Box(half_extents, rotation), filter)
+    }
+
+    pub fn overlap_point(&self, point: Position, filter: &QueryFilter) -> Vec<Entity> {
+        self.overlap_sphere(point, 0.0, filter)
+    }
+
+    fn overlap(&self, center: Position, bounds: &Aabb, shape: &QueryShape, filter: &QueryFilter) -> Vec<Entity> {
+        let large = self.large.iter().copied().filter(|x| {
+            let (entity, aabb) = &self.colliders[*x];
+            match self.entities.get::<&Collider>(*entity).map(|x| matches!(x.shape, ColliderShape::Heightfield(_))) {
+                Ok(true) => under_or_overlaps(aabb, bounds),
+                _ => aabb.overlaps(bounds),
+            }
+        });
+        let mut found: Vec<Entity> = self.grid.query(bounds).into_iter()
+            .chain(large)
+            .filter_map(|i| {
+                let entity = self.colliders[i].0;
+                let transform = self.entities.get::<&Transform>(entity).ok()?;
+                let collider = self.entities.get::<&Collider>(entity).ok()?;
+                if filter.mask & (1 << collider.layer) == 0 || (collider.is_trigger && !filter.include_triggers) {
+                    return None;
+                }
+                let offset = Vec3d::from(collider.center(&transform) - center);
+                touches(shape, &collider.shape, offset).then_some(entity)
+            })
+            .collect();
+        found.sort_unstable_by_key(|x| x.id());
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        physics::{
+            collider::{Collider, ColliderShape},
+            heightfield::Heightfield,
+            settings::PhysicsSettings,
+        },
+        types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+    };
+
+    use super::{PhysicsQuery, QueryFilter};
+
+    fn spawn(entities: &mut hecs::World, position: [f64; 3], collider: Collider) -> hecs::Entity {
+        let transform = Transform::new(Position::from(Vec3d::new(position)), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+        entities.spawn((transform, collider))
+    }
+
+    fn sphere(radius: f64) -> Collider {
+        Collider::new(ColliderShape::Sphere(radius))
+    }
+
+    fn cube(half: f64) -> Collider {
+        Collider::new(ColliderShape::Box(Vec3d::new([half, half, half])))
+    }
+
+    #[test]
+    fn test_overlap_sphere_and_point() {
+        let mut entities = hecs::World::new();
+        let below = spawn(&mut entities, [0.0, -2.4, 0.0], sphere(0.5));
+        let close_box = spawn(&mut entities, [1.0, 1.0, 0.0], cube(0.5));
+        // bounds overlapping the query's, shapes just out of reach
+        spawn(&mut entities, [2.0, 2.0, 0.0], sphere(0.5));
+        spawn(&mut entities, [2.3, 2.3, 0.0], cube(0.5));
+        spawn(&mut entities, [50.0, 0.0, 0.0], sphere(1.0));
+        let mut zone = sphere(0.2);
+        zone.is_trigger = true;
+        let zone = spawn(&mut entities, [1.0, 0.0, 0.0], zone);
+        let mut other_layer = sphere(0.3);
+        other_layer.layer = 1;
+        let other_layer = spawn(&mut entities, [0.0, 1.0, 0.0], other_layer);
+        let field = spawn(&mut entities, [0.0, -5.0, 0.0], Collider::new(ColliderShape::Heightfield(Heightfield::new(vec![0.0; 9], (3, 3), 10.0))));
+
+        let query = PhysicsQuery::new(&entities, &PhysicsSettings::default());
+        let origin = Position::default();
+        assert_eq!(query.overlap_sphere(origin, 2.0, &QueryFilter::default()), vec![below, close_box, other_layer]);
+        let filter = QueryFilter { mask: 1, include_triggers: true };
+        assert_eq!(query.overlap_sphere(origin, 2.0, &filter), vec![below, close_box, zone]);
+
+        assert_eq!(query.overlap_point(Position::from(Vec3d::new([0.0, -2.2, 0.1])), &QueryFilter::default()), vec![below]);
+        assert_eq!(query.overlap_point(Position::from(Vec3d::new([0.0, -5.5, 3.0])), &QueryFilter::default()), vec![field]);
+        assert!(query.overlap_point(Position::from(Vec3d::new([0.0, -4.5, 3.0])), &QueryFilter::default()).is_empty());
+    }
+
+    #[test]
+    fn test_overlap_turned_box() {
+        let mut entities = hecs::World::new();
+        // a long thin box turned an eighth about y, one cube along it and one beside it
+        let half = std::f32::consts::FRAC_PI_8;
+        let rotation = Quat::new([half.cos(), 0.0, half.sin(), 0.0]);
+        let along = (Vec3f::new([1.3, 0.0, 0.0]) * rotation).to_vec3d();
+        let beside = (Vec3f::new([0.0, 0.0, 1.3]) * rotation).to_vec3d();
+        let hit = spawn(&mut entities, [along.x, along.y, along.z], cube(0.1));
+        spawn(&mut entities, [beside.x, beside.y, beside.z], cube(0.1));
+        let ball = spawn(&mut entities, [-along.x, 0.0, -along.z], sphere(0.2));
+        // just under the box, reached once it's thicker
+        let field = spawn(&mut entities, [0.0, -0.15, 0.0], Collider::new(ColliderShape::Heightfield(Heightfield::new(vec![0.0; 4], (2, 2), 4.0))));
+
+        let query = PhysicsQuery::new(&entities, &PhysicsSettings::default());
+        let found = query.overlap_box(Position::default(), Vec3d::new([2.0, 0.1, 0.1]), rotation, &QueryFilter::default());
+        assert_eq!(found, vec![hit, ball]);
+        let found = query.overlap_box(Position::default(), Vec3d::new([2.0, 0.2, 0.1]), rotation, &QueryFilter::default());
+        assert_eq!(found, vec![hit, ball, field]);
+    }
+}
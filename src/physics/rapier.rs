@@ -0,0 +1,460 @@
+use std::{cell::RefCell, collections::{BTreeSet, HashMap, HashSet}};
+
+use arrayvec::ArrayVec;
+use crossbeam_channel::unbounded;
+use hecs::Entity;
+use rapier3d::{
+    na::{DMatrix, Quaternion, UnitQuaternion},
+    prelude::{
+        ActiveEvents, CCDSolver, ChannelEventCollector, ColliderBuilder, ColliderHandle, ColliderSet, CollisionEvent,
+        DefaultBroadPhase, Group, ImpulseJointSet, IntegrationParameters, InteractionGroups, IslandManager, Isometry,
+        MassProperties, MultibodyJointSet, NarrowPhase, PhysicsPipeline, Point, QueryPipeline, Real, RigidBodyBuilder,
+        RigidBodyHandle, RigidBodySet, RigidBodyType, Vector,
+    },
+};
+
+use crate::{
+    ecs::System,
+    types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+};
+
+use super::{
+    collider::{Collider, ColliderShape, Collision, ContactManifold, ContactPoint},
+    collision_handler::TriggerEvent,
+    rigidbody::{clamp_velocity, BodyType, Rigidbody},
+    settings::PhysicsSettings,
+};
+
+// rapier's f32 coordinates are around the camera snapped to this grid
+const ORIGIN_GRID: f64 = 1024.0;
+
+fn to_vector(vec: Vec3f) -> Vector<Real> {
+    Vector::new(vec.x, vec.y, vec.z)
+}
+
+fn from_vector(vec: &Vector<Real>) -> Vec3f {
+    Vec3f::new([vec.x, vec.y, vec.z])
+}
+
+fn to_rotation(rotation: Quat) -> UnitQuaternion<Real> {
+    UnitQuaternion::from_quaternion(Quaternion::new(rotation.w, rotation.x, rotation.y, rotation.z))
+}
+
+fn from_rotation(rotation: &UnitQuaternion<Real>) -> Quat {
+    Quat::new([rotation.w, rotation.i, rotation.j, rotation.k])
+}
+
+fn to_local(position: Position, origin: Position) -> Vector<Real> {
+    to_vector(Vec3d::from(position - origin).to_vec3f())
+}
+
+fn from_local(local: &Vector<Real>, origin: Position) -> Position {
+    origin + Position::from(from_vector(local).to_vec3d())
+}
+
+// near the camera where precision matters, only moving every ORIGIN_GRID units
+fn floating_origin(camera: Position) -> Position {
+    let snap = |x: f64| (x / ORIGIN_GRID).round() * ORIGIN_GRID;
+    let position = camera.position;
+    Position::new(camera.chunk, Vec3d::new([snap(position.x), snap(position.y), snap(position.z)]))
+}
+
+fn body_type(body_type: BodyType) -> RigidBodyType {
+    match body_type {
+        BodyType::Dynamic => RigidBodyType::Dynamic,
+        BodyType::Kinematic => RigidBodyType::KinematicVelocityBased,
+        BodyType::Static => RigidBodyType::Fixed,
+    }
+}
+
+// the mass comes from the rigidbody, not the collider's density
+fn collider_builder(entity: Entity, collider: &Collider) -> ColliderBuilder {
+    let builder = match &collider.shape {
+        ColliderShape::Sphere(radius) => ColliderBuilder::ball(*radius as Real),
+        ColliderShape::Box(half) => ColliderBuilder::cuboid(half.x as Real, half.y as Real, half.z as Real),
+        ColliderShape::Heightfield(field) => {
+            // rows along z, columns along x, stretched over the footprint
+            let (columns, rows) = (field.resolution.0 as usize, field.resolution.1 as usize);
+            let heights = DMatrix::from_fn(rows, columns, |j, i| field.heights[j * columns + i]);
+            let size = |count: usize| field.cell_size * count.saturating_sub(1) as Real;
+            ColliderBuilder::heightfield(heights, Vector::new(size(columns), 1.0, size(rows)))
+        }
+    };
+    let groups = InteractionGroups::new(Group::from_bits_truncate(1 << collider.layer), Group::from_bits_truncate(collider.mask));
+    builder
+        .sensor(collider.is_trigger)
+        .collision_groups(groups)
+        .active_events(ActiveEvents::COLLISION_EVENTS)
+        .density(0.0)
+        .user_data(entity.to_bits().get() as u128)
+}
+
+// everything rapier keeps between steps, the engine's components stay the source of truth and are
+// copied in before and out after every step
+struct RapierWorld {
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: DefaultBroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+    // no body for colliders without a rigidbody and heightfields, no collider for rigidbodies without one
+    handles: HashMap<Entity, (Option<RigidBodyHandle>, Option<ColliderHandle>)>,
+    // entity and whether it's a trigger, kept for the events of colliders removed since
+    owners: HashMap<ColliderHandle, (Entity, bool)>,
+    // (trigger, other) pairs overlapping after the last step
+    overlaps: BTreeSet<(Entity, Entity)>,
+}
+
+impl RapierWorld {
+    fn new() -> RapierWorld {
+        RapierWorld {
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: DefaultBroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            handles: HashMap::new(),
+            owners: HashMap::new(),
+            overlaps: BTreeSet::new(),
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, collider: Option<&Collider>, with_body: bool) -> (Option<RigidBodyHandle>, Option<ColliderHandle>) {
+        let body = with_body.then(|| self.bodies.insert(RigidBodyBuilder::dynamic().build()));
+        let collider_handle = collider.map(|collider| {
+            let builder = collider_builder(entity, collider);
+            let handle = match body {
+                Some(body) => {
+                    let builder = builder.translation(to_vector(collider.offset));
+                    self.colliders.insert_with_parent(builder, body, &mut self.bodies)
+                }
+                None => self.colliders.insert(builder),
+            };
+            self.owners.insert(handle, (entity, collider.is_trigger));
+            handle
+        });
+        (body, collider_handle)
+    }
+
+    fn remove(&mut self, (body, collider): (Option<RigidBodyHandle>, Option<ColliderHandle>)) {
+        match (body, collider) {
+            (Some(body), _) => {
+                self.bodies.remove(body, &mut self.islands, &mut self.colliders, &mut self.impulse_joints, &mut self.multibody_joints, true);
+            }
+            (None, Some(collider)) => {
+                self.colliders.remove(collider, &mut self.islands, &mut self.bodies, true);
+            }
+            (None, None) => {}
+        }
+    }
+
+    // adds, updates and removes rapier's bodies to match the components, velocities clamped like
+    // integrate does, forces added since the last step are handed over and the pose before the step
+    // is kept for interpolation
+    fn sync(&mut self, entities: &hecs::World, settings: &PhysicsSettings, origin: Position) {
+        let mut seen = HashSet::new();
+        let mut query = entities.query::<(&Transform, Option<&mut Rigidbody>, Option<&Collider>)>();
+        for (entity, (transform, rigidbody, collider)) in query.iter().filter(|(_, (_, x, y))| x.is_some() || y.is_some()) {
+            seen.insert(entity);
+            let with_body = rigidbody.is_some() && !collider.is_some_and(|x| matches!(x.shape, ColliderShape::Heightfield(_)));
+            let handles = match self.handles.get(&entity) {
+                Some(handles) if handles.0.is_some() == with_body && handles.1.is_some() == collider.is_some() => *handles,
+                previous => {
+                    if let Some(previous) = previous.copied() {
+                        self.remove(previous);
+                    }
+                    let handles = self.insert(entity, collider, with_body);
+                    self.handles.insert(entity, handles);
+                    handles
+                }
+            };
+
+            let (Some(handle), Some(rigidbody)) = (handles.0, rigidbody) else {
+                // moved only by its transform
+                if let (Some(collider), Some(collider_handle)) = (collider, handles.1) {
+                    let center = to_local(collider.center(transform), origin);
+                    self.colliders.get_mut(collider_handle).unwrap().set_position(Isometry::translation(center.x, center.y, center.z));
+                }
+                continue;
+            };
+            clamp_velocity(rigidbody, settings);
+            let (force, torque) = rigidbody.start_step(transform);
+            let body = self.bodies.get_mut(handle).unwrap();
+            let position = Isometry::from_parts(to_local(transform.position, origin).into(), to_rotation(transform.rotation));
+            let (velocity, angular_velocity) = (to_vector(rigidbody.velocity), to_vector(rigidbody.angular_velocity));
+            // anything rapier didn't leave it at wakes it up
+            let changed = body.position() != &position || body.linvel() != &velocity || body.angvel() != &angular_velocity
+                || force != Vec3f::new([0.0, 0.0, 0.0]) || torque != Vec3f::new([0.0, 0.0, 0.0]);
+
+            body.set_body_type(body_type(rigidbody.body_type), changed);
+            body.set_position(position, changed);
+            body.set_linvel(velocity, changed);
+            body.set_angvel(angular_velocity, changed);
+            body.set_gravity_scale(if rigidbody.use_gravity { rigidbody.gravity_scale } else { 0.0 }, changed);
+            body.enable_ccd(rigidbody.ccd);
+            // predictive contacts as far as the body moves this step, it would land inside the floor and
+            // be pushed out over the next steps otherwise
+            body.set_soft_ccd_prediction(velocity.norm() * settings.timestep as Real);
+            let mass = MassProperties::new(Point::from(to_vector(rigidbody.center_of_mass)), rigidbody.mass, to_vector(rigidbody.inertia));
+            body.set_additional_mass_properties(mass, changed);
+            body.reset_forces(false);
+            body.reset_torques(false);
+            body.add_force(to_vector(force), changed);
+            body.add_torque(to_vector(torque), changed);
+        }
+
+        let gone: Vec<Entity> = self.handles.keys().filter(|x| !seen.contains(x)).copied().collect();
+        for entity in gone {
+            let handles = self.handles.remove(&entity).unwrap();
+            self.remove(handles);
+        }
+    }
+
+    // the components synced in, one rapier step, the poses and velocities copied back out,
+    // returns the trigger events and contacts as CollisionHandler would
+    fn step(&mut self, entities: &hecs::World, settings: &PhysicsSettings, origin: Position) -> (Vec<TriggerEvent>, Vec<Collision>) {
+        self.sync(entities, settings, origin);
+
+        let (collision_send, collision_receive) = unbounded();
+        let (force_send, _) = unbounded();
+        let events = ChannelEventCollector::new(collision_send, force_send);
+        let parameters = IntegrationParameters { dt: settings.timestep as Real, ..Default::default() };
+        self.pipeline.step(
+            &to_vector(settings.gravity),
+            &parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            Some(&mut self.query_pipeline),
+            &(),
+            &events,
+        );
+
+        for (entity, (transform, rigidbody)) in entities.query::<(&mut Transform, &mut Rigidbody)>().iter() {
+            let Some((Some(handle), _)) = self.handles.get(&entity) else {
+                continue;
+            };
+            let body = &self.bodies[*handle];
+            transform.position = from_local(body.translation(), origin);
+            transform.rotation = from_rotation(body.rotation());
+            rigidbody.velocity = from_vector(body.linvel());
+            rigidbody.angular_velocity = from_vector(body.angvel());
+        }
+
+        // sensor events into the same enter, stay and exit events as the built-in backend
+        let mut entered = BTreeSet::new();
+        let mut exits = Vec::new();
+        while let Ok(event) = collision_receive.try_recv() {
+            let (Some(a), Some(b)) = (self.owners.get(&event.collider1()), self.owners.get(&event.collider2())) else {
+                continue;
+            };
+            if !event.sensor() {
+                continue;
+            }
+            let pair = if a.1 { (a.0, b.0) } else { (b.0, a.0) };
+            if let CollisionEvent::Started(..) = event {
+                self.overlaps.insert(pair);
+                entered.insert(pair);
+            } else if self.overlaps.remove(&pair) {
+                exits.push(TriggerEvent::Exit { trigger: pair.0, other: pair.1 });
+            }
+        }
+        let live: HashSet<ColliderHandle> = self.colliders.iter().map(|(handle, _)| handle).collect();
+        self.owners.retain(|handle, _| live.contains(handle));
+        let rest = self.overlaps.iter().map(|(trigger, other)| {
+            if entered.contains(&(*trigger, *other)) {
+                TriggerEvent::Enter { trigger: *trigger, other: *other }
+            } else {
+                TriggerEvent::Stay { trigger: *trigger, other: *other }
+            }
+        });
+        let trigger_events = exits.into_iter().chain(rest).collect();
+
+        (trigger_events, self.contacts(entities, origin))
+    }
+
+    // pairs rapier's broadphase found, touching or not
+    fn pair_count(&self) -> usize {
+        self.narrow_phase.contact_pairs().count() + self.narrow_phase.intersection_pairs().count()
+    }
+
+    // every touching pair with its deepest manifold, points from the first entity's position
+    fn contacts(&self, entities: &hecs::World, origin: Position) -> Vec<Collision> {
+        let mut contacts = Vec::new();
+        for pair in self.narrow_phase.contact_pairs().filter(|x| x.has_any_active_contact) {
+            let (Some(a), Some(b)) = (self.owners.get(&pair.collider1), self.owners.get(&pair.collider2)) else {
+                continue;
+            };
+            let Ok(transform) = entities.get::<&Transform>(a.0) else {
+                continue;
+            };
+            let deepest = pair.manifolds.iter()
+                .filter_map(|x| Some((x.data.solver_contacts.iter().map(|x| x.dist).reduce(Real::min)?, x)))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let Some((_, manifold)) = deepest else {
+                continue;
+            };
+            let points: ArrayVec<ContactPoint, 4> = manifold.data.solver_contacts.iter()
+                .take(4)
+                .enumerate()
+                .map(|(i, x)| ContactPoint {
+                    point: Vec3d::from(from_local(&x.point.coords, origin) - transform.position),
+                    depth: -x.dist as f64,
+                    feature: i as u32,
+                })
+                .collect();
+            contacts.push(Collision {
+                entity_a: a.0,
+                entity_b: b.0,
+                move_a: Vec3d::new([0.0, 0.0, 0.0]),
+                move_b: Vec3d::new([0.0, 0.0, 0.0]),
+                manifold: ContactManifold { points, normal: from_vector(&manifold.data.normal).to_vec3d() },
+            });
+        }
+        contacts
+    }
+}
+
+// runs rigidbodies and colliders through rapier instead of RigidbodyHandler and CollisionHandler,
+// same components and the same trigger events and contacts in State, boxes turn with their body here
+pub struct RapierPhysics {
+    world: RefCell<RapierWorld>,
+}
+
+impl RapierPhysics {
+    pub fn new() -> RapierPhysics {
+        RapierPhysics { world: RefCell::new(RapierWorld::new()) }
+    }
+}
+
+impl Default for RapierPhysics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for RapierPhysics {
+    fn on_start(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    fn on_update(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    fn on_fixed_update(&self, world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
+        let entities = world.entities.borrow_mut();
+        // there's no camera to stay close to headless
+        let camera = state.renderer.get().map_or(Position::default(), |x| x.active_view().position);
+        let origin = floating_origin(camera);
+        let mut rapier_world = self.world.borrow_mut();
+        let (events, contacts) = rapier_world.step(&entities, &state.physics, origin);
+        state.trigger_events.extend(events);
+        state.contacts = contacts;
+        state.pair_count = rapier_world.pair_count() as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        physics::{
+            collider::{Collider, ColliderShape},
+            collision_handler::{CollisionHandler, TriggerEvent},
+            rigidbody::{integrate, Rigidbody},
+            settings::PhysicsSettings,
+        },
+        types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f, Vec3i}},
+    };
+
+    use super::{floating_origin, RapierWorld};
+
+    fn transform(y: f64) -> Transform {
+        Transform::new(Position::from(Vec3d::new([0.0, y, 0.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))
+    }
+
+    // a floor, a ball dropped on it and a trigger zone it falls through
+    fn scene() -> (hecs::World, hecs::Entity, hecs::Entity) {
+        let mut entities = hecs::World::new();
+        entities.spawn((transform(-0.5), Collider::new(ColliderShape::Box(Vec3d::new([5.0, 0.5, 5.0])))));
+        let mut zone = Collider::new(ColliderShape::Box(Vec3d::new([1.0, 0.25, 1.0])));
+        zone.is_trigger = true;
+        let zone = entities.spawn((transform(2.0), zone));
+        let ball = Collider::new(ColliderShape::Sphere(0.5));
+        let ball = entities.spawn((transform(4.0), Rigidbody::with_collider_inertia(1.0, &ball), ball));
+        (entities, ball, zone)
+    }
+
+    // heights of the ball and trigger events over three seconds with either backend
+    fn run(rapier: bool) -> (Vec<f64>, Vec<TriggerEvent>, hecs::Entity, hecs::Entity) {
+        let (mut entities, ball, zone) = scene();
+        let settings = PhysicsSettings::default();
+        let native = CollisionHandler::new();
+        let mut rapier_world = RapierWorld::new();
+        let (mut heights, mut events) = (Vec::new(), Vec::new());
+        for _ in 0..180 {
+            if rapier {
+                events.extend(rapier_world.step(&entities, &settings, Position::default()).0);
+            } else {
+                for (_, (rigidbody, transform)) in entities.query_mut::<(&mut Rigidbody, &mut Transform)>() {
+                    integrate(rigidbody, transform, &settings, settings.timestep as f32);
+                }
+                events.extend(native.step(&entities, settings.broadphase_cell_size));
+            }
+            heights.push(entities.get::<&Transform>(ball).unwrap().position.position.y);
+        }
+        (heights, events, ball, zone)
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        for rapier in [false, true] {
+            let (heights, events, ball, zone) = run(rapier);
+            // lands without bouncing back up and comes to rest on the floor
+            let landed = heights.iter().position(|x| *x < 0.55).unwrap();
+            assert!(heights[landed..].iter().all(|x| (x - 0.5).abs() < 0.05), "rapier: {rapier}");
+            assert!((heights.last().unwrap() - 0.5).abs() < 0.02, "rapier: {rapier}");
+            // through the trigger zone on the way
+            assert_eq!(events.first(), Some(&TriggerEvent::Enter { trigger: zone, other: ball }), "rapier: {rapier}");
+            assert_eq!(events.last(), Some(&TriggerEvent::Exit { trigger: zone, other: ball }), "rapier: {rapier}");
+        }
+    }
+
+    #[test]
+    fn test_body_without_collider_moves() {
+        let mut entities = hecs::World::new();
+        let mut rigidbody = Rigidbody::new(1.0, Vec3f::new([3.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        rigidbody.use_gravity = false;
+        let slider = entities.spawn((transform(0.0), rigidbody));
+        let settings = PhysicsSettings::default();
+        let mut rapier_world = RapierWorld::new();
+        for _ in 0..60 {
+            rapier_world.step(&entities, &settings, Position::default());
+        }
+        let x = entities.get::<&Transform>(slider).unwrap().position.position.x;
+        assert!((x - 3.0).abs() < 0.01, "{x}");
+
+        entities.remove_one::<Rigidbody>(slider).unwrap();
+        rapier_world.step(&entities, &settings, Position::default());
+        assert!(rapier_world.handles.is_empty());
+    }
+
+    #[test]
+    fn test_floating_origin_snaps() {
+        let camera = Position::new(Vec3i::new([2, 0, 0]), Vec3d::new([1500.0, -10.0, 511.0]));
+        let origin = floating_origin(camera);
+        assert_eq!(origin.chunk, Vec3i::new([2, 0, 0]));
+        assert_eq!(origin.position, Vec3d::new([1024.0, 0.0, 0.0]));
+    }
+}
@@ -1,12 +1,48 @@
-use crate::{ecs::System, types::{quaternion::Quat, transform::Transform, vectors::Vec3f}};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::warn;
+
+use crate::{ecs::System, types::{animation::slerp, position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}}};
+
+use super::{collider::{Collider, ColliderShape}, settings::PhysicsSettings};
+
+// a broken step is only logged the first time
+static SKIPPED_STEP: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BodyType {
+    #[default]
+    Dynamic,
+    // moved only by its velocity, ignores gravity and forces, pushes dynamic bodies but is never pushed
+    Kinematic,
+    // never moves, same as a collider without a rigidbody
+    Static,
+}
 
 #[derive(Debug, Clone)]
 pub struct Rigidbody {
     pub mass: f32,
+    pub body_type: BodyType,
     pub velocity: Vec3f,
     pub angular_velocity: Vec3f,
+    // diagonal of the inertia tensor around the body's own axes through the center of mass
+    pub inertia: Vec3f,
+    // from the transform's position in the body's own axes, the body turns around it
+    // and forces through it don't turn the body
+    pub center_of_mass: Vec3f,
+    // multiplies PhysicsSettings::gravity
+    pub gravity_scale: f32,
+    pub use_gravity: bool,
+    // swept along its motion every step so it can't pass through anything thinner than it moves,
+    // for small fast bodies, costs a cast against everything it could have hit
+    pub ccd: bool,
+    // PhysicsSettings::max_linear_velocity and max_angular_velocity for this body
+    pub max_linear_velocity: Option<f32>,
+    pub max_angular_velocity: Option<f32>,
     torque: Vec3f,
-    force: Vec3f
+    force: Vec3f,
+    // pose before the last fixed step, drawn blended towards the transform
+    previous: Option<(Position, Quat)>
 }
 
 impl Rigidbody {
@@ -18,22 +54,205 @@ impl Rigidbody {
         self.force += force;
     }
 
+    #[deprecated(note = "`point` has to be from the center of mass in world axes, use add_force_at_world_point")]
     pub fn add_force_at_point(&mut self, force: Vec3f, point: Vec3f) {
         self.torque += point.cross(force);
         self.force += force;
     }
+
+    // `force` in world axes pushing at `point` in world space
+    pub fn add_force_at_world_point(&mut self, force: Vec3f, point: Position, transform: &Transform) {
+        let arm = Vec3d::from(point - self.world_center_of_mass(transform)).to_vec3f();
+        self.torque += arm.cross(force);
+        self.force += force;
+    }
+
+    // `force` in the body's own axes through its center of mass
+    pub fn add_relative_force(&mut self, force: Vec3f, transform: &Transform) {
+        self.force += force * transform.rotation;
+    }
+
+    pub fn world_center_of_mass(&self, transform: &Transform) -> Position {
+        transform.position + Position::from((self.center_of_mass * transform.rotation).to_vec3d())
+    }
+
+    // where the last fixed step started
+    pub(super) fn previous_position(&self) -> Option<Position> {
+        self.previous.map(|x| x.0)
+    }
+
+    pub(super) fn previous_pose(&self) -> Option<(Position, Quat)> {
+        self.previous
+    }
+
+    // for a backend that moves the body itself, keeps the pose the step starts from like integrate does
+    // and hands over the force and torque added since the last step
+    #[cfg(feature = "rapier")]
+    pub(super) fn start_step(&mut self, transform: &Transform) -> (Vec3f, Vec3f) {
+        let zero = Vec3f::new([0.0, 0.0, 0.0]);
+        let taken = (std::mem::replace(&mut self.force, zero), std::mem::replace(&mut self.torque, zero));
+        match self.body_type {
+            BodyType::Dynamic => {
+                self.previous = Some((transform.position, transform.rotation));
+                taken
+            }
+            BodyType::Kinematic => {
+                self.previous = Some((transform.position, transform.rotation));
+                (zero, zero)
+            }
+            BodyType::Static => {
+                self.previous = None;
+                (zero, zero)
+            }
+        }
+    }
+
+    // zero for kinematic and static bodies, their mass counts as infinite in collisions
+    pub fn inverse_mass(&self) -> f32 {
+        match self.body_type {
+            BodyType::Dynamic => 1.0 / self.mass,
+            BodyType::Kinematic | BodyType::Static => 0.0,
+        }
+    }
+
+    // the inverse inertia tensor in world space applied to `vec`, zero for kinematic and static bodies
+    pub fn inverse_inertia(&self, rotation: Quat, vec: Vec3f) -> Vec3f {
+        match self.body_type {
+            BodyType::Dynamic => (vec * rotation.inv()) / self.inertia * rotation,
+            BodyType::Kinematic | BodyType::Static => Vec3f::new([0.0, 0.0, 0.0]),
+        }
+    }
+
+    // `transform` as drawn `alpha` of the way from the previous fixed step to the last one
+    pub fn interpolated(&self, transform: &Transform, alpha: f32) -> Transform {
+        let Some((position, rotation)) = self.previous else {
+            return transform.clone();
+        };
+        let offset: Vec3d = (transform.position - position).into();
+        Transform {
+            position: position + Position::from(offset * alpha as f64),
+            scale: transform.scale,
+            rotation: slerp(rotation, transform.rotation, alpha),
+        }
+    }
+}
+
+// diagonal inertia tensor of a solid shape around its center
+pub fn shape_inertia(mass: f32, shape: &ColliderShape) -> Vec3f {
+    match shape {
+        ColliderShape::Sphere(radius) => {
+            let inertia = 0.4 * mass * (radius * radius) as f32;
+            Vec3f::new([inertia, inertia, inertia])
+        }
+        ColliderShape::Box(half) => {
+            let (x, y, z) = ((half.x * half.x) as f32, (half.y * half.y) as f32, (half.z * half.z) as f32);
+            Vec3f::new([y + z, x + z, x + y]) * (mass / 3.0)
+        }
+        // never turns
+        ColliderShape::Heightfield(_) => Vec3f::new([mass, mass, mass]),
+    }
 }
 
 impl Rigidbody {
     pub fn new(m: f32, v: Vec3f, w: Vec3f) -> Rigidbody {
         Rigidbody { 
             mass: m, 
+            body_type: BodyType::Dynamic,
             velocity: v, 
             angular_velocity: w, 
+            // as if all of the mass sat a unit away from the center
+            inertia: Vec3f::new([m, m, m]),
+            center_of_mass: Vec3f::new([0.0, 0.0, 0.0]),
+            gravity_scale: 1.0,
+            use_gravity: true,
+            ccd: false,
+            max_linear_velocity: None,
+            max_angular_velocity: None,
             torque: Vec3f::new([0.0, 0.0, 0.0]),
-            force: Vec3f::new([0.0, 0.0, 0.0])
+            force: Vec3f::new([0.0, 0.0, 0.0]),
+            previous: None
         }
     }
+
+    // at rest, with the collider's shape filled with `mass`
+    pub fn with_collider_inertia(mass: f32, collider: &Collider) -> Rigidbody {
+        let mut rigidbody = Rigidbody::new(mass, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        rigidbody.inertia = shape_inertia(mass, &collider.shape);
+        rigidbody.center_of_mass = collider.offset;
+        rigidbody
+    }
+}
+
+fn finite(vec: Vec3f) -> bool {
+    vec.x.is_finite() && vec.y.is_finite() && vec.z.is_finite()
+}
+
+// `vec` shortened to `max` if it's longer, measured in f64 so huge ones don't overflow
+fn clamp_length(vec: Vec3f, max: f32) -> Vec3f {
+    let length = vec.to_vec3d().length();
+    if length > max as f64 {
+        vec * (max as f64 / length) as f32
+    } else {
+        vec
+    }
+}
+
+// the velocities slowed down to the body's or the settings' limits
+pub(super) fn clamp_velocity(rigidbody: &mut Rigidbody, settings: &PhysicsSettings) {
+    let linear = rigidbody.max_linear_velocity.unwrap_or(settings.max_linear_velocity);
+    let angular = rigidbody.max_angular_velocity.unwrap_or(settings.max_angular_velocity);
+    rigidbody.velocity = clamp_length(rigidbody.velocity, linear);
+    rigidbody.angular_velocity = clamp_length(rigidbody.angular_velocity, angular);
+}
+
+// one semi-implicit euler step, the velocity is the center of mass's, a step with a broken `delta_time`
+// or force is skipped and the forces dropped
+pub(super) fn integrate(rigidbody: &mut Rigidbody, transform: &mut Transform, settings: &PhysicsSettings, delta_time: f32) {
+    if !delta_time.is_finite() || delta_time < 0.0 || !finite(rigidbody.force) || !finite(rigidbody.torque) {
+        if !SKIPPED_STEP.swap(true, Ordering::Relaxed) {
+            warn!("skipped a physics step with delta time {} or a non-finite force", delta_time);
+        }
+        rigidbody.force = Vec3f::new([0.0, 0.0, 0.0]);
+        rigidbody.torque = Vec3f::new([0.0, 0.0, 0.0]);
+        return;
+    }
+    match rigidbody.body_type {
+        BodyType::Static => {
+            rigidbody.previous = None;
+            return;
+        }
+        BodyType::Kinematic => {
+            rigidbody.force = Vec3f::new([0.0, 0.0, 0.0]);
+            rigidbody.torque = Vec3f::new([0.0, 0.0, 0.0]);
+        }
+        BodyType::Dynamic => {
+            if rigidbody.use_gravity {
+                rigidbody.velocity += settings.gravity * rigidbody.gravity_scale * delta_time;
+            }
+        }
+    }
+    rigidbody.velocity += rigidbody.force * delta_time / rigidbody.mass;
+    rigidbody.force = Vec3f::new([0.0, 0.0, 0.0]);
+    rigidbody.previous = Some((transform.position, transform.rotation));
+
+    let delta_pos = rigidbody.velocity.to_vec3d() * delta_time as f64;
+    transform.position += delta_pos.into();
+
+    rigidbody.angular_velocity += rigidbody.inverse_inertia(transform.rotation, rigidbody.torque) * delta_time;
+    rigidbody.torque = Vec3f::new([0.0, 0.0, 0.0]);
+    clamp_velocity(rigidbody, settings);
+
+    // turned around the center of mass, not the position
+    let before = rigidbody.center_of_mass * transform.rotation;
+    transform.rotation = rotate(transform.rotation, rigidbody.angular_velocity * delta_time);
+    let after = rigidbody.center_of_mass * transform.rotation;
+    transform.position += (before - after).to_vec3d().into();
+}
+
+// `rotation` turned further by the small world space rotation `angle`, its axis scaled by the angle in radians
+pub(super) fn rotate(rotation: Quat, angle: Vec3f) -> Quat {
+    let angle = Quat::new([0.0, angle.x, angle.y, angle.z]);
+    (rotation + rotation * angle * 0.5).normalize()
 }
 
 pub struct RigidbodyHandler {}
@@ -41,23 +260,176 @@ pub struct RigidbodyHandler {}
 impl System for RigidbodyHandler {
     fn on_start(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
 
-    fn on_update(&self, world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
+    fn on_update(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut crate::state::State) {}
+
+    // physics_time_scale already decides how many fixed steps run
+    fn on_fixed_update(&self, world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
         let entities = world.entities.borrow_mut();
 
         for (_, (rigidbody, transform)) in entities.query::<(&mut Rigidbody, &mut Transform)>().iter() {
-            rigidbody.velocity += rigidbody.force * state.delta_time as f32 / rigidbody.mass;
-            rigidbody.force = Vec3f::new([0.0, 0.0, 0.0]);
+            integrate(rigidbody, transform, &state.physics, state.physics.timestep as f32);
+        }
+    }
+}
 
-            let delta_pos = rigidbody.velocity.to_vec3d() * state.delta_time * state.physics_time_scale as f64;
-            transform.position += delta_pos.into();
+#[cfg(test)]
+mod tests {
+    use crate::{
+        physics::{
+            collider::{Collider, ColliderShape},
+            settings::PhysicsSettings,
+        },
+        types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+    };
 
-            rigidbody.angular_velocity += rigidbody.torque * state.delta_time as f32 / rigidbody.mass;
-            rigidbody.torque = Vec3f::new([0.0, 0.0, 0.0]);
+    use super::{integrate, Rigidbody};
 
-            let angular_velocity_quat = Quat::new([0.0, rigidbody.angular_velocity.x, rigidbody.angular_velocity.y, rigidbody.angular_velocity.z]);
-            let avtr = angular_velocity_quat * transform.rotation;
-            let d_rotation = avtr * (state.delta_time / 2.0) as f32;
-            transform.rotation = (transform.rotation + d_rotation).normalize();
+    // height after 60 steps of 1/60 s, each `time_scale` times as long
+    fn fall(rigidbody: &mut Rigidbody, time_scale: f32) -> f64 {
+        let mut transform = Transform::new(Position::default(), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+        for _ in 0..60 {
+            integrate(rigidbody, &mut transform, &PhysicsSettings::default(), 1.0 / 60.0 * time_scale);
         }
+        transform.position.position.y
+    }
+
+    #[test]
+    fn test_free_fall_distance() {
+        let mut rigidbody = Rigidbody::new(2.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        let height = fall(&mut rigidbody, 1.0);
+        // semi-implicit euler lands a little past 1/2 g t^2, by 1/2 g t dt
+        let expected = -0.5 * 9.81 * (1.0 + 1.0 / 60.0);
+        assert!((height - expected).abs() < 1e-3);
+        assert!((rigidbody.velocity.y + 9.81).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_gravity_scale_and_time_scale() {
+        let mut heavy = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        heavy.gravity_scale = 2.0;
+        let mut slow = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        let mut floating = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        floating.use_gravity = false;
+
+        assert!((fall(&mut heavy, 1.0) + 9.81 * (1.0 + 1.0 / 60.0)).abs() < 1e-3);
+        // half the time scale, half the velocity
+        fall(&mut slow, 0.5);
+        assert!((slow.velocity.y + 9.81 * 0.5).abs() < 1e-3);
+        assert_eq!(fall(&mut floating, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_torque_on_long_and_cubic_boxes() {
+        let cube = Collider::new(ColliderShape::Box(Vec3d::new([0.5, 0.5, 0.5])));
+        let long = Collider::new(ColliderShape::Box(Vec3d::new([2.0, 0.5, 0.5])));
+        let dt = 1.0 / 60.0;
+        // one step from rest under a unit torque, the angular acceleration is 1 / inertia
+        let spin = |collider: &Collider, torque: [f32; 3]| {
+            let mut rigidbody = Rigidbody::with_collider_inertia(1.0, collider);
+            rigidbody.use_gravity = false;
+            rigidbody.add_torque(Vec3f::new(torque));
+            let mut transform = Transform::new(Position::default(), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+            integrate(&mut rigidbody, &mut transform, &PhysicsSettings::default(), dt);
+            rigidbody.angular_velocity / dt
+        };
+
+        // m (h^2 + w^2) / 12
+        assert!((spin(&cube, [0.0, 0.0, 1.0]).z - 6.0).abs() < 1e-3);
+        assert!((spin(&long, [0.0, 0.0, 1.0]).z - 12.0 / 17.0).abs() < 1e-3);
+        // about its long axis the thin box turns as easily as the cube
+        assert!((spin(&long, [1.0, 0.0, 0.0]).x - 6.0).abs() < 1e-3);
+        let sphere = Collider::new(ColliderShape::Sphere(1.0));
+        assert!((spin(&sphere, [0.0, 1.0, 0.0]).y - 2.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_interpolated_between_fixed_steps() {
+        let mut rigidbody = Rigidbody::new(1.0, Vec3f::new([6.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        rigidbody.use_gravity = false;
+        let mut transform = Transform::new(Position::default(), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+        // nothing to blend from before the first step
+        assert_eq!(rigidbody.interpolated(&transform, 0.5).position, transform.position);
+
+        integrate(&mut rigidbody, &mut transform, &PhysicsSettings::default(), 0.5);
+        assert_eq!(transform.position.position.x, 3.0);
+        assert_eq!(rigidbody.interpolated(&transform, 0.0).position.position.x, 0.0);
+        assert_eq!(rigidbody.interpolated(&transform, 0.5).position.position.x, 1.5);
+        assert_eq!(rigidbody.interpolated(&transform, 1.0).position, transform.position);
+    }
+
+    #[test]
+    fn test_forces_around_center_of_mass() {
+        let mut collider = Collider::new(ColliderShape::Sphere(0.5));
+        collider.offset = Vec3f::new([0.0, 1.0, 0.0]);
+        let mut rigidbody = Rigidbody::with_collider_inertia(1.0, &collider);
+        // a quarter turn about x puts the center of mass along z
+        let half = std::f32::consts::FRAC_PI_4;
+        let rotation = Quat::new([half.cos(), half.sin(), 0.0, 0.0]);
+        let transform = Transform::new(Position::from(Vec3d::new([1.0, 2.0, 3.0])), Vec3f::new([1.0, 1.0, 1.0]), rotation);
+        let center = rigidbody.world_center_of_mass(&transform);
+        assert!((Vec3d::from(center - transform.position).length() - 1.0).abs() < 1e-6);
+        assert!((center.position.y - 2.0).abs() < 1e-6);
+
+        rigidbody.add_force_at_world_point(Vec3f::new([3.0, -1.0, 2.0]), center, &transform);
+        assert!(rigidbody.torque.length() < 1e-6);
+        assert_eq!(rigidbody.force, Vec3f::new([3.0, -1.0, 2.0]));
+
+        // r x F with r = (2, 0, 0) and F = (0, 5, 0)
+        rigidbody.force = Vec3f::new([0.0, 0.0, 0.0]);
+        let point = center + Position::from(Vec3d::new([2.0, 0.0, 0.0]));
+        rigidbody.add_force_at_world_point(Vec3f::new([0.0, 5.0, 0.0]), point, &transform);
+        assert!((rigidbody.torque - Vec3f::new([0.0, 0.0, 10.0])).length() < 1e-5);
+
+        rigidbody.force = Vec3f::new([0.0, 0.0, 0.0]);
+        rigidbody.torque = Vec3f::new([0.0, 0.0, 0.0]);
+        rigidbody.add_relative_force(Vec3f::new([0.0, 4.0, 0.0]), &transform);
+        assert!((rigidbody.force.length() - 4.0).abs() < 1e-5);
+        assert!(rigidbody.force.y.abs() < 1e-5);
+        assert!(rigidbody.torque.length() < 1e-6);
+    }
+
+    #[test]
+    fn test_spins_around_center_of_mass() {
+        let mut rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 3.0]));
+        rigidbody.use_gravity = false;
+        rigidbody.center_of_mass = Vec3f::new([2.0, 0.0, 0.0]);
+        let mut transform = Transform::new(Position::default(), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+        let center = rigidbody.world_center_of_mass(&transform);
+        for _ in 0..60 {
+            integrate(&mut rigidbody, &mut transform, &PhysicsSettings::default(), 1.0 / 60.0);
+        }
+        // the position swings around the center of mass, which stays put
+        assert!(Vec3d::from(rigidbody.world_center_of_mass(&transform) - center).length() < 1e-4);
+        assert!(Vec3d::from(transform.position - Position::default()).length() > 1.0);
+    }
+
+    #[test]
+    fn test_velocity_clamped_and_broken_steps_skipped() {
+        let settings = PhysicsSettings::default();
+        let mut transform = Transform::new(Position::default(), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]));
+        let mut rigidbody = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        rigidbody.add_force(Vec3f::new([1e30, 0.0, 0.0]));
+        rigidbody.add_torque(Vec3f::new([0.0, 1e30, 0.0]));
+        integrate(&mut rigidbody, &mut transform, &settings, 1.0 / 60.0);
+        assert!(rigidbody.velocity.length() <= settings.max_linear_velocity * 1.0001);
+        assert!(rigidbody.angular_velocity.length() <= settings.max_angular_velocity * 1.0001);
+        assert!(transform.position.position.x <= settings.max_linear_velocity as f64 / 60.0 + 1e-3);
+
+        // its own limits instead of the settings'
+        rigidbody.max_linear_velocity = Some(5.0);
+        rigidbody.add_force(Vec3f::new([1e30, 0.0, 0.0]));
+        integrate(&mut rigidbody, &mut transform, &settings, 1.0 / 60.0);
+        assert!((rigidbody.velocity.length() - 5.0).abs() < 1e-3);
+
+        // nothing moves and the broken force is dropped
+        let before = transform.position;
+        rigidbody.add_force(Vec3f::new([f32::NAN, 0.0, 0.0]));
+        integrate(&mut rigidbody, &mut transform, &settings, 1.0 / 60.0);
+        integrate(&mut rigidbody, &mut transform, &settings, f32::NAN);
+        integrate(&mut rigidbody, &mut transform, &settings, f32::INFINITY);
+        assert_eq!(transform.position, before);
+        integrate(&mut rigidbody, &mut transform, &settings, 1.0 / 60.0);
+        assert!(transform.position.position.x.is_finite() && rigidbody.velocity.x.is_finite());
+        assert!(transform.position.position.x > before.position.x);
     }
 }
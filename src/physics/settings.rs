@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::vectors::Vec3f;
+
+// colliders have 32 layer bits
+pub const MAX_LAYERS: usize = 32;
+
+// world-wide physics parameters, read by RigidbodyHandler and CollisionHandler every fixed step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhysicsSettings {
+    // acceleration applied to every rigidbody with use_gravity, scaled by its gravity_scale
+    pub gravity: Vec3f,
+    // seconds of scaled time simulated by each fixed step
+    pub timestep: f64,
+    // edge of the broadphase grid cells, around the size of a typical collider
+    pub broadphase_cell_size: f64,
+    // wireframes of every collider and the last step's contacts, drawn by PhysicsDebugRenderer
+    #[serde(default)]
+    pub debug_draw: bool,
+    // with debug_draw, also the broadphase cells holding a collider
+    #[serde(default)]
+    pub debug_draw_broadphase: bool,
+    // rigidbodies are slowed down to these after every step unless they set their own
+    #[serde(default = "default_max_linear_velocity")]
+    pub max_linear_velocity: f32,
+    #[serde(default = "default_max_angular_velocity")]
+    pub max_angular_velocity: f32,
+    // longest frame simulated, the rest of a longer one is dropped
+    #[serde(default = "default_max_delta_time")]
+    pub max_delta_time: f64,
+    // names of the collider layers, the index is the layer
+    #[serde(default = "default_layers")]
+    layers: Vec<String>,
+}
+
+fn default_layers() -> Vec<String> {
+    vec!["default".to_string()]
+}
+
+fn default_max_linear_velocity() -> f32 {
+    1000.0
+}
+
+fn default_max_angular_velocity() -> f32 {
+    100.0
+}
+
+fn default_max_delta_time() -> f64 {
+    0.1
+}
+
+impl PhysicsSettings {
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.layers.iter().position(|x| x == name).map(|x| x as u32)
+    }
+
+    // the layer called `name`, added after the others if there is none, None once all layers are taken
+    pub fn add_layer(&mut self, name: &str) -> Option<u32> {
+        if let Some(layer) = self.layer(name) {
+            return Some(layer);
+        }
+        if self.layers.len() >= MAX_LAYERS {
+            return None;
+        }
+        self.layers.push(name.to_string());
+        Some(self.layers.len() as u32 - 1)
+    }
+
+    pub fn layer_names(&self) -> &[String] {
+        &self.layers
+    }
+
+    // `delta_time` as the physics sees it, at most max_delta_time and nothing for a broken one
+    pub fn physics_delta_time(&self, delta_time: f64) -> f64 {
+        if !delta_time.is_finite() || delta_time < 0.0 {
+            return 0.0;
+        }
+        delta_time.min(self.max_delta_time)
+    }
+
+    // a collider mask hitting only these layers, None if one of them doesn't exist
+    pub fn mask(&self, names: &[&str]) -> Option<u32> {
+        names.iter().try_fold(0, |mask, name| Some(mask | 1 << self.layer(name)?))
+    }
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        PhysicsSettings {
+            gravity: Vec3f::new([0.0, -9.81, 0.0]),
+            timestep: 1.0 / 60.0,
+            broadphase_cell_size: 4.0,
+            debug_draw: false,
+            debug_draw_broadphase: false,
+            max_linear_velocity: default_max_linear_velocity(),
+            max_angular_velocity: default_max_angular_velocity(),
+            max_delta_time: default_max_delta_time(),
+            layers: default_layers(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PhysicsSettings, MAX_LAYERS};
+
+    #[test]
+    fn test_named_layers_round_trip() {
+        let mut settings = PhysicsSettings::default();
+        assert_eq!(settings.add_layer("bullets"), Some(1));
+        assert_eq!(settings.add_layer("debris"), Some(2));
+        assert_eq!(settings.add_layer("bullets"), Some(1));
+        assert_eq!(settings.mask(&["default", "debris"]), Some(0b101));
+        assert_eq!(settings.mask(&["player"]), None);
+
+        let bytes = rmp_serde::to_vec(&settings).unwrap();
+        let loaded: PhysicsSettings = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(loaded.layer_names(), settings.layer_names());
+        assert_eq!(loaded.layer("debris"), Some(2));
+
+        for i in 3..MAX_LAYERS {
+            assert!(settings.add_layer(&format!("layer_{}", i)).is_some());
+        }
+        assert_eq!(settings.add_layer("one_too_many"), None);
+    }
+
+    #[test]
+    fn test_physics_delta_time() {
+        let settings = PhysicsSettings::default();
+        assert_eq!(settings.physics_delta_time(0.016), 0.016);
+        // ten seconds alt-tabbed away
+        assert_eq!(settings.physics_delta_time(10.0), settings.max_delta_time);
+        assert_eq!(settings.physics_delta_time(f64::NAN), 0.0);
+        assert_eq!(settings.physics_delta_time(f64::INFINITY), 0.0);
+        assert_eq!(settings.physics_delta_time(-1.0), 0.0);
+    }
+}
@@ -1,83 +1,127 @@
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::mem::offset_of;
 use std::time::Duration;
 
 use bytemuck::{Pod, Zeroable};
 
-use log::{error, trace, warn};
+use hecs::Entity;
 
+use log::{debug, error, warn};
+
+use billboard::{billboard_material, BillboardRenderingComponent, BillboardVertex};
+use debug_lines::{debug_line_material, DebugLineRenderingComponent, DebugLineVertex};
+use particles::{particle_material, ParticleInstance, ParticleRenderingComponent};
+use mesh_cache::{FrameCache, MeshBuffers};
 use render_meshes::MeshRenderingComponent;
+use render_stats::RenderStats;
+use renderer_graph::{CompiledRenderGraph, RenderGraph};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::buffer::{BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
 use vulkano::command_buffer::{
     AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
 };
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::physical::PhysicalDevice;
 use vulkano::device::
     Device
 ;
-use vulkano::format::Format;
+use vulkano::format::{ClearValue, Format};
 use vulkano::image::view::ImageView;
-use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
-use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::image::{Image, ImageAspects, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
+use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
 use vulkano::pipeline::graphics::color_blend::{
     AttachmentBlend, ColorBlendAttachmentState, ColorBlendState, ColorComponents,
 };
-use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::depth_stencil::{DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
 use vulkano::pipeline::graphics::multisample::MultisampleState;
-use vulkano::pipeline::graphics::rasterization::{PolygonMode, RasterizationState};
-use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
-use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::tessellation::TessellationState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition, VertexInputState};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
 use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
 use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
 use vulkano::pipeline::{
-    GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
 };
-use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+use vulkano::render_pass::{AttachmentLoadOp, Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::swapchain::{
-    self, PresentFuture, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
+    self, ColorSpace, PresentFuture, PresentMode, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
     SwapchainPresentInfo,
 };
 use vulkano::sync::future::{FenceSignalFuture, JoinFuture};
 use vulkano::sync::{self, GpuFuture};
 use vulkano::{Validated, VulkanError};
 
-use winit::dpi::PhysicalSize;
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::monitor::{MonitorHandle, VideoModeHandle};
+use winit::window::{CursorGrabMode, Fullscreen};
 
 use crate::asset_library::AssetLibrary;
+use crate::engine_config::{load_icon, supported_samples, EngineConfig, FullscreenMode, MonitorIndex};
+use crate::engine_error::EngineError;
 use crate::ecs::{System, World};
 use crate::state::State;
-use crate::types::camera::Camera;
-use crate::types::material::RenderingType;
+use crate::types::camera::{update_camera_views, ViewportRect};
+use crate::types::material::{error_material, DepthSettings, Material, RenderingType};
 use crate::types::matrices::*;
 use crate::types::position::Position;
 use crate::types::shader::{Shader, ShaderType};
+use crate::types::staging_buffer::UpdatableRingBuffer;
 use crate::types::vectors::*;
 use crate::ui::ui_layout::UiVertexData;
+use crate::ui::ui_mesh::{UiBatchMesh, UiMeshBuffers};
+use crate::ui::ui_scale::UiScaleMode;
 use crate::ui::ui_rendering::UiRenderingComponent;
 use crate::vulkan::context::VulkanContext;
 use crate::vulkan::memory::MemoryAllocators;
 
+use self::compute_component::ComputeComponent;
 use self::rendering_component::RenderingComponent;
 
 pub mod rendering_component;
 pub mod render_meshes;
+pub mod compute_component;
+pub mod compute_gradient;
+pub mod billboard;
+pub mod debug_lines;
+pub mod particles;
+pub mod picking;
+pub mod mesh_cache;
+pub mod render_stats;
+pub mod renderer_graph;
 
 #[derive(Pod, Zeroable, Clone, Copy, Debug, Serialize, Deserialize, Vertex)]
 #[repr(C)]
 pub struct VertexData {
     #[format(R32G32B32A32_SFLOAT)]
-    pub position: Vec3f,
+    pub position: GpuVec3f,
     #[format(R32G32B32A32_SFLOAT)]
-    pub uv: Vec2f,
+    pub uv: GpuVec2f,
     #[format(R32G32B32A32_SFLOAT)]
-    pub normal: Vec3f,
+    pub normal: GpuVec3f,
     #[format(R32G32B32A32_SFLOAT)]
     pub tangent: Vec4f,
 }
 
+const _: () = assert!(size_of::<VertexData>() == 64);
+const _: () = assert!(offset_of!(VertexData, uv) == 16 && offset_of!(VertexData, normal) == 32 && offset_of!(VertexData, tangent) == 48);
+
+// second vertex stream of skinned meshes, the four joints with the most influence
+#[derive(Pod, Zeroable, Clone, Copy, Debug, Serialize, Deserialize, Vertex, PartialEq)]
+#[repr(C)]
+pub struct SkinVertexData {
+    #[format(R32G32B32A32_UINT)]
+    pub joints: [u32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub weights: [f32; 4],
+}
+
 #[derive(Pod, Zeroable, Clone, Copy, Debug)]
 #[repr(C)]
 pub struct VPData {
@@ -85,35 +129,163 @@ pub struct VPData {
     pub projection: Matrix4f,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct CameraView {
+    pub vp_data: VPData,
+    pub position: Position,
+    pub viewport: ViewportRect,
+}
+
+impl Default for CameraView {
+    fn default() -> Self {
+        CameraView {
+            vp_data: VPData {
+                view: Matrix4f::indentity(),
+                projection: Matrix4f::indentity(),
+            },
+            position: Position::default(),
+            viewport: ViewportRect::full(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Window {
     pub window_handle: Arc<winit::window::Window>,
+    // applied again after fullscreen changes, some platforms drop it on the way
+    cursor_grab: Cell<CursorGrabMode>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorInfo {
+    pub index: MonitorIndex,
+    pub name: Option<String>,
+    // physical pixels
+    pub size: (u32, u32),
+    pub refresh_rate_millihertz: Option<u32>,
+    pub scale_factor: f64,
 }
 
 impl Window {
-    pub fn new(event_loop: &EventLoop) -> Window {
-        Window {
-            #[allow(deprecated)]
-            window_handle: Arc::new(event_loop.event_loop.create_window(winit::window::Window::default_attributes()).unwrap()),
+    pub fn new(event_loop: &EventLoop, config: &EngineConfig) -> Result<Window, EngineError> {
+        let mut attributes = winit::window::Window::default_attributes()
+            .with_title(config.window_title.clone())
+            .with_resizable(config.resizable);
+        if let Some((width, height)) = config.initial_size {
+            attributes = attributes.with_inner_size(LogicalSize::new(width, height));
+        }
+        if let Some((width, height)) = config.min_size {
+            attributes = attributes.with_min_inner_size(LogicalSize::new(width, height));
+        }
+        if let Some(path) = &config.icon {
+            match load_icon(path) {
+                Ok(icon) => attributes = attributes.with_window_icon(Some(icon)),
+                Err(e) => warn!("Can't load window icon {}: {}", path.display(), e),
+            }
+        }
+
+        #[allow(deprecated)]
+        let window_handle = event_loop.event_loop.create_window(attributes).map_err(EngineError::WindowCreation)?;
+        // monitors are only listed through an existing window outside of the running event loop
+        let monitors: Vec<MonitorHandle> = window_handle.available_monitors().collect();
+        window_handle.set_fullscreen(fullscreen(config.fullscreen, &monitors, window_handle.primary_monitor()));
+
+        Ok(Window {
+            window_handle: Arc::new(window_handle),
+            cursor_grab: Cell::new(CursorGrabMode::None),
+        })
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        let size = self.window_handle.inner_size();
+        size.width == 0 || size.height == 0
+    }
+
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        self.window_handle
+            .available_monitors()
+            .enumerate()
+            .map(|(index, monitor)| MonitorInfo {
+                index,
+                name: monitor.name(),
+                size: monitor.size().into(),
+                refresh_rate_millihertz: monitor.refresh_rate_millihertz(),
+                scale_factor: monitor.scale_factor(),
+            })
+            .collect()
+    }
+
+    pub fn fullscreen_mode(&self) -> FullscreenMode {
+        match self.window_handle.fullscreen() {
+            None => FullscreenMode::Windowed,
+            Some(Fullscreen::Exclusive(_)) => FullscreenMode::Exclusive,
+            Some(Fullscreen::Borderless(monitor)) => {
+                FullscreenMode::Borderless(monitor.and_then(|x| self.window_handle.available_monitors().position(|y| y == x)))
+            }
+        }
+    }
+
+    // the Resized event that follows recreates the swapchain, State::set_fullscreen also does without one
+    pub fn set_fullscreen(&self, mode: FullscreenMode) {
+        let monitors: Vec<MonitorHandle> = self.window_handle.available_monitors().collect();
+        self.window_handle.set_fullscreen(fullscreen(mode, &monitors, self.window_handle.current_monitor()));
+        if self.cursor_grab.get() != CursorGrabMode::None {
+            self.set_cursor_grab(self.cursor_grab.get());
+        }
+    }
+
+    // Locked falls back to Confined where it isn't supported
+    pub fn set_cursor_grab(&self, mode: CursorGrabMode) {
+        let result = self.window_handle.set_cursor_grab(mode).or_else(|e| match mode {
+            CursorGrabMode::Locked => self.window_handle.set_cursor_grab(CursorGrabMode::Confined),
+            _ => Err(e),
+        });
+        match result {
+            Ok(()) => self.cursor_grab.set(mode),
+            Err(e) => warn!("Can't grab the cursor: {}", e),
         }
     }
 }
 
-pub struct EventLoop {
-    pub event_loop: winit::event_loop::EventLoop<()>,
+// the native resolution at the highest refresh rate, or the largest mode when the native one isn't listed,
+// modes are (width, height, refresh rate)
+fn best_video_mode(native: (u32, u32), modes: &[(u32, u32, u32)]) -> Option<usize> {
+    modes
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (width, height, refresh_rate))| ((*width, *height) == native, width * height, *refresh_rate))
+        .map(|x| x.0)
 }
 
-impl EventLoop {
-    pub fn new() -> EventLoop {
-        EventLoop {
-            event_loop: winit::event_loop::EventLoop::new().unwrap(),
+// `current` is used when there's no index or it's out of range, exclusive fullscreen falls back to borderless
+// on monitors without video modes
+fn fullscreen(mode: FullscreenMode, monitors: &[MonitorHandle], current: Option<MonitorHandle>) -> Option<Fullscreen> {
+    match mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless(index) => {
+            Some(Fullscreen::Borderless(index.and_then(|x| monitors.get(x).cloned()).or(current)))
+        }
+        FullscreenMode::Exclusive => {
+            let video_mode = current.and_then(|monitor| {
+                let modes: Vec<VideoModeHandle> = monitor.video_modes().collect();
+                let sizes: Vec<(u32, u32, u32)> =
+                    modes.iter().map(|x| (x.size().width, x.size().height, x.refresh_rate_millihertz())).collect();
+                best_video_mode(monitor.size().into(), &sizes).map(|x| modes[x].clone())
+            });
+            Some(video_mode.map_or(Fullscreen::Borderless(None), Fullscreen::Exclusive))
         }
     }
 }
 
-impl Default for EventLoop {
-    fn default() -> Self {
-        Self::new()
+pub struct EventLoop {
+    pub event_loop: winit::event_loop::EventLoop<()>,
+}
+
+impl EventLoop {
+    pub fn new() -> Result<EventLoop, EngineError> {
+        Ok(EventLoop {
+            event_loop: winit::event_loop::EventLoop::new().map_err(EngineError::EventLoop)?,
+        })
     }
 }
 
@@ -131,125 +303,142 @@ type Fence = Option<
 pub struct PipelineIdentifier {
     vertex_shader: Uuid,
     fragment_shader: Uuid,
-    rendering_type: RenderingType
+    rendering_type: RenderingType,
+    transparent: bool,
+    depth: DepthSettings,
+    geometry_shader: Option<Uuid>,
+    tessellation_shaders: Option<(Uuid, Uuid)>
 }
 
 impl PipelineIdentifier {
-    pub fn new(vertex_shader: Uuid, fragment_shader: Uuid, rendering_type: RenderingType) -> PipelineIdentifier {
+    pub fn new(vertex_shader: Uuid, fragment_shader: Uuid, rendering_type: RenderingType, transparent: bool, depth: DepthSettings) -> PipelineIdentifier {
         PipelineIdentifier {
             vertex_shader,
             fragment_shader,
-            rendering_type
+            rendering_type,
+            transparent,
+            depth,
+            geometry_shader: None,
+            tessellation_shaders: None
+        }
+    }
+
+    pub fn from_material(material: &Material) -> PipelineIdentifier {
+        PipelineIdentifier {
+            geometry_shader: material.geometry_shader,
+            tessellation_shaders: material.tessellation_shaders,
+            ..PipelineIdentifier::new(
+                material.vertex_shader,
+                material.fragment_shader,
+                material.rendering_type,
+                material.transparent,
+                material.depth
+            )
         }
     }
 }
 
 #[allow(dead_code)]
 pub struct Renderer {
+    pub render_graph: RenderGraph,
     pub render_pass: Arc<RenderPass>,
+    pub compiled_graph: CompiledRenderGraph,
     pub swapchain: Arc<Swapchain>,
+    pub image_format: Format,
     images: Vec<Arc<Image>>,
     framebuffers: Vec<Arc<Framebuffer>>,
     pub viewport: Viewport,
 
-    pub vp_data: VPData,
-    pub vp_pos: Position,
-    pub vp_buffers: Vec<Subbuffer<VPData>>,
+    pub views: Vec<CameraView>,
+    pub active_view: Cell<usize>,
+    // the highest priority active camera, none while the identity view is drawn
+    pub active_camera: Option<Entity>,
+    // one per camera view
+    pub vp_buffers: Vec<UpdatableRingBuffer<VPData>>,
 
     pub window_resized: bool,
     pub recreate_swapchain: bool,
+    // built by UiMeshBuilder, one draw each
+    pub ui_batches: Vec<UiBatchMesh>,
+    pub ui_scale_mode: UiScaleMode,
+    // set by set_ui_scale_mode and DPI changes, UiMeshBuilder lays the UI out again
+    pub ui_scale_changed: bool,
     pub frames_in_flight: usize,
 
     pub fences: Vec<Fence>,
     pub previous_fence: usize,
+    pub mesh_cache: RefCell<FrameCache<MeshBuffers>>,
+    // ui meshes outlive removed or rebuilt elements until the frames drawing them retire
+    pub ui_cache: RefCell<FrameCache<UiMeshBuffers>>,
 
     pub pipelines: HashMap<PipelineIdentifier, Arc<GraphicsPipeline>>,
+    pub invalid_materials: HashSet<Uuid>,
+    pub fullscreen_pipelines: HashMap<usize, Arc<GraphicsPipeline>>,
+    // vertex shader of the fullscreen passes, see src/rendering/shaders/fullscreen.vert
+    pub fullscreen_shader: Shader,
     pub rendering_components: Vec<Box<dyn RenderingComponent>>,
+    pub compute_pipelines: HashMap<Uuid, Arc<ComputePipeline>>,
+    pub compute_components: Vec<Box<dyn ComputeComponent>>,
 
-    pub anisotropic: Option<f32>
-}
+    pub frame_stats: RefCell<RenderStats>,
+    pub last_frame_stats: RenderStats,
 
-fn get_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<RenderPass> {
-    vulkano::single_pass_renderpass!(
-        device.clone(),
-        attachments: {
-            inter: {
-                format: swapchain.image_format(),
-                samples: SampleCount::Sample8,
-                load_op: Clear,
-                store_op: Store,
-            },
-            color: {
-                format: swapchain.image_format(),
-                samples: 1,
-                load_op: Clear,
-                store_op: Store,
-            },
-            depth: {
-                format: Format::D32_SFLOAT,
-                samples: SampleCount::Sample8,
-                load_op: Clear,
-                store_op: DontCare,
-            }
-        },
-        pass: {
-            color: [inter],
-            color_resolve: [color],
-            depth_stencil: {depth},
-        },
-    )
-    .unwrap()
+    pub pick_request: Option<Vec2f>,
+    pub pick_result: Option<Entity>,
+
+    pub anisotropic: Option<f32>,
+    // how far past a LOD boundary the camera has to move before the level switches
+    pub lod_hysteresis: f32
 }
 
 fn get_framebuffers(
     device: Arc<Device>,
     images: &[Arc<Image>],
     render_pass: Arc<RenderPass>,
+    output_attachment: u32,
 ) -> Vec<Arc<Framebuffer>> {
     let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
-    let depth_buffer = ImageView::new_default(
-        Image::new(
-            memory_allocator.clone(),
-            ImageCreateInfo {
-                image_type: ImageType::Dim2d,
-                format: Format::D32_SFLOAT,
-                extent: images[0].extent(),
-                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
-                samples: SampleCount::Sample8,
-                ..Default::default()
-            },
-            AllocationCreateInfo::default(),
-        )
-        .unwrap(),
-    )
-    .unwrap();
-
     images
         .iter()
         .map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
-            let inter = ImageView::new_default(
-                Image::new(
-                    memory_allocator.clone(),
-                    ImageCreateInfo {
-                        image_type: ImageType::Dim2d,
-                        format: image.format(),
-                        extent: image.extent(),
-                        usage: ImageUsage::COLOR_ATTACHMENT,
-                        samples: SampleCount::Sample8,
-                        ..Default::default()
-                    },
-                    AllocationCreateInfo::default(),
-                )
-                .unwrap(),
-            )
-            .unwrap();
+            let attachments = render_pass
+                .attachments()
+                .iter()
+                .enumerate()
+                .map(|(id, attachment)| {
+                    if id as u32 == output_attachment {
+                        return ImageView::new_default(image.clone()).unwrap();
+                    }
+
+                    let usage = if attachment.format.aspects().intersects(ImageAspects::DEPTH) {
+                        ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT
+                    } else {
+                        ImageUsage::COLOR_ATTACHMENT | ImageUsage::INPUT_ATTACHMENT
+                    };
+                    ImageView::new_default(
+                        Image::new(
+                            memory_allocator.clone(),
+                            ImageCreateInfo {
+                                image_type: ImageType::Dim2d,
+                                format: attachment.format,
+                                extent: image.extent(),
+                                usage,
+                                samples: attachment.samples,
+                                ..Default::default()
+                            },
+                            AllocationCreateInfo::default(),
+                        )
+                        .unwrap(),
+                    )
+                    .unwrap()
+                })
+                .collect();
 
             Framebuffer::new(
                 render_pass.clone(),
                 FramebufferCreateInfo {
-                    attachments: vec![inter, view, depth_buffer.clone()],
+                    attachments,
                     ..Default::default()
                 },
             )
@@ -258,7 +447,39 @@ fn get_framebuffers(
     .collect::<Vec<_>>()
 }
 
-pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, polygon_mode: PolygonMode) -> Arc<GraphicsPipeline> {
+fn get_clear_values(render_pass: &RenderPass) -> Vec<Option<ClearValue>> {
+    render_pass
+        .attachments()
+        .iter()
+        .map(|attachment| {
+            if attachment.load_op != AttachmentLoadOp::Clear {
+                None
+            } else if attachment.format.aspects().intersects(ImageAspects::DEPTH) {
+                Some(0f32.into())
+            } else {
+                Some([0.0, 0.0, 0.0, 1.0].into())
+            }
+        })
+        .collect()
+}
+
+// `extra` are the material's extra_shaders in the same order
+// transparent materials are tested against the depth but never write it
+fn depth_stencil_state(material: &Material) -> DepthStencilState {
+    DepthStencilState {
+        depth: if material.depth.test {
+            Some(DepthState {
+                write_enable: material.depth.write && !material.transparent,
+                compare_op: material.depth.compare.into(),
+            })
+        } else {
+            None
+        },
+        ..Default::default()
+    }
+}
+
+pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, extra: &[&Shader], material: &Material) -> Arc<GraphicsPipeline> {
     let vertex_type = vs.shader_type;
 
     let vs = vs.module.as_ref().unwrap().entry_point("main").unwrap();
@@ -268,16 +489,43 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, polygon_mode: Polyg
         ShaderType::Vertex => {
             VertexData::per_vertex().definition(&vs.info().input_interface).unwrap()
         },
+        ShaderType::SkinnedVertex => {
+            [VertexData::per_vertex(), SkinVertexData::per_vertex()].definition(&vs.info().input_interface).unwrap()
+        },
         ShaderType::UiVertex => {
             UiVertexData::per_vertex().definition(&vs.info().input_interface).unwrap()
         },
+        ShaderType::BillboardVertex => {
+            BillboardVertex::per_vertex().definition(&vs.info().input_interface).unwrap()
+        },
+        ShaderType::ParticleVertex => {
+            ParticleInstance::per_instance().definition(&vs.info().input_interface).unwrap()
+        },
+        ShaderType::DebugLineVertex => {
+            DebugLineVertex::per_vertex().definition(&vs.info().input_interface).unwrap()
+        },
         _ => panic!("")
     };
 
-    let stages = [
-        PipelineShaderStageCreateInfo::new(vs),
-        PipelineShaderStageCreateInfo::new(fs),
-    ];
+    let tessellated = extra.iter().any(|x| matches!(x.shader_type, ShaderType::TessellationControl));
+    let input_assembly = match vertex_type {
+        _ if tessellated => InputAssemblyState {
+            topology: PrimitiveTopology::PatchList,
+            ..Default::default()
+        },
+        ShaderType::DebugLineVertex => InputAssemblyState {
+            topology: PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        _ => InputAssemblyState::default()
+    };
+
+    let stages: Vec<PipelineShaderStageCreateInfo> = [vs]
+        .into_iter()
+        .chain(extra.iter().map(|x| x.module.as_ref().unwrap().entry_point("main").unwrap()))
+        .chain([fs])
+        .map(PipelineShaderStageCreateInfo::new)
+        .collect();
 
     let layout = PipelineLayout::new(
         state.vulkan_context.device.clone(),
@@ -289,30 +537,22 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, polygon_mode: Polyg
 
     let subpass = Subpass::from(state.renderer.render_pass.clone(), 0).unwrap();
 
-    GraphicsPipeline::new(
+    let pipeline = GraphicsPipeline::new(
         state.vulkan_context.device.clone(),
         None,
         GraphicsPipelineCreateInfo {
             stages: stages.into_iter().collect(),
             vertex_input_state: Some(vertex_input),
-            input_assembly_state: Some(InputAssemblyState::default()),
-            viewport_state: Some(ViewportState {
-                viewports: [state.renderer.viewport.clone()].into_iter().collect(),
-                ..Default::default()
-            }),
+            input_assembly_state: Some(input_assembly),
+            tessellation_state: tessellated.then(TessellationState::default),
+            viewport_state: Some(ViewportState::default()),
             rasterization_state: Some(RasterizationState {
-                polygon_mode,
-                ..Default::default()
-            }),
-            depth_stencil_state: Some(DepthStencilState {
-                depth: Some(DepthState {
-                    write_enable: true,
-                    compare_op: CompareOp::Greater,
-                }),
+                polygon_mode: material.rendering_type.into(),
                 ..Default::default()
             }),
+            depth_stencil_state: Some(depth_stencil_state(material)),
             multisample_state: Some(MultisampleState {
-                rasterization_samples: SampleCount::Sample8,
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
                 ..Default::default()
             }),
             color_blend_state: Some(ColorBlendState::with_attachment_states(
@@ -323,6 +563,55 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, polygon_mode: Polyg
                     color_write_enable: true,
                 },
             )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap();
+    state.vulkan_context.set_object_name(&*pipeline, &format!("{} pipeline", material.name));
+    pipeline
+}
+
+pub fn get_fullscreen_pipeline(state: &State, vs: &Shader, fs: &Shader, subpass: u32) -> Arc<GraphicsPipeline> {
+    let vs = vs.module.as_ref().unwrap().entry_point("main").unwrap();
+    let fs = fs.module.as_ref().unwrap().entry_point("main").unwrap();
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    let layout = PipelineLayout::new(
+        state.vulkan_context.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(state.vulkan_context.device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    let subpass = Subpass::from(state.renderer.render_pass.clone(), subpass).unwrap();
+
+    GraphicsPipeline::new(
+        state.vulkan_context.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState {
+                viewports: [state.renderer.viewport.clone()].into_iter().collect(),
+                ..Default::default()
+            }),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
             subpass: Some(subpass.into()),
             ..GraphicsPipelineCreateInfo::layout(layout)
         },
@@ -330,13 +619,129 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, polygon_mode: Polyg
     .unwrap()
 }
 
+pub fn get_compute_pipeline(state: &State, cs: &Shader) -> Arc<ComputePipeline> {
+    create_compute_pipeline(&state.vulkan_context.device, cs)
+}
+
+pub(crate) fn create_compute_pipeline(device: &Arc<Device>, cs: &Shader) -> Arc<ComputePipeline> {
+    let cs = cs.module.as_ref().unwrap().entry_point("main").unwrap();
+    let stage = PipelineShaderStageCreateInfo::new(cs);
+
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    ComputePipeline::new(device.clone(), None, ComputePipelineCreateInfo::stage_layout(stage, layout)).unwrap()
+}
+
+fn set_viewport(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>, StandardCommandBufferAllocator>,
+    rect: ViewportRect,
+    window_extent: [f32; 2],
+) {
+    let (offset, extent) = rect.to_pixels(window_extent);
+    builder
+        .set_viewport(
+            0,
+            [Viewport {
+                offset,
+                extent,
+                depth_range: 0.0..=1.0,
+            }]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+    builder
+        .set_scissor(
+            0,
+            [Scissor {
+                offset: [offset[0] as u32, offset[1] as u32],
+                extent: [extent[0] as u32, extent[1] as u32],
+            }]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+}
+
+fn ordered_rendering_components(
+    components: &[Box<dyn RenderingComponent>]
+) -> Vec<&dyn RenderingComponent> {
+    let mut ordered = components.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+    ordered.sort_by_key(|x| x.priority());
+    ordered
+}
+
+// the view each component is drawn in, in the order they're drawn, None is the whole window after every view
+fn rendering_draw_order(
+    components: &[Box<dyn RenderingComponent>],
+    view_count: usize
+) -> Vec<(Option<usize>, &dyn RenderingComponent)> {
+    let ordered = ordered_rendering_components(components);
+    let per_view = (0..view_count)
+        .flat_map(|view_id| ordered.iter().filter(|x| x.per_view()).map(move |x| (Some(view_id), *x)));
+    let window = ordered.iter().filter(|x| !x.per_view()).map(|x| (None, *x));
+    per_view.chain(window).collect()
+}
+
+fn draw_fullscreen_pass(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>, StandardCommandBufferAllocator>,
+    state: &State,
+    pipeline: &Arc<GraphicsPipeline>,
+    framebuffer: &Framebuffer,
+    subpass: usize,
+) {
+    let input_attachments = state.renderer.render_pass.subpasses()[subpass]
+        .input_attachments
+        .iter()
+        .enumerate()
+        .filter_map(|(binding, reference)| {
+            reference.as_ref().map(|reference| {
+                WriteDescriptorSet::image_view(
+                    binding as u32,
+                    framebuffer.attachments()[reference.attachment as usize].clone(),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let descriptor_count = input_attachments.len();
+
+    let input_set = PersistentDescriptorSet::new(
+        state.memory_allocators.descriptor_set_allocator.as_ref(),
+        pipeline.layout().set_layouts().first().unwrap().clone(),
+        input_attachments,
+        [],
+    )
+    .unwrap();
+
+    {
+        let mut stats = state.renderer.frame_stats.borrow_mut();
+        stats.record_pipeline_bind();
+        stats.record_descriptor_sets(if descriptor_count > 0 { 1 } else { 0 });
+        stats.record_draw(3);
+    }
+
+    builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+    builder
+        .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, input_set)
+        .unwrap();
+    builder.draw(3, 1, 0, 0).unwrap();
+}
+
 fn get_command_buffers(
     world: &World,
     assets: &mut AssetLibrary,
     state: &mut State,
     image_id: usize,
 ) -> Arc<PrimaryAutoCommandBuffer> {
-    let framebuffer = state.renderer.framebuffers.get(image_id).unwrap();
+    *state.renderer.frame_stats.borrow_mut() = RenderStats::default();
+
+    let framebuffer = state.renderer.framebuffers[image_id].clone();
     let mut builder = AutoCommandBufferBuilder::primary(
         state.memory_allocators.command_buffer_allocator.as_ref(),
         state.vulkan_context.queue.queue_family_index(),
@@ -344,14 +749,22 @@ fn get_command_buffers(
     )
     .unwrap();
 
+    for compute_component in state.renderer.compute_components.iter() {
+        builder = compute_component.compute(builder, world, assets, state, image_id);
+    }
+
+    // uniform copies have to land before the render pass starts
+    for buffer in state.renderer.vp_buffers.iter_mut() {
+        buffer.flush(image_id, &mut builder);
+    }
+    for material in assets.materials.values_mut() {
+        material.flush_parameters(image_id, &mut builder);
+    }
+
     builder
         .begin_render_pass(
             RenderPassBeginInfo {
-                clear_values: vec![
-                    Some([0.0, 0.0, 0.0, 1.0].into()),
-                    Some([0.0, 0.0, 0.0, 1.0].into()),
-                    Some(0f32.into()),
-                ],
+                clear_values: get_clear_values(&state.renderer.render_pass),
                 ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
             },
             SubpassBeginInfo {
@@ -361,30 +774,106 @@ fn get_command_buffers(
         )
         .unwrap();
 
-    for rendering_component in state.renderer.rendering_components.iter() {
+    let window_extent = state.renderer.viewport.extent;
+    let mut current_view = None;
+    for (view_id, rendering_component) in rendering_draw_order(&state.renderer.rendering_components, state.renderer.views.len()) {
+        if current_view != Some(view_id) {
+            current_view = Some(view_id);
+            match view_id {
+                Some(view_id) => {
+                    state.renderer.active_view.set(view_id);
+                    set_viewport(&mut builder, state.renderer.views[view_id].viewport, window_extent);
+                }
+                None => set_viewport(&mut builder, ViewportRect::full(), window_extent),
+            }
+        }
         builder = rendering_component.render(builder, world, assets, state, image_id);
     }
+    if current_view != Some(None) {
+        set_viewport(&mut builder, ViewportRect::full(), window_extent);
+    }
+
+    for (subpass, node) in state.renderer.compiled_graph.subpass_nodes.iter().enumerate().skip(1) {
+        builder
+            .next_subpass(
+                Default::default(),
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        if let Some(pipeline) = state.renderer.fullscreen_pipelines.get(node) {
+            draw_fullscreen_pass(&mut builder, state, pipeline, &framebuffer, subpass);
+        }
+    }
 
     builder.end_render_pass(Default::default()).unwrap();
     builder.build().unwrap()
 }
 
+const PREFERRED_SURFACE_FORMATS: [Format; 5] = [
+    Format::B8G8R8A8_SRGB,
+    Format::R8G8B8A8_SRGB,
+    Format::A8B8G8R8_SRGB_PACK32,
+    Format::B8G8R8A8_UNORM,
+    Format::R8G8B8A8_UNORM,
+];
+
+// SRGB formats are preferred so shaders can always output linear color,
+// UNORM formats are only used when the surface doesn't expose any SRGB ones
+fn select_surface_format(formats: &[(Format, ColorSpace)]) -> (Format, ColorSpace) {
+    PREFERRED_SURFACE_FORMATS
+        .iter()
+        .find_map(|preferred| {
+            formats
+                .iter()
+                .find(|(format, color_space)| {
+                    format == preferred && *color_space == ColorSpace::SrgbNonLinear
+                })
+                .copied()
+        })
+        .unwrap_or_else(|| {
+            warn!("No preferred surface format available, falling back to {:?}", formats[0].0);
+            formats[0]
+        })
+}
+
 fn get_swapchain(
     window_size: PhysicalSize<u32>,
     physical_device: Arc<PhysicalDevice>,
     device: Arc<Device>,
     surface: Arc<Surface>,
-) -> (Arc<Swapchain>, Vec<Arc<Image>>) {
+    present_mode: PresentMode,
+) -> Result<(Arc<Swapchain>, Vec<Arc<Image>>), EngineError> {
+    let surface_error = |e: Validated<VulkanError>| EngineError::SurfaceCreation { source: Box::new(e) };
     let caps = physical_device
         .surface_capabilities(&surface, Default::default())
-        .expect("failed to get surface capabilities");
+        .map_err(surface_error)?;
+    let supported = physical_device
+        .surface_present_modes(&surface, Default::default())
+        .is_ok_and(|mut modes| modes.any(|x| x == present_mode));
+    let present_mode = match supported {
+        true => present_mode,
+        false => {
+            warn!("Present mode {:?} not supported, falling back to Fifo", present_mode);
+            PresentMode::Fifo
+        }
+    };
 
-    let dimensions = window_size;
+    let dimensions = surface_extent(
+        window_size.into(),
+        caps.min_image_extent,
+        caps.max_image_extent
+    ).unwrap_or(caps.min_image_extent);
     let composite_alpha = caps.supported_composite_alpha.into_iter().next().unwrap();
-    let image_format = physical_device
-        .surface_formats(&surface, Default::default())
-        .unwrap()[0]
-        .0;
+    let (image_format, image_color_space) = select_surface_format(
+        &physical_device
+            .surface_formats(&surface, Default::default())
+            .map_err(surface_error)?
+    );
+    debug!("Selected swapchain format {:?} {:?}", image_format, image_color_space);
 
     Swapchain::new(
         device.clone(),
@@ -392,65 +881,157 @@ fn get_swapchain(
         SwapchainCreateInfo {
             min_image_count: caps.min_image_count,
             image_format,
-            image_extent: dimensions.into(),
+            image_color_space,
+            image_extent: dimensions,
             image_usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST,
             composite_alpha,
+            present_mode,
             ..Default::default()
         },
     )
-    .unwrap()
+    .map_err(surface_error)
 }
 
-fn recreate_pipelines(assets: &AssetLibrary, state: &mut State) {
-    for (_, material) in assets.materials.iter() {
-        state.renderer.pipelines.insert(
-            PipelineIdentifier::new(material.vertex_shader, material.fragment_shader, material.rendering_type),
-            get_pipeline(
-                state, 
-                assets.shaders.get(&material.vertex_shader).unwrap(), 
-                assets.shaders.get(&material.fragment_shader).unwrap(), 
-                material.rendering_type.into()
-            )        
+const FULLSCREEN_SHADER: &[u8] = include_bytes!("rendering/shaders/fullscreen.spv");
+
+// a single triangle covering the screen from 3 vertices without a vertex buffer, uv at location 0
+fn builtin_fullscreen_shader() -> Shader {
+    let words = vulkano::shader::spirv::bytes_to_words(FULLSCREEN_SHADER).expect("Built-in fullscreen shader malformed");
+    Shader::from_words(String::from("fullscreen"), ShaderType::Vertex, words.into_owned())
+}
+
+fn surface_extent(window_size: [u32; 2], min: [u32; 2], max: [u32; 2]) -> Option<[u32; 2]> {
+    if window_size[0] == 0 || window_size[1] == 0 {
+        return None;
+    }
+
+    Some([
+        window_size[0].clamp(min[0], max[0]),
+        window_size[1].clamp(min[1], max[1]),
+    ])
+}
+
+pub fn recreate_pipelines(assets: &AssetLibrary, state: &mut State) {
+    let fullscreen_nodes = state
+        .renderer
+        .compiled_graph
+        .subpass_nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(subpass, node)| {
+            state.renderer.render_graph.nodes[*node]
+                .fragment_shader()
+                .map(|fragment_shader| (subpass as u32, *node, fragment_shader))
+        })
+        .collect::<Vec<_>>();
+    for (subpass, node, fragment_shader) in fullscreen_nodes {
+        // a "fullscreen" shader among the assets replaces the built-in one
+        let vertex_shader = assets
+            .shader_by_name("fullscreen")
+            .map_or(&state.renderer.fullscreen_shader, |(_, x)| x);
+        let pipeline = get_fullscreen_pipeline(
+            state,
+            vertex_shader,
+            assets.shaders.get(&fragment_shader).expect("Fullscreen pass shader not found"),
+            subpass
         );
+        state.renderer.fullscreen_pipelines.insert(node, pipeline);
+    }
+
+    for (uuid, material) in assets.materials.iter() {
+        if state.renderer.invalid_materials.contains(uuid) {
+            continue;
+        }
+        let extra: Vec<&Shader> = material.extra_shaders().iter().map(|x| assets.shaders.get(x).unwrap()).collect();
+        let pipeline = get_pipeline(
+            state, 
+            assets.shaders.get(&material.vertex_shader).unwrap(), 
+            assets.shaders.get(&material.fragment_shader).unwrap(), 
+            &extra,
+            material
+        );
+        state.renderer.pipelines.insert(PipelineIdentifier::from_material(material), pipeline);
+    }
+
+    for material in [billboard_material(assets), particle_material(assets), debug_line_material(assets), error_material(assets)].into_iter().flatten() {
+        let pipeline = get_pipeline(
+            state,
+            assets.shaders.get(&material.vertex_shader).unwrap(),
+            assets.shaders.get(&material.fragment_shader).unwrap(),
+            &[],
+            &material
+        );
+        state.renderer.pipelines.insert(PipelineIdentifier::from_material(&material), pipeline);
     }
 }
 
 fn recalculate_projection(world: &World, state: &mut State, new_dimensions: PhysicalSize<u32>) {
-    let entities = world.entities.borrow_mut();
-
-    let mut camera = entities.query::<&Camera>();
-    let camera_data = camera.iter().next().expect("Camera not found").1;
-    state.renderer.vp_data.projection = Matrix4f::perspective(
-        camera_data.vfov.to_radians(),
-        (new_dimensions.width as f32) / (new_dimensions.height as f32),
-        camera_data.near
+    update_camera_views(
+        world,
+        state,
+        [new_dimensions.width as f32, new_dimensions.height as f32]
     );
 }
 
+fn update_vp_buffers(state: &mut State, image_id: usize) {
+    while state.renderer.vp_buffers.len() < state.renderer.views.len() {
+        let buffer = UpdatableRingBuffer::new(state, BufferUsage::UNIFORM_BUFFER, 1);
+        state.renderer.vp_buffers.push(buffer);
+    }
+
+    let renderer = &mut *state.renderer;
+    for (buffer, view) in renderer.vp_buffers.iter_mut().zip(renderer.views.iter()) {
+        buffer.write(image_id, &[view.vp_data]);
+    }
+}
+
 fn handle_possible_resize(world: &World, assets: &AssetLibrary, state: &mut State) -> bool {
+    let window_size = state.window.window_handle.inner_size().into();
+    resize_to(world, assets, state, window_size)
+}
+
+// recreates the swapchain, framebuffers and pipelines for `window_size` when a resize is pending, returns true
+// when the frame can't be drawn, also while the window is minimized to 0x0 and the resize stays pending
+fn resize_to(world: &World, assets: &AssetLibrary, state: &mut State, window_size: [u32; 2]) -> bool {
     if state.renderer.window_resized || state.renderer.recreate_swapchain {
+        let caps = state
+            .vulkan_context
+            .physical_device
+            .surface_capabilities(&state.vulkan_context.render_surface, Default::default())
+            .expect("failed to get surface capabilities");
+        let new_extent = match surface_extent(
+            window_size,
+            caps.min_image_extent,
+            caps.max_image_extent
+        ) {
+            Some(val) => val,
+            None => return true
+        };
+        let new_dimensions = PhysicalSize::new(new_extent[0], new_extent[1]);
+
         state.renderer.recreate_swapchain = false;
         state.renderer.window_resized = false;
 
-        let new_dimensions = state.window.window_handle.inner_size();
         let (new_swapchain, new_images) = state
             .renderer
             .swapchain
             .recreate(SwapchainCreateInfo {
-                image_extent: new_dimensions.into(),
+                image_extent: new_extent,
                 ..state.renderer.swapchain.create_info()
             })
             .expect("failed to recreate swapchain");
 
         state.renderer.swapchain = new_swapchain;
         state.renderer.images = new_images;
+        state.vulkan_context.name_swapchain_images(&state.renderer.images);
         state.renderer.framebuffers = get_framebuffers(
             state.vulkan_context.device.clone(),
             &state.renderer.images,
             state.renderer.render_pass.clone(),
+            state.renderer.compiled_graph.output_attachment,
         );
 
-        state.renderer.viewport.extent = new_dimensions.into();
+        state.renderer.viewport.extent = [new_extent[0] as f32, new_extent[1] as f32];
 
         recalculate_projection(world, state, new_dimensions);
         recreate_pipelines(assets, state);
@@ -480,8 +1061,6 @@ fn render(world: &World, assets: &mut AssetLibrary, state: &mut State) {
         state.renderer.recreate_swapchain = true;
     }
     
-    let command_buffer = get_command_buffers(world, assets, state, image_i as usize);
-    
     let previous_future = match state.renderer.fences[state.renderer.previous_fence].clone() {
         None => {
             let mut now = sync::now(state.vulkan_context.device.clone());
@@ -502,17 +1081,11 @@ fn render(world: &World, assets: &mut AssetLibrary, state: &mut State) {
             }
         }
     }
+    state.renderer.mesh_cache.borrow_mut().release(image_i as usize);
+    state.renderer.ui_cache.borrow_mut().release(image_i as usize);
 
-    {
-        let mut contents = state
-            .renderer
-            .vp_buffers
-            .get(image_i as usize)
-            .unwrap()
-            .write()
-            .unwrap();
-        *contents = state.renderer.vp_data;
-    }
+    update_vp_buffers(state, image_i as usize);
+    let command_buffer = get_command_buffers(world, assets, state, image_i as usize);
     
     let future = previous_future
         .join(acquire_future)
@@ -525,7 +1098,11 @@ fn render(world: &World, assets: &mut AssetLibrary, state: &mut State) {
         .then_signal_fence_and_flush();
     
     state.renderer.fences[image_i as usize] = match future.map_err(Validated::unwrap) {
-        Ok(value) => Some(Arc::new(value)),
+        Ok(value) => {
+            let stats = *state.renderer.frame_stats.borrow();
+            state.renderer.last_frame_stats = stats;
+            Some(Arc::new(value))
+        },
         Err(VulkanError::OutOfDate) => {
             state.renderer.recreate_swapchain = true;
             None
@@ -539,17 +1116,78 @@ fn render(world: &World, assets: &mut AssetLibrary, state: &mut State) {
 }
 
 impl Renderer {
-    pub fn new(context: &VulkanContext, memory_allocators: &MemoryAllocators, window: &Window) -> Renderer {
+    pub fn set_ui_scale_mode(&mut self, mode: UiScaleMode) {
+        if self.ui_scale_mode != mode {
+            self.ui_scale_mode = mode;
+            self.ui_scale_changed = true;
+        }
+    }
+
+    pub fn frame_retired(&self, image_id: usize) -> bool {
+        match self.fences.get(image_id) {
+            Some(Some(fence)) => fence.is_signaled().unwrap_or(false),
+            _ => true,
+        }
+    }
+
+    pub fn wait_for_frames(&self) {
+        for fence in self.fences.iter().flatten() {
+            if let Err(e) = fence.wait(None) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    pub fn active_view(&self) -> &CameraView {
+        &self.views[self.active_view.get()]
+    }
+
+    pub fn active_vp_buffer(&self, image_id: usize) -> Subbuffer<VPData> {
+        self.vp_buffers[self.active_view.get()].current(image_id).index(0)
+    }
+
+    pub fn pick(&mut self, cursor: Vec2f) -> Option<Entity> {
+        self.pick_request = Some(cursor);
+        self.pick_result
+    }
+
+    pub fn new(
+        context: &VulkanContext,
+        memory_allocators: &MemoryAllocators,
+        window: &Window,
+        config: &EngineConfig
+    ) -> Result<Renderer, EngineError> {
+        let properties = context.physical_device.properties();
+        let samples = supported_samples(
+            config.msaa,
+            properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts
+        );
+        Renderer::new_with_graph(context, memory_allocators, window, RenderGraph::with_samples(samples), config.present_mode)
+    }
+
+    pub fn new_with_graph(
+        context: &VulkanContext,
+        memory_allocators: &MemoryAllocators,
+        window: &Window,
+        render_graph: RenderGraph,
+        present_mode: PresentMode
+    ) -> Result<Renderer, EngineError> {
         let (swapchain, images) = get_swapchain(
             window.window_handle.inner_size(),
             context.physical_device.clone(),
             context.device.clone(),
             context.render_surface.clone(),
-        );
-
+            present_mode,
+        )?;
+        context.name_swapchain_images(&images);
 
-        let render_pass = get_render_pass(context.device.clone(), swapchain.clone());
-        let framebuffers = get_framebuffers(context.device.clone(), &images, render_pass.clone());
+        let (render_pass, compiled_graph) = render_graph.get_render_pass(context.device.clone(), swapchain.image_format())?;
+        let framebuffers = get_framebuffers(
+            context.device.clone(),
+            &images,
+            render_pass.clone(),
+            compiled_graph.output_attachment
+        );
 
         let viewport = Viewport {
             offset: [0.0, 0.0],
@@ -560,54 +1198,53 @@ impl Renderer {
         let frames_in_flight = images.len();
         let fences = vec![None; frames_in_flight];
 
-        let vp_buffers = {
-            let mut vec = Vec::new();
-            for _ in 0..frames_in_flight {
-                vec.push(
-                    Buffer::new_sized::<VPData>(
-                        memory_allocators.standard_memory_allocator.clone(),
-                        BufferCreateInfo {
-                            usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
-                            ..Default::default()
-                        },
-                        AllocationCreateInfo {
-                            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
-                            ..Default::default()
-                        },
-                    )
-                    .unwrap(),
-                )
-            }
-            vec
-        };
-        let vp_data = VPData {
-            view: Matrix4f::indentity(),
-            projection: Matrix4f::indentity(),
-        };
-        let vp_pos = Position::default();
+        let mut fullscreen_shader = builtin_fullscreen_shader();
+        fullscreen_shader.load(context)?;
 
-        Renderer {
+        Ok(Renderer {
+            render_graph,
             render_pass,
+            compiled_graph,
+            image_format: swapchain.image_format(),
             swapchain,
             images,
             framebuffers,
             viewport,
             window_resized: false,
             recreate_swapchain: false,
-            frames_in_flight: 0,
+            ui_batches: Vec::new(),
+            ui_scale_mode: UiScaleMode::default(),
+            ui_scale_changed: false,
+            frames_in_flight,
             fences,
             previous_fence: 0,
-            vp_data,
-            vp_pos,
-            vp_buffers,
+            mesh_cache: RefCell::new(FrameCache::new(frames_in_flight)),
+            ui_cache: RefCell::new(FrameCache::new(frames_in_flight)),
+            views: vec![CameraView::default()],
+            active_view: Cell::new(0),
+            active_camera: None,
+            // created by update_vp_buffers once the views are known
+            vp_buffers: Vec::new(),
             pipelines: HashMap::new(),
+            invalid_materials: HashSet::new(),
+            fullscreen_pipelines: HashMap::new(),
+            fullscreen_shader,
             rendering_components: vec![
                 Box::new(MeshRenderingComponent::new(memory_allocators)),
-                Box::new(UiRenderingComponent {})
+                Box::new(BillboardRenderingComponent::new(memory_allocators)),
+                Box::new(ParticleRenderingComponent::new(memory_allocators)),
+                Box::new(DebugLineRenderingComponent::new(memory_allocators)),
+                Box::new(UiRenderingComponent::new())
             ],
-            anisotropic: Some(context.physical_device.properties().max_sampler_anisotropy)
-        }
+            compute_pipelines: HashMap::new(),
+            compute_components: Vec::new(),
+            frame_stats: RefCell::new(RenderStats::default()),
+            last_frame_stats: RenderStats::default(),
+            pick_request: None,
+            pick_result: None,
+            anisotropic: Some(context.physical_device.properties().max_sampler_anisotropy),
+            lod_hysteresis: 2.0
+        })
     }
 }
 
@@ -616,8 +1253,220 @@ pub struct RendererHandler {}
 impl System for RendererHandler {
     fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
     fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
-        if !handle_possible_resize(world, assets, state) {
+        if !state.window.is_minimized() && !state.occluded && !handle_possible_resize(world, assets, state) {
             render(world, assets, state);
         }
+        state.debug_lines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use vulkano::{
+        device::Features,
+        format::Format,
+        shader::spirv::{ExecutionModel, Instruction, Spirv},
+        swapchain::ColorSpace,
+    };
+
+    use crate::{
+        asset_descriptions::{AssetDescriptions, MaterialDescription, ShaderDescription},
+        asset_library::AssetLibrary,
+        asset_loading::AssetLoading,
+        ecs::World,
+        engine_config::{AssetPaths, EngineConfig},
+        new_state,
+        state::Windowed,
+        types::{material::RenderingType, shader::ShaderType},
+        ui::ui_rendering::UiRenderingComponent,
+        vulkan::{context::VulkanContext, memory::MemoryAllocators},
+    };
+
+    use super::{
+        best_video_mode, builtin_fullscreen_shader, depth_stencil_state, ordered_rendering_components, rendering_component::RenderingComponent,
+        rendering_draw_order, resize_to, select_surface_format, surface_extent, EventLoop, PipelineIdentifier, Renderer,
+        Window,
+    };
+
+    struct TestMeshComponent {}
+
+    impl RenderingComponent for TestMeshComponent {}
+
+    #[test]
+    fn test_ui_rendered_after_meshes() {
+        let components: Vec<Box<dyn RenderingComponent>> = vec![
+            Box::new(UiRenderingComponent::new()),
+            Box::new(TestMeshComponent {}),
+        ];
+        let ordered = ordered_rendering_components(&components);
+
+        assert_eq!(ordered.first().unwrap().priority(), 0);
+        assert_eq!(ordered.last().unwrap().priority(), UiRenderingComponent::new().priority());
+    }
+
+    // registered before the meshes, the UI still draws over the whole window after every view, without the
+    // depth test so the meshes can't hide it
+    #[test]
+    fn test_ui_registered_first_drawn_last() {
+        let root = std::env::temp_dir().join(format!("oxide_test_ui_depth_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        for name in ["ui_vertex", "mesh_vertex", "color"] {
+            std::fs::copy("assets/shaders/bin/gradient.spv", root.join(format!("{}.spv", name))).unwrap();
+        }
+        let paths = AssetPaths { shader_binaries: root.clone(), ..AssetPaths::default() };
+        let shader = |name: &str, shader_type| ShaderDescription { name: name.to_string(), shader_type, source: None };
+        let material = |name: &str, vertex: &str| MaterialDescription {
+            name: name.to_string(),
+            vertex: vertex.to_string(),
+            fragment: "color".to_string(),
+            attachments: vec![],
+            paramaters: None,
+            rendering_type: RenderingType::Fill,
+            transparent: false,
+            depth: None,
+            geometry: None,
+            tessellation: None,
+        };
+        let descriptions = AssetDescriptions {
+            shaders: vec![
+                shader("ui_vertex", ShaderType::UiVertex),
+                shader("mesh_vertex", ShaderType::Vertex),
+                shader("color", ShaderType::Fragment),
+            ],
+            textures: vec![],
+            models: vec![],
+            materials: vec![material("ui", "ui_vertex"), material("mesh", "mesh_vertex")],
+            ui_elements: vec![],
+            fonts: vec![],
+        };
+        let assets = descriptions.generate_library(&paths).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let components: Vec<Box<dyn RenderingComponent>> = vec![
+            Box::new(UiRenderingComponent::new()),
+            Box::new(TestMeshComponent {}),
+        ];
+        let order = rendering_draw_order(&components, 2);
+        let ui_priority = UiRenderingComponent::new().priority();
+        assert_eq!(order.len(), 3);
+        assert_eq!(order.iter().map(|(view, _)| *view).collect::<Vec<_>>(), vec![Some(0), Some(1), None]);
+        assert!(order[..2].iter().all(|(_, x)| x.priority() == 0));
+        assert_eq!(order[2].1.priority(), ui_priority);
+
+        let (_, ui) = assets.material_by_name("ui").unwrap();
+        let (_, mesh) = assets.material_by_name("mesh").unwrap();
+        assert!(depth_stencil_state(ui).depth.is_none());
+        assert!(depth_stencil_state(mesh).depth.unwrap().write_enable);
+        assert_ne!(PipelineIdentifier::from_material(ui), PipelineIdentifier::from_material(mesh));
+    }
+
+    #[test]
+    fn test_builtin_fullscreen_shader() {
+        let shader = builtin_fullscreen_shader();
+        assert_eq!(shader.name, "fullscreen");
+
+        let spirv = Spirv::new(&shader.source).unwrap();
+        assert!(spirv.iter_entry_point().any(|x| matches!(
+            x,
+            Instruction::EntryPoint { execution_model: ExecutionModel::Vertex, name, .. } if name == "main"
+        )));
+    }
+
+    #[test]
+    fn test_select_surface_format_prefers_srgb() {
+        let formats = [
+            (Format::B8G8R8A8_UNORM, ColorSpace::SrgbNonLinear),
+            (Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear),
+            (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear),
+        ];
+        let reordered = [formats[2], formats[0], formats[1]];
+
+        assert_eq!(select_surface_format(&formats).0, Format::B8G8R8A8_SRGB);
+        assert_eq!(select_surface_format(&reordered).0, Format::B8G8R8A8_SRGB);
+    }
+
+    #[test]
+    fn test_select_surface_format_fallback() {
+        let formats = [(Format::A2B10G10R10_UNORM_PACK32, ColorSpace::SrgbNonLinear)];
+
+        assert_eq!(select_surface_format(&formats).0, Format::A2B10G10R10_UNORM_PACK32);
+    }
+
+    #[test]
+    fn test_surface_extent_minimized() {
+        assert_eq!(surface_extent([0, 0], [1, 1], [4096, 4096]), None);
+        assert_eq!(surface_extent([800, 0], [1, 1], [4096, 4096]), None);
+    }
+
+    // needs a display and a vulkan driver, skipped without them
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_surface_extent_restored() {
+        use winit::platform::x11::EventLoopBuilderExtX11;
+
+        let Ok(event_loop) = winit::event_loop::EventLoop::builder().with_any_thread(true).build() else {
+            return;
+        };
+        let event_loop = EventLoop { event_loop };
+        let config = EngineConfig::default();
+        let Ok(window) = Window::new(&event_loop, &config) else {
+            return;
+        };
+        let Ok(vulkan_context) = VulkanContext::new(&window, &config, Features::empty()) else {
+            return;
+        };
+        let memory_allocators = MemoryAllocators::new(&vulkan_context);
+        let renderer = Renderer::new(&vulkan_context, &memory_allocators, &window, &config).unwrap();
+        let asset_loading = AssetLoading::new(&vulkan_context, &memory_allocators, 1);
+        let mut state = new_state(
+            Windowed::new(window),
+            Windowed::new(vulkan_context),
+            Windowed::new(memory_allocators),
+            Windowed::new(renderer),
+            Windowed::new(asset_loading),
+        );
+        let world = World::new();
+        let assets = AssetLibrary::default();
+
+        // minimized, nothing is recreated and the resize stays pending
+        let swapchain = state.renderer.swapchain.clone();
+        let framebuffers = state.renderer.framebuffers.clone();
+        state.renderer.window_resized = true;
+        assert!(resize_to(&world, &assets, &mut state, [0, 0]));
+        assert!(state.renderer.window_resized);
+        assert!(Arc::ptr_eq(&swapchain, &state.renderer.swapchain));
+
+        // restored, the swapchain and framebuffers are recreated at the new size
+        let caps = state
+            .vulkan_context
+            .physical_device
+            .surface_capabilities(&state.vulkan_context.render_surface, Default::default())
+            .unwrap();
+        let extent = surface_extent([640, 480], caps.min_image_extent, caps.max_image_extent).unwrap();
+        assert!(resize_to(&world, &assets, &mut state, [640, 480]));
+        assert!(!state.renderer.window_resized);
+        assert!(!Arc::ptr_eq(&swapchain, &state.renderer.swapchain));
+        assert_eq!(state.renderer.swapchain.image_extent(), extent);
+        assert_eq!(state.renderer.framebuffers.len(), state.renderer.images.len());
+        assert!(state.renderer.framebuffers.iter().all(|x| !framebuffers.iter().any(|old| Arc::ptr_eq(x, old))));
+        assert!(state.renderer.framebuffers.iter().all(|x| x.extent() == extent));
+    }
+
+    #[test]
+    fn test_surface_extent_clamped() {
+        assert_eq!(surface_extent([8000, 600], [1, 1], [4096, 4096]), Some([4096, 600]));
+        assert_eq!(surface_extent([800, 600], [1024, 1024], [4096, 4096]), Some([1024, 1024]));
+    }
+
+    #[test]
+    fn test_best_video_mode() {
+        let modes = [(1920, 1080, 60000), (2560, 1440, 59940), (1920, 1080, 144000), (2560, 1440, 165000), (3840, 2160, 30000)];
+        // the native size beats larger and faster ones
+        assert_eq!(best_video_mode((2560, 1440), &modes), Some(3));
+        assert_eq!(best_video_mode((1920, 1080), &modes), Some(2));
+        assert_eq!(best_video_mode((1280, 720), &modes), Some(4));
+        assert_eq!(best_video_mode((1920, 1080), &[]), None);
     }
 }
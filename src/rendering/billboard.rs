@@ -0,0 +1,309 @@
+use std::mem::offset_of;
+
+use bytemuck::{Pod, Zeroable};
+use uuid::Uuid;
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    memory::allocator::MemoryTypeFilter,
+    pipeline::{graphics::vertex_input::Vertex, Pipeline, PipelineBindPoint},
+};
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::World,
+    state::State,
+    types::{
+        material::{Attachment, DepthSettings, Material, RenderingType},
+        matrices::Matrix4f,
+        texture::resident_texture,
+        transform::Transform,
+        vectors::{GpuVec2f, GpuVec3f, Vec2f, Vec3f, Vec4f},
+    },
+    vulkan::memory::MemoryAllocators,
+};
+
+use super::{rendering_component::RenderingComponent, PipelineIdentifier};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Billboard {
+    pub texture: Uuid,
+    pub size: Vec2f,
+    pub color: Vec4f,
+}
+
+#[derive(Pod, Zeroable, Clone, Copy, Debug, Vertex)]
+#[repr(C)]
+pub struct BillboardVertex {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub position: GpuVec3f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub uv: GpuVec2f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: Vec4f,
+}
+
+const _: () = assert!(size_of::<BillboardVertex>() == 48 && offset_of!(BillboardVertex, uv) == 16 && offset_of!(BillboardVertex, color) == 32);
+
+pub fn billboard_material(assets: &AssetLibrary) -> Option<Material> {
+    let (vertex_shader, _) = assets.shader_by_name("billboard")?;
+    let (fragment_shader, _) = assets.shader_by_name("unlit")?;
+
+    Some(Material::new(
+        "billboard".to_string(),
+        vertex_shader,
+        fragment_shader,
+        vec![Attachment::DefaultTexture],
+        None,
+        RenderingType::Fill,
+        true,
+        DepthSettings::default(),
+    ))
+}
+
+struct BillboardBatch {
+    texture: Uuid,
+    vertices: Vec<BillboardVertex>,
+}
+
+fn camera_axes(view: &Matrix4f) -> (Vec3f, Vec3f) {
+    (
+        Vec3f::new([view.0[0][0], view.0[1][0], view.0[2][0]]),
+        Vec3f::new([view.0[0][1], view.0[1][1], view.0[2][1]]),
+    )
+}
+
+// billboards are sorted back-to-front and a batch holds a run of neighbours sharing a texture, so drawing
+// the batches in order keeps the whole set back-to-front, interleaved textures cost a draw per run
+fn build_batches(billboards: &mut [(Billboard, Vec3f)], right: Vec3f, up: Vec3f) -> Vec<BillboardBatch> {
+    billboards.sort_by(|a, b| b.1.length().total_cmp(&a.1.length()));
+
+    let mut batches: Vec<BillboardBatch> = Vec::new();
+    for (billboard, center) in billboards.iter() {
+        if batches.last().map(|x| x.texture) != Some(billboard.texture) {
+            batches.push(BillboardBatch {
+                texture: billboard.texture,
+                vertices: Vec::new(),
+            });
+        }
+        let batch_id = batches.len() - 1;
+
+        let half_right = right * (billboard.size.x * 0.5);
+        let half_up = up * (billboard.size.y * 0.5);
+        let corner = |x: f32, y: f32| BillboardVertex {
+            position: GpuVec3f::from(*center + half_right * x + half_up * y),
+            uv: GpuVec2f::new([(x + 1.0) * 0.5, (y + 1.0) * 0.5]),
+            color: billboard.color,
+        };
+
+        batches[batch_id].vertices.extend([
+            corner(-1.0, -1.0),
+            corner(-1.0, 1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(1.0, -1.0),
+        ]);
+    }
+
+    batches
+}
+
+pub struct BillboardRenderingComponent {
+    vertex_allocator: SubbufferAllocator,
+}
+
+impl BillboardRenderingComponent {
+    pub fn new(allocators: &MemoryAllocators) -> BillboardRenderingComponent {
+        BillboardRenderingComponent {
+            vertex_allocator: SubbufferAllocator::new(
+                allocators.standard_memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+}
+
+impl RenderingComponent for BillboardRenderingComponent {
+    fn priority(&self) -> i32 {
+        50
+    }
+
+    fn render(
+        &self,
+        mut builder: AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+            StandardCommandBufferAllocator,
+        >,
+        world: &World,
+        assets: &AssetLibrary,
+        state: &State,
+        image_id: usize,
+    ) -> AutoCommandBufferBuilder<
+        PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+        StandardCommandBufferAllocator,
+    > {
+        let material = match billboard_material(assets) {
+            Some(val) => val,
+            None => return builder,
+        };
+        let pipeline = match state.renderer.pipelines.get(&PipelineIdentifier::from_material(&material)) {
+            Some(val) => val,
+            None => return builder,
+        };
+
+        let camera_pos = state.renderer.active_view().position;
+        let mut billboards = world
+            .entities
+            .borrow()
+            .query::<(&Billboard, &Transform)>()
+            .iter()
+            .map(|(_, (billboard, transform))| (*billboard, (transform.position - camera_pos).into()))
+            .collect::<Vec<(Billboard, Vec3f)>>();
+        if billboards.is_empty() {
+            return builder;
+        }
+
+        let (right, up) = camera_axes(&state.renderer.active_view().vp_data.view);
+        let vp_set = PersistentDescriptorSet::new(
+            state.memory_allocators.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts().first().unwrap().clone(),
+            [WriteDescriptorSet::buffer(
+                0,
+                state.renderer.active_vp_buffer(image_id),
+            )],
+            [],
+        )
+        .unwrap();
+
+        for batch in build_batches(&mut billboards, right, up) {
+            let texture = match resident_texture(assets, &batch.texture) {
+                Some(val) => val,
+                None => continue,
+            };
+            let (image_view, sampler) = match (texture.image_view.as_ref(), texture.sampler.as_ref()) {
+                (Some(image_view), Some(sampler)) => (image_view, sampler),
+                _ => continue,
+            };
+
+            let texture_set = PersistentDescriptorSet::new(
+                state.memory_allocators.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts().get(1).unwrap().clone(),
+                [WriteDescriptorSet::image_view_sampler(0, image_view.clone(), sampler.clone())],
+                [],
+            )
+            .unwrap();
+
+            let vertex_buffer = self
+                .vertex_allocator
+                .allocate_slice(batch.vertices.len() as u64)
+                .unwrap();
+            vertex_buffer.write().unwrap().copy_from_slice(&batch.vertices);
+
+            {
+                let mut stats = state.renderer.frame_stats.borrow_mut();
+                stats.record_pipeline_bind();
+                stats.record_descriptor_sets(2);
+                stats.record_draw(batch.vertices.len() as u32);
+            }
+
+            builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    vec![vp_set.clone(), texture_set],
+                )
+                .unwrap();
+            builder.bind_vertex_buffers(0, vertex_buffer).unwrap();
+            builder.draw(batch.vertices.len() as u32, 1, 0, 0).unwrap();
+        }
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::types::vectors::{Vec2f, Vec3f, Vec4f};
+
+    use super::{build_batches, Billboard};
+
+    #[test]
+    fn test_one_batch_per_texture() {
+        let textures = [Uuid::new_v4(), Uuid::new_v4()];
+        let mut billboards = (0..1000)
+            .map(|i| {
+                (
+                    Billboard {
+                        texture: textures[i / 500],
+                        size: Vec2f::new([1.0, 1.0]),
+                        color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+                    },
+                    Vec3f::new([0.0, 0.0, i as f32]),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let batches = build_batches(&mut billboards, Vec3f::new([1.0, 0.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|x| x.vertices.len() == 500 * 6));
+        assert_eq!(batches[0].texture, textures[1]);
+    }
+
+    #[test]
+    fn test_batches_sorted_back_to_front() {
+        let texture = Uuid::new_v4();
+        let billboard = Billboard {
+            texture,
+            size: Vec2f::new([2.0, 2.0]),
+            color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+        };
+        let mut billboards = vec![
+            (billboard, Vec3f::new([0.0, 0.0, 1.0])),
+            (billboard, Vec3f::new([0.0, 0.0, 5.0])),
+        ];
+
+        let batches = build_batches(&mut billboards, Vec3f::new([1.0, 0.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+
+        assert_eq!(*batches[0].vertices[0].position, Vec3f::new([-1.0, -1.0, 5.0]));
+        assert_eq!(*batches[0].vertices[6].position, Vec3f::new([-1.0, -1.0, 1.0]));
+    }
+
+    #[test]
+    fn test_interleaved_textures_stay_back_to_front() {
+        let textures = [Uuid::new_v4(), Uuid::new_v4()];
+        let billboard = |texture| Billboard {
+            texture,
+            size: Vec2f::new([2.0, 2.0]),
+            color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+        };
+        let mut billboards = vec![
+            (billboard(textures[0]), Vec3f::new([0.0, 0.0, 1.0])),
+            (billboard(textures[1]), Vec3f::new([0.0, 0.0, 3.0])),
+            (billboard(textures[0]), Vec3f::new([0.0, 0.0, 5.0])),
+        ];
+
+        let batches = build_batches(&mut billboards, Vec3f::new([1.0, 0.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+
+        assert_eq!(batches.iter().map(|x| x.texture).collect::<Vec<_>>(), vec![textures[0], textures[1], textures[0]]);
+        let depths = batches.iter().flat_map(|x| x.vertices.iter().map(|v| v.position.z)).collect::<Vec<_>>();
+        assert!(depths.windows(2).all(|x| x[0] >= x[1]));
+    }
+}
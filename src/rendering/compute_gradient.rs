@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use log::{error, warn};
+use vulkano::{
+    command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet},
+    image::view::ImageView,
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+    Validated, VulkanError,
+};
+
+use crate::{asset_library::AssetLibrary, ecs::World, state::State};
+
+use super::compute_component::ComputeComponent;
+
+// fills a storage texture using a compute shader like assets/shaders/gradient.comp, the texture is bound as a
+// storage image at set 0, binding 0 and the shader runs in 8x8 groups
+pub struct GradientComputeComponent {
+    pub shader_name: String,
+    pub texture_name: String,
+}
+
+impl GradientComputeComponent {
+    pub fn new(shader_name: &str, texture_name: &str) -> GradientComputeComponent {
+        GradientComputeComponent {
+            shader_name: shader_name.to_string(),
+            texture_name: texture_name.to_string(),
+        }
+    }
+}
+
+// binds `pipeline` with `image_view` as its storage image and dispatches enough groups to cover `extent`
+pub(crate) fn record_gradient(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>, StandardCommandBufferAllocator>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    pipeline: &Arc<ComputePipeline>,
+    image_view: Arc<ImageView>,
+    extent: [u32; 2],
+) -> Result<(), Validated<VulkanError>> {
+    let set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view(0, image_view)],
+        [],
+    )?;
+
+    builder
+        .bind_pipeline_compute(pipeline.clone())?
+        .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline.layout().clone(), 0, set)?
+        .dispatch([extent[0].div_ceil(8), extent[1].div_ceil(8), 1])?;
+    Ok(())
+}
+
+impl ComputeComponent for GradientComputeComponent {
+    fn compute(
+        &self,
+        mut builder:
+            AutoCommandBufferBuilder<
+                PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+                StandardCommandBufferAllocator
+            >,
+        _world: &World,
+        assets: &AssetLibrary,
+        state: &State,
+        _image_id: usize
+        ) -> AutoCommandBufferBuilder<
+                PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+                StandardCommandBufferAllocator
+            > {
+        let shader = assets.shader_by_name(&self.shader_name);
+        let texture = assets.texture_by_name(&self.texture_name);
+        let (Some((shader_uuid, _)), Some((_, texture))) = (shader, texture) else {
+            warn!("Gradient compute shader or texture not found");
+            return builder;
+        };
+        let (Some(pipeline), Some(image_view)) = (
+            state.renderer.compute_pipelines.get(&shader_uuid),
+            texture.image_view.as_ref()
+        ) else {
+            return builder;
+        };
+        if !texture.storage {
+            warn!("Texture {} is not a storage texture", texture.name);
+            return builder;
+        }
+
+        if let Err(e) = record_gradient(
+            &mut builder,
+            &state.memory_allocators.descriptor_set_allocator,
+            pipeline,
+            image_view.clone(),
+            [texture.width, texture.height],
+        ) {
+            error!("Failed to record the gradient compute pass into {}: {}", texture.name, e);
+        }
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use vulkano::{
+        command_buffer::{
+            allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        },
+        descriptor_set::allocator::StandardDescriptorSetAllocator,
+        device::{Device, DeviceCreateInfo, QueueCreateInfo, QueueFlags},
+        format::Format,
+        image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+        instance::{Instance, InstanceCreateInfo, InstanceCreateFlags},
+        memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+        shader::{spirv::{bytes_to_words, ExecutionModel, Instruction, Spirv}, ShaderModule, ShaderModuleCreateInfo},
+        VulkanLibrary,
+    };
+
+    use crate::{
+        engine_config::AssetPaths,
+        rendering::create_compute_pipeline,
+        types::shader::{Shader, ShaderType},
+    };
+
+    use super::record_gradient;
+
+    const GRADIENT: &[u8] = include_bytes!("../../assets/shaders/bin/gradient.spv");
+
+    #[test]
+    fn test_gradient_shader_shipped() {
+        let shader = Shader::new(String::from("gradient"), ShaderType::Compute, &AssetPaths::default()).unwrap();
+        assert!(matches!(shader.shader_type, ShaderType::Compute));
+
+        let spirv = Spirv::new(&bytes_to_words(GRADIENT).unwrap()).unwrap();
+        assert!(spirv.iter_entry_point().any(|x| matches!(
+            x,
+            Instruction::EntryPoint { execution_model: ExecutionModel::GLCompute, name, .. } if name == "main"
+        )));
+    }
+
+    // skipped when there's no vulkan driver to create the device with
+    #[test]
+    fn test_gradient_pipeline_binds() {
+        let Ok(library) = VulkanLibrary::new() else {
+            return;
+        };
+        let Ok(instance) = Instance::new(
+            library,
+            InstanceCreateInfo {
+                flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+                ..Default::default()
+            },
+        ) else {
+            return;
+        };
+        let Some((physical_device, queue_family_index)) = instance.enumerate_physical_devices().ok().and_then(|mut x| {
+            x.find_map(|p| {
+                let index = p
+                    .queue_family_properties()
+                    .iter()
+                    .position(|q| q.queue_flags.intersects(QueueFlags::COMPUTE))?;
+                Some((p, index as u32))
+            })
+        }) else {
+            return;
+        };
+        let (device, _queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut shader =
+            Shader::from_words(String::from("gradient"), ShaderType::Compute, bytes_to_words(GRADIENT).unwrap().to_vec());
+        shader.module = Some(unsafe { ShaderModule::new(device.clone(), ShaderModuleCreateInfo::new(&shader.source)).unwrap() });
+        let pipeline = create_compute_pipeline(&device, &shader);
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        let image = Image::new(
+            memory_allocator,
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_UNORM,
+                extent: [20, 12, 1],
+                usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let image_view = ImageView::new_default(image).unwrap();
+
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(device.clone(), Default::default());
+        let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            queue_family_index,
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        record_gradient(&mut builder, &descriptor_set_allocator, &pipeline, image_view, [20, 12]).unwrap();
+        builder.build().unwrap();
+    }
+}
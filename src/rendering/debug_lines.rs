@@ -0,0 +1,304 @@
+use std::{f64::consts::TAU, mem::offset_of};
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    memory::allocator::MemoryTypeFilter,
+    pipeline::{graphics::vertex_input::Vertex, Pipeline, PipelineBindPoint},
+};
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::World,
+    state::State,
+    types::{
+        material::{DepthSettings, Material, RenderingType},
+        position::Position,
+        vectors::{GpuVec3f, Vec3d, Vec3f, Vec4f},
+    },
+    vulkan::memory::MemoryAllocators,
+};
+
+use super::{rendering_component::RenderingComponent, PipelineIdentifier};
+
+// straight pieces of every circle and arc
+const CIRCLE_SEGMENTS: usize = 24;
+
+#[derive(Pod, Zeroable, Clone, Copy, Debug, Vertex)]
+#[repr(C)]
+pub struct DebugLineVertex {
+    // relative to the camera
+    #[format(R32G32B32A32_SFLOAT)]
+    pub position: GpuVec3f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: Vec4f,
+}
+
+const _: () = assert!(size_of::<DebugLineVertex>() == 32 && offset_of!(DebugLineVertex, color) == 16);
+
+// lines added during a frame are drawn by the next frame rendered and then cleared,
+// add them again every frame to keep them on screen
+#[derive(Debug, Default)]
+pub struct DebugLines {
+    lines: Vec<(Position, Position, Vec4f)>,
+}
+
+fn offset(center: Position, offset: Vec3d) -> Position {
+    center + Position::from(offset)
+}
+
+impl DebugLines {
+    pub fn line(&mut self, start: Position, end: Position, color: Vec4f) {
+        self.lines.push((start, end, color));
+    }
+
+    // `sweep` radians around `center` from `from`, turning towards `towards`, both at a right angle to each other
+    pub fn arc(&mut self, center: Position, from: Vec3d, towards: Vec3d, radius: f64, sweep: f64, color: Vec4f) {
+        let point = |i: usize| {
+            let angle = sweep * i as f64 / CIRCLE_SEGMENTS as f64;
+            offset(center, (from * angle.cos() + towards * angle.sin()) * radius)
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    pub fn circle(&mut self, center: Position, normal: Vec3d, radius: f64, color: Vec4f) {
+        let normal = normal.normalize();
+        let other = if normal.x.abs() < 0.9 { Vec3d::new([1.0, 0.0, 0.0]) } else { Vec3d::new([0.0, 1.0, 0.0]) };
+        let from = normal.cross(other).normalize();
+        self.arc(center, from, normal.cross(from), radius, TAU, color);
+    }
+
+    // three great circles around the axes
+    pub fn wire_sphere(&mut self, center: Position, radius: f64, color: Vec4f) {
+        for normal in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+            self.circle(center, Vec3d::new(normal), radius, color);
+        }
+    }
+
+    // the 12 edges of an axis aligned box
+    pub fn wire_box(&mut self, center: Position, half_extents: Vec3d, color: Vec4f) {
+        let corner = |x: f64, y: f64, z: f64| {
+            offset(center, Vec3d::new([x * half_extents.x, y * half_extents.y, z * half_extents.z]))
+        };
+        for a in [-1.0, 1.0] {
+            for b in [-1.0, 1.0] {
+                self.line(corner(-1.0, a, b), corner(1.0, a, b), color);
+                self.line(corner(a, -1.0, b), corner(a, 1.0, b), color);
+                self.line(corner(a, b, -1.0), corner(a, b, 1.0), color);
+            }
+        }
+    }
+
+    // an upright capsule `height` tall from the bottom to the top, circles where the caps meet the sides,
+    // four lines along the sides and the outline of each cap
+    pub fn wire_capsule(&mut self, center: Position, radius: f64, height: f64, color: Vec4f) {
+        let half = (height / 2.0 - radius).max(0.0);
+        let up = Vec3d::new([0.0, 1.0, 0.0]);
+        for (sign, cap) in [(1.0, offset(center, up * half)), (-1.0, offset(center, up * -half))] {
+            self.circle(cap, up, radius, color);
+            self.arc(cap, Vec3d::new([1.0, 0.0, 0.0]), up * sign, radius, TAU / 2.0, color);
+            self.arc(cap, Vec3d::new([0.0, 0.0, 1.0]), up * sign, radius, TAU / 2.0, color);
+        }
+        for side in [[1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0]] {
+            let side = Vec3d::new(side) * radius;
+            self.line(offset(center, side + up * half), offset(center, side + up * -half), color);
+        }
+    }
+
+    pub fn lines(&self) -> &[(Position, Position, Vec4f)] {
+        &self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+pub fn debug_line_material(assets: &AssetLibrary) -> Option<Material> {
+    let (vertex_shader, _) = assets.shader_by_name("debug_line")?;
+    let (fragment_shader, _) = assets.shader_by_name("debug_line_color")?;
+
+    Some(Material::new(
+        "debug_line".to_string(),
+        vertex_shader,
+        fragment_shader,
+        Vec::new(),
+        None,
+        RenderingType::Fill,
+        false,
+        DepthSettings::default(),
+    ))
+}
+
+fn line_vertices(lines: &[(Position, Position, Vec4f)], camera_pos: Position) -> Vec<DebugLineVertex> {
+    lines
+        .iter()
+        .flat_map(|(start, end, color)| {
+            [*start, *end].map(|x| DebugLineVertex { position: GpuVec3f::from(Vec3f::from(x - camera_pos)), color: *color })
+        })
+        .collect()
+}
+
+pub struct DebugLineRenderingComponent {
+    vertex_allocator: SubbufferAllocator,
+}
+
+impl DebugLineRenderingComponent {
+    pub fn new(allocators: &MemoryAllocators) -> DebugLineRenderingComponent {
+        DebugLineRenderingComponent {
+            vertex_allocator: SubbufferAllocator::new(
+                allocators.standard_memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+}
+
+impl RenderingComponent for DebugLineRenderingComponent {
+    fn priority(&self) -> i32 {
+        70
+    }
+
+    fn render(
+        &self,
+        mut builder: AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+            StandardCommandBufferAllocator,
+        >,
+        _world: &World,
+        assets: &AssetLibrary,
+        state: &State,
+        image_id: usize,
+    ) -> AutoCommandBufferBuilder<
+        PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+        StandardCommandBufferAllocator,
+    > {
+        if state.debug_lines.is_empty() {
+            return builder;
+        }
+        let material = match debug_line_material(assets) {
+            Some(val) => val,
+            None => return builder,
+        };
+        let pipeline = match state.renderer.pipelines.get(&PipelineIdentifier::from_material(&material)) {
+            Some(val) => val,
+            None => return builder,
+        };
+
+        let vertices = line_vertices(state.debug_lines.lines(), state.renderer.active_view().position);
+        let vertex_buffer = self
+            .vertex_allocator
+            .allocate_slice(vertices.len() as u64)
+            .unwrap();
+        vertex_buffer.write().unwrap().copy_from_slice(&vertices);
+
+        let vp_set = PersistentDescriptorSet::new(
+            state.memory_allocators.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts().first().unwrap().clone(),
+            [WriteDescriptorSet::buffer(
+                0,
+                state.renderer.active_vp_buffer(image_id),
+            )],
+            [],
+        )
+        .unwrap();
+
+        {
+            let mut stats = state.renderer.frame_stats.borrow_mut();
+            stats.record_pipeline_bind();
+            stats.record_descriptor_sets(1);
+            stats.record_draw(vertices.len() as u32);
+        }
+
+        builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                vp_set,
+            )
+            .unwrap();
+        builder.bind_vertex_buffers(0, vertex_buffer).unwrap();
+        builder.draw(vertices.len() as u32, 1, 0, 0).unwrap();
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{
+        position::Position,
+        vectors::{Vec3d, Vec3f, Vec3i, Vec4f},
+    };
+
+    use super::{line_vertices, DebugLines, CIRCLE_SEGMENTS};
+
+    fn distance(a: Position, b: Position) -> f64 {
+        Vec3d::from(a - b).length()
+    }
+
+    #[test]
+    fn test_wireframe_shapes() {
+        let white = Vec4f::new([1.0, 1.0, 1.0, 1.0]);
+        let center = Position::from(Vec3d::new([1.0, 2.0, 3.0]));
+        let mut lines = DebugLines::default();
+
+        lines.wire_box(center, Vec3d::new([1.0, 2.0, 3.0]), white);
+        assert_eq!(lines.lines().len(), 12);
+        assert!(lines.lines().iter().all(|(a, b, _)| {
+            let corner = |x: Position| {
+                let offset = Vec3d::from(x - center);
+                offset.x.abs() == 1.0 && offset.y.abs() == 2.0 && offset.z.abs() == 3.0
+            };
+            corner(*a) && corner(*b)
+        }));
+
+        lines.clear();
+        lines.wire_sphere(center, 0.5, white);
+        assert_eq!(lines.lines().len(), 3 * CIRCLE_SEGMENTS);
+        assert!(lines.lines().iter().all(|(a, b, _)| {
+            (distance(*a, center) - 0.5).abs() < 1e-9 && (distance(*b, center) - 0.5).abs() < 1e-9
+        }));
+        // closed circles
+        assert!(distance(lines.lines()[0].0, lines.lines()[CIRCLE_SEGMENTS - 1].1) < 1e-9);
+
+        lines.clear();
+        lines.wire_capsule(center, 0.5, 2.0, white);
+        let highest = lines.lines().iter().map(|(a, _, _)| Vec3d::from(*a - center).y).fold(f64::MIN, f64::max);
+        assert!((highest - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_vertices_relative_to_camera_in_another_chunk() {
+        let camera = Position::new(Vec3i::new([1, 0, 0]), Vec3d::new([-1.0, 0.0, 0.0]));
+        let start = Position::new(Vec3i::new([1, 0, 0]), Vec3d::new([1.0, 0.0, 0.0]));
+        let end = Position::new(Vec3i::new([0, 0, 0]), Vec3d::new([1e12 - 2.0, 0.0, 0.0]));
+        let color = Vec4f::new([1.0, 0.0, 0.0, 1.0]);
+
+        let vertices = line_vertices(&[(start, end, color)], camera);
+        assert_eq!(vertices.len(), 2);
+        assert_eq!(*vertices[0].position, Vec3f::new([2.0, 0.0, 0.0]));
+        assert_eq!(*vertices[1].position, Vec3f::new([-1.0, 0.0, 0.0]));
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{IndexBuffer, Subbuffer};
+
+use super::VertexData;
+
+pub type MeshBuffers = (Arc<Subbuffer<[VertexData]>>, Arc<IndexBuffer>);
+
+// keeps resources used by a frame alive until the fence of that frame is waited on
+pub struct FrameCache<T> {
+    frames: Vec<Vec<T>>,
+}
+
+impl<T> FrameCache<T> {
+    pub fn new(frames_in_flight: usize) -> FrameCache<T> {
+        FrameCache {
+            frames: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn retain(&mut self, frame: usize, item: T) {
+        self.frames[frame].push(item);
+    }
+
+    pub fn release(&mut self, frame: usize) {
+        self.frames[frame].clear();
+    }
+
+    pub fn len(&self, frame: usize) -> usize {
+        self.frames[frame].len()
+    }
+
+    pub fn is_empty(&self, frame: usize) -> bool {
+        self.frames[frame].is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::FrameCache;
+
+    #[test]
+    fn test_buffers_outlive_replacement() {
+        let frames_in_flight = 3;
+        let mut cache = FrameCache::new(frames_in_flight);
+        let mut mesh_buffer = Arc::new(0);
+        let mut in_flight = Vec::new();
+
+        for frame in 0..500 {
+            let image = frame % frames_in_flight;
+            cache.release(image);
+            in_flight.retain(|(f, _)| *f != image);
+
+            cache.retain(image, mesh_buffer.clone());
+            in_flight.push((image, Arc::downgrade(&mesh_buffer)));
+            mesh_buffer = Arc::new(frame + 1);
+
+            assert!(in_flight.iter().all(|(_, x)| x.upgrade().is_some()));
+            assert_eq!(cache.len(image), 1);
+        }
+    }
+
+    #[test]
+    fn test_release_clears_only_one_frame() {
+        let mut cache = FrameCache::new(2);
+        cache.retain(0, Arc::new(0));
+        cache.retain(1, Arc::new(1));
+        cache.release(0);
+
+        assert!(cache.is_empty(0));
+        assert_eq!(cache.len(1), 1);
+    }
+}
@@ -0,0 +1,424 @@
+use std::{cell::RefCell, f32::consts::PI, mem::offset_of};
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    memory::allocator::MemoryTypeFilter,
+    pipeline::{graphics::vertex_input::Vertex, Pipeline, PipelineBindPoint},
+};
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+    types::{
+        material::{DepthSettings, Material, RenderingType},
+        position::Position,
+        transform::Transform,
+        vectors::{GpuVec2f, GpuVec3f, Vec3f, Vec4f},
+    },
+    vulkan::memory::MemoryAllocators,
+};
+
+use super::{rendering_component::RenderingComponent, PipelineIdentifier};
+
+pub const MAX_PARTICLE_INSTANCES: usize = 65536;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vec3f,
+    pub velocity: Vec3f,
+    pub age: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub spawn_rate: f32,
+    pub lifetime: f32,
+    pub direction: Vec3f,
+    pub cone_angle: f32,
+    pub speed: f32,
+    pub gravity: Vec3f,
+    pub start_color: Vec4f,
+    pub end_color: Vec4f,
+    pub start_size: f32,
+    pub end_size: f32,
+    pub max_particles: usize,
+    particles: Vec<Particle>,
+    spawn_accumulator: f32,
+    pending_burst: usize,
+    seed: u64,
+}
+
+impl ParticleEmitter {
+    pub fn new(max_particles: usize) -> ParticleEmitter {
+        ParticleEmitter {
+            spawn_rate: 10.0,
+            lifetime: 1.0,
+            direction: Vec3f::new([0.0, 1.0, 0.0]),
+            cone_angle: 0.3,
+            speed: 1.0,
+            gravity: Vec3f::new([0.0, -9.81, 0.0]),
+            start_color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+            end_color: Vec4f::new([1.0, 1.0, 1.0, 0.0]),
+            start_size: 0.1,
+            end_size: 0.1,
+            max_particles,
+            particles: Vec::with_capacity(max_particles),
+            spawn_accumulator: 0.0,
+            pending_burst: 0,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    pub fn burst(&mut self, count: usize) {
+        self.pending_burst += count;
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn random_direction(&mut self) -> Vec3f {
+        let cos_theta = 1.0 - self.random() * (1.0 - self.cone_angle.cos());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = self.random() * 2.0 * PI;
+
+        let forward = self.direction.normalize();
+        let helper = if forward.x.abs() < 0.9 {
+            Vec3f::new([1.0, 0.0, 0.0])
+        } else {
+            Vec3f::new([0.0, 1.0, 0.0])
+        };
+        let right = forward.cross(helper).normalize();
+        let up = right.cross(forward);
+
+        forward * cos_theta + right * (sin_theta * phi.cos()) + up * (sin_theta * phi.sin())
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        let mut i = 0;
+        while i < self.particles.len() {
+            let particle = &mut self.particles[i];
+            particle.age += delta_time;
+            if particle.age >= self.lifetime {
+                self.particles.swap_remove(i);
+                continue;
+            }
+            particle.velocity += self.gravity * delta_time;
+            particle.position += particle.velocity * delta_time;
+            i += 1;
+        }
+
+        self.spawn_accumulator += self.spawn_rate * delta_time;
+        let spawned = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= spawned;
+
+        let count = (spawned as usize + self.pending_burst).min(self.max_particles.saturating_sub(self.particles.len()));
+        self.pending_burst = 0;
+        for _ in 0..count {
+            let velocity = self.random_direction() * self.speed;
+            self.particles.push(Particle {
+                position: Vec3f::new([0.0, 0.0, 0.0]),
+                velocity,
+                age: 0.0,
+            });
+        }
+    }
+}
+
+pub struct ParticleUpdater {}
+
+impl System for ParticleUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let delta_time = state.time_struct.delta as f32;
+        let mut entities = world.entities.borrow_mut();
+
+        for (_, emitter) in entities.query_mut::<&mut ParticleEmitter>() {
+            emitter.update(delta_time);
+        }
+    }
+}
+
+#[derive(Pod, Zeroable, Clone, Copy, Debug, Vertex)]
+#[repr(C)]
+pub struct ParticleInstance {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub position: GpuVec3f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: Vec4f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub size: GpuVec2f,
+}
+
+const _: () = assert!(size_of::<ParticleInstance>() == 48 && offset_of!(ParticleInstance, color) == 16 && offset_of!(ParticleInstance, size) == 32);
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn collect_instances<'a>(
+    emitters: impl Iterator<Item = (&'a ParticleEmitter, Position)>,
+    camera_pos: Position,
+    instances: &mut Vec<ParticleInstance>,
+) {
+    instances.clear();
+    for (emitter, position) in emitters {
+        let origin: Vec3f = (position - camera_pos).into();
+        for particle in emitter.particles().iter() {
+            if instances.len() >= MAX_PARTICLE_INSTANCES {
+                return;
+            }
+
+            let t = particle.age / emitter.lifetime;
+            let size = lerp(emitter.start_size, emitter.end_size, t);
+            instances.push(ParticleInstance {
+                position: (origin + particle.position).into(),
+                color: Vec4f::new([
+                    lerp(emitter.start_color.x, emitter.end_color.x, t),
+                    lerp(emitter.start_color.y, emitter.end_color.y, t),
+                    lerp(emitter.start_color.z, emitter.end_color.z, t),
+                    lerp(emitter.start_color.w, emitter.end_color.w, t),
+                ]),
+                size: GpuVec2f::new([size, size]),
+            });
+        }
+    }
+}
+
+pub fn particle_material(assets: &AssetLibrary) -> Option<Material> {
+    let (vertex_shader, _) = assets.shader_by_name("particle")?;
+    let (fragment_shader, _) = assets.shader_by_name("particle_color")?;
+
+    Some(Material::new(
+        "particle".to_string(),
+        vertex_shader,
+        fragment_shader,
+        Vec::new(),
+        None,
+        RenderingType::Fill,
+        true,
+        DepthSettings::default(),
+    ))
+}
+
+pub struct ParticleRenderingComponent {
+    instance_allocator: SubbufferAllocator,
+    instances: RefCell<Vec<ParticleInstance>>,
+}
+
+impl ParticleRenderingComponent {
+    pub fn new(allocators: &MemoryAllocators) -> ParticleRenderingComponent {
+        ParticleRenderingComponent {
+            instance_allocator: SubbufferAllocator::new(
+                allocators.standard_memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+            instances: RefCell::new(Vec::with_capacity(MAX_PARTICLE_INSTANCES)),
+        }
+    }
+}
+
+impl RenderingComponent for ParticleRenderingComponent {
+    fn priority(&self) -> i32 {
+        60
+    }
+
+    fn render(
+        &self,
+        mut builder: AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+            StandardCommandBufferAllocator,
+        >,
+        world: &World,
+        assets: &AssetLibrary,
+        state: &State,
+        image_id: usize,
+    ) -> AutoCommandBufferBuilder<
+        PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+        StandardCommandBufferAllocator,
+    > {
+        let material = match particle_material(assets) {
+            Some(val) => val,
+            None => return builder,
+        };
+        let pipeline = match state.renderer.pipelines.get(&PipelineIdentifier::from_material(&material)) {
+            Some(val) => val,
+            None => return builder,
+        };
+
+        let mut instances = self.instances.borrow_mut();
+        {
+            let entities = world.entities.borrow();
+            let mut query = entities.query::<(&ParticleEmitter, &Transform)>();
+            collect_instances(
+                query.iter().map(|(_, (emitter, transform))| (emitter, transform.position)),
+                state.renderer.active_view().position,
+                &mut instances,
+            );
+        }
+        if instances.is_empty() {
+            return builder;
+        }
+
+        let instance_buffer = self
+            .instance_allocator
+            .allocate_slice(instances.len() as u64)
+            .unwrap();
+        instance_buffer.write().unwrap().copy_from_slice(&instances);
+
+        let vp_set = PersistentDescriptorSet::new(
+            state.memory_allocators.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts().first().unwrap().clone(),
+            [WriteDescriptorSet::buffer(
+                0,
+                state.renderer.active_vp_buffer(image_id),
+            )],
+            [],
+        )
+        .unwrap();
+
+        {
+            let mut stats = state.renderer.frame_stats.borrow_mut();
+            stats.record_pipeline_bind();
+            stats.record_descriptor_sets(1);
+            stats.record_draw(6 * instances.len() as u32);
+        }
+
+        builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, vp_set)
+            .unwrap();
+        builder.bind_vertex_buffers(0, instance_buffer).unwrap();
+        builder.draw(6, instances.len() as u32, 0, 0).unwrap();
+
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::{
+        asset_library::AssetLibrary,
+        ecs::{System, World},
+        headless_state,
+        types::{position::Position, vectors::Vec3f},
+    };
+
+    use super::{collect_instances, ParticleEmitter, ParticleUpdater, MAX_PARTICLE_INSTANCES};
+
+    #[test]
+    fn test_emitter_respects_max_particles() {
+        let mut emitter = ParticleEmitter::new(50000);
+        emitter.lifetime = 10.0;
+        let capacity = emitter.particles.capacity();
+        emitter.burst(100000);
+        emitter.update(0.016);
+        assert_eq!(emitter.particles().len(), 50000);
+        assert_eq!(emitter.particles.capacity(), capacity);
+
+        for _ in 0..10 {
+            emitter.burst(1000);
+            emitter.update(0.016);
+            assert_eq!(emitter.particles().len(), 50000);
+        }
+    }
+
+    #[test]
+    fn test_expired_particles_removed() {
+        let mut emitter = ParticleEmitter::new(100);
+        emitter.spawn_rate = 0.0;
+        emitter.lifetime = 0.5;
+        emitter.burst(10);
+        emitter.update(0.1);
+        assert_eq!(emitter.particles().len(), 10);
+
+        emitter.update(0.5);
+        assert!(emitter.particles().is_empty());
+    }
+
+    #[test]
+    fn test_paused_emitter_does_not_spawn() {
+        let mut emitter = ParticleEmitter::new(100);
+        emitter.spawn_rate = 1000.0;
+        emitter.update(0.0);
+        assert!(emitter.particles().is_empty());
+    }
+
+    #[test]
+    fn test_particles_follow_cone() {
+        let mut emitter = ParticleEmitter::new(1000);
+        emitter.gravity = Vec3f::new([0.0, 0.0, 0.0]);
+        emitter.cone_angle = 0.1;
+        emitter.burst(1000);
+        emitter.update(0.0);
+
+        for particle in emitter.particles().iter() {
+            assert!(particle.velocity.normalize().dot(emitter.direction) >= 0.1f32.cos() - 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_upload_size_bounded() {
+        let mut emitters = [ParticleEmitter::new(50000), ParticleEmitter::new(50000)];
+        for emitter in emitters.iter_mut() {
+            emitter.lifetime = 10.0;
+            emitter.burst(50000);
+            emitter.update(0.016);
+        }
+
+        let mut instances = Vec::new();
+        collect_instances(
+            emitters.iter().map(|x| (x, Position::default())),
+            Position::default(),
+            &mut instances,
+        );
+        assert_eq!(instances.len(), MAX_PARTICLE_INSTANCES);
+    }
+
+    // game time ages the particles, the physics time scale doesn't
+    #[test]
+    fn test_updater_ignores_physics_time_scale() {
+        let world = World::new();
+        let mut emitter = ParticleEmitter::new(10);
+        emitter.spawn_rate = 0.0;
+        emitter.burst(1);
+        let entity = world.entities.borrow_mut().spawn((emitter,));
+
+        let mut assets = AssetLibrary::default();
+        let mut state = headless_state();
+        state.time_struct.advance(0.1);
+        ParticleUpdater {}.on_update(&world, &mut assets, &mut state);
+        state.physics_time_scale = 0.25;
+        ParticleUpdater {}.on_update(&world, &mut assets, &mut state);
+
+        let entities = world.entities.borrow();
+        let emitter = entities.get::<&ParticleEmitter>(entity).unwrap();
+        assert_eq!(emitter.particles().len(), 1);
+        assert_relative_eq!(emitter.particles()[0].age, 0.1, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,226 @@
+use hecs::Entity;
+use uuid::Uuid;
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+    types::{
+        matrices::Matrix4f,
+        mesh::{DynamicMesh, Mesh},
+        model::ModelComponent,
+        position::Position,
+        transform::Transform,
+        vectors::{Vec2f, Vec3f},
+    },
+};
+
+use super::VPData;
+
+fn cursor_ray(cursor: Vec2f, window_size: Vec2f, vp_data: &VPData) -> Vec3f {
+    let ndc_x = 2.0 * cursor.x / window_size.x - 1.0;
+    let ndc_y = 2.0 * cursor.y / window_size.y - 1.0;
+    let view_dir = Vec3f::new([
+        ndc_x / vp_data.projection.0[0][0],
+        ndc_y / vp_data.projection.0[1][1],
+        -1.0,
+    ]);
+    vp_data.view.vec_mul_inv(view_dir).normalize()
+}
+
+fn ray_aabb(origin: Vec3f, dir: Vec3f, min: Vec3f, max: Vec3f) -> Option<f32> {
+    let mut t_near = 0.0f32;
+    let mut t_far = f32::INFINITY;
+
+    for (o, d, lo, hi) in [
+        (origin.x, dir.x, min.x, max.x),
+        (origin.y, dir.y, min.y, max.y),
+        (origin.z, dir.z, min.z, max.z),
+    ] {
+        if d.abs() < f32::EPSILON {
+            if o < lo || o > hi {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (lo - o) / d;
+        let t2 = (hi - o) / d;
+        t_near = t_near.max(t1.min(t2));
+        t_far = t_far.min(t1.max(t2));
+        if t_near > t_far {
+            return None;
+        }
+    }
+
+    Some(t_near)
+}
+
+fn ray_mesh_distance(dir: Vec3f, camera_pos: Position, transform: &Transform, mesh: &Mesh) -> Option<f32> {
+    let rotation: Matrix4f = transform.rotation.to_matrix();
+    let relative_position: Vec3f = (transform.position - camera_pos).into();
+    let origin = rotation.vec_mul_inv(Vec3f::new([0.0, 0.0, 0.0]) - relative_position) / transform.scale;
+    let local_dir = rotation.vec_mul_inv(dir) / transform.scale;
+    ray_aabb(origin, local_dir, mesh.aabb.min, mesh.aabb.max)
+}
+
+pub fn pick_entity(
+    world: &World,
+    assets: &AssetLibrary,
+    camera_pos: Position,
+    dir: Vec3f,
+) -> Option<Entity> {
+    let entities = world.entities.borrow();
+    let mut closest: Option<(Entity, f32)> = None;
+    let mut test = |entity: Entity, transform: &Transform, mesh: &Uuid| {
+        let mesh = match assets.meshes.get(mesh) {
+            Some(val) => val,
+            None => return,
+        };
+        if let Some(distance) = ray_mesh_distance(dir, camera_pos, transform, mesh) {
+            let closer = match closest {
+                Some((_, val)) => distance < val,
+                None => true,
+            };
+            if closer {
+                closest = Some((entity, distance));
+            }
+        }
+    };
+
+    for (entity, (dyn_mesh, transform)) in entities.query::<(&DynamicMesh, &Transform)>().iter() {
+        if let Some(mesh) = dyn_mesh.mesh.as_ref() {
+            test(entity, transform, mesh);
+        }
+    }
+
+    for (entity, (model_comp, transform)) in entities.query::<(&ModelComponent, &Transform)>().iter() {
+        if let Some(model) = assets.models.get(&model_comp.model_uuid) {
+            // skinned meshes are tested in their bind pose
+            for primitive in model_comp.primitives(model) {
+                test(entity, &transform.with_node(&primitive.transform), &primitive.mesh);
+            }
+        }
+    }
+
+    closest.map(|(entity, _)| entity)
+}
+
+pub struct PickingHandler {}
+
+impl System for PickingHandler {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let cursor = match state.renderer.pick_request.take() {
+            Some(val) => val,
+            None => return,
+        };
+
+        let window_size = state
+            .window
+            .window_handle
+            .inner_size()
+            .to_logical::<f32>(state.window.window_handle.scale_factor());
+        let cursor = cursor / Vec2f::new([window_size.width, window_size.height]);
+        let view = match state.renderer.views.iter().find(|x| x.viewport.contains(cursor)) {
+            Some(val) => val,
+            None => {
+                state.renderer.pick_result = None;
+                return;
+            }
+        };
+
+        let dir = cursor_ray(view.viewport.to_local(cursor), Vec2f::new([1.0, 1.0]), &view.vp_data);
+        state.renderer.pick_result = pick_entity(world, assets, view.position, dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{
+        asset_library::AssetLibrary,
+        ecs::World,
+        rendering::{VPData, VertexData},
+        types::{
+            matrices::Matrix4f,
+            mesh::{DynamicMesh, Mesh},
+            position::Position,
+            quaternion::Quat,
+            transform::Transform,
+            vectors::{GpuVec2f, GpuVec3f, Vec2f, Vec3d, Vec3f, Vec4f},
+        },
+    };
+
+    use super::{cursor_ray, pick_entity, ray_aabb};
+
+    fn cube() -> Mesh {
+        let vertices = [[-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]]
+            .into_iter()
+            .map(|p| VertexData {
+                position: GpuVec3f::new(p),
+                uv: GpuVec2f::new([0.0, 0.0]),
+                normal: GpuVec3f::new([0.0, 1.0, 0.0]),
+                tangent: Vec4f::new([1.0, 0.0, 0.0, 1.0]),
+            })
+            .collect();
+        Mesh::new("cube", vertices, vec![0, 1, 0])
+    }
+
+    #[test]
+    fn test_ray_aabb() {
+        let min = Vec3f::new([-1.0, -1.0, -1.0]);
+        let max = Vec3f::new([1.0, 1.0, 1.0]);
+
+        let hit = ray_aabb(Vec3f::new([0.0, 0.0, 5.0]), Vec3f::new([0.0, 0.0, -1.0]), min, max);
+        assert_eq!(hit, Some(4.0));
+        let miss = ray_aabb(Vec3f::new([3.0, 0.0, 5.0]), Vec3f::new([0.0, 0.0, -1.0]), min, max);
+        assert_eq!(miss, None);
+        let behind = ray_aabb(Vec3f::new([0.0, 0.0, 5.0]), Vec3f::new([0.0, 0.0, 1.0]), min, max);
+        assert_eq!(behind, None);
+    }
+
+    #[test]
+    fn test_cursor_ray_center() {
+        let vp_data = VPData {
+            view: Matrix4f::indentity(),
+            projection: Matrix4f::perspective(90.0f32.to_radians(), 1.0, 0.1),
+        };
+        let dir = cursor_ray(Vec2f::new([50.0, 50.0]), Vec2f::new([100.0, 100.0]), &vp_data);
+        assert!((dir.z + 1.0).abs() < 1e-6);
+        assert!(dir.x.abs() < 1e-6 && dir.y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_pick_closest_entity() {
+        let mesh = Uuid::new_v4();
+        let mut assets = AssetLibrary::default();
+        assets.meshes.insert(mesh, cube());
+
+        let world = World::new();
+        let at = |z: f64| {
+            (
+                DynamicMesh {
+                    material: Uuid::nil(),
+                    material_name: String::new(),
+                    mesh: Some(mesh),
+                },
+                Transform::new(
+                    Position::from(Vec3d::new([0.0, 0.0, z])),
+                    Vec3f::new([1.0, 1.0, 1.0]),
+                    Quat::new([1.0, 0.0, 0.0, 0.0]),
+                ),
+            )
+        };
+        let far = world.entities.borrow_mut().spawn(at(-20.0));
+        let near = world.entities.borrow_mut().spawn(at(-10.0));
+
+        let dir = Vec3f::new([0.0, 0.0, -1.0]);
+        assert_eq!(pick_entity(&world, &assets, Position::default(), dir), Some(near));
+
+        world.entities.borrow_mut().despawn(near).unwrap();
+        assert_eq!(pick_entity(&world, &assets, Position::default(), dir), Some(far));
+        assert_eq!(pick_entity(&world, &assets, Position::default(), Vec3f::new([0.0, 1.0, 0.0])), None);
+    }
+}
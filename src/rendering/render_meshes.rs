@@ -1,8 +1,13 @@
+use uuid::Uuid;
 use vulkano::{
     buffer::{
         allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
         BufferUsage, Subbuffer,
     },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
     descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     memory::allocator::MemoryTypeFilter,
     pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint},
@@ -10,11 +15,17 @@ use vulkano::{
 
 use crate::{
     asset_library::AssetLibrary,
+    ecs::World,
+    physics::{character_controller::CharacterController, rigidbody::Rigidbody},
     state::State,
     types::{
-        material::{Attachment, Material},
+        animation::AnimationPlayer,
+        material::{error_material, Attachment, Material},
+        shader::ShaderType,
         mesh::DynamicMesh,
         model::ModelComponent,
+        position::Position,
+        texture::resident_texture,
         transform::{ModelData, Transform},
     },
     vulkan::memory::MemoryAllocators,
@@ -48,6 +59,7 @@ fn get_descriptor_sets(
     material: &Material,
     pipeline: &GraphicsPipeline,
     model: &Subbuffer<ModelData>,
+    joints: Option<&Subbuffer<[Matrix4f]>>,
     image_id: usize,
 ) -> Vec<std::sync::Arc<PersistentDescriptorSet>> {
     let vp_set = PersistentDescriptorSet::new(
@@ -55,7 +67,7 @@ fn get_descriptor_sets(
         pipeline.layout().set_layouts().first().unwrap().clone(),
         [WriteDescriptorSet::buffer(
             0,
-            state.renderer.vp_buffers.get(image_id).unwrap().clone(),
+            state.renderer.active_vp_buffer(image_id),
         )],
         [],
     )
@@ -64,7 +76,8 @@ fn get_descriptor_sets(
     let m_set = PersistentDescriptorSet::new(
         state.memory_allocators.descriptor_set_allocator.as_ref(),
         pipeline.layout().set_layouts().get(1).unwrap().clone(),
-        [WriteDescriptorSet::buffer(0, model.clone())],
+        std::iter::once(WriteDescriptorSet::buffer(0, model.clone()))
+            .chain(joints.map(|x| WriteDescriptorSet::buffer(1, x.clone()))),
         [],
     )
     .unwrap();
@@ -80,7 +93,8 @@ fn get_descriptor_sets(
                     .enumerate()
                     .map(|(id, attachment)| match attachment {
                         Attachment::Texture(uuid) => {
-                            let tex = assets.textures.get(uuid).unwrap();
+                            let tex = resident_texture(assets, uuid)
+                                .expect("Default texture not loaded");
                             WriteDescriptorSet::image_view_sampler(
                                 id as u32,
                                 tex.image_view.as_ref().unwrap().clone(),
@@ -89,10 +103,8 @@ fn get_descriptor_sets(
                         }
                         Attachment::DefaultTexture => {
                             let (_, tex) = assets
-                                .textures
-                                .iter()
-                                .find(|(_, x)| x.name == *"default")
-                                .unwrap();
+                                .texture_by_name("default")
+                                .expect("Default texture not loaded");
                             WriteDescriptorSet::image_view_sampler(
                                 id as u32,
                                 tex.image_view.as_ref().unwrap().clone(),
@@ -109,33 +121,29 @@ fn get_descriptor_sets(
         None
     };
 
-    let material_set = if material.parameter_buffer.is_some() {
-        Some(
-            PersistentDescriptorSet::new(
-                state.memory_allocators.descriptor_set_allocator.as_ref(),
-                pipeline
-                    .layout()
-                    .set_layouts()
-                    .get({
-                        if attachment_set.is_some() {
-                            3
-                        } else {
-                            2
-                        }
-                    })
-                    .unwrap()
-                    .clone(),
-                [WriteDescriptorSet::buffer(
-                    0,
-                    material.parameter_buffer.as_ref().unwrap().clone(),
-                )],
-                [],
-            )
-            .unwrap(),
+    let material_set = material.parameter_buffer(image_id).map(|parameter_buffer| {
+        PersistentDescriptorSet::new(
+            state.memory_allocators.descriptor_set_allocator.as_ref(),
+            pipeline
+                .layout()
+                .set_layouts()
+                .get({
+                    if attachment_set.is_some() {
+                        3
+                    } else {
+                        2
+                    }
+                })
+                .unwrap()
+                .clone(),
+            [WriteDescriptorSet::buffer(
+                0,
+                parameter_buffer,
+            )],
+            [],
         )
-    } else {
-        None
-    };
+        .unwrap()
+    });
 
     let mut sets = vec![vp_set, m_set];
     if let Some(attachment_set) = attachment_set {
@@ -148,152 +156,263 @@ fn get_descriptor_sets(
     sets
 }
 
+struct MeshDraw {
+    mesh: Uuid,
+    material: Uuid,
+    model: ModelData,
+    // joint matrices of the skin deforming the mesh
+    joints: Option<Subbuffer<[Matrix4f]>>,
+    distance: f64,
+}
+
+impl MeshDraw {
+    fn new(mesh: Uuid, material: Uuid, transform: &Transform, camera_pos: Position) -> MeshDraw {
+        let relative_position = transform.position - camera_pos;
+        MeshDraw {
+            mesh,
+            material,
+            model: ModelData {
+                translation: Matrix4f::translation(relative_position.into()),
+                rotation: transform.rotation.to_matrix(),
+                scale: Matrix4f::scale(transform.scale),
+            },
+            joints: None,
+            distance: relative_position.length(),
+        }
+    }
+}
+
+fn sort_back_to_front(draws: &mut [MeshDraw]) {
+    draws.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+}
+
+impl MeshRenderingComponent {
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+            StandardCommandBufferAllocator,
+        >,
+        assets: &AssetLibrary,
+        state: &State,
+        image_id: usize,
+        draw: &MeshDraw,
+    ) {
+        let model_buffer = self.model_allocator.allocate_sized().unwrap();
+        *model_buffer.write().unwrap() = draw.model;
+
+        let mesh = assets.meshes.get(&draw.mesh).expect("Mesh not found");
+        // still uploading
+        let (Some(vertex_buffer), Some(index_buffer)) =
+            (mesh.vertex_buffer.as_ref(), mesh.index_buffer.as_ref())
+        else {
+            return;
+        };
+        state
+            .renderer
+            .mesh_cache
+            .borrow_mut()
+            .retain(image_id, (vertex_buffer.clone(), index_buffer.clone()));
+        let material = assets
+            .materials
+            .get(&draw.material)
+            .expect("Material not found");
+        let fallback;
+        let material = if state.renderer.invalid_materials.contains(&draw.material) {
+            fallback = match error_material(assets) {
+                Some(val) => val,
+                None => return,
+            };
+            &fallback
+        } else {
+            material
+        };
+        let pipeline = match state
+            .renderer
+            .pipelines
+            .get(&PipelineIdentifier::from_material(material))
+        {
+            Some(val) => val,
+            None => return,
+        };
+
+        // skinned shaders need the second vertex stream and the joints
+        let skinned = assets
+            .shaders
+            .get(&material.vertex_shader)
+            .is_some_and(|x| matches!(x.shader_type, ShaderType::SkinnedVertex));
+        let skin = if skinned {
+            match (mesh.skin_buffer.as_ref(), draw.joints.as_ref()) {
+                (Some(skin_buffer), Some(joints)) => Some((skin_buffer, joints)),
+                _ => return,
+            }
+        } else {
+            None
+        };
+
+        let descriptor_sets = get_descriptor_sets(
+            state,
+            assets,
+            material,
+            pipeline,
+            &model_buffer,
+            skin.map(|(_, joints)| joints),
+            image_id,
+        );
+
+        {
+            let mut stats = state.renderer.frame_stats.borrow_mut();
+            stats.record_pipeline_bind();
+            stats.record_descriptor_sets(descriptor_sets.len());
+            stats.record_draw(mesh.indices.len() as u32);
+        }
+
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .expect("GP bind faild");
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                descriptor_sets,
+            )
+            .unwrap();
+        builder
+            .bind_index_buffer(index_buffer.as_ref().clone())
+            .expect("Index buffer bind failed");
+        builder
+            .bind_vertex_buffers(0, vertex_buffer.as_ref().clone())
+            .expect("Vertex buffer bind failed");
+        if let Some((skin_buffer, _)) = skin {
+            builder
+                .bind_vertex_buffers(1, skin_buffer.as_ref().clone())
+                .expect("Skin buffer bind failed");
+        }
+        builder
+            .draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)
+            .expect("Draw failed");
+    }
+}
+
 impl RenderingComponent for MeshRenderingComponent {
     fn render(
         &self,
-        mut builder: vulkano::command_buffer::AutoCommandBufferBuilder<
-            vulkano::command_buffer::PrimaryAutoCommandBuffer<
-                vulkano::command_buffer::allocator::StandardCommandBufferAllocator,
-            >,
-            vulkano::command_buffer::allocator::StandardCommandBufferAllocator,
+        mut builder: AutoCommandBufferBuilder<
+            PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+            StandardCommandBufferAllocator,
         >,
-        world: &crate::ecs::World,
-        assets: &crate::asset_library::AssetLibrary,
-        state: &crate::state::State,
+        world: &World,
+        assets: &AssetLibrary,
+        state: &State,
         image_id: usize,
-    ) -> vulkano::command_buffer::AutoCommandBufferBuilder<
-        vulkano::command_buffer::PrimaryAutoCommandBuffer<
-            vulkano::command_buffer::allocator::StandardCommandBufferAllocator,
-        >,
-        vulkano::command_buffer::allocator::StandardCommandBufferAllocator,
+    ) -> AutoCommandBufferBuilder<
+        PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>,
+        StandardCommandBufferAllocator,
     > {
-        let camera_pos = state.renderer.vp_pos;
+        let camera_pos = state.renderer.active_view().position;
         let entities = world.entities.borrow();
-        for (_, (dyn_mesh, transform)) in entities.query::<(&DynamicMesh, &Transform)>().iter() {
-            let model = ModelData {
-                translation: Matrix4f::translation((transform.position - camera_pos).into()),
-                rotation: transform.rotation.to_matrix(),
-                scale: Matrix4f::scale(transform.scale),
-            };
-            let model_buffer = self.model_allocator.allocate_sized().unwrap();
-            *model_buffer.write().unwrap() = model;
-
-            let mesh = assets
-                .meshes
-                .get(&dyn_mesh.mesh.expect("Mesh not set"))
-                .expect("Mesh not found");
-            let vertex_buffer = mesh
-                .vertex_buffer
-                .as_ref()
-                .expect("Vertex buffer not found")
-                .as_ref();
-            let index_buffer = mesh
-                .index_buffer
-                .as_ref()
-                .expect("Index buffer not found")
-                .as_ref();
-            let material = assets
-                .materials
-                .get(&dyn_mesh.material)
-                .expect("Material not found");
-            let pipeline = state
-                .renderer
-                .pipelines
-                .get(&PipelineIdentifier::new(
-                    material.vertex_shader,
-                    material.fragment_shader,
-                    material.rendering_type,
-                ))
-                .unwrap();
-
-            let descriptor_sets =
-                get_descriptor_sets(state, assets, material, pipeline, &model_buffer, image_id);
+        let mut draws = Vec::new();
 
-            builder
-                .bind_pipeline_graphics(pipeline.clone())
-                .expect("GP bind faild");
-            builder
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    pipeline.layout().clone(),
-                    0,
-                    descriptor_sets,
-                )
-                .unwrap();
-            builder
-                .bind_index_buffer(index_buffer.clone())
-                .expect("Index buffer bind failed");
-            builder
-                .bind_vertex_buffers(0, vertex_buffer.clone())
-                .expect("Vertex buffer bind failed");
-            builder
-                .draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)
-                .expect("Draw failed");
+        // rigidbodies and characters are drawn between their last two fixed steps
+        let alpha = state.fixed_step_alpha();
+        let blended = |transform: &Transform, body: (Option<&Rigidbody>, Option<&CharacterController>)| match body {
+            (Some(rigidbody), _) => rigidbody.interpolated(transform, alpha),
+            (None, Some(controller)) => controller.interpolated(transform, alpha),
+            (None, None) => transform.clone(),
+        };
+
+        for (_, (dyn_mesh, transform, rigidbody, controller)) in entities
+            .query::<(&DynamicMesh, &Transform, Option<&Rigidbody>, Option<&CharacterController>)>()
+            .iter()
+        {
+            draws.push(MeshDraw::new(
+                dyn_mesh.mesh.expect("Mesh not set"),
+                dyn_mesh.material,
+                &blended(transform, (rigidbody, controller)),
+                camera_pos,
+            ));
         }
 
-        for (_, (model_comp, transform)) in entities.query::<(&ModelComponent, &Transform)>().iter()
+        for (_, (model_comp, transform, player, rigidbody, controller)) in entities
+            .query::<(&mut ModelComponent, &Transform, Option<&AnimationPlayer>, Option<&Rigidbody>, Option<&CharacterController>)>()
+            .iter()
         {
+            let transform = blended(transform, (rigidbody, controller));
             let model = assets.models.get(&model_comp.model_uuid).unwrap();
-            for (mesh_uuid, material_uuid) in model.meshes_and_materials.iter() {
-                let model = ModelData {
-                    translation: Matrix4f::translation((transform.position - camera_pos).into()),
-                    rotation: transform.rotation.to_matrix(),
-                    scale: Matrix4f::scale(transform.scale),
-                };
-                let model_buffer = self.model_allocator.allocate_sized().unwrap();
-                *model_buffer.write().unwrap() = model;
-
-                let mesh = assets.meshes.get(mesh_uuid).expect("Mesh not found");
-                let vertex_buffer = mesh
-                    .vertex_buffer
-                    .as_ref()
-                    .expect("Vertex buffer not found")
-                    .as_ref();
-                let index_buffer = mesh
-                    .index_buffer
-                    .as_ref()
-                    .expect("Index buffer not found")
-                    .as_ref();
-                let material = assets
-                    .materials
-                    .get(material_uuid)
-                    .expect("Material not found");
-                let pipeline = state
-                    .renderer
-                    .pipelines
-                    .get(&PipelineIdentifier::new(
-                        material.vertex_shader,
-                        material.fragment_shader,
-                        material.rendering_type,
-                    ))
-                    .unwrap();
-
-                let descriptor_sets =
-                    get_descriptor_sets(state, assets, material, pipeline, &model_buffer, image_id);
-
-                builder
-                    .bind_pipeline_graphics(pipeline.clone())
-                    .expect("GP bind faild");
-                builder
-                    .bind_descriptor_sets(
-                        PipelineBindPoint::Graphics,
-                        pipeline.layout().clone(),
-                        0,
-                        descriptor_sets,
-                    )
-                    .unwrap();
-                builder
-                    .bind_index_buffer(index_buffer.clone())
-                    .expect("Index buffer bind failed");
-                builder
-                    .bind_vertex_buffers(0, vertex_buffer.clone())
-                    .expect("Vertex buffer bind failed");
-                builder
-                    .draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)
-                    .expect("Draw failed");
+            let distance = (transform.position - camera_pos).length() as f32;
+            let mut stats = state.renderer.frame_stats.borrow_mut();
+            for primitive in model_comp.lod_primitives(model, distance, state.renderer.lod_hysteresis) {
+                stats.record_lod(primitive.lod);
+                let mut draw = MeshDraw::new(primitive.mesh, primitive.material, &transform.with_node(&primitive.transform), camera_pos);
+                draw.joints = primitive.skin.zip(player).and_then(|(skin, player)| player.joint_buffers.get(skin).cloned());
+                draws.push(draw);
             }
         }
 
+        let (opaque, mut transparent): (Vec<_>, Vec<_>) = draws.into_iter().partition(|draw| {
+            !assets
+                .materials
+                .get(&draw.material)
+                .expect("Material not found")
+                .transparent
+        });
+        sort_back_to_front(&mut transparent);
+
+        for draw in opaque.iter().chain(transparent.iter()) {
+            self.draw(&mut builder, assets, state, image_id, draw);
+        }
+
         builder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}};
+
+    use super::{sort_back_to_front, MeshDraw};
+
+    fn quad_at(z: f64) -> Transform {
+        Transform::new(
+            Position::from(Vec3d::new([0.0, 0.0, z])),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        )
+    }
+
+    #[test]
+    fn test_transparent_order_follows_camera() {
+        let near = Uuid::new_v4();
+        let far = Uuid::new_v4();
+        let quads = [(near, quad_at(1.0)), (far, quad_at(2.0))];
+
+        let camera = Position::from(Vec3d::new([0.0, 0.0, -5.0]));
+        let mut draws: Vec<_> = quads.iter().map(|(id, t)| MeshDraw::new(*id, *id, t, camera)).collect();
+        sort_back_to_front(&mut draws);
+        assert_eq!(draws.iter().map(|x| x.mesh).collect::<Vec<_>>(), vec![far, near]);
+
+        let camera = Position::from(Vec3d::new([0.0, 0.0, 5.0]));
+        let mut draws: Vec<_> = quads.iter().map(|(id, t)| MeshDraw::new(*id, *id, t, camera)).collect();
+        sort_back_to_front(&mut draws);
+        assert_eq!(draws.iter().map(|x| x.mesh).collect::<Vec<_>>(), vec![near, far]);
+    }
+
+    #[test]
+    fn test_transparent_order_far_from_origin() {
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let offset = Vec3d::new([3.5e12, 0.0, 0.0]);
+        let quads = [
+            (a, Transform::new(Position::from(offset + Vec3d::new([0.0, 0.0, 1.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))),
+            (b, Transform::new(Position::from(offset + Vec3d::new([0.0, 0.0, 2.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))),
+        ];
+
+        let camera = Position::from(offset + Vec3d::new([0.0, 0.0, -5.0]));
+        let mut draws: Vec<_> = quads.iter().map(|(id, t)| MeshDraw::new(*id, *id, t, camera)).collect();
+        sort_back_to_front(&mut draws);
+        assert_eq!(draws.iter().map(|x| x.mesh).collect::<Vec<_>>(), vec![b, a]);
+    }
+}
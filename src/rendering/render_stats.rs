@@ -0,0 +1,113 @@
+use std::cell::Cell;
+
+use log::info;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+
+// levels past the last one are counted with it
+pub const TRACKED_LOD_LEVELS: usize = 4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draws: u32,
+    pub triangles: u64,
+    pub pipeline_binds: u32,
+    pub descriptor_set_allocations: u32,
+    pub culled_meshes: u32,
+    // model primitives drawn at every LOD level
+    pub lod_selections: [u32; TRACKED_LOD_LEVELS]
+}
+
+impl RenderStats {
+    pub fn record_draw(&mut self, index_count: u32) {
+        self.draws += 1;
+        self.triangles += (index_count / 3) as u64;
+    }
+
+    pub fn record_pipeline_bind(&mut self) {
+        self.pipeline_binds += 1;
+    }
+
+    pub fn record_descriptor_sets(&mut self, count: usize) {
+        self.descriptor_set_allocations += count as u32;
+    }
+
+    pub fn record_culled(&mut self) {
+        self.culled_meshes += 1;
+    }
+
+    pub fn record_lod(&mut self, level: usize) {
+        self.lod_selections[level.min(TRACKED_LOD_LEVELS - 1)] += 1;
+    }
+}
+
+pub struct RenderStatsLogger {
+    last_log: Cell<f64>
+}
+
+impl RenderStatsLogger {
+    pub fn new() -> RenderStatsLogger {
+        RenderStatsLogger {
+            last_log: Cell::new(0.0)
+        }
+    }
+}
+
+impl Default for RenderStatsLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for RenderStatsLogger {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        self.last_log.set(state.time_struct.unscaled_elapsed);
+    }
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        if state.time_struct.unscaled_elapsed - self.last_log.get() < 1.0 {
+            return;
+        }
+        self.last_log.set(state.time_struct.unscaled_elapsed);
+
+        let stats = state.renderer.last_frame_stats;
+        info!(
+            "draws: {}, triangles: {}, pipeline binds: {}, descriptor sets: {}, culled: {}, lods: {:?}",
+            stats.draws,
+            stats.triangles,
+            stats.pipeline_binds,
+            stats.descriptor_set_allocations,
+            stats.culled_meshes,
+            stats.lod_selections
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderStats;
+
+    #[test]
+    fn test_record_draw_counts_triangles() {
+        let mut stats = RenderStats::default();
+        stats.record_draw(6);
+        stats.record_draw(36);
+        stats.record_pipeline_bind();
+        stats.record_descriptor_sets(4);
+
+        assert_eq!(stats.draws, 2);
+        assert_eq!(stats.triangles, 14);
+        assert_eq!(stats.pipeline_binds, 1);
+        assert_eq!(stats.descriptor_set_allocations, 4);
+        assert_eq!(stats.culled_meshes, 0);
+    }
+
+    #[test]
+    fn test_record_lod_clamps_level() {
+        let mut stats = RenderStats::default();
+        stats.record_lod(0);
+        stats.record_lod(1);
+        stats.record_lod(7);
+        assert_eq!(stats.lod_selections, [1, 1, 0, 1]);
+    }
+}
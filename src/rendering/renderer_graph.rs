@@ -0,0 +1,486 @@
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
+
+use uuid::Uuid;
+use vulkano::{
+    device::Device,
+    format::Format,
+    image::{ImageAspects, ImageLayout, SampleCount},
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        RenderPass, RenderPassCreateInfo, SubpassDependency, SubpassDescription,
+    },
+    sync::{AccessFlags, DependencyFlags, PipelineStages},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    Swapchain,
+    RGBA8,
+    RGBA16F,
+    RGB16,
+    F32,
+    Depth
+}
+
+impl TextureFormat {
+    pub fn into_vulkan_format(self, swapchain_format: Format) -> Format {
+        match self {
+            TextureFormat::Swapchain => swapchain_format,
+            TextureFormat::RGBA8 => Format::R8G8B8A8_UNORM,
+            TextureFormat::RGBA16F => Format::R16G16B16A16_SFLOAT,
+            // three channel formats are rarely usable as color attachments
+            TextureFormat::RGB16 => Format::R16G16B16A16_UNORM,
+            TextureFormat::F32 => Format::R32_SFLOAT,
+            TextureFormat::Depth => Format::D32_SFLOAT
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderGraphError {
+    InvalidConnection(usize),
+    DanglingInput { node: usize, input: usize },
+    Cycle,
+    NoOutput
+}
+
+pub trait RendererNode {
+    fn inputs(&self) -> usize {
+        0
+    }
+
+    fn outputs(&self) -> Vec<TextureFormat> {
+        Vec::new()
+    }
+
+    fn depth(&self) -> Option<TextureFormat> {
+        None
+    }
+
+    fn samples(&self) -> SampleCount {
+        SampleCount::Sample1
+    }
+
+    fn is_output(&self) -> bool {
+        false
+    }
+
+    fn fragment_shader(&self) -> Option<Uuid> {
+        None
+    }
+}
+
+pub struct ColorNode {
+    pub samples: SampleCount
+}
+
+impl RendererNode for ColorNode {
+    fn outputs(&self) -> Vec<TextureFormat> {
+        vec![TextureFormat::Swapchain]
+    }
+
+    fn depth(&self) -> Option<TextureFormat> {
+        Some(TextureFormat::Depth)
+    }
+
+    fn samples(&self) -> SampleCount {
+        self.samples
+    }
+}
+
+pub struct FullscreenPassNode {
+    pub fragment_shader: Uuid
+}
+
+impl RendererNode for FullscreenPassNode {
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    fn outputs(&self) -> Vec<TextureFormat> {
+        vec![TextureFormat::Swapchain]
+    }
+
+    fn fragment_shader(&self) -> Option<Uuid> {
+        Some(self.fragment_shader)
+    }
+}
+
+pub struct OutputNode {}
+
+impl RendererNode for OutputNode {
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    fn is_output(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    pub from: usize,
+    pub output: usize,
+    pub to: usize,
+    pub input: usize
+}
+
+pub struct CompiledRenderGraph {
+    pub create_info: RenderPassCreateInfo,
+    pub output_attachment: u32,
+    pub subpass_nodes: Vec<usize>
+}
+
+fn push_attachment(
+    attachments: &mut Vec<AttachmentDescription>,
+    format: Format,
+    samples: SampleCount,
+    store: bool,
+    layout: ImageLayout
+) -> u32 {
+    attachments.push(AttachmentDescription {
+        format,
+        samples,
+        load_op: AttachmentLoadOp::Clear,
+        store_op: if store { AttachmentStoreOp::Store } else { AttachmentStoreOp::DontCare },
+        initial_layout: ImageLayout::Undefined,
+        final_layout: layout,
+        ..Default::default()
+    });
+    (attachments.len() - 1) as u32
+}
+
+pub struct RenderGraph {
+    pub nodes: Vec<Box<dyn RendererNode>>,
+    pub connections: Vec<Connection>
+}
+
+impl RenderGraph {
+    // the default forward pass with `samples` per pixel
+    pub fn with_samples(samples: SampleCount) -> RenderGraph {
+        let mut graph = RenderGraph::new();
+        let color = graph.add_node(ColorNode { samples });
+        let output = graph.add_node(OutputNode {});
+        graph.connect(color, 0, output, 0);
+        graph
+    }
+
+    pub fn new() -> RenderGraph {
+        RenderGraph {
+            nodes: Vec::new(),
+            connections: Vec::new()
+        }
+    }
+
+    pub fn add_node<T: 'static + RendererNode>(&mut self, node: T) -> usize {
+        self.nodes.push(Box::new(node));
+        self.nodes.len() - 1
+    }
+
+    pub fn connect(&mut self, from: usize, output: usize, to: usize, input: usize) {
+        self.connections.push(Connection { from, output, to, input });
+    }
+
+    fn validate(&self) -> Result<(), RenderGraphError> {
+        for (id, connection) in self.connections.iter().enumerate() {
+            let valid = self.nodes.get(connection.from).is_some_and(|x| connection.output < x.outputs().len())
+                && self.nodes.get(connection.to).is_some_and(|x| connection.input < x.inputs());
+            if !valid {
+                return Err(RenderGraphError::InvalidConnection(id));
+            }
+        }
+
+        for (node_id, node) in self.nodes.iter().enumerate() {
+            for input in 0..node.inputs() {
+                let count = self.connections.iter().filter(|x| x.to == node_id && x.input == input).count();
+                if count != 1 {
+                    return Err(RenderGraphError::DanglingInput { node: node_id, input });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sorted_nodes(&self) -> Result<Vec<usize>, RenderGraphError> {
+        let mut incoming = vec![0; self.nodes.len()];
+        for connection in self.connections.iter() {
+            incoming[connection.to] += 1;
+        }
+
+        let mut queue = (0..self.nodes.len()).filter(|x| incoming[*x] == 0).collect::<VecDeque<_>>();
+        let mut sorted = Vec::new();
+        while let Some(node) = queue.pop_front() {
+            sorted.push(node);
+            for connection in self.connections.iter().filter(|x| x.from == node) {
+                incoming[connection.to] -= 1;
+                if incoming[connection.to] == 0 {
+                    queue.push_back(connection.to);
+                }
+            }
+        }
+
+        if sorted.len() != self.nodes.len() {
+            return Err(RenderGraphError::Cycle);
+        }
+        Ok(sorted)
+    }
+
+    pub fn compile(&self, swapchain_format: Format) -> Result<CompiledRenderGraph, RenderGraphError> {
+        self.validate()?;
+        let sorted = self.sorted_nodes()?;
+
+        let mut attachments: Vec<AttachmentDescription> = Vec::new();
+        let mut subpasses = Vec::new();
+        let mut subpass_nodes = Vec::new();
+        let mut output_attachments: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut output_attachment = None;
+
+        for node_id in sorted.iter().copied() {
+            let node = &self.nodes[node_id];
+            let source = |input: usize| {
+                let connection = self.connections.iter().find(|x| x.to == node_id && x.input == input).unwrap();
+                output_attachments[&(connection.from, connection.output)]
+            };
+
+            if node.is_output() {
+                output_attachment = Some(source(0));
+                continue;
+            }
+
+            let input_attachments = (0..node.inputs())
+                .map(|input| {
+                    let attachment = source(input);
+                    attachments[attachment as usize].final_layout = ImageLayout::ShaderReadOnlyOptimal;
+                    Some(AttachmentReference {
+                        attachment,
+                        layout: ImageLayout::ShaderReadOnlyOptimal,
+                        aspects: ImageAspects::COLOR,
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut color_attachments = Vec::new();
+            let mut color_resolve_attachments = Vec::new();
+            for (output, format) in node.outputs().into_iter().enumerate() {
+                let format = format.into_vulkan_format(swapchain_format);
+                if node.samples() != SampleCount::Sample1 {
+                    let multisampled = push_attachment(&mut attachments, format, node.samples(), true, ImageLayout::ColorAttachmentOptimal);
+                    color_attachments.push(Some(AttachmentReference {
+                        attachment: multisampled,
+                        layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    }));
+                    let resolved = push_attachment(&mut attachments, format, SampleCount::Sample1, true, ImageLayout::ColorAttachmentOptimal);
+                    color_resolve_attachments.push(Some(AttachmentReference {
+                        attachment: resolved,
+                        layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    }));
+                    output_attachments.insert((node_id, output), resolved);
+                } else {
+                    let attachment = push_attachment(&mut attachments, format, SampleCount::Sample1, true, ImageLayout::ColorAttachmentOptimal);
+                    color_attachments.push(Some(AttachmentReference {
+                        attachment,
+                        layout: ImageLayout::ColorAttachmentOptimal,
+                        ..Default::default()
+                    }));
+                    output_attachments.insert((node_id, output), attachment);
+                }
+            }
+
+            let depth_stencil_attachment = node.depth().map(|format| {
+                let attachment = push_attachment(
+                    &mut attachments,
+                    format.into_vulkan_format(swapchain_format),
+                    node.samples(),
+                    false,
+                    ImageLayout::DepthStencilAttachmentOptimal
+                );
+                AttachmentReference {
+                    attachment,
+                    layout: ImageLayout::DepthStencilAttachmentOptimal,
+                    ..Default::default()
+                }
+            });
+
+            subpasses.push(SubpassDescription {
+                input_attachments,
+                color_attachments,
+                color_resolve_attachments,
+                depth_stencil_attachment,
+                ..Default::default()
+            });
+            subpass_nodes.push(node_id);
+        }
+
+        let subpass_of = |node: usize| subpass_nodes.iter().position(|x| *x == node).map(|x| x as u32);
+        let mut dependencies: Vec<SubpassDependency> = Vec::new();
+        for connection in self.connections.iter() {
+            let (Some(src), Some(dst)) = (subpass_of(connection.from), subpass_of(connection.to)) else {
+                continue;
+            };
+            if dependencies.iter().any(|x| x.src_subpass == Some(src) && x.dst_subpass == Some(dst)) {
+                continue;
+            }
+            dependencies.push(SubpassDependency {
+                src_subpass: Some(src),
+                dst_subpass: Some(dst),
+                src_stages: PipelineStages::COLOR_ATTACHMENT_OUTPUT,
+                dst_stages: PipelineStages::FRAGMENT_SHADER,
+                src_access: AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access: AccessFlags::INPUT_ATTACHMENT_READ,
+                dependency_flags: DependencyFlags::BY_REGION,
+                ..Default::default()
+            });
+        }
+
+        Ok(CompiledRenderGraph {
+            create_info: RenderPassCreateInfo {
+                attachments,
+                subpasses,
+                dependencies,
+                ..Default::default()
+            },
+            output_attachment: output_attachment.ok_or(RenderGraphError::NoOutput)?,
+            subpass_nodes
+        })
+    }
+
+    pub fn get_render_pass(&self, device: Arc<Device>, swapchain_format: Format) -> Result<(Arc<RenderPass>, CompiledRenderGraph), RenderGraphError> {
+        let compiled = self.compile(swapchain_format)?;
+        Ok((
+            RenderPass::new(device, compiled.create_info.clone()).expect("Failed to create render pass"),
+            compiled
+        ))
+    }
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        RenderGraph::with_samples(SampleCount::Sample8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use vulkano::{format::Format, image::{ImageLayout, SampleCount}};
+
+    use super::{ColorNode, FullscreenPassNode, OutputNode, RenderGraph, RenderGraphError, RendererNode, TextureFormat};
+
+    struct PassthroughNode {}
+
+    impl RendererNode for PassthroughNode {
+        fn inputs(&self) -> usize {
+            1
+        }
+
+        fn outputs(&self) -> Vec<TextureFormat> {
+            vec![TextureFormat::RGBA8]
+        }
+    }
+
+    #[test]
+    fn test_default_graph_matches_forward_pass() {
+        let compiled = RenderGraph::default().compile(Format::B8G8R8A8_SRGB).unwrap();
+        let info = compiled.create_info;
+
+        assert_eq!(info.attachments.len(), 3);
+        assert_eq!(info.attachments[0].samples, SampleCount::Sample8);
+        assert_eq!(info.attachments[1].samples, SampleCount::Sample1);
+        assert_eq!(info.attachments[1].format, Format::B8G8R8A8_SRGB);
+        assert_eq!(info.attachments[2].format, Format::D32_SFLOAT);
+        assert_eq!(info.subpasses.len(), 1);
+        assert_eq!(info.subpasses[0].color_attachments[0].as_ref().unwrap().attachment, 0);
+        assert_eq!(info.subpasses[0].color_resolve_attachments[0].as_ref().unwrap().attachment, 1);
+        assert_eq!(info.subpasses[0].depth_stencil_attachment.as_ref().unwrap().attachment, 2);
+        assert!(info.dependencies.is_empty());
+        assert_eq!(compiled.output_attachment, 1);
+    }
+
+    #[test]
+    fn test_single_sample_graph() {
+        let mut graph = RenderGraph::new();
+        let color = graph.add_node(ColorNode { samples: SampleCount::Sample1 });
+        let output = graph.add_node(OutputNode {});
+        graph.connect(color, 0, output, 0);
+        let compiled = graph.compile(Format::B8G8R8A8_SRGB).unwrap();
+
+        assert_eq!(compiled.create_info.attachments.len(), 2);
+        assert!(compiled.create_info.subpasses[0].color_resolve_attachments.is_empty());
+        assert_eq!(compiled.output_attachment, 0);
+    }
+
+    #[test]
+    fn test_fullscreen_pass_reads_input_attachment() {
+        let mut graph = RenderGraph::new();
+        let color = graph.add_node(ColorNode { samples: SampleCount::Sample8 });
+        let post = graph.add_node(FullscreenPassNode { fragment_shader: Uuid::new_v4() });
+        let output = graph.add_node(OutputNode {});
+        graph.connect(color, 0, post, 0);
+        graph.connect(post, 0, output, 0);
+        let compiled = graph.compile(Format::B8G8R8A8_SRGB).unwrap();
+        let info = compiled.create_info;
+
+        assert_eq!(info.subpasses.len(), 2);
+        assert_eq!(compiled.subpass_nodes, vec![color, post]);
+        assert!(info.subpasses[0].input_attachments.is_empty());
+        assert_eq!(info.subpasses[1].input_attachments.len(), 1);
+
+        let input = info.subpasses[1].input_attachments[0].as_ref().unwrap();
+        assert_eq!(input.attachment, 1);
+        assert_eq!(input.layout, ImageLayout::ShaderReadOnlyOptimal);
+        assert_eq!(info.attachments[1].samples, SampleCount::Sample1);
+
+        assert_eq!(info.attachments.len(), 4);
+        assert_eq!(compiled.output_attachment, 3);
+        assert_eq!(info.dependencies.len(), 1);
+        assert_eq!(info.dependencies[0].src_subpass, Some(0));
+        assert_eq!(info.dependencies[0].dst_subpass, Some(1));
+    }
+
+    #[test]
+    fn test_texture_formats() {
+        assert_eq!(TextureFormat::F32.into_vulkan_format(Format::B8G8R8A8_SRGB), Format::R32_SFLOAT);
+        assert_eq!(TextureFormat::RGB16.into_vulkan_format(Format::B8G8R8A8_SRGB), Format::R16G16B16A16_UNORM);
+        assert_eq!(TextureFormat::Swapchain.into_vulkan_format(Format::B8G8R8A8_SRGB), Format::B8G8R8A8_SRGB);
+    }
+
+    #[test]
+    fn test_dangling_input_rejected() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(ColorNode { samples: SampleCount::Sample1 });
+        graph.add_node(OutputNode {});
+
+        assert_eq!(
+            graph.compile(Format::B8G8R8A8_SRGB).err(),
+            Some(RenderGraphError::DanglingInput { node: 1, input: 0 })
+        );
+    }
+
+    #[test]
+    fn test_cycle_rejected() {
+        let mut graph = RenderGraph::new();
+        let a = graph.add_node(PassthroughNode {});
+        let b = graph.add_node(PassthroughNode {});
+        let output = graph.add_node(OutputNode {});
+        graph.connect(a, 0, b, 0);
+        graph.connect(b, 0, a, 0);
+        graph.connect(b, 0, output, 0);
+
+        assert_eq!(graph.compile(Format::B8G8R8A8_SRGB).err(), Some(RenderGraphError::Cycle));
+    }
+
+    #[test]
+    fn test_invalid_connection_rejected() {
+        let mut graph = RenderGraph::new();
+        let color = graph.add_node(ColorNode { samples: SampleCount::Sample1 });
+        let output = graph.add_node(OutputNode {});
+        graph.connect(color, 1, output, 0);
+
+        assert_eq!(graph.compile(Format::B8G8R8A8_SRGB).err(), Some(RenderGraphError::InvalidConnection(0)));
+    }
+}
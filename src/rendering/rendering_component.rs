@@ -3,6 +3,16 @@ use vulkano::command_buffer::{allocator::StandardCommandBufferAllocator, AutoCom
 use crate::{asset_library::AssetLibrary, ecs::World, state::State};
 
 pub trait RenderingComponent {
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    // components rendered per view are replayed for every camera viewport,
+    // the rest are rendered once over the whole window
+    fn per_view(&self) -> bool {
+        true
+    }
+
     fn render(
         &self,
         builder:
@@ -0,0 +1,11 @@
+#version 450
+
+// Built-in vertex shader of fullscreen passes, covers the screen with a single triangle drawn from 3 vertices
+// without a vertex buffer. Outputs the screen uv at location 0.
+
+layout(location = 0) out vec2 out_uv;
+
+void main() {
+    out_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(out_uv * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -1,14 +1,158 @@
+use std::ops::{Deref, DerefMut};
+
+use winit::keyboard::Key;
+
 use crate::{
-    input::InputManager, rendering::{Renderer, Window}, vulkan::{context::VulkanContext, memory::MemoryAllocators}
+    asset_loading::AssetLoading, diagnostics::Diagnostics, engine_config::{AssetPaths, FullscreenMode}, events::EngineEvent, frame_pacer::FixedTimestep, input::InputManager, physics::{collider::Collision, collision_handler::TriggerEvent, settings::PhysicsSettings}, rendering::{debug_lines::DebugLines, CameraView, Renderer, Window}, time::Time, types::{camera::Camera, geometry::Ray, position::Position, vectors::Vec2f}, ui::ui_focus::UiFocus, vulkan::{context::VulkanContext, memory::MemoryAllocators}
 };
 
+// a part of the state that needs a window and a device, run_headless leaves them out, using one there
+// panics, systems that also run headless check with `get`
+pub struct Windowed<T>(Option<T>);
+
+impl<T> Windowed<T> {
+    pub fn new(value: T) -> Windowed<T> {
+        Windowed(Some(value))
+    }
+
+    pub fn headless() -> Windowed<T> {
+        Windowed(None)
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.0.as_ref()
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.0.as_mut()
+    }
+}
+
+impl<T> Deref for Windowed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0.as_ref().expect("not available when running headless")
+    }
+}
+
+impl<T> DerefMut for Windowed<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0.as_mut().expect("not available when running headless")
+    }
+}
+
 pub struct State {
-    pub window: Window,
+    pub window: Windowed<Window>,
     pub input: InputManager,
-    pub vulkan_context: VulkanContext,
-    pub memory_allocators: MemoryAllocators,
-    pub renderer: Renderer,
-    pub time: f64,
-    pub delta_time: f64,
-    pub physics_time_scale: f32
+    pub vulkan_context: Windowed<VulkanContext>,
+    pub memory_allocators: Windowed<MemoryAllocators>,
+    pub renderer: Windowed<Renderer>,
+    pub time_struct: Time,
+    pub physics_time_scale: f32,
+    pub fixed_timestep: FixedTimestep,
+    pub physics: PhysicsSettings,
+    // overlaps with trigger colliders during this frame's fixed steps
+    pub trigger_events: Vec<TriggerEvent>,
+    // collisions resolved by the last fixed step
+    pub contacts: Vec<Collision>,
+    // candidate pairs the broadphase found in the last fixed step, most of them don't touch
+    pub pair_count: u32,
+    // drawn by the next frame rendered, then cleared
+    pub debug_lines: DebugLines,
+    // raised by the window since the last frame, cleared after it
+    pub events: Vec<EngineEvent>,
+    pub target_frame_rate: Option<f32>,
+    // the window has keyboard focus, gameplay can pause on losing it
+    pub focused: bool,
+    // the window is minimized or fully covered, nothing is drawn meanwhile
+    pub occluded: bool,
+    pub run_when_unfocused: bool,
+    pub background_frame_rate: f32,
+    pub asset_reload_requests: Vec<String>,
+    // where run_with_config read the assets from, for loading more of them later
+    pub asset_paths: AssetPaths,
+    pub asset_loading: Windowed<AssetLoading>,
+    // what the last frame cost, see DiagnosticsOverlay
+    pub diagnostics: Diagnostics,
+    // set by request_exit
+    pub exit_requested: bool,
+    pub quit_key: Option<Key>,
+    pub ui_focus: UiFocus
+}
+
+impl State {
+    pub fn is_headless(&self) -> bool {
+        self.window.get().is_none()
+    }
+
+    // unfocused or occluded, updates slow down to background_frame_rate unless run_when_unfocused is set
+    pub fn in_background(&self) -> bool {
+        !self.focused || self.occluded
+    }
+
+    // the frame that's running finishes, then the on_exit hooks run and the engine stops, asked for
+    // during on_start it stops before the first frame
+    pub fn request_exit(&mut self) {
+        self.exit_requested = true;
+    }
+
+    #[deprecated(note = "use time_struct.elapsed")]
+    pub fn time(&self) -> f64 {
+        self.time_struct.elapsed
+    }
+
+    #[deprecated(note = "use time_struct.delta")]
+    pub fn delta_time(&self) -> f64 {
+        self.time_struct.delta
+    }
+
+    // reloads the texture, shader, material or model with this name between frames (dev_tools only)
+    pub fn request_asset_reload(&mut self, name: &str) {
+        self.asset_reload_requests.push(name.to_string());
+    }
+
+    // how far the frame is between the last two fixed steps, rigidbodies are drawn blended by it
+    pub fn fixed_step_alpha(&self) -> f32 {
+        self.fixed_timestep.alpha(self.physics.timestep)
+    }
+
+    // fraction of queued texture and mesh uploads that are resident, nothing is uploaded headless
+    pub fn asset_loading_progress(&self) -> f32 {
+        self.asset_loading.get().map_or(1.0, |x| x.progress())
+    }
+
+    // the swapchain is recreated next frame even where the platform doesn't send a Resized event for it
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode) {
+        self.window.set_fullscreen(mode);
+        self.renderer.window_resized = true;
+    }
+
+    // in logical pixels like the cursor position
+    fn window_size(&self) -> Vec2f {
+        let size = self.window.window_handle.inner_size().to_logical::<f32>(self.window.window_handle.scale_factor());
+        Vec2f::new([size.width, size.height])
+    }
+
+    // the view's viewport in logical pixels
+    fn view_rect(&self, view: &CameraView) -> (Vec2f, Vec2f) {
+        let window_size = self.window_size();
+        let (offset, extent) = view.viewport.to_pixels([window_size.x, window_size.y]);
+        (Vec2f::new(offset), Vec2f::new(extent))
+    }
+
+    // the ray under the cursor through the camera whose viewport it's over
+    pub fn screen_to_ray(&self, cursor: Vec2f) -> Ray {
+        let local = cursor / self.window_size();
+        let view = self.renderer.views.iter().find(|x| x.viewport.contains(local)).unwrap_or(&self.renderer.views[0]);
+        let (offset, extent) = self.view_rect(view);
+        Camera::screen_to_ray(cursor - offset, extent, &view.vp_data.view, &view.vp_data.projection, view.position)
+    }
+
+    // where `pos` shows up through the active camera, in logical pixels from the window's top left corner
+    pub fn world_to_screen(&self, pos: Position) -> Option<Vec2f> {
+        let view = &self.renderer.views[0];
+        let (offset, extent) = self.view_rect(view);
+        Camera::world_to_screen(pos, extent, &view.vp_data.view, &view.vp_data.projection, view.position).map(|x| x + offset)
+    }
 }
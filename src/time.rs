@@ -0,0 +1,116 @@
+// frames smoothed_delta is averaged over, roughly
+const SMOOTHED_FRAMES: f64 = 30.0;
+
+// the clock systems read, `delta` and `elapsed` are game time which follows time_scale and stops while paused,
+// the unscaled ones keep going for ui, input and anything else that shouldn't freeze with the game
+#[derive(Debug, Clone)]
+pub struct Time {
+    // frames run so far, counting the current one
+    pub frame_count: u64,
+    pub delta: f64,
+    // unscaled_delta averaged over the last few dozen frames, steadier for showing the frame time
+    pub smoothed_delta: f64,
+    pub unscaled_delta: f64,
+    pub elapsed: f64,
+    pub unscaled_elapsed: f64,
+    pub time_scale: f64,
+    pub paused: bool,
+    // longest frame counted, the rest of a hitch is dropped so it doesn't cascade into huge steps
+    pub max_delta: f64,
+}
+
+impl Time {
+    pub fn new() -> Time {
+        Time {
+            frame_count: 0,
+            delta: 0.0,
+            smoothed_delta: 0.0,
+            unscaled_delta: 0.0,
+            elapsed: 0.0,
+            unscaled_elapsed: 0.0,
+            time_scale: 1.0,
+            paused: false,
+            max_delta: 0.25,
+        }
+    }
+
+    // starts a frame that took `real_delta` seconds since the last one
+    pub fn advance(&mut self, real_delta: f64) {
+        self.advance_paced(real_delta, 0.0);
+    }
+
+    // like advance for a loop held to `frame_interval` seconds a frame, frames that slow on purpose aren't clamped
+    pub fn advance_paced(&mut self, real_delta: f64, frame_interval: f64) {
+        let max_delta = self.max_delta.max(frame_interval);
+        let unscaled_delta = if real_delta.is_finite() { real_delta.clamp(0.0, max_delta) } else { 0.0 };
+        self.smoothed_delta = if self.frame_count == 0 {
+            unscaled_delta
+        } else {
+            self.smoothed_delta + (unscaled_delta - self.smoothed_delta) * 2.0 / (SMOOTHED_FRAMES + 1.0)
+        };
+        self.frame_count += 1;
+        self.unscaled_delta = unscaled_delta;
+        self.unscaled_elapsed += unscaled_delta;
+        self.delta = if self.paused { 0.0 } else { unscaled_delta * self.time_scale.max(0.0) };
+        self.elapsed += self.delta;
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use super::Time;
+
+    #[test]
+    fn test_scale_pause_and_clamp() {
+        let mut time = Time::new();
+        time.advance(0.1);
+        time.time_scale = 0.5;
+        time.advance(0.1);
+        assert_relative_eq!(time.delta, 0.05);
+        assert_relative_eq!(time.elapsed, 0.15);
+
+        time.paused = true;
+        time.advance(0.1);
+        assert_eq!(time.delta, 0.0);
+        assert_relative_eq!(time.elapsed, 0.15);
+        assert_relative_eq!(time.unscaled_elapsed, 0.3);
+        assert_eq!(time.frame_count, 3);
+
+        // a two second stall counts as max_delta
+        time.paused = false;
+        time.time_scale = 1.0;
+        time.advance(2.0);
+        assert_eq!(time.unscaled_delta, time.max_delta);
+        time.advance(f64::NAN);
+        assert_eq!(time.delta, 0.0);
+
+        // a throttled loop at two frames a second
+        time.advance_paced(0.5, 0.5);
+        assert_eq!(time.delta, 0.5);
+    }
+
+    #[test]
+    fn test_smoothed_delta_follows_frame_time() {
+        let mut time = Time::new();
+        for _ in 0..10 {
+            time.advance(1.0 / 60.0);
+        }
+        assert_relative_eq!(time.smoothed_delta, 1.0 / 60.0);
+
+        // one slow frame barely moves it, a lasting change gets there
+        time.advance(0.1);
+        assert!(time.smoothed_delta < 0.025);
+        for _ in 0..300 {
+            time.advance(1.0 / 30.0);
+        }
+        assert_relative_eq!(time.smoothed_delta, 1.0 / 30.0, epsilon = 1e-6);
+    }
+}
@@ -1,11 +1,26 @@
+pub mod aabb;
+pub mod geometry;
 pub mod matrices;
 pub mod transform;
 pub mod vectors;
 pub mod camera;
+pub mod camera_controller;
+#[cfg(feature = "dev_tools")]
+pub mod debug_fly_camera;
 pub mod shader;
+#[cfg(feature = "dev_tools")]
+pub mod shader_compiler;
+pub mod staging_buffer;
 pub mod mesh;
+pub mod mesh_optimizer;
 pub mod material;
 pub mod texture;
+pub mod font;
 pub mod model;
 pub mod quaternion;
 pub mod position;
+pub mod animation;
+#[cfg(feature = "nalgebra-interop")]
+pub mod nalgebra_interop;
+#[cfg(feature = "glam-interop")]
+pub mod glam_interop;
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{matrices::Matrix4f, vectors::Vec3f};
+
+// axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+fn min(a: Vec3f, b: Vec3f) -> Vec3f {
+    Vec3f::new([a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)])
+}
+
+fn max(a: Vec3f, b: Vec3f) -> Vec3f {
+    Vec3f::new([a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)])
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // None for no points
+    pub fn from_points(points: impl IntoIterator<Item = Vec3f>) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb::new(first, first), |aabb, x| Aabb::new(min(aabb.min, x), max(aabb.max, x))))
+    }
+
+    pub fn center(&self) -> Vec3f {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn corners(&self) -> [Vec3f; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| Vec3f::new([
+            if i & 1 == 0 { self.min.x } else { self.max.x },
+            if i & 2 == 0 { self.min.y } else { self.max.y },
+            if i & 4 == 0 { self.min.z } else { self.max.z },
+        ]))
+    }
+
+    // the box around this one after `matrix`, translation included
+    pub fn transform(&self, matrix: &Matrix4f) -> Aabb {
+        let translation = Vec3f::new([matrix.0[3][0], matrix.0[3][1], matrix.0[3][2]]);
+        Aabb::from_points(self.corners().map(|x| matrix.vec_mul(x) + translation)).unwrap()
+    }
+
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb::new(min(self.min, other.min), max(self.max, other.max))
+    }
+
+    // points on the surface count as inside
+    pub fn contains_point(&self, point: Vec3f) -> bool {
+        self.min.x <= point.x && point.x <= self.max.x &&
+        self.min.y <= point.y && point.y <= self.max.y &&
+        self.min.z <= point.z && point.z <= self.max.z
+    }
+
+    // touching boxes count as overlapping
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x &&
+        self.min.y <= other.max.y && other.min.y <= self.max.y &&
+        self.min.z <= other.max.z && other.min.z <= self.max.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{matrices::Matrix4f, vectors::Vec3f};
+
+    use super::Aabb;
+
+    fn unit() -> Aabb {
+        Aabb::new(Vec3f::new([-1.0, -1.0, -1.0]), Vec3f::new([1.0, 1.0, 1.0]))
+    }
+
+    fn close(a: Vec3f, b: Vec3f) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn test_from_points() {
+        assert_eq!(Aabb::from_points([]), None);
+        let aabb = Aabb::from_points([Vec3f::new([1.0, -2.0, 0.5]), Vec3f::new([-1.0, 2.0, 0.0])]).unwrap();
+        assert_eq!(aabb, Aabb::new(Vec3f::new([-1.0, -2.0, 0.0]), Vec3f::new([1.0, 2.0, 0.5])));
+    }
+
+    #[test]
+    fn test_transform() {
+        let moved = unit().transform(&(Matrix4f::translation(Vec3f::new([2.0, 0.0, 0.0])) * Matrix4f::scale(Vec3f::new([1.0, 3.0, 1.0]))));
+        assert!(close(moved.min, Vec3f::new([1.0, -3.0, -1.0])));
+        assert!(close(moved.max, Vec3f::new([3.0, 3.0, 1.0])));
+
+        // a box turned by 45 degrees needs a wider box around it
+        let turned = unit().transform(&Matrix4f::rotation_y(std::f32::consts::FRAC_PI_4));
+        let half_diagonal = 2.0f32.sqrt();
+        assert!(close(turned.min, Vec3f::new([-half_diagonal, -1.0, -half_diagonal])));
+        assert!(close(turned.max, Vec3f::new([half_diagonal, 1.0, half_diagonal])));
+    }
+
+    #[test]
+    fn test_merge() {
+        let other = Aabb::new(Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([4.0, 0.5, 0.5]));
+        let merged = unit().merge(&other);
+        assert_eq!(merged, Aabb::new(Vec3f::new([-1.0, -1.0, -1.0]), Vec3f::new([4.0, 1.0, 1.0])));
+        assert_eq!(merged, other.merge(&unit()));
+    }
+
+    #[test]
+    fn test_contains_point() {
+        assert!(unit().contains_point(Vec3f::new([0.0, 0.0, 0.0])));
+        assert!(unit().contains_point(Vec3f::new([1.0, -1.0, 0.5])));
+        assert!(!unit().contains_point(Vec3f::new([1.01, 0.0, 0.0])));
+        assert!(!unit().contains_point(Vec3f::new([0.0, 0.0, -2.0])));
+    }
+}
@@ -0,0 +1,475 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferUsage, Subbuffer,
+    },
+    memory::allocator::MemoryTypeFilter,
+};
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+    types::{
+        matrices::Matrix4f,
+        model::{Model, ModelComponent, NodeTransform},
+        quaternion::Quat,
+        vectors::Vec3f,
+    },
+    vulkan::memory::MemoryAllocators,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    Linear,
+    Step,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationChannel {
+    // index of the animated node in the order of Model::node_transforms
+    pub node: usize,
+    pub property: ChannelProperty,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    // rotations as [x, y, z, w], translations and scales leave the last component unused
+    pub values: Vec<[f32; 4]>,
+}
+
+impl AnimationChannel {
+    fn sample(&self, time: f32) -> Option<[f32; 4]> {
+        let next = self.times.partition_point(|x| *x <= time).min(self.values.len());
+        if next == 0 {
+            return self.values.first().copied();
+        }
+        if next == self.values.len() {
+            return self.values.last().copied();
+        }
+
+        let prev = next - 1;
+        if self.interpolation == Interpolation::Step {
+            return Some(self.values[prev]);
+        }
+
+        let t = (time - self.times[prev]) / (self.times[next] - self.times[prev]);
+        Some(match self.property {
+            ChannelProperty::Rotation => {
+                let q = slerp(Quat::new_sl(self.values[prev]), Quat::new_sl(self.values[next]), t);
+                [q.x, q.y, q.z, q.w]
+            }
+            _ => std::array::from_fn(|i| self.values[prev][i] + (self.values[next][i] - self.values[prev][i]) * t),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl AnimationClip {
+    // overwrites the animated properties of `pose`, everything else keeps its value
+    pub fn sample(&self, time: f32, pose: &mut [NodeTransform]) {
+        for channel in self.channels.iter() {
+            let (Some(transform), Some(value)) = (pose.get_mut(channel.node), channel.sample(time)) else {
+                continue;
+            };
+            match channel.property {
+                ChannelProperty::Translation => transform.0 = Vec3f::new([value[0], value[1], value[2]]),
+                ChannelProperty::Rotation => transform.1 = Quat::new_sl(value),
+                ChannelProperty::Scale => transform.2 = Vec3f::new([value[0], value[1], value[2]]),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Skin {
+    // node indices in the order of Model::node_transforms
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Matrix4f>,
+}
+
+pub(crate) fn slerp(a: Quat, b: Quat, t: f32) -> Quat {
+    let mut dot = a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w;
+    // q and -q are the same rotation, take the shorter way
+    let b = if dot < 0.0 {
+        dot = -dot;
+        b * -1.0
+    } else {
+        b
+    };
+
+    if dot > 0.9995 {
+        return (a * (1.0 - t) + b * t).normalize();
+    }
+    let theta = dot.acos();
+    let sin = theta.sin();
+    a * (((1.0 - t) * theta).sin() / sin) + b * ((t * theta).sin() / sin)
+}
+
+fn blend(from: &[NodeTransform], to: &[NodeTransform], t: f32) -> Vec<NodeTransform> {
+    from.iter()
+        .zip(to.iter())
+        .map(|(a, b)| (a.0 + (b.0 - a.0) * t, slerp(a.1, b.1, t), a.2 + (b.2 - a.2) * t))
+        .collect()
+}
+
+fn node_matrix(transform: &NodeTransform) -> Matrix4f {
+    Matrix4f::translation(transform.0) * transform.1.to_matrix() * Matrix4f::scale(transform.2)
+}
+
+// for every skin of the model, the matrices moving its vertices from the bind pose into `pose`
+pub fn joint_matrices(model: &Model, pose: &[NodeTransform]) -> Vec<Vec<Matrix4f>> {
+    let parents = model.node_parents();
+    let mut world: Vec<Matrix4f> = Vec::with_capacity(pose.len());
+    for (transform, parent) in pose.iter().zip(parents.iter()) {
+        let local = node_matrix(transform);
+        world.push(match parent {
+            Some(parent) => world[*parent] * local,
+            None => local,
+        });
+    }
+
+    model
+        .skins
+        .iter()
+        .map(|skin| {
+            skin.joints
+                .iter()
+                .zip(skin.inverse_bind_matrices.iter())
+                .map(|(joint, inverse_bind)| world.get(*joint).copied().unwrap_or(Matrix4f::indentity()) * *inverse_bind)
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PlayingClip {
+    clip: usize,
+    time: f32,
+}
+
+impl PlayingClip {
+    fn advance(&mut self, clip: &AnimationClip, delta_time: f32, looping: bool) {
+        self.time += delta_time;
+        if looping && clip.duration > 0.0 {
+            self.time = self.time.rem_euclid(clip.duration);
+        } else {
+            self.time = self.time.clamp(0.0, clip.duration);
+        }
+    }
+}
+
+// plays the animations of the entity's model, the pose replaces the node transforms of its ModelComponent
+// and skinned meshes of the model are only drawn on entities with a player
+pub struct AnimationPlayer {
+    pub speed: f32,
+    pub looping: bool,
+    current: Option<PlayingClip>,
+    previous: Option<PlayingClip>,
+    crossfade: f32,
+    fade_elapsed: f32,
+    requested: Option<(String, f32)>,
+    pub(crate) joint_buffers: Vec<Subbuffer<[Matrix4f]>>,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        AnimationPlayer::new()
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new() -> AnimationPlayer {
+        AnimationPlayer {
+            speed: 1.0,
+            looping: true,
+            current: None,
+            previous: None,
+            crossfade: 0.0,
+            fade_elapsed: 0.0,
+            requested: None,
+            joint_buffers: Vec::new(),
+        }
+    }
+
+    // switches to the clip over `crossfade` seconds, 0 cuts immediately
+    pub fn play(&mut self, name: &str, crossfade: f32) {
+        self.requested = Some((name.to_string(), crossfade));
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.previous = None;
+        self.requested = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.current.is_some() || self.requested.is_some()
+    }
+
+    // advances the playing clips and returns the blended pose, None while nothing plays
+    pub fn update(&mut self, model: &Model, rest_pose: &[NodeTransform], delta_time: f32) -> Option<Vec<NodeTransform>> {
+        if let Some((name, crossfade)) = self.requested.take() {
+            match model.animations.iter().position(|x| x.name == name) {
+                Some(clip) => {
+                    self.previous = if crossfade > 0.0 { self.current.take() } else { None };
+                    self.current = Some(PlayingClip { clip, time: 0.0 });
+                    self.crossfade = crossfade;
+                    self.fade_elapsed = 0.0;
+                }
+                None => error!("Animation {} not found in {}", name, model.name),
+            }
+        }
+
+        let current = self.current.as_mut()?;
+        let clip = model.animations.get(current.clip)?;
+        current.advance(clip, delta_time, self.looping);
+        let mut pose = rest_pose.to_vec();
+        clip.sample(current.time, &mut pose);
+
+        let Some(previous) = self.previous.as_mut() else {
+            return Some(pose);
+        };
+        self.fade_elapsed += delta_time.abs();
+        let weight = self.fade_elapsed / self.crossfade;
+        let Some(previous_clip) = model.animations.get(previous.clip).filter(|_| weight < 1.0) else {
+            self.previous = None;
+            return Some(pose);
+        };
+        previous.advance(previous_clip, delta_time, self.looping);
+        let mut previous_pose = rest_pose.to_vec();
+        previous_clip.sample(previous.time, &mut previous_pose);
+        Some(blend(&previous_pose, &pose, weight))
+    }
+}
+
+pub struct AnimationSystem {
+    joint_allocator: SubbufferAllocator,
+}
+
+impl AnimationSystem {
+    pub fn new(allocators: &MemoryAllocators) -> AnimationSystem {
+        AnimationSystem {
+            joint_allocator: SubbufferAllocator::new(
+                allocators.standard_memory_allocator.clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::STORAGE_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+}
+
+impl System for AnimationSystem {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        advance_players(world, assets, state);
+        let mut entities = world.entities.borrow_mut();
+
+        for (_, (player, model_comp)) in entities.query_mut::<(&mut AnimationPlayer, &ModelComponent)>() {
+            let Some(model) = assets.models.get(&model_comp.model_uuid) else {
+                continue;
+            };
+            let pose: Vec<NodeTransform> = model_comp.node_transforms.iter().map(|(_, x)| *x).collect();
+            player.joint_buffers = joint_matrices(model, &pose)
+                .into_iter()
+                .map(|matrices| {
+                    let buffer = self.joint_allocator.allocate_slice(matrices.len().max(1) as u64).unwrap();
+                    if matrices.is_empty() {
+                        buffer.write().unwrap()[0] = Matrix4f::indentity();
+                    } else {
+                        buffer.write().unwrap().copy_from_slice(&matrices);
+                    }
+                    buffer
+                })
+                .collect();
+        }
+    }
+}
+
+// steps the players by this frame's game time and poses their models' node transforms
+fn advance_players(world: &World, assets: &AssetLibrary, state: &State) {
+    let delta_time = state.time_struct.delta as f32;
+    let mut entities = world.entities.borrow_mut();
+
+    for (_, (player, model_comp)) in entities.query_mut::<(&mut AnimationPlayer, &mut ModelComponent)>() {
+        let Some(model) = assets.models.get(&model_comp.model_uuid) else {
+            continue;
+        };
+        let rest: Vec<NodeTransform> = model.node_transforms().into_iter().map(|(_, x)| x).collect();
+        // the model was reloaded with different nodes
+        if model_comp.node_transforms.len() != rest.len() {
+            model_comp.node_transforms = model.node_transforms();
+        }
+
+        if let Some(pose) = player.update(model, &rest, delta_time * player.speed) {
+            for ((_, transform), posed) in model_comp.node_transforms.iter_mut().zip(pose) {
+                *transform = posed;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use uuid::Uuid;
+
+    use crate::{
+        asset_library::AssetLibrary,
+        ecs::World,
+        headless_state,
+        types::{
+            matrices::Matrix4f,
+            model::{identity_transform, Model, ModelComponent, ModelNode, NodeTransform},
+            quaternion::Quat,
+            vectors::Vec3f,
+        },
+    };
+
+    use super::{
+        advance_players, joint_matrices, AnimationChannel, AnimationClip, AnimationPlayer, ChannelProperty, Interpolation, Skin,
+    };
+
+    const S: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+    fn assert_matrix(a: &Matrix4f, b: [[f32; 4]; 4]) {
+        for (col_a, col_b) in a.0.iter().zip(b.iter()) {
+            for (x, y) in col_a.iter().zip(col_b.iter()) {
+                assert_relative_eq!(*x, *y, epsilon = 1e-5);
+            }
+        }
+    }
+
+    // a root joint turning 90 degrees about z over a second and a bone one unit above it
+    // that steps up another unit halfway through
+    fn arm() -> Model {
+        let mut root = ModelNode::new("Root".to_string(), identity_transform(), Vec::new());
+        root.children.push(ModelNode::new(
+            "Bone".to_string(),
+            (Vec3f::new([0.0, 1.0, 0.0]), Quat::new([1.0, 0.0, 0.0, 0.0]), Vec3f::new([1.0, 1.0, 1.0])),
+            Vec::new(),
+        ));
+
+        let mut model = Model::new("arm.gltf".to_string());
+        model.nodes.push(root);
+        model.skins.push(Skin {
+            joints: vec![0, 1],
+            inverse_bind_matrices: vec![Matrix4f::indentity(), Matrix4f::translation(Vec3f::new([0.0, -1.0, 0.0]))],
+        });
+        model.animations.push(AnimationClip {
+            name: "wave".to_string(),
+            duration: 1.0,
+            channels: vec![
+                AnimationChannel {
+                    node: 0,
+                    property: ChannelProperty::Rotation,
+                    interpolation: Interpolation::Linear,
+                    times: vec![0.0, 1.0],
+                    values: vec![[0.0, 0.0, 0.0, 1.0], [0.0, 0.0, S, S]],
+                },
+                AnimationChannel {
+                    node: 1,
+                    property: ChannelProperty::Translation,
+                    interpolation: Interpolation::Step,
+                    times: vec![0.0, 0.5],
+                    values: vec![[0.0, 1.0, 0.0, 0.0], [0.0, 2.0, 0.0, 0.0]],
+                },
+            ],
+        });
+        model.animations.push(AnimationClip {
+            name: "rest".to_string(),
+            duration: 1.0,
+            channels: Vec::new(),
+        });
+        model
+    }
+
+    fn rest_pose(model: &Model) -> Vec<NodeTransform> {
+        model.node_transforms().into_iter().map(|(_, x)| x).collect()
+    }
+
+    #[test]
+    fn test_joint_matrices_follow_clip() {
+        let model = arm();
+        let rest = rest_pose(&model);
+        let mut player = AnimationPlayer::new();
+        player.play("wave", 0.0);
+
+        let pose = player.update(&model, &rest, 0.0).unwrap();
+        let joints = joint_matrices(&model, &pose);
+        assert_matrix(&joints[0][0], Matrix4f::indentity().0);
+        assert_matrix(&joints[0][1], Matrix4f::indentity().0);
+
+        // 45 degrees about z, the bone has already stepped to two units
+        let (c, s) = (S, S);
+        let pose = player.update(&model, &rest, 0.5).unwrap();
+        let joints = joint_matrices(&model, &pose);
+        assert_matrix(&joints[0][0], [[c, s, 0.0, 0.0], [-s, c, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert_matrix(&joints[0][1], [[c, s, 0.0, 0.0], [-s, c, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [-s, c, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn test_crossfade_blends_poses() {
+        let model = arm();
+        let rest = rest_pose(&model);
+        let mut player = AnimationPlayer::new();
+        player.looping = false;
+        player.play("wave", 0.0);
+        player.update(&model, &rest, 0.5);
+
+        player.play("rest", 1.0);
+        let pose = player.update(&model, &rest, 0.5).unwrap();
+        // halfway between the wave at 1.0 (90 degrees, bone at 2) and the rest pose
+        assert_relative_eq!(pose[1].0.y, 1.5, epsilon = 1e-5);
+        assert_relative_eq!(pose[0].1.z, (std::f32::consts::PI / 8.0).sin(), epsilon = 1e-5);
+
+        let pose = player.update(&model, &rest, 0.5).unwrap();
+        assert_relative_eq!(pose[1].0.y, 1.0, epsilon = 1e-5);
+        assert_relative_eq!(pose[0].1.w, 1.0, epsilon = 1e-5);
+        assert!(player.previous.is_none());
+    }
+
+    // game time moves the players, the physics time scale doesn't
+    #[test]
+    fn test_players_ignore_physics_time_scale() {
+        let mut assets = AssetLibrary::default();
+        let uuid = Uuid::new_v4();
+        assets.models.insert(uuid, arm());
+        let world = World::new();
+        let mut model_comp = ModelComponent::new("arm.gltf");
+        model_comp.model_uuid = uuid;
+        let mut player = AnimationPlayer::new();
+        player.play("wave", 0.0);
+        let entity = world.entities.borrow_mut().spawn((player, model_comp));
+
+        let mut state = headless_state();
+        state.time_struct.advance(0.1);
+        advance_players(&world, &assets, &state);
+        state.physics_time_scale = 0.25;
+        advance_players(&world, &assets, &state);
+
+        let entities = world.entities.borrow();
+        let player = entities.get::<&AnimationPlayer>(entity).unwrap();
+        assert_relative_eq!(player.current.as_ref().unwrap().time, 0.2, epsilon = 1e-6);
+        // the bone steps up at 0.5
+        assert_relative_eq!(entities.get::<&ModelComponent>(entity).unwrap().node_transforms[1].1.0.y, 1.0);
+    }
+}
@@ -1,11 +1,179 @@
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use super::{matrices::Matrix4f, transform::Transform, vectors::Vec3f};
+use hecs::Entity;
+use log::warn;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::{CameraView, VPData}, state::State};
+
+use super::{geometry::Ray, matrices::Matrix4f, position::Position, transform::Transform, vectors::{Vec2f, Vec3f, Vec4f}};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewportRect {
+    pub offset: Vec2f,
+    pub extent: Vec2f,
+}
+
+impl ViewportRect {
+    pub fn full() -> ViewportRect {
+        ViewportRect {
+            offset: Vec2f::new([0.0, 0.0]),
+            extent: Vec2f::new([1.0, 1.0]),
+        }
+    }
+
+    pub fn to_pixels(&self, window_extent: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+        (
+            [self.offset.x * window_extent[0], self.offset.y * window_extent[1]],
+            [self.extent.x * window_extent[0], self.extent.y * window_extent[1]],
+        )
+    }
+
+    pub fn aspect_ratio(&self, window_extent: [f32; 2]) -> f32 {
+        let (_, extent) = self.to_pixels(window_extent);
+        extent[0] / extent[1]
+    }
+
+    pub fn contains(&self, point: Vec2f) -> bool {
+        point.x >= self.offset.x
+            && point.y >= self.offset.y
+            && point.x <= self.offset.x + self.extent.x
+            && point.y <= self.offset.y + self.extent.y
+    }
+
+    pub fn to_local(&self, point: Vec2f) -> Vec2f {
+        (point - self.offset) / self.extent
+    }
+}
+
+impl Default for ViewportRect {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionKind {
+    // vfov in degrees, without a far plane nothing in front of the camera is clipped
+    Perspective { vfov: f32, near: f32, far: Option<f32> },
+    // height of the view in world units, the width follows the viewport's aspect ratio
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl ProjectionKind {
+    pub fn matrix(&self, aspect_ratio: f32) -> Matrix4f {
+        match *self {
+            ProjectionKind::Perspective { vfov, near, far: None } => Matrix4f::perspective(vfov.to_radians(), aspect_ratio, near),
+            ProjectionKind::Perspective { vfov, near, far: Some(far) } => {
+                Matrix4f::perspective_finite(vfov.to_radians(), aspect_ratio, near, far)
+            }
+            ProjectionKind::Orthographic { height, near, far } => {
+                let (half_width, half_height) = (height * aspect_ratio / 2.0, height / 2.0);
+                Matrix4f::orthographic(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        }
+    }
+}
+
+// a missing camera is only logged until one shows up again
+static NO_CAMERA: AtomicBool = AtomicBool::new(false);
 
 #[derive(Clone, Copy)]
 pub struct Camera {
-    pub vfov: f32,
-    pub near: f32,
+    pub projection: ProjectionKind,
+    pub viewport: Option<ViewportRect>,
+    // inactive cameras are skipped, flip it to switch between cameras without despawning them
+    pub active: bool,
+    // of the active cameras sharing a viewport only the one with the highest priority is drawn
+    pub priority: i32,
+}
+
+// `view` and `projection` as in VPData, relative to the camera at `position` like everything drawn, screen
+// coordinates are in pixels from the top left corner of `window_size`
+impl Camera {
+    // the ray under `cursor` from the near plane, a degenerate matrix gives one straight down -z
+    pub fn screen_to_ray(cursor: Vec2f, window_size: Vec2f, view: &Matrix4f, projection: &Matrix4f, position: Position) -> Ray {
+        let ndc = cursor / window_size * 2.0 - 1.0;
+        let inverse = (*projection * *view).inverse().unwrap_or_else(Matrix4f::indentity);
+        // reversed-Z, depth 1 is the near plane and 0 the far one, at infinity for the infinite perspective
+        let near = inverse * Vec4f::new([ndc.x, ndc.y, 1.0, 1.0]);
+        let far = inverse * Vec4f::new([ndc.x, ndc.y, 0.0, 1.0]);
+        // far / far.w - near / near.w scaled by both w, still a direction when far.w is 0
+        let dir = far.truncate() * near.w - near.truncate() * far.w;
+        Ray::new(position + Position::from((near.truncate() / near.w).to_vec3d()), dir.to_vec3d())
+    }
+
+    // where `pos` shows up on the screen, none when it's behind the camera
+    pub fn world_to_screen(pos: Position, window_size: Vec2f, view: &Matrix4f, projection: &Matrix4f, position: Position) -> Option<Vec2f> {
+        let relative: Vec3f = (pos - position).into();
+        let clip = *projection * *view * relative.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some((Vec2f::new([clip.x, clip.y]) / clip.w + 1.0) / 2.0 * window_size)
+    }
+}
+
+// the cameras that get drawn, highest priority first, ties go to the lower entity id so the pick doesn't
+// depend on the query order
+pub fn active_cameras(world: &World) -> Vec<Entity> {
+    let entities = world.entities.borrow();
+
+    let mut cameras: Vec<(Entity, Camera)> = entities
+        .query::<(&Camera, &Transform)>()
+        .iter()
+        .filter(|(_, (camera, _))| camera.active)
+        .map(|(entity, (camera, _))| (entity, *camera))
+        .collect();
+    cameras.sort_by_key(|(entity, camera)| (-(camera.priority as i64), entity.id()));
+
+    let mut drawn: Vec<(Entity, ViewportRect)> = Vec::new();
+    for (entity, camera) in cameras {
+        let viewport = camera.viewport.unwrap_or_default();
+        if !drawn.iter().any(|(_, x)| *x == viewport) {
+            drawn.push((entity, viewport));
+        }
+    }
+    drawn.into_iter().map(|(entity, _)| entity).collect()
+}
+
+pub fn camera_views(world: &World, cameras: &[Entity], window_extent: [f32; 2]) -> Vec<CameraView> {
+    let entities = world.entities.borrow();
+
+    cameras
+        .iter()
+        .filter_map(|x| Some((*entities.get::<&Camera>(*x).ok()?, entities.get::<&Transform>(*x).ok()?.clone())))
+        .map(|(camera, transform)| {
+            let viewport = camera.viewport.unwrap_or_default();
+            CameraView {
+                vp_data: VPData {
+                    view: Matrix4f::look_at(
+                        Vec3f::new([0.0, 0.0, 0.0]),
+                        transform.front(),
+                        transform.up(),
+                    ),
+                    projection: camera.projection.matrix(viewport.aspect_ratio(window_extent)),
+                },
+                position: transform.position,
+                viewport,
+            }
+        })
+        .collect()
+}
+
+// without any active camera the scene is drawn with the identity view instead of panicking
+pub fn update_camera_views(world: &World, state: &mut State, window_extent: [f32; 2]) {
+    let cameras = active_cameras(world);
+    let views = camera_views(world, &cameras, window_extent);
+    state.renderer.active_camera = cameras.first().copied();
+    if views.is_empty() {
+        if !NO_CAMERA.swap(true, Ordering::Relaxed) {
+            warn!("No active camera with a transform, drawing with the identity view");
+        }
+        state.renderer.views = vec![CameraView::default()];
+    } else {
+        NO_CAMERA.store(false, Ordering::Relaxed);
+        state.renderer.views = views;
+    }
 }
 
 pub struct CameraUpdater {}
@@ -13,16 +181,181 @@ pub struct CameraUpdater {}
 impl System for CameraUpdater {
     fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
     fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
-        let entities = world.entities.borrow_mut();
-
-        let mut query = entities.query::<(&Camera, &Transform)>();
-        let transform_data = query.iter().next().expect("Camera with trasform not found!").1.1;
-        let cam_rot = transform_data.rotation;
-        state.renderer.vp_pos = transform_data.position;
-        state.renderer.vp_data.view = Matrix4f::look_at(
-            Vec3f::new([0.0, 0.0, 0.0]),
-            cam_rot * Vec3f::new([0.0, 0.0, -1.0]),
-            cam_rot * Vec3f::new([0.0, 1.0, 0.0]),
+        let window_extent = state.renderer.viewport.extent;
+        update_camera_views(world, state, window_extent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::{
+        ecs::World,
+        types::{
+            matrices::Matrix4f,
+            position::Position,
+            quaternion::Quat,
+            transform::Transform,
+            vectors::{Vec2f, Vec3d, Vec3f, Vec3i, Vec4f},
+        },
+    };
+
+    use super::{active_cameras, camera_views, Camera, ProjectionKind, ViewportRect};
+
+    fn left_half() -> ViewportRect {
+        ViewportRect {
+            offset: Vec2f::new([0.0, 0.0]),
+            extent: Vec2f::new([0.5, 1.0]),
+        }
+    }
+
+    fn camera(priority: i32, viewport: Option<ViewportRect>) -> Camera {
+        Camera {
+            projection: ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+            viewport,
+            active: true,
+            priority,
+        }
+    }
+
+    fn transform(yaw: f32) -> Transform {
+        Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::from_axis_angle(Vec3f::new([0.0, 1.0, 0.0]), yaw),
+        )
+    }
+
+    #[test]
+    fn test_toggling_active_switches_view() {
+        let world = World::new();
+        let gameplay = world.entities.borrow_mut().spawn((camera(0, None), transform(0.0)));
+        let cutscene = world.entities.borrow_mut().spawn((camera(1, None), transform(1.0)));
+        let view_of = |world: &World| camera_views(world, &active_cameras(world), [1920.0, 1080.0])[0].vp_data.view;
+
+        assert_eq!(active_cameras(&world), vec![cutscene]);
+        let cutscene_view = view_of(&world);
+
+        world.entities.borrow_mut().get::<&mut Camera>(cutscene).unwrap().active = false;
+        assert_eq!(active_cameras(&world), vec![gameplay]);
+        assert_ne!(view_of(&world), cutscene_view);
+
+        world.entities.borrow_mut().get::<&mut Camera>(gameplay).unwrap().active = false;
+        assert!(active_cameras(&world).is_empty());
+        world.entities.borrow_mut().get::<&mut Camera>(cutscene).unwrap().active = true;
+        assert_eq!(view_of(&world), cutscene_view);
+    }
+
+    #[test]
+    fn test_camera_selection_is_deterministic() {
+        let world = World::new();
+        let first = world.entities.borrow_mut().spawn((camera(2, None), transform(0.0)));
+        world.entities.borrow_mut().spawn((camera(2, None), transform(1.0)));
+        let minimap = world.entities.borrow_mut().spawn((camera(0, Some(left_half())), transform(0.0)));
+        // no transform, never drawn
+        world.entities.borrow_mut().spawn((camera(5, None),));
+
+        assert_eq!(active_cameras(&world), vec![first, minimap]);
+    }
+
+    // a camera far from the origin looking somewhere sideways and down
+    fn view_from_afar() -> (Position, Vec3f, Transform, Matrix4f) {
+        let position = Position::new(Vec3i::new([40, -3, 1000]), Vec3d::new([12.5, 0.25, -7.0]));
+        let forward = Vec3f::new([1.0, -0.5, 2.0]).normalize();
+        let transform = Transform::new(
+            position,
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::look_rotation(forward, Vec3f::new([0.0, 1.0, 0.0])),
         );
+        let view = Matrix4f::look_at(Vec3f::new([0.0, 0.0, 0.0]), transform.front(), transform.up());
+        (position, forward, transform, view)
+    }
+
+    const PROJECTIONS: [ProjectionKind; 3] = [
+        ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None },
+        ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: Some(500.0) },
+        ProjectionKind::Orthographic { height: 10.0, near: 0.1, far: 500.0 },
+    ];
+
+    #[test]
+    fn test_screen_center_ray_is_forward() {
+        let (position, forward, _, view) = view_from_afar();
+        let window = Vec2f::new([1280.0, 720.0]);
+        for projection in PROJECTIONS {
+            let projection = projection.matrix(window.x / window.y);
+            let ray = Camera::screen_to_ray(window / 2.0, window, &view, &projection, position);
+            assert_relative_eq!(ray.dir.x, forward.x as f64, epsilon = 1e-5);
+            assert_relative_eq!(ray.dir.y, forward.y as f64, epsilon = 1e-5);
+            assert_relative_eq!(ray.dir.z, forward.z as f64, epsilon = 1e-5);
+            // starting on the near plane
+            let origin: Vec3f = (ray.origin - position).into();
+            assert_relative_eq!(origin.dot(forward), 0.1, epsilon = 1e-5);
+            assert_relative_eq!(origin.length(), 0.1, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_world_to_screen() {
+        let (position, forward, transform, view) = view_from_afar();
+        let window = Vec2f::new([1280.0, 720.0]);
+        for projection in PROJECTIONS {
+            let projection = projection.matrix(window.x / window.y);
+            let project = |pos: Position| Camera::world_to_screen(pos, window, &view, &projection, position);
+
+            let ahead = position + Position::from((forward * 25.0).to_vec3d());
+            let center = project(ahead).unwrap();
+            assert_relative_eq!(center.x, 640.0, epsilon = 1e-2);
+            assert_relative_eq!(center.y, 360.0, epsilon = 1e-2);
+            // y goes down the screen
+            assert!(project(ahead + Position::from(transform.up().to_vec3d())).unwrap().y < 360.0);
+
+            // back to the pixel a ray went through
+            let ray = Camera::screen_to_ray(Vec2f::new([100.0, 650.0]), window, &view, &projection, position);
+            let pixel = project(ray.at(30.0)).unwrap();
+            assert_relative_eq!(pixel.x, 100.0, epsilon = 1e-2);
+            assert_relative_eq!(pixel.y, 650.0, epsilon = 1e-2);
+        }
+
+        let projection = PROJECTIONS[0].matrix(window.x / window.y);
+        let behind = position + Position::from((forward * -5.0).to_vec3d());
+        assert_eq!(Camera::world_to_screen(behind, window, &view, &projection, position), None);
+    }
+
+    #[test]
+    fn test_split_screen_aspect_ratio() {
+        let window = [1920.0, 1080.0];
+        assert_eq!(ViewportRect::full().aspect_ratio(window), 1920.0 / 1080.0);
+        assert_eq!(left_half().aspect_ratio(window), 960.0 / 1080.0);
+    }
+
+    #[test]
+    fn test_viewport_to_pixels() {
+        let right_half = ViewportRect {
+            offset: Vec2f::new([0.5, 0.0]),
+            extent: Vec2f::new([0.5, 1.0]),
+        };
+        assert_eq!(right_half.to_pixels([1920.0, 1080.0]), ([960.0, 0.0], [960.0, 1080.0]));
+    }
+
+    #[test]
+    fn test_viewport_local_coordinates() {
+        let rect = left_half();
+        assert!(rect.contains(Vec2f::new([0.25, 0.5])));
+        assert!(!rect.contains(Vec2f::new([0.75, 0.5])));
+        assert_eq!(rect.to_local(Vec2f::new([0.25, 0.5])), Vec2f::new([0.5, 0.5]));
+    }
+
+    #[test]
+    fn test_orthographic_camera_fills_viewport() {
+        let projection = ProjectionKind::Orthographic { height: 10.0, near: 0.1, far: 100.0 }.matrix(2.0);
+        let corner = projection * Vec4f::new([10.0, 5.0, -50.0, 1.0]);
+        assert_eq!((corner.x, corner.y, corner.w), (1.0, 1.0, 1.0));
+
+        let finite = ProjectionKind::Perspective { vfov: 90.0, near: 0.1, far: Some(10.0) }.matrix(1.0);
+        let infinite = ProjectionKind::Perspective { vfov: 90.0, near: 0.1, far: None }.matrix(1.0);
+        assert_eq!(finite.0[1][1], infinite.0[1][1]);
+        assert_eq!(infinite.0[2][2], 0.0);
+        assert!(finite.0[2][2] > 0.0);
     }
 }
@@ -0,0 +1,231 @@
+use std::cell::Cell;
+
+use hecs::Entity;
+use winit::event::MouseButton;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+
+use super::{position::Position, quaternion::Quat, transform::Transform, vectors::Vec3f};
+
+// a little short of straight up or down, where yaw stops meaning anything and the view flips
+pub const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+// the inputs the camera controllers read, kept on each controller so they can be rebound
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraBindings {
+    // held while dragging to rotate, without one every mouse move rotates
+    pub rotate: Option<MouseButton>,
+    // the scroll wheel zooms, flip its sign to zoom the other way
+    pub zoom: f32,
+}
+
+impl Default for CameraBindings {
+    fn default() -> Self {
+        CameraBindings { rotate: Some(MouseButton::Right), zoom: 1.0 }
+    }
+}
+
+// circles `target` at `distance`, yaw and pitch in radians with both 0 looking down -z like an unrotated camera
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitCameraController {
+    pub target: Entity,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub yaw: f32,
+    // clamped to MAX_PITCH
+    pub pitch: f32,
+    // radians per pixel the mouse moves
+    pub sensitivity: f32,
+    // units per scroll step
+    pub zoom_speed: f32,
+    pub bindings: CameraBindings,
+}
+
+impl OrbitCameraController {
+    pub fn new(target: Entity, distance: f32) -> OrbitCameraController {
+        OrbitCameraController {
+            target,
+            distance,
+            min_distance: 0.5,
+            max_distance: 100.0,
+            yaw: 0.0,
+            pitch: -20f32.to_radians(),
+            sensitivity: 0.005,
+            zoom_speed: 1.0,
+            bindings: CameraBindings::default(),
+        }
+    }
+}
+
+// stays at `offset` from `target` in world space and looks at it, the gap closes by e every `smoothing` seconds
+// whatever the frame rate, 0 sticks to the target
+#[derive(Clone, Copy, Debug)]
+pub struct FollowCameraController {
+    pub target: Entity,
+    pub offset: Vec3f,
+    pub smoothing: f32,
+}
+
+impl FollowCameraController {
+    pub fn new(target: Entity, offset: Vec3f) -> FollowCameraController {
+        FollowCameraController { target, offset, smoothing: 0.2 }
+    }
+}
+
+// looking down -z turned by `pitch` about x, then by `yaw` about y
+pub fn orbit_rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_euler(Vec3f::new([pitch.clamp(-MAX_PITCH, MAX_PITCH), yaw, 0.0]))
+}
+
+// the yaw and pitch orbit_rotation needs to look along `dir`
+pub fn look_angles(dir: Vec3f) -> (f32, f32) {
+    let dir = dir.normalize();
+    ((-dir.x).atan2(-dir.z), dir.y.clamp(-1.0, 1.0).asin())
+}
+
+// how much of the remaining gap to close this frame
+pub fn smoothing_factor(smoothing: f32, delta_time: f64) -> f64 {
+    if smoothing <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-delta_time / smoothing as f64).exp()
+    }
+}
+
+// where the camera is after one frame of following a target at `target`
+pub fn follow_step(position: Position, target: Position, offset: Vec3f, smoothing: f32, delta_time: f64) -> Position {
+    position.lerp(&(target + Position::from(offset.to_vec3d())), smoothing_factor(smoothing, delta_time))
+}
+
+// the controllers with where their targets are, gathered first since a target can be another camera
+fn targets<T: hecs::Component>(entities: &hecs::World, target: impl Fn(&T) -> Entity) -> Vec<(Entity, Position)> {
+    entities
+        .query::<&T>()
+        .iter()
+        .filter_map(|(entity, x)| Some((entity, entities.get::<&Transform>(target(x)).ok()?.position)))
+        .collect()
+}
+
+// the mouse goes to the ui while it's over an element and for whole drags started there, so moving a slider
+// doesn't turn the camera too
+#[derive(Default)]
+pub struct OrbitCameraSystem {
+    ui_drag: Cell<bool>,
+}
+
+impl OrbitCameraSystem {
+    pub fn new() -> OrbitCameraSystem {
+        OrbitCameraSystem::default()
+    }
+}
+
+impl System for OrbitCameraSystem {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let over_ui = state.ui_focus.cursor_over_ui();
+        let entities = world.entities.borrow();
+        for (entity, target) in targets::<OrbitCameraController>(&entities, |x| x.target) {
+            let (Ok(mut controller), Ok(mut transform)) =
+                (entities.get::<&mut OrbitCameraController>(entity), entities.get::<&mut Transform>(entity))
+            else {
+                continue;
+            };
+
+            let rotating = match controller.bindings.rotate {
+                Some(button) => {
+                    if state.input.button_pressed.contains(&button) {
+                        self.ui_drag.set(over_ui);
+                    }
+                    state.input.button_down.contains(&button) && !self.ui_drag.get()
+                }
+                None => !over_ui,
+            };
+            if rotating {
+                let delta = state.input.get_mouse_delta();
+                controller.yaw -= delta.x * controller.sensitivity;
+                controller.pitch = (controller.pitch - delta.y * controller.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+            if !over_ui {
+                let zoom = state.input.scroll_delta * controller.bindings.zoom * controller.zoom_speed;
+                controller.distance = (controller.distance - zoom).clamp(controller.min_distance, controller.max_distance);
+            }
+
+            transform.rotation = orbit_rotation(controller.yaw, controller.pitch);
+            let back = transform.front() * -controller.distance;
+            transform.position = target + Position::from(back.to_vec3d());
+        }
+    }
+}
+
+pub struct FollowCameraSystem {}
+
+impl System for FollowCameraSystem {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let entities = world.entities.borrow();
+        for (entity, target) in targets::<FollowCameraController>(&entities, |x| x.target) {
+            let (Ok(controller), Ok(mut transform)) =
+                (entities.get::<&FollowCameraController>(entity), entities.get::<&mut Transform>(entity))
+            else {
+                continue;
+            };
+
+            transform.position = follow_step(transform.position, target, controller.offset, controller.smoothing, state.time_struct.delta);
+            let dir: Vec3f = (target - transform.position).into();
+            if dir.length_sqr() > 1e-12 {
+                let (yaw, pitch) = look_angles(dir);
+                transform.rotation = orbit_rotation(yaw, pitch);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::types::{position::Position, vectors::{Vec3d, Vec3f}};
+
+    use super::{follow_step, look_angles, orbit_rotation, MAX_PITCH};
+
+    #[test]
+    fn test_follow_smoothing_converges() {
+        let target = Position::from(Vec3d::new([1e6, 20.0, -3e5]));
+        let offset = Vec3f::new([0.0, 3.0, 6.0]);
+        let goal = target + Position::from(offset.to_vec3d());
+
+        // two seconds at two frame rates end up in the same place, close to the target offset
+        let mut at_60 = Position::default();
+        for _ in 0..120 {
+            at_60 = follow_step(at_60, target, offset, 0.2, 1.0 / 60.0);
+        }
+        let mut at_30 = Position::default();
+        for _ in 0..60 {
+            at_30 = follow_step(at_30, target, offset, 0.2, 1.0 / 30.0);
+        }
+        let start = Position::default().distance_to(&goal);
+        assert_relative_eq!(at_60.distance_to(&goal), start * (-10.0f64).exp(), max_relative = 1e-6);
+        assert!(at_60.distance_to(&at_30) < 1e-6 * start);
+
+        // no smoothing sticks to it
+        assert!(follow_step(Position::default(), target, offset, 0.0, 1.0 / 60.0).approx_eq(&goal, 1e-6));
+    }
+
+    #[test]
+    fn test_orbit_pitch_is_clamped() {
+        let front = Vec3f::new([0.0, 0.0, -1.0]) * orbit_rotation(0.3, 10.0);
+        let up = Vec3f::new([0.0, 1.0, 0.0]) * orbit_rotation(0.3, 10.0);
+        assert_relative_eq!(front.y, MAX_PITCH.sin(), epsilon = 1e-6);
+        assert!(up.y > 0.0);
+
+        let (yaw, pitch) = look_angles(Vec3f::new([1.0, -0.5, 2.0]));
+        let front = Vec3f::new([0.0, 0.0, -1.0]) * orbit_rotation(yaw, pitch);
+        let expected = Vec3f::new([1.0, -0.5, 2.0]).normalize();
+        assert_relative_eq!(front.x, expected.x, epsilon = 1e-6);
+        assert_relative_eq!(front.y, expected.y, epsilon = 1e-6);
+        assert_relative_eq!(front.z, expected.z, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,180 @@
+use std::cell::Cell;
+
+use hecs::Entity;
+use winit::{keyboard::{Key, NamedKey}, window::CursorGrabMode};
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+
+use super::{
+    camera::{Camera, ProjectionKind},
+    camera_controller::{look_angles, orbit_rotation, MAX_PITCH},
+    position::Position,
+    quaternion::Quat,
+    transform::Transform,
+    vectors::Vec3f,
+};
+
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 10000.0;
+
+// a camera to fly around the scene with, detached from the gameplay one, `toggle` switches to it and back,
+// WASD moves, Q and E go down and up, the mouse looks around and the scroll wheel changes the speed,
+// it only ever touches its own entity, which wins over the gameplay cameras by priority while it's active,
+// added with `world.add_system(DebugFlyCamera::default())` before `run` to toggle it with F1
+pub struct DebugFlyCamera {
+    pub toggle: Key,
+    // radians per pixel the mouse moves
+    pub sensitivity: f32,
+    camera: Cell<Option<Entity>>,
+    active: Cell<bool>,
+    // units per second
+    speed: Cell<f32>,
+    yaw: Cell<f32>,
+    pitch: Cell<f32>,
+}
+
+impl DebugFlyCamera {
+    pub fn new(toggle: Key) -> DebugFlyCamera {
+        DebugFlyCamera {
+            toggle,
+            sensitivity: 0.003,
+            camera: Cell::new(None),
+            active: Cell::new(false),
+            speed: Cell::new(10.0),
+            yaw: Cell::new(0.0),
+            pitch: Cell::new(0.0),
+        }
+    }
+
+    // starts where the camera that was being looked through is, the first time
+    fn spawn(&self, world: &World, state: &State) -> Entity {
+        let mut entities = world.entities.borrow_mut();
+        let (projection, transform) = match state.renderer.active_camera {
+            Some(active) => (
+                entities.get::<&Camera>(active).ok().map(|x| x.projection),
+                entities.get::<&Transform>(active).ok().map(|x| (*x).clone()),
+            ),
+            None => (None, None),
+        };
+        let transform = transform.unwrap_or(Transform::new(
+            Position::default(),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Quat::new([1.0, 0.0, 0.0, 0.0]),
+        ));
+        let (yaw, pitch) = look_angles(transform.front());
+        self.yaw.set(yaw);
+        self.pitch.set(pitch);
+
+        let camera = Camera {
+            projection: projection.unwrap_or(ProjectionKind::Perspective { vfov: 60.0, near: 0.1, far: None }),
+            viewport: None,
+            active: true,
+            priority: i32::MAX,
+        };
+        entities.spawn((camera, transform))
+    }
+
+    fn set_active(&self, world: &World, state: &mut State, active: bool) {
+        let existing = self.camera.get().filter(|x| world.entities.borrow().contains(*x));
+        let camera = match existing {
+            None if active => Some(self.spawn(world, state)),
+            _ => existing,
+        };
+        self.camera.set(camera);
+        if let Some(camera) = camera {
+            if let Ok(mut camera) = world.entities.borrow().get::<&mut Camera>(camera) {
+                camera.active = active;
+            }
+        }
+        self.active.set(active);
+
+        // relative mouse movement without the cursor leaving the window
+        state.window.set_cursor_grab(if active { CursorGrabMode::Locked } else { CursorGrabMode::None });
+        state.window.window_handle.set_cursor_visible(!active);
+    }
+}
+
+impl Default for DebugFlyCamera {
+    fn default() -> Self {
+        DebugFlyCamera::new(Key::Named(NamedKey::F1))
+    }
+}
+
+// where `position` ends up moving along `input` for a frame, x right, y up and z forward, relative to `rotation`
+// except y which is always world up
+pub fn fly_step(position: Position, rotation: Quat, input: Vec3f, speed: f32, delta_time: f64) -> Position {
+    if input.length_sqr() == 0.0 {
+        return position;
+    }
+    let front = Vec3f::new([0.0, 0.0, -1.0]) * rotation;
+    let right = Vec3f::new([1.0, 0.0, 0.0]) * rotation;
+    let dir = (right * input.x + Vec3f::new([0.0, input.y, 0.0]) + front * input.z).normalize();
+    position + Position::from(dir.to_vec3d() * (speed as f64 * delta_time))
+}
+
+impl System for DebugFlyCamera {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        if state.input.key_pressed.contains(&self.toggle) {
+            self.set_active(world, state, !self.active.get());
+        }
+        if !self.active.get() {
+            return;
+        }
+        let Some(camera) = self.camera.get().filter(|x| world.entities.borrow().contains(*x)) else {
+            // despawned by someone else, spawned again next toggle
+            self.set_active(world, state, false);
+            return;
+        };
+        let entities = world.entities.borrow();
+        let Ok(mut transform) = entities.get::<&mut Transform>(camera) else {
+            return;
+        };
+
+        let delta = state.input.get_mouse_delta();
+        self.yaw.set(self.yaw.get() - delta.x * self.sensitivity);
+        self.pitch.set((self.pitch.get() - delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH));
+        transform.rotation = orbit_rotation(self.yaw.get(), self.pitch.get());
+
+        // every scroll step is 20% faster or slower
+        self.speed.set((self.speed.get() * 1.2f32.powf(state.input.scroll_delta)).clamp(MIN_SPEED, MAX_SPEED));
+
+        let held = |key: &str| state.input.key_down.contains(&Key::Character(key.into())) as i32 as f32;
+        let input = Vec3f::new([held("d") - held("a"), held("e") - held("q"), held("w") - held("s")]);
+        // keeps flying while the game is paused
+        transform.position = fly_step(transform.position, transform.rotation, input, self.speed.get(), state.time_struct.unscaled_delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::types::{camera_controller::orbit_rotation, position::Position, vectors::{Vec3d, Vec3f}};
+
+    use super::fly_step;
+
+    #[test]
+    fn test_fly_step_far_from_origin() {
+        let start = Position::from(Vec3d::new([3e12, -5e11, 7e12]));
+        let rotation = orbit_rotation(std::f32::consts::FRAC_PI_2, 0.0);
+
+        // a millimeter a frame still adds up out here, facing -x
+        let mut position = start;
+        for _ in 0..1000 {
+            position = fly_step(position, rotation, Vec3f::new([0.0, 0.0, 1.0]), 0.06, 1.0 / 60.0);
+        }
+        let moved = start.offset_to(&position);
+        assert_relative_eq!(moved.x, -1.0, epsilon = 1e-6);
+        assert_relative_eq!(moved.z, 0.0, epsilon = 1e-6);
+
+        // up ignores where it's looking, diagonals aren't faster
+        let rotation = orbit_rotation(0.0, 1.0);
+        let moved = start.offset_to(&fly_step(start, rotation, Vec3f::new([0.0, 1.0, 0.0]), 2.0, 0.5));
+        assert_relative_eq!(moved.y, 1.0, epsilon = 1e-9);
+        let moved = start.offset_to(&fly_step(start, rotation, Vec3f::new([1.0, 1.0, 1.0]), 2.0, 0.5));
+        assert_relative_eq!(moved.length(), 1.0, epsilon = 1e-6);
+        assert_eq!(fly_step(start, rotation, Vec3f::new([0.0, 0.0, 0.0]), 2.0, 0.5), start);
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::HashMap, fmt, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{engine_config::AssetPaths, types::{texture::Texture, vectors::Vec2f}};
+
+// printable ascii and latin-1
+pub const DEFAULT_CHARACTERS: &str =
+    " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~\
+    ¡¢£¤¥¦§¨©ª«¬®¯°±²³´µ¶·¸¹º»¼½¾¿ÀÁÂÃÄÅÆÇÈÉÊËÌÍÎÏÐÑÒÓÔÕÖ×ØÙÚÛÜÝÞßàáâãäåæçèéêëìíîïðñòóôõö÷øùúûüýþÿ";
+
+const ATLAS_WIDTH: u32 = 512;
+// empty pixels around every glyph so linear filtering doesn't bleed neighbours in
+const ATLAS_PADDING: u32 = 1;
+
+pub fn default_font_size() -> f32 {
+    48.0
+}
+
+#[derive(Debug)]
+pub enum FontLoadError {
+    Open { path: String, error: io::Error },
+    Parse { path: String, error: &'static str },
+}
+
+impl fmt::Display for FontLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontLoadError::Open { path, error } => write!(f, "failed to open font {}: {}", path, error),
+            FontLoadError::Parse { path, error } => write!(f, "failed to parse font {}: {}", path, error),
+        }
+    }
+}
+
+// metrics are in ems, so they scale with the size text is drawn at
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Glyph {
+    pub uv_min: Vec2f,
+    pub uv_max: Vec2f,
+    pub size: Vec2f,
+    // from the pen position on the baseline to the bottom left corner, y up
+    pub offset: Vec2f,
+    pub advance: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Font {
+    pub name: String,
+    // name of the texture holding the glyphs, same as the font
+    pub atlas: String,
+    pub ascent: f32,
+    pub line_height: f32,
+    pub glyphs: HashMap<char, Glyph>,
+    pub kerning: HashMap<(char, char), f32>,
+    // drawn for characters the font or the baked set doesn't have
+    pub replacement: Glyph,
+}
+
+impl Font {
+    pub fn glyph(&self, character: char) -> &Glyph {
+        self.glyphs.get(&character).unwrap_or(&self.replacement)
+    }
+
+    pub fn kerning(&self, left: char, right: char) -> f32 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0.0)
+    }
+
+    // rasterizes `characters` at `size` pixels into an atlas texture
+    pub fn bake(name: String, size: f32, characters: &str, paths: &AssetPaths) -> Result<(Font, Texture), FontLoadError> {
+        let path = paths.fonts.join(&name).display().to_string();
+        let bytes = fs::read(&path).map_err(|error| FontLoadError::Open { path: path.clone(), error })?;
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings { scale: size, ..Default::default() })
+            .map_err(|error| FontLoadError::Parse { path: path.clone(), error })?;
+
+        let mut characters: Vec<char> = characters.chars().filter(|x| font.lookup_glyph_index(*x) != 0).collect();
+        characters.sort_unstable();
+        characters.dedup();
+
+        let mut bitmaps: Vec<(u32, u32, Vec<u8>)> = Vec::with_capacity(characters.len() + 1);
+        let mut metrics = Vec::with_capacity(characters.len());
+        for character in characters.iter() {
+            let (metric, coverage) = font.rasterize(*character, size);
+            bitmaps.push((metric.width as u32, metric.height as u32, coverage));
+            metrics.push(metric);
+        }
+        let replacement_size = ((size * 0.5).ceil() as u32, (size * 0.7).ceil() as u32);
+        bitmaps.push((replacement_size.0, replacement_size.1, replacement_box(replacement_size.0, replacement_size.1)));
+
+        let (positions, height) = pack_glyphs(&bitmaps.iter().map(|x| (x.0, x.1)).collect::<Vec<_>>(), ATLAS_WIDTH);
+        let mut pixels = vec![0u8; (ATLAS_WIDTH * height * 4) as usize];
+        for ((width, glyph_height, coverage), (x, y)) in bitmaps.iter().zip(positions.iter()) {
+            for row in 0..*glyph_height {
+                for column in 0..*width {
+                    let pixel = (((y + row) * ATLAS_WIDTH + x + column) * 4) as usize;
+                    pixels[pixel..pixel + 3].fill(255);
+                    pixels[pixel + 3] = coverage[(row * width + column) as usize];
+                }
+            }
+        }
+
+        let atlas_size = Vec2f::new([ATLAS_WIDTH as f32, height as f32]);
+        let to_glyph = |(x, y): (u32, u32), (width, height): (u32, u32), offset: Vec2f, advance: f32| Glyph {
+            uv_min: Vec2f::new([x as f32, y as f32]) / atlas_size,
+            uv_max: Vec2f::new([(x + width) as f32, (y + height) as f32]) / atlas_size,
+            size: Vec2f::new([width as f32 / size, height as f32 / size]),
+            offset: offset / size,
+            advance: advance / size,
+        };
+
+        let mut glyphs = HashMap::with_capacity(characters.len());
+        for (i, (character, metric)) in characters.iter().zip(metrics.iter()).enumerate() {
+            glyphs.insert(*character, to_glyph(
+                positions[i],
+                (bitmaps[i].0, bitmaps[i].1),
+                Vec2f::new([metric.xmin as f32, metric.ymin as f32]),
+                metric.advance_width,
+            ));
+        }
+        let replacement = to_glyph(
+            positions[characters.len()],
+            replacement_size,
+            Vec2f::new([size * 0.05, 0.0]),
+            replacement_size.0 as f32 + size * 0.1,
+        );
+
+        let mut kerning = HashMap::new();
+        for left in characters.iter() {
+            for right in characters.iter() {
+                if let Some(kern) = font.horizontal_kern(*left, *right, size).filter(|x| *x != 0.0) {
+                    kerning.insert((*left, *right), kern / size);
+                }
+            }
+        }
+
+        let (ascent, line_height) = match font.horizontal_line_metrics(size) {
+            Some(val) => (val.ascent / size, val.new_line_size / size),
+            None => (1.0, 1.2),
+        };
+
+        let texture = Texture::from_rgba8(&name, ATLAS_WIDTH, height, pixels);
+        Ok((Font { atlas: name.clone(), name, ascent, line_height, glyphs, kerning, replacement }, texture))
+    }
+}
+
+// a hollow rectangle, the usual look of a missing glyph
+fn replacement_box(width: u32, height: u32) -> Vec<u8> {
+    let border = (width / 8).max(1);
+    let mut coverage = vec![0u8; (width * height) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            if x < border || y < border || x >= width - border || y >= height - border {
+                coverage[(y * width + x) as usize] = 255;
+            }
+        }
+    }
+    coverage
+}
+
+// places rectangles left to right in rows of `width`, returns their corners and the total height
+pub fn pack_glyphs(sizes: &[(u32, u32)], width: u32) -> (Vec<(u32, u32)>, u32) {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    // tallest first keeps the rows tight
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut row_height) = (ATLAS_PADDING, ATLAS_PADDING, 0);
+    for i in order {
+        let (glyph_width, glyph_height) = sizes[i];
+        if x + glyph_width + ATLAS_PADDING > width && x > ATLAS_PADDING {
+            x = ATLAS_PADDING;
+            y += row_height + ATLAS_PADDING;
+            row_height = 0;
+        }
+        positions[i] = (x, y);
+        x += glyph_width + ATLAS_PADDING;
+        row_height = row_height.max(glyph_height);
+    }
+
+    (positions, y + row_height + ATLAS_PADDING)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack_glyphs, replacement_box, ATLAS_PADDING};
+
+    #[test]
+    fn test_packed_glyphs_dont_overlap() {
+        let sizes: Vec<(u32, u32)> = (0..200).map(|i| (5 + i % 23, 8 + i % 17)).collect();
+        let (positions, height) = pack_glyphs(&sizes, 128);
+
+        let rects: Vec<(u32, u32, u32, u32)> = positions.iter().zip(sizes.iter())
+            .map(|((x, y), (w, h))| (*x, *y, x + w, y + h))
+            .collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.0 >= ATLAS_PADDING && a.2 + ATLAS_PADDING <= 128 && a.3 + ATLAS_PADDING <= height);
+            for b in rects[i + 1..].iter() {
+                let apart = a.2 + ATLAS_PADDING <= b.0 || b.2 + ATLAS_PADDING <= a.0 ||
+                    a.3 + ATLAS_PADDING <= b.1 || b.3 + ATLAS_PADDING <= a.1;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_replacement_box_is_hollow() {
+        let coverage = replacement_box(16, 24);
+        assert_eq!(coverage[0], 255);
+        assert_eq!(coverage[16 * 24 - 1], 255);
+        assert_eq!(coverage[12 * 16 + 8], 0);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::types::{matrices::Matrix4f, position::Position, vectors::{Vec3d, Vec3f}};
+
+pub use super::aabb::Aabb;
+
+// a half line, Ray::new normalizes `dir` so distances along it are in world units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Position,
+    pub dir: Vec3d,
+}
+
+// the points where normal.dot(point) + d is 0, for a unit normal that's the signed distance to them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3f,
+    pub d: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3f,
+    pub radius: f32,
+}
+
+// the planes around what a view-projection matrix shows, normals pointing in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+// the shapes in f32 are relative to `space` in the ray tests, like the camera's position for culling or an
+// entity's for its bounds, so rays stay precise far from the origin
+impl Ray {
+    pub fn new(origin: Position, dir: Vec3d) -> Ray {
+        Ray { origin, dir: dir.normalize() }
+    }
+
+    pub fn at(&self, distance: f64) -> Position {
+        self.origin + Position::from(self.dir * distance)
+    }
+
+    fn local_origin(&self, space: Position) -> Vec3d {
+        Vec3d::from(self.origin - space)
+    }
+
+    // slab test, the distance to where the ray enters the box, 0 when it starts inside
+    pub fn intersect_aabb(&self, aabb: &Aabb, space: Position) -> Option<f64> {
+        let origin = self.local_origin(space);
+        let (min, max) = (aabb.min.to_vec3d(), aabb.max.to_vec3d());
+        let mut t_near = 0.0f64;
+        let mut t_far = f64::INFINITY;
+        for (o, d, lo, hi) in [
+            (origin.x, self.dir.x, min.x, max.x),
+            (origin.y, self.dir.y, min.y, max.y),
+            (origin.z, self.dir.z, min.z, max.z),
+        ] {
+            if d == 0.0 {
+                if o < lo || o > hi {
+                    return None;
+                }
+                continue;
+            }
+            let (a, b) = ((lo - o) / d, (hi - o) / d);
+            t_near = t_near.max(a.min(b));
+            t_far = t_far.min(a.max(b));
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some(t_near)
+    }
+
+    // the distance to the nearer hit, 0 when the ray starts inside
+    pub fn intersect_sphere(&self, sphere: &Sphere, space: Position) -> Option<f64> {
+        let offset = self.local_origin(space) - sphere.center.to_vec3d();
+        let radius = sphere.radius as f64;
+        let b = offset.dot(self.dir);
+        let c = offset.length_sqr() - radius * radius;
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+        let discriminant = b * b - c;
+        if b > 0.0 || discriminant < 0.0 {
+            return None;
+        }
+        Some(-b - discriminant.sqrt())
+    }
+
+    // None for rays running along the plane or pointing away from it
+    pub fn intersect_plane(&self, plane: &Plane, space: Position) -> Option<f64> {
+        let normal = plane.normal.to_vec3d();
+        let along = normal.dot(self.dir);
+        if along == 0.0 {
+            return None;
+        }
+        let distance = -(normal.dot(self.local_origin(space)) + plane.d as f64) / along;
+        (distance >= 0.0).then_some(distance)
+    }
+}
+
+impl Plane {
+    pub fn new(normal: Vec3f, point: Vec3f) -> Plane {
+        let normal = normal.normalize();
+        Plane { normal, d: -normal.dot(point) }
+    }
+
+    // the normal faces where the points go around counterclockwise, None when they're on a line
+    pub fn from_points(a: Vec3f, b: Vec3f, c: Vec3f) -> Option<Plane> {
+        let normal = (b - a).cross(c - a);
+        (normal.length_sqr() > 0.0).then(|| Plane::new(normal, a))
+    }
+
+    pub fn signed_distance(&self, point: Vec3f) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+impl Sphere {
+    pub fn new(center: Vec3f, radius: f32) -> Sphere {
+        Sphere { center, radius }
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let closest = self.center.clamp(aabb.min, aabb.max);
+        (closest - self.center).length_sqr() <= self.radius * self.radius
+    }
+}
+
+impl Frustum {
+    // for the reversed-Z projections, depth 1 at the near plane and 0 at the far one, the far plane of an
+    // infinite projection has no normal and keeps everything
+    pub fn from_matrix(view_projection: &Matrix4f) -> Frustum {
+        let row = |i: usize| view_projection.0.map(|x| x[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let add = |a: [f32; 4], b: [f32; 4], sign: f32| [0, 1, 2, 3].map(|i| a[i] + b[i] * sign);
+        let plane = |v: [f32; 4]| {
+            let normal = Vec3f::new([v[0], v[1], v[2]]);
+            let length = normal.length();
+            if length == 0.0 {
+                return Plane { normal, d: v[3] };
+            }
+            Plane { normal: normal / length, d: v[3] / length }
+        };
+        Frustum {
+            planes: [
+                plane(add(w, x, 1.0)),
+                plane(add(w, x, -1.0)),
+                plane(add(w, y, 1.0)),
+                plane(add(w, y, -1.0)),
+                // near, depth at most 1
+                plane(add(w, z, -1.0)),
+                // far, depth at least 0
+                plane(z),
+            ],
+        }
+    }
+
+    // can give false positives for boxes near the frustum's corners, never false negatives
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let furthest = Vec3f::new([
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            ]);
+            plane.signed_distance(furthest) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{
+        matrices::Matrix4f,
+        position::Position,
+        vectors::{Vec3d, Vec3f, Vec3i},
+    };
+
+    use super::{Aabb, Frustum, Plane, Ray, Sphere};
+
+    fn unit() -> Aabb {
+        Aabb::new(Vec3f::new([-1.0, -1.0, -1.0]), Vec3f::new([1.0, 1.0, 1.0]))
+    }
+
+    fn ray(origin: [f64; 3], dir: [f64; 3]) -> Ray {
+        Ray::new(Position::from(Vec3d::new(origin)), Vec3d::new(dir))
+    }
+
+    // a cube of half size `half` around `center`
+    fn cube(center: [f32; 3], half: f32) -> Aabb {
+        let center = Vec3f::new(center);
+        Aabb::new(center - half, center + half)
+    }
+
+    #[test]
+    fn test_ray_aabb() {
+        let origin = Position::default();
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, -2.0]).intersect_aabb(&unit(), origin), Some(4.0));
+        assert_eq!(ray([0.0, 0.5, 0.0], [1.0, 0.0, 0.0]).intersect_aabb(&unit(), origin), Some(0.0));
+        assert_eq!(ray([3.0, 0.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&unit(), origin), None);
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, 1.0]).intersect_aabb(&unit(), origin), None);
+        // diagonal through the corner region, enters through x = -1 at (-1, -0.5, 0)
+        let hit = ray([-3.0, -1.5, 0.0], [2.0, 1.0, 0.0]).intersect_aabb(&unit(), origin).unwrap();
+        assert!((hit - 5.0f64.sqrt()).abs() < 1e-12);
+
+        // far from the origin, the box relative to the chunk it's in
+        let space = Position::new(Vec3i::new([7, 0, -3]), Vec3d::new([10.0, 0.0, 0.0]));
+        let far = Ray::new(space + Position::from(Vec3d::new([0.0, 0.0, 5.0])), Vec3d::new([0.0, 0.0, -1.0]));
+        assert_eq!(far.intersect_aabb(&unit(), space), Some(4.0));
+        assert_eq!(far.at(4.0), space + Position::from(Vec3d::new([0.0, 0.0, 1.0])));
+    }
+
+    #[test]
+    fn test_ray_sphere_and_plane() {
+        let origin = Position::default();
+        let sphere = Sphere::new(Vec3f::new([0.0, 0.0, 0.0]), 1.0);
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, -1.0]).intersect_sphere(&sphere, origin), Some(4.0));
+        assert_eq!(ray([0.0, 0.0, 0.5], [0.0, 0.0, -1.0]).intersect_sphere(&sphere, origin), Some(0.0));
+        assert_eq!(ray([0.0, 2.0, 5.0], [0.0, 0.0, -1.0]).intersect_sphere(&sphere, origin), None);
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, 1.0]).intersect_sphere(&sphere, origin), None);
+        // grazing the top
+        assert_eq!(ray([0.0, 1.0, 5.0], [0.0, 0.0, -1.0]).intersect_sphere(&sphere, origin), Some(5.0));
+
+        let ground = Plane::new(Vec3f::new([0.0, 2.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+        assert_eq!(ground.d, -1.0);
+        assert_eq!(ray([3.0, 5.0, 0.0], [0.0, -1.0, 0.0]).intersect_plane(&ground, origin), Some(4.0));
+        assert_eq!(ray([0.0, 5.0, 0.0], [1.0, 0.0, 0.0]).intersect_plane(&ground, origin), None);
+        assert_eq!(ray([0.0, 5.0, 0.0], [0.0, 1.0, 0.0]).intersect_plane(&ground, origin), None);
+        let hit = ray([0.0, 3.0, 0.0], [1.0, -1.0, 0.0]).intersect_plane(&ground, origin).unwrap();
+        assert!((hit - 8.0f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_plane_from_points() {
+        let plane = Plane::from_points(Vec3f::new([0.0, 2.0, 0.0]), Vec3f::new([1.0, 2.0, 0.0]), Vec3f::new([0.0, 2.0, -1.0])).unwrap();
+        assert_eq!(plane.normal, Vec3f::new([0.0, 1.0, 0.0]));
+        assert_eq!(plane.signed_distance(Vec3f::new([4.0, 5.0, 1.0])), 3.0);
+        assert_eq!(plane.signed_distance(Vec3f::new([0.0, 0.0, 0.0])), -2.0);
+        // same points the other way around face down
+        let flipped = Plane::from_points(Vec3f::new([0.0, 2.0, 0.0]), Vec3f::new([0.0, 2.0, -1.0]), Vec3f::new([1.0, 2.0, 0.0])).unwrap();
+        assert_eq!(flipped.normal, Vec3f::new([0.0, -1.0, 0.0]));
+        assert_eq!(Plane::from_points(Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([1.0, 1.0, 1.0]), Vec3f::new([2.0, 2.0, 2.0])), None);
+    }
+
+    #[test]
+    fn test_aabb_and_sphere_overlap() {
+        assert!(unit().intersects(&cube([1.5, 0.0, 0.0], 1.0)));
+        // touching faces count
+        assert!(unit().intersects(&cube([2.0, 0.0, 0.0], 1.0)));
+        assert!(!unit().intersects(&cube([2.0, 2.5, 0.0], 1.0)));
+
+        assert!(Sphere::new(Vec3f::new([2.0, 0.0, 0.0]), 1.01).intersects_aabb(&unit()));
+        assert!(!Sphere::new(Vec3f::new([2.0, 0.0, 0.0]), 0.99).intersects_aabb(&unit()));
+        // off the edge the closest point is the edge, not the face
+        assert!(!Sphere::new(Vec3f::new([2.0, 2.0, 0.0]), 1.4).intersects_aabb(&unit()));
+        assert!(Sphere::new(Vec3f::new([2.0, 2.0, 0.0]), 1.42).intersects_aabb(&unit()));
+        assert!(Sphere::new(Vec3f::new([0.0, 0.0, 0.0]), 0.1).intersects_aabb(&unit()));
+    }
+
+    #[test]
+    fn test_frustum_reversed_z() {
+        // 90 degrees so the sides are at |x| = |z| and |y| = |z|, looking down -z
+        let infinite = Frustum::from_matrix(&Matrix4f::perspective(90.0f32.to_radians(), 1.0, 0.1));
+        assert!(infinite.intersects_aabb(&cube([0.0, 0.0, -5.0], 0.5)));
+        assert!(!infinite.intersects_aabb(&cube([0.0, 0.0, 5.0], 0.5)));
+        assert!(!infinite.intersects_aabb(&cube([10.0, 0.0, -5.0], 0.5)));
+        assert!(!infinite.intersects_aabb(&cube([0.0, -10.0, -5.0], 0.5)));
+        assert!(infinite.intersects_aabb(&cube([5.0, 0.0, -5.0], 0.5)));
+        // before the near plane
+        assert!(!infinite.intersects_aabb(&cube([0.0, 0.0, -0.05], 0.01)));
+        // nothing is too far without a far plane
+        assert!(infinite.intersects_aabb(&cube([0.0, 0.0, -1e6], 0.5)));
+
+        let finite = Frustum::from_matrix(&Matrix4f::perspective_finite(90.0f32.to_radians(), 1.0, 0.1, 100.0));
+        assert!(finite.intersects_aabb(&cube([0.0, 0.0, -99.0], 0.5)));
+        assert!(!finite.intersects_aabb(&cube([0.0, 0.0, -200.0], 0.5)));
+
+        // the camera moved 50 along x, view times projection
+        let view = Matrix4f::translation(Vec3f::new([-50.0, 0.0, 0.0]));
+        let moved = Frustum::from_matrix(&(Matrix4f::perspective(90.0f32.to_radians(), 1.0, 0.1) * view));
+        assert!(moved.intersects_aabb(&cube([50.0, 0.0, -5.0], 0.5)));
+        assert!(!moved.intersects_aabb(&cube([0.0, 0.0, -5.0], 0.5)));
+
+        let orthographic = Frustum::from_matrix(&Matrix4f::orthographic(-2.0, 2.0, -1.0, 1.0, 0.5, 10.0));
+        assert!(orthographic.intersects_aabb(&cube([1.5, 0.5, -5.0], 0.1)));
+        assert!(!orthographic.intersects_aabb(&cube([2.5, 0.0, -5.0], 0.1)));
+        assert!(!orthographic.intersects_aabb(&cube([0.0, 0.0, -11.0], 0.5)));
+        assert!(!orthographic.intersects_aabb(&cube([0.0, 0.0, 0.0], 0.1)));
+    }
+}
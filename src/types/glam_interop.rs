@@ -0,0 +1,185 @@
+use glam::{DMat4, DVec2, DVec3, Mat4, Vec2, Vec3, Vec4};
+
+use super::{matrices::{Matrix4d, Matrix4f}, quaternion::Quat, vectors::{Vec2d, Vec2f, Vec3d, Vec3f, Vec4f}};
+
+// conversions to and from glam, rotations turn vectors the same way on both sides, but `a * b` of ours is
+// glam's `b * a`, matrices are column major in both
+
+impl From<Vec2f> for Vec2 {
+    fn from(value: Vec2f) -> Self {
+        Vec2::new(value.x, value.y)
+    }
+}
+
+impl From<Vec2> for Vec2f {
+    fn from(value: Vec2) -> Self {
+        Vec2f::new([value.x, value.y])
+    }
+}
+
+impl From<Vec3f> for Vec3 {
+    fn from(value: Vec3f) -> Self {
+        Vec3::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<Vec3> for Vec3f {
+    fn from(value: Vec3) -> Self {
+        Vec3f::new([value.x, value.y, value.z])
+    }
+}
+
+impl From<Vec4f> for Vec4 {
+    fn from(value: Vec4f) -> Self {
+        Vec4::new(value.x, value.y, value.z, value.w)
+    }
+}
+
+impl From<Vec4> for Vec4f {
+    fn from(value: Vec4) -> Self {
+        Vec4f::new([value.x, value.y, value.z, value.w])
+    }
+}
+
+impl From<Vec2d> for DVec2 {
+    fn from(value: Vec2d) -> Self {
+        DVec2::new(value.x, value.y)
+    }
+}
+
+impl From<DVec2> for Vec2d {
+    fn from(value: DVec2) -> Self {
+        Vec2d::new([value.x, value.y])
+    }
+}
+
+impl From<Vec3d> for DVec3 {
+    fn from(value: Vec3d) -> Self {
+        DVec3::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<DVec3> for Vec3d {
+    fn from(value: DVec3) -> Self {
+        Vec3d::new([value.x, value.y, value.z])
+    }
+}
+
+// normalized on the way, glam expects unit quaternions
+impl From<Quat> for glam::Quat {
+    fn from(value: Quat) -> Self {
+        glam::Quat::from_xyzw(value.x, value.y, value.z, value.w).normalize()
+    }
+}
+
+impl From<glam::Quat> for Quat {
+    fn from(value: glam::Quat) -> Self {
+        Quat::new([value.w, value.x, value.y, value.z])
+    }
+}
+
+impl From<Matrix4f> for Mat4 {
+    fn from(value: Matrix4f) -> Self {
+        Mat4::from_cols_array_2d(&value.0)
+    }
+}
+
+impl From<Mat4> for Matrix4f {
+    fn from(value: Mat4) -> Self {
+        Matrix4f(value.to_cols_array_2d())
+    }
+}
+
+impl From<Matrix4d> for DMat4 {
+    fn from(value: Matrix4d) -> Self {
+        DMat4::from_cols_array_2d(&value.0)
+    }
+}
+
+impl From<DMat4> for Matrix4d {
+    fn from(value: DMat4) -> Self {
+        Matrix4d(value.to_cols_array_2d())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use glam::{DMat4, DVec2, DVec3, Mat4, Vec2, Vec3, Vec4};
+
+    use crate::types::{
+        matrices::{Matrix4d, Matrix4f},
+        quaternion::Quat,
+        vectors::{Vec2d, Vec2f, Vec3d, Vec3f, Vec4f},
+    };
+
+    // values in [-100, 100) from a fixed lcg
+    fn random(count: usize) -> Vec<f64> {
+        let mut seed: u64 = 13;
+        (0..count)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (seed >> 11) as f64 / (1u64 << 53) as f64 * 200.0 - 100.0
+            })
+            .collect()
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert_relative_eq!(a.x, b.x, epsilon = 1e-3, max_relative = 1e-5);
+        assert_relative_eq!(a.y, b.y, epsilon = 1e-3, max_relative = 1e-5);
+        assert_relative_eq!(a.z, b.z, epsilon = 1e-3, max_relative = 1e-5);
+    }
+
+    #[test]
+    fn test_vectors_round_trip() {
+        for x in random(400).chunks_exact(4) {
+            let f = x.iter().map(|x| *x as f32).collect::<Vec<_>>();
+            let vec2f = Vec2f::new([f[0], f[1]]);
+            assert_eq!(Vec2f::from(Vec2::from(vec2f)), vec2f);
+            let vec3f = Vec3f::new([f[0], f[1], f[2]]);
+            assert_eq!(Vec3f::from(Vec3::from(vec3f)), vec3f);
+            assert_eq!(Vec3::from(vec3f), Vec3::new(f[0], f[1], f[2]));
+            let vec4f = Vec4f::new([f[0], f[1], f[2], f[3]]);
+            assert_eq!(Vec4f::from(Vec4::from(vec4f)), vec4f);
+            let vec2d = Vec2d::new([x[0], x[1]]);
+            assert_eq!(Vec2d::from(DVec2::from(vec2d)), vec2d);
+            let vec3d = Vec3d::new([x[0], x[1], x[2]]);
+            assert_eq!(Vec3d::from(DVec3::from(vec3d)), vec3d);
+        }
+    }
+
+    #[test]
+    fn test_rotations_round_trip() {
+        for x in random(400).chunks_exact(4) {
+            let quat = Quat::from_axis_angle(Vec3f::new([x[0] as f32, x[1] as f32, x[2] as f32]), x[3] as f32 * 0.05);
+            let glam_quat = glam::Quat::from(quat);
+            let (back, quat) = (Quat::from(glam_quat), quat.normalize());
+            assert_relative_eq!(back.w, quat.w, epsilon = 1e-6);
+            assert_relative_eq!(back.x, quat.x, epsilon = 1e-6);
+            assert_relative_eq!(back.y, quat.y, epsilon = 1e-6);
+            assert_relative_eq!(back.z, quat.z, epsilon = 1e-6);
+
+            let vec = Vec3f::new([x[3] as f32, x[0] as f32, x[1] as f32]);
+            assert_close(glam_quat * Vec3::from(vec), Vec3::from(vec * quat));
+            assert_close(Mat4::from_quat(glam_quat).transform_vector3(Vec3::from(vec)), Vec3::from(quat.to_matrix().vec_mul(vec)));
+        }
+    }
+
+    #[test]
+    fn test_matrices_round_trip() {
+        let values = random(640);
+        let matrices: Vec<[[f64; 4]; 4]> = values.chunks_exact(16).map(|x| std::array::from_fn(|c| std::array::from_fn(|r| x[c * 4 + r]))).collect();
+        for pair in matrices.windows(2) {
+            let (a, b) = (Matrix4d(pair[0]), Matrix4d(pair[1]));
+            assert_eq!(Matrix4d::from(DMat4::from(a)), a);
+            assert!(DMat4::from(a * b).abs_diff_eq(DMat4::from(a) * DMat4::from(b), 1e-9));
+
+            let af = Matrix4f(pair[0].map(|x| x.map(|x| x as f32)));
+            assert_eq!(Matrix4f::from(Mat4::from(af)), af);
+            // translations land in the last column
+            assert_eq!(Mat4::from(af).w_axis.y, af.0[3][1]);
+            let vec = Vec4f::new([1.0, -2.0, 0.5, 1.0]);
+            assert!(Vec4::from(af * vec).abs_diff_eq(Mat4::from(af) * Vec4::from(vec), 1e-3));
+        }
+    }
+}
@@ -1,19 +1,46 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::{self, Debug}};
 
 use serde::{Deserialize, Serialize};
-use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, pipeline::graphics::rasterization::PolygonMode};
+use vulkano::{buffer::{BufferContents, BufferUsage, Subbuffer}, command_buffer::{allocator::CommandBufferAllocator, AutoCommandBufferBuilder}, pipeline::graphics::{depth_stencil::CompareOp, rasterization::PolygonMode}};
 use uuid::Uuid;
 
 use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
 
-use super::vectors::Vec3f;
+use super::{shader::{Shader, ShaderType}, staging_buffer::UpdatableRingBuffer, vectors::GpuVec3f};
 
+// fields missing from older packs take their default value
 #[derive(BufferContents, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 #[repr(C)]
 pub struct MaterialParameters {
-    pub diffuse_color: Vec3f,
+    pub diffuse_color: GpuVec3f,
     pub use_diffuse_texture: u32,
     pub use_normal_texture: u32,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: GpuVec3f,
+    pub use_metallic_roughness_texture: u32,
+    pub use_emissive_texture: u32,
+    pub use_occlusion_texture: u32,
+    // fragments with a lower alpha are discarded, 0 keeps every fragment
+    pub alpha_cutoff: f32,
+}
+
+impl Default for MaterialParameters {
+    fn default() -> Self {
+        MaterialParameters {
+            diffuse_color: GpuVec3f::new([1.0, 1.0, 1.0]),
+            use_diffuse_texture: 0,
+            use_normal_texture: 0,
+            metallic_factor: 0.0,
+            roughness_factor: 1.0,
+            emissive_factor: GpuVec3f::new([0.0, 0.0, 0.0]),
+            use_metallic_roughness_texture: 0,
+            use_emissive_texture: 0,
+            use_occlusion_texture: 0,
+            alpha_cutoff: 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -39,6 +66,103 @@ impl From<RenderingType> for PolygonMode {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DepthCompareOp {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always
+}
+
+impl From<DepthCompareOp> for CompareOp {
+    fn from(val: DepthCompareOp) -> Self {
+        match val {
+            DepthCompareOp::Never => CompareOp::Never,
+            DepthCompareOp::Less => CompareOp::Less,
+            DepthCompareOp::Equal => CompareOp::Equal,
+            DepthCompareOp::LessOrEqual => CompareOp::LessOrEqual,
+            DepthCompareOp::Greater => CompareOp::Greater,
+            DepthCompareOp::NotEqual => CompareOp::NotEqual,
+            DepthCompareOp::GreaterOrEqual => CompareOp::GreaterOrEqual,
+            DepthCompareOp::Always => CompareOp::Always
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthSettings {
+    pub test: bool,
+    pub write: bool,
+    pub compare: DepthCompareOp
+}
+
+impl DepthSettings {
+    pub fn ui() -> DepthSettings {
+        DepthSettings {
+            test: false,
+            write: false,
+            compare: DepthCompareOp::Always
+        }
+    }
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        DepthSettings {
+            test: true,
+            write: true,
+            compare: DepthCompareOp::Greater
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MaterialValidationError {
+    pub material: String,
+    pub shader: String,
+    pub set: u32,
+    pub kind: &'static str,
+    pub provided: u32,
+    pub expected: u32,
+}
+
+impl fmt::Display for MaterialValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "material '{}' provides {} {}{} but shader '{}' expects {} at set {}",
+            self.material,
+            self.provided,
+            self.kind,
+            if self.provided == 1 { "" } else { "s" },
+            self.shader,
+            self.expected,
+            self.set
+        )
+    }
+}
+
+// uses the mesh vertex shader with a fragment shader named "error", for materials that failed validation
+pub fn error_material(assets: &AssetLibrary) -> Option<Material> {
+    let (vertex_shader, _) = assets.shader_by_name("perspective")?;
+    let (fragment_shader, _) = assets.shader_by_name("error")?;
+
+    Some(Material::new(
+        "error".to_string(),
+        vertex_shader,
+        fragment_shader,
+        vec![],
+        None,
+        RenderingType::Fill,
+        false,
+        DepthSettings::default(),
+    ))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Material {
     pub name: String,
@@ -47,11 +171,25 @@ pub struct Material {
     pub attachments: Vec<Attachment>,
     pub parameters: Option<MaterialParameters>,
     pub rendering_type: RenderingType,
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default)]
+    pub depth: DepthSettings,
+    // optional stages between the vertex and fragment shaders
+    #[serde(default)]
+    pub geometry_shader: Option<Uuid>,
+    // control and evaluation shader, drawn as patches of three control points
+    #[serde(default)]
+    pub tessellation_shaders: Option<(Uuid, Uuid)>,
+    #[serde(skip)]
+    pub parameter_buffers: Option<UpdatableRingBuffer<MaterialParameters>>,
+    // frames whose parameter buffer still holds outdated values
     #[serde(skip)]
-    pub parameter_buffer: Option<Subbuffer<MaterialParameters>>,
+    stale_frames: Vec<bool>,
 }
 
 impl Material {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
         vertex_shader: Uuid,
@@ -59,6 +197,8 @@ impl Material {
         attachments: Vec<Attachment>,
         parameters: Option<MaterialParameters>,
         rendering_type: RenderingType,
+        transparent: bool,
+        depth: DepthSettings,
     ) -> Material {
         Material {
             name: name.to_string(),
@@ -66,30 +206,124 @@ impl Material {
             fragment_shader,
             attachments,
             parameters,
-            parameter_buffer: None,
-            rendering_type
+            parameter_buffers: None,
+            stale_frames: Vec::new(),
+            rendering_type,
+            transparent,
+            depth,
+            geometry_shader: None,
+            tessellation_shaders: None
+        }
+    }
+
+    // the optional stages in pipeline order
+    pub fn extra_shaders(&self) -> Vec<Uuid> {
+        let mut shaders = Vec::new();
+        if let Some((control, evaluation)) = self.tessellation_shaders {
+            shaders.extend([control, evaluation]);
+        }
+        shaders.extend(self.geometry_shader);
+        shaders
+    }
+
+    // descriptor sets bound by the rendering component drawing this material, as (set, bindings, kind)
+    fn provided_sets(&self, vertex_type: ShaderType) -> Option<Vec<(u32, u32, &'static str)>> {
+        let mut sets = match vertex_type {
+            ShaderType::Vertex => vec![(0, 1, "view projection buffer"), (1, 1, "model buffer")],
+            ShaderType::SkinnedVertex => vec![(0, 1, "view projection buffer"), (1, 2, "model and joint buffer")],
+            ShaderType::UiVertex => vec![(0, 1, "parameter buffer")],
+            _ => return None,
+        };
+        if !self.attachments.is_empty() {
+            sets.push((sets.len() as u32, self.attachments.len() as u32, "attachment"));
+        }
+        if self.parameters.is_some() && matches!(vertex_type, ShaderType::Vertex | ShaderType::SkinnedVertex) {
+            sets.push((sets.len() as u32, 1, "parameter buffer"));
+        }
+        Some(sets)
+    }
+
+    // `extra` are the shaders of extra_shaders
+    pub fn validate(&self, vertex: &Shader, fragment: &Shader, extra: &[&Shader]) -> Result<(), MaterialValidationError> {
+        let provided = match self.provided_sets(vertex.shader_type) {
+            Some(val) => val,
+            None => return Ok(()),
+        };
+
+        let mut expected: BTreeMap<u32, (u32, &Shader)> = BTreeMap::new();
+        for shader in [fragment].into_iter().chain(extra.iter().rev().copied()).chain([vertex]) {
+            for (set, count) in shader.descriptor_bindings.iter() {
+                let entry = expected.entry(*set).or_insert((*count, shader));
+                if *count > entry.0 {
+                    *entry = (*count, shader);
+                }
+            }
+        }
+
+        let error = |set: u32, kind: &'static str, provided: u32, expected: u32, shader: &Shader| MaterialValidationError {
+            material: self.name.clone(),
+            shader: shader.name.clone(),
+            set,
+            kind,
+            provided,
+            expected,
+        };
+
+        for (set, count, kind) in provided {
+            let (expected_count, shader) = expected.remove(&set).unwrap_or((0, fragment));
+            if expected_count != count {
+                return Err(error(set, kind, count, expected_count, shader));
+            }
+        }
+        if let Some((set, (expected_count, shader))) = expected.into_iter().next() {
+            return Err(error(set, "binding", 0, expected_count, shader));
+        }
+
+        Ok(())
+    }
+
+    pub fn parameter_buffer(&self, image_id: usize) -> Option<Subbuffer<MaterialParameters>> {
+        self.parameter_buffers.as_ref().map(|x| x.current(image_id).index(0))
+    }
+
+    // records the copy of parameters written since this frame last ran
+    pub fn flush_parameters<L, A: CommandBufferAllocator>(&mut self, image_id: usize, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        if let Some(buffers) = self.parameter_buffers.as_mut() {
+            buffers.flush(image_id, builder);
+        }
+    }
+
+    // the new values are written by MaterialUpdater once the frames using the old ones have retired
+    pub fn set_parameters(&mut self, state: &State, parameters: MaterialParameters) {
+        self.stage_parameters(parameters, state.renderer.frames_in_flight);
+    }
+
+    fn stage_parameters(&mut self, parameters: MaterialParameters, frames_in_flight: usize) {
+        self.parameters = Some(parameters);
+        self.stale_frames = vec![true; frames_in_flight];
+    }
+
+    fn take_stale_frame(&mut self, frame: usize) -> bool {
+        match self.stale_frames.get_mut(frame) {
+            Some(stale) => std::mem::replace(stale, false),
+            None => false,
+        }
+    }
+
+    fn write_parameters(&mut self, frame: usize) {
+        if let (Some(buffers), Some(parameters)) = (self.parameter_buffers.as_mut(), self.parameters.as_ref()) {
+            buffers.write(frame, std::slice::from_ref(parameters));
         }
     }
 
     pub fn load(&mut self, state: &State) {
         if self.parameters.is_none() { return; }
 
-        self.parameter_buffer = Some(
-            Buffer::new_sized::<MaterialParameters>(
-                state.memory_allocators.standard_memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_RANDOM_ACCESS,
-                    ..Default::default()
-                }
-            ).unwrap()
-        );
-        let mut content = self.parameter_buffer.as_ref().unwrap().write().unwrap();
-        *content = self.parameters.as_ref().unwrap().clone();
+        self.parameter_buffers = Some(UpdatableRingBuffer::new(state, BufferUsage::UNIFORM_BUFFER, 1));
+        self.stale_frames = vec![false; state.renderer.frames_in_flight];
+        for frame in 0..state.renderer.frames_in_flight {
+            self.write_parameters(frame);
+        }
     }
 }
 
@@ -104,3 +338,119 @@ impl System for MaterialLoader {
 
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
 }
+
+pub struct MaterialUpdater {}
+
+impl System for MaterialUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        for frame in (0..state.renderer.frames_in_flight).filter(|x| state.renderer.frame_retired(*x)) {
+            for (_, material) in assets.materials.iter_mut() {
+                if material.take_stale_frame(frame) {
+                    material.write_parameters(frame);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use super::{Attachment, DepthSettings, Material, MaterialParameters, RenderingType};
+    use crate::types::{shader::{Shader, ShaderType}, vectors::GpuVec3f};
+
+    fn shader(name: &str, shader_type: ShaderType, bindings: &[(u32, u32)]) -> Shader {
+        let mut shader = Shader::from_words(name.to_string(), shader_type, vec![]);
+        shader.descriptor_bindings = bindings.iter().copied().collect::<HashMap<_, _>>();
+        shader
+    }
+
+    fn material(attachments: usize) -> Material {
+        Material::new(
+            "crate.metal".to_string(),
+            Uuid::nil(),
+            Uuid::nil(),
+            vec![Attachment::DefaultTexture; attachments],
+            Some(parameters(1.0)),
+            RenderingType::Fill,
+            false,
+            DepthSettings::default(),
+        )
+    }
+
+    #[test]
+    fn test_material_matches_shaders() {
+        let vertex = shader("perspective", ShaderType::Vertex, &[(0, 1), (1, 1)]);
+        let fragment = shader("lit", ShaderType::Fragment, &[(2, 2), (3, 1)]);
+        assert_eq!(material(2).validate(&vertex, &fragment, &[]), Ok(()));
+    }
+
+    #[test]
+    fn test_material_attachment_mismatch() {
+        let vertex = shader("perspective", ShaderType::Vertex, &[(0, 1), (1, 1)]);
+        let fragment = shader("lit", ShaderType::Fragment, &[(2, 2), (3, 1)]);
+        let error = material(1).validate(&vertex, &fragment, &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "material 'crate.metal' provides 1 attachment but shader 'lit' expects 2 at set 2"
+        );
+    }
+
+    #[test]
+    fn test_material_missing_set() {
+        let vertex = shader("perspective", ShaderType::Vertex, &[(0, 1), (1, 1)]);
+        let fragment = shader("lit", ShaderType::Fragment, &[(2, 2), (3, 1), (4, 1)]);
+        let error = material(2).validate(&vertex, &fragment, &[]).unwrap_err();
+        assert_eq!((error.set, error.provided, error.expected), (4, 0, 1));
+    }
+
+    #[test]
+    fn test_material_geometry_shader_sets() {
+        let vertex = shader("perspective", ShaderType::Vertex, &[(0, 1), (1, 1)]);
+        let geometry = shader("debug_normals", ShaderType::Geometry, &[(0, 1), (4, 1)]);
+        let fragment = shader("lit", ShaderType::Fragment, &[(2, 2), (3, 1)]);
+        let mut material = material(2);
+        material.geometry_shader = Some(Uuid::from_u128(1));
+        material.tessellation_shaders = Some((Uuid::from_u128(2), Uuid::from_u128(3)));
+        assert_eq!(material.extra_shaders(), vec![Uuid::from_u128(2), Uuid::from_u128(3), Uuid::from_u128(1)]);
+
+        // a set only the geometry shader declares
+        let error = material.validate(&vertex, &fragment, &[&geometry]).unwrap_err();
+        assert_eq!((error.shader.as_str(), error.set), ("debug_normals", 4));
+    }
+
+    fn parameters(red: f32) -> MaterialParameters {
+        MaterialParameters {
+            diffuse_color: GpuVec3f::new([red, 0.0, 0.0]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_staged_parameters_written_once_per_frame() {
+        let mut material = Material::new(
+            "pulse".to_string(),
+            Uuid::nil(),
+            Uuid::nil(),
+            vec![],
+            Some(parameters(0.0)),
+            RenderingType::Fill,
+            false,
+            DepthSettings::default(),
+        );
+
+        for step in 0..100 {
+            material.stage_parameters(parameters(step as f32 / 100.0), 3);
+            let retired = step % 3;
+            assert!(material.take_stale_frame(retired));
+            assert!(!material.take_stale_frame(retired));
+            assert_eq!(material.parameters.as_ref().unwrap().diffuse_color.x, step as f32 / 100.0);
+        }
+        assert!(!material.take_stale_frame(3));
+    }
+}
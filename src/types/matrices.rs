@@ -2,9 +2,13 @@ use std::ops::Mul;
 
 use bytemuck::{Pod, Zeroable};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "simd")]
+use wide::f32x4;
 
-use crate::types::vectors::*;
+use crate::types::{quaternion::Quat, vectors::*};
 
+// column major, `.0[column][row]` like GLSL's mat4, translations go in `.0[3]`, `a * b` is the usual product
+// and vectors are multiplied from the right
 #[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
 #[repr(C)]
 pub struct Matrix4f(pub [[f32; 4]; 4]);
@@ -16,21 +20,49 @@ pub struct Matrix4d(pub [[f64; 4]; 4]);
 impl Mul for Matrix4f {
     type Output = Self;
 
+    // every column of the product is the columns of self weighted by the one in rhs
+    #[inline]
     fn mul(self, rhs: Self) -> Self::Output {
-        let mut output = Matrix4f::indentity();
-        for i in (0..4).step_by(1) {
-            for j in (0..4).step_by(1) {
-                output.0[i][j] = 0.0;
-                for k in (0..4).step_by(1) {
-                    output.0[i][j] += self.0[k][j] * rhs.0[i][k];
-                }
-            }
+        Matrix4f(rhs.0.map(|x| self.combine_columns(x)))
+    }
+}
+
+// the first N columns times `weights`, summed from the first without fused multiply-adds so both paths round the same
+#[cfg_attr(feature = "simd", allow(dead_code))]
+#[inline]
+fn combine_columns_scalar<const N: usize>(columns: &[[f32; 4]; 4], weights: [f32; N]) -> [f32; 4] {
+    let mut output = columns[0].map(|x| x * weights[0]);
+    for (column, weight) in columns.iter().zip(weights).skip(1) {
+        for (sum, x) in output.iter_mut().zip(column) {
+            *sum += x * weight;
         }
-        output
     }
+    output
+}
+
+#[cfg(feature = "simd")]
+#[inline]
+fn combine_columns_simd<const N: usize>(columns: &[[f32; 4]; 4], weights: [f32; N]) -> [f32; 4] {
+    let mut output = f32x4::from(columns[0]) * f32x4::splat(weights[0]);
+    for (column, weight) in columns.iter().zip(weights).skip(1) {
+        output += f32x4::from(*column) * f32x4::splat(weight);
+    }
+    output.to_array()
 }
 
 impl Matrix4f {
+    #[cfg(feature = "simd")]
+    #[inline]
+    fn combine_columns<const N: usize>(&self, weights: [f32; N]) -> [f32; 4] {
+        combine_columns_simd(&self.0, weights)
+    }
+
+    #[cfg(not(feature = "simd"))]
+    #[inline]
+    fn combine_columns<const N: usize>(&self, weights: [f32; N]) -> [f32; 4] {
+        combine_columns_scalar(&self.0, weights)
+    }
+
     pub fn indentity() -> Matrix4f {
         Matrix4f([
             [1.0, 0.0, 0.0, 0.0],
@@ -101,6 +133,8 @@ impl Matrix4f {
         Matrix4f::rotation_z(xyz.z) * Matrix4f::rotation_y(xyz.y) * Matrix4f::rotation_x(xyz.x)
     }
 
+    // reversed-Z like every projection here, depth is 1 at the near plane and goes to 0 in the distance,
+    // drawn with CompareOp::Greater into a depth buffer cleared to 0, this one has no far plane
     pub fn perspective(fovy: f32, aspect: f32, near: f32) -> Matrix4f {
         let f = 1.0 / (fovy / 2.0).tan();
         Matrix4f([
@@ -111,28 +145,47 @@ impl Matrix4f {
         ])
     }
 
-    pub fn look_at(eye: Vec3f, dir: Vec3f, mut up: Vec3f) -> Matrix4f {
-        up.x *= -1.0;
-        up.y *= -1.0;
-        up.z *= -1.0;
+    // depth reaches 0 at `far` and anything past it is clipped
+    pub fn perspective_finite(fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4f {
+        let f = 1.0 / (fovy / 2.0).tan();
+        let range = far - near;
+        Matrix4f([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, near / range, -1.0],
+            [0.0, 0.0, near * far / range, 0.0],
+        ])
+    }
+
+    // the box between the planes, looking down -z like the perspective ones, depth 1 at near and 0 at far
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Matrix4f {
+        let (width, height, range) = (right - left, top - bottom, far - near);
+        Matrix4f([
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, 2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0 / range, 0.0],
+            [-(right + left) / width, -(top + bottom) / height, far / range, 1.0],
+        ])
+    }
+
+    // looking down -z like the projections with x to the right, y points down the screen like vulkan's clip space
+    pub fn look_at(eye: Vec3f, dir: Vec3f, up: Vec3f) -> Matrix4f {
         let f = dir.normalize();
-        let u = f.cross(up.normalize()).normalize();
-        let v = u.cross(f);
+        let r = f.cross(up).normalize();
+        let d = f.cross(r);
 
         Matrix4f([
-            [u.x, v.x, f.x, 0.0],
-            [u.y, v.y, f.y, 0.0],
-            [u.z, v.z, f.z, 0.0],
-            [-eye.dot(u), -eye.dot(v), -eye.dot(f), 1.0],
+            [r.x, d.x, -f.x, 0.0],
+            [r.y, d.y, -f.y, 0.0],
+            [r.z, d.z, -f.z, 0.0],
+            [-eye.dot(r), -eye.dot(d), eye.dot(f), 1.0],
         ])
     }
 
+    #[inline]
     pub fn vec_mul(&self, vec: Vec3f) -> Vec3f {
-        Vec3f::new([
-            vec.x * self.0[0][0] + vec.y * self.0[1][0] + vec.z * self.0[2][0],
-            vec.x * self.0[0][1] + vec.y * self.0[1][1] + vec.z * self.0[2][1],
-            vec.x * self.0[0][2] + vec.y * self.0[1][2] + vec.z * self.0[2][2],
-        ])
+        let [x, y, z, _] = self.combine_columns([vec.x, vec.y, vec.z]);
+        Vec3f::new([x, y, z])
     }
 
     pub fn vec_mul_inv(&self, vec: Vec3f) -> Vec3f {
@@ -142,6 +195,150 @@ impl Matrix4f {
             vec.x * self.0[2][0] + vec.y * self.0[2][1] + vec.z * self.0[2][2],
         ])
     }
+
+    pub fn transpose(&self) -> Matrix4f {
+        let mut output = *self;
+        for i in 0..4 {
+            for j in 0..4 {
+                output.0[i][j] = self.0[j][i];
+            }
+        }
+        output
+    }
+
+    // products of the 2x2 blocks in the top two and bottom two rows, shared by the determinant and the inverse
+    fn minors(&self) -> ([f32; 6], [f32; 6]) {
+        let a = &self.0;
+        let s = [
+            a[0][0] * a[1][1] - a[1][0] * a[0][1],
+            a[0][0] * a[1][2] - a[1][0] * a[0][2],
+            a[0][0] * a[1][3] - a[1][0] * a[0][3],
+            a[0][1] * a[1][2] - a[1][1] * a[0][2],
+            a[0][1] * a[1][3] - a[1][1] * a[0][3],
+            a[0][2] * a[1][3] - a[1][2] * a[0][3],
+        ];
+        let c = [
+            a[2][0] * a[3][1] - a[3][0] * a[2][1],
+            a[2][0] * a[3][2] - a[3][0] * a[2][2],
+            a[2][0] * a[3][3] - a[3][0] * a[2][3],
+            a[2][1] * a[3][2] - a[3][1] * a[2][2],
+            a[2][1] * a[3][3] - a[3][1] * a[2][3],
+            a[2][2] * a[3][3] - a[3][2] * a[2][3],
+        ];
+        (s, c)
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    // None for singular matrices
+    pub fn inverse(&self) -> Option<Matrix4f> {
+        let (s, c) = self.minors();
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let a = &self.0;
+        let inv = 1.0 / det;
+        Some(Matrix4f([
+            [
+                (a[1][1] * c[5] - a[1][2] * c[4] + a[1][3] * c[3]) * inv,
+                (-a[0][1] * c[5] + a[0][2] * c[4] - a[0][3] * c[3]) * inv,
+                (a[3][1] * s[5] - a[3][2] * s[4] + a[3][3] * s[3]) * inv,
+                (-a[2][1] * s[5] + a[2][2] * s[4] - a[2][3] * s[3]) * inv,
+            ],
+            [
+                (-a[1][0] * c[5] + a[1][2] * c[2] - a[1][3] * c[1]) * inv,
+                (a[0][0] * c[5] - a[0][2] * c[2] + a[0][3] * c[1]) * inv,
+                (-a[3][0] * s[5] + a[3][2] * s[2] - a[3][3] * s[1]) * inv,
+                (a[2][0] * s[5] - a[2][2] * s[2] + a[2][3] * s[1]) * inv,
+            ],
+            [
+                (a[1][0] * c[4] - a[1][1] * c[2] + a[1][3] * c[0]) * inv,
+                (-a[0][0] * c[4] + a[0][1] * c[2] - a[0][3] * c[0]) * inv,
+                (a[3][0] * s[4] - a[3][1] * s[2] + a[3][3] * s[0]) * inv,
+                (-a[2][0] * s[4] + a[2][1] * s[2] - a[2][3] * s[0]) * inv,
+            ],
+            [
+                (-a[1][0] * c[3] + a[1][1] * c[1] - a[1][2] * c[0]) * inv,
+                (a[0][0] * c[3] - a[0][1] * c[1] + a[0][2] * c[0]) * inv,
+                (-a[3][0] * s[3] + a[3][1] * s[1] - a[3][2] * s[0]) * inv,
+                (a[2][0] * s[3] - a[2][1] * s[1] + a[2][2] * s[0]) * inv,
+            ],
+        ]))
+    }
+
+    // cheaper inverse for matrices with no projection, like the ones built from translation, rotation and scale,
+    // None for other or singular matrices
+    pub fn try_inverse_affine(&self) -> Option<Matrix4f> {
+        let a = &self.0;
+        if a[0][3] != 0.0 || a[1][3] != 0.0 || a[2][3] != 0.0 || a[3][3] != 1.0 {
+            return None;
+        }
+
+        let [x, y, z] = [a[0], a[1], a[2]].map(|column| Vec3f::new([column[0], column[1], column[2]]));
+        let det = x.dot(y.cross(z));
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        // rows of the inverted 3x3 part
+        let rows = [y.cross(z), z.cross(x), x.cross(y)].map(|row| row / det);
+        let translation = Vec3f::new([a[3][0], a[3][1], a[3][2]]);
+        Some(Matrix4f([
+            [rows[0].x, rows[1].x, rows[2].x, 0.0],
+            [rows[0].y, rows[1].y, rows[2].y, 0.0],
+            [rows[0].z, rows[1].z, rows[2].z, 0.0],
+            [-rows[0].dot(translation), -rows[1].dot(translation), -rows[2].dot(translation), 1.0],
+        ]))
+    }
+
+    #[inline]
+    pub fn mul_vec4(&self, vec: Vec4f) -> Vec4f {
+        Vec4f::new(self.combine_columns([vec.x, vec.y, vec.z, vec.w]))
+    }
+
+    // translation, rotation and scale of a matrix built like Transform::to_matrix, the scale is the length of
+    // every axis, a mirroring matrix comes back with a negative x scale, shear can't be represented and is dropped
+    // from the rotation which keeps the x axis, the other axes bend to stay perpendicular to it
+    pub fn decompose(&self) -> (Vec3f, Quat, Vec3f) {
+        let column = |i: usize| Vec3f::new([self.0[i][0], self.0[i][1], self.0[i][2]]);
+        let (x, y, z) = (column(0), column(1), column(2));
+        let mirrored = x.dot(y.cross(z)) < 0.0;
+        let sign = if mirrored { -1.0 } else { 1.0 };
+        let scale = Vec3f::new([x.length() * sign, y.length(), z.length()]);
+
+        // axes scaled to nothing are rebuilt from the others
+        let mut x_axis = x.normalize() * sign;
+        if x_axis.length_sqr() == 0.0 {
+            x_axis = y.cross(z).normalize();
+        }
+        if x_axis.length_sqr() == 0.0 {
+            x_axis = Vec3f::new([1.0, 0.0, 0.0]);
+        }
+        let mut y_axis = (y - x_axis * x_axis.dot(y)).normalize();
+        if y_axis.length_sqr() == 0.0 {
+            y_axis = (z * sign).cross(x_axis).normalize();
+        }
+        if y_axis.length_sqr() == 0.0 {
+            let other = if x_axis.x.abs() < 0.9 { Vec3f::new([1.0, 0.0, 0.0]) } else { Vec3f::new([0.0, 1.0, 0.0]) };
+            y_axis = x_axis.cross(other).normalize();
+        }
+        let rotation = Quat::from_axes(x_axis, y_axis, x_axis.cross(y_axis));
+
+        (column(3), rotation, scale)
+    }
+}
+
+impl Mul<Vec4f> for Matrix4f {
+    type Output = Vec4f;
+
+    #[inline]
+    fn mul(self, rhs: Vec4f) -> Self::Output {
+        self.mul_vec4(rhs)
+    }
 }
 
 impl Mul for Matrix4d {
@@ -232,6 +429,8 @@ impl Matrix4d {
         Matrix4d::rotation_z(xyz.z) * Matrix4d::rotation_y(xyz.y) * Matrix4d::rotation_x(xyz.x)
     }
 
+    // reversed-Z like every projection here, depth is 1 at the near plane and goes to 0 in the distance,
+    // drawn with CompareOp::Greater into a depth buffer cleared to 0, this one has no far plane
     pub fn perspective(fovy: f64, aspect: f64, near: f64) -> Matrix4d {
         let f = 1.0 / (fovy / 2.0).tan();
         Matrix4d([
@@ -242,19 +441,40 @@ impl Matrix4d {
         ])
     }
 
-    pub fn look_at(eye: Vec3d, dir: Vec3d, mut up: Vec3d) -> Matrix4d {
-        up.x *= -1.0;
-        up.y *= -1.0;
-        up.z *= -1.0;
+    // depth reaches 0 at `far` and anything past it is clipped
+    pub fn perspective_finite(fovy: f64, aspect: f64, near: f64, far: f64) -> Matrix4d {
+        let f = 1.0 / (fovy / 2.0).tan();
+        let range = far - near;
+        Matrix4d([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, near / range, -1.0],
+            [0.0, 0.0, near * far / range, 0.0],
+        ])
+    }
+
+    // the box between the planes, looking down -z like the perspective ones, depth 1 at near and 0 at far
+    pub fn orthographic(left: f64, right: f64, bottom: f64, top: f64, near: f64, far: f64) -> Matrix4d {
+        let (width, height, range) = (right - left, top - bottom, far - near);
+        Matrix4d([
+            [2.0 / width, 0.0, 0.0, 0.0],
+            [0.0, 2.0 / height, 0.0, 0.0],
+            [0.0, 0.0, 1.0 / range, 0.0],
+            [-(right + left) / width, -(top + bottom) / height, far / range, 1.0],
+        ])
+    }
+
+    // looking down -z like the projections with x to the right, y points down the screen like vulkan's clip space
+    pub fn look_at(eye: Vec3d, dir: Vec3d, up: Vec3d) -> Matrix4d {
         let f = dir.normalize();
-        let u = f.cross(up.normalize()).normalize();
-        let v = u.cross(f);
+        let r = f.cross(up).normalize();
+        let d = f.cross(r);
 
         Matrix4d([
-            [u.x, v.x, f.x, 0.0],
-            [u.y, v.y, f.y, 0.0],
-            [u.z, v.z, f.z, 0.0],
-            [-eye.dot(u), -eye.dot(v), -eye.dot(f), 1.0],
+            [r.x, d.x, -f.x, 0.0],
+            [r.y, d.y, -f.y, 0.0],
+            [r.z, d.z, -f.z, 0.0],
+            [-eye.dot(r), -eye.dot(d), eye.dot(f), 1.0],
         ])
     }
 
@@ -273,4 +493,299 @@ impl Matrix4d {
             vec.x * self.0[2][0] + vec.y * self.0[2][1] + vec.z * self.0[2][2],
         ])
     }
+
+    pub fn transpose(&self) -> Matrix4d {
+        let mut output = *self;
+        for i in 0..4 {
+            for j in 0..4 {
+                output.0[i][j] = self.0[j][i];
+            }
+        }
+        output
+    }
+
+    // products of the 2x2 blocks in the top two and bottom two rows, shared by the determinant and the inverse
+    fn minors(&self) -> ([f64; 6], [f64; 6]) {
+        let a = &self.0;
+        let s = [
+            a[0][0] * a[1][1] - a[1][0] * a[0][1],
+            a[0][0] * a[1][2] - a[1][0] * a[0][2],
+            a[0][0] * a[1][3] - a[1][0] * a[0][3],
+            a[0][1] * a[1][2] - a[1][1] * a[0][2],
+            a[0][1] * a[1][3] - a[1][1] * a[0][3],
+            a[0][2] * a[1][3] - a[1][2] * a[0][3],
+        ];
+        let c = [
+            a[2][0] * a[3][1] - a[3][0] * a[2][1],
+            a[2][0] * a[3][2] - a[3][0] * a[2][2],
+            a[2][0] * a[3][3] - a[3][0] * a[2][3],
+            a[2][1] * a[3][2] - a[3][1] * a[2][2],
+            a[2][1] * a[3][3] - a[3][1] * a[2][3],
+            a[2][2] * a[3][3] - a[3][2] * a[2][3],
+        ];
+        (s, c)
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    // None for singular matrices
+    pub fn inverse(&self) -> Option<Matrix4d> {
+        let (s, c) = self.minors();
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let a = &self.0;
+        let inv = 1.0 / det;
+        Some(Matrix4d([
+            [
+                (a[1][1] * c[5] - a[1][2] * c[4] + a[1][3] * c[3]) * inv,
+                (-a[0][1] * c[5] + a[0][2] * c[4] - a[0][3] * c[3]) * inv,
+                (a[3][1] * s[5] - a[3][2] * s[4] + a[3][3] * s[3]) * inv,
+                (-a[2][1] * s[5] + a[2][2] * s[4] - a[2][3] * s[3]) * inv,
+            ],
+            [
+                (-a[1][0] * c[5] + a[1][2] * c[2] - a[1][3] * c[1]) * inv,
+                (a[0][0] * c[5] - a[0][2] * c[2] + a[0][3] * c[1]) * inv,
+                (-a[3][0] * s[5] + a[3][2] * s[2] - a[3][3] * s[1]) * inv,
+                (a[2][0] * s[5] - a[2][2] * s[2] + a[2][3] * s[1]) * inv,
+            ],
+            [
+                (a[1][0] * c[4] - a[1][1] * c[2] + a[1][3] * c[0]) * inv,
+                (-a[0][0] * c[4] + a[0][1] * c[2] - a[0][3] * c[0]) * inv,
+                (a[3][0] * s[4] - a[3][1] * s[2] + a[3][3] * s[0]) * inv,
+                (-a[2][0] * s[4] + a[2][1] * s[2] - a[2][3] * s[0]) * inv,
+            ],
+            [
+                (-a[1][0] * c[3] + a[1][1] * c[1] - a[1][2] * c[0]) * inv,
+                (a[0][0] * c[3] - a[0][1] * c[1] + a[0][2] * c[0]) * inv,
+                (-a[3][0] * s[3] + a[3][1] * s[1] - a[3][2] * s[0]) * inv,
+                (a[2][0] * s[3] - a[2][1] * s[1] + a[2][2] * s[0]) * inv,
+            ],
+        ]))
+    }
+
+    // cheaper inverse for matrices with no projection, like the ones built from translation, rotation and scale,
+    // None for other or singular matrices
+    pub fn try_inverse_affine(&self) -> Option<Matrix4d> {
+        let a = &self.0;
+        if a[0][3] != 0.0 || a[1][3] != 0.0 || a[2][3] != 0.0 || a[3][3] != 1.0 {
+            return None;
+        }
+
+        let [x, y, z] = [a[0], a[1], a[2]].map(|column| Vec3d::new([column[0], column[1], column[2]]));
+        let det = x.dot(y.cross(z));
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        // rows of the inverted 3x3 part
+        let rows = [y.cross(z), z.cross(x), x.cross(y)].map(|row| row / det);
+        let translation = Vec3d::new([a[3][0], a[3][1], a[3][2]]);
+        Some(Matrix4d([
+            [rows[0].x, rows[1].x, rows[2].x, 0.0],
+            [rows[0].y, rows[1].y, rows[2].y, 0.0],
+            [rows[0].z, rows[1].z, rows[2].z, 0.0],
+            [-rows[0].dot(translation), -rows[1].dot(translation), -rows[2].dot(translation), 1.0],
+        ]))
+    }
+
+    // there's no double precision Vec4, so plain arrays
+    pub fn mul_vec4(&self, vec: [f64; 4]) -> [f64; 4] {
+        let [x, y, z, w] = vec;
+        std::array::from_fn(|i| x * self.0[0][i] + y * self.0[1][i] + z * self.0[2][i] + w * self.0[3][i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::types::{quaternion::Quat, vectors::{Vec3d, Vec3f, Vec4f}};
+
+    use super::{Matrix4d, Matrix4f};
+
+    extern crate nalgebra as na;
+
+    // well conditioned matrices from a fixed lcg, random entries on top of a strong diagonal
+    fn random_matrices(count: usize) -> Vec<[[f64; 4]; 4]> {
+        let mut seed: u64 = 11;
+        let mut random = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+        };
+        (0..count)
+            .map(|_| std::array::from_fn(|i| std::array::from_fn(|j| random() + if i == j { 3.0 } else { 0.0 })))
+            .collect()
+    }
+
+    fn assert_identity(matrix: Matrix4f) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_relative_eq!(matrix.0[i][j], if i == j { 1.0 } else { 0.0 }, epsilon = 1e-5);
+            }
+        }
+    }
+
+    // with or without the simd feature every entry is the same sum in the same order, bit for bit
+    #[test]
+    fn test_products_match_scalar_order() {
+        let matrices: Vec<Matrix4f> = random_matrices(40).into_iter().map(|x| Matrix4f(x.map(|x| x.map(|x| x as f32 * 7.3)))).collect();
+        let entry = |a: &Matrix4f, weights: &[f32], row: usize| {
+            (1..weights.len()).fold(a.0[0][row] * weights[0], |sum, k| sum + a.0[k][row] * weights[k])
+        };
+        for pair in matrices.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let product = a * b;
+            for (i, j) in (0..4).flat_map(|i| (0..4).map(move |j| (i, j))) {
+                assert_eq!(product.0[i][j].to_bits(), entry(&a, &b.0[i], j).to_bits());
+            }
+            let vec = b.0[0];
+            let moved = a.mul_vec4(Vec4f::new(vec));
+            let turned = a.vec_mul(Vec3f::new([vec[0], vec[1], vec[2]]));
+            for (j, value) in [moved.x, moved.y, moved.z, moved.w].into_iter().enumerate() {
+                assert_eq!(value.to_bits(), entry(&a, &vec, j).to_bits());
+            }
+            for (j, value) in [turned.x, turned.y, turned.z].into_iter().enumerate() {
+                assert_eq!(value.to_bits(), entry(&a, &vec[..3], j).to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverse_matches_na() {
+        for columns in random_matrices(100) {
+            let matrix = Matrix4f(columns.map(|x| x.map(|x| x as f32)));
+            let na_matrix = na::Matrix4::from_fn(|r, c| matrix.0[c][r]);
+            let inverse = matrix.inverse().unwrap();
+            let na_inverse = na_matrix.try_inverse().unwrap();
+            assert_relative_eq!(matrix.determinant(), na_matrix.determinant(), epsilon = 1e-5, max_relative = 1e-5);
+            for (r, c) in (0..4).flat_map(|r| (0..4).map(move |c| (r, c))) {
+                assert_relative_eq!(inverse.0[c][r], na_inverse[(r, c)], epsilon = 1e-5);
+                assert_eq!(matrix.transpose().0[c][r], matrix.0[r][c]);
+            }
+
+            let vec = Vec4f::new([0.5, -1.0, 2.0, 1.0]);
+            let na_vec = na_matrix * na::Vector4::new(0.5, -1.0, 2.0, 1.0);
+            let product = matrix * vec;
+            assert_relative_eq!(product.x, na_vec.x, epsilon = 1e-5);
+            assert_relative_eq!(product.y, na_vec.y, epsilon = 1e-5);
+            assert_relative_eq!(product.z, na_vec.z, epsilon = 1e-5);
+            assert_relative_eq!(product.w, na_vec.w, epsilon = 1e-5);
+
+            let matrix = Matrix4d(columns);
+            let na_matrix = na::Matrix4::from_fn(|r, c| matrix.0[c][r]);
+            let inverse = matrix.inverse().unwrap();
+            let na_inverse = na_matrix.try_inverse().unwrap();
+            assert_relative_eq!(matrix.determinant(), na_matrix.determinant(), max_relative = 1e-12);
+            for (r, c) in (0..4).flat_map(|r| (0..4).map(move |c| (r, c))) {
+                assert_relative_eq!(inverse.0[c][r], na_inverse[(r, c)], epsilon = 1e-12);
+            }
+            let product = matrix.mul_vec4([0.5, -1.0, 2.0, 1.0]);
+            let na_vec = na_matrix * na::Vector4::new(0.5, -1.0, 2.0, 1.0);
+            assert_relative_eq!(na::Vector4::from(product), na_vec, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_look_at_inverse() {
+        let view = Matrix4f::look_at(Vec3f::new([3.0, -2.0, 5.0]), Vec3f::new([-1.0, 0.5, -2.0]), Vec3f::new([0.0, 1.0, 0.0]));
+        assert_identity(view * view.inverse().unwrap());
+        assert_identity(view * view.try_inverse_affine().unwrap());
+        assert_identity(view.inverse().unwrap() * view);
+    }
+
+    // depth of a point `distance` in front of the camera
+    fn depth(projection: Matrix4f, distance: f32) -> f32 {
+        let clip = projection * Vec4f::new([0.0, 0.0, -distance, 1.0]);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn test_reversed_z() {
+        let infinite = Matrix4f::perspective(1.0, 1.5, 0.1);
+        assert_relative_eq!(depth(infinite, 0.1), 1.0);
+        assert!(depth(infinite, 1.0) > depth(infinite, 100.0));
+        assert!(depth(infinite, 1e6) > 0.0 && depth(infinite, 1e6) < 1e-6);
+
+        let finite = Matrix4f::perspective_finite(1.0, 1.5, 0.1, 100.0);
+        assert_relative_eq!(depth(finite, 0.1), 1.0, epsilon = 1e-6);
+        assert_relative_eq!(depth(finite, 100.0), 0.0, epsilon = 1e-6);
+        assert!(depth(finite, 200.0) < 0.0);
+        // x and y match the infinite one
+        assert_eq!((finite.0[0][0], finite.0[1][1]), (infinite.0[0][0], infinite.0[1][1]));
+
+        let orthographic = Matrix4f::orthographic(-4.0, 2.0, -1.0, 3.0, 0.5, 50.5);
+        assert_relative_eq!(depth(orthographic, 0.5), 1.0, epsilon = 1e-6);
+        assert_relative_eq!(depth(orthographic, 25.5), 0.5, epsilon = 1e-6);
+        assert_relative_eq!(depth(orthographic, 50.5), 0.0, epsilon = 1e-6);
+        let corner = orthographic * Vec4f::new([2.0, -1.0, -10.0, 1.0]);
+        assert_relative_eq!(corner.x, 1.0, epsilon = 1e-6);
+        assert_relative_eq!(corner.y, -1.0, epsilon = 1e-6);
+        assert_eq!(corner.w, 1.0);
+    }
+
+    fn assert_matrix_eq(a: Matrix4f, b: Matrix4f) {
+        for (a, b) in a.0.iter().flatten().zip(b.0.iter().flatten()) {
+            assert_relative_eq!(*a, *b, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_decompose_round_trip() {
+        let rotation = Quat::from_axis_angle(Vec3f::new([0.4, -1.0, 0.3]), 2.2);
+        let translation = Vec3f::new([4.0, -1.0, 2.5]);
+        for scale in [[1.0, 1.0, 1.0], [2.0, 0.5, 3.0], [-2.0, 0.5, 3.0], [2.0, -0.5, 3.0], [0.0, 1.0, 2.0]] {
+            let scale = Vec3f::new(scale);
+            let matrix = Matrix4f::translation(translation) * rotation.to_matrix() * Matrix4f::scale(scale);
+            let (t, r, s) = matrix.decompose();
+            assert_eq!(t, translation);
+            assert_relative_eq!(r.length(), 1.0, epsilon = 1e-5);
+            assert_matrix_eq(Matrix4f::translation(t) * r.to_matrix() * Matrix4f::scale(s), matrix);
+            if scale.x > 0.0 && scale.y > 0.0 {
+                assert_matrix_eq(r.to_matrix(), rotation.to_matrix());
+                assert_relative_eq!(s.x, scale.x, epsilon = 1e-5);
+                assert_relative_eq!(s.y, scale.y, epsilon = 1e-5);
+                assert_relative_eq!(s.z, scale.z, epsilon = 1e-5);
+            }
+        }
+        // one mirrored axis comes back on x
+        let (_, _, s) = (rotation.to_matrix() * Matrix4f::scale(Vec3f::new([2.0, -0.5, 3.0]))).decompose();
+        assert!(s.x < 0.0 && s.y > 0.0 && s.z > 0.0);
+
+        // shear keeps the x axis and the lengths, the rotation is still a rotation
+        let mut sheared = Matrix4f::scale(Vec3f::new([2.0, 1.0, 1.0]));
+        sheared.0[1][0] = 1.0;
+        let (_, r, s) = sheared.decompose();
+        assert_relative_eq!(r.length(), 1.0, epsilon = 1e-5);
+        assert_matrix_eq(r.to_matrix(), Matrix4f::indentity());
+        assert_relative_eq!(s.y, 2.0f32.sqrt(), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_affine_and_singular() {
+        let trs = Matrix4f::translation(Vec3f::new([4.0, -1.0, 2.5]))
+            * Matrix4f::rotation_yxz(Vec3f::new([0.3, 1.2, -0.7]))
+            * Matrix4f::scale(Vec3f::new([2.0, 0.5, 3.0]));
+        let fast = trs.try_inverse_affine().unwrap();
+        let general = trs.inverse().unwrap();
+        for (a, b) in fast.0.iter().flatten().zip(general.0.iter().flatten()) {
+            assert_relative_eq!(*a, *b, epsilon = 1e-5);
+        }
+        assert_identity(trs * fast);
+
+        let trs = Matrix4d::translation(Vec3d::new([4.0, -1.0, 2.5])) * Matrix4d::scale(Vec3d::new([2.0, 0.5, 3.0]));
+        assert_eq!(trs.try_inverse_affine().unwrap().mul_vec4([4.0, -1.0, 2.5, 1.0]), [0.0, 0.0, 0.0, 1.0]);
+
+        // projections aren't affine
+        assert_eq!(Matrix4f::perspective(1.0, 1.5, 0.1).try_inverse_affine(), None);
+        assert!(Matrix4f::perspective(1.0, 1.5, 0.1).inverse().is_some());
+        let flat = Matrix4f::scale(Vec3f::new([1.0, 0.0, 1.0]));
+        assert_eq!(flat.determinant(), 0.0);
+        assert_eq!(flat.inverse(), None);
+        assert_eq!(flat.try_inverse_affine(), None);
+    }
 }
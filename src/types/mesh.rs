@@ -1,21 +1,111 @@
-use std::{sync::{mpsc::{self, Receiver, Sender}, Arc}, thread::{self}};
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, device::Device, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator}};
+use vulkano::{buffer::{AllocateBufferError, BufferContents, BufferUsage, IndexBuffer, Subbuffer}, command_buffer::CopyBufferInfo, memory::allocator::StandardMemoryAllocator, Validated};
 use log::{debug, error};
 
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, loaders::{gltf::load_gltf, obj::load_obj}, rendering::VertexData, state::State};
+use crate::{asset_library::AssetLibrary, asset_loading::{UploadContext, UploadError, UploadJob}, ecs::{System, World}, engine_config::AssetPaths, loaders::{gltf::load_gltf, obj::load_obj}, rendering::{SkinVertexData, VertexData}, state::State, vulkan::transfer::TransferManager, types::{aabb::Aabb, mesh_optimizer::{deduplicate_by, flat_normals, optimize_vertex_cache, skin_key, smooth_normals, vertex_key}, model::{fold_lods, Model}}};
+
+// meshes with fewer vertices than u16::MAX use half the index memory,
+// 0xFFFF stays free as the primitive restart index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredIndexData")]
+pub enum IndexData {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+#[derive(Deserialize)]
+enum TaggedIndexData {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+// packs written before IndexData stored a plain u32 list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredIndexData {
+    Tagged(TaggedIndexData),
+    Plain(Vec<u32>),
+}
+
+impl From<StoredIndexData> for IndexData {
+    fn from(value: StoredIndexData) -> Self {
+        match value {
+            StoredIndexData::Tagged(TaggedIndexData::U16(indices)) => IndexData::U16(indices),
+            StoredIndexData::Tagged(TaggedIndexData::U32(indices)) => IndexData::U32(indices),
+            StoredIndexData::Plain(indices) => {
+                let vertex_count = indices.iter().max().map_or(0, |x| *x as usize + 1);
+                IndexData::new(indices, vertex_count)
+            }
+        }
+    }
+}
+
+impl IndexData {
+    pub fn new(indices: Vec<u32>, vertex_count: usize) -> IndexData {
+        if vertex_count <= u16::MAX as usize {
+            IndexData::U16(indices.into_iter().map(|x| x as u16).collect())
+        } else {
+            IndexData::U32(indices)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            IndexData::U16(indices) => indices.len(),
+            IndexData::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_u32(&self) -> Vec<u32> {
+        match self {
+            IndexData::U16(indices) => indices.iter().map(|x| *x as u32).collect(),
+            IndexData::U32(indices) => indices.clone(),
+        }
+    }
+
+    fn write_range(&mut self, start: usize, data: &[u32]) {
+        match self {
+            IndexData::U16(indices) => write_range(indices, start, &data.iter().map(|x| *x as u16).collect::<Vec<_>>()),
+            IndexData::U32(indices) => write_range(indices, start, data),
+        }
+    }
+
+    fn create_buffer(&self, transfer: &TransferManager, memory_allocator: Arc<StandardMemoryAllocator>) -> Result<IndexBuffer, Validated<AllocateBufferError>> {
+        Ok(match self {
+            IndexData::U16(indices) => IndexBuffer::U16(
+                transfer.upload_buffer(memory_allocator, BufferUsage::INDEX_BUFFER, indices.iter().copied())?
+            ),
+            IndexData::U32(indices) => IndexBuffer::U32(
+                transfer.upload_buffer(memory_allocator, BufferUsage::INDEX_BUFFER, indices.iter().copied())?
+            ),
+        })
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Mesh {
     pub name: String,
     pub vertices: Vec<VertexData>,
-    pub indices: Vec<u32>,
+    pub indices: IndexData,
+    // joints and weights for every vertex of a skinned mesh
+    #[serde(default)]
+    pub skin: Option<Vec<SkinVertexData>>,
+    pub aabb: Aabb,
+    // distance of the farthest vertex from the mesh origin
+    pub bounding_radius: f32,
     #[serde(skip)]
     pub vertex_buffer: Option<Arc<Subbuffer<[VertexData]>>>,
     #[serde(skip)]
-    pub index_buffer: Option<Arc<Subbuffer<[u32]>>>,
+    pub index_buffer: Option<Arc<IndexBuffer>>,
+    #[serde(skip)]
+    pub skin_buffer: Option<Arc<Subbuffer<[SkinVertexData]>>>,
     #[serde(skip)]
     pub transfer_requested: bool,
     #[serde(skip)]
@@ -23,63 +113,98 @@ pub struct Mesh {
     #[serde(skip)]
     pub new_vertices: Option<Vec<VertexData>>,
     #[serde(skip)]
-    pub new_indices: Option<Vec<u32>>,
+    pub new_indices: Option<IndexData>,
+    // uploads that failed in a row
+    #[serde(skip)]
+    pub failed_uploads: u32,
 }
 
 #[derive(Debug)]
-struct MeshUpdateSubmitData {
-    uuid: Uuid,
+pub struct MeshUpload {
+    pub uuid: Uuid,
     vertices: Vec<VertexData>,
-    indices: Vec<u32>,
-}
-
-type MeshSubbuffers = (Uuid, Subbuffer<[VertexData]>, Subbuffer<[u32]>, Vec<VertexData>, Vec<u32>);
-
-fn run_worker(
-    device: Arc<Device>,
-    work_recv: Receiver<MeshUpdateSubmitData>,
-    buffer_send: Sender<MeshSubbuffers>
-) {
-    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
-    thread::spawn(move || {
-        while let Ok(submit_data) = work_recv.recv() {
-            let res = (
-                submit_data.uuid,
-                Buffer::from_iter(
-                    memory_allocator.clone(),
-                    BufferCreateInfo {
-                        usage: BufferUsage::VERTEX_BUFFER,
-                        ..Default::default()
-                    },
-                    AllocationCreateInfo {
-                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                        MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                        ..Default::default()
-                    },
-                    submit_data.vertices.clone()
-                ).unwrap(),
-                Buffer::from_iter(
-                    memory_allocator.clone(),
-                    BufferCreateInfo {
-                        usage: BufferUsage::INDEX_BUFFER,
-                        ..Default::default()
-                    },
-                    AllocationCreateInfo {
-                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                        MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                        ..Default::default()
-                    },
-                    submit_data.indices.clone()
-                ).unwrap(),
-                submit_data.vertices,
-                submit_data.indices
-            );
-            
-            let _ = memory_allocator.clone();
-
-            buffer_send.send(res).unwrap();
-        }
-    });
+    indices: IndexData,
+    skin: Option<Vec<SkinVertexData>>,
+}
+
+#[derive(Debug)]
+pub struct UploadedMesh {
+    pub uuid: Uuid,
+    vertex_buffer: Subbuffer<[VertexData]>,
+    index_buffer: IndexBuffer,
+    skin_buffer: Option<Subbuffer<[SkinVertexData]>>,
+    vertices: Vec<VertexData>,
+    indices: IndexData,
+}
+
+fn create_vertex_buffer<T: BufferContents>(
+    transfer: &TransferManager,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    data: Vec<T>
+) -> Result<Subbuffer<[T]>, Validated<AllocateBufferError>> {
+    transfer.upload_buffer(memory_allocator, BufferUsage::VERTEX_BUFFER, data)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeUpload {
+    InPlace,
+    Full,
+}
+
+// an edit is copied into the existing buffer unless it doesn't fit or a full upload is already queued
+fn plan_range_upload(start: usize, count: usize, capacity: Option<usize>, uploading: bool) -> RangeUpload {
+    match capacity {
+        Some(capacity) if !uploading && start + count <= capacity => RangeUpload::InPlace,
+        _ => RangeUpload::Full,
+    }
+}
+
+// overwrites from `start` on, growing the list when the data runs past its end
+fn write_range<T: Copy>(target: &mut Vec<T>, start: usize, data: &[T]) {
+    assert!(start <= target.len(), "Range starts past the end of the mesh");
+    let overlap = data.len().min(target.len() - start);
+    target[start..start + overlap].copy_from_slice(&data[..overlap]);
+    target.extend_from_slice(&data[overlap..]);
+}
+
+fn copy_range<T: BufferContents + Copy>(state: &State, target: Subbuffer<[T]>, start: usize, data: &[T]) {
+    let transfer = &state.memory_allocators.transfer;
+    let staging = transfer
+        .staging_buffer(state.memory_allocators.standard_memory_allocator.clone(), data.iter().copied())
+        .unwrap();
+
+    let mut copies = transfer.copy_builder();
+    copies
+        .copy_buffer(CopyBufferInfo::buffers(staging, target.slice(start as u64..(start + data.len()) as u64)))
+        .unwrap();
+
+    // frames still in flight may be reading the buffer
+    state.renderer.wait_for_frames();
+    transfer.submit(copies, None);
+}
+
+fn bounds(vertices: &[VertexData]) -> (Aabb, f32) {
+    let aabb = Aabb::from_points(vertices.iter().map(|x| x.position.into())).expect("Empty vertex list not allowed!");
+    let radius = vertices.iter().map(|x| x.position.length_sqr()).fold(0.0, f32::max).sqrt();
+    (aabb, radius)
+}
+
+impl MeshUpload {
+    pub(crate) fn run(self, context: &UploadContext) -> Result<UploadedMesh, UploadError> {
+        let uuid = self.uuid;
+        let error = |e: Validated<AllocateBufferError>| UploadError { uuid, message: e.to_string() };
+        Ok(UploadedMesh {
+            uuid,
+            vertex_buffer: create_vertex_buffer(&context.transfer, context.memory_allocator.clone(), self.vertices.clone()).map_err(error)?,
+            index_buffer: self.indices.create_buffer(&context.transfer, context.memory_allocator.clone()).map_err(error)?,
+            skin_buffer: self.skin
+                .map(|x| create_vertex_buffer(&context.transfer, context.memory_allocator.clone(), x))
+                .transpose()
+                .map_err(error)?,
+            vertices: self.vertices,
+            indices: self.indices
+        })
+    }
 }
 
 impl Mesh {
@@ -95,54 +220,132 @@ impl Mesh {
             panic!("Index larger than vertex buffer length!");
         }
 
+        let (aabb, bounding_radius) = bounds(&vertices);
         Mesh {
             name: name.to_string(),
-            vertices: vertices.clone(),
-            indices: indices.clone(),
+            indices: IndexData::new(indices, vertices.len()),
+            vertices,
+            skin: None,
+            aabb,
+            bounding_radius,
             vertex_buffer: None,
             index_buffer: None,
+            skin_buffer: None,
             transfer_requested: false,
             transfering: false,
             new_vertices: None,
-            new_indices: None
+            new_indices: None,
+            failed_uploads: 0
+        }
+    }
+
+    pub fn new_skinned(name: &str, vertices: Vec<VertexData>, indices: Vec<u32>, skin: Vec<SkinVertexData>) -> Mesh {
+        if skin.len() != vertices.len() {
+            panic!("Skin data doesn't match the vertex count!");
+        }
+
+        let mut mesh = Mesh::new(name, vertices, indices);
+        mesh.skin = Some(skin);
+        mesh
+    }
+
+    // same triangles with shared vertices merged, in an order friendlier to the vertex cache
+    pub fn optimize(&mut self) {
+        let (sources, indices) = deduplicate_by(self.vertices.len(), &self.indices.to_u32(), |x| {
+            (vertex_key(&self.vertices[x]), self.skin.as_ref().map(|skin| skin_key(&skin[x])))
+        });
+        self.indices = IndexData::new(optimize_vertex_cache(&indices, sources.len()), sources.len());
+        self.vertices = sources.iter().map(|x| self.vertices[*x as usize]).collect();
+        if let Some(skin) = self.skin.as_mut() {
+            *skin = sources.iter().map(|x| skin[*x as usize]).collect();
+        }
+    }
+
+    // recalculates normals from the triangles, flat shading splits the vertices shared by differently facing faces,
+    // uvs and tangents are kept, the result still has to be uploaded with `load`
+    pub fn recompute_normals(&mut self, smooth: bool) {
+        let indices = self.indices.to_u32();
+        if smooth {
+            let normals = smooth_normals(&self.vertices, &indices);
+            for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+                vertex.normal = normal.into();
+            }
+            return;
+        }
+
+        let (sources, normals, indices) = flat_normals(&self.vertices, &indices);
+        self.vertices = sources
+            .iter()
+            .zip(normals)
+            .map(|(x, normal)| VertexData { normal: normal.into(), ..self.vertices[*x as usize] })
+            .collect();
+        self.indices = IndexData::new(indices, sources.len());
+        if let Some(skin) = self.skin.as_mut() {
+            *skin = sources.iter().map(|x| skin[*x as usize]).collect();
         }
     }
 
     pub fn load(&mut self, _state: &State, vertices: Vec<VertexData>, indices: Vec<u32>) {
+        (self.aabb, self.bounding_radius) = bounds(&vertices);
         self.transfer_requested = true;
+        self.new_indices = Some(IndexData::new(indices, vertices.len()));
         self.new_vertices = Some(vertices);
-        self.new_indices = Some(indices);
+    }
+
+    // replaces the vertices from `start` on, only that range is copied into the existing buffer,
+    // a mesh that grows or already waits for an upload goes through `load`
+    pub fn update_vertices_range(&mut self, state: &State, start: usize, data: &[VertexData]) {
+        let capacity = self.vertex_buffer.as_ref().map(|x| x.len() as usize);
+        match plan_range_upload(start, data.len(), capacity, self.transfer_requested || self.transfering) {
+            RangeUpload::InPlace => {
+                write_range(&mut self.vertices, start, data);
+                (self.aabb, self.bounding_radius) = bounds(&self.vertices);
+                copy_range(state, self.vertex_buffer.as_ref().unwrap().as_ref().clone(), start, data);
+            }
+            RangeUpload::Full => {
+                let mut vertices = self.new_vertices.take().unwrap_or_else(|| self.vertices.clone());
+                write_range(&mut vertices, start, data);
+                let indices = self.new_indices.take().unwrap_or_else(|| self.indices.clone()).to_u32();
+                self.load(state, vertices, indices);
+            }
+        }
+    }
+
+    // same as update_vertices_range for the index buffer
+    pub fn update_indices_range(&mut self, state: &State, start: usize, data: &[u32]) {
+        let vertex_count = self.new_vertices.as_ref().unwrap_or(&self.vertices).len();
+        if data.iter().any(|x| *x as usize >= vertex_count) {
+            panic!("Index larger than vertex buffer length!");
+        }
+
+        let capacity = self.index_buffer.as_ref().map(|x| x.len() as usize);
+        match (plan_range_upload(start, data.len(), capacity, self.transfer_requested || self.transfering), self.index_buffer.as_deref()) {
+            (RangeUpload::InPlace, Some(IndexBuffer::U16(buffer))) => {
+                self.indices.write_range(start, data);
+                copy_range(state, buffer.clone(), start, &data.iter().map(|x| *x as u16).collect::<Vec<_>>());
+            }
+            (RangeUpload::InPlace, Some(IndexBuffer::U32(buffer))) => {
+                self.indices.write_range(start, data);
+                copy_range(state, buffer.clone(), start, data);
+            }
+            _ => {
+                let vertices = self.new_vertices.take().unwrap_or_else(|| self.vertices.clone());
+                let mut indices = self.new_indices.take().unwrap_or_else(|| self.indices.clone()).to_u32();
+                write_range(&mut indices, start, data);
+                self.load(state, vertices, indices);
+            }
+        }
     }
 
     pub fn load_immidiate(&mut self, state: &State, vertices: Vec<VertexData>, indices: Vec<u32>) {
         self.vertices.clone_from(&vertices);
-        self.indices.clone_from(&indices);
-        self.vertex_buffer = Some(Arc::new(Buffer::from_iter(
-            state.memory_allocators.standard_memory_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::VERTEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            vertices
-        ).unwrap()));
-        self.index_buffer = Some(Arc::new(Buffer::from_iter(
-            state.memory_allocators.standard_memory_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::INDEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            indices
-        ).unwrap()));
+        self.indices = IndexData::new(indices, vertices.len());
+        (self.aabb, self.bounding_radius) = bounds(&vertices);
+        let memory_allocator = &state.memory_allocators.standard_memory_allocator;
+        let transfer = &state.memory_allocators.transfer;
+        self.vertex_buffer = Some(Arc::new(create_vertex_buffer(transfer, memory_allocator.clone(), vertices).unwrap()));
+        self.index_buffer = Some(Arc::new(self.indices.create_buffer(transfer, memory_allocator.clone()).unwrap()));
+        self.skin_buffer = self.skin.clone().map(|x| Arc::new(create_vertex_buffer(transfer, memory_allocator.clone(), x).unwrap()));
     }
 }
 
@@ -158,43 +361,60 @@ impl DynamicMesh {
     }
 
     pub fn load_material(&mut self, assets: &AssetLibrary) {
-        self.material = *assets.materials.iter().find(|(_, v)| v.name == self.material_name).expect("Material not found").0;
+        self.material = match assets.material_by_name(&self.material_name) {
+            Some((uuid, _)) => uuid,
+            None => panic!("Material {} not found", self.material_name)
+        };
     }
 }
 
-pub struct MeshBufferLoader {
-    work_send: Sender<MeshUpdateSubmitData>,
-    buffer_recv: Receiver<MeshSubbuffers>
-}
+// a mesh failing this many uploads in a row stays without buffers
+const MAX_UPLOAD_ATTEMPTS: u32 = 3;
 
-impl MeshBufferLoader {
-    pub fn new(state: &mut State) -> MeshBufferLoader {
-        let (work_send, work_recv) = mpsc::channel();
-        let (buffer_send, buffer_recv) = mpsc::channel();
-        run_worker(state.vulkan_context.device.clone(), work_recv, buffer_send);
-        MeshBufferLoader {
-            work_send,
-            buffer_recv
-        }
-    }
-}
+pub struct MeshBufferLoader {}
 
 impl System for MeshBufferLoader {
     fn on_start(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
-        for (_, mesh) in assets.meshes.iter_mut() {
-            mesh.load_immidiate(state, mesh.vertices.clone(), mesh.indices.clone());
+        if let Err(e) = assets.load_pending_meshes() {
+            error!("{}", e);
+        }
+        for (uuid, mesh) in assets.meshes.iter_mut() {
+            mesh.transfering = true;
+            state.asset_loading.submit(UploadJob::Mesh(MeshUpload {
+                uuid: *uuid,
+                vertices: mesh.vertices.clone(),
+                indices: mesh.indices.clone(),
+                skin: mesh.skin.clone()
+            }));
         }
     }
 
-    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, _state: &mut State) {
-        while let Ok(ret_data) = self.buffer_recv.try_recv() {
-            let (uuid, vertex, index, vertices, indices) = ret_data;
-            if let Some(mesh) = assets.meshes.get_mut(&uuid) {
-                mesh.vertex_buffer = Some(Arc::new(vertex));
-                mesh.index_buffer = Some(Arc::new(index));
-                mesh.vertices = vertices;
-                mesh.indices = indices;
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        for result in state.asset_loading.finished_meshes() {
+            let uploaded = match result {
+                Ok(uploaded) => uploaded,
+                Err(e) => {
+                    error!("{}", e);
+                    let Some(mesh) = assets.meshes.get_mut(&e.uuid) else { continue };
+                    mesh.transfering = false;
+                    mesh.failed_uploads += 1;
+                    // a newer request replaces the failed data anyway
+                    if mesh.failed_uploads < MAX_UPLOAD_ATTEMPTS && !mesh.transfer_requested {
+                        mesh.transfer_requested = true;
+                        mesh.new_vertices = Some(mesh.vertices.clone());
+                        mesh.new_indices = Some(mesh.indices.clone());
+                    }
+                    continue;
+                }
+            };
+            if let Some(mesh) = assets.meshes.get_mut(&uploaded.uuid) {
+                mesh.vertex_buffer = Some(Arc::new(uploaded.vertex_buffer));
+                mesh.index_buffer = Some(Arc::new(uploaded.index_buffer));
+                mesh.skin_buffer = uploaded.skin_buffer.map(Arc::new);
+                mesh.vertices = uploaded.vertices;
+                mesh.indices = uploaded.indices;
                 mesh.transfering = false;
+                mesh.failed_uploads = 0;
             }
         }
 
@@ -202,25 +422,59 @@ impl System for MeshBufferLoader {
             if mesh.transfer_requested && !mesh.transfering {
                 mesh.transfer_requested = false;
                 mesh.transfering = true;
-                let data = MeshUpdateSubmitData {
+                // the cpu copy follows right away so range updates build on the data being uploaded
+                mesh.vertices = mesh.new_vertices.take().unwrap();
+                mesh.indices = mesh.new_indices.take().unwrap();
+                state.asset_loading.submit(UploadJob::Mesh(MeshUpload {
                     uuid: *uuid,
-                    vertices: mesh.new_vertices.take().unwrap(),
-                    indices: mesh.new_indices.take().unwrap()
-                };
-                self.work_send.send(data).unwrap();
+                    vertices: mesh.vertices.clone(),
+                    indices: mesh.indices.clone(),
+                    skin: mesh.skin.clone()
+                }));
             }
         }
     }
+
+    fn on_exit(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        state.asset_loading.shutdown();
+    }
 }
 
-pub fn load_model_meshes(assets: &mut AssetLibrary) {
+#[allow(clippy::result_unit_err)]
+pub fn load_model(model_name: &str, assets: &mut AssetLibrary, paths: &AssetPaths) -> Result<Model, ()> {
+    debug!("Loading model {}", model_name);
+    let mut model = match model_name.split_once('.') {
+        Some((name, "obj")) => load_obj(name.to_string(), assets, paths)?,
+        Some((name, extension @ ("gltf" | "glb"))) => load_gltf(name.to_string(), extension, assets, paths)?,
+        _ => return Err(())
+    };
+    model.name = model_name.to_string();
+    let lod_distances = assets.model_by_name(model_name).map(|(_, x)| x.lod_distances.clone()).unwrap_or_default();
+    fold_lods(&mut model.nodes, &lod_distances);
+
+    if assets.model_by_name(model_name).is_none_or(|(_, x)| x.optimize) {
+        let mut meshes: Vec<Uuid> = model.meshes_and_materials().into_iter().map(|(mesh, _)| mesh).collect();
+        meshes.sort();
+        meshes.dedup();
+
+        let (mut before, mut after) = (0, 0);
+        for mesh_uuid in meshes.iter() {
+            let mesh = assets.meshes.get_mut(mesh_uuid).unwrap();
+            before += mesh.vertices.len();
+            mesh.optimize();
+            after += mesh.vertices.len();
+        }
+        debug!("Optimized {}: {} -> {} vertices", model_name, before, after);
+    }
+    Ok(model)
+}
+
+pub fn load_model_meshes(assets: &mut AssetLibrary, paths: &AssetPaths) {
     let len = assets.models.len();
     for i in 0..len {
         let model_name = assets.models.values().nth(i).unwrap().name.clone();
-        debug!("Loading model {}", model_name);
-        let mam = match model_name.split_once('.') {
-            Some((name, "obj")) => load_obj(name.to_string(), assets).expect("Failed to load"),
-            Some((name, "gltf")) => load_gltf(name.to_string(), assets).expect("Failed to load"),
+        let loaded = match model_name.split_once('.') {
+            Some((_, "obj" | "gltf" | "glb")) => load_model(&model_name, assets, paths).expect("Failed to load"),
             Some(_) => {
                 error!("Unsupportes format {}", model_name);
                 continue;
@@ -230,7 +484,10 @@ pub fn load_model_meshes(assets: &mut AssetLibrary) {
                 continue;
             }
         };
-        assets.models.values_mut().nth(i).unwrap().meshes_and_materials = mam;
+        let model = assets.models.values_mut().nth(i).unwrap();
+        model.nodes = loaded.nodes;
+        model.skins = loaded.skins;
+        model.animations = loaded.animations;
     }
 }
 
@@ -248,3 +505,188 @@ impl System for DynamicMeshMaterialLoader {
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
 }
 
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use crate::{rendering::{SkinVertexData, VertexData}, types::{aabb::Aabb, vectors::{GpuVec2f, GpuVec3f, Vec3f}}};
+
+    use super::{bounds, plan_range_upload, write_range, IndexData, Mesh, RangeUpload};
+
+    #[test]
+    fn test_index_type_follows_vertex_count() {
+        let small = Mesh::new("small", vec![VertexData::zeroed(); u16::MAX as usize], vec![0, 1, 65534]);
+        assert_eq!(small.indices, IndexData::U16(vec![0, 1, 65534]));
+
+        let large = Mesh::new("large", vec![VertexData::zeroed(); u16::MAX as usize + 1], vec![0, 1, 65535]);
+        assert_eq!(large.indices, IndexData::U32(vec![0, 1, 65535]));
+        assert_eq!(large.indices.len(), 3);
+    }
+
+    #[test]
+    fn test_reads_plain_u32_indices() {
+        let plain = rmp_serde::to_vec(&vec![0u32, 1, 2]).unwrap();
+        let indices: IndexData = rmp_serde::from_slice(&plain).unwrap();
+        assert_eq!(indices, IndexData::U16(vec![0, 1, 2]));
+
+        for indices in [IndexData::U16(vec![3, 4, 5]), IndexData::U32(vec![0, 70000, 1])] {
+            let bytes = rmp_serde::to_vec(&indices).unwrap();
+            assert_eq!(rmp_serde::from_slice::<IndexData>(&bytes).unwrap(), indices);
+        }
+    }
+
+    #[test]
+    fn test_optimize_keeps_skin_per_vertex() {
+        let vertex = |x: f32| VertexData { position: GpuVec3f::new([x, 0.0, 0.0]), ..VertexData::zeroed() };
+        let skin = |joint: u32| SkinVertexData { joints: [joint, 0, 0, 0], weights: [1.0, 0.0, 0.0, 0.0] };
+        // the last two vertices share a position but not a joint, so they can't be merged
+        let mut mesh = Mesh::new_skinned(
+            "skinned",
+            vec![vertex(0.0), vertex(1.0), vertex(2.0), vertex(1.0), vertex(2.0)],
+            vec![0, 1, 2, 0, 3, 4],
+            vec![skin(0), skin(1), skin(2), skin(1), skin(3)]
+        );
+        mesh.optimize();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        let skin = mesh.skin.as_ref().unwrap();
+        let joints: Vec<(u32, u32)> = mesh.indices.to_u32().iter()
+            .map(|x| (mesh.vertices[*x as usize].position.x as u32, skin[*x as usize].joints[0]))
+            .collect();
+        let mut triangles: Vec<_> = joints.chunks_exact(3).map(|x| x.to_vec()).collect();
+        triangles.sort();
+        assert_eq!(triangles, vec![vec![(0, 0), (1, 1), (2, 2)], vec![(0, 0), (1, 1), (2, 3)]]);
+    }
+
+    #[test]
+    fn test_small_edits_stay_in_place() {
+        const VERTEX_COUNT: usize = 100_000;
+        let mut vertices = vec![VertexData::zeroed(); VERTEX_COUNT];
+        let edit = vec![VertexData { position: GpuVec3f::new([0.0, 1.0, 0.0]), ..VertexData::zeroed() }; VERTEX_COUNT / 100];
+
+        // a terrain brush touching 1% of the mesh every frame
+        for frame in 0..100 {
+            let start = frame * 7919 % (VERTEX_COUNT - edit.len());
+            assert_eq!(plan_range_upload(start, edit.len(), Some(VERTEX_COUNT), false), RangeUpload::InPlace);
+            write_range(&mut vertices, start, &edit);
+        }
+        assert_eq!(vertices.len(), VERTEX_COUNT);
+        assert_eq!(vertices[7919].position.y, 1.0);
+    }
+
+    #[test]
+    fn test_growing_or_busy_meshes_reupload() {
+        assert_eq!(plan_range_upload(90, 20, Some(100), false), RangeUpload::Full);
+        assert_eq!(plan_range_upload(0, 10, Some(100), true), RangeUpload::Full);
+        assert_eq!(plan_range_upload(0, 10, None, false), RangeUpload::Full);
+
+        let mut indices = IndexData::U16(vec![0, 1, 2]);
+        indices.write_range(2, &[3, 4, 5]);
+        assert_eq!(indices, IndexData::U16(vec![0, 1, 3, 4, 5]));
+    }
+
+    fn cube() -> Mesh {
+        let vertices = (0..8)
+            .map(|x| VertexData {
+                position: GpuVec3f::new([(x & 1) as f32 - 0.5, ((x >> 1) & 1) as f32 - 0.5, (x >> 2) as f32 - 0.5]),
+                ..VertexData::zeroed()
+            })
+            .collect();
+        let indices = vec![
+            0, 2, 1, 1, 2, 3, // -z
+            4, 5, 6, 5, 7, 6, // +z
+            0, 1, 4, 1, 5, 4, // -y
+            2, 6, 3, 3, 6, 7, // +y
+            0, 4, 2, 2, 4, 6, // -x
+            1, 3, 5, 3, 7, 5, // +x
+        ];
+        Mesh::new("cube", vertices, indices)
+    }
+
+    // uv sphere with a duplicated seam column, like an exported one
+    fn sphere(rings: u32, segments: u32) -> Mesh {
+        let mut vertices = Vec::new();
+        for ring in 0..=rings {
+            for segment in 0..=segments {
+                let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+                let phi = std::f32::consts::TAU * (segment % segments) as f32 / segments as f32;
+                vertices.push(VertexData {
+                    position: GpuVec3f::new([theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()]),
+                    uv: GpuVec2f::new([segment as f32 / segments as f32, ring as f32 / rings as f32]),
+                    ..VertexData::zeroed()
+                });
+            }
+        }
+
+        let mut indices = Vec::new();
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * (segments + 1) + segment;
+                let b = a + segments + 1;
+                indices.extend([a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+        Mesh::new("sphere", vertices, indices)
+    }
+
+    #[test]
+    fn test_bounds_follow_vertices() {
+        let mut mesh = cube();
+        assert_eq!(mesh.aabb, Aabb::new(Vec3f::new([-0.5, -0.5, -0.5]), Vec3f::new([0.5, 0.5, 0.5])));
+        assert!((mesh.bounding_radius - 0.75f32.sqrt()).abs() < 1e-6);
+
+        let far = VertexData { position: GpuVec3f::new([0.0, 4.0, 0.0]), ..VertexData::zeroed() };
+        write_range(&mut mesh.vertices, 8, &[far]);
+        (mesh.aabb, mesh.bounding_radius) = bounds(&mesh.vertices);
+        assert_eq!(mesh.aabb.max.y, 4.0);
+        assert_eq!(mesh.bounding_radius, 4.0);
+    }
+
+    #[test]
+    fn test_flat_normals_on_cube() {
+        let mut mesh = cube();
+        mesh.vertices[0].uv = GpuVec2f::new([0.25, 0.75]);
+        mesh.recompute_normals(false);
+
+        // every corner is split into one vertex per face
+        assert_eq!(mesh.vertices.len(), 24);
+        let indices = mesh.indices.to_u32();
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|x| mesh.vertices[x as usize]);
+            let center = (*a.position + *b.position + *c.position) / 3.0;
+            // the face normal is the axis the face's center is offset along
+            let axis = [center.x, center.y, center.z].map(|x| if x.abs() > 0.4 { x.signum() } else { 0.0 });
+            for vertex in [a, b, c] {
+                assert!((*vertex.normal - Vec3f::new(axis)).length() < 1e-5);
+            }
+        }
+        assert!(mesh.vertices.iter().filter(|x| x.position.x == -0.5 && x.position.y == -0.5 && x.position.z == -0.5)
+            .all(|x| x.uv.x == 0.25 && x.uv.y == 0.75));
+    }
+
+    #[test]
+    fn test_smooth_normals_on_sphere() {
+        let mut mesh = sphere(32, 64);
+        let uvs: Vec<_> = mesh.vertices.iter().map(|x| x.uv).collect();
+        mesh.recompute_normals(true);
+
+        for (vertex, uv) in mesh.vertices.iter().zip(uvs) {
+            assert!((*vertex.normal - *vertex.position).length() < 2e-2);
+            assert_eq!(vertex.uv, uv);
+        }
+    }
+
+    #[test]
+    fn test_degenerate_triangles_keep_normals() {
+        let up = Vec3f::new([0.0, 1.0, 0.0]);
+        let vertex = |x: f32, z: f32| VertexData { position: GpuVec3f::new([x, 0.0, z]), normal: up.into(), ..VertexData::zeroed() };
+        // a proper triangle, a zero-area one hanging off it and the first one again
+        let vertices = vec![vertex(0.0, 0.0), vertex(0.0, 1.0), vertex(1.0, 0.0), vertex(2.0, 0.0), vertex(3.0, 0.0)];
+        for smooth in [true, false] {
+            let mut mesh = Mesh::new("flat", vertices.clone(), vec![0, 1, 2, 2, 3, 4, 0, 1, 2]);
+            mesh.recompute_normals(smooth);
+            assert!(mesh.vertices.iter().all(|x| !x.normal.x.is_nan() && (*x.normal - up).length() < 1e-5));
+        }
+    }
+}
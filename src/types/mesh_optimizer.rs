@@ -0,0 +1,273 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{rendering::{SkinVertexData, VertexData}, types::vectors::Vec3f};
+
+// vertices closer than this on every attribute are merged
+const QUANTIZATION: f32 = 100000.0;
+
+// faces with a smaller cross product than this are treated as degenerate
+const DEGENERATE_AREA: f32 = 1e-12;
+
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+fn quantize(value: f32) -> i64 {
+    (value * QUANTIZATION).round() as i64
+}
+
+pub(crate) fn vertex_key(vertex: &VertexData) -> [i64; 12] {
+    let VertexData { position, uv, normal, tangent } = vertex;
+    [
+        position.x, position.y, position.z,
+        uv.x, uv.y,
+        normal.x, normal.y, normal.z,
+        tangent.x, tangent.y, tangent.z, tangent.w,
+    ]
+    .map(quantize)
+}
+
+pub(crate) fn skin_key(skin: &SkinVertexData) -> ([u32; 4], [i64; 4]) {
+    (skin.joints, skin.weights.map(quantize))
+}
+
+// merges vertices with the same key and drops the ones no index refers to,
+// returns which original vertex every merged one was taken from and the new indices
+pub fn deduplicate_by<K: Hash + Eq>(vertex_count: usize, indices: &[u32], key: impl Fn(usize) -> K) -> (Vec<u32>, Vec<u32>) {
+    let mut sources = Vec::new();
+    let mut by_key: HashMap<K, u32> = HashMap::new();
+    let mut remap: Vec<Option<u32>> = vec![None; vertex_count];
+
+    let indices = indices
+        .iter()
+        .map(|index| {
+            *remap[*index as usize].get_or_insert_with(|| {
+                *by_key.entry(key(*index as usize)).or_insert_with(|| {
+                    sources.push(*index);
+                    (sources.len() - 1) as u32
+                })
+            })
+        })
+        .collect();
+
+    (sources, indices)
+}
+
+pub fn deduplicate(vertices: &[VertexData], indices: &[u32]) -> (Vec<VertexData>, Vec<u32>) {
+    let (sources, indices) = deduplicate_by(vertices.len(), indices, |x| vertex_key(&vertices[x]));
+    (sources.iter().map(|x| vertices[*x as usize]).collect(), indices)
+}
+
+// not normalized, the length is twice the triangle's area
+fn face_normal(vertices: &[VertexData], triangle: &[u32]) -> Vec3f {
+    let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|x| *vertices[x as usize].position);
+    (b - a).cross(c - a)
+}
+
+fn normalized(normal: Vec3f) -> Option<Vec3f> {
+    (normal.length_sqr() > DEGENERATE_AREA).then(|| normal.normalize())
+}
+
+// area-weighted average of the faces around every position, vertices split on uv seams still get the same normal,
+// vertices only touched by degenerate faces keep the one they had
+pub fn smooth_normals(vertices: &[VertexData], indices: &[u32]) -> Vec<Vec3f> {
+    let position_key = |x: usize| [vertices[x].position.x, vertices[x].position.y, vertices[x].position.z].map(quantize);
+    let mut sums: HashMap<[i64; 3], Vec3f> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let normal = face_normal(vertices, triangle);
+        for vertex in triangle {
+            *sums.entry(position_key(*vertex as usize)).or_insert(Vec3f::new([0.0, 0.0, 0.0])) += normal;
+        }
+    }
+
+    (0..vertices.len())
+        .map(|x| sums.get(&position_key(x)).and_then(|x| normalized(*x)).unwrap_or(*vertices[x].normal))
+        .collect()
+}
+
+// gives every face its own normal, corners are split wherever faces with different normals met,
+// returns which original vertex every new one was taken from, their normals and the new indices
+pub fn flat_normals(vertices: &[VertexData], indices: &[u32]) -> (Vec<u32>, Vec<Vec3f>, Vec<u32>) {
+    let corner_normal = |corner: usize| {
+        let triangle = corner / 3 * 3;
+        indices.get(triangle..triangle + 3).and_then(|x| normalized(face_normal(vertices, x)))
+    };
+
+    let (corners, new_indices) = deduplicate_by(indices.len(), &(0..indices.len() as u32).collect::<Vec<_>>(), |x| {
+        (indices[x], corner_normal(x).map(|x| [x.x, x.y, x.z].map(quantize)))
+    });
+    let normals = corners
+        .iter()
+        .map(|x| corner_normal(*x as usize).unwrap_or(*vertices[indices[*x as usize] as usize].normal))
+        .collect();
+    (corners.iter().map(|x| indices[*x as usize]).collect(), normals, new_indices)
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        None => 0.0,
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+    };
+    cache_score + VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+fn best_triangle(scores: &[f32], emitted: &[bool]) -> Option<usize> {
+    (0..scores.len())
+        .filter(|x| !emitted[*x])
+        .max_by(|a, b| scores[*a].total_cmp(&scores[*b]))
+}
+
+// reorders triangles for the post-transform vertex cache (Tom Forsyth's linear-speed algorithm),
+// every triangle keeps its winding
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let triangle = |id: usize| [indices[3 * id], indices[3 * id + 1], indices[3 * id + 2]];
+
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for id in 0..triangle_count {
+        for vertex in triangle(id) {
+            vertex_triangles[vertex as usize].push(id);
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = vertex_triangles.iter().map(|x| vertex_score(None, x.len())).collect();
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+        .map(|id| triangle(id).iter().map(|x| vertex_scores[*x as usize]).sum())
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut optimized = Vec::with_capacity(indices.len());
+
+    let mut next = best_triangle(&triangle_scores, &emitted);
+    while let Some(id) = next {
+        emitted[id] = true;
+        let vertices = triangle(id);
+        optimized.extend_from_slice(&vertices);
+        for vertex in vertices {
+            vertex_triangles[vertex as usize].retain(|x| *x != id);
+        }
+
+        let mut new_cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        for vertex in vertices.into_iter().chain(cache.iter().copied()) {
+            if !new_cache.contains(&vertex) {
+                new_cache.push(vertex);
+            }
+        }
+
+        for (position, vertex) in new_cache.iter().enumerate() {
+            let vertex = *vertex as usize;
+            cache_positions[vertex] = (position < CACHE_SIZE).then_some(position);
+            vertex_scores[vertex] = vertex_score(cache_positions[vertex], vertex_triangles[vertex].len());
+        }
+
+        next = None;
+        for vertex in new_cache.iter() {
+            for candidate in vertex_triangles[*vertex as usize].iter() {
+                triangle_scores[*candidate] = triangle(*candidate).iter().map(|x| vertex_scores[*x as usize]).sum();
+                if next.is_none_or(|x: usize| triangle_scores[*candidate] > triangle_scores[x]) {
+                    next = Some(*candidate);
+                }
+            }
+        }
+        if next.is_none() {
+            next = best_triangle(&triangle_scores, &emitted);
+        }
+
+        new_cache.truncate(CACHE_SIZE);
+        cache = new_cache;
+    }
+
+    // a trailing partial triangle isn't drawn anyway, keep it so the index count doesn't change
+    optimized.extend_from_slice(&indices[triangle_count * 3..]);
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use crate::{
+        rendering::VertexData,
+        types::vectors::{GpuVec2f, GpuVec3f},
+    };
+
+    use super::{deduplicate, optimize_vertex_cache, vertex_key};
+
+    fn vertex(x: f32, y: f32) -> VertexData {
+        VertexData {
+            position: GpuVec3f::new([x, y, 0.0]),
+            uv: GpuVec2f::new([x, y]),
+            normal: GpuVec3f::new([0.0, 0.0, 1.0]),
+            ..VertexData::zeroed()
+        }
+    }
+
+    // triangles as vertex keys, rotated so each starts at its smallest vertex to keep the winding
+    fn triangles(vertices: &[VertexData], indices: &[u32]) -> Vec<[[i64; 12]; 3]> {
+        let mut triangles: Vec<_> = indices
+            .chunks_exact(3)
+            .map(|x| {
+                let mut triangle = [x[0], x[1], x[2]].map(|i| vertex_key(&vertices[i as usize]));
+                let first = (0..3).min_by_key(|i| triangle[*i]).unwrap();
+                triangle.rotate_left(first);
+                triangle
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    // an unindexed grid, every quad stores its own six vertices
+    fn duplicated_grid(size: u32) -> (Vec<VertexData>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let (x, y) = (x as f32, y as f32);
+                vertices.extend([
+                    vertex(x, y), vertex(x + 1.0, y), vertex(x + 1.0, y + 1.0),
+                    vertex(x, y), vertex(x + 1.0, y + 1.0), vertex(x, y + 1.0),
+                ]);
+            }
+        }
+        let indices = (0..vertices.len() as u32).collect();
+        (vertices, indices)
+    }
+
+    #[test]
+    fn test_deduplicate_keeps_triangles() {
+        let (vertices, indices) = duplicated_grid(4);
+        let (unique, remapped) = deduplicate(&vertices, &indices);
+
+        assert_eq!(vertices.len(), 96);
+        assert_eq!(unique.len(), 25);
+        assert_eq!(triangles(&vertices, &indices), triangles(&unique, &remapped));
+    }
+
+    #[test]
+    fn test_cache_order_keeps_triangles() {
+        let (vertices, indices) = deduplicate(&duplicated_grid(8).0, &duplicated_grid(8).1);
+        let optimized = optimize_vertex_cache(&indices, vertices.len());
+
+        assert_eq!(optimized.len(), indices.len());
+        assert_eq!(triangles(&vertices, &indices), triangles(&vertices, &optimized));
+    }
+
+    #[test]
+    fn test_merges_nearly_equal_vertices() {
+        let vertices = vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0 + 1e-7, 0.0), vertex(0.0, 1.0)];
+        let (unique, indices) = deduplicate(&vertices, &[0, 1, 3, 0, 2, 3]);
+        assert_eq!(unique.len(), 3);
+        assert_eq!(indices, vec![0, 1, 2, 0, 1, 2]);
+    }
+}
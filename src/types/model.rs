@@ -1,39 +1,320 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{asset_library::AssetLibrary, ecs::System};
+use crate::{asset_library::AssetLibrary, ecs::System, types::{aabb::Aabb, animation::{AnimationClip, Skin}, quaternion::Quat, vectors::Vec3f}};
+
+// translation, rotation and scale, applied as v * scale * rotation + translation like a Transform
+pub type NodeTransform = (Vec3f, Quat, Vec3f);
+
+pub fn identity_transform() -> NodeTransform {
+    (Vec3f::new([0.0, 0.0, 0.0]), Quat::new([1.0, 0.0, 0.0, 0.0]), Vec3f::new([1.0, 1.0, 1.0]))
+}
+
+// places `local` inside `parent`, exact as long as the parent scale is uniform
+pub fn compose(parent: &NodeTransform, local: &NodeTransform) -> NodeTransform {
+    let (position, rotation, scale) = *parent;
+    (local.0 * scale * rotation + position, local.1 * rotation, local.2 * scale)
+}
+
+// spacing of LOD levels without a distance in the model description
+pub const DEFAULT_LOD_DISTANCE: f32 = 25.0;
+
+// a simpler version of a node's meshes, drawn from `distance` on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelLod {
+    pub distance: f32,
+    pub meshes_and_materials: Vec<(Uuid, Uuid)>,
+}
+
+fn lod_name(name: &str) -> Option<(&str, usize)> {
+    let (base, level) = name.rsplit_once("_LOD")?;
+    Some((base, level.parse().ok()?))
+}
+
+// folds nodes named like Rock_LOD1, Rock_LOD2 into their sibling Rock_LOD0 (or Rock),
+// `distances` are where LOD1, LOD2, ... start, the LOD nodes' own transforms and children are dropped
+pub fn fold_lods(nodes: &mut Vec<ModelNode>, distances: &[f32]) {
+    for node in nodes.iter_mut() {
+        fold_lods(&mut node.children, distances);
+    }
+
+    let (lods, rest): (Vec<ModelNode>, Vec<ModelNode>) = std::mem::take(nodes)
+        .into_iter()
+        .partition(|x| lod_name(&x.name).is_some_and(|(_, level)| level > 0));
+    *nodes = rest;
+
+    for lod in lods {
+        let (base, level) = lod_name(&lod.name).unwrap();
+        match nodes.iter_mut().find(|x| x.name == base || lod_name(&x.name) == Some((base, 0))) {
+            Some(node) => node.lods.push(ModelLod {
+                distance: distances.get(level - 1).copied().unwrap_or(DEFAULT_LOD_DISTANCE * level as f32),
+                meshes_and_materials: lod.mesh_primitives,
+            }),
+            // nothing to be a simpler version of
+            None => nodes.push(lod),
+        }
+    }
+    for node in nodes.iter_mut() {
+        node.lods.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    }
+}
+
+// level to draw at `distance` when `current` was drawn last frame, a level is only left
+// once the distance is `hysteresis` / 2 past its boundary so it doesn't flicker
+pub fn select_lod(lods: &[ModelLod], current: usize, distance: f32, hysteresis: f32) -> usize {
+    let mut level = current.min(lods.len());
+    while level < lods.len() && distance > lods[level].distance + hysteresis / 2.0 {
+        level += 1;
+    }
+    while level > 0 && distance < lods[level - 1].distance - hysteresis / 2.0 {
+        level -= 1;
+    }
+    level
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelNode {
+    pub name: String,
+    pub transform: NodeTransform,
+    pub mesh_primitives: Vec<(Uuid, Uuid)>,
+    pub children: Vec<ModelNode>,
+    // index into Model::skins deforming this node's meshes
+    #[serde(default)]
+    pub skin: Option<usize>,
+    // LOD1 onwards sorted by distance, mesh_primitives are LOD0
+    #[serde(default)]
+    pub lods: Vec<ModelLod>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ModelPrimitive {
+    pub transform: NodeTransform,
+    pub mesh: Uuid,
+    pub material: Uuid,
+    pub skin: Option<usize>,
+    pub lod: usize,
+}
+
+impl ModelNode {
+    pub fn new(name: String, transform: NodeTransform, mesh_primitives: Vec<(Uuid, Uuid)>) -> ModelNode {
+        ModelNode {
+            name,
+            transform,
+            mesh_primitives,
+            children: Vec::new(),
+            skin: None,
+            lods: Vec::new(),
+        }
+    }
+
+    // primitives of every LOD level
+    pub fn all_primitives(&self) -> impl Iterator<Item = &(Uuid, Uuid)> {
+        self.mesh_primitives.iter().chain(self.lods.iter().flat_map(|x| x.meshes_and_materials.iter()))
+    }
+
+    // depth first, parents before their children
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a ModelNode)) {
+        f(self);
+        for child in self.children.iter() {
+            child.visit(f);
+        }
+    }
+
+    fn visit_primitives_mut(&mut self, f: &mut impl FnMut(&mut (Uuid, Uuid))) {
+        self.mesh_primitives.iter_mut().for_each(&mut *f);
+        for lod in self.lods.iter_mut() {
+            lod.meshes_and_materials.iter_mut().for_each(&mut *f);
+        }
+        for child in self.children.iter_mut() {
+            child.visit_primitives_mut(f);
+        }
+    }
+
+    fn collect_primitives(
+        &self,
+        parent: &NodeTransform,
+        overrides: &[(String, NodeTransform)],
+        levels: &[usize],
+        index: &mut usize,
+        primitives: &mut Vec<ModelPrimitive>
+    ) {
+        let local = match overrides.get(*index) {
+            Some((name, transform)) if *name == self.name => transform,
+            _ => &self.transform
+        };
+        let lod = levels.get(*index).copied().unwrap_or(0).min(self.lods.len());
+        *index += 1;
+
+        let transform = compose(parent, local);
+        // joint matrices already place skinned vertices relative to the model origin
+        let placed = if self.skin.is_some() { identity_transform() } else { transform };
+        let meshes_and_materials = match lod {
+            0 => &self.mesh_primitives,
+            level => &self.lods[level - 1].meshes_and_materials
+        };
+        primitives.extend(meshes_and_materials.iter().map(|(mesh, material)| ModelPrimitive {
+            transform: placed,
+            mesh: *mesh,
+            material: *material,
+            skin: self.skin,
+            lod,
+        }));
+        for child in self.children.iter() {
+            child.collect_primitives(&transform, overrides, levels, index, primitives);
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Model {
     pub name: String,
-    pub meshes_and_materials: Vec<(Uuid, Uuid)>
+    pub nodes: Vec<ModelNode>,
+    // merge duplicate vertices and reorder indices when the meshes are imported
+    #[serde(default = "default_optimize")]
+    pub optimize: bool,
+    #[serde(default)]
+    pub skins: Vec<Skin>,
+    #[serde(default)]
+    pub animations: Vec<AnimationClip>,
+    // where LOD1, LOD2, ... start, from the model description
+    #[serde(default)]
+    pub lod_distances: Vec<f32>,
+}
+
+pub fn default_optimize() -> bool {
+    true
 }
 
 impl Model {
     pub fn new(name: String) -> Model {
         Model {
             name,
-            meshes_and_materials: Vec::new(),
+            nodes: Vec::new(),
+            optimize: true,
+            skins: Vec::new(),
+            animations: Vec::new(),
+            lod_distances: Vec::new(),
+        }
+    }
+
+    // every primitive of every node and LOD level, a mesh shared by several nodes shows up once per node
+    pub fn meshes_and_materials(&self) -> Vec<(Uuid, Uuid)> {
+        let mut meshes_and_materials = Vec::new();
+        for node in self.nodes.iter() {
+            node.visit(&mut |x| meshes_and_materials.extend(x.all_primitives().copied()));
         }
+        meshes_and_materials
+    }
+
+    pub fn node_transforms(&self) -> Vec<(String, NodeTransform)> {
+        let mut transforms = Vec::new();
+        for node in self.nodes.iter() {
+            node.visit(&mut |x| transforms.push((x.name.clone(), x.transform)));
+        }
+        transforms
+    }
+
+    // parent of every node in the order of node_transforms
+    pub fn node_parents(&self) -> Vec<Option<usize>> {
+        fn visit(node: &ModelNode, parent: Option<usize>, parents: &mut Vec<Option<usize>>) {
+            let index = parents.len();
+            parents.push(parent);
+            for child in node.children.iter() {
+                visit(child, Some(index), parents);
+            }
+        }
+
+        let mut parents = Vec::new();
+        for node in self.nodes.iter() {
+            visit(node, None, &mut parents);
+        }
+        parents
+    }
+
+    pub fn visit_primitives_mut(&mut self, mut f: impl FnMut(&mut (Uuid, Uuid))) {
+        for node in self.nodes.iter_mut() {
+            node.visit_primitives_mut(&mut f);
+        }
+    }
+
+    // primitives with their transform relative to the model origin, `overrides` replace
+    // the local node transforms and `levels` pick the LOD of every node, both in the order
+    // returned by node_transforms
+    pub fn primitives(&self, overrides: &[(String, NodeTransform)], levels: &[usize]) -> Vec<ModelPrimitive> {
+        let mut primitives = Vec::new();
+        let mut index = 0;
+        for node in self.nodes.iter() {
+            node.collect_primitives(&identity_transform(), overrides, levels, &mut index, &mut primitives);
+        }
+        primitives
+    }
+
+    // bounds of the rest pose at full detail relative to the model origin, meshes that aren't loaded are left out
+    pub fn combined_aabb(&self, assets: &AssetLibrary) -> Option<Aabb> {
+        self.primitives(&[], &[])
+            .iter()
+            .filter_map(|primitive| {
+                let (position, rotation, scale) = primitive.transform;
+                let mesh = assets.meshes.get(&primitive.mesh)?;
+                Aabb::from_points(mesh.aabb.corners().map(|x| x * scale * rotation + position))
+            })
+            .reduce(|a, b| a.merge(&b))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ModelComponent {
     pub model_uuid: Uuid,
-    model_name: String
+    model_name: String,
+    // this entity's copy of the model's node transforms
+    pub(crate) node_transforms: Vec<(String, NodeTransform)>,
+    // LOD level of every node drawn last frame, updated while rendering
+    lod_levels: Vec<usize>
 }
 
 impl ModelComponent {
     pub fn new(name: &str) -> ModelComponent {
         ModelComponent {
             model_uuid: Uuid::nil(),
-            model_name: name.to_string()
+            model_name: name.to_string(),
+            node_transforms: Vec::new(),
+            lod_levels: Vec::new()
         }
     }
 
     pub fn load_uuid(&mut self, assets: &AssetLibrary) {
-        self.model_uuid = *assets.models.iter().find(|(_, v)| v.name == self.model_name).expect("Model name not found").0;
+        let (uuid, model) = match assets.model_by_name(&self.model_name) {
+            Some(val) => val,
+            None => panic!("Model {} not found", self.model_name)
+        };
+        self.model_uuid = uuid;
+        self.node_transforms = model.node_transforms();
+    }
+
+    // local transform of a node of this entity's model, changes only affect this entity
+    pub fn node_transform_mut(&mut self, name: &str) -> Option<&mut NodeTransform> {
+        self.node_transforms.iter_mut().find(|(x, _)| x == name).map(|(_, transform)| transform)
+    }
+
+    // every node at its most detailed level
+    pub fn primitives(&self, model: &Model) -> Vec<ModelPrimitive> {
+        model.primitives(&self.node_transforms, &[])
+    }
+
+    // every node at the level for an entity `distance` away from the camera
+    pub fn lod_primitives(&mut self, model: &Model, distance: f32, hysteresis: f32) -> Vec<ModelPrimitive> {
+        let levels = &mut self.lod_levels;
+        let mut index = 0;
+        for node in model.nodes.iter() {
+            node.visit(&mut |x| {
+                if index >= levels.len() {
+                    levels.push(0);
+                }
+                levels[index] = select_lod(&x.lods, levels[index], distance, hysteresis);
+                index += 1;
+            });
+        }
+        model.primitives(&self.node_transforms, &self.lod_levels)
     }
 }
 
@@ -50,3 +331,118 @@ impl System for ModelComponentUuidLoader {
 
     fn on_update(&self, _world: &crate::ecs::World, _assets: &mut AssetLibrary, _state: &mut crate::state::State) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+    use uuid::Uuid;
+
+    use crate::{asset_library::AssetLibrary, rendering::VertexData, types::{mesh::Mesh, quaternion::Quat, vectors::{GpuVec3f, Vec3f}}};
+
+    use super::{compose, fold_lods, identity_transform, select_lod, Model, ModelComponent, ModelNode, NodeTransform, DEFAULT_LOD_DISTANCE};
+
+    fn apply(transform: &NodeTransform, point: Vec3f) -> Vec3f {
+        point * transform.2 * transform.1 + transform.0
+    }
+
+    fn assert_close(a: Vec3f, b: Vec3f) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn turret() -> (Model, Uuid, Uuid) {
+        let (hull, barrel) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut root = ModelNode::new("Hull".to_string(), identity_transform(), vec![(hull, hull)]);
+        root.children.push(ModelNode::new(
+            "TurretBone".to_string(),
+            (Vec3f::new([0.0, 1.0, 0.0]), Quat::new([1.0, 0.0, 0.0, 0.0]), Vec3f::new([1.0, 1.0, 1.0])),
+            vec![(barrel, barrel)]
+        ));
+        let mut model = Model::new("tank.gltf".to_string());
+        model.nodes.push(root);
+        (model, hull, barrel)
+    }
+
+    #[test]
+    fn test_compose_matches_nested_application() {
+        let parent = (Vec3f::new([1.0, -2.0, 0.5]), Quat::new([0.8, 0.3, -0.4, 0.33]).normalize(), Vec3f::new([2.0, 2.0, 2.0]));
+        let local = (Vec3f::new([0.0, 1.0, 3.0]), Quat::new([0.6, -0.2, 0.7, 0.1]).normalize(), Vec3f::new([1.0, 0.5, 3.0]));
+        let point = Vec3f::new([0.2, 1.5, -0.7]);
+
+        assert_close(apply(&compose(&parent, &local), point), apply(&parent, apply(&local, point)));
+    }
+
+    #[test]
+    fn test_node_overrides_are_per_entity() {
+        let (model, hull, barrel) = turret();
+        assert_eq!(model.meshes_and_materials(), vec![(hull, hull), (barrel, barrel)]);
+
+        let mut component = ModelComponent::new("tank.gltf");
+        component.node_transforms = model.node_transforms();
+        assert_eq!(model.node_parents(), vec![None, Some(0)]);
+        let other = ModelComponent { node_transforms: model.node_transforms(), ..ModelComponent::new("tank.gltf") };
+
+        let turret = component.node_transform_mut("TurretBone").unwrap();
+        turret.1 = Quat::new([0.0, 0.0, 1.0, 0.0]);
+        assert!(component.node_transform_mut("Missing").is_none());
+
+        let tip = Vec3f::new([1.0, 0.0, 0.0]);
+        let rotated = component.primitives(&model);
+        let untouched = other.primitives(&model);
+        assert_eq!(rotated[1].mesh, barrel);
+        assert_close(apply(&rotated[0].transform, tip), tip);
+        assert_close(apply(&untouched[1].transform, tip), Vec3f::new([1.0, 1.0, 0.0]));
+        assert_close(apply(&rotated[1].transform, tip), tip * Quat::new([0.0, 0.0, 1.0, 0.0]) + Vec3f::new([0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn test_lod_nodes_fold_into_base() {
+        let [rock, rock_far, rock_farthest, tree, orphan] = [(); 5].map(|_| Uuid::new_v4());
+        let mut nodes: Vec<ModelNode> = [("Rock_LOD2", rock_farthest), ("Rock_LOD0", rock), ("Tree", tree), ("Rock_LOD1", rock_far), ("Bush_LOD1", orphan)]
+            .into_iter()
+            .map(|(name, mesh)| ModelNode::new(name.to_string(), identity_transform(), vec![(mesh, mesh)]))
+            .collect();
+        fold_lods(&mut nodes, &[10.0]);
+
+        let names: Vec<&str> = nodes.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["Rock_LOD0", "Tree", "Bush_LOD1"]);
+        let lods: Vec<(f32, Uuid)> = nodes[0].lods.iter().map(|x| (x.distance, x.meshes_and_materials[0].0)).collect();
+        assert_eq!(lods, vec![(10.0, rock_far), (2.0 * DEFAULT_LOD_DISTANCE, rock_farthest)]);
+        assert_eq!(nodes[0].all_primitives().count(), 3);
+    }
+
+    #[test]
+    fn test_lod_selection_hysteresis() {
+        let (lod0, lod1) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut model = Model::new("rock.gltf".to_string());
+        model.nodes.push(ModelNode::new("Scene".to_string(), identity_transform(), Vec::new()));
+        model.nodes[0].children.push(ModelNode::new("Rock".to_string(), identity_transform(), vec![(lod0, lod0)]));
+        model.nodes[0].children.push(ModelNode::new("Rock_LOD1".to_string(), identity_transform(), vec![(lod1, lod1)]));
+        fold_lods(&mut model.nodes, &[20.0]);
+
+        let lods = &model.nodes[0].children[0].lods;
+        assert_eq!(select_lod(lods, 0, 20.5, 2.0), 0);
+        assert_eq!(select_lod(lods, 0, 21.5, 2.0), 1);
+        assert_eq!(select_lod(lods, 1, 19.5, 2.0), 1);
+        assert_eq!(select_lod(lods, 1, 18.5, 2.0), 0);
+
+        let mut component = ModelComponent::new("rock.gltf");
+        let mut mesh_at = |distance: f32| component.lod_primitives(&model, distance, 2.0)[0].mesh;
+        assert_eq!([mesh_at(5.0), mesh_at(20.5), mesh_at(30.0), mesh_at(19.5), mesh_at(10.0)], [lod0, lod0, lod1, lod1, lod0]);
+        assert_eq!(component.primitives(&model)[0].mesh, lod0);
+    }
+
+    #[test]
+    fn test_combined_aabb_places_meshes() {
+        let (model, hull, barrel) = turret();
+        let mut assets = AssetLibrary::default();
+        assert_eq!(model.combined_aabb(&assets), None);
+
+        let corner = |x: f32| VertexData { position: GpuVec3f::new([x, x, x]), ..VertexData::zeroed() };
+        for uuid in [hull, barrel] {
+            assets.meshes.insert(uuid, Mesh::new("box", vec![corner(-0.5), corner(0.5)], vec![0, 1, 0]));
+        }
+        let aabb = model.combined_aabb(&assets).unwrap();
+        assert_close(aabb.min, Vec3f::new([-0.5, -0.5, -0.5]));
+        assert_close(aabb.max, Vec3f::new([0.5, 1.5, 0.5]));
+    }
+}
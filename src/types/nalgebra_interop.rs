@@ -0,0 +1,176 @@
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector2, Vector3, Vector4};
+
+use super::{matrices::{Matrix4d, Matrix4f}, quaternion::Quat, vectors::{Vec2d, Vec2f, Vec3d, Vec3f, Vec4f}};
+
+// conversions to and from nalgebra, rotations turn vectors the same way on both sides, but `a * b` of ours is
+// nalgebra's `b * a`, matrices are the same matrix with ours stored column by column
+
+impl From<Vec2f> for Vector2<f32> {
+    fn from(value: Vec2f) -> Self {
+        Vector2::new(value.x, value.y)
+    }
+}
+
+impl From<Vector2<f32>> for Vec2f {
+    fn from(value: Vector2<f32>) -> Self {
+        Vec2f::new([value.x, value.y])
+    }
+}
+
+impl From<Vec3f> for Vector3<f32> {
+    fn from(value: Vec3f) -> Self {
+        Vector3::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<Vector3<f32>> for Vec3f {
+    fn from(value: Vector3<f32>) -> Self {
+        Vec3f::new([value.x, value.y, value.z])
+    }
+}
+
+impl From<Vec4f> for Vector4<f32> {
+    fn from(value: Vec4f) -> Self {
+        Vector4::new(value.x, value.y, value.z, value.w)
+    }
+}
+
+impl From<Vector4<f32>> for Vec4f {
+    fn from(value: Vector4<f32>) -> Self {
+        Vec4f::new([value.x, value.y, value.z, value.w])
+    }
+}
+
+impl From<Vec2d> for Vector2<f64> {
+    fn from(value: Vec2d) -> Self {
+        Vector2::new(value.x, value.y)
+    }
+}
+
+impl From<Vector2<f64>> for Vec2d {
+    fn from(value: Vector2<f64>) -> Self {
+        Vec2d::new([value.x, value.y])
+    }
+}
+
+impl From<Vec3d> for Vector3<f64> {
+    fn from(value: Vec3d) -> Self {
+        Vector3::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<Vector3<f64>> for Vec3d {
+    fn from(value: Vector3<f64>) -> Self {
+        Vec3d::new([value.x, value.y, value.z])
+    }
+}
+
+// normalized on the way, like to_matrix does with ours
+impl From<Quat> for UnitQuaternion<f32> {
+    fn from(value: Quat) -> Self {
+        UnitQuaternion::new_normalize(Quaternion::new(value.w, value.x, value.y, value.z))
+    }
+}
+
+impl From<UnitQuaternion<f32>> for Quat {
+    fn from(value: UnitQuaternion<f32>) -> Self {
+        Quat::new([value.w, value.i, value.j, value.k])
+    }
+}
+
+impl From<Matrix4f> for Matrix4<f32> {
+    fn from(value: Matrix4f) -> Self {
+        Matrix4::from_fn(|row, column| value.0[column][row])
+    }
+}
+
+impl From<Matrix4<f32>> for Matrix4f {
+    fn from(value: Matrix4<f32>) -> Self {
+        Matrix4f(std::array::from_fn(|column| std::array::from_fn(|row| value[(row, column)])))
+    }
+}
+
+impl From<Matrix4d> for Matrix4<f64> {
+    fn from(value: Matrix4d) -> Self {
+        Matrix4::from_fn(|row, column| value.0[column][row])
+    }
+}
+
+impl From<Matrix4<f64>> for Matrix4d {
+    fn from(value: Matrix4<f64>) -> Self {
+        Matrix4d(std::array::from_fn(|column| std::array::from_fn(|row| value[(row, column)])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{Matrix4, UnitQuaternion, Vector2, Vector3, Vector4};
+
+    use crate::types::{
+        matrices::{Matrix4d, Matrix4f},
+        quaternion::Quat,
+        vectors::{Vec2d, Vec2f, Vec3d, Vec3f, Vec4f},
+    };
+
+    // values in [-100, 100) from a fixed lcg
+    fn random(count: usize) -> Vec<f64> {
+        let mut seed: u64 = 7;
+        (0..count)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (seed >> 11) as f64 / (1u64 << 53) as f64 * 200.0 - 100.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_vectors_round_trip() {
+        for x in random(400).chunks_exact(4) {
+            let f = x.iter().map(|x| *x as f32).collect::<Vec<_>>();
+            let vec2f = Vec2f::new([f[0], f[1]]);
+            assert_eq!(Vec2f::from(Vector2::from(vec2f)), vec2f);
+            let vec3f = Vec3f::new([f[0], f[1], f[2]]);
+            assert_eq!(Vec3f::from(Vector3::from(vec3f)), vec3f);
+            assert_eq!(Vector3::from(vec3f), Vector3::new(f[0], f[1], f[2]));
+            let vec4f = Vec4f::new([f[0], f[1], f[2], f[3]]);
+            assert_eq!(Vec4f::from(Vector4::from(vec4f)), vec4f);
+            let vec2d = Vec2d::new([x[0], x[1]]);
+            assert_eq!(Vec2d::from(Vector2::from(vec2d)), vec2d);
+            let vec3d = Vec3d::new([x[0], x[1], x[2]]);
+            assert_eq!(Vec3d::from(Vector3::from(vec3d)), vec3d);
+        }
+    }
+
+    #[test]
+    fn test_rotations_round_trip() {
+        for x in random(400).chunks_exact(4) {
+            let quat = Quat::from_axis_angle(Vec3f::new([x[0] as f32, x[1] as f32, x[2] as f32]), x[3] as f32 * 0.05);
+            let na_quat = UnitQuaternion::from(quat);
+            let (back, quat) = (Quat::from(na_quat), quat.normalize());
+            assert_relative_eq!(Vector4::new(back.w, back.x, back.y, back.z), Vector4::new(quat.w, quat.x, quat.y, quat.z), epsilon = 1e-6);
+
+            let vec = Vec3f::new([x[3] as f32, x[0] as f32, x[1] as f32]);
+            assert_relative_eq!(na_quat * Vector3::from(vec), Vector3::from(vec * quat), epsilon = 1e-3, max_relative = 1e-5);
+            assert_relative_eq!(na_quat.to_homogeneous(), Matrix4::from(quat.to_matrix()), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_matrices_round_trip() {
+        let values = random(640);
+        let matrices: Vec<[[f64; 4]; 4]> = values.chunks_exact(16).map(|x| std::array::from_fn(|c| std::array::from_fn(|r| x[c * 4 + r]))).collect();
+        for pair in matrices.windows(2) {
+            let (a, b) = (Matrix4d(pair[0]), Matrix4d(pair[1]));
+            assert_eq!(Matrix4d::from(Matrix4::from(a)), a);
+            assert_relative_eq!(Matrix4::from(a * b), Matrix4::from(a) * Matrix4::from(b), epsilon = 1e-9);
+
+            let af = Matrix4f(pair[0].map(|x| x.map(|x| x as f32)));
+            assert_eq!(Matrix4f::from(Matrix4::from(af)), af);
+            // translations land in the last column
+            assert_eq!(Matrix4::from(af)[(1, 3)], af.0[3][1]);
+            let vec = Vec4f::new([1.0, -2.0, 0.5, 1.0]);
+            assert_relative_eq!(Vector4::from(af * vec), Matrix4::from(af) * Vector4::from(vec), epsilon = 1e-3);
+        }
+    }
+}
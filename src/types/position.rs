@@ -1,4 +1,4 @@
-use std::ops::{Add, AddAssign, Div, Sub, SubAssign};
+use std::{fmt, ops::{Add, AddAssign, Div, Mul, Sub, SubAssign}};
 
 use serde::{Deserialize, Serialize};
 
@@ -11,27 +11,62 @@ pub struct Position {
     pub position: Vec3d
 }
 
-const CHUNK_SIZE: f64 = 1e12;
+// edge of a chunk in world units
+pub const CHUNK_SIZE: f64 = 1e12;
 
 impl Position {
     pub fn new(chunk: Vec3i, position: Vec3d) -> Position {
         Position { chunk, position }
     }
 
+    // moves whole chunks out of `position` so it ends up within half a chunk of the chunk's center, every point
+    // has one way to be written and doing it again changes nothing
     pub fn recalculate_chunk(&mut self) {
-        let x_offset = (self.position.x / CHUNK_SIZE) as i64; 
-        let y_offset = (self.position.y / CHUNK_SIZE) as i64; 
-        let z_offset = (self.position.z / CHUNK_SIZE) as i64; 
-        self.position.x -= x_offset as f64 * CHUNK_SIZE;
-        self.position.y -= y_offset as f64 * CHUNK_SIZE;
-        self.position.z -= z_offset as f64 * CHUNK_SIZE;
+        let (x_offset, x) = split_chunk(self.position.x);
+        let (y_offset, y) = split_chunk(self.position.y);
+        let (z_offset, z) = split_chunk(self.position.z);
+        self.position = Vec3d::new([x, y, z]);
         self.chunk += Vec3i::new([x_offset, y_offset, z_offset]);
     }
 
     pub fn length(&self) -> f64 {
-        let vec: Vec3d = self.position + self.chunk.into();
-        vec.length()
+        self.distance_to(&Position::default())
     }
+
+    // the chunks are subtracted first, exactly, so nearby positions far from the origin keep their precision
+    pub fn offset_to(&self, other: &Position) -> Vec3d {
+        let chunks = Vec3d::from(other.chunk - self.chunk) * CHUNK_SIZE;
+        chunks + (other.position - self.position)
+    }
+
+    pub fn distance_to(&self, other: &Position) -> f64 {
+        self.offset_to(other).length()
+    }
+
+    pub fn lerp(&self, other: &Position, t: f64) -> Position {
+        *self + (*other - *self) * t
+    }
+
+    // closer than `epsilon`
+    pub fn approx_eq(&self, other: &Position, epsilon: f64) -> bool {
+        self.distance_to(other) <= epsilon
+    }
+}
+
+// whole chunks in `value` and what's left, always in [-CHUNK_SIZE / 2, CHUNK_SIZE / 2), small values stay in
+// chunk 0 as they are instead of becoming nearly a chunk in the one below, where f64 has no precision left
+fn split_chunk(value: f64) -> (i64, f64) {
+    let mut chunks = (value / CHUNK_SIZE).round() as i64;
+    let mut rest = value - chunks as f64 * CHUNK_SIZE;
+    if rest < -CHUNK_SIZE / 2.0 {
+        rest += CHUNK_SIZE;
+        chunks -= 1;
+    }
+    if rest >= CHUNK_SIZE / 2.0 {
+        rest -= CHUNK_SIZE;
+        chunks += 1;
+    }
+    (chunks, rest)
 }
 
 impl Default for Position {
@@ -93,6 +128,29 @@ impl Div<f64> for Position {
     }
 }
 
+// scales the whole position, chunks included, about the origin
+impl Mul<f64> for Position {
+    type Output = Position;
+    fn mul(self, rhs: f64) -> Self::Output {
+        // the fractions of chunks become world units, the whole ones stay exact
+        let scaled = Vec3d::from(self.chunk) * rhs;
+        let whole = Vec3d::new([scaled.x.round(), scaled.y.round(), scaled.z.round()]);
+        let mut pos = Position {
+            chunk: Vec3i::new([whole.x as i64, whole.y as i64, whole.z as i64]),
+            position: (scaled - whole) * CHUNK_SIZE + self.position * rhs,
+        };
+        pos.recalculate_chunk();
+        pos
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (chunk, position) = (self.chunk, self.position);
+        write!(f, "chunk ({}, {}, {}) + ({}, {}, {})", chunk.x, chunk.y, chunk.z, position.x, position.y, position.z)
+    }
+}
+
 impl From<Vec3d> for Position {
     fn from(value: Vec3d) -> Self {
         let mut vec = Position { chunk: Vec3i::new([0, 0, 0]), position: value };
@@ -100,3 +158,85 @@ impl From<Vec3d> for Position {
         vec
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::types::vectors::{Vec3d, Vec3i};
+
+    use super::{Position, CHUNK_SIZE};
+
+    #[test]
+    fn test_negative_positions_stay_put() {
+        let mut position = Position::from(Vec3d::new([-0.5, 0.0, 0.0]));
+        assert_eq!(position, Position::new(Vec3i::new([0, 0, 0]), Vec3d::new([-0.5, 0.0, 0.0])));
+        position.recalculate_chunk();
+        assert_eq!(position.position.x, -0.5);
+        let step = Position::from(Vec3d::new([1.0, 0.0, 0.0]));
+        assert_eq!(position + step - step, position);
+
+        // truncating left both of these as they were, two ways to write the same point
+        let mut below = Position::new(Vec3i::new([0, 0, 0]), Vec3d::new([-0.75 * CHUNK_SIZE, 0.0, 0.0]));
+        let mut above = Position::new(Vec3i::new([-1, 0, 0]), Vec3d::new([0.25 * CHUNK_SIZE, 0.0, 0.0]));
+        below.recalculate_chunk();
+        above.recalculate_chunk();
+        assert_eq!(below, above);
+        assert_eq!(below.chunk, Vec3i::new([-1, 0, 0]));
+    }
+
+    #[test]
+    fn test_just_below_chunk_size() {
+        // a power of two so it's exact next to CHUNK_SIZE
+        let epsilon = 1.0 / 1024.0;
+        let mut position = Position::from(Vec3d::new([CHUNK_SIZE - epsilon, 0.0, 0.0]));
+        assert_eq!(position.chunk, Vec3i::new([1, 0, 0]));
+        assert_eq!(position.position.x, -epsilon);
+        position.recalculate_chunk();
+        assert_eq!(position.chunk, Vec3i::new([1, 0, 0]));
+        let across = position + Position::from(Vec3d::new([2.0 * epsilon, 0.0, 0.0]));
+        assert_eq!(across.chunk, Vec3i::new([1, 0, 0]));
+        assert_eq!(across.position.x, epsilon);
+
+        // halfway and rounding right onto the edges still ends up inside a chunk
+        for value in [CHUNK_SIZE / 2.0, -CHUNK_SIZE / 2.0, 1.5 * CHUNK_SIZE, CHUNK_SIZE / 2.0 * (1.0 - f64::EPSILON), -1e-20] {
+            let position = Position::from(Vec3d::new([value, 0.0, 0.0]));
+            assert!((-CHUNK_SIZE / 2.0..CHUNK_SIZE / 2.0).contains(&position.position.x), "{value}");
+            assert_eq!(Vec3d::from(position).x, value);
+        }
+    }
+
+    #[test]
+    fn test_distance_far_from_origin() {
+        // 1e12 * 1e6 would leave f64 with no precision below 1e2 or so
+        let a = Position::new(Vec3i::new([1_000_000, 0, -3]), Vec3d::new([0.25, 1.0, 0.0]));
+        let b = Position::new(Vec3i::new([1_000_000, 0, -3]), Vec3d::new([3.25, 5.0, 0.0]));
+        assert_eq!(a.distance_to(&b), 5.0);
+        let c = Position::new(Vec3i::new([1_000_001, 0, -3]), Vec3d::new([0.25, 1.0, 0.0]));
+        assert_eq!(a.distance_to(&c), CHUNK_SIZE);
+        assert!(a.approx_eq(&(b - Position::from(Vec3d::new([3.0, 4.0, 0.0]))), 1e-9));
+        assert!(!a.approx_eq(&b, 4.9));
+        assert_eq!(Position::from(Vec3d::new([3.0, -4.0, 0.0])).length(), 5.0);
+    }
+
+    #[test]
+    fn test_lerp_and_scale() {
+        let a = Position::new(Vec3i::new([4, 0, 0]), Vec3d::new([10.0, 0.0, 0.0]));
+        let b = Position::new(Vec3i::new([4, 0, 0]), Vec3d::new([30.0, 8.0, 0.0]));
+        assert_eq!(a.lerp(&b, 0.25), Position::new(Vec3i::new([4, 0, 0]), Vec3d::new([15.0, 2.0, 0.0])));
+        // backwards, into the chunk below
+        assert_eq!(a.offset_to(&b.lerp(&a, 1.5)), Vec3d::new([-10.0, -4.0, 0.0]));
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+
+        // half a chunk becomes world units
+        let scaled = Position::new(Vec3i::new([3, 0, 0]), Vec3d::new([4.0, 0.0, 0.0])) * 0.5;
+        assert_eq!(scaled, Position::new(Vec3i::new([2, 0, 0]), Vec3d::new([2.0 - CHUNK_SIZE / 2.0, 0.0, 0.0])));
+        assert_eq!(Position::from(Vec3d::new([1.0, 2.0, 3.0])) * 2.0, Position::from(Vec3d::new([2.0, 4.0, 6.0])));
+        assert_eq!(Position::from(Vec3d::new([1.0, 2.0, 3.0])) * -1.0, Position::from(Vec3d::new([-1.0, -2.0, -3.0])));
+    }
+
+    #[test]
+    fn test_display() {
+        let position = Position::new(Vec3i::new([1, 0, -2]), Vec3d::new([12.5, 0.0, 3.0]));
+        assert_eq!(position.to_string(), "chunk (1, 0, -2) + (12.5, 0, 3)");
+    }
+}
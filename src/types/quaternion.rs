@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::types::{matrices::Matrix4f, vectors::Vec3f};
 
+// rotations in the right-handed, y-up space of Matrix4f::look_at and glTF, `v * q` (or `q * v`, the same) turns
+// v counterclockwise looking down the axis, and `a * b` is a then b, so `v * (a * b) == v * a * b`
 #[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
 #[repr(C, align(16))]
 pub struct Quat {
@@ -24,6 +26,7 @@ impl Quat {
         }
     }
     
+    // from [x, y, z, w] like glTF stores them, same convention as ours
     pub fn new_sl(val: [f32; 4]) -> Quat {
         Quat {
             w: val[3],
@@ -42,35 +45,33 @@ impl Quat {
         ])
     }
 
+    // the matrix turning vectors like `v * self`, its columns are where the axes end up
     pub fn to_matrix(&self) -> Matrix4f {
-        let q = self.normalize();
-        let qr = q.w;
-        let qi = q.x;
-        let qj = q.z;
-        let qk = q.y;
+        let Quat { x, y, z, w } = self.normalize();
 
         Matrix4f([
-            [1.0-2.0*(qj*qj + qk*qk), 2.0*(qi*qk + qj*qr),     2.0*(qi*qj - qk*qr),     0.0],
-            [2.0*(qi*qk - qj*qr),     1.0-2.0*(qi*qi + qj*qj), 2.0*(qj*qk + qi*qr),     0.0],
-            [2.0*(qi*qj + qk*qr),     2.0*(qj*qk - qi*qr),     1.0-2.0*(qi*qi + qk*qk), 0.0],
+            [1.0-2.0*(y*y + z*z), 2.0*(x*y + z*w),     2.0*(x*z - y*w),     0.0],
+            [2.0*(x*y - z*w),     1.0-2.0*(x*x + z*z), 2.0*(y*z + x*w),     0.0],
+            [2.0*(x*z + y*w),     2.0*(y*z - x*w),     1.0-2.0*(x*x + y*y), 0.0],
             [0.0, 0.0, 0.0, 1.0]
         ])
     }
 
+    // radians about each axis, z first, then x, then y, the same rotation as Matrix4f::rotation_yxz
     pub fn from_euler(e: Vec3f) -> Quat {
-        let cr = (e.x / 2.0).cos();
+        let cx = (e.x / 2.0).cos();
         let cy = (e.y / 2.0).cos();
-        let cp = (e.z / 2.0).cos();
+        let cz = (e.z / 2.0).cos();
         
-        let sr = (e.x / 2.0).sin();
+        let sx = (e.x / 2.0).sin();
         let sy = (e.y / 2.0).sin();
-        let sp = (e.z / 2.0).sin();
+        let sz = (e.z / 2.0).sin();
 
         Quat {
-            w: cr*cy*cp + sr*sy*sp,
-            x: sr*cy*cp - cr*sy*sp,
-            y: cr*sy*cp - sr*cy*sp,
-            z: cr*cy*sp + sr*sy*cp,
+            w: cx*cy*cz + sx*sy*sz,
+            x: sx*cy*cz + cx*sy*sz,
+            y: cx*sy*cz - sx*cy*sz,
+            z: cx*cy*sz - sx*sy*cz,
         }.normalize()
     }
 
@@ -86,6 +87,105 @@ impl Quat {
         let len = self.length();
         Quat::new([self.w / len, self.x / len, self.y / len, self.z / len])
     }
+
+    pub fn dot(&self, other: &Quat) -> f32 {
+        self.w*other.w + self.x*other.x + self.y*other.y + self.z*other.z
+    }
+
+    // `angle` radians about `axis`, counterclockwise looking down the axis like `Vec3f * Quat` turns
+    pub fn from_axis_angle(axis: Vec3f, angle: f32) -> Quat {
+        if axis.length_sqr() == 0.0 {
+            return Quat::new([1.0, 0.0, 0.0, 0.0]);
+        }
+        let axis = axis.normalize() * (angle / 2.0).sin();
+        Quat::new([(angle / 2.0).cos(), axis.x, axis.y, axis.z])
+    }
+
+    // the rotation turning -z to `forward` and y as close to `up` as it gets, how Matrix4f::look_at and cameras
+    // look, an `up` parallel to `forward` is swapped for another axis
+    pub fn look_rotation(forward: Vec3f, up: Vec3f) -> Quat {
+        let back = -forward.normalize();
+        let mut right = up.cross(back);
+        if right.length_sqr() < 1e-12 {
+            let other = if back.y.abs() < 0.9 { Vec3f::new([0.0, 1.0, 0.0]) } else { Vec3f::new([1.0, 0.0, 0.0]) };
+            right = other.cross(back);
+        }
+        let right = right.normalize();
+        Quat::from_axes(right, back.cross(right), back)
+    }
+
+    // from the columns of a rotation matrix
+    pub(super) fn from_axes(x: Vec3f, y: Vec3f, z: Vec3f) -> Quat {
+        let trace = x.x + y.y + z.z;
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat::new([s / 4.0, (y.z - z.y) / s, (z.x - x.z) / s, (x.y - y.x) / s])
+        } else if x.x > y.y && x.x > z.z {
+            let s = (1.0 + x.x - y.y - z.z).sqrt() * 2.0;
+            Quat::new([(y.z - z.y) / s, s / 4.0, (y.x + x.y) / s, (z.x + x.z) / s])
+        } else if y.y > z.z {
+            let s = (1.0 + y.y - x.x - z.z).sqrt() * 2.0;
+            Quat::new([(z.x - x.z) / s, (y.x + x.y) / s, s / 4.0, (z.y + y.z) / s])
+        } else {
+            let s = (1.0 + z.z - x.x - y.y).sqrt() * 2.0;
+            Quat::new([(x.y - y.x) / s, (z.x + x.z) / s, (z.y + y.z) / s, s / 4.0])
+        };
+        q.normalize()
+    }
+
+    // the angles from_euler takes back, x within +-90 degrees, near those the z angle is folded into y
+    pub fn to_euler(&self) -> Vec3f {
+        let Quat { w, x, y, z } = self.normalize();
+        // the rotation matrix entries the angles are read from, row then column
+        let m12 = 2.0*(y*z - w*x);
+        let angle_x = (-m12).clamp(-1.0, 1.0).asin();
+        if m12.abs() < 0.9999 {
+            let (m02, m22) = (2.0*(x*z + w*y), 1.0 - 2.0*(x*x + y*y));
+            let (m10, m11) = (2.0*(x*y + w*z), 1.0 - 2.0*(x*x + z*z));
+            Vec3f::new([angle_x, m02.atan2(m22), m10.atan2(m11)])
+        } else {
+            let (m00, m20) = (1.0 - 2.0*(y*y + z*z), 2.0*(x*z - w*y));
+            Vec3f::new([angle_x, (-m20).atan2(m00), 0.0])
+        }
+    }
+
+    // normalized linear blend, cheaper than slerp and close to it for nearby rotations, takes the shorter way
+    pub fn nlerp(&self, other: &Quat, t: f32) -> Quat {
+        let other = if self.dot(other) < 0.0 { *other * -1.0 } else { *other };
+        (*self * (1.0 - t) + other * t).normalize()
+    }
+
+    // constant speed blend the shorter way around, nlerp for nearly equal rotations where slerp divides by ~0
+    pub fn slerp(&self, other: &Quat, t: f32) -> Quat {
+        let mut dot = self.dot(other);
+        let mut other = *other;
+        if dot < 0.0 {
+            other = other * -1.0;
+            dot = -dot;
+        }
+        if dot > 0.9995 {
+            return self.nlerp(&other, t);
+        }
+
+        let angle = dot.acos();
+        let sin = angle.sin();
+        (*self * (((1.0 - t) * angle).sin() / sin) + other * ((t * angle).sin() / sin)).normalize()
+    }
+
+    // radians it takes to turn one rotation into the other, at most pi
+    pub fn angle_to(&self, other: &Quat) -> f32 {
+        let dot = self.normalize().dot(&other.normalize()).abs().min(1.0);
+        2.0 * dot.acos()
+    }
+
+    // turns towards `target` by at most `max_radians`, reaching it when it's closer
+    pub fn rotate_towards(&self, target: &Quat, max_radians: f32) -> Quat {
+        let angle = self.angle_to(target);
+        if angle <= max_radians.max(0.0) {
+            return *target;
+        }
+        self.slerp(target, max_radians.max(0.0) / angle)
+    }
 }
 
 impl Add for Quat {
@@ -97,6 +197,7 @@ impl Add for Quat {
 
 impl Mul for Quat {
     type Output = Quat;
+    #[inline]
     fn mul(self, rhs: Self) -> Self::Output {
         Quat::new([
             self.w*rhs.w - self.x*rhs.x - self.z*rhs.z - self.y*rhs.y,
@@ -107,17 +208,18 @@ impl Mul for Quat {
     }
 }
 
+// the same rotation as `rhs * self`
 impl Mul<Vec3f> for Quat {
     type Output = Vec3f;
+    #[inline]
     fn mul(self, rhs: Vec3f) -> Vec3f {
-        let p = Quat::new([0.0, rhs.x, rhs.y, rhs.z]);
-        let pp = self * p * self.inv();
-        Vec3f::new([pp.x, pp.y, pp.z])
+        rhs * self
     }
 }
 
 impl Mul<f32> for Quat {
     type Output = Quat;
+    #[inline]
     fn mul(self, rhs: f32) -> Quat {
         Quat::new([self.w * rhs, self.x * rhs, self.y * rhs, self.z * rhs])
     }
@@ -128,10 +230,27 @@ mod tests {
     use approx::assert_relative_eq;
     use nalgebra::UnitQuaternion;
 
-    use crate::types::{quaternion::Quat, vectors::Vec3f};
+    use crate::types::{matrices::Matrix4f, quaternion::Quat, vectors::Vec3f};
 
     extern crate nalgebra as na;
 
+    fn na_vec(vec: Vec3f) -> na::Vector3<f32> {
+        na::Vector3::new(vec.x, vec.y, vec.z)
+    }
+
+    // q and -q are the same rotation
+    fn assert_same_rotation(my_quat: Quat, na_quat: UnitQuaternion<f32>) {
+        let sign = if my_quat.w*na_quat.w + my_quat.x*na_quat.i + my_quat.y*na_quat.j + my_quat.z*na_quat.k < 0.0 { -1.0 } else { 1.0 };
+        assert_relative_eq!(my_quat.w, sign * na_quat.w, epsilon=1e-5);
+        assert_relative_eq!(my_quat.x, sign * na_quat.i, epsilon=1e-5);
+        assert_relative_eq!(my_quat.y, sign * na_quat.j, epsilon=1e-5);
+        assert_relative_eq!(my_quat.z, sign * na_quat.k, epsilon=1e-5);
+    }
+
+    fn to_na(quat: Quat) -> UnitQuaternion<f32> {
+        UnitQuaternion::new_normalize(na::Quaternion::new(quat.w, quat.x, quat.y, quat.z))
+    }
+
     #[test]
     fn test_quat_from_euler_na() {
         let my_quat = Quat::from_euler(Vec3f::new([0.0, 0.4, 0.0]));
@@ -172,21 +291,71 @@ mod tests {
     }
 
 
+    // a * b is a then b, nalgebra's b * a
     #[test]
     fn test_quat_mul_na() {
         let my_quat_1 = Quat::from_euler(Vec3f::new([0.1, 1.6, 0.4]));
-        let na_quat_1 = UnitQuaternion::from_euler_angles(0.1, 0.4, 1.6);
-
         let my_quat_2 = Quat::from_euler(Vec3f::new([-0.3, 0.6, -1.4]));
-        let na_quat_2 = UnitQuaternion::from_euler_angles(-0.3, -1.4, 0.6);
-
         let my_quat = my_quat_1 * my_quat_2;
-        let na_quat = na_quat_1 * na_quat_2;
+        assert_same_rotation(my_quat, to_na(my_quat_2) * to_na(my_quat_1));
 
-        assert_relative_eq!(my_quat.x, na_quat.i);
-        assert_relative_eq!(my_quat.y, na_quat.k);
-        assert_relative_eq!(my_quat.z, na_quat.j);
-        assert_relative_eq!(my_quat.w, na_quat.w);
+        let vec = Vec3f::new([0.5, -1.0, 2.0]);
+        assert_relative_eq!(na_vec(vec * my_quat), na_vec(vec * my_quat_1 * my_quat_2), epsilon=1e-5);
+    }
+
+    #[test]
+    fn test_to_matrix_na() {
+        let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [0.3, -1.0, 0.5]].map(Vec3f::new);
+        let mut rotations: Vec<Quat> = axes.iter().map(|x| Quat::from_axis_angle(*x, 0.7)).collect();
+        // composed ones, where swapped axes would show
+        rotations.push(rotations[0] * rotations[1]);
+        rotations.push(rotations[1] * rotations[2] * rotations[0]);
+        rotations.push(rotations[3] * rotations[2]);
+
+        for my_quat in rotations {
+            let na_matrix = to_na(my_quat).to_homogeneous();
+            let matrix = my_quat.to_matrix();
+            for (r, c) in (0..4).flat_map(|r| (0..4).map(move |c| (r, c))) {
+                assert_relative_eq!(matrix.0[c][r], na_matrix[(r, c)], epsilon=1e-5);
+            }
+            for vec in axes {
+                let na_turned = to_na(my_quat) * na_vec(vec);
+                assert_relative_eq!(na_vec(vec * my_quat), na_turned, epsilon=1e-5);
+                assert_relative_eq!(na_vec(my_quat * vec), na_turned, epsilon=1e-5);
+                assert_relative_eq!(na_vec(matrix.vec_mul(vec)), na_turned, epsilon=1e-5);
+            }
+        }
+
+        // a quarter turn about each axis, counterclockwise looking down it
+        let quarter = std::f32::consts::FRAC_PI_2;
+        let x = Vec3f::new([0.0, 1.0, 0.0]) * Quat::from_axis_angle(axes[0], quarter);
+        let y = Vec3f::new([0.0, 0.0, 1.0]) * Quat::from_axis_angle(axes[1], quarter);
+        let z = Vec3f::new([1.0, 0.0, 0.0]) * Quat::from_axis_angle(axes[2], quarter);
+        assert_relative_eq!(na_vec(x), na::Vector3::z(), epsilon=1e-6);
+        assert_relative_eq!(na_vec(y), na::Vector3::x(), epsilon=1e-6);
+        assert_relative_eq!(na_vec(z), na::Vector3::y(), epsilon=1e-6);
+    }
+
+    #[test]
+    fn test_from_euler_matches_rotation_yxz() {
+        for e in [[0.3, -0.7, 1.1], [-1.2, 2.5, 0.4], [1.0, 0.0, -2.0]] {
+            let my_quat = Quat::from_euler(Vec3f::new(e));
+            let na_quat = UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), e[1])
+                * UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), e[0])
+                * UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), e[2]);
+            assert_same_rotation(my_quat, na_quat);
+            let matrix = Matrix4f::rotation_yxz(Vec3f::new(e));
+            for (a, b) in my_quat.to_matrix().0.iter().flatten().zip(matrix.0.iter().flatten()) {
+                assert_relative_eq!(*a, *b, epsilon=1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_sl_gltf_order() {
+        let [x, y, z, w] = [0.1f32, -0.3, 0.3, 0.9];
+        let my_quat = Quat::new_sl([x, y, z, w]);
+        assert_same_rotation(my_quat, UnitQuaternion::new_normalize(na::Quaternion::new(w, x, y, z)));
     }
     
     #[test]
@@ -199,4 +368,96 @@ mod tests {
         assert_relative_eq!(my_vec.x, 0.0);
         assert_relative_eq!(my_vec.z, 0.0);
     }
+
+    #[test]
+    fn test_quat_vec_mul_composes() {
+        let q1 = Quat::from_euler(Vec3f::new([0.1, 1.6, 0.4]));
+        let q2 = Quat::from_euler(Vec3f::new([-0.3, 0.6, -1.4]));
+        let vec = Vec3f::new([0.5, -1.0, 2.0]);
+        assert_relative_eq!(na_vec(vec * (q1 * q2)), na_vec((vec * q1) * q2), epsilon=1e-5);
+        assert_relative_eq!(na_vec((q1 * q2) * vec), na_vec(vec * (q1 * q2)), epsilon=1e-5);
+    }
+
+    #[test]
+    fn test_axis_angle_na() {
+        let axis = Vec3f::new([0.3, -1.0, 0.5]);
+        let my_quat = Quat::from_axis_angle(axis, 1.2);
+        let na_quat = UnitQuaternion::from_axis_angle(&na::Unit::new_normalize(na_vec(axis)), 1.2);
+        assert_same_rotation(my_quat, na_quat);
+
+        let my_vec = Vec3f::new([1.0, 2.0, -0.5]) * my_quat;
+        assert_relative_eq!(na_vec(my_vec), na_quat * na::Vector3::new(1.0, 2.0, -0.5), epsilon=1e-5);
+        assert_eq!(Quat::from_axis_angle(Vec3f::new([0.0, 0.0, 0.0]), 1.0), Quat::new([1.0, 0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_look_rotation_matches_look_at() {
+        let cases = [
+            (Vec3f::new([0.0, 0.0, -1.0]), Vec3f::new([0.0, 1.0, 0.0])),
+            (Vec3f::new([1.0, -0.5, 2.0]), Vec3f::new([0.0, 1.0, 0.0])),
+            (Vec3f::new([-3.0, 1.0, 0.2]), Vec3f::new([0.2, 1.0, -0.4])),
+            (Vec3f::new([0.0, 0.0, 1.0]), Vec3f::new([0.0, 1.0, 0.0])),
+        ];
+        for (forward, up) in cases {
+            let my_quat = Quat::look_rotation(forward, up);
+            // nalgebra turns z to the direction, cameras look down -z
+            assert_same_rotation(my_quat, UnitQuaternion::face_towards(&-na_vec(forward), &na_vec(up)));
+
+            let eye = Vec3f::new([1.0, 2.0, 3.0]);
+            let view = Matrix4f::look_at(eye, forward, up);
+            let turned = Matrix4f::look_at(eye, Vec3f::new([0.0, 0.0, -1.0]) * my_quat, Vec3f::new([0.0, 1.0, 0.0]) * my_quat);
+            for (a, b) in view.0.iter().flatten().zip(turned.0.iter().flatten()) {
+                assert_relative_eq!(*a, *b, epsilon=1e-5);
+            }
+        }
+
+        // up along forward still gives a rotation that looks the right way
+        let forward = Vec3f::new([0.0, 1.0, 0.0]) * Quat::look_rotation(Vec3f::new([0.0, 1.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+        assert_relative_eq!(forward.length(), 1.0, epsilon=1e-5);
+        let forward = Vec3f::new([0.0, 0.0, -1.0]) * Quat::look_rotation(Vec3f::new([0.0, 2.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+        assert_relative_eq!(forward.y, 1.0, epsilon=1e-5);
+    }
+
+    #[test]
+    fn test_slerp_na() {
+        let a = Quat::from_axis_angle(Vec3f::new([0.0, 1.0, 0.0]), 0.3);
+        let b = Quat::from_axis_angle(Vec3f::new([1.0, 0.5, 0.0]), 2.1);
+        for t in [0.0, 0.25, 0.5, 0.9, 1.0] {
+            assert_same_rotation(a.slerp(&b, t), to_na(a).slerp(&to_na(b), t));
+            // the same rotation written negated still takes the short way
+            assert_same_rotation(a.slerp(&(b * -1.0), t), to_na(a).slerp(&to_na(b), t));
+        }
+        assert_relative_eq!(a.angle_to(&b), to_na(a).angle_to(&to_na(b)), epsilon=1e-5);
+
+        // nearly equal rotations fall back to nlerp instead of dividing by zero
+        let close = Quat::from_axis_angle(Vec3f::new([0.0, 1.0, 0.0]), 0.3001);
+        let halfway = a.slerp(&close, 0.5);
+        assert!(!halfway.w.is_nan());
+        assert_same_rotation(halfway, UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), 0.30005));
+        assert_same_rotation(a.nlerp(&close, 0.5), UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), 0.30005));
+    }
+
+    #[test]
+    fn test_rotate_towards() {
+        let a = Quat::from_axis_angle(Vec3f::new([0.0, 0.0, 1.0]), 0.0);
+        let b = Quat::from_axis_angle(Vec3f::new([0.0, 0.0, 1.0]), 1.0);
+        let step = a.rotate_towards(&b, 0.25);
+        assert_relative_eq!(a.angle_to(&step), 0.25, epsilon=1e-4);
+        assert_relative_eq!(step.angle_to(&b), 0.75, epsilon=1e-4);
+        assert_eq!(a.rotate_towards(&b, 2.0), b);
+        assert!(a.rotate_towards(&b, -1.0).angle_to(&a) < 1e-3);
+    }
+
+    #[test]
+    fn test_to_euler_round_trip() {
+        for e in [[0.3, -0.7, 1.1], [-1.2, 2.5, 0.4], [0.0, 0.4, 0.0], [1.5, -3.0, -3.0]] {
+            let euler = Quat::from_euler(Vec3f::new(e)).to_euler();
+            assert_relative_eq!(euler.x, e[0], epsilon=1e-4);
+            assert_relative_eq!(euler.y, e[1], epsilon=1e-4);
+            assert_relative_eq!(euler.z, e[2], epsilon=1e-4);
+        }
+        // straight up about x the other two angles can't be told apart, the rotation still comes back
+        let quat = Quat::from_euler(Vec3f::new([std::f32::consts::FRAC_PI_2, 0.5, 0.3]));
+        assert_same_rotation(Quat::from_euler(quat.to_euler()), to_na(quat));
+    }
 }
@@ -1,15 +1,39 @@
-use std::{fs::File, io::Read, sync::Arc};
+use std::{collections::HashMap, fs, io, path::Path, sync::Arc};
 
+use log::error;
 use serde::{Deserialize, Serialize};
-use vulkano::shader::{spirv::bytes_to_words, ShaderModule, ShaderModuleCreateInfo};
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::{get_pipeline, PipelineIdentifier}, state::State, vulkan::context::VulkanContext};
+use vulkano::{device::Features, shader::{spirv::bytes_to_words, ShaderModule, ShaderModuleCreateInfo}, Validated, VulkanError};
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, engine_config::AssetPaths, engine_error::EngineError, rendering::{get_compute_pipeline, recreate_pipelines}, state::State, vulkan::context::VulkanContext};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum ShaderType {
     Fragment,
     Vertex,
     UiFragment,
-    UiVertex
+    UiVertex,
+    Compute,
+    BillboardVertex,
+    ParticleVertex,
+    Geometry,
+    TessellationControl,
+    TessellationEvaluation,
+    // like Vertex with SkinVertexData as a second vertex buffer and the joint matrices at set 1 binding 1
+    SkinnedVertex,
+    // DebugLineVertex pairs drawn as a line list
+    DebugLineVertex
+}
+
+impl ShaderType {
+    // device features a pipeline with this stage needs on top of the ones always enabled
+    pub fn required_features(&self) -> Features {
+        match self {
+            ShaderType::Geometry => Features { geometry_shader: true, ..Features::empty() },
+            ShaderType::TessellationControl | ShaderType::TessellationEvaluation => {
+                Features { tessellation_shader: true, ..Features::empty() }
+            }
+            _ => Features::empty(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,54 +43,102 @@ pub struct Shader {
     pub source: Vec<u32>,
     #[serde(skip)]
     pub module: Option<Arc<ShaderModule>>,
+    // binding count of every descriptor set the shader declares, filled when loaded
+    #[serde(skip)]
+    pub descriptor_bindings: HashMap<u32, u32>,
 }
 
 impl Shader {
-    pub fn load(&mut self, context: &VulkanContext) {
-        unsafe {
-            self.module = Some(ShaderModule::new(
+    pub fn load(&mut self, context: &VulkanContext) -> Result<(), EngineError> {
+        self.try_load(context).map_err(|e| EngineError::ShaderCompile { name: self.name.clone(), message: e.to_string() })
+    }
+
+    pub fn try_load(&mut self, context: &VulkanContext) -> Result<(), Validated<VulkanError>> {
+        let module = unsafe {
+            ShaderModule::new(
                 context.device.clone(), 
                 ShaderModuleCreateInfo::new(self.source.as_slice())
-            ).unwrap());
+            )?
+        };
+
+        self.descriptor_bindings.clear();
+        if let Some(entry_point) = module.entry_point("main") {
+            for (set, binding) in entry_point.info().descriptor_binding_requirements.keys() {
+                let count = self.descriptor_bindings.entry(*set).or_insert(0);
+                *count = (*count).max(binding + 1);
+            }
         }
+        self.module = Some(module);
+        Ok(())
     }
 
-    pub fn new(name: String, shader_type: ShaderType) -> Shader {
+    pub fn from_words(name: String, shader_type: ShaderType, source: Vec<u32>) -> Shader {
         Shader {
-            name: name.clone(),
+            name,
             shader_type,
-            source: read_file_to_words(format!("assets/shaders/bin/{}.spv", name).as_str()),
-            module: None
+            source,
+            module: None,
+            descriptor_bindings: HashMap::new()
         }
     }
+
+    // reads the compiled spir-v from AssetPaths::shader_binaries
+    pub fn new(name: String, shader_type: ShaderType, paths: &AssetPaths) -> Result<Shader, EngineError> {
+        let path = paths.shader_binaries.join(format!("{}.spv", name));
+        match read_file_to_words(&path) {
+            Ok(source) => Ok(Shader::from_words(name, shader_type, source)),
+            Err(error) => Err(EngineError::MissingShader { name, path: path.display().to_string(), error }),
+        }
+    }
+}
+
+pub fn read_file_to_words(path: &Path) -> io::Result<Vec<u32>> {
+    let bytes = fs::read(path)?;
+    let words = bytes_to_words(&bytes).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not a whole number of words"))?;
+    Ok(words.to_vec())
 }
 
-pub fn read_file_to_words(path: &str) -> Vec<u32> {
-    let mut file = File::open(path).unwrap();
-    let mut buffer = vec![0u8; file.metadata().unwrap().len() as usize];
-    file.read_exact(buffer.as_mut_slice()).unwrap();
-    bytes_to_words(buffer.as_slice()).unwrap().to_vec()
+// marks materials that don't match their shaders so their pipelines aren't created
+pub fn validate_materials(assets: &AssetLibrary, state: &mut State) {
+    state.renderer.invalid_materials.clear();
+    for (uuid, material) in assets.materials.iter() {
+        let extra: Option<Vec<&Shader>> = material.extra_shaders().iter().map(|x| assets.shaders.get(x)).collect();
+        let (vertex, fragment, extra) = match (assets.shaders.get(&material.vertex_shader), assets.shaders.get(&material.fragment_shader), extra) {
+            (Some(vertex), Some(fragment), Some(extra)) => (vertex, fragment, extra),
+            _ => {
+                error!("material '{}' references a missing shader", material.name);
+                state.renderer.invalid_materials.insert(*uuid);
+                continue;
+            }
+        };
+        if let Err(e) = material.validate(vertex, fragment, &extra) {
+            error!("{}", e);
+            state.renderer.invalid_materials.insert(*uuid);
+        }
+    }
 }
 
 pub struct ShaderLoader {}
 
 impl System for ShaderLoader {
     fn on_start(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
-        for (_, shader) in assets.shaders.iter_mut() {
-            shader.load(&state.vulkan_context);
+        // run loads every shader in the pack before starting, this only catches ones added since
+        for (_, shader) in assets.shaders.iter_mut().filter(|(_, x)| x.module.is_none()) {
+            if let Err(e) = shader.load(&state.vulkan_context) {
+                error!("{}", e);
+            }
         }
 
-        for (_, material) in assets.materials.iter() {
-            state.renderer.pipelines.insert(
-                PipelineIdentifier::new(material.vertex_shader, material.fragment_shader, material.rendering_type),
-                get_pipeline(
-                    state, 
-                    assets.shaders.get(&material.vertex_shader).unwrap(), 
-                    assets.shaders.get(&material.fragment_shader).unwrap(), 
-                    material.rendering_type.into()
-                )        
-            );
+        validate_materials(assets, state);
+
+        for (uuid, shader) in assets.shaders.iter() {
+            if matches!(shader.shader_type, ShaderType::Compute) {
+                let pipeline = get_compute_pipeline(state, shader);
+                state.renderer.compute_pipelines.insert(*uuid, pipeline);
+            }
         }
+
+        recreate_pipelines(assets, state);
     }
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
 }
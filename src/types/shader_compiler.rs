@@ -0,0 +1,123 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use shaderc::{CompileOptions, Compiler, IncludeType, ResolvedInclude, ShaderKind};
+
+use crate::engine_config::AssetPaths;
+
+#[derive(Debug)]
+pub enum ShaderCompileError {
+    UnknownExtension(String),
+    Read { path: String, error: io::Error },
+    Compiler(String),
+    Compile { path: String, message: String },
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderCompileError::UnknownExtension(path) => {
+                write!(f, "{} is not a .vert, .frag, .geom, .tesc, .tese or .comp shader source", path)
+            }
+            ShaderCompileError::Read { path, error } => write!(f, "failed to read shader {}: {}", path, error),
+            ShaderCompileError::Compiler(message) => write!(f, "failed to initialize shaderc: {}", message),
+            ShaderCompileError::Compile { path, message } => {
+                write!(f, "failed to compile shader {}:\n{}", path, message)
+            }
+        }
+    }
+}
+
+fn shader_kind(path: &Path) -> Option<ShaderKind> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(ShaderKind::Vertex),
+        "frag" => Some(ShaderKind::Fragment),
+        "geom" => Some(ShaderKind::Geometry),
+        "tesc" => Some(ShaderKind::TessControl),
+        "tese" => Some(ShaderKind::TessEvaluation),
+        "comp" => Some(ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+// relative includes are looked up next to the including file, standard ones in the shader directory
+fn resolve_include(shader_dir: &Path, requested: &str, requesting: &str, include_type: IncludeType) -> PathBuf {
+    match include_type {
+        IncludeType::Relative => Path::new(requesting)
+            .parent()
+            .unwrap_or(shader_dir)
+            .join(requested),
+        IncludeType::Standard => shader_dir.join(requested),
+    }
+}
+
+pub fn compile_glsl(file_name: &str, paths: &AssetPaths) -> Result<Vec<u32>, ShaderCompileError> {
+    let shader_dir = paths.shaders.as_path();
+    let path = shader_dir.join(file_name);
+    let path_name = path.display().to_string();
+    debug!("Compiling shader {}", path_name);
+
+    let kind = shader_kind(&path).ok_or_else(|| ShaderCompileError::UnknownExtension(path_name.clone()))?;
+    let source = fs::read_to_string(&path).map_err(|error| ShaderCompileError::Read {
+        path: path_name.clone(),
+        error,
+    })?;
+
+    let compiler = Compiler::new().ok_or_else(|| ShaderCompileError::Compiler("no compiler available".to_string()))?;
+    let mut options =
+        CompileOptions::new().ok_or_else(|| ShaderCompileError::Compiler("no compile options available".to_string()))?;
+    options.set_include_callback(|requested, include_type, requesting, _depth| {
+        let include_path = resolve_include(shader_dir, requested, requesting, include_type);
+        let content = fs::read_to_string(&include_path)
+            .map_err(|error| format!("failed to include {}: {}", include_path.display(), error))?;
+        Ok(ResolvedInclude {
+            resolved_name: include_path.display().to_string(),
+            content,
+        })
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(&source, kind, &path_name, "main", Some(&options))
+        .map_err(|error| ShaderCompileError::Compile {
+            path: path_name.clone(),
+            message: error.to_string(),
+        })?;
+
+    Ok(artifact.as_binary().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use shaderc::{IncludeType, ShaderKind};
+
+    use super::{resolve_include, shader_kind};
+
+    #[test]
+    fn test_shader_kind_from_extension() {
+        assert_eq!(shader_kind(Path::new("lit.vert")), Some(ShaderKind::Vertex));
+        assert_eq!(shader_kind(Path::new("lit.frag")), Some(ShaderKind::Fragment));
+        assert_eq!(shader_kind(Path::new("gradient.comp")), Some(ShaderKind::Compute));
+        assert_eq!(shader_kind(Path::new("debug_normals.geom")), Some(ShaderKind::Geometry));
+        assert_eq!(shader_kind(Path::new("terrain.tesc")), Some(ShaderKind::TessControl));
+        assert_eq!(shader_kind(Path::new("terrain.tese")), Some(ShaderKind::TessEvaluation));
+        assert_eq!(shader_kind(Path::new("lit.spv")), None);
+    }
+
+    #[test]
+    fn test_include_paths() {
+        let shader_dir = Path::new("assets/shaders");
+        assert_eq!(
+            resolve_include(shader_dir, "common/light.glsl", "assets/shaders/lit.frag", IncludeType::Standard),
+            Path::new("assets/shaders/common/light.glsl")
+        );
+        assert_eq!(
+            resolve_include(shader_dir, "brdf.glsl", "assets/shaders/common/light.glsl", IncludeType::Relative),
+            Path::new("assets/shaders/common/brdf.glsl")
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{allocator::CommandBufferAllocator, AutoCommandBufferBuilder, CopyBufferInfo},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+use crate::state::State;
+
+// a device local buffer written through a host visible copy of it,
+// the copy is recorded by `flush` so it happens in order with the commands using the buffer
+#[derive(Debug)]
+pub struct StagingBuffer<T: BufferContents> {
+    staging: Subbuffer<[T]>,
+    main: Subbuffer<[T]>,
+    pending: bool,
+}
+
+impl<T: BufferContents + Clone> StagingBuffer<T> {
+    pub fn new(state: &State, usage: BufferUsage, len: u64) -> StagingBuffer<T> {
+        let allocator = state.memory_allocators.standard_memory_allocator.clone();
+        let staging = Buffer::new_slice::<T>(
+            allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            len,
+        )
+        .unwrap();
+        let main = Buffer::new_slice::<T>(
+            allocator,
+            BufferCreateInfo {
+                usage: usage | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            len,
+        )
+        .unwrap();
+
+        StagingBuffer { staging, main, pending: false }
+    }
+
+    // the staging half must not be read by a command buffer that's still executing
+    pub fn write(&mut self, data: &[T]) {
+        assert_eq!(data.len() as u64, self.staging.len(), "Data doesn't match the staging buffer length");
+        self.staging.write().unwrap().clone_from_slice(data);
+        self.pending = true;
+    }
+
+    // records the copy of the last write into the main half, nothing when it's already there
+    pub fn flush<L, A: CommandBufferAllocator>(&mut self, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        if std::mem::take(&mut self.pending) {
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(self.staging.clone(), self.main.clone()))
+                .unwrap();
+        }
+    }
+
+    pub fn buffer(&self) -> Subbuffer<[T]> {
+        self.main.clone()
+    }
+}
+
+// one StagingBuffer per frame in flight, so writing the next frame's data never touches
+// a region the previous frames may still copy from or read
+#[derive(Debug)]
+pub struct UpdatableRingBuffer<T: BufferContents> {
+    frames: Vec<StagingBuffer<T>>,
+}
+
+impl<T: BufferContents + Clone> UpdatableRingBuffer<T> {
+    pub fn new(state: &State, usage: BufferUsage, len: u64) -> UpdatableRingBuffer<T> {
+        UpdatableRingBuffer {
+            frames: (0..state.renderer.frames_in_flight).map(|_| StagingBuffer::new(state, usage, len)).collect(),
+        }
+    }
+
+    // call once the frame's fence has signaled
+    pub fn write(&mut self, frame: usize, data: &[T]) {
+        self.frames[frame].write(data);
+    }
+
+    pub fn flush<L, A: CommandBufferAllocator>(&mut self, frame: usize, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        self.frames[frame].flush(builder);
+    }
+
+    pub fn current(&self, frame: usize) -> Subbuffer<[T]> {
+        self.frames[frame].buffer()
+    }
+}
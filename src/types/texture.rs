@@ -1,71 +1,370 @@
-use std::sync::Arc;
+use std::{collections::HashMap, fmt, io, path::Path, sync::Arc};
 
-use image::{ImageReader, RgbaImage};
+use image::{imageops, ImageError, ImageReader, Rgba, RgbaImage};
 
-use log::debug;
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use vulkano::{
-    buffer::{Buffer, BufferCreateInfo, BufferUsage},
-    command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        CopyBufferToImageInfo,
-    },
+    command_buffer::{BlitImageInfo, BufferImageCopy, CopyBufferToImageInfo, ImageBlit},
     format::Format,
     image::{
-        sampler::{Sampler, SamplerCreateInfo},
-        view::{ImageView, ImageViewCreateInfo},
-        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceLayers, ImageType,
+        ImageUsage,
     },
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
-    sync::{now, GpuFuture},
 };
 
 use crate::{
     asset_library::AssetLibrary,
+    asset_loading::{UploadContext, UploadJob},
+    types::material::Attachment,
     ecs::{System, World},
+    engine_config::AssetPaths,
     state::State,
+    vulkan::transfer::TransferCommandBuilder,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextureKind {
+    #[default]
+    D2,
+    Cube,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextureFormat {
+    #[default]
+    Rgba8,
+    Rgba16F,
+}
+
+#[derive(Debug)]
+pub enum TextureLoadError {
+    Open { path: String, error: io::Error },
+    Decode { path: String, error: ImageError },
+}
+
+impl fmt::Display for TextureLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureLoadError::Open { path, error } => write!(f, "failed to open texture {}: {}", path, error),
+            TextureLoadError::Decode { path, error } => write!(f, "failed to decode texture {}: {}", path, error),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CubemapError {
+    Load(String),
+    FaceCount(usize),
+    NotSquare { face: usize, width: u32, height: u32 },
+    SizeMismatch { face: usize, expected: u32, found: u32 },
+    UnknownLayout { width: u32, height: u32 },
+}
+
+impl fmt::Display for CubemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CubemapError::Load(error) => write!(f, "{}", error),
+            CubemapError::FaceCount(count) => {
+                write!(f, "cubemap needs 6 face images or a single layout image, got {}", count)
+            }
+            CubemapError::NotSquare { face, width, height } => {
+                write!(f, "cubemap face {} is {}x{}, faces must be square", face, width, height)
+            }
+            CubemapError::SizeMismatch { face, expected, found } => {
+                write!(f, "cubemap face {} is {} pixels wide, expected {}", face, found, expected)
+            }
+            CubemapError::UnknownLayout { width, height } => write!(
+                f,
+                "cubemap layout image is {}x{}, expected a 4x3 or 3x4 cross or a 6x1 or 1x6 strip",
+                width, height
+            ),
+        }
+    }
+}
+
+impl From<TextureLoadError> for CubemapError {
+    fn from(value: TextureLoadError) -> Self {
+        CubemapError::Load(value.to_string())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Texture {
     pub name: String,
     pub image_data: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    #[serde(default)]
+    pub srgb: bool,
+    #[serde(default = "default_generate_mips")]
+    pub generate_mips: bool,
+    #[serde(default)]
+    pub kind: TextureKind,
+    #[serde(default)]
+    pub format: TextureFormat,
     #[serde(skip)]
     pub image: Option<Arc<Image>>,
     #[serde(skip)]
     pub image_view: Option<Arc<ImageView>>,
     #[serde(skip)]
     pub sampler: Option<Arc<Sampler>>,
+    // written by compute shaders instead of uploaded, image_data stays empty
+    #[serde(default)]
+    pub storage: bool,
+}
+
+pub fn default_generate_mips() -> bool {
+    true
+}
+
+pub fn mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn record_mip_chain(builder: &mut TransferCommandBuilder, image: &Arc<Image>) {
+    let [mut width, mut height, _] = image.extent();
+    for level in 1..image.mip_levels() {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        builder
+            .blit_image(BlitImageInfo {
+                regions: [ImageBlit {
+                    src_subresource: ImageSubresourceLayers {
+                        aspects: ImageAspects::COLOR,
+                        mip_level: level - 1,
+                        array_layers: 0..image.array_layers(),
+                    },
+                    src_offsets: [[0, 0, 0], [width, height, 1]],
+                    dst_subresource: ImageSubresourceLayers {
+                        aspects: ImageAspects::COLOR,
+                        mip_level: level,
+                        array_layers: 0..image.array_layers(),
+                    },
+                    dst_offsets: [[0, 0, 0], [next_width, next_height, 1]],
+                    ..Default::default()
+                }]
+                .into(),
+                filter: Filter::Linear,
+                ..BlitImageInfo::images(image.clone(), image.clone())
+            })
+            .unwrap();
+        (width, height) = (next_width, next_height);
+    }
+}
+
+fn decode_image(path: &Path) -> Result<image::DynamicImage, TextureLoadError> {
+    debug!("Loading texture {}", path.display());
+    let open_error = |error| TextureLoadError::Open { path: path.display().to_string(), error };
+    ImageReader::open(path)
+        .map_err(open_error)?
+        .with_guessed_format()
+        .map_err(open_error)?
+        .decode()
+        .map_err(|error| TextureLoadError::Decode { path: path.display().to_string(), error })
+}
+
+fn read_image(name: &str, paths: &AssetPaths) -> Result<RgbaImage, TextureLoadError> {
+    Ok(decode_image(&paths.textures.join(name))?.to_rgba8())
+}
+
+fn is_hdr(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x.eq_ignore_ascii_case("hdr") || x.eq_ignore_ascii_case("exr"))
+}
+
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = (mantissa | 0x80_0000) >> (1 - exponent);
+        return sign | ((mantissa + 0x1000) >> 13) as u16;
+    }
+
+    // a rounding carry out of the mantissa correctly bumps the exponent
+    sign | (((exponent as u32) << 10) + ((mantissa + 0x1000) >> 13)) as u16
+}
+
+pub(crate) fn f16_to_f32(value: u16) -> f32 {
+    let sign = if value & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((value >> 10) & 0x1f) as i32;
+    let mantissa = (value & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2.0f32.powi(-24),
+        0x1f if mantissa != 0.0 => f32::NAN,
+        0x1f => sign * f32::INFINITY,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+    }
+}
+
+// hdr sources are kept as half floats so values above 1.0 survive the upload
+fn read_pixels(path: &Path) -> Result<(TextureFormat, u32, u32, Vec<u8>), TextureLoadError> {
+    let image = decode_image(path)?;
+
+    if is_hdr(path) {
+        let image = image.to_rgba32f();
+        let data = image
+            .as_raw()
+            .iter()
+            .flat_map(|x| f32_to_f16(*x).to_le_bytes())
+            .collect();
+        Ok((TextureFormat::Rgba16F, image.width(), image.height(), data))
+    } else {
+        let image = image.to_rgba8();
+        Ok((TextureFormat::Rgba8, image.width(), image.height(), image.into_raw()))
+    }
+}
+
+// returns the common edge length of the faces
+fn validate_faces(faces: &[RgbaImage]) -> Result<u32, CubemapError> {
+    if faces.len() != 6 {
+        return Err(CubemapError::FaceCount(faces.len()));
+    }
+
+    let size = faces[0].width();
+    for (face, image) in faces.iter().enumerate() {
+        if image.width() != image.height() {
+            return Err(CubemapError::NotSquare { face, width: image.width(), height: image.height() });
+        }
+        if image.width() != size {
+            return Err(CubemapError::SizeMismatch { face, expected: size, found: image.width() });
+        }
+    }
+
+    Ok(size)
+}
+
+// splits a cross or strip layout into faces ordered +X, -X, +Y, -Y, +Z, -Z
+fn split_cube_layout(image: &RgbaImage) -> Result<Vec<RgbaImage>, CubemapError> {
+    let (width, height) = image.dimensions();
+    let (size, cells, flip_last) = if width * 3 == height * 4 {
+        (width / 4, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)], false)
+    } else if width * 4 == height * 3 {
+        (width / 3, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)], true)
+    } else if width == height * 6 {
+        (height, [(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0)], false)
+    } else if height == width * 6 {
+        (width, [(0, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5)], false)
+    } else {
+        return Err(CubemapError::UnknownLayout { width, height });
+    };
+
+    let mut faces = cells
+        .iter()
+        .map(|(x, y)| imageops::crop_imm(image, x * size, y * size, size, size).to_image())
+        .collect::<Vec<_>>();
+    // the -Z face of a vertical cross is stored upside down
+    if flip_last {
+        imageops::rotate180_in_place(&mut faces[5]);
+    }
+
+    Ok(faces)
 }
 
 impl Texture {
-    pub fn new(name: String) -> Texture {
-        debug!("Loading texture {}", format!("assets/textures/{}", name));
-        let image = ImageReader::open(format!("assets/textures/{}", name))
-            .unwrap()
-            .with_guessed_format()
-            .unwrap()
-            .decode()
-            .unwrap()
-            .to_rgba8();
+    pub fn new(name: String, srgb: bool, generate_mips: bool, paths: &AssetPaths) -> Result<Texture, TextureLoadError> {
+        let (format, width, height, image_data) = read_pixels(&paths.textures.join(&name))?;
+
+        Ok(Texture {
+            name,
+            image_data,
+            width,
+            height,
+            srgb,
+            generate_mips,
+            kind: TextureKind::D2,
+            format,
+            image: None,
+            image_view: None,
+            sampler: None,
+            storage: false,
+        })
+    }
+
+    // accepts either six face images or a single cross/strip layout image
+    pub fn new_cube(
+        name: String,
+        faces: &[String],
+        srgb: bool,
+        generate_mips: bool,
+        paths: &AssetPaths,
+    ) -> Result<Texture, CubemapError> {
+        let faces = match faces.len() {
+            1 => split_cube_layout(&read_image(&faces[0], paths)?)?,
+            _ => faces.iter().map(|x| read_image(x, paths)).collect::<Result<Vec<_>, _>>()?,
+        };
+        let size = validate_faces(&faces)?;
+
+        Ok(Texture {
+            name,
+            image_data: faces.iter().flat_map(|x| x.as_raw().iter().copied()).collect(),
+            width: size,
+            height: size,
+            srgb,
+            generate_mips,
+            kind: TextureKind::Cube,
+            format: TextureFormat::Rgba8,
+            image: None,
+            image_view: None,
+            sampler: None,
+            storage: false,
+        })
+    }
+
+    pub fn from_rgba8(name: &str, width: u32, height: u32, pixels: Vec<u8>) -> Texture {
+        assert_eq!(pixels.len(), (width * height * 4) as usize, "Pixel data doesn't match texture size");
 
         Texture {
-            name: name.clone(),
-            image_data: image.to_vec(),
-            width: image.width(),
-            height: image.height(),
+            name: name.to_string(),
+            image_data: pixels,
+            width,
+            height,
+            srgb: true,
+            generate_mips: false,
+            kind: TextureKind::D2,
+            format: TextureFormat::Rgba8,
             image: None,
             image_view: None,
             sampler: None,
+            storage: false,
         }
     }
 
-    fn load(&mut self, state: &State) {
-        let img = RgbaImage::from_vec(self.width, self.height, self.image_data.clone()).unwrap();
+    pub fn new_storage(name: &str, width: u32, height: u32) -> Texture {
+        Texture {
+            name: name.to_string(),
+            image_data: vec![],
+            width,
+            height,
+            srgb: false,
+            generate_mips: false,
+            kind: TextureKind::D2,
+            format: TextureFormat::Rgba8,
+            image: None,
+            image_view: None,
+            sampler: None,
+            storage: true,
+        }
+    }
 
+    fn load_storage(&mut self, state: &State) {
         self.image = Some(
             Image::new(
                 state.memory_allocators.standard_memory_allocator.clone(),
@@ -73,7 +372,7 @@ impl Texture {
                     image_type: ImageType::Dim2d,
                     format: Format::R8G8B8A8_UNORM,
                     extent: [self.width, self.height, 1],
-                    usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+                    usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
                     ..Default::default()
                 },
                 AllocationCreateInfo {
@@ -84,48 +383,6 @@ impl Texture {
             .unwrap(),
         );
 
-        let command_buffer_allocator =
-            StandardCommandBufferAllocator::new(state.vulkan_context.device.clone(), Default::default());
-
-        let mut builder = AutoCommandBufferBuilder::primary(
-            &command_buffer_allocator,
-            state.vulkan_context.queue.queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
-
-        let temp_buffer = Buffer::from_iter(
-            state.memory_allocators.standard_memory_allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            img.into_flat_samples().as_slice().to_owned(),
-        )
-        .unwrap();
-
-        builder
-            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
-                temp_buffer,
-                self.image.as_ref().unwrap().to_owned(),
-            ))
-            .unwrap();
-
-        let command_buffer = builder.build().unwrap();
-
-        let future = now(state.vulkan_context.device.clone())
-            .then_execute(state.vulkan_context.queue.clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
-
-        future.wait(None).unwrap();
-
         self.image_view = Some(
             ImageView::new(
                 self.image.as_ref().unwrap().clone(),
@@ -145,34 +402,282 @@ impl Texture {
             .unwrap(),
         );
     }
+
+    pub fn mip_levels(&self) -> u32 {
+        if self.generate_mips && !self.storage {
+            mip_levels(self.width, self.height)
+        } else {
+            1
+        }
+    }
+
+    pub fn vulkan_format(&self) -> Format {
+        match self.format {
+            TextureFormat::Rgba8 if self.srgb => Format::R8G8B8A8_SRGB,
+            TextureFormat::Rgba8 => Format::R8G8B8A8_UNORM,
+            TextureFormat::Rgba16F => Format::R16G16B16A16_SFLOAT,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.image.is_some()
+    }
+
+    fn write_region(&mut self, x: u32, y: u32, width: u32, height: u32, pixels: &[u8]) {
+        assert_eq!(self.format, TextureFormat::Rgba8, "Only rgba8 textures can be updated");
+        assert!(x + width <= self.width && y + height <= self.height, "Region out of texture bounds");
+        assert_eq!(pixels.len(), (width * height * 4) as usize, "Pixel data doesn't match region size");
+
+        let row_size = (width * 4) as usize;
+        for row in 0..height {
+            let src = (row * width * 4) as usize;
+            let dst = (((y + row) * self.width + x) * 4) as usize;
+            self.image_data[dst..dst + row_size].copy_from_slice(&pixels[src..src + row_size]);
+        }
+    }
+
+    // copies new rgba8 pixels into the existing image, the image view and sampler stay the same
+    pub fn update_region(&mut self, state: &State, x: u32, y: u32, width: u32, height: u32, pixels: &[u8]) {
+        self.write_region(x, y, width, height, pixels);
+
+        let image = match self.image.as_ref() {
+            Some(val) => val.clone(),
+            None => return,
+        };
+
+        let transfer = &state.memory_allocators.transfer;
+        let mut copies = transfer.copy_builder();
+
+        let temp_buffer = transfer
+            .staging_buffer(state.memory_allocators.standard_memory_allocator.clone(), pixels.iter().copied())
+            .unwrap();
+
+        copies
+            .copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: [BufferImageCopy {
+                    image_subresource: image.subresource_layers(),
+                    image_offset: [x, y, 0],
+                    image_extent: [width, height, 1],
+                    ..Default::default()
+                }]
+                .into(),
+                ..CopyBufferToImageInfo::buffer_image(temp_buffer, image.clone())
+            })
+            .unwrap();
+        let mips = (image.mip_levels() > 1).then(|| {
+            let mut mips = transfer.graphics_builder();
+            record_mip_chain(&mut mips, &image);
+            mips
+        });
+
+        // frames still in flight may be sampling the image
+        state.renderer.wait_for_frames();
+        transfer.submit(copies, mips);
+    }
+
+    pub fn load(&mut self, state: &State) {
+        if self.storage {
+            self.load_storage(state);
+            return;
+        }
+
+        let uploaded = self.upload_job(Uuid::nil()).run(&UploadContext::new(state));
+        self.finish_upload(state, uploaded);
+    }
+
+    // moves the pixels into a job that can be uploaded away from the asset library
+    pub(crate) fn upload_job(&mut self, uuid: Uuid) -> TextureUpload {
+        TextureUpload {
+            uuid,
+            pixels: std::mem::take(&mut self.image_data),
+            width: self.width,
+            height: self.height,
+            format: self.vulkan_format(),
+            kind: self.kind,
+            mip_levels: self.mip_levels(),
+        }
+    }
+
+    pub(crate) fn finish_upload(&mut self, state: &State, uploaded: UploadedTexture) {
+        let mut create_info = SamplerCreateInfo::simple_repeat_linear();
+        create_info.anisotropy = state.renderer.anisotropic;
+        if self.kind == TextureKind::Cube {
+            create_info.address_mode = [SamplerAddressMode::ClampToEdge; 3];
+        }
+        create_info.lod = 0.0..=self.mip_levels() as f32;
+
+        self.sampler = Some(Sampler::new(state.vulkan_context.device.clone(), create_info).unwrap());
+        self.image_data = uploaded.pixels;
+        self.image = Some(uploaded.image);
+        self.image_view = Some(uploaded.image_view);
+    }
+}
+
+pub struct TextureUpload {
+    pub uuid: Uuid,
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    format: Format,
+    kind: TextureKind,
+    mip_levels: u32,
+}
+
+pub struct UploadedTexture {
+    pub uuid: Uuid,
+    pixels: Vec<u8>,
+    image: Arc<Image>,
+    image_view: Arc<ImageView>,
+}
+
+impl TextureUpload {
+    pub(crate) fn run(self, context: &UploadContext) -> UploadedTexture {
+        let (flags, array_layers) = match self.kind {
+            TextureKind::D2 => (ImageCreateFlags::empty(), 1),
+            TextureKind::Cube => (ImageCreateFlags::CUBE_COMPATIBLE, 6),
+        };
+
+        let image = Image::new(
+            context.memory_allocator.clone(),
+            context.transfer.image_info(ImageCreateInfo {
+                flags,
+                image_type: ImageType::Dim2d,
+                format: self.format,
+                extent: [self.width, self.height, 1],
+                array_layers,
+                mip_levels: self.mip_levels,
+                usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+                ..Default::default()
+            }),
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let temp_buffer = context
+            .transfer
+            .staging_buffer(context.memory_allocator.clone(), self.pixels.iter().copied())
+            .unwrap();
+
+        let mut copies = context.transfer.copy_builder();
+        copies
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(temp_buffer, image.clone()))
+            .unwrap();
+        // blits need the graphics queue
+        let mips = (self.mip_levels > 1).then(|| {
+            let mut mips = context.transfer.graphics_builder();
+            record_mip_chain(&mut mips, &image);
+            mips
+        });
+        context.transfer.submit(copies, mips);
+
+        let view_type = match self.kind {
+            TextureKind::D2 => ImageViewType::Dim2d,
+            TextureKind::Cube => ImageViewType::Cube,
+        };
+        let image_view = ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                view_type,
+                ..ImageViewCreateInfo::from_image(image.as_ref())
+            },
+        )
+        .unwrap();
+
+        UploadedTexture {
+            uuid: self.uuid,
+            pixels: self.pixels,
+            image,
+            image_view,
+        }
+    }
+}
+
+// the texture itself while it's resident, the default texture while it's still uploading
+pub fn resident_texture<'a>(assets: &'a AssetLibrary, uuid: &Uuid) -> Option<&'a Texture> {
+    match assets.textures.get(uuid) {
+        Some(texture) if texture.is_loaded() => Some(texture),
+        _ => assets.texture_by_name("default").map(|x| x.1),
+    }
+}
+
+// loads a texture into the map, falling back to the default texture when it can't be read
+pub fn texture_attachment(textures: &mut HashMap<Uuid, Texture>, name: String, srgb: bool, paths: &AssetPaths) -> Attachment {
+    match Texture::new(name, srgb, true, paths) {
+        Ok(texture) => {
+            let uuid = Uuid::new_v4();
+            textures.insert(uuid, texture);
+            Attachment::Texture(uuid)
+        }
+        Err(e) => {
+            error!("{}", e);
+            Attachment::DefaultTexture
+        }
+    }
 }
 
 pub struct TextureLoader {}
 
 impl System for TextureLoader {
     fn on_start(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
-        for (_, mesh) in assets.textures.iter_mut() {
-            mesh.load(state);
+        if let Err(e) = assets.load_pending_textures() {
+            error!("{}", e);
+        }
+        for (uuid, texture) in assets.textures.iter_mut().filter(|(_, x)| !x.is_loaded()) {
+            if texture.storage {
+                texture.load(state);
+            } else {
+                state.asset_loading.submit(UploadJob::Texture(texture.upload_job(*uuid)));
+            }
+        }
+    }
+
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        for result in state.asset_loading.finished_textures() {
+            match result {
+                Ok(uploaded) => {
+                    if let Some(texture) = assets.textures.get_mut(&uploaded.uuid) {
+                        texture.finish_upload(state, uploaded);
+                    }
+                }
+                // the pixels went with the upload, the default texture is drawn instead
+                Err(e) => error!("{}", e),
+            }
         }
     }
-    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
 }
 
 pub struct DefaultTextureLoader {}
 
+const DEFAULT_TEXTURE_SIZE: u32 = 64;
+const DEFAULT_TEXTURE_CELL: u32 = 8;
+
+fn checkerboard(size: u32, cell: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        if (x / cell + y / cell).is_multiple_of(2) {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
 fn default_texture(state: &State) -> Texture {
-    let img = RgbaImage::new(1, 1);
+    let img = checkerboard(DEFAULT_TEXTURE_SIZE, DEFAULT_TEXTURE_CELL);
+    let transfer = &state.memory_allocators.transfer;
 
     let image = Some(
         Image::new(
             state.memory_allocators.standard_memory_allocator.clone(),
-            ImageCreateInfo {
+            transfer.image_info(ImageCreateInfo {
                 image_type: ImageType::Dim2d,
                 format: Format::R8G8B8A8_UNORM,
                 extent: [img.width(), img.height(), 1],
                 usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
                 ..Default::default()
-            },
+            }),
             AllocationCreateInfo {
                 memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
                 ..Default::default()
@@ -181,47 +686,21 @@ fn default_texture(state: &State) -> Texture {
         .unwrap(),
     );
 
-    let command_buffer_allocator =
-        StandardCommandBufferAllocator::new(state.vulkan_context.device.clone(), Default::default());
-
-    let mut builder = AutoCommandBufferBuilder::primary(
-        &command_buffer_allocator,
-        state.vulkan_context.queue.queue_family_index(),
-        CommandBufferUsage::OneTimeSubmit,
-    )
-    .unwrap();
-
-    let temp_buffer = Buffer::from_iter(
-        state.memory_allocators.standard_memory_allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_SRC,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
-        img.into_flat_samples().as_slice().to_owned(),
-    )
-    .unwrap();
-
-    builder
+    let temp_buffer = transfer
+        .staging_buffer(
+            state.memory_allocators.standard_memory_allocator.clone(),
+            img.into_flat_samples().as_slice().to_owned(),
+        )
+        .unwrap();
+
+    let mut copies = transfer.copy_builder();
+    copies
         .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
             temp_buffer,
             image.as_ref().unwrap().to_owned(),
         ))
         .unwrap();
-
-    let command_buffer = builder.build().unwrap();
-
-    let future = now(state.vulkan_context.device.clone())
-        .then_execute(state.vulkan_context.queue.clone(), command_buffer)
-        .unwrap()
-        .then_signal_fence_and_flush()
-        .unwrap();
-
-    future.wait(None).unwrap();
+    transfer.submit(copies, None);
 
     let image_view = Some(
         ImageView::new(
@@ -240,11 +719,16 @@ fn default_texture(state: &State) -> Texture {
     Texture {
         name: "default".to_string(),
         image_data: vec![],
-        width: 1,
-        height: 1,
+        width: DEFAULT_TEXTURE_SIZE,
+        height: DEFAULT_TEXTURE_SIZE,
+        srgb: false,
+        generate_mips: false,
+        kind: TextureKind::D2,
+        format: TextureFormat::Rgba8,
         image,
         image_view,
         sampler,
+        storage: false,
     }
 }
 
@@ -256,3 +740,161 @@ impl System for DefaultTextureLoader {
     }
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs::File, path::Path};
+
+    use image::{codecs::hdr::HdrEncoder, Rgb, Rgba, RgbaImage};
+
+    use crate::{engine_config::AssetPaths, types::material::Attachment};
+
+    use super::{
+        checkerboard, f16_to_f32, f32_to_f16, mip_levels, read_pixels, split_cube_layout, texture_attachment, validate_faces,
+        CubemapError, Texture, TextureFormat, TextureLoadError,
+    };
+
+    fn texture(width: u32, height: u32, generate_mips: bool) -> Texture {
+        let mut texture = Texture::new_storage("test", width, height);
+        texture.storage = false;
+        texture.generate_mips = generate_mips;
+        texture
+    }
+
+    #[test]
+    fn test_mip_levels() {
+        assert_eq!(mip_levels(1, 1), 1);
+        assert_eq!(mip_levels(256, 256), 9);
+        assert_eq!(mip_levels(1024, 512), 11);
+        assert_eq!(mip_levels(300, 17), 9);
+    }
+
+    #[test]
+    fn test_storage_flag_serialized() {
+        let bytes = rmp_serde::to_vec(&Texture::new_storage("gradient", 64, 32)).unwrap();
+        let texture: Texture = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(texture.storage);
+        assert_eq!((texture.width, texture.height), (64, 32));
+        assert_eq!(texture.mip_levels(), 1);
+    }
+
+    #[test]
+    fn test_image_mip_count() {
+        assert_eq!(texture(512, 512, true).mip_levels(), 10);
+        assert_eq!(texture(512, 512, false).mip_levels(), 1);
+        assert_eq!(Texture::new_storage("storage", 512, 512).mip_levels(), 1);
+    }
+
+    fn face_grid(columns: u32, rows: u32, size: u32) -> RgbaImage {
+        RgbaImage::from_fn(columns * size, rows * size, |x, y| {
+            Rgba([(x / size) as u8, (y / size) as u8, 0, 255])
+        })
+    }
+
+    #[test]
+    fn test_horizontal_cross_faces() {
+        let faces = split_cube_layout(&face_grid(4, 3, 8)).unwrap();
+        let cells = faces.iter().map(|x| (x.get_pixel(0, 0)[0], x.get_pixel(0, 0)[1])).collect::<Vec<_>>();
+        assert_eq!(cells, vec![(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)]);
+        assert_eq!(validate_faces(&faces), Ok(8));
+    }
+
+    #[test]
+    fn test_strip_faces() {
+        let faces = split_cube_layout(&face_grid(6, 1, 4)).unwrap();
+        assert!(faces.iter().enumerate().all(|(id, x)| x.get_pixel(3, 3)[0] == id as u8));
+        assert_eq!(validate_faces(&faces), Ok(4));
+    }
+
+    #[test]
+    fn test_invalid_cube_faces() {
+        assert_eq!(
+            split_cube_layout(&face_grid(2, 1, 4)).unwrap_err(),
+            CubemapError::UnknownLayout { width: 8, height: 4 }
+        );
+
+        let mut faces = vec![RgbaImage::new(4, 4); 6];
+        assert_eq!(validate_faces(&faces[..5]), Err(CubemapError::FaceCount(5)));
+        faces[2] = RgbaImage::new(4, 2);
+        assert_eq!(validate_faces(&faces), Err(CubemapError::NotSquare { face: 2, width: 4, height: 2 }));
+        faces[2] = RgbaImage::new(8, 8);
+        assert_eq!(validate_faces(&faces), Err(CubemapError::SizeMismatch { face: 2, expected: 4, found: 8 }));
+    }
+
+    #[test]
+    fn test_write_region() {
+        let mut texture = Texture::from_rgba8("procedural", 4, 4, vec![0; 64]);
+        texture.write_region(1, 2, 2, 1, &[255; 8]);
+
+        let written = texture
+            .image_data
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, x)| x[0] == 255)
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(written, vec![9, 10]);
+    }
+
+    #[test]
+    fn test_f32_to_f16() {
+        for value in [0.0, 1.0, -2.5, 4.0, 65504.0, 2.0f32.powi(-14)] {
+            assert_eq!(f16_to_f32(f32_to_f16(value)), value);
+        }
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+    }
+
+    #[test]
+    fn test_hdr_values_survive_upload() {
+        let path = std::env::temp_dir().join(format!("oxide_test_{}.hdr", std::process::id()));
+        let pixels = [Rgb([4.0, 1.5, 0.25]), Rgb([16.0, 0.5, 2.0])];
+        HdrEncoder::new(File::create(&path).unwrap()).encode(&pixels, 2, 1).unwrap();
+
+        let (format, width, height, data) = read_pixels(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((format, width, height), (TextureFormat::Rgba16F, 2, 1));
+        let values = data
+            .chunks_exact(2)
+            .map(|x| f16_to_f32(u16::from_le_bytes([x[0], x[1]])))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![4.0, 1.5, 0.25, 1.0, 16.0, 0.5, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn test_missing_texture() {
+        let error = read_pixels(Path::new("assets/textures/missing.png")).unwrap_err();
+        assert!(matches!(error, TextureLoadError::Open { .. }));
+        assert!(error.to_string().contains("assets/textures/missing.png"));
+
+        let mut textures = HashMap::new();
+        let attachment = texture_attachment(&mut textures, "missing.png".to_string(), true, &AssetPaths::default());
+        assert!(matches!(attachment, Attachment::DefaultTexture));
+        assert!(textures.is_empty());
+    }
+
+    #[test]
+    fn test_textures_from_asset_root() {
+        let root = std::env::temp_dir().join(format!("oxide_test_root_{}", std::process::id()));
+        let paths = AssetPaths::with_root(&root);
+        std::fs::create_dir_all(paths.textures.join("ship")).unwrap();
+        RgbaImage::from_pixel(4, 2, Rgba([0, 128, 255, 255])).save(paths.textures.join("ship/hull.png")).unwrap();
+
+        let texture = Texture::new("ship/hull.png".to_string(), true, false, &paths);
+        let missing = Texture::new("ship/hull.png".to_string(), true, false, &AssetPaths::default());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let texture = texture.unwrap();
+        assert_eq!((texture.name.as_str(), texture.width, texture.height), ("ship/hull.png", 4, 2));
+        assert_eq!(&texture.image_data[..4], &[0, 128, 255, 255]);
+        assert!(matches!(missing, Err(TextureLoadError::Open { .. })));
+    }
+
+    #[test]
+    fn test_default_checkerboard() {
+        let image = checkerboard(16, 8);
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 255, 255]);
+        assert_eq!(image.get_pixel(8, 0).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(8, 8).0, [255, 0, 255, 255]);
+    }
+}
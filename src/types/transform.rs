@@ -4,10 +4,10 @@ use crate::{
     asset_library::AssetLibrary,
     ecs::{System, World},
     state::State,
-    types::{quaternion::Quat, vectors::*},
+    types::{model::NodeTransform, quaternion::Quat, vectors::*},
 };
 
-use super::{matrices::Matrix4f, position::Position};
+use super::{matrices::{Matrix4d, Matrix4f}, position::Position};
 
 #[derive(Clone)]
 pub struct Transform {
@@ -33,14 +33,35 @@ impl Transform {
         }
     }
 
+    // where a model node placed at `node` ends up in the world
+    pub fn with_node(&self, node: &NodeTransform) -> Transform {
+        let offset = node.0 * self.scale * self.rotation;
+        Transform {
+            position: self.position + Position::from(Vec3d::new([offset.x as f64, offset.y as f64, offset.z as f64])),
+            scale: node.2 * self.scale,
+            rotation: node.1 * self.rotation,
+        }
+    }
+
+    // the model matrix, translation, rotation and scale in one, placed relative to `relative_to` so it stays
+    // precise far from the origin, usually the camera's position
+    pub fn to_matrix(&self, relative_to: Position) -> Matrix4f {
+        Matrix4f::translation((self.position - relative_to).into()) * self.rotation.to_matrix() * Matrix4f::scale(self.scale)
+    }
+
+    // the model matrix in world space, in doubles
+    pub fn to_matrix_d(&self) -> Matrix4d {
+        let rotation = Matrix4d(self.rotation.to_matrix().0.map(|x| x.map(|x| x as f64)));
+        Matrix4d::translation(self.position.into()) * rotation * Matrix4d::scale(self.scale.to_vec3d())
+    }
+
+    // where -z ends up, the way cameras look
     pub fn front(&self) -> Vec3f {
-        let f = self.rotation.to_matrix().vec_mul(Vec3f::new([1.0, 0.0, 0.0]));
-        Vec3f::new([f.x, f.y, f.z])
+        Vec3f::new([0.0, 0.0, -1.0]) * self.rotation
     }
     
     pub fn up(&self) -> Vec3f {
-        let f = self.rotation.to_matrix().vec_mul(Vec3f::new([0.0, 1.0, 0.0]));
-        Vec3f::new([f.x, f.y, f.z])
+        Vec3f::new([0.0, 1.0, 0.0]) * self.rotation
     }
 }
 
@@ -49,3 +70,59 @@ impl System for TransformUpdater {
     fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use crate::types::{matrices::Matrix4f, position::Position, quaternion::Quat, vectors::{Vec3d, Vec3f, Vec3i, Vec4f}};
+
+    use super::{ModelData, Transform};
+
+    #[test]
+    fn test_matrix_places_points_like_the_transform() {
+        let rotation = Quat::from_axis_angle(Vec3f::new([0.2, 1.0, -0.4]), 0.9);
+        let transform = Transform::new(Position::new(Vec3i::new([3, 0, -1]), Vec3d::new([5.0, -2.0, 1.5])), Vec3f::new([2.0, 0.5, 1.5]), rotation);
+        let camera = Position::new(Vec3i::new([3, 0, -1]), Vec3d::new([4.0, 0.0, 0.0]));
+
+        let point = Vec3f::new([1.0, -2.0, 0.5]);
+        let expected = point * transform.scale * rotation + Vec3f::new([1.0, -2.0, 1.5]);
+        let placed = transform.to_matrix(camera) * Vec4f::new([point.x, point.y, point.z, 1.0]);
+        assert_relative_eq!(placed.x, expected.x, epsilon = 1e-5);
+        assert_relative_eq!(placed.y, expected.y, epsilon = 1e-5);
+        assert_relative_eq!(placed.z, expected.z, epsilon = 1e-5);
+
+        // the same as the three matrices the shaders multiply
+        let model = ModelData {
+            translation: Matrix4f::translation(Vec3f::new([1.0, -2.0, 1.5])),
+            rotation: rotation.to_matrix(),
+            scale: Matrix4f::scale(transform.scale),
+        };
+        let combined = model.translation * model.rotation * model.scale;
+        for (a, b) in combined.0.iter().flatten().zip(transform.to_matrix(camera).0.iter().flatten()) {
+            assert_relative_eq!(*a, *b, epsilon = 1e-6);
+        }
+
+        let near_origin = Transform { position: Position::from(Vec3d::new([5.0, -2.0, 1.5])), ..transform };
+        let world = near_origin.to_matrix_d().mul_vec4([point.x as f64, point.y as f64, point.z as f64, 1.0]);
+        assert_relative_eq!(world[0], (expected.x + 4.0) as f64, epsilon = 1e-5);
+        assert_relative_eq!(world[1], expected.y as f64, epsilon = 1e-5);
+        assert_relative_eq!(world[2], expected.z as f64, epsilon = 1e-5);
+    }
+    #[test]
+    fn test_front_and_up_follow_look_rotation() {
+        let forward = Vec3f::new([1.0, -0.5, 2.0]);
+        let transform = Transform::new(Position::default(), Vec3f::new([1.0, 1.0, 1.0]), Quat::look_rotation(forward, Vec3f::new([0.0, 1.0, 0.0])));
+        let (front, up) = (transform.front(), transform.up());
+        let expected = forward.normalize();
+        assert_relative_eq!(front.x, expected.x, epsilon = 1e-5);
+        assert_relative_eq!(front.y, expected.y, epsilon = 1e-5);
+        assert_relative_eq!(front.z, expected.z, epsilon = 1e-5);
+        assert_relative_eq!(up.dot(front), 0.0, epsilon = 1e-5);
+        assert!(up.y > 0.0);
+
+        let turned = Transform { rotation: Quat::from_axis_angle(Vec3f::new([0.0, 1.0, 0.0]), std::f32::consts::FRAC_PI_2), ..transform };
+        assert_relative_eq!(turned.front().x, -1.0, epsilon = 1e-6);
+        assert_relative_eq!(turned.up().y, 1.0, epsilon = 1e-6);
+    }
+}
@@ -1,24 +1,26 @@
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Deref, DerefMut, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use bytemuck::{Pod, Zeroable};
-use serde::{Deserialize, Serialize};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 
-use super::{position::Position, quaternion::Quat};
+use super::{position::{Position, CHUNK_SIZE}, quaternion::Quat};
 
+// plain vectors for math, packs from before they lost their padding still load,
+// GpuVec2f and GpuVec3f keep the old layout for shaders
 #[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
-#[repr(C, align(16))]
+#[serde(from = "StoredVec2f")]
+#[repr(C)]
 pub struct Vec2f {
     pub x: f32,
     pub y: f32,
-    _align: i64
 }
 #[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
-#[repr(C, align(16))]
+#[serde(from = "StoredVec3f")]
+#[repr(C)]
 pub struct Vec3f {
     pub x: f32,
     pub y: f32,
     pub z: f32,
-    _align: i32
 }
 
 #[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
@@ -52,28 +54,236 @@ pub struct Vec3i {
     pub z: i64
 }
 
+// Vec2f and Vec3f padded to the 16 bytes vertex attributes and uniform buffers use,
+// stored the way the vectors themselves were before they lost their padding
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
+#[serde(from = "PaddedVec2f", into = "PaddedVec2f")]
+#[repr(C, align(16))]
+pub struct GpuVec2f {
+    pub vec: Vec2f,
+    _padding: [f32; 2],
+}
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
+#[serde(from = "PaddedVec3f", into = "PaddedVec3f")]
+#[repr(C, align(16))]
+pub struct GpuVec3f {
+    pub vec: Vec3f,
+    _padding: f32,
+}
+
+const _: () = assert!(size_of::<Vec2f>() == 8 && size_of::<Vec3f>() == 12);
+const _: () = assert!(size_of::<GpuVec2f>() == 16 && size_of::<GpuVec3f>() == 16);
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct PaddedVec2f {
+    x: f32,
+    y: f32,
+    _align: i64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct PaddedVec3f {
+    x: f32,
+    y: f32,
+    z: f32,
+    _align: i32,
+}
+
+// with or without the padding, packs store structs as arrays and untagged enums
+// only match those against tuple variants, the named ones are for text formats
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredVec2f {
+    Padded(f32, f32, IgnoredAny),
+    Plain(f32, f32),
+    Named { x: f32, y: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredVec3f {
+    Padded(f32, f32, f32, IgnoredAny),
+    Plain(f32, f32, f32),
+    Named { x: f32, y: f32, z: f32 },
+}
+
+impl From<StoredVec2f> for Vec2f {
+    #[inline]
+    fn from(value: StoredVec2f) -> Self {
+        match value {
+            StoredVec2f::Padded(x, y, _) | StoredVec2f::Plain(x, y) | StoredVec2f::Named { x, y } => Vec2f::new([x, y]),
+        }
+    }
+}
+
+impl From<StoredVec3f> for Vec3f {
+    #[inline]
+    fn from(value: StoredVec3f) -> Self {
+        match value {
+            StoredVec3f::Padded(x, y, z, _) | StoredVec3f::Plain(x, y, z) | StoredVec3f::Named { x, y, z } => {
+                Vec3f::new([x, y, z])
+            }
+        }
+    }
+}
+
+impl From<PaddedVec2f> for GpuVec2f {
+    #[inline]
+    fn from(value: PaddedVec2f) -> Self {
+        Vec2f::new([value.x, value.y]).into()
+    }
+}
+
+impl From<GpuVec2f> for PaddedVec2f {
+    #[inline]
+    fn from(value: GpuVec2f) -> Self {
+        PaddedVec2f { x: value.x, y: value.y, _align: 0 }
+    }
+}
+
+impl From<PaddedVec3f> for GpuVec3f {
+    #[inline]
+    fn from(value: PaddedVec3f) -> Self {
+        Vec3f::new([value.x, value.y, value.z]).into()
+    }
+}
+
+impl From<GpuVec3f> for PaddedVec3f {
+    #[inline]
+    fn from(value: GpuVec3f) -> Self {
+        PaddedVec3f { x: value.x, y: value.y, z: value.z, _align: 0 }
+    }
+}
+
+impl GpuVec2f {
+    #[inline]
+    pub fn new(val: [f32; 2]) -> GpuVec2f {
+        Vec2f::new(val).into()
+    }
+}
+
+impl GpuVec3f {
+    #[inline]
+    pub fn new(val: [f32; 3]) -> GpuVec3f {
+        Vec3f::new(val).into()
+    }
+}
+
+impl From<Vec2f> for GpuVec2f {
+    #[inline]
+    fn from(value: Vec2f) -> Self {
+        GpuVec2f { vec: value, _padding: [0.0; 2] }
+    }
+}
+
+impl From<GpuVec2f> for Vec2f {
+    #[inline]
+    fn from(value: GpuVec2f) -> Self {
+        value.vec
+    }
+}
+
+impl From<Vec3f> for GpuVec3f {
+    #[inline]
+    fn from(value: Vec3f) -> Self {
+        GpuVec3f { vec: value, _padding: 0.0 }
+    }
+}
+
+impl From<GpuVec3f> for Vec3f {
+    #[inline]
+    fn from(value: GpuVec3f) -> Self {
+        value.vec
+    }
+}
+
+impl Deref for GpuVec2f {
+    type Target = Vec2f;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl DerefMut for GpuVec2f {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.vec
+    }
+}
+
+impl Deref for GpuVec3f {
+    type Target = Vec3f;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl DerefMut for GpuVec3f {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.vec
+    }
+}
+
+// the padding isn't compared
+impl PartialEq for GpuVec2f {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.vec == other.vec
+    }
+}
+
+impl PartialEq for GpuVec3f {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.vec == other.vec
+    }
+}
+
 impl Add for Vec2f {
     type Output = Vec2f;
+    #[inline]
     fn add(self, rhs: Self) -> Self::Output {
         Vec2f::new([self.x + rhs.x, self.y + rhs.y])
     }
 }
 
 impl AddAssign for Vec2f {
+    #[inline]
     fn add_assign(&mut self, rhs: Self) {
         self.x += rhs.x;
         self.y += rhs.y;
     }
 }
 
+impl Add<f32> for Vec2f {
+    type Output = Vec2f;
+    #[inline]
+    fn add(self, rhs: f32) -> Self::Output {
+        Vec2f::new([self.x + rhs, self.y + rhs])
+    }
+}
+
+impl AddAssign<f32> for Vec2f {
+    #[inline]
+    fn add_assign(&mut self, rhs: f32) {
+        self.x += rhs;
+        self.y += rhs;
+    }
+}
+
 impl Add for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn add(self, rhs: Self) -> Self::Output {
         Vec3f::new([self.x + rhs.x, self.y + rhs.y, self.z + rhs.z])
     }
 }
 
 impl AddAssign for Vec3f {
+    #[inline]
     fn add_assign(&mut self, rhs: Self) {
         self.x += rhs.x;
         self.y += rhs.y;
@@ -81,28 +291,101 @@ impl AddAssign for Vec3f {
     }
 }
 
+impl Add<f32> for Vec3f {
+    type Output = Vec3f;
+    #[inline]
+    fn add(self, rhs: f32) -> Self::Output {
+        Vec3f::new([self.x + rhs, self.y + rhs, self.z + rhs])
+    }
+}
+
+impl AddAssign<f32> for Vec3f {
+    #[inline]
+    fn add_assign(&mut self, rhs: f32) {
+        self.x += rhs;
+        self.y += rhs;
+        self.z += rhs;
+    }
+}
+
+impl Add for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn add(self, rhs: Self) -> Self::Output {
+        Vec4f::new([self.x + rhs.x, self.y + rhs.y, self.z + rhs.z, self.w + rhs.w])
+    }
+}
+
+impl AddAssign for Vec4f {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        self.x += rhs.x;
+        self.y += rhs.y;
+        self.z += rhs.z;
+        self.w += rhs.w;
+    }
+}
+
+impl Add<f32> for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn add(self, rhs: f32) -> Self::Output {
+        Vec4f::new([self.x + rhs, self.y + rhs, self.z + rhs, self.w + rhs])
+    }
+}
+
+impl AddAssign<f32> for Vec4f {
+    #[inline]
+    fn add_assign(&mut self, rhs: f32) {
+        self.x += rhs;
+        self.y += rhs;
+        self.z += rhs;
+        self.w += rhs;
+    }
+}
+
 impl Add for Vec2d {
     type Output = Vec2d;
+    #[inline]
     fn add(self, rhs: Self) -> Self::Output {
         Vec2d::new([self.x + rhs.x, self.y + rhs.y])
     }
 }
 
 impl AddAssign for Vec2d {
+    #[inline]
     fn add_assign(&mut self, rhs: Self) {
         self.x += rhs.x;
         self.y += rhs.y;
     }
 }
 
+impl Add<f64> for Vec2d {
+    type Output = Vec2d;
+    #[inline]
+    fn add(self, rhs: f64) -> Self::Output {
+        Vec2d::new([self.x + rhs, self.y + rhs])
+    }
+}
+
+impl AddAssign<f64> for Vec2d {
+    #[inline]
+    fn add_assign(&mut self, rhs: f64) {
+        self.x += rhs;
+        self.y += rhs;
+    }
+}
+
 impl Add for Vec3d {
     type Output = Vec3d;
+    #[inline]
     fn add(self, rhs: Self) -> Self::Output {
         Vec3d::new([self.x + rhs.x, self.y + rhs.y, self.z + rhs.z])
     }
 }
 
 impl AddAssign for Vec3d {
+    #[inline]
     fn add_assign(&mut self, rhs: Self) {
         self.x += rhs.x;
         self.y += rhs.y;
@@ -110,14 +393,33 @@ impl AddAssign for Vec3d {
     }
 }
 
+impl Add<f64> for Vec3d {
+    type Output = Vec3d;
+    #[inline]
+    fn add(self, rhs: f64) -> Self::Output {
+        Vec3d::new([self.x + rhs, self.y + rhs, self.z + rhs])
+    }
+}
+
+impl AddAssign<f64> for Vec3d {
+    #[inline]
+    fn add_assign(&mut self, rhs: f64) {
+        self.x += rhs;
+        self.y += rhs;
+        self.z += rhs;
+    }
+}
+
 impl Add for Vec3i {
     type Output = Vec3i;
+    #[inline]
     fn add(self, rhs: Self) -> Self::Output {
         Vec3i::new([self.x + rhs.x, self.y + rhs.y, self.z + rhs.z])
     }
 }
 
 impl AddAssign for Vec3i {
+    #[inline]
     fn add_assign(&mut self, rhs: Self) {
         self.x += rhs.x;
         self.y += rhs.y;
@@ -125,8 +427,26 @@ impl AddAssign for Vec3i {
     }
 }
 
+impl Add<i64> for Vec3i {
+    type Output = Vec3i;
+    #[inline]
+    fn add(self, rhs: i64) -> Self::Output {
+        Vec3i::new([self.x + rhs, self.y + rhs, self.z + rhs])
+    }
+}
+
+impl AddAssign<i64> for Vec3i {
+    #[inline]
+    fn add_assign(&mut self, rhs: i64) {
+        self.x += rhs;
+        self.y += rhs;
+        self.z += rhs;
+    }
+}
+
 impl Div for Vec2f {
     type Output = Vec2f;
+    #[inline]
     fn div(self, rhs: Self) -> Self::Output {
         Vec2f::new([self.x / rhs.x, self.y / rhs.y])
     }
@@ -134,12 +454,14 @@ impl Div for Vec2f {
 
 impl Div<f32> for Vec2f {
     type Output = Vec2f;
+    #[inline]
     fn div(self, rhs: f32) -> Self::Output {
         Vec2f::new([self.x / rhs, self.y / rhs])
     }
 }
 
 impl DivAssign for Vec2f {
+    #[inline]
     fn div_assign(&mut self, rhs: Self) {
         self.x /= rhs.x;
         self.y /= rhs.y;
@@ -147,6 +469,7 @@ impl DivAssign for Vec2f {
 }
 
 impl DivAssign<f32> for Vec2f {
+    #[inline]
     fn div_assign(&mut self, rhs: f32) {
         self.x /= rhs;
         self.y /= rhs;
@@ -155,6 +478,7 @@ impl DivAssign<f32> for Vec2f {
 
 impl Div for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn div(self, rhs: Self) -> Self::Output {
         Vec3f::new([self.x / rhs.x, self.y / rhs.y, self.z / rhs.z])
     }
@@ -162,12 +486,14 @@ impl Div for Vec3f {
 
 impl Div<f32> for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn div(self, rhs: f32) -> Self::Output {
         Vec3f::new([self.x / rhs, self.y / rhs, self.z / rhs])
     }
 }
 
 impl DivAssign for Vec3f {
+    #[inline]
     fn div_assign(&mut self, rhs: Self) {
         self.x /= rhs.x;
         self.y /= rhs.y;
@@ -176,15 +502,53 @@ impl DivAssign for Vec3f {
 }
 
 impl DivAssign<f32> for Vec3f {
+    #[inline]
+    fn div_assign(&mut self, rhs: f32) {
+        self.x /= rhs;
+        self.y /= rhs;
+        self.z /= rhs;
+    }
+}
+
+impl Div for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn div(self, rhs: Self) -> Self::Output {
+        Vec4f::new([self.x / rhs.x, self.y / rhs.y, self.z / rhs.z, self.w / rhs.w])
+    }
+}
+
+impl DivAssign for Vec4f {
+    #[inline]
+    fn div_assign(&mut self, rhs: Self) {
+        self.x /= rhs.x;
+        self.y /= rhs.y;
+        self.z /= rhs.z;
+        self.w /= rhs.w;
+    }
+}
+
+impl Div<f32> for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn div(self, rhs: f32) -> Self::Output {
+        Vec4f::new([self.x / rhs, self.y / rhs, self.z / rhs, self.w / rhs])
+    }
+}
+
+impl DivAssign<f32> for Vec4f {
+    #[inline]
     fn div_assign(&mut self, rhs: f32) {
         self.x /= rhs;
         self.y /= rhs;
         self.z /= rhs;
+        self.w /= rhs;
     }
 }
 
 impl Div for Vec2d {
     type Output = Vec2d;
+    #[inline]
     fn div(self, rhs: Self) -> Self::Output {
         Vec2d::new([self.x / rhs.x, self.y / rhs.y])
     }
@@ -192,12 +556,14 @@ impl Div for Vec2d {
 
 impl Div<f64> for Vec2d {
     type Output = Vec2d;
+    #[inline]
     fn div(self, rhs: f64) -> Self::Output {
         Vec2d::new([self.x / rhs, self.y / rhs])
     }
 }
 
 impl DivAssign for Vec2d {
+    #[inline]
     fn div_assign(&mut self, rhs: Self) {
         self.x /= rhs.x;
         self.y /= rhs.y;
@@ -205,6 +571,7 @@ impl DivAssign for Vec2d {
 }
 
 impl DivAssign<f64> for Vec2d {
+    #[inline]
     fn div_assign(&mut self, rhs: f64) {
         self.x /= rhs;
         self.y /= rhs;
@@ -213,6 +580,7 @@ impl DivAssign<f64> for Vec2d {
 
 impl Div for Vec3d {
     type Output = Vec3d;
+    #[inline]
     fn div(self, rhs: Self) -> Self::Output {
         Vec3d::new([self.x / rhs.x, self.y / rhs.y, self.z / rhs.z])
     }
@@ -220,12 +588,14 @@ impl Div for Vec3d {
 
 impl Div<f64> for Vec3d {
     type Output = Vec3d;
+    #[inline]
     fn div(self, rhs: f64) -> Self::Output {
         Vec3d::new([self.x / rhs, self.y / rhs, self.z / rhs])
     }
 }
 
 impl DivAssign for Vec3d {
+    #[inline]
     fn div_assign(&mut self, rhs: Self) {
         self.x /= rhs.x;
         self.y /= rhs.y;
@@ -234,6 +604,7 @@ impl DivAssign for Vec3d {
 }
 
 impl DivAssign<f64> for Vec3d {
+    #[inline]
     fn div_assign(&mut self, rhs: f64) {
         self.x /= rhs;
         self.y /= rhs;
@@ -243,6 +614,7 @@ impl DivAssign<f64> for Vec3d {
 
 impl Div for Vec3i {
     type Output = Vec3i;
+    #[inline]
     fn div(self, rhs: Self) -> Self::Output {
         Vec3i::new([self.x / rhs.x, self.y / rhs.y, self.z / rhs.z])
     }
@@ -250,12 +622,14 @@ impl Div for Vec3i {
 
 impl Div<i64> for Vec3i {
     type Output = Vec3i;
+    #[inline]
     fn div(self, rhs: i64) -> Self::Output {
         Vec3i::new([self.x / rhs, self.y / rhs, self.z / rhs])
     }
 }
 
 impl DivAssign for Vec3i {
+    #[inline]
     fn div_assign(&mut self, rhs: Self) {
         self.x /= rhs.x;
         self.y /= rhs.y;
@@ -264,6 +638,7 @@ impl DivAssign for Vec3i {
 }
 
 impl DivAssign<i64> for Vec3i {
+    #[inline]
     fn div_assign(&mut self, rhs: i64) {
         self.x /= rhs;
         self.y /= rhs;
@@ -271,14 +646,24 @@ impl DivAssign<i64> for Vec3i {
     }
 }
 
+impl Mul for Vec2f {
+    type Output = Vec2f;
+    #[inline]
+    fn mul(self, rhs: Self) -> Self::Output {
+        Vec2f::new([self.x * rhs.x, self.y * rhs.y])
+    }
+}
+
 impl Mul<f32> for Vec2f {
     type Output = Vec2f;
+    #[inline]
     fn mul(self, rhs: f32) -> Self::Output {
         Vec2f::new([self.x * rhs, self.y * rhs])
     }
 }
 
 impl MulAssign for Vec2f {
+    #[inline]
     fn mul_assign(&mut self, rhs: Self) {
         self.x *= rhs.x;
         self.y *= rhs.y;
@@ -286,14 +671,24 @@ impl MulAssign for Vec2f {
 }
 
 impl MulAssign<f32> for Vec2f {
+    #[inline]
     fn mul_assign(&mut self, rhs: f32) {
         self.x *= rhs;
         self.y *= rhs;
     }
 }
 
+impl Mul<Vec2f> for f32 {
+    type Output = Vec2f;
+    #[inline]
+    fn mul(self, rhs: Vec2f) -> Self::Output {
+        rhs * self
+    }
+}
+
 impl Mul for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn mul(self, rhs: Self) -> Self::Output {
         Vec3f::new([self.x * rhs.x, self.y * rhs.y, self.z * rhs.z])
     }
@@ -301,12 +696,14 @@ impl Mul for Vec3f {
 
 impl Mul<f32> for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn mul(self, rhs: f32) -> Self::Output {
         Vec3f::new([self.x * rhs, self.y * rhs, self.z * rhs])
     }
 }
 
 impl MulAssign for Vec3f {
+    #[inline]
     fn mul_assign(&mut self, rhs: Self) {
         self.x *= rhs.x;
         self.y *= rhs.y;
@@ -315,6 +712,7 @@ impl MulAssign for Vec3f {
 }
 
 impl MulAssign<f32> for Vec3f {
+    #[inline]
     fn mul_assign(&mut self, rhs: f32) {
         self.x *= rhs;
         self.y *= rhs;
@@ -322,8 +720,61 @@ impl MulAssign<f32> for Vec3f {
     }
 }
 
+impl Mul for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn mul(self, rhs: Self) -> Self::Output {
+        Vec4f::new([self.x * rhs.x, self.y * rhs.y, self.z * rhs.z, self.w * rhs.w])
+    }
+}
+
+impl MulAssign for Vec4f {
+    #[inline]
+    fn mul_assign(&mut self, rhs: Self) {
+        self.x *= rhs.x;
+        self.y *= rhs.y;
+        self.z *= rhs.z;
+        self.w *= rhs.w;
+    }
+}
+
+impl Mul<f32> for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn mul(self, rhs: f32) -> Self::Output {
+        Vec4f::new([self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs])
+    }
+}
+
+impl MulAssign<f32> for Vec4f {
+    #[inline]
+    fn mul_assign(&mut self, rhs: f32) {
+        self.x *= rhs;
+        self.y *= rhs;
+        self.z *= rhs;
+        self.w *= rhs;
+    }
+}
+
+impl Mul<Vec3f> for f32 {
+    type Output = Vec3f;
+    #[inline]
+    fn mul(self, rhs: Vec3f) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl Mul<Vec4f> for f32 {
+    type Output = Vec4f;
+    #[inline]
+    fn mul(self, rhs: Vec4f) -> Self::Output {
+        rhs * self
+    }
+}
+
 impl Mul for Vec2d {
     type Output = Vec2d;
+    #[inline]
     fn mul(self, rhs: Self) -> Self::Output {
         Vec2d::new([self.x * rhs.x, self.y * rhs.y])
     }
@@ -331,12 +782,14 @@ impl Mul for Vec2d {
 
 impl Mul<f64> for Vec2d {
     type Output = Vec2d;
+    #[inline]
     fn mul(self, rhs: f64) -> Self::Output {
         Vec2d::new([self.x * rhs, self.y * rhs])
     }
 }
 
 impl MulAssign for Vec2d {
+    #[inline]
     fn mul_assign(&mut self, rhs: Self) {
         self.x *= rhs.x;
         self.y *= rhs.y;
@@ -344,14 +797,24 @@ impl MulAssign for Vec2d {
 }
 
 impl MulAssign<f64> for Vec2d {
+    #[inline]
     fn mul_assign(&mut self, rhs: f64) {
         self.x *= rhs;
         self.y *= rhs;
     }
 }
 
+impl Mul<Vec2d> for f64 {
+    type Output = Vec2d;
+    #[inline]
+    fn mul(self, rhs: Vec2d) -> Self::Output {
+        rhs * self
+    }
+}
+
 impl Mul for Vec3d {
     type Output = Vec3d;
+    #[inline]
     fn mul(self, rhs: Self) -> Self::Output {
         Vec3d::new([self.x * rhs.x, self.y * rhs.y, self.z * rhs.z])
     }
@@ -359,12 +822,14 @@ impl Mul for Vec3d {
 
 impl Mul<f64> for Vec3d {
     type Output = Vec3d;
+    #[inline]
     fn mul(self, rhs: f64) -> Self::Output {
         Vec3d::new([self.x * rhs, self.y * rhs, self.z * rhs])
     }
 }
 
 impl MulAssign for Vec3d {
+    #[inline]
     fn mul_assign(&mut self, rhs: Self) {
         self.x *= rhs.x;
         self.y *= rhs.y;
@@ -373,6 +838,7 @@ impl MulAssign for Vec3d {
 }
 
 impl MulAssign<f64> for Vec3d {
+    #[inline]
     fn mul_assign(&mut self, rhs: f64) {
         self.x *= rhs;
         self.y *= rhs;
@@ -380,8 +846,17 @@ impl MulAssign<f64> for Vec3d {
     }
 }
 
+impl Mul<Vec3d> for f64 {
+    type Output = Vec3d;
+    #[inline]
+    fn mul(self, rhs: Vec3d) -> Self::Output {
+        rhs * self
+    }
+}
+
 impl Mul for Vec3i {
     type Output = Vec3i;
+    #[inline]
     fn mul(self, rhs: Self) -> Self::Output {
         Vec3i::new([self.x * rhs.x, self.y * rhs.y, self.z * rhs.z])
     }
@@ -389,12 +864,14 @@ impl Mul for Vec3i {
 
 impl Mul<i64> for Vec3i {
     type Output = Vec3i;
+    #[inline]
     fn mul(self, rhs: i64) -> Self::Output {
         Vec3i::new([self.x * rhs, self.y * rhs, self.z * rhs])
     }
 }
 
 impl MulAssign for Vec3i {
+    #[inline]
     fn mul_assign(&mut self, rhs: Self) {
         self.x *= rhs.x;
         self.y *= rhs.y;
@@ -403,6 +880,7 @@ impl MulAssign for Vec3i {
 }
 
 impl MulAssign<i64> for Vec3i {
+    #[inline]
     fn mul_assign(&mut self, rhs: i64) {
         self.x *= rhs;
         self.y *= rhs;
@@ -410,57 +888,206 @@ impl MulAssign<i64> for Vec3i {
     }
 }
 
+impl Mul<Vec3i> for i64 {
+    type Output = Vec3i;
+    #[inline]
+    fn mul(self, rhs: Vec3i) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl Neg for Vec2f {
+    type Output = Vec2f;
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Vec2f::new([-self.x, -self.y])
+    }
+}
+
+impl Neg for Vec3f {
+    type Output = Vec3f;
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Vec3f::new([-self.x, -self.y, -self.z])
+    }
+}
+
+impl Neg for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Vec4f::new([-self.x, -self.y, -self.z, -self.w])
+    }
+}
+
+impl Neg for Vec2d {
+    type Output = Vec2d;
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Vec2d::new([-self.x, -self.y])
+    }
+}
+
+impl Neg for Vec3d {
+    type Output = Vec3d;
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Vec3d::new([-self.x, -self.y, -self.z])
+    }
+}
+
+impl Neg for Vec3i {
+    type Output = Vec3i;
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Vec3i::new([-self.x, -self.y, -self.z])
+    }
+}
+
 impl Sub for Vec2f {
     type Output = Vec2f;
+    #[inline]
     fn sub(self, rhs: Self) -> Self::Output {
         Vec2f::new([self.x - rhs.x, self.y - rhs.y])
     }
 }
 
 impl SubAssign for Vec2f {
+    #[inline]
     fn sub_assign(&mut self, rhs: Self) {
         self.x -= rhs.x;
         self.y -= rhs.y;
     }
 }
 
+impl Sub<f32> for Vec2f {
+    type Output = Vec2f;
+    #[inline]
+    fn sub(self, rhs: f32) -> Self::Output {
+        Vec2f::new([self.x - rhs, self.y - rhs])
+    }
+}
+
+impl SubAssign<f32> for Vec2f {
+    #[inline]
+    fn sub_assign(&mut self, rhs: f32) {
+        self.x -= rhs;
+        self.y -= rhs;
+    }
+}
+
 impl Sub for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn sub(self, rhs: Self) -> Self::Output {
         Vec3f::new([self.x - rhs.x, self.y - rhs.y, self.z - rhs.z])
     }
 }
 
 impl SubAssign for Vec3f {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        self.x -= rhs.x;
+        self.y -= rhs.y;
+        self.z -= rhs.z;
+    }
+}
+
+impl Sub<f32> for Vec3f {
+    type Output = Vec3f;
+    #[inline]
+    fn sub(self, rhs: f32) -> Self::Output {
+        Vec3f::new([self.x - rhs, self.y - rhs, self.z - rhs])
+    }
+}
+
+impl SubAssign<f32> for Vec3f {
+    #[inline]
+    fn sub_assign(&mut self, rhs: f32) {
+        self.x -= rhs;
+        self.y -= rhs;
+        self.z -= rhs;
+    }
+}
+
+impl Sub for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vec4f::new([self.x - rhs.x, self.y - rhs.y, self.z - rhs.z, self.w - rhs.w])
+    }
+}
+
+impl SubAssign for Vec4f {
+    #[inline]
     fn sub_assign(&mut self, rhs: Self) {
         self.x -= rhs.x;
         self.y -= rhs.y;
         self.z -= rhs.z;
+        self.w -= rhs.w;
+    }
+}
+
+impl Sub<f32> for Vec4f {
+    type Output = Vec4f;
+    #[inline]
+    fn sub(self, rhs: f32) -> Self::Output {
+        Vec4f::new([self.x - rhs, self.y - rhs, self.z - rhs, self.w - rhs])
+    }
+}
+
+impl SubAssign<f32> for Vec4f {
+    #[inline]
+    fn sub_assign(&mut self, rhs: f32) {
+        self.x -= rhs;
+        self.y -= rhs;
+        self.z -= rhs;
+        self.w -= rhs;
     }
 }
 
 impl Sub for Vec2d {
     type Output = Vec2d;
+    #[inline]
     fn sub(self, rhs: Self) -> Self::Output {
         Vec2d::new([self.x - rhs.x, self.y - rhs.y])
     }
 }
 
 impl SubAssign for Vec2d {
+    #[inline]
     fn sub_assign(&mut self, rhs: Self) {
         self.x -= rhs.x;
         self.y -= rhs.y;
     }
 }
 
+impl Sub<f64> for Vec2d {
+    type Output = Vec2d;
+    #[inline]
+    fn sub(self, rhs: f64) -> Self::Output {
+        Vec2d::new([self.x - rhs, self.y - rhs])
+    }
+}
+
+impl SubAssign<f64> for Vec2d {
+    #[inline]
+    fn sub_assign(&mut self, rhs: f64) {
+        self.x -= rhs;
+        self.y -= rhs;
+    }
+}
+
 impl Sub for Vec3d {
     type Output = Vec3d;
+    #[inline]
     fn sub(self, rhs: Self) -> Self::Output {
         Vec3d::new([self.x - rhs.x, self.y - rhs.y, self.z - rhs.z])
     }
 }
 
 impl SubAssign for Vec3d {
+    #[inline]
     fn sub_assign(&mut self, rhs: Self) {
         self.x -= rhs.x;
         self.y -= rhs.y;
@@ -468,14 +1095,33 @@ impl SubAssign for Vec3d {
     }
 }
 
+impl Sub<f64> for Vec3d {
+    type Output = Vec3d;
+    #[inline]
+    fn sub(self, rhs: f64) -> Self::Output {
+        Vec3d::new([self.x - rhs, self.y - rhs, self.z - rhs])
+    }
+}
+
+impl SubAssign<f64> for Vec3d {
+    #[inline]
+    fn sub_assign(&mut self, rhs: f64) {
+        self.x -= rhs;
+        self.y -= rhs;
+        self.z -= rhs;
+    }
+}
+
 impl Sub for Vec3i {
     type Output = Vec3i;
+    #[inline]
     fn sub(self, rhs: Self) -> Self::Output {
         Vec3i::new([self.x - rhs.x, self.y - rhs.y, self.z - rhs.z])
     }
 }
 
 impl SubAssign for Vec3i {
+    #[inline]
     fn sub_assign(&mut self, rhs: Self) {
         self.x -= rhs.x;
         self.y -= rhs.y;
@@ -483,23 +1129,41 @@ impl SubAssign for Vec3i {
     }
 }
 
+impl Sub<i64> for Vec3i {
+    type Output = Vec3i;
+    #[inline]
+    fn sub(self, rhs: i64) -> Self::Output {
+        Vec3i::new([self.x - rhs, self.y - rhs, self.z - rhs])
+    }
+}
+
+impl SubAssign<i64> for Vec3i {
+    #[inline]
+    fn sub_assign(&mut self, rhs: i64) {
+        self.x -= rhs;
+        self.y -= rhs;
+        self.z -= rhs;
+    }
+}
+
 impl Vec2f {
+    #[inline]
     pub fn new(val: [f32; 2]) -> Vec2f {
         Vec2f {
             x: val[0],
-            y: val[1],
-            _align: 0
+            y: val[1]
         }
     }
 
+    #[inline]
     pub fn from_vec2d(val: Vec2d) -> Vec2f {
         Vec2f {
             x: val.x as f32,
-            y: val.y as f32,
-            _align: 0
+            y: val.y as f32
         }
     }
 
+    #[inline]
     pub fn to_vec2d(&self) -> Vec2d {
         Vec2d {
             x: self.x as f64,
@@ -507,34 +1171,120 @@ impl Vec2f {
         }
     }
 
+    #[inline]
     pub fn dot(&self, vec: Vec2f) -> f32 {
         self.x * vec.x + self.y * vec.y
     }
 
+    #[inline]
     pub fn cross(&self, vec: Vec2f) -> f32 {
         (self.x * vec.y) - (self.y * vec.x)
     }
+
+    #[inline]
+    pub fn length_sqr(&self) -> f32 {
+        self.x * self.x + self.y * self.y
+    }
+
+    #[inline]
+    pub fn length(&self) -> f32 {
+        self.length_sqr().sqrt()
+    }
+
+    // the zero vector stays zero
+    #[inline]
+    pub fn normalize(&self) -> Vec2f {
+        let len = self.length();
+        if len == 0.0 {
+            return Vec2f::new([0.0, 0.0]);
+        }
+        *self / len
+    }
+
+    #[inline]
+    pub fn distance_sqr(&self, vec: Vec2f) -> f32 {
+        (*self - vec).length_sqr()
+    }
+
+    #[inline]
+    pub fn distance(&self, vec: Vec2f) -> f32 {
+        (*self - vec).length()
+    }
+
+    // `t` of the way from this to `vec`
+    #[inline]
+    pub fn lerp(&self, vec: Vec2f, t: f32) -> Vec2f {
+        *self + (vec - *self) * t
+    }
+
+    // in radians from 0 to pi, 0 if either is the zero vector
+    #[inline]
+    pub fn angle_between(&self, vec: Vec2f) -> f32 {
+        let lengths = (self.length_sqr() * vec.length_sqr()).sqrt();
+        if lengths == 0.0 {
+            return 0.0;
+        }
+        (self.dot(vec) / lengths).clamp(-1.0, 1.0).acos()
+    }
+
+    // the part of this along `vec`, zero if `vec` is
+    #[inline]
+    pub fn project_onto(&self, vec: Vec2f) -> Vec2f {
+        let len = vec.length_sqr();
+        if len == 0.0 {
+            return Vec2f::new([0.0, 0.0]);
+        }
+        vec * (self.dot(vec) / len)
+    }
+
+    // mirrored by the plane through the origin with the normalized `normal`
+    #[inline]
+    pub fn reflect(&self, normal: Vec2f) -> Vec2f {
+        *self - normal * (2.0 * self.dot(normal))
+    }
+
+    #[inline]
+    pub fn min(&self, vec: Vec2f) -> Vec2f {
+        Vec2f::new([self.x.min(vec.x), self.y.min(vec.y)])
+    }
+
+    #[inline]
+    pub fn max(&self, vec: Vec2f) -> Vec2f {
+        Vec2f::new([self.x.max(vec.x), self.y.max(vec.y)])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec2f {
+        Vec2f::new([self.x.abs(), self.y.abs()])
+    }
+
+    // every component between the ones of `min` and `max`
+    #[inline]
+    pub fn clamp(&self, min: Vec2f, max: Vec2f) -> Vec2f {
+        Vec2f::new([self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y)])
+    }
 }
 
 impl Vec3f {
+    #[inline]
     pub fn new(val: [f32; 3]) -> Vec3f {
         Vec3f {
             x: val[0],
             y: val[1],
-            z: val[2],
-            _align: 0
+            z: val[2]
         }
     }
 
+    #[inline]
     pub fn from_vec3d(val: Vec3d) -> Vec3f {
         Vec3f {
             x: val.x as f32,
             y: val.y as f32,
-            z: val.z as f32,
-            _align: 0
+            z: val.z as f32
         }
     }
 
+    #[inline]
     pub fn to_vec3d(&self) -> Vec3d {
         Vec3d {
             x: self.x as f64,
@@ -543,39 +1293,111 @@ impl Vec3f {
         }
     }
 
+    #[inline]
     pub fn dot(&self, vec: Vec3f) -> f32 {
         self.x * vec.x + self.y * vec.y + self.z * vec.z
     }
 
+    #[inline]
     pub fn cross(&self, vec: Vec3f) -> Vec3f {
         Vec3f {
             x: (self.y * vec.z) - (self.z * vec.y),
             y: (self.z * vec.x) - (self.x * vec.z),
-            z: (self.x * vec.y) - (self.y * vec.x),
-            _align: 0
+            z: (self.x * vec.y) - (self.y * vec.x)
         }
     }
 
+    #[inline]
     pub fn length_sqr(&self) -> f32 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    #[inline]
     pub fn length(&self) -> f32 {
         self.length_sqr().sqrt()
     }
 
+    // the zero vector stays zero
+    #[inline]
     pub fn normalize(&self) -> Vec3f {
         let len = self.length();
-        Vec3f {
-            x: self.x / len,
-            y: self.y / len,
-            z: self.z / len,
-            _align: 0
+        if len == 0.0 {
+            return Vec3f::new([0.0, 0.0, 0.0]);
+        }
+        *self / len
+    }
+
+    #[inline]
+    pub fn distance_sqr(&self, vec: Vec3f) -> f32 {
+        (*self - vec).length_sqr()
+    }
+
+    #[inline]
+    pub fn distance(&self, vec: Vec3f) -> f32 {
+        (*self - vec).length()
+    }
+
+    // `t` of the way from this to `vec`
+    #[inline]
+    pub fn lerp(&self, vec: Vec3f, t: f32) -> Vec3f {
+        *self + (vec - *self) * t
+    }
+
+    // in radians from 0 to pi, 0 if either is the zero vector
+    #[inline]
+    pub fn angle_between(&self, vec: Vec3f) -> f32 {
+        let lengths = (self.length_sqr() * vec.length_sqr()).sqrt();
+        if lengths == 0.0 {
+            return 0.0;
         }
+        (self.dot(vec) / lengths).clamp(-1.0, 1.0).acos()
+    }
+
+    // the part of this along `vec`, zero if `vec` is
+    #[inline]
+    pub fn project_onto(&self, vec: Vec3f) -> Vec3f {
+        let len = vec.length_sqr();
+        if len == 0.0 {
+            return Vec3f::new([0.0, 0.0, 0.0]);
+        }
+        vec * (self.dot(vec) / len)
+    }
+
+    // mirrored by the plane through the origin with the normalized `normal`
+    #[inline]
+    pub fn reflect(&self, normal: Vec3f) -> Vec3f {
+        *self - normal * (2.0 * self.dot(normal))
+    }
+
+    #[inline]
+    pub fn min(&self, vec: Vec3f) -> Vec3f {
+        Vec3f::new([self.x.min(vec.x), self.y.min(vec.y), self.z.min(vec.z)])
+    }
+
+    #[inline]
+    pub fn max(&self, vec: Vec3f) -> Vec3f {
+        Vec3f::new([self.x.max(vec.x), self.y.max(vec.y), self.z.max(vec.z)])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec3f {
+        Vec3f::new([self.x.abs(), self.y.abs(), self.z.abs()])
+    }
+
+    // every component between the ones of `min` and `max`
+    #[inline]
+    pub fn clamp(&self, min: Vec3f, max: Vec3f) -> Vec3f {
+        Vec3f::new([self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y), self.z.clamp(min.z, max.z)])
+    }
+
+    #[inline]
+    pub fn extend(&self, w: f32) -> Vec4f {
+        Vec4f::new([self.x, self.y, self.z, w])
     }
 }
 
 impl Vec2d {
+    #[inline]
     pub fn new(val: [f64; 2]) -> Vec2d {
         Vec2d {
             x: val[0],
@@ -583,6 +1405,7 @@ impl Vec2d {
         }
     }
 
+    #[inline]
     pub fn from_vec2f(val: Vec2f) -> Vec2d {
         Vec2d {
             x: val.x as f64,
@@ -590,24 +1413,110 @@ impl Vec2d {
         }
     }
 
+    #[inline]
     pub fn to_vec2f(&self) -> Vec2f {
         Vec2f {
             x: self.x as f32,
-            y: self.y as f32,
-            _align: 0
+            y: self.y as f32
         }
     }
 
+    #[inline]
     pub fn dot(&self, vec: Vec2d) -> f64 {
         self.x * vec.x + self.y * vec.y
     }
 
+    #[inline]
     pub fn cross(&self, vec: Vec2d) -> f64 {
         (self.x * vec.y) - (self.y * vec.x)
     }
+
+    #[inline]
+    pub fn length_sqr(&self) -> f64 {
+        self.x * self.x + self.y * self.y
+    }
+
+    #[inline]
+    pub fn length(&self) -> f64 {
+        self.length_sqr().sqrt()
+    }
+
+    // the zero vector stays zero
+    #[inline]
+    pub fn normalize(&self) -> Vec2d {
+        let len = self.length();
+        if len == 0.0 {
+            return Vec2d::new([0.0, 0.0]);
+        }
+        *self / len
+    }
+
+    #[inline]
+    pub fn distance_sqr(&self, vec: Vec2d) -> f64 {
+        (*self - vec).length_sqr()
+    }
+
+    #[inline]
+    pub fn distance(&self, vec: Vec2d) -> f64 {
+        (*self - vec).length()
+    }
+
+    // `t` of the way from this to `vec`
+    #[inline]
+    pub fn lerp(&self, vec: Vec2d, t: f64) -> Vec2d {
+        *self + (vec - *self) * t
+    }
+
+    // in radians from 0 to pi, 0 if either is the zero vector
+    #[inline]
+    pub fn angle_between(&self, vec: Vec2d) -> f64 {
+        let lengths = (self.length_sqr() * vec.length_sqr()).sqrt();
+        if lengths == 0.0 {
+            return 0.0;
+        }
+        (self.dot(vec) / lengths).clamp(-1.0, 1.0).acos()
+    }
+
+    // the part of this along `vec`, zero if `vec` is
+    #[inline]
+    pub fn project_onto(&self, vec: Vec2d) -> Vec2d {
+        let len = vec.length_sqr();
+        if len == 0.0 {
+            return Vec2d::new([0.0, 0.0]);
+        }
+        vec * (self.dot(vec) / len)
+    }
+
+    // mirrored by the plane through the origin with the normalized `normal`
+    #[inline]
+    pub fn reflect(&self, normal: Vec2d) -> Vec2d {
+        *self - normal * (2.0 * self.dot(normal))
+    }
+
+    #[inline]
+    pub fn min(&self, vec: Vec2d) -> Vec2d {
+        Vec2d::new([self.x.min(vec.x), self.y.min(vec.y)])
+    }
+
+    #[inline]
+    pub fn max(&self, vec: Vec2d) -> Vec2d {
+        Vec2d::new([self.x.max(vec.x), self.y.max(vec.y)])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec2d {
+        Vec2d::new([self.x.abs(), self.y.abs()])
+    }
+
+    // every component between the ones of `min` and `max`
+    #[inline]
+    pub fn clamp(&self, min: Vec2d, max: Vec2d) -> Vec2d {
+        Vec2d::new([self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y)])
+    }
 }
 
 impl Vec3d {
+    #[inline]
     pub fn new(val: [f64; 3]) -> Vec3d {
         Vec3d {
             x: val[0],
@@ -616,6 +1525,7 @@ impl Vec3d {
         }
     }
 
+    #[inline]
     pub fn from_vec3f(val: Vec3f) -> Vec3d {
         Vec3d {
             x: val.x as f64,
@@ -624,19 +1534,21 @@ impl Vec3d {
         }
     }
 
+    #[inline]
     pub fn to_vec3f(&self) -> Vec3f {
         Vec3f {
             x: self.x as f32,
             y: self.y as f32,
-            z: self.z as f32,
-            _align: 0
+            z: self.z as f32
         }
     }
 
+    #[inline]
     pub fn dot(&self, vec: Vec3d) -> f64 {
         self.x * vec.x + self.y * vec.y + self.z * vec.z
     }
 
+    #[inline]
     pub fn cross(&self, vec: Vec3d) -> Vec3d {
         Vec3d {
             x: (self.y * vec.z) - (self.z * vec.y),
@@ -645,25 +1557,92 @@ impl Vec3d {
         }
     }
 
+    #[inline]
     pub fn length_sqr(&self) -> f64 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    #[inline]
     pub fn length(&self) -> f64 {
         self.length_sqr().sqrt()
     }
 
+    // the zero vector stays zero
+    #[inline]
     pub fn normalize(&self) -> Vec3d {
         let len = self.length();
-        Vec3d {
-            x: self.x / len,
-            y: self.y / len,
-            z: self.z / len,
+        if len == 0.0 {
+            return Vec3d::new([0.0, 0.0, 0.0]);
+        }
+        *self / len
+    }
+
+    #[inline]
+    pub fn distance_sqr(&self, vec: Vec3d) -> f64 {
+        (*self - vec).length_sqr()
+    }
+
+    #[inline]
+    pub fn distance(&self, vec: Vec3d) -> f64 {
+        (*self - vec).length()
+    }
+
+    // `t` of the way from this to `vec`
+    #[inline]
+    pub fn lerp(&self, vec: Vec3d, t: f64) -> Vec3d {
+        *self + (vec - *self) * t
+    }
+
+    // in radians from 0 to pi, 0 if either is the zero vector
+    #[inline]
+    pub fn angle_between(&self, vec: Vec3d) -> f64 {
+        let lengths = (self.length_sqr() * vec.length_sqr()).sqrt();
+        if lengths == 0.0 {
+            return 0.0;
         }
+        (self.dot(vec) / lengths).clamp(-1.0, 1.0).acos()
+    }
+
+    // the part of this along `vec`, zero if `vec` is
+    #[inline]
+    pub fn project_onto(&self, vec: Vec3d) -> Vec3d {
+        let len = vec.length_sqr();
+        if len == 0.0 {
+            return Vec3d::new([0.0, 0.0, 0.0]);
+        }
+        vec * (self.dot(vec) / len)
+    }
+
+    // mirrored by the plane through the origin with the normalized `normal`
+    #[inline]
+    pub fn reflect(&self, normal: Vec3d) -> Vec3d {
+        *self - normal * (2.0 * self.dot(normal))
+    }
+
+    #[inline]
+    pub fn min(&self, vec: Vec3d) -> Vec3d {
+        Vec3d::new([self.x.min(vec.x), self.y.min(vec.y), self.z.min(vec.z)])
+    }
+
+    #[inline]
+    pub fn max(&self, vec: Vec3d) -> Vec3d {
+        Vec3d::new([self.x.max(vec.x), self.y.max(vec.y), self.z.max(vec.z)])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec3d {
+        Vec3d::new([self.x.abs(), self.y.abs(), self.z.abs()])
+    }
+
+    // every component between the ones of `min` and `max`
+    #[inline]
+    pub fn clamp(&self, min: Vec3d, max: Vec3d) -> Vec3d {
+        Vec3d::new([self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y), self.z.clamp(min.z, max.z)])
     }
 }
 
 impl Vec3i {
+    #[inline]
     pub fn new(val: [i64; 3]) -> Vec3i {
         Vec3i {
             x: val[0],
@@ -672,22 +1651,47 @@ impl Vec3i {
         }
     }
     
+    #[inline]
     pub fn length_sqr(&self) -> i64 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
     
+    #[inline]
     pub fn length(&self) -> f64 {
         (self.length_sqr() as f64).sqrt()
     }
+
+    #[inline]
+    pub fn min(&self, vec: Vec3i) -> Vec3i {
+        Vec3i::new([self.x.min(vec.x), self.y.min(vec.y), self.z.min(vec.z)])
+    }
+
+    #[inline]
+    pub fn max(&self, vec: Vec3i) -> Vec3i {
+        Vec3i::new([self.x.max(vec.x), self.y.max(vec.y), self.z.max(vec.z)])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec3i {
+        Vec3i::new([self.x.abs(), self.y.abs(), self.z.abs()])
+    }
+
+    // every component between the ones of `min` and `max`
+    #[inline]
+    pub fn clamp(&self, min: Vec3i, max: Vec3i) -> Vec3i {
+        Vec3i::new([self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y), self.z.clamp(min.z, max.z)])
+    }
 }
 
 impl From<Vec4f> for Vec3f {
+    #[inline]
     fn from(value: Vec4f) -> Self {
-        Vec3f::new([value.x, value.y, value.z])
+        value.truncate()
     }
 }
 
 impl Vec4f {
+    #[inline]
     pub fn new(val: [f32; 4]) -> Vec4f {
         Vec4f {
             x: val[0],
@@ -697,16 +1701,23 @@ impl Vec4f {
         }
     }
     
+    #[inline]
     pub fn length_sqr_xyz(&self) -> f32 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
     
+    #[inline]
     pub fn length_xyz(&self) -> f32 {
         self.length_sqr_xyz().sqrt()
     }
     
+    // w is kept, zero xyz stay zero
+    #[inline]
     pub fn normalize_xyz(&self) -> Vec4f {
         let len = self.length_xyz();
+        if len == 0.0 {
+            return *self;
+        }
         Vec4f {
             x: self.x / len,
             y: self.y / len,
@@ -714,10 +1725,63 @@ impl Vec4f {
             w: self.w
         }
     }
+
+    #[inline]
+    pub fn min(&self, vec: Vec4f) -> Vec4f {
+        Vec4f::new([self.x.min(vec.x), self.y.min(vec.y), self.z.min(vec.z), self.w.min(vec.w)])
+    }
+
+    #[inline]
+    pub fn max(&self, vec: Vec4f) -> Vec4f {
+        Vec4f::new([self.x.max(vec.x), self.y.max(vec.y), self.z.max(vec.z), self.w.max(vec.w)])
+    }
+
+    #[inline]
+    pub fn abs(&self) -> Vec4f {
+        Vec4f::new([self.x.abs(), self.y.abs(), self.z.abs(), self.w.abs()])
+    }
+
+    // every component between the ones of `min` and `max`
+    #[inline]
+    pub fn clamp(&self, min: Vec4f, max: Vec4f) -> Vec4f {
+        Vec4f::new([self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y), self.z.clamp(min.z, max.z), self.w.clamp(min.w, max.w)])
+    }
+
+    #[inline]
+    pub fn dot(&self, vec: Vec4f) -> f32 {
+        self.x * vec.x + self.y * vec.y + self.z * vec.z + self.w * vec.w
+    }
+
+    // `t` of 0 gives self, 1 gives `vec`, not clamped
+    #[inline]
+    pub fn lerp(&self, vec: Vec4f, t: f32) -> Vec4f {
+        *self + (vec - *self) * t
+    }
+
+    // w is dropped
+    #[inline]
+    pub fn truncate(&self) -> Vec3f {
+        Vec3f::new([self.x, self.y, self.z])
+    }
+}
+
+impl From<[f32; 4]> for Vec4f {
+    #[inline]
+    fn from(value: [f32; 4]) -> Self {
+        Vec4f::new(value)
+    }
+}
+
+impl From<Vec4f> for [f32; 4] {
+    #[inline]
+    fn from(value: Vec4f) -> Self {
+        [value.x, value.y, value.z, value.w]
+    }
 }
 
 impl Mul<Quat> for Vec3f {
     type Output = Vec3f;
+    #[inline]
     fn mul(self, rhs: Quat) -> Self::Output {
         let p = Quat::new([0.0, self.x, self.y, self.z]);
         let pp = rhs.inv() * p * rhs;
@@ -726,19 +1790,180 @@ impl Mul<Quat> for Vec3f {
 }
 
 impl From<Vec3i> for Vec3d {
+    #[inline]
     fn from(value: Vec3i) -> Self {
         Vec3d::new([value.x as f64, value.y as f64, value.z as f64])
     }
 }
 
 impl From<Position> for Vec3d {
+    #[inline]
     fn from(value: Position) -> Self {
-        value.position + value.chunk.into()
+        value.position + Vec3d::from(value.chunk) * CHUNK_SIZE
     }
 }
 
 impl From<Position> for Vec3f {
+    #[inline]
     fn from(value: Position) -> Self {
-        (value.position + value.chunk.into()).to_vec3f()
+        Vec3d::from(value).to_vec3f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GpuVec2f, GpuVec3f, PaddedVec2f, PaddedVec3f, Vec2d, Vec2f, Vec3d, Vec3f, Vec3i, Vec4f};
+
+    #[test]
+    fn test_stored_with_and_without_padding() {
+        let vec = Vec3f::new([1.0, -2.0, 3.0]);
+        let plain = rmp_serde::to_vec(&vec).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Vec3f>(&plain).unwrap(), vec);
+        assert_eq!(rmp_serde::from_slice::<Vec3f>(&rmp_serde::to_vec_named(&vec).unwrap()).unwrap(), vec);
+
+        // the way packs stored them before
+        let padded = rmp_serde::to_vec(&PaddedVec3f { x: 1.0, y: -2.0, z: 3.0, _align: 0 }).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Vec3f>(&padded).unwrap(), vec);
+        let padded = rmp_serde::to_vec(&PaddedVec2f { x: 0.5, y: 4.0, _align: 0 }).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Vec2f>(&padded).unwrap(), Vec2f::new([0.5, 4.0]));
+
+        // vertices and material parameters keep their format
+        assert_eq!(rmp_serde::to_vec(&GpuVec3f::from(vec)).unwrap(), rmp_serde::to_vec(&PaddedVec3f::from(GpuVec3f::from(vec))).unwrap());
+        let gpu = GpuVec2f::new([0.5, 4.0]);
+        assert_eq!(rmp_serde::from_slice::<GpuVec2f>(&rmp_serde::to_vec(&gpu).unwrap()).unwrap(), gpu);
+        assert_eq!(*gpu, Vec2f::new([0.5, 4.0]));
+    }
+
+    #[test]
+    fn test_negation_and_scalar_ops() {
+        assert_eq!(-Vec2f::new([1.0, -2.0]), Vec2f::new([-1.0, 2.0]));
+        assert_eq!(-Vec3f::new([1.0, -2.0, 3.0]), Vec3f::new([-1.0, 2.0, -3.0]));
+        assert_eq!(-Vec2d::new([1.0, -2.0]), Vec2d::new([-1.0, 2.0]));
+        assert_eq!(-Vec3d::new([1.0, -2.0, 3.0]), Vec3d::new([-1.0, 2.0, -3.0]));
+        assert_eq!(-Vec3i::new([1, -2, 3]), Vec3i::new([-1, 2, -3]));
+
+        assert_eq!(Vec3f::new([1.0, 2.0, 3.0]) - 1.0, Vec3f::new([0.0, 1.0, 2.0]));
+        assert_eq!(Vec2d::new([1.0, 2.0]) + 1.0, Vec2d::new([2.0, 3.0]));
+        let mut vec = Vec3i::new([1, 2, 3]);
+        vec -= 2;
+        vec += 1;
+        assert_eq!(vec, Vec3i::new([0, 1, 2]));
+
+        assert_eq!(2.0 * Vec2f::new([1.0, -2.0]), Vec2f::new([2.0, -4.0]));
+        assert_eq!(2.0 * Vec3f::new([1.0, -2.0, 3.0]), Vec3f::new([1.0, -2.0, 3.0]) * 2.0);
+        assert_eq!(0.5 * Vec3d::new([2.0, 4.0, 6.0]), Vec3d::new([1.0, 2.0, 3.0]));
+        assert_eq!(3 * Vec3i::new([1, 2, 3]), Vec3i::new([3, 6, 9]));
+    }
+
+    #[test]
+    fn test_component_min_max_abs_clamp() {
+        let (a, b) = (Vec3f::new([1.0, -5.0, 3.0]), Vec3f::new([2.0, -6.0, 0.0]));
+        assert_eq!(a.min(b), Vec3f::new([1.0, -6.0, 0.0]));
+        assert_eq!(a.max(b), Vec3f::new([2.0, -5.0, 3.0]));
+        assert_eq!(a.abs(), Vec3f::new([1.0, 5.0, 3.0]));
+        assert_eq!(a.clamp(Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([2.0, 2.0, 2.0])), Vec3f::new([1.0, 0.0, 2.0]));
+
+        assert_eq!(Vec2f::new([-1.0, 4.0]).clamp(Vec2f::new([0.0, 0.0]), Vec2f::new([1.0, 1.0])), Vec2f::new([0.0, 1.0]));
+        assert_eq!(Vec2d::new([-1.0, 4.0]).abs().max(Vec2d::new([2.0, 0.0])), Vec2d::new([2.0, 4.0]));
+        assert_eq!(Vec3d::new([-1.0, 4.0, 0.5]).min(Vec3d::new([0.0, 0.0, 0.0])), Vec3d::new([-1.0, 0.0, 0.0]));
+        assert_eq!(Vec3i::new([-7, 4, 0]).abs().clamp(Vec3i::new([1, 1, 1]), Vec3i::new([5, 5, 5])), Vec3i::new([5, 4, 1]));
+        assert_eq!(Vec4f::new([-1.0, 2.0, -3.0, 4.0]).abs(), Vec4f::new([1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(
+            Vec4f::new([-1.0, 2.0, -3.0, 4.0]).max(Vec4f::new([0.0, 0.0, 0.0, 0.0])).min(Vec4f::new([3.0, 3.0, 3.0, 3.0])),
+            Vec4f::new([0.0, 2.0, 0.0, 3.0])
+        );
+    }
+
+    #[test]
+    fn test_lengths_and_distances() {
+        assert_eq!(Vec2f::new([3.0, 4.0]).length(), 5.0);
+        assert_eq!(Vec2d::new([3.0, 4.0]).length_sqr(), 25.0);
+        assert_eq!(Vec2f::new([3.0, 4.0]).normalize(), Vec2f::new([0.6, 0.8]));
+        assert_eq!(Vec2d::new([0.0, -2.0]).normalize(), Vec2d::new([0.0, -1.0]));
+
+        // zero stays zero instead of turning into NaN
+        assert_eq!(Vec2f::new([0.0, 0.0]).normalize(), Vec2f::new([0.0, 0.0]));
+        assert_eq!(Vec3f::new([0.0, 0.0, 0.0]).normalize(), Vec3f::new([0.0, 0.0, 0.0]));
+        assert_eq!(Vec2d::new([0.0, 0.0]).normalize(), Vec2d::new([0.0, 0.0]));
+        assert_eq!(Vec3d::new([0.0, 0.0, 0.0]).normalize(), Vec3d::new([0.0, 0.0, 0.0]));
+        assert_eq!(Vec4f::new([0.0, 0.0, 0.0, 1.0]).normalize_xyz(), Vec4f::new([0.0, 0.0, 0.0, 1.0]));
+
+        let (a, b) = (Vec3d::new([1.0, 2.0, 3.0]), Vec3d::new([4.0, 6.0, 3.0]));
+        assert_eq!(a.distance(b), 5.0);
+        assert_eq!(a.distance_sqr(b), 25.0);
+        assert_eq!(Vec3f::new([1.0, 1.0, 1.0]).distance(Vec3f::new([1.0, 1.0, 3.0])), 2.0);
+        assert_eq!(Vec2f::new([0.0, 0.0]).distance_sqr(Vec2f::new([1.0, 1.0])), 2.0);
+        assert_eq!(Vec2d::new([0.0, 0.0]).distance(Vec2d::new([0.0, -3.0])), 3.0);
+    }
+
+    #[test]
+    fn test_lerp_angle_project_reflect() {
+        let (a, b) = (Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([2.0, 4.0, -2.0]));
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 0.5), Vec3f::new([1.0, 2.0, -1.0]));
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(Vec2d::new([1.0, 1.0]).lerp(Vec2d::new([3.0, -1.0]), 0.25), Vec2d::new([1.5, 0.5]));
+
+        let (x, y) = (Vec3d::new([1.0, 0.0, 0.0]), Vec3d::new([0.0, 2.0, 0.0]));
+        assert!((x.angle_between(y) - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert!((x.angle_between(-x) - std::f64::consts::PI).abs() < 1e-12);
+        assert_eq!(x.angle_between(x * 3.0), 0.0);
+        assert_eq!(x.angle_between(Vec3d::new([0.0, 0.0, 0.0])), 0.0);
+        assert!((Vec2f::new([1.0, 1.0]).angle_between(Vec2f::new([1.0, 0.0])) - std::f32::consts::FRAC_PI_4).abs() < 1e-6);
+
+        assert_eq!(Vec3f::new([3.0, 4.0, 5.0]).project_onto(Vec3f::new([0.0, 2.0, 0.0])), Vec3f::new([0.0, 4.0, 0.0]));
+        assert_eq!(Vec2d::new([3.0, 4.0]).project_onto(Vec2d::new([0.0, 0.0])), Vec2d::new([0.0, 0.0]));
+
+        // bouncing off the floor
+        assert_eq!(Vec3f::new([1.0, -2.0, 0.5]).reflect(Vec3f::new([0.0, 1.0, 0.0])), Vec3f::new([1.0, 2.0, 0.5]));
+        assert_eq!(Vec2f::new([1.0, -1.0]).reflect(Vec2f::new([0.0, 1.0])), Vec2f::new([1.0, 1.0]));
+        assert_eq!(Vec3d::new([1.0, 2.0, 3.0]).reflect(Vec3d::new([1.0, 0.0, 0.0])), Vec3d::new([-1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn test_vec4_operators() {
+        let (a, b) = (Vec4f::new([1.0, -2.0, 3.0, 4.0]), Vec4f::new([2.0, 4.0, -1.0, 0.5]));
+        assert_eq!(a + b, Vec4f::new([3.0, 2.0, 2.0, 4.5]));
+        assert_eq!(a - b, Vec4f::new([-1.0, -6.0, 4.0, 3.5]));
+        assert_eq!(a * b, Vec4f::new([2.0, -8.0, -3.0, 2.0]));
+        assert_eq!(a / b, Vec4f::new([0.5, -0.5, -3.0, 8.0]));
+        assert_eq!(-a, Vec4f::new([-1.0, 2.0, -3.0, -4.0]));
+
+        assert_eq!(a + 1.0, Vec4f::new([2.0, -1.0, 4.0, 5.0]));
+        assert_eq!(a - 1.0, Vec4f::new([0.0, -3.0, 2.0, 3.0]));
+        assert_eq!(a * 2.0, 2.0 * a);
+        assert_eq!(a * 2.0, Vec4f::new([2.0, -4.0, 6.0, 8.0]));
+        assert_eq!(a / 2.0, Vec4f::new([0.5, -1.0, 1.5, 2.0]));
+
+        let mut vec = a;
+        vec += b;
+        vec -= 1.0;
+        vec *= 2.0;
+        vec /= b;
+        assert_eq!(vec, Vec4f::new([2.0, 0.5, -2.0, 14.0]));
+        vec -= a;
+        vec += 1.0;
+        vec *= b;
+        vec /= 2.0;
+        assert_eq!(vec, Vec4f::new([2.0, 7.0, 2.0, 2.75]));
+    }
+
+    #[test]
+    fn test_vec4_dot_lerp_and_conversions() {
+        let (a, b) = (Vec4f::new([1.0, -2.0, 3.0, 4.0]), Vec4f::new([2.0, 4.0, -1.0, 0.5]));
+        assert_eq!(a.dot(b), -7.0);
+        assert_eq!(a.dot(a), 30.0);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.5), Vec4f::new([1.5, 1.0, 1.0, 2.25]));
+
+        assert_eq!(a.truncate(), Vec3f::new([1.0, -2.0, 3.0]));
+        assert_eq!(Vec3f::from(a), a.truncate());
+        assert_eq!(Vec3f::new([1.0, -2.0, 3.0]).extend(4.0), a);
+        assert_eq!(Vec4f::from([1.0, -2.0, 3.0, 4.0]), a);
+        let array: [f32; 4] = a.into();
+        assert_eq!(array, [1.0, -2.0, 3.0, 4.0]);
+        // still plain data for buffers
+        assert_eq!(bytemuck::cast::<Vec4f, [f32; 4]>(a), array);
     }
 }
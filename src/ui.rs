@@ -1,3 +1,7 @@
+pub mod ui_focus;
 pub mod ui_layout;
 pub mod ui_rendering;
 pub mod ui_mesh;
+pub mod ui_scale;
+pub mod ui_text;
+pub mod ui_tooltip;
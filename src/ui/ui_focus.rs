@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::types::vectors::Vec2f;
+
+use super::ui_layout::{ui_visible, UiElement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiNavigation {
+    Up,
+    Down,
+    Left,
+    Right,
+    // same as clicking the focused element
+    Activate,
+}
+
+impl UiNavigation {
+    // y down like the ui rects
+    fn direction(&self) -> Option<Vec2f> {
+        match self {
+            UiNavigation::Up => Some(Vec2f::new([0.0, -1.0])),
+            UiNavigation::Down => Some(Vec2f::new([0.0, 1.0])),
+            UiNavigation::Left => Some(Vec2f::new([-1.0, 0.0])),
+            UiNavigation::Right => Some(Vec2f::new([1.0, 0.0])),
+            UiNavigation::Activate => None,
+        }
+    }
+}
+
+// which element keyboard and gamepad input goes to, arrow keys and enter are handled by UiHandler,
+// other devices like a gamepad d-pad and south button queue the same navigation with `navigate`
+#[derive(Debug, Default)]
+pub struct UiFocus {
+    focused: Option<Uuid>,
+    // where focus continues from when the focused element is removed or hidden
+    last_center: Option<Vec2f>,
+    last_cursor: Option<Vec2f>,
+    pending: Vec<UiNavigation>,
+    over_ui: bool,
+}
+
+// focusable elements that can be seen, with the centers of their rects
+fn candidates(ui: &HashMap<Uuid, UiElement>) -> impl Iterator<Item = (Uuid, Vec2f)> + '_ {
+    ui.iter()
+        .filter(|(uuid, x)| x.focusable && !x.clip().is_some_and(|x| x.is_empty()) && ui_visible(ui, **uuid))
+        .filter_map(|(uuid, x)| x.rect().map(|rect| (*uuid, rect.center())))
+}
+
+fn nearest(ui: &HashMap<Uuid, UiElement>, point: Vec2f) -> Option<Uuid> {
+    candidates(ui)
+        .map(|(uuid, center)| {
+            let offset = center - point;
+            (uuid, offset.x * offset.x + offset.y * offset.y)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|x| x.0)
+}
+
+// the closest element on the `direction` side of `from`, straying sideways costs twice as much as going forward
+fn nearest_in_direction(ui: &HashMap<Uuid, UiElement>, from: Uuid, center: Vec2f, direction: Vec2f) -> Option<Uuid> {
+    candidates(ui)
+        .filter(|(uuid, _)| *uuid != from)
+        .filter_map(|(uuid, x)| {
+            let offset = x - center;
+            let along = offset.x * direction.x + offset.y * direction.y;
+            let across = (offset.x * direction.y - offset.y * direction.x).abs();
+            (along > 1e-5).then_some((uuid, along + 2.0 * across))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|x| x.0)
+}
+
+impl UiFocus {
+    pub fn focused(&self) -> Option<Uuid> {
+        self.focused
+    }
+
+    // whether the cursor was over an element the last time UiHandler ran, the mouse is the ui's then
+    pub fn cursor_over_ui(&self) -> bool {
+        self.over_ui
+    }
+
+    // handled by UiHandler next frame
+    pub fn navigate(&mut self, navigation: UiNavigation) {
+        self.pending.push(navigation);
+    }
+
+    pub fn focus(&mut self, ui: &mut HashMap<Uuid, UiElement>, uuid: Option<Uuid>) {
+        if self.focused == uuid {
+            return;
+        }
+        if let Some(element) = self.focused.and_then(|x| ui.get_mut(&x)) {
+            element.set_focused(false);
+        }
+        if let Some(element) = uuid.and_then(|x| ui.get_mut(&x)) {
+            element.set_focused(true);
+            self.last_center = element.rect().map(|x| x.center());
+        }
+        self.focused = uuid;
+    }
+
+    // moves focus off elements that were removed, hidden or made unfocusable
+    pub(crate) fn update(&mut self, ui: &mut HashMap<Uuid, UiElement>) {
+        let Some(focused) = self.focused else {
+            return;
+        };
+        let current = candidates(ui).find(|x| x.0 == focused);
+        match current {
+            Some((_, center)) => self.last_center = Some(center),
+            None => {
+                let next = self.last_center.and_then(|x| nearest(ui, x));
+                self.focus(ui, next);
+            }
+        }
+    }
+
+    // the element under a moving cursor takes the focus, a cursor that stays put leaves it to the keyboard
+    pub(crate) fn hover(&mut self, ui: &mut HashMap<Uuid, UiElement>, cursor: Vec2f) {
+        self.over_ui = candidates(ui).any(|(uuid, _)| ui[&uuid].hit(cursor));
+        if self.last_cursor.replace(cursor).is_none_or(|x| x == cursor) {
+            return;
+        }
+        let hovered = candidates(ui)
+            .filter(|(uuid, _)| ui[uuid].hit(cursor))
+            .max_by_key(|(uuid, _)| ui[uuid].depth())
+            .map(|x| x.0);
+        if hovered.is_some() {
+            self.focus(ui, hovered);
+        }
+    }
+
+    // applies `navigation` and the queued navigation, returns the elements activated
+    pub(crate) fn apply(&mut self, ui: &mut HashMap<Uuid, UiElement>, navigation: impl IntoIterator<Item = UiNavigation>) -> Vec<Uuid> {
+        let mut activated = Vec::new();
+        let pending = std::mem::take(&mut self.pending);
+        for navigation in navigation.into_iter().chain(pending) {
+            let Some(direction) = navigation.direction() else {
+                activated.extend(self.focused);
+                continue;
+            };
+            // the first key press only picks the element closest to the middle of the screen
+            let next = match self.focused.and_then(|x| ui.get(&x)?.rect().map(|rect| (x, rect.center()))) {
+                Some((focused, center)) => nearest_in_direction(ui, focused, center, direction),
+                None => nearest(ui, Vec2f::new([0.0, 0.0])),
+            };
+            if next.is_some() {
+                self.focus(ui, next);
+            }
+        }
+        activated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use crate::{
+        types::vectors::Vec2f,
+        ui::{
+            ui_layout::{layout_ui, Anchor, UiElement, UiElementType},
+            ui_scale::UiScaleMode,
+        },
+    };
+
+    use super::{UiFocus, UiNavigation};
+
+    // a 3x3 grid of focusable buttons 0.5 apart, row by row from the top left
+    fn grid() -> (HashMap<Uuid, UiElement>, Vec<Uuid>) {
+        let mut ui = HashMap::new();
+        let mut uuids = Vec::new();
+        for row in 0..3 {
+            for column in 0..3 {
+                let position = Vec2f::new([(column as f32 - 1.0) * 0.5, (1.0 - row as f32) * 0.5]);
+                let element_type = UiElementType::Button(Uuid::new_v4());
+                let mut element = UiElement::new("button", element_type, Uuid::nil(), Anchor::Center, position, 0.2, 0.2);
+                element.focusable = true;
+                let uuid = Uuid::new_v4();
+                ui.insert(uuid, element);
+                uuids.push(uuid);
+            }
+        }
+        layout_ui(&mut ui, &UiScaleMode::Ndc.scale(1000, 1000, 1.0));
+        (ui, uuids)
+    }
+
+    #[test]
+    fn test_navigates_to_nearest_in_direction() {
+        let (mut ui, grid) = grid();
+        let mut focus = UiFocus::default();
+
+        focus.apply(&mut ui, [UiNavigation::Down]);
+        assert_eq!(focus.focused(), Some(grid[4]));
+        assert!(ui[&grid[4]].focused());
+
+        focus.apply(&mut ui, [UiNavigation::Up, UiNavigation::Left]);
+        assert_eq!(focus.focused(), Some(grid[0]));
+        assert!(!ui[&grid[4]].focused());
+        // nothing further left, focus stays
+        focus.apply(&mut ui, [UiNavigation::Left]);
+        assert_eq!(focus.focused(), Some(grid[0]));
+
+        focus.navigate(UiNavigation::Right);
+        let activated = focus.apply(&mut ui, [UiNavigation::Activate]);
+        assert_eq!(activated, vec![grid[0]]);
+        assert_eq!(focus.focused(), Some(grid[1]));
+    }
+
+    #[test]
+    fn test_focus_moves_off_removed_and_hidden_elements() {
+        let (mut ui, grid) = grid();
+        let mut focus = UiFocus::default();
+        focus.focus(&mut ui, Some(grid[2]));
+
+        ui.remove(&grid[2]);
+        focus.update(&mut ui);
+        assert!(focus.focused() == Some(grid[1]) || focus.focused() == Some(grid[5]));
+
+        let next = focus.focused().unwrap();
+        ui.get_mut(&next).unwrap().visible = false;
+        focus.update(&mut ui);
+        assert!(focus.focused().is_some_and(|x| x != next && ui.contains_key(&x)));
+
+        ui.clear();
+        focus.update(&mut ui);
+        assert_eq!(focus.focused(), None);
+    }
+
+    #[test]
+    fn test_moving_cursor_steals_focus() {
+        let (mut ui, grid) = grid();
+        let mut focus = UiFocus::default();
+        let over_last = Vec2f::new([0.5, 0.5]);
+        focus.hover(&mut ui, over_last);
+        focus.focus(&mut ui, Some(grid[0]));
+
+        // a resting cursor leaves keyboard focus alone
+        focus.hover(&mut ui, over_last);
+        assert_eq!(focus.focused(), Some(grid[0]));
+        focus.hover(&mut ui, Vec2f::new([0.51, 0.5]));
+        assert_eq!(focus.focused(), Some(grid[8]));
+        assert!(focus.cursor_over_ui());
+        // moving over empty space keeps it
+        focus.hover(&mut ui, Vec2f::new([0.25, 0.25]));
+        assert_eq!(focus.focused(), Some(grid[8]));
+        assert!(!focus.cursor_over_ui());
+    }
+}
@@ -1,20 +1,37 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}, mem::offset_of};
+
 use bytemuck::{Pod, Zeroable};
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use vulkano::pipeline::graphics::vertex_input::Vertex;
-use winit::event::MouseButton;
+use winit::{event::MouseButton, keyboard::{Key, NamedKey}};
 
-use crate::{ecs::System, state::State, types::vectors::Vec2f};
+use crate::{ecs::System, state::State, types::{font::Font, material::{Attachment, Material}, texture::Texture, vectors::{GpuVec2f, Vec2f, Vec4f}}};
 
-use super::ui_mesh::UiMesh;
+use super::{ui_focus::UiNavigation, ui_mesh::{batch_ui, UiMesh}, ui_scale::UiScale, ui_text::{default_text_color, layout_text, UiText}, ui_tooltip::{UiTooltip, UiTooltips}};
 
 #[derive(Pod, Zeroable, Clone, Copy, Debug, Serialize, Deserialize, Vertex)]
 #[repr(C)]
 pub struct UiVertexData {
     #[format(R32G32B32A32_SFLOAT)]
-    pub position: Vec2f,
+    pub position: GpuVec2f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub uv: GpuVec2f,
     #[format(R32G32B32A32_SFLOAT)]
-    pub uv: Vec2f,
+    #[serde(default = "default_text_color")]
+    pub color: Vec4f,
+}
+
+const _: () = assert!(size_of::<UiVertexData>() == 48 && offset_of!(UiVertexData, uv) == 16 && offset_of!(UiVertexData, color) == 32);
+
+fn push_quad(vertices: &mut Vec<UiVertexData>, indices: &mut Vec<u32>, min: Vec2f, max: Vec2f, uv_min: Vec2f, uv_max: Vec2f, color: Vec4f) {
+    let start = vertices.len() as u32;
+    vertices.push(UiVertexData { position: GpuVec2f::new([min.x, min.y]), uv: GpuVec2f::new([uv_min.x, uv_min.y]), color });
+    vertices.push(UiVertexData { position: GpuVec2f::new([min.x, max.y]), uv: GpuVec2f::new([uv_min.x, uv_max.y]), color });
+    vertices.push(UiVertexData { position: GpuVec2f::new([max.x, max.y]), uv: GpuVec2f::new([uv_max.x, uv_max.y]), color });
+    vertices.push(UiVertexData { position: GpuVec2f::new([max.x, min.y]), uv: GpuVec2f::new([uv_max.x, uv_min.y]), color });
+    indices.extend([0, 1, 2, 0, 2, 3].map(|x| start + x));
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -30,6 +47,46 @@ pub enum Anchor {
     DownRight
 }
 
+// where in its parent an element goes, like other engines' rect transforms
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct UiAnchors {
+    // fractions of the parent's rect from its top left corner, equal anchors pin the element
+    // to a point and different ones stretch it with the parent
+    pub anchor_min: Vec2f,
+    pub anchor_max: Vec2f,
+    // added to the anchored corners with y down, in layout units (logical pixels with
+    // UiScaleMode::ConstantPhysicalSize, reference pixels with ScaleWithHeight)
+    pub offset_min: Vec2f,
+    pub offset_max: Vec2f,
+}
+
+impl UiAnchors {
+    // the centered placement from before anchors, `position` moves the center away from `anchor` with y up
+    pub fn from_anchor(anchor: Anchor, position: Vec2f, width: f32, height: f32) -> UiAnchors {
+        let point = (anchor_to_offset(anchor) + Vec2f::new([1.0, 1.0])) / 2.0;
+        let center = Vec2f::new([position.x, -position.y]);
+        let half = Vec2f::new([width, height]) / 2.0;
+        UiAnchors { anchor_min: point, anchor_max: point, offset_min: center - half, offset_max: center + half }
+    }
+
+    // fills the parent, `margin` units in from every edge
+    pub fn stretch(margin: f32) -> UiAnchors {
+        UiAnchors {
+            anchor_min: Vec2f::new([0.0, 0.0]),
+            anchor_max: Vec2f::new([1.0, 1.0]),
+            offset_min: Vec2f::new([margin, margin]),
+            offset_max: Vec2f::new([-margin, -margin]),
+        }
+    }
+
+    pub fn rect(&self, parent: UiRect, scale: &UiScale) -> UiRect {
+        UiRect {
+            min: parent.min + self.anchor_min * parent.size() + self.offset_min * scale.unit,
+            max: parent.min + self.anchor_max * parent.size() + self.offset_max * scale.unit,
+        }
+    }
+}
+
 fn anchor_to_offset(anchor: Anchor) -> Vec2f {
     match anchor {
         Anchor::Center => Vec2f::new([0.0, 0.0]),
@@ -44,10 +101,142 @@ fn anchor_to_offset(anchor: Anchor) -> Vec2f {
     }
 }
 
+// screen rect of an element in normalized device coordinates, y down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiRect {
+    pub min: Vec2f,
+    pub max: Vec2f,
+}
+
+impl UiRect {
+    pub fn screen() -> UiRect {
+        UiRect { min: Vec2f::new([-1.0, -1.0]), max: Vec2f::new([1.0, 1.0]) }
+    }
+
+    pub fn from_center(center: Vec2f, size: Vec2f) -> UiRect {
+        UiRect { min: center - size / 2.0, max: center + size / 2.0 }
+    }
+
+    pub fn center(&self) -> Vec2f {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Vec2f {
+        self.max - self.min
+    }
+
+    // edges count as inside
+    pub fn contains(&self, point: Vec2f) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
+    }
+
+    // collapses to an empty rect on the edge of `self` when they don't overlap
+    pub fn intersect(&self, other: &UiRect) -> UiRect {
+        let min = Vec2f::new([self.min.x.max(other.min.x), self.min.y.max(other.min.y)]);
+        let max = Vec2f::new([self.max.x.min(other.max.x).max(min.x), self.max.y.min(other.max.y).max(min.y)]);
+        UiRect { min, max }
+    }
+
+    pub fn translated(&self, offset: Vec2f) -> UiRect {
+        UiRect { min: self.min + offset, max: self.max + offset }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max.x <= self.min.x || self.max.y <= self.min.y
+    }
+}
+
+// how an element places its children, spacing is in the units of the element's width
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum UiLayoutKind {
+    // every child uses its own anchors inside the parent's rect
+    #[default]
+    Free,
+    // top to bottom from the parent's top edge, centered horizontally
+    VerticalStack { spacing: f32 },
+    // left to right from the parent's left edge, centered vertically
+    HorizontalStack { spacing: f32 },
+    // the parent's rect split into equal cells, children centered in them row by row
+    Grid { columns: u32 },
+}
+
+// how the material's texture covers the element
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum UiSpriteMode {
+    #[default]
+    Stretch,
+    // borders in texture pixels drawn at native size, only the edges and the center stretch
+    NineSlice { left: f32, right: f32, top: f32, bottom: f32 },
+}
+
+// 16 vertices in rows from the top, `borders` and `uv_borders` are left, right, top, bottom;
+// borders that don't fit the rect shrink so opposite ones meet instead of folding over
+fn nine_slice(vertices: &mut Vec<UiVertexData>, indices: &mut Vec<u32>, rect: UiRect, borders: [f32; 4], uv_borders: [f32; 4], color: Vec4f) {
+    let size = rect.size();
+    let x_scale = (size.x / (borders[0] + borders[1])).min(1.0);
+    let y_scale = (size.y / (borders[2] + borders[3])).min(1.0);
+    let xs = [rect.min.x, rect.min.x + borders[0] * x_scale, rect.max.x - borders[1] * x_scale, rect.max.x];
+    let ys = [rect.min.y, rect.min.y + borders[2] * y_scale, rect.max.y - borders[3] * y_scale, rect.max.y];
+    let us = [0.0, uv_borders[0], 1.0 - uv_borders[1], 1.0];
+    let vs = [0.0, uv_borders[2], 1.0 - uv_borders[3], 1.0];
+
+    let start = vertices.len() as u32;
+    for row in 0..4 {
+        for column in 0..4 {
+            vertices.push(UiVertexData {
+                position: GpuVec2f::new([xs[column], ys[row]]),
+                uv: GpuVec2f::new([us[column], vs[row]]),
+                color,
+            });
+        }
+    }
+    for row in 0..3 {
+        for column in 0..3 {
+            let corner = start + row * 4 + column;
+            indices.extend([corner, corner + 4, corner + 5, corner, corner + 5, corner + 1]);
+        }
+    }
+}
+
+// what mesh generation reads from the asset library, borrowed next to a mutable assets.ui
+pub struct UiMeshSources<'a> {
+    pub fonts: &'a HashMap<Uuid, Font>,
+    pub materials: &'a HashMap<Uuid, Material>,
+    pub textures: &'a HashMap<Uuid, Texture>,
+}
+
+// children are moved up by `offset` and clipped to the view, the mouse wheel scrolls it while hovered
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct UiScrollView {
+    // in the units of the element's height, kept between the top and the bottom of the content by layout_ui
+    pub offset: f32,
+    // units per wheel line
+    pub speed: f32,
+}
+
+impl Default for UiScrollView {
+    fn default() -> Self {
+        UiScrollView { offset: 0.0, speed: 0.1 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum UiElementType {
     None,
-    Button(Uuid)
+    Button(Uuid),
+    Text(UiText),
+    ScrollView(UiScrollView),
+}
+
+// multiplied into the vertex colors of the focused element
+const FOCUS_TINT: [f32; 3] = [0.7, 0.85, 1.0];
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -55,32 +244,213 @@ pub struct UiElement {
     pub element_type: UiElementType,
     pub name: String,
     pub material: Uuid,
-    position: Vec2f,
-    screen_anchor: Anchor,
-    width: f32,
-    height: f32,
+    anchors: UiAnchors,
     pub mesh: Option<UiMesh>,
+    // defaults keep packs from before the hierarchy loading
+    #[serde(default)]
+    pub layout: UiLayoutKind,
+    #[serde(default)]
+    pub parent: Option<Uuid>,
+    // in layout order
+    #[serde(default)]
+    pub children: Vec<Uuid>,
+    #[serde(default)]
+    pub sprite_mode: UiSpriteMode,
+    // hidden elements and everything under them are neither drawn nor clicked, their meshes are kept
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    // children are only drawn and clicked inside this element's rect, scroll views always clip
+    #[serde(default)]
+    pub clip_children: bool,
+    // reachable with the arrow keys or `UiFocus::navigate`
+    #[serde(default)]
+    pub focusable: bool,
+    #[serde(default)]
+    pub tooltip: Option<UiTooltip>,
+    // drawn above every element that isn't, whatever the depth
+    #[serde(default)]
+    pub overlay: bool,
+    // set by layout_ui, None until the element was laid out
+    #[serde(skip)]
+    rect: Option<UiRect>,
+    #[serde(skip)]
+    depth: u32,
+    // the part of the screen the element may cover, None when no parent clips
+    #[serde(skip)]
+    clip: Option<UiRect>,
+    // opacity multiplied by the opacity of every parent, baked into the mesh
+    #[serde(skip, default = "default_opacity")]
+    group_opacity: f32,
+    // set by UiFocus
+    #[serde(skip)]
+    focused: bool,
+    // set when the content, size or rect changed, UiMeshBuilder rebuilds the mesh next frame
+    #[serde(skip)]
+    dirty: bool,
 }
 
 impl UiElement {
+    // centered on one of nine points of the parent, see UiAnchors::from_anchor
     pub fn new(name: &str, element_type: UiElementType, material: Uuid, screen_anchor: Anchor, position: Vec2f, width: f32, height: f32) -> UiElement {
-        UiElement { name: name.to_string(), element_type, material, screen_anchor, position, width, height, mesh: None }
+        UiElement::with_anchors(name, element_type, material, UiAnchors::from_anchor(screen_anchor, position, width, height))
+    }
+
+    pub fn with_anchors(name: &str, element_type: UiElementType, material: Uuid, anchors: UiAnchors) -> UiElement {
+        UiElement { name: name.to_string(), element_type, material, anchors, mesh: None,
+            layout: UiLayoutKind::Free, parent: None, children: Vec::new(), sprite_mode: UiSpriteMode::Stretch, visible: true, opacity: 1.0, clip_children: false, focusable: false, tooltip: None, overlay: false, rect: None, depth: 0, clip: None, group_opacity: 1.0, focused: false, dirty: false }
+    }
+
+    pub fn rect(&self) -> Option<UiRect> {
+        self.rect
     }
 
-    pub fn generate_mesh(&self, state: &State) -> UiMesh {
-        let offset = anchor_to_offset(self.screen_anchor);
-        let x_offset = self.width / 2.0;
-        let y_offset = self.height / 2.0;
-        let window_size = state.window.window_handle.inner_size();
-        let ratio = window_size.width as f32 / window_size.height as f32;
+    // rebuilds the mesh and re-runs the layout next frame
+    pub(crate) fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
 
-        let v1 = UiVertexData {position: Vec2f::new([self.position.x + offset.x - x_offset, -self.position.y * ratio + offset.y - y_offset * ratio]), uv: Vec2f::new([0.0, 0.0])};
-        let v2 = UiVertexData {position: Vec2f::new([self.position.x + offset.x - x_offset, -self.position.y * ratio + offset.y + y_offset * ratio]), uv: Vec2f::new([0.0, 1.0])};
-        let v3 = UiVertexData {position: Vec2f::new([self.position.x + offset.x + x_offset, -self.position.y * ratio + offset.y + y_offset * ratio]), uv: Vec2f::new([1.0, 1.0])};
-        let v4 = UiVertexData {position: Vec2f::new([self.position.x + offset.x + x_offset, -self.position.y * ratio + offset.y - y_offset * ratio]), uv: Vec2f::new([1.0, 0.0])};
-        let vertices = vec![v1, v2, v3, v4];
-        let indices = vec![0, 1, 2, 0, 2, 3];
-        UiMesh::new(vertices, indices)
+    pub fn clip(&self) -> Option<UiRect> {
+        self.clip
+    }
+
+    fn clips_children(&self) -> bool {
+        self.clip_children || matches!(self.element_type, UiElementType::ScrollView(_))
+    }
+
+    // visible and under `point` inside its clip rect
+    pub(crate) fn hit(&self, point: Vec2f) -> bool {
+        self.rect.is_some_and(|x| x.contains(point)) && self.clip.is_none_or(|x| x.contains(point))
+    }
+
+    // number of parents above the element, parents are drawn before their children
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    // rebuilds the meshes of the element and its children, prefer `visible` for hiding
+    pub fn set_opacity(&mut self, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        if self.opacity != opacity {
+            self.opacity = opacity;
+            self.dirty = true;
+        }
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    pub(crate) fn set_focused(&mut self, focused: bool) {
+        if self.focused != focused {
+            self.focused = focused;
+            self.dirty = true;
+        }
+    }
+
+    fn tint(&self, color: Vec4f) -> Vec4f {
+        let focus = if self.focused { FOCUS_TINT } else { [1.0; 3] };
+        Vec4f::new([color.x * focus[0], color.y * focus[1], color.z * focus[2], color.w * self.group_opacity])
+    }
+
+    pub fn anchors(&self) -> UiAnchors {
+        self.anchors
+    }
+
+    // takes effect next frame
+    pub fn set_anchors(&mut self, anchors: UiAnchors) {
+        if self.anchors != anchors {
+            self.anchors = anchors;
+            self.dirty = true;
+        }
+    }
+
+    // stacks and grids only take the size from the anchors
+    fn size(&self, parent: UiRect, scale: &UiScale) -> Vec2f {
+        self.anchors.rect(parent, scale).size()
+    }
+
+    // None when there is nothing to draw, like empty text
+    // size of the first texture attached to the material
+    fn sprite_size(&self, sources: &UiMeshSources) -> Option<Vec2f> {
+        let material = sources.materials.get(&self.material)?;
+        let texture = material.attachments.iter().find_map(|x| match x {
+            Attachment::Texture(uuid) => sources.textures.get(uuid),
+            Attachment::DefaultTexture => None,
+        })?;
+        Some(Vec2f::new([texture.width as f32, texture.height as f32]))
+    }
+
+    pub fn generate_mesh(&self, sources: &UiMeshSources, state: &State) -> Option<UiMesh> {
+        let rect = self.rect?;
+        let scale = ui_scale(state);
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        match &self.element_type {
+            UiElementType::Text(text) => {
+                let font = match sources.fonts.values().find(|x| x.name == text.font) {
+                    Some(val) => val,
+                    None => {
+                        error!("Font {} not found for {}", text.font, self.name);
+                        return None;
+                    }
+                };
+                for quad in layout_text(font, &text.content, text.size, rect.size().x / scale.unit.x) {
+                    let min = rect.min + quad.min * scale.unit;
+                    let max = rect.min + quad.max * scale.unit;
+                    push_quad(&mut vertices, &mut indices, min, max, quad.uv_min, quad.uv_max, self.tint(text.color));
+                }
+            },
+            _ => match (self.sprite_mode, self.sprite_size(sources)) {
+                (UiSpriteMode::NineSlice { left, right, top, bottom }, Some(sprite)) => {
+                    let pixel = scale.texture_pixel;
+                    let borders = [left * pixel.x, right * pixel.x, top * pixel.y, bottom * pixel.y];
+                    let uv_borders = [left / sprite.x, right / sprite.x, top / sprite.y, bottom / sprite.y];
+                    nine_slice(&mut vertices, &mut indices, rect, borders, uv_borders, self.tint(default_text_color()));
+                },
+                (sprite_mode, _) => {
+                    if sprite_mode != UiSpriteMode::Stretch {
+                        warn!("{} has no texture to slice, stretching it", self.name);
+                    }
+                    push_quad(&mut vertices, &mut indices, rect.min, rect.max, Vec2f::new([0.0, 0.0]), Vec2f::new([1.0, 1.0]), self.tint(default_text_color()));
+                }
+            }
+        }
+
+        match vertices.is_empty() {
+            true => None,
+            false => Some(UiMesh::new(vertices, indices)),
+        }
+    }
+
+    // no effect on elements that aren't text
+    pub fn set_text(&mut self, content: &str) {
+        if let UiElementType::Text(text) = &mut self.element_type {
+            if text.content != content {
+                text.content = content.to_string();
+                self.dirty = true;
+            }
+        }
+    }
+
+    // takes effect next frame, siblings in a stack move along; keeps the center and adds to
+    // the stretch of elements with different anchors
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        let center = (self.anchors.offset_min + self.anchors.offset_max) / 2.0;
+        let half = Vec2f::new([width, height]) / 2.0;
+        self.set_anchors(UiAnchors { offset_min: center - half, offset_max: center + half, ..self.anchors });
+    }
+
+    // only uploaded as part of a batch
+    pub fn rebuild_mesh(&mut self, sources: &UiMeshSources, state: &State) {
+        self.mesh = self.generate_mesh(sources, state);
+        self.dirty = false;
     }
 }
 
@@ -90,58 +460,593 @@ impl Default for UiElement {
     }
 }
 
+pub fn ui_scale(state: &State) -> UiScale {
+    let window_size = state.window.window_handle.inner_size();
+    state.renderer.ui_scale_mode.scale(window_size.width, window_size.height, state.window.window_handle.scale_factor())
+}
+
+fn arrange(layout: UiLayoutKind, parent: UiRect, children: &[&UiElement], scale: &UiScale) -> Vec<UiRect> {
+    match layout {
+        UiLayoutKind::Free => children.iter().map(|x| x.anchors.rect(parent, scale)).collect(),
+        UiLayoutKind::VerticalStack { spacing } => {
+            let mut top = parent.min.y;
+            children.iter().map(|x| {
+                let size = x.size(parent, scale);
+                let rect = UiRect::from_center(Vec2f::new([parent.center().x, top + size.y / 2.0]), size);
+                top += size.y + spacing * scale.unit.y;
+                rect
+            }).collect()
+        },
+        UiLayoutKind::HorizontalStack { spacing } => {
+            let mut left = parent.min.x;
+            children.iter().map(|x| {
+                let size = x.size(parent, scale);
+                let rect = UiRect::from_center(Vec2f::new([left + size.x / 2.0, parent.center().y]), size);
+                left += size.x + spacing * scale.unit.x;
+                rect
+            }).collect()
+        },
+        UiLayoutKind::Grid { columns } => {
+            let columns = columns.max(1) as usize;
+            let rows = children.len().div_ceil(columns).max(1);
+            let cell = parent.size() / Vec2f::new([columns as f32, rows as f32]);
+            children.iter().enumerate().map(|(i, x)| {
+                let cell_center = Vec2f::new([(i % columns) as f32 + 0.5, (i / columns) as f32 + 0.5]) * cell;
+                UiRect::from_center(parent.min + cell_center, x.size(parent, scale))
+            }).collect()
+        },
+    }
+}
+
+// false when the element or any of its parents is hidden
+pub fn ui_visible(ui: &HashMap<Uuid, UiElement>, uuid: Uuid) -> bool {
+    let mut visited = HashSet::new();
+    let mut current = Some(uuid);
+    // a parent cycle would never reach a root
+    while let Some(uuid) = current.filter(|x| visited.insert(*x)) {
+        match ui.get(&uuid) {
+            Some(element) if !element.visible => return false,
+            Some(element) => current = element.parent,
+            None => break,
+        }
+    }
+    true
+}
+
+// callbacks of the visible buttons under `point`, parts clipped away by a parent don't count
+fn hit_buttons(ui: &HashMap<Uuid, UiElement>, point: Vec2f) -> Vec<Uuid> {
+    ui.iter()
+        .filter_map(|(uuid, ui_element)| match ui_element.element_type {
+            UiElementType::Button(callback) if ui_element.hit(point) && ui_visible(ui, *uuid) => Some(callback),
+            _ => None
+        })
+        .collect()
+}
+
+// the innermost visible scroll view under `point`
+fn scroll_target(ui: &HashMap<Uuid, UiElement>, point: Vec2f) -> Option<Uuid> {
+    ui.iter()
+        .filter(|(uuid, x)| matches!(x.element_type, UiElementType::ScrollView(_)) && x.hit(point) && ui_visible(ui, **uuid))
+        .max_by_key(|(_, x)| x.depth)
+        .map(|(uuid, _)| *uuid)
+}
+
+// computes every rect from the screen down, elements whose rect changed are marked for a mesh rebuild
+pub fn layout_ui(ui: &mut HashMap<Uuid, UiElement>, scale: &UiScale) {
+    let roots: Vec<Uuid> = ui.iter()
+        .filter(|(_, x)| x.parent.is_none_or(|parent| !ui.contains_key(&parent)))
+        .map(|(uuid, _)| *uuid)
+        .collect();
+    let root_rects = arrange(UiLayoutKind::Free, UiRect::screen(), &roots.iter().map(|x| &ui[x]).collect::<Vec<_>>(), scale);
+
+    let mut queue: Vec<(Uuid, UiRect, u32, f32, Option<UiRect>)> = roots.into_iter().zip(root_rects)
+        .map(|(uuid, rect)| (uuid, rect, 0, 1.0, None))
+        .collect();
+    let mut visited = HashSet::new();
+    while let Some((uuid, rect, depth, parent_opacity, clip)) = queue.pop() {
+        // children lists edited at runtime could form a cycle
+        if !visited.insert(uuid) {
+            continue;
+        }
+
+        let element = ui.get_mut(&uuid).unwrap();
+        if element.rect != Some(rect) {
+            element.rect = Some(rect);
+            element.dirty = true;
+        }
+        element.depth = depth;
+        // only the scissor changes, the mesh stays
+        element.clip = clip;
+        let group_opacity = parent_opacity * element.opacity;
+        if element.group_opacity != group_opacity {
+            element.group_opacity = group_opacity;
+            element.dirty = true;
+        }
+
+        let element = &ui[&uuid];
+        let child_clip = match element.clips_children() {
+            true => Some(clip.map_or(rect, |x| x.intersect(&rect))),
+            false => clip,
+        };
+        let children: Vec<(Uuid, &UiElement)> = element.children.iter()
+            .filter_map(|x| ui.get(x).map(|child| (*x, child)))
+            .collect();
+        let mut rects = arrange(element.layout, rect, &children.iter().map(|x| x.1).collect::<Vec<_>>(), scale);
+        let children: Vec<Uuid> = children.into_iter().map(|x| x.0).collect();
+
+        if let UiElementType::ScrollView(scroll) = &mut ui.get_mut(&uuid).unwrap().element_type {
+            let bottom = rects.iter().fold(rect.max.y, |bottom, x| bottom.max(x.max.y));
+            scroll.offset = scroll.offset.clamp(0.0, (bottom - rect.max.y) / scale.unit.y);
+            let offset = Vec2f::new([0.0, -scroll.offset * scale.unit.y]);
+            rects.iter_mut().for_each(|x| *x = x.translated(offset));
+        }
+        queue.extend(children.into_iter().zip(rects).map(|(child, rect)| (child, rect, depth + 1, group_opacity, child_clip)));
+    }
+}
+
+// combines the meshes into batches again, reusing batches whose elements are the same and weren't rebuilt
+fn rebuild_batches(ui: &HashMap<Uuid, UiElement>, state: &mut State, rebuilt: &HashSet<Uuid>) {
+    let mut previous = std::mem::take(&mut state.renderer.ui_batches);
+    let batches = batch_ui(ui).into_iter()
+        .map(|batch| {
+            let unchanged = previous.iter().position(|x| x.batch == batch && !batch.elements.iter().any(|x| rebuilt.contains(x)));
+            match unchanged {
+                Some(i) => previous.swap_remove(i),
+                None => {
+                    let mut combined = batch.combine(ui);
+                    combined.mesh.load(state);
+                    combined
+                }
+            }
+        })
+        .collect();
+    state.renderer.ui_batches = batches;
+}
+
 pub struct UiMeshBuilder {}
 
 impl System for UiMeshBuilder {
     fn on_start(&self, _world: &crate::ecs::World, assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
+        layout_ui(&mut assets.ui, &ui_scale(state));
+        let sources = UiMeshSources { fonts: &assets.fonts, materials: &assets.materials, textures: &assets.textures };
         for (_, element) in assets.ui.iter_mut() {
-            let mut mesh = element.generate_mesh(state);
-            mesh.load(state);
-            element.mesh = Some(mesh);
+            element.rebuild_mesh(&sources, state);
         }
+        rebuild_batches(&assets.ui, state, &assets.ui.keys().copied().collect());
     }
 
     fn on_update(&self, _world: &crate::ecs::World, assets: &mut crate::asset_library::AssetLibrary, state: &mut crate::state::State) {
-        if !state.renderer.window_resized { return; }
-        for (_, element) in assets.ui.iter_mut() {
-            let mut mesh = element.generate_mesh(state);
-            mesh.load(state);
-            element.mesh = Some(mesh);
+        if state.window.is_minimized() { return; }
+        // a new scale mode or DPI moves everything just like a resize
+        let resized = state.renderer.window_resized || std::mem::take(&mut state.renderer.ui_scale_changed);
+        // elements added at runtime have no rect yet
+        if resized || assets.ui.values().any(|x| x.dirty || x.rect.is_none()) {
+            layout_ui(&mut assets.ui, &ui_scale(state));
+        }
+        let sources = UiMeshSources { fonts: &assets.fonts, materials: &assets.materials, textures: &assets.textures };
+        let mut rebuilt = HashSet::new();
+        for (uuid, element) in assets.ui.iter_mut() {
+            if resized || element.dirty {
+                element.rebuild_mesh(&sources, state);
+                rebuilt.insert(*uuid);
+            }
         }
+        // visibility, removals and new clip rects regroup the batches without touching the elements' meshes
+        rebuild_batches(&assets.ui, state, &rebuilt);
     }
 }
 
-pub struct UiHandler {}
+pub struct UiHandler {
+    tooltips: RefCell<UiTooltips>,
+}
+
+impl UiHandler {
+    pub fn new() -> UiHandler {
+        UiHandler { tooltips: RefCell::new(UiTooltips::default()) }
+    }
+}
+
+impl Default for UiHandler {
+    fn default() -> Self {
+        UiHandler::new()
+    }
+}
 
 impl System for UiHandler {
     fn on_start(&self, _world: &crate::ecs::World, _assets: &mut crate::asset_library::AssetLibrary, _state: &mut State) {}
 
     fn on_update(&self, world: &crate::ecs::World, assets: &mut crate::asset_library::AssetLibrary, state: &mut State) {
-        let window_size = Vec2f::new([state.window.window_handle.inner_size().width as f32, state.window.window_handle.inner_size().height as f32]);
-        let normalized_position = (state.input.cursor_position / window_size - Vec2f::new([0.5, 0.5])) * 2.0;
-
-        for i in 0..assets.ui.len() {
-            let ui_element = assets.ui.values().nth(i).unwrap();
-            match ui_element.element_type {
-                UiElementType::Button(uuid) => {
-                    let offset = anchor_to_offset(ui_element.screen_anchor);
-                    let x_offset = ui_element.width / 2.0;
-                    let y_offset = ui_element.height / 2.0;
-                    let window_size = state.window.window_handle.inner_size();
-                    let ratio = window_size.width as f32 / window_size.height as f32;
-
-                    let left =  ui_element.position.x         + offset.x - x_offset; 
-                    let right = ui_element.position.x         + offset.x + x_offset; 
-                    let up =   -ui_element.position.y * ratio + offset.y - y_offset * ratio;
-                    let down = -ui_element.position.y * ratio + offset.y + y_offset * ratio;
-
-                    let hit = normalized_position.x >= left && normalized_position.x <= right && normalized_position.y >= up && normalized_position.y <= down;
-                    if hit && state.input.button_pressed.contains(&MouseButton::Left) {
-                        world.callbacks.get(&uuid).expect("Callback not found").action(world, assets, state);
-                    }
-                },
-                UiElementType::None => {}
+        // there's no cursor headless, the ui still follows the keyboard
+        let cursor = state.window.get().map(|window| {
+            let window_size = Vec2f::new([window.window_handle.inner_size().width as f32, window.window_handle.inner_size().height as f32]);
+            (state.input.cursor_position / window_size - Vec2f::new([0.5, 0.5])) * 2.0
+        });
+
+        if let Some(normalized_position) = cursor.filter(|_| state.input.scroll_delta != 0.0) {
+            if let Some(element) = scroll_target(&assets.ui, normalized_position).and_then(|x| assets.ui.get_mut(&x)) {
+                if let UiElementType::ScrollView(scroll) = &mut element.element_type {
+                    // wheel up moves the content down
+                    scroll.offset = (scroll.offset - state.input.scroll_delta * scroll.speed).max(0.0);
+                    element.dirty = true;
+                }
             }
         }
+
+        state.ui_focus.update(&mut assets.ui);
+        if let Some(normalized_position) = cursor {
+            state.ui_focus.hover(&mut assets.ui, normalized_position);
+        }
+        let keys = [
+            (NamedKey::ArrowUp, UiNavigation::Up),
+            (NamedKey::ArrowDown, UiNavigation::Down),
+            (NamedKey::ArrowLeft, UiNavigation::Left),
+            (NamedKey::ArrowRight, UiNavigation::Right),
+            (NamedKey::Enter, UiNavigation::Activate),
+        ];
+        let navigation = keys.into_iter()
+            .filter(|(key, _)| state.input.key_pressed.contains(&Key::Named(*key)))
+            .map(|(_, navigation)| navigation);
+        let activated = state.ui_focus.apply(&mut assets.ui, navigation.collect::<Vec<_>>());
+        if let Some(normalized_position) = cursor {
+            self.tooltips.borrow_mut().update(assets, normalized_position, state.time_struct.unscaled_elapsed, &ui_scale(state));
+        }
+
+        // callbacks may add or remove elements, so the hits are collected first
+        let mut callbacks: Vec<Uuid> = activated.iter()
+            .filter_map(|x| match assets.ui.get(x)?.element_type {
+                UiElementType::Button(callback) => Some(callback),
+                _ => None,
+            })
+            .collect();
+        if let Some(normalized_position) = cursor.filter(|_| state.input.button_pressed.contains(&MouseButton::Left)) {
+            callbacks.extend(hit_buttons(&assets.ui, normalized_position));
+        }
+        for uuid in callbacks {
+            world.callbacks.get(&uuid).expect("Callback not found").action(world, assets, state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use crate::types::vectors::Vec2f;
+
+    use crate::ui::{ui_scale::{UiScale, UiScaleMode}, ui_text::default_text_color};
+
+    use super::{
+        hit_buttons, layout_ui, nine_slice, scroll_target, ui_visible, Anchor, UiAnchors, UiElement, UiElementType,
+        UiLayoutKind, UiRect, UiScrollView, UiVertexData,
+    };
+
+    fn element(anchor: Anchor, position: [f32; 2], width: f32, height: f32) -> UiElement {
+        UiElement::new("element", UiElementType::None, Uuid::nil(), anchor, Vec2f::new(position), width, height)
+    }
+
+    // a parent with `count` children of the given size, returns the parent and the children in order
+    fn container(ui: &mut HashMap<Uuid, UiElement>, layout: UiLayoutKind, count: usize, child: (f32, f32)) -> (Uuid, Vec<Uuid>) {
+        let parent = Uuid::new_v4();
+        let children: Vec<Uuid> = (0..count).map(|_| Uuid::new_v4()).collect();
+        let mut parent_element = element(Anchor::Center, [0.0, 0.0], 1.0, 1.0);
+        parent_element.layout = layout;
+        parent_element.children.clone_from(&children);
+        ui.insert(parent, parent_element);
+        for uuid in children.iter() {
+            let mut child_element = element(Anchor::Center, [0.0, 0.0], child.0, child.1);
+            child_element.parent = Some(parent);
+            ui.insert(*uuid, child_element);
+        }
+        (parent, children)
+    }
+
+    // the old behaviour, heights are scaled by the aspect ratio
+    fn ndc(ratio: f32) -> UiScale {
+        UiScaleMode::Ndc.scale((ratio * 1000.0) as u32, 1000, 1.0)
+    }
+
+    fn close(a: Vec2f, b: [f32; 2]) -> bool {
+        (a.x - b[0]).abs() < 1e-5 && (a.y - b[1]).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_vertical_stack() {
+        let mut ui = HashMap::new();
+        let (parent, children) = container(&mut ui, UiLayoutKind::VerticalStack { spacing: 0.05 }, 3, (0.5, 0.1));
+        layout_ui(&mut ui, &ndc(2.0));
+
+        // heights and spacing are scaled by the aspect ratio
+        assert_eq!(ui[&parent].rect(), Some(UiRect::from_center(Vec2f::new([0.0, 0.0]), Vec2f::new([1.0, 2.0]))));
+        let tops: Vec<f32> = children.iter().map(|x| ui[x].rect().unwrap().min.y).collect();
+        assert!(close(Vec2f::new([tops[0], tops[1]]), [-1.0, -0.7]));
+        assert!((tops[2] + 0.4).abs() < 1e-5);
+        assert!(close(ui[&children[1]].rect().unwrap().center(), [0.0, -0.6]));
+        assert!(children.iter().all(|x| ui[x].depth() == 1));
+    }
+
+    #[test]
+    fn test_horizontal_stack_and_grid() {
+        let mut ui = HashMap::new();
+        let (_, row) = container(&mut ui, UiLayoutKind::HorizontalStack { spacing: 0.1 }, 3, (0.2, 0.2));
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(close(ui[&row[0]].rect().unwrap().min, [-0.5, -0.1]));
+        assert!(close(ui[&row[2]].rect().unwrap().min, [0.1, -0.1]));
+
+        let mut ui = HashMap::new();
+        let (_, cells) = container(&mut ui, UiLayoutKind::Grid { columns: 2 }, 3, (0.1, 0.1));
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(close(ui[&cells[0]].rect().unwrap().center(), [-0.25, -0.25]));
+        assert!(close(ui[&cells[1]].rect().unwrap().center(), [0.25, -0.25]));
+        assert!(close(ui[&cells[2]].rect().unwrap().center(), [-0.25, 0.25]));
+    }
+
+    #[test]
+    fn test_free_children_anchor_to_parent() {
+        let mut ui = HashMap::new();
+        let parent = Uuid::new_v4();
+        let child = Uuid::new_v4();
+        let mut parent_element = element(Anchor::Left, [0.5, 0.0], 1.0, 0.5);
+        parent_element.children.push(child);
+        let mut child_element = element(Anchor::UpRight, [0.0, 0.0], 0.2, 0.2);
+        child_element.parent = Some(parent);
+        ui.insert(parent, parent_element);
+        ui.insert(child, child_element);
+        layout_ui(&mut ui, &ndc(1.0));
+
+        // the parent sits at the left edge of the screen, the child on the parent's top right corner
+        assert_eq!(ui[&parent].rect(), Some(UiRect::from_center(Vec2f::new([-0.5, 0.0]), Vec2f::new([1.0, 0.5]))));
+        assert!(close(ui[&child].rect().unwrap().center(), [0.0, -0.25]));
+    }
+
+    #[test]
+    fn test_scale_with_height_layout() {
+        let mode = UiScaleMode::ScaleWithHeight { reference_height: 1080.0 };
+        for (width, height) in [(1280, 720), (3840, 2160)] {
+            let mut ui = HashMap::new();
+            let uuid = Uuid::new_v4();
+            ui.insert(uuid, element(Anchor::UpLeft, [100.0, -100.0], 100.0, 100.0));
+            layout_ui(&mut ui, &mode.scale(width, height, 1.0));
+
+            // the same part of the window at both heights, 100 reference pixels in from the corner
+            let rect = ui[&uuid].rect().unwrap();
+            assert!((rect.size().y / 2.0 - 100.0 / 1080.0).abs() < 1e-5);
+            assert!(close(rect.center(), [-1.0 + 200.0 * 9.0 / (1080.0 * 16.0), -1.0 + 200.0 / 1080.0]));
+        }
+    }
+
+    #[test]
+    fn test_stretch_to_fill() {
+        let mut ui = HashMap::new();
+        let (parent, children) = container(&mut ui, UiLayoutKind::Free, 2, (0.1, 0.1));
+        ui.get_mut(&parent).unwrap().set_size(1.0, 0.5);
+        ui.get_mut(&children[0]).unwrap().set_anchors(UiAnchors::stretch(0.0));
+        ui.get_mut(&children[1]).unwrap().set_anchors(UiAnchors::stretch(0.1));
+        layout_ui(&mut ui, &ndc(2.0));
+
+        assert_eq!(ui[&children[0]].rect(), ui[&parent].rect());
+        // margins are in layout units, heights scaled by the aspect ratio like everything else
+        let inset = ui[&children[1]].rect().unwrap();
+        assert!(close(inset.min, [-0.4, -0.3]));
+        assert!(close(inset.max, [0.4, 0.3]));
+
+        // follows the parent when it grows
+        ui.get_mut(&parent).unwrap().set_size(1.5, 0.5);
+        layout_ui(&mut ui, &ndc(2.0));
+        assert_eq!(ui[&children[0]].rect(), ui[&parent].rect());
+        assert!(ui[&children[0]].dirty);
+    }
+
+    #[test]
+    fn test_fixed_margin_bar() {
+        // 10 logical pixels below the top, 80% of the width, 30 pixels tall
+        let bar = UiAnchors {
+            anchor_min: Vec2f::new([0.1, 0.0]),
+            anchor_max: Vec2f::new([0.9, 0.0]),
+            offset_min: Vec2f::new([0.0, 10.0]),
+            offset_max: Vec2f::new([0.0, 40.0]),
+        };
+        for (width, height, scale_factor) in [(1000, 500, 1.0), (3000, 1000, 2.0)] {
+            let mut ui = HashMap::new();
+            let uuid = Uuid::new_v4();
+            ui.insert(uuid, UiElement::with_anchors("bar", UiElementType::None, Uuid::nil(), bar));
+            layout_ui(&mut ui, &UiScaleMode::ConstantPhysicalSize.scale(width, height, scale_factor));
+
+            let rect = ui[&uuid].rect().unwrap();
+            let to_pixels = |ndc: f32, size: u32| (ndc + 1.0) / 2.0 * size as f32;
+            assert!((to_pixels(rect.min.x, width) - 0.1 * width as f32).abs() < 1e-2);
+            assert!((to_pixels(rect.max.x, width) - 0.9 * width as f32).abs() < 1e-2);
+            assert!((to_pixels(rect.min.y, height) - 10.0 * scale_factor as f32).abs() < 1e-2);
+            assert!((to_pixels(rect.max.y, height) - 40.0 * scale_factor as f32).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn test_from_anchor_matches_center_placement() {
+        let anchors = UiAnchors::from_anchor(Anchor::DownRight, Vec2f::new([-0.2, 0.1]), 0.2, 0.1);
+        let rect = anchors.rect(UiRect::screen(), &ndc(1.0));
+        assert_eq!(anchors.anchor_min, Vec2f::new([1.0, 1.0]));
+        assert!(close(rect.center(), [0.8, 0.9]));
+        assert!(close(rect.size(), [0.2, 0.1]));
+    }
+
+    #[test]
+    fn test_layout_marks_only_changed_rects() {
+        let mut ui = HashMap::new();
+        let (parent, children) = container(&mut ui, UiLayoutKind::VerticalStack { spacing: 0.0 }, 3, (0.5, 0.1));
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(ui.values().all(|x| x.dirty));
+
+        ui.values_mut().for_each(|x| x.dirty = false);
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(ui.values().all(|x| !x.dirty));
+
+        // growing the first child pushes its siblings down, the parent stays
+        ui.get_mut(&children[0]).unwrap().set_size(0.5, 0.2);
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(!ui[&parent].dirty);
+        assert!(children.iter().all(|x| ui[x].dirty));
+        assert!(ui[&children[0]].rect().unwrap().contains(Vec2f::new([0.0, -0.35])));
+        assert!(!ui[&children[1]].rect().unwrap().contains(Vec2f::new([0.0, -0.35])));
+    }
+
+    #[test]
+    fn test_hidden_elements_are_not_hit() {
+        let (front_callback, back_callback) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut ui = HashMap::new();
+        let (front, back) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut button = element(Anchor::Center, [0.0, 0.0], 0.5, 0.5);
+        button.element_type = UiElementType::Button(front_callback);
+        ui.insert(front, button);
+        let mut button = element(Anchor::Center, [0.0, 0.0], 1.0, 1.0);
+        button.element_type = UiElementType::Button(back_callback);
+        ui.insert(back, button);
+        layout_ui(&mut ui, &ndc(1.0));
+
+        let point = Vec2f::new([0.0, 0.0]);
+        assert_eq!(hit_buttons(&ui, point).len(), 2);
+        // clicks go through the hidden button to the one behind it
+        ui.get_mut(&front).unwrap().visible = false;
+        assert_eq!(hit_buttons(&ui, point), vec![back_callback]);
+        ui.get_mut(&back).unwrap().visible = false;
+        assert!(hit_buttons(&ui, point).is_empty());
+    }
+
+    #[test]
+    fn test_hidden_parent_hides_children() {
+        let callback = Uuid::new_v4();
+        let mut ui = HashMap::new();
+        let (parent, children) = container(&mut ui, UiLayoutKind::VerticalStack { spacing: 0.0 }, 2, (0.5, 0.1));
+        ui.get_mut(&children[0]).unwrap().element_type = UiElementType::Button(callback);
+        let grandchild = Uuid::new_v4();
+        let mut label = element(Anchor::Center, [0.0, 0.0], 0.1, 0.1);
+        label.parent = Some(children[0]);
+        ui.get_mut(&children[0]).unwrap().children.push(grandchild);
+        ui.insert(grandchild, label);
+        layout_ui(&mut ui, &ndc(1.0));
+        ui.values_mut().for_each(|x| x.dirty = false);
+
+        let point = ui[&children[0]].rect().unwrap().center();
+        assert_eq!(hit_buttons(&ui, point), vec![callback]);
+        ui.get_mut(&parent).unwrap().visible = false;
+        assert!(hit_buttons(&ui, point).is_empty());
+        assert!(!ui_visible(&ui, grandchild));
+        assert!(!ui_visible(&ui, children[1]));
+
+        // toggling visibility leaves the meshes alone
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(ui.values().all(|x| !x.dirty));
+        ui.get_mut(&parent).unwrap().visible = true;
+        assert!(ui_visible(&ui, grandchild));
+    }
+
+    #[test]
+    fn test_opacity_multiplies_down_the_hierarchy() {
+        let mut ui = HashMap::new();
+        let (parent, children) = container(&mut ui, UiLayoutKind::VerticalStack { spacing: 0.0 }, 2, (0.5, 0.1));
+        layout_ui(&mut ui, &ndc(1.0));
+        ui.values_mut().for_each(|x| x.dirty = false);
+
+        ui.get_mut(&parent).unwrap().set_opacity(0.5);
+        ui.get_mut(&children[0]).unwrap().set_opacity(0.5);
+        layout_ui(&mut ui, &ndc(1.0));
+        assert!(ui.values().all(|x| x.dirty));
+        assert!((ui[&children[0]].group_opacity - 0.25).abs() < 1e-5);
+        assert!((ui[&children[1]].group_opacity - 0.5).abs() < 1e-5);
+        let tinted = ui[&children[0]].tint(default_text_color());
+        assert!((tinted.w - 0.25).abs() < 1e-5 && tinted.x == 1.0);
+    }
+
+    #[test]
+    fn test_scroll_view_clips_and_offsets_children() {
+        let callbacks: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let mut ui = HashMap::new();
+        // a 1x1 view with four 0.4 tall buttons, 0.6 of content below the view
+        let (view, items) = container(&mut ui, UiLayoutKind::VerticalStack { spacing: 0.0 }, 4, (0.5, 0.4));
+        ui.get_mut(&view).unwrap().element_type = UiElementType::ScrollView(UiScrollView::default());
+        for (item, callback) in items.iter().zip(callbacks.iter()) {
+            ui.get_mut(item).unwrap().element_type = UiElementType::Button(*callback);
+        }
+        layout_ui(&mut ui, &ndc(1.0));
+
+        let view_rect = ui[&view].rect().unwrap();
+        assert!(items.iter().all(|x| ui[x].clip() == Some(view_rect)));
+        assert_eq!(ui[&view].clip(), None);
+        // the third button sticks out of the view, only its visible part is clickable
+        assert_eq!(hit_buttons(&ui, Vec2f::new([0.0, 0.45])), vec![callbacks[2]]);
+        assert!(hit_buttons(&ui, Vec2f::new([0.0, 0.55])).is_empty());
+        assert_eq!(scroll_target(&ui, Vec2f::new([0.0, 0.0])), Some(view));
+
+        // scrolling past the end stops at the last button's bottom edge
+        if let UiElementType::ScrollView(scroll) = &mut ui.get_mut(&view).unwrap().element_type {
+            scroll.offset = 5.0;
+        }
+        layout_ui(&mut ui, &ndc(1.0));
+        let UiElementType::ScrollView(scroll) = ui[&view].element_type else { unreachable!() };
+        assert!((scroll.offset - 0.6).abs() < 1e-5);
+        assert!((ui[&items[3]].rect().unwrap().max.y - 0.5).abs() < 1e-5);
+        assert_eq!(hit_buttons(&ui, Vec2f::new([0.0, 0.45])), vec![callbacks[3]]);
+        assert!(hit_buttons(&ui, Vec2f::new([0.0, -0.55])).is_empty());
+    }
+
+    #[test]
+    fn test_nested_clips_intersect() {
+        let mut ui = HashMap::new();
+        let (outer, children) = container(&mut ui, UiLayoutKind::Free, 1, (1.0, 1.0));
+        let inner = children[0];
+        ui.get_mut(&outer).unwrap().set_size(1.0, 0.5);
+        ui.get_mut(&outer).unwrap().clip_children = true;
+        ui.get_mut(&inner).unwrap().clip_children = true;
+        let leaf = Uuid::new_v4();
+        let mut element = element(Anchor::Center, [0.0, 0.0], 2.0, 2.0);
+        element.parent = Some(inner);
+        ui.get_mut(&inner).unwrap().children.push(leaf);
+        ui.insert(leaf, element);
+        layout_ui(&mut ui, &ndc(1.0));
+
+        assert_eq!(ui[&inner].clip(), ui[&outer].rect());
+        assert_eq!(ui[&leaf].clip(), Some(UiRect::from_center(Vec2f::new([0.0, 0.0]), Vec2f::new([1.0, 0.5]))));
+        let apart = UiRect { min: Vec2f::new([2.0, 2.0]), max: Vec2f::new([3.0, 3.0]) };
+        assert!(UiRect::screen().intersect(&apart).is_empty());
+    }
+
+    fn sliced(rect: UiRect, borders: [f32; 4]) -> (Vec<UiVertexData>, Vec<u32>) {
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+        nine_slice(&mut vertices, &mut indices, rect, borders, [0.25, 0.125, 0.25, 0.5], default_text_color());
+        (vertices, indices)
+    }
+
+    #[test]
+    fn test_nine_slice_keeps_borders() {
+        let rect = UiRect { min: Vec2f::new([-0.5, -0.5]), max: Vec2f::new([1.5, 0.5]) };
+        let (vertices, indices) = sliced(rect, [0.1, 0.2, 0.1, 0.3]);
+        assert_eq!(vertices.len(), 16);
+        assert_eq!(indices.len(), 54);
+        assert!(indices.iter().all(|x| *x < 16));
+        // same winding as a plain quad
+        assert_eq!(indices[..6], [0, 4, 5, 0, 5, 1]);
+
+        let positions: Vec<f32> = vertices[..4].iter().map(|x| x.position.x).collect();
+        assert!(positions.iter().zip([-0.5, -0.4, 1.3, 1.5]).all(|(a, b)| (a - b).abs() < 1e-5));
+        let rows: Vec<f32> = vertices.iter().step_by(4).map(|x| x.position.y).collect();
+        assert!(rows.iter().zip([-0.5, -0.4, 0.2, 0.5]).all(|(a, b)| (a - b).abs() < 1e-5));
+
+        assert!(close(*vertices[5].uv, [0.25, 0.25]));
+        assert!(close(*vertices[10].uv, [0.875, 0.5]));
+        assert!(close(*vertices[15].uv, [1.0, 1.0]));
+    }
+
+    #[test]
+    fn test_nine_slice_clamps_small_rects() {
+        let rect = UiRect { min: Vec2f::new([0.0, 0.0]), max: Vec2f::new([0.1, 1.0]) };
+        let (vertices, _) = sliced(rect, [0.1, 0.1, 0.1, 0.1]);
+
+        // the left and right borders meet in the middle instead of crossing
+        let columns: Vec<f32> = vertices[..4].iter().map(|x| x.position.x).collect();
+        assert!(columns.iter().zip([0.0, 0.05, 0.05, 0.1]).all(|(a, b)| (a - b).abs() < 1e-5));
+        assert!(close(*vertices[5].position, [0.05, 0.1]));
+        // the corners still show the whole border of the texture
+        assert!(close(*vertices[5].uv, [0.25, 0.25]));
     }
 }
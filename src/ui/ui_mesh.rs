@@ -1,9 +1,14 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
-use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}};
+use uuid::Uuid;
+use vulkano::buffer::{BufferUsage, Subbuffer};
 
 use crate::state::State;
 
-use super::ui_layout::UiVertexData;
+use super::ui_layout::{ui_visible, UiElement, UiRect, UiVertexData};
+
+pub type UiMeshBuffers = (Subbuffer<[UiVertexData]>, Subbuffer<[u32]>);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UiMesh {
@@ -36,35 +41,151 @@ impl UiMesh {
     }
 
     pub fn load(&mut self, state: &State) {
+        let transfer = &state.memory_allocators.transfer;
+        let memory_allocator = &state.memory_allocators.standard_memory_allocator;
         self.vertex_buffer = Some(
-            Buffer::from_iter(
-                state.memory_allocators.standard_memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                        MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                self.vertices.clone()
-            ).unwrap()
+            transfer.upload_buffer(memory_allocator.clone(), BufferUsage::VERTEX_BUFFER, self.vertices.clone()).unwrap()
         );
         self.index_buffer = Some(
-            Buffer::from_iter(
-                state.memory_allocators.standard_memory_allocator.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::INDEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                        MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                self.indices.clone()
-            ).unwrap()
+            transfer.upload_buffer(memory_allocator.clone(), BufferUsage::INDEX_BUFFER, self.indices.clone()).unwrap()
         )
     }
 }
+
+// elements sharing a material and clip rect, drawn with a single call
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiBatch {
+    pub material: Uuid,
+    pub clip: Option<UiRect>,
+    pub overlay: bool,
+    // in draw order
+    pub elements: Vec<Uuid>,
+}
+
+// the meshes of a batch's elements in one buffer
+#[derive(Debug)]
+pub struct UiBatchMesh {
+    pub batch: UiBatch,
+    // first vertex of every element, in the order of `batch.elements`
+    pub vertex_offsets: Vec<u32>,
+    pub mesh: UiMesh,
+}
+
+// visible elements with a mesh, parents before their children and overlays last; batches are ordered by
+// their shallowest element, so overlapping elements of different materials layer by batch, not depth
+pub fn batch_ui(ui: &HashMap<Uuid, UiElement>) -> Vec<UiBatch> {
+    let mut elements: Vec<(&Uuid, &UiElement)> = ui.iter()
+        .filter(|(uuid, x)| x.mesh.is_some() && !x.clip().is_some_and(|x| x.is_empty()) && ui_visible(ui, **uuid))
+        .collect();
+    // map order changes between runs, the uuid keeps equal depths stable
+    elements.sort_by_key(|(uuid, x)| (x.overlay, x.depth(), **uuid));
+
+    let mut batches: Vec<UiBatch> = Vec::new();
+    for (uuid, element) in elements {
+        let key = |x: &UiBatch| x.material == element.material && x.clip == element.clip() && x.overlay == element.overlay;
+        match batches.iter_mut().find(|x| key(x)) {
+            Some(batch) => batch.elements.push(*uuid),
+            None => batches.push(UiBatch {
+                material: element.material,
+                clip: element.clip(),
+                overlay: element.overlay,
+                elements: vec![*uuid],
+            }),
+        }
+    }
+    batches
+}
+
+impl UiBatch {
+    // expects every element to still have its mesh, like right after batch_ui
+    pub fn combine(&self, ui: &HashMap<Uuid, UiElement>) -> UiBatchMesh {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut vertex_offsets = Vec::new();
+        for mesh in self.elements.iter().filter_map(|x| ui.get(x)?.mesh.as_ref()) {
+            let offset = vertices.len() as u32;
+            vertex_offsets.push(offset);
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend(mesh.indices.iter().map(|x| x + offset));
+        }
+        UiBatchMesh { batch: self.clone(), vertex_offsets, mesh: UiMesh::new(vertices, indices) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use uuid::Uuid;
+
+    use crate::{
+        types::vectors::{GpuVec2f, Vec2f, Vec4f},
+        ui::{
+            ui_layout::{layout_ui, Anchor, UiElement, UiElementType, UiVertexData},
+            ui_scale::UiScaleMode,
+        },
+    };
+
+    use super::{batch_ui, UiMesh};
+
+    // a mesh of `quads` quads, every vertex's x is `tag`
+    fn element(material: Uuid, quads: u32, tag: f32) -> UiElement {
+        let mut element = UiElement::new("element", UiElementType::None, material, Anchor::Center, Vec2f::new([0.0, 0.0]), 0.1, 0.1);
+        let vertex = UiVertexData {
+            position: GpuVec2f::new([tag, 0.0]),
+            uv: GpuVec2f::new([0.0, 0.0]),
+            color: Vec4f::new([1.0, 1.0, 1.0, 1.0]),
+        };
+        let indices = (0..quads).flat_map(|x| [0, 1, 2, 0, 2, 3].map(|i| x * 4 + i)).collect();
+        element.mesh = Some(UiMesh::new(vec![vertex; quads as usize * 4], indices));
+        element
+    }
+
+    #[test]
+    fn test_batches_per_material() {
+        let (panel_material, text_material) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut ui = HashMap::new();
+        let panel = Uuid::new_v4();
+        let mut panel_element = element(panel_material, 1, 0.0);
+        let children: Vec<(Uuid, Uuid, u32)> = vec![
+            (Uuid::new_v4(), text_material, 3),
+            (Uuid::new_v4(), panel_material, 1),
+            (Uuid::new_v4(), text_material, 2),
+        ];
+        for (i, (uuid, material, quads)) in children.iter().enumerate() {
+            let mut child = element(*material, *quads, i as f32 + 1.0);
+            child.parent = Some(panel);
+            panel_element.children.push(*uuid);
+            ui.insert(*uuid, child);
+        }
+        ui.insert(panel, panel_element);
+        let hidden = Uuid::new_v4();
+        let mut hidden_element = element(text_material, 1, -1.0);
+        hidden_element.visible = false;
+        ui.insert(hidden, hidden_element);
+        layout_ui(&mut ui, &UiScaleMode::Ndc.scale(1000, 1000, 1.0));
+
+        let batches = batch_ui(&ui);
+        assert_eq!(batches.len(), 2);
+        // the panel is shallower than any text, so its material draws first
+        assert_eq!(batches[0].material, panel_material);
+        assert_eq!(batches[0].elements[0], panel);
+        assert!(!batches[1].elements.contains(&hidden));
+
+        let combined = batches[1].combine(&ui);
+        let vertex_counts: Vec<u32> = batches[1].elements.iter().map(|x| ui[x].mesh.as_ref().unwrap().vertices.len() as u32).collect();
+        assert_eq!(combined.vertex_offsets, vec![0, vertex_counts[0]]);
+        assert_eq!(combined.mesh.vertices.len(), 20);
+        assert_eq!(combined.mesh.indices.len(), 30);
+        // every element's indices point at its own vertices
+        let mut index = 0;
+        for (offset, uuid) in combined.vertex_offsets.iter().zip(batches[1].elements.iter()) {
+            let tag = ui[uuid].mesh.as_ref().unwrap().vertices[0].position.x;
+            for _ in 0..ui[uuid].mesh.as_ref().unwrap().indices.len() {
+                let vertex = combined.mesh.indices[index];
+                assert!(vertex >= *offset && combined.mesh.vertices[vertex as usize].position.x == tag);
+                index += 1;
+            }
+        }
+    }
+}
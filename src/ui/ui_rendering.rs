@@ -1,10 +1,92 @@
-use vulkano::{pipeline::{Pipeline, PipelineBindPoint}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, PrimaryAutoCommandBuffer}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}};
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use uuid::Uuid;
+use vulkano::{buffer::Subbuffer, pipeline::{graphics::viewport::Scissor, GraphicsPipeline, Pipeline, PipelineBindPoint}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, PrimaryAutoCommandBuffer}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, image::{sampler::Sampler, view::ImageView}};
  
-use crate::{asset_library::AssetLibrary, ecs::World, rendering::{rendering_component::RenderingComponent, PipelineIdentifier}, state::State, types::material::Attachment};
+use crate::{asset_library::AssetLibrary, ecs::World, rendering::{rendering_component::RenderingComponent, PipelineIdentifier}, state::State, types::{material::{Attachment, Material, MaterialParameters}, texture::resident_texture}, ui::ui_layout::UiRect};
+
+// pixels of a `extent` sized window covered by `clip`, the whole window for None
+fn ui_scissor(clip: Option<UiRect>, extent: [f32; 2]) -> Scissor {
+    let Some(clip) = clip else {
+        return Scissor { offset: [0, 0], extent: [extent[0] as u32, extent[1] as u32] };
+    };
+    let to_pixels = |ndc: f32, size: f32| (((ndc + 1.0) / 2.0).clamp(0.0, 1.0) * size).round() as u32;
+    let min = [to_pixels(clip.min.x, extent[0]), to_pixels(clip.min.y, extent[1])];
+    let max = [to_pixels(clip.max.x, extent[0]), to_pixels(clip.max.y, extent[1])];
+    Scissor { offset: min, extent: [max[0].saturating_sub(min[0]), max[1].saturating_sub(min[1])] }
+}
+
+// one set of descriptor sets per material and frame, with the addresses of what they were written with
+type CachedSets = (Vec<usize>, Vec<Arc<PersistentDescriptorSet>>);
+
+pub struct UiRenderingComponent {
+    descriptor_sets: RefCell<HashMap<(Uuid, usize), CachedSets>>,
+}
 
-pub struct UiRenderingComponent {}
+impl UiRenderingComponent {
+    pub fn new() -> UiRenderingComponent {
+        UiRenderingComponent { descriptor_sets: RefCell::new(HashMap::new()) }
+    }
+}
+
+impl Default for UiRenderingComponent {
+    fn default() -> Self {
+        UiRenderingComponent::new()
+    }
+}
+
+fn attachment_textures(assets: &AssetLibrary, material: &Material) -> Vec<(Arc<ImageView>, Arc<Sampler>)> {
+    material
+        .attachments
+        .iter()
+        .map(|attachment| {
+            let tex = match attachment {
+                Attachment::Texture(uuid) => resident_texture(assets, uuid).expect("Default texture not loaded"),
+                Attachment::DefaultTexture => assets.texture_by_name("default").expect("Default texture not loaded").1,
+            };
+            (tex.image_view.as_ref().unwrap().clone(), tex.sampler.as_ref().unwrap().clone())
+        })
+        .collect()
+}
+
+fn create_descriptor_sets(
+    state: &State,
+    pipeline: &GraphicsPipeline,
+    parameters: Subbuffer<MaterialParameters>,
+    textures: &[(Arc<ImageView>, Arc<Sampler>)],
+) -> Vec<Arc<PersistentDescriptorSet>> {
+    let material_set = PersistentDescriptorSet::new(
+        state.memory_allocators.descriptor_set_allocator.as_ref(),
+        pipeline.layout().set_layouts().first().unwrap().clone(),
+        [WriteDescriptorSet::buffer(0, parameters)],
+        [],
+    ).unwrap();
+
+    let mut sets = vec![material_set];
+    if !textures.is_empty() {
+        sets.push(PersistentDescriptorSet::new(
+            state.memory_allocators.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts().get(1).unwrap().clone(),
+            textures
+                .iter()
+                .enumerate()
+                .map(|(id, (view, sampler))| WriteDescriptorSet::image_view_sampler(id as u32, view.clone(), sampler.clone()))
+                .collect::<Vec<_>>(),
+            [],
+        ).unwrap());
+    }
+    sets
+}
 
 impl RenderingComponent for UiRenderingComponent {
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn per_view(&self) -> bool {
+        false
+    }
+
     fn render(
             &self,
             mut builder:
@@ -15,73 +97,89 @@ impl RenderingComponent for UiRenderingComponent {
             _world: &World,
             assets: &AssetLibrary,
             state: &State,
-            _image_id: usize
+            image_id: usize
             ) -> AutoCommandBufferBuilder<
                     PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>, 
                     StandardCommandBufferAllocator
         > {
-            
-        for (_, ui_layout) in assets.ui.iter() {
-            let material = assets.materials.get(&ui_layout.material).unwrap();
-            let pipeline = state.renderer.pipelines.get(&PipelineIdentifier::new(material.vertex_shader, material.fragment_shader, material.rendering_type)).unwrap().clone();
-            let material_set = PersistentDescriptorSet::new(
-                state.memory_allocators.descriptor_set_allocator.as_ref(),
-                pipeline.layout().set_layouts().first().unwrap().clone(),
-                [WriteDescriptorSet::buffer(
-                    0,
-                    material.parameter_buffer.as_ref().unwrap().clone(),
-                )],
-                [],
-            ).unwrap();
-            
-            let attachment_set = if !material.attachments.is_empty() {
-                Some({
-                    PersistentDescriptorSet::new(
-                        state.memory_allocators.descriptor_set_allocator.as_ref(),
-                        pipeline.layout().set_layouts().get(1).unwrap().clone(),
-                        material
-                        .attachments
-                        .iter()
-                        .enumerate()
-                        .map(|(id, attachment)| match attachment {
-                            Attachment::Texture(uuid) => {
-                                let tex = assets.textures.get(uuid).unwrap();
-                                WriteDescriptorSet::image_view_sampler(
-                                    id as u32,
-                                    tex.image_view.as_ref().unwrap().clone(),
-                                    tex.sampler.as_ref().unwrap().clone(),
-                                )
-                            },
-                            Attachment::DefaultTexture => {
-                                let (_, tex) = assets.textures.iter().find(|(_, x)| x.name == *"default").unwrap();
-                                WriteDescriptorSet::image_view_sampler(
-                                    id as u32,
-                                    tex.image_view.as_ref().unwrap().clone(),
-                                    tex.sampler.as_ref().unwrap().clone(),
-                                )
-                            }
-                        })
-                        .collect::<Vec<_>>(),
-                        [],
-                    ).unwrap()
-                })
-            } else {
-                None
+        let mut descriptor_sets = self.descriptor_sets.borrow_mut();
+        descriptor_sets.retain(|(material, _), _| assets.materials.contains_key(material));
+
+        let window_extent = state.renderer.viewport.extent;
+        let mut scissor = None;
+        for batch in state.renderer.ui_batches.iter() {
+            if state.renderer.invalid_materials.contains(&batch.batch.material) {
+                continue;
+            }
+            let (Some(vertex_buffer), Some(index_buffer)) = (batch.mesh.vertex_buffer.as_ref(), batch.mesh.index_buffer.as_ref()) else {
+                continue;
             };
-            
-            let mut sets = vec![material_set];
-            if let Some(attachment_set) = attachment_set {
-                sets.push(attachment_set);
+            let Some(material) = assets.materials.get(&batch.batch.material) else {
+                continue;
+            };
+            let pipeline = match state.renderer.pipelines.get(&PipelineIdentifier::from_material(material)) {
+                Some(val) => val.clone(),
+                None => continue,
+            };
+
+            // written again only when the pipeline, the parameter buffer or a texture was replaced
+            let parameters = material.parameter_buffer(image_id).unwrap();
+            let textures = attachment_textures(assets, material);
+            let resources: Vec<usize> = [Arc::as_ptr(&pipeline) as usize, Arc::as_ptr(parameters.buffer()) as usize, parameters.offset() as usize]
+                .into_iter()
+                .chain(textures.iter().flat_map(|(view, sampler)| [Arc::as_ptr(view) as usize, Arc::as_ptr(sampler) as usize]))
+                .collect();
+            let sets = match descriptor_sets.get(&(batch.batch.material, image_id)) {
+                Some((cached, sets)) if *cached == resources => sets.clone(),
+                _ => {
+                    let sets = create_descriptor_sets(state, &pipeline, parameters, &textures);
+                    state.renderer.frame_stats.borrow_mut().record_descriptor_sets(sets.len());
+                    descriptor_sets.insert((batch.batch.material, image_id), (resources, sets.clone()));
+                    sets
+                }
+            };
+
+            {
+                let mut stats = state.renderer.frame_stats.borrow_mut();
+                stats.record_pipeline_bind();
+                stats.record_draw(batch.mesh.indices.len() as u32);
             }
 
+            if batch.batch.clip != scissor {
+                scissor = batch.batch.clip;
+                builder.set_scissor(0, [ui_scissor(scissor, window_extent)].into_iter().collect()).unwrap();
+            }
             builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
             builder.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, sets).unwrap();
-            builder.bind_index_buffer(ui_layout.mesh.as_ref().unwrap().index_buffer.as_ref().unwrap().clone()).unwrap();
-            builder.bind_vertex_buffers(0, ui_layout.mesh.as_ref().unwrap().vertex_buffer.as_ref().unwrap().clone()).unwrap();
-            builder.draw_indexed(ui_layout.mesh.as_ref().unwrap().indices.len() as u32, 1, 0, 0, 0).unwrap();
+            state.renderer.ui_cache.borrow_mut().retain(image_id, (vertex_buffer.clone(), index_buffer.clone()));
+            builder.bind_index_buffer(index_buffer.clone()).unwrap();
+            builder.bind_vertex_buffers(0, vertex_buffer.clone()).unwrap();
+            builder.draw_indexed(batch.mesh.indices.len() as u32, 1, 0, 0, 0).unwrap();
+        }
+
+        // components drawn after the UI expect the full window
+        if scissor.is_some() {
+            builder.set_scissor(0, [ui_scissor(None, window_extent)].into_iter().collect()).unwrap();
         }
 
         builder
     }
-    
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{types::vectors::Vec2f, ui::ui_layout::UiRect};
+
+    use super::ui_scissor;
+
+    #[test]
+    fn test_scissor_covers_clip_in_pixels() {
+        let full = ui_scissor(None, [800.0, 600.0]);
+        assert_eq!((full.offset, full.extent), ([0, 0], [800, 600]));
+
+        let clip = UiRect { min: Vec2f::new([-0.5, 0.0]), max: Vec2f::new([0.5, 2.0]) };
+        let scissor = ui_scissor(Some(clip), [800.0, 600.0]);
+        // cut at the bottom edge of the window
+        assert_eq!((scissor.offset, scissor.extent), ([200, 300], [400, 300]));
+    }
 }
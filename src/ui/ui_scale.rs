@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::vectors::Vec2f;
+
+// what one unit of an element's position, size or text size means on screen
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum UiScaleMode {
+    // x units span half the window width, y units the same length as x ones
+    #[default]
+    Ndc,
+    // units are logical pixels, they keep their physical size across DPI settings
+    ConstantPhysicalSize,
+    // units are pixels of a window `reference_height` pixels tall, the UI grows with the window height
+    ScaleWithHeight { reference_height: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiScale {
+    // normalized device coordinates per unit
+    pub unit: Vec2f,
+    // normalized device coordinates per texture pixel, for nine-slice borders
+    pub texture_pixel: Vec2f,
+}
+
+impl UiScaleMode {
+    // `width` and `height` are the window's physical size
+    pub fn scale(&self, width: u32, height: u32, scale_factor: f64) -> UiScale {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        let screen_pixel = Vec2f::new([2.0 / width, 2.0 / height]);
+        match self {
+            UiScaleMode::Ndc => UiScale {
+                unit: Vec2f::new([1.0, width / height]),
+                texture_pixel: screen_pixel,
+            },
+            UiScaleMode::ConstantPhysicalSize => {
+                let unit = screen_pixel * scale_factor as f32;
+                UiScale { unit, texture_pixel: unit }
+            },
+            UiScaleMode::ScaleWithHeight { reference_height } => {
+                let unit = screen_pixel * (height / reference_height);
+                UiScale { unit, texture_pixel: unit }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UiScaleMode;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_scale_with_height_keeps_screen_fraction() {
+        let mode = UiScaleMode::ScaleWithHeight { reference_height: 1080.0 };
+        let small = mode.scale(1280, 720, 1.0);
+        let large = mode.scale(3840, 2160, 2.0);
+
+        // a 100 unit element covers the same part of a 720 and a 2160 pixel tall window of the same shape
+        let fraction = |unit: f32| 100.0 * unit / 2.0;
+        assert!(close(fraction(small.unit.y), 100.0 / 1080.0));
+        assert!(close(fraction(small.unit.y), fraction(large.unit.y)));
+        assert!(close(fraction(small.unit.x), fraction(large.unit.x)));
+        // and stays square in pixels
+        assert!(close(small.unit.x * 1280.0, small.unit.y * 720.0));
+    }
+
+    #[test]
+    fn test_constant_physical_size() {
+        let scale = UiScaleMode::ConstantPhysicalSize.scale(4000, 2000, 2.0);
+        // 100 logical pixels are 200 physical ones
+        assert!(close(100.0 * scale.unit.y / 2.0, 0.1));
+        assert!(close(100.0 * scale.unit.x / 2.0, 0.05));
+        assert_eq!(scale.texture_pixel, scale.unit);
+    }
+
+    #[test]
+    fn test_ndc_matches_aspect_correction() {
+        let scale = UiScaleMode::Ndc.scale(1600, 900, 1.5);
+        assert!(close(scale.unit.x, 1.0));
+        assert!(close(scale.unit.y, 1600.0 / 900.0));
+        assert!(close(scale.texture_pixel.x, 2.0 / 1600.0));
+    }
+}
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{font::Font, vectors::{Vec2f, Vec4f}};
+
+pub fn default_text_color() -> Vec4f {
+    Vec4f::new([1.0, 1.0, 1.0, 1.0])
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UiText {
+    pub content: String,
+    // name of the font, its atlas texture is named the same so materials can attach it
+    pub font: String,
+    // height of an em in the units of the element's width
+    pub size: f32,
+    #[serde(default = "default_text_color")]
+    pub color: Vec4f,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphQuad {
+    pub min: Vec2f,
+    pub max: Vec2f,
+    pub uv_min: Vec2f,
+    pub uv_max: Vec2f,
+}
+
+struct Layout<'a> {
+    font: &'a Font,
+    size: f32,
+    pen: f32,
+    line: usize,
+    previous: Option<char>,
+    quads: Vec<GlyphQuad>,
+}
+
+impl Layout<'_> {
+    fn advance(&self, previous: Option<char>, character: char) -> f32 {
+        let kerning = previous.map_or(0.0, |x| self.font.kerning(x, character));
+        (kerning + self.font.glyph(character).advance) * self.size
+    }
+
+    fn width(&self, word: &str) -> f32 {
+        let mut previous = self.previous;
+        let mut width = 0.0;
+        for character in word.chars() {
+            width += self.advance(previous, character);
+            previous = Some(character);
+        }
+        width
+    }
+
+    fn new_line(&mut self) {
+        self.pen = 0.0;
+        self.line += 1;
+        self.previous = None;
+    }
+
+    fn place(&mut self, character: char) {
+        if let Some(previous) = self.previous {
+            self.pen += self.font.kerning(previous, character) * self.size;
+        }
+
+        let glyph = self.font.glyph(character);
+        if glyph.size.x > 0.0 && glyph.size.y > 0.0 {
+            let baseline = (self.font.ascent + self.line as f32 * self.font.line_height) * self.size;
+            let min = Vec2f::new([
+                self.pen + glyph.offset.x * self.size,
+                baseline - (glyph.offset.y + glyph.size.y) * self.size,
+            ]);
+            self.quads.push(GlyphQuad { min, max: min + glyph.size * self.size, uv_min: glyph.uv_min, uv_max: glyph.uv_max });
+        }
+
+        self.pen += glyph.advance * self.size;
+        self.previous = Some(character);
+    }
+}
+
+// lays `content` out from the top left corner with y down, in the units of `size`,
+// words that would cross `max_width` go to the next line and words longer than a line are broken
+pub fn layout_text(font: &Font, content: &str, size: f32, max_width: f32) -> Vec<GlyphQuad> {
+    let mut layout = Layout { font, size, pen: 0.0, line: 0, previous: None, quads: Vec::new() };
+
+    for (i, paragraph) in content.lines().enumerate() {
+        if i > 0 {
+            layout.new_line();
+        }
+        for word in paragraph.split_inclusive(' ') {
+            if layout.pen > 0.0 && layout.pen + layout.width(word.trim_end_matches(' ')) > max_width {
+                layout.new_line();
+            }
+            for character in word.chars() {
+                let overflows = layout.pen + layout.advance(layout.previous, character) > max_width;
+                if character != ' ' && layout.pen > 0.0 && overflows {
+                    layout.new_line();
+                }
+                layout.place(character);
+            }
+        }
+    }
+
+    layout.quads
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::types::{font::{Font, Glyph}, vectors::Vec2f};
+
+    use super::layout_text;
+
+    fn glyph(width: f32, uv: f32) -> Glyph {
+        Glyph {
+            uv_min: Vec2f::new([uv, uv]),
+            uv_max: Vec2f::new([uv + 0.1, uv + 0.1]),
+            size: Vec2f::new([width, if width > 0.0 { 0.6 } else { 0.0 }]),
+            offset: Vec2f::new([0.05, 0.0]),
+            advance: 0.5,
+        }
+    }
+
+    // monospace, every letter is 0.4 wide and advances by 0.5
+    fn font() -> Font {
+        let mut glyphs: HashMap<char, Glyph> = ('a'..='z').chain('A'..='Z').map(|x| (x, glyph(0.4, 0.0))).collect();
+        glyphs.insert(' ', glyph(0.0, 0.0));
+        Font {
+            name: "test.ttf".to_string(),
+            atlas: "test.ttf".to_string(),
+            ascent: 0.8,
+            line_height: 1.0,
+            glyphs,
+            kerning: HashMap::from([(('A', 'V'), -0.1)]),
+            replacement: glyph(0.4, 0.9),
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_wraps_words_at_width() {
+        let quads = layout_text(&font(), "aaa bbb ccc", 1.0, 4.0);
+        assert_eq!(quads.len(), 9);
+        assert!(close(quads[3].min.x, 2.05));
+        assert!(close(quads[5].min.y, quads[0].min.y));
+        assert!(close(quads[6].min.x, 0.05));
+        assert!(close(quads[6].min.y, quads[0].min.y + 1.0));
+        assert!(close(quads[0].min.y, 0.2));
+    }
+
+    #[test]
+    fn test_breaks_long_words_and_newlines() {
+        let quads = layout_text(&font(), "aaaaaaaaaa\nb", 2.0, 4.0);
+        let lines: Vec<f32> = quads.iter().map(|x| x.min.y).collect();
+        assert!(close(lines[3], lines[0]));
+        assert!(close(lines[4], lines[0] + 2.0));
+        assert!(close(lines[8], lines[0] + 4.0));
+        assert!(close(lines[10], lines[0] + 6.0));
+        assert!(quads.iter().all(|x| x.max.x <= 4.0));
+    }
+
+    #[test]
+    fn test_applies_kerning() {
+        let kerned = layout_text(&font(), "AV", 1.0, 10.0);
+        let plain = layout_text(&font(), "AA", 1.0, 10.0);
+        assert!(close(kerned[1].min.x, 0.45));
+        assert!(close(plain[1].min.x, 0.55));
+    }
+
+    #[test]
+    fn test_unknown_glyphs_use_replacement() {
+        let quads = layout_text(&font(), "héllo 日本 🙂", 1.0, 100.0);
+        assert_eq!(quads.len(), 8);
+        let replaced: Vec<usize> = (0..quads.len()).filter(|x| close(quads[*x].uv_min.x, 0.9)).collect();
+        assert_eq!(replaced, vec![1, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{asset_library::AssetLibrary, types::vectors::Vec2f};
+
+use super::{
+    ui_layout::{ui_visible, UiAnchors, UiElement, UiElementType, UiRect},
+    ui_scale::UiScale,
+    ui_text::UiText,
+};
+
+// between the cursor and the tooltip, in screen pixels
+const CURSOR_GAP: f32 = 16.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UiTooltipContent {
+    Text(UiText),
+    // only the material, like an element of UiElementType::None
+    Sprite,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UiTooltip {
+    pub content: UiTooltipContent,
+    pub material: Uuid,
+    // in layout units, like an element's size
+    pub width: f32,
+    pub height: f32,
+    // seconds of hovering before it shows
+    pub delay: f32,
+}
+
+// below and right of the cursor, flipped to the other side of it at the right and bottom edges
+// and kept on the screen, tooltips larger than the screen stick to the top left corner
+pub fn tooltip_rect(cursor: Vec2f, size: Vec2f, gap: Vec2f) -> UiRect {
+    let place = |cursor: f32, size: f32, gap: f32| {
+        let after = cursor + gap;
+        let before = cursor - gap - size;
+        let start = if after + size > 1.0 && before >= -1.0 { before } else { after };
+        start.min(1.0 - size).max(-1.0)
+    };
+    let min = Vec2f::new([place(cursor.x, size.x, gap.x), place(cursor.y, size.y, gap.y)]);
+    UiRect { min, max: min + size }
+}
+
+// the element the cursor rests on and the tooltip spawned for it
+#[derive(Debug, Default)]
+pub(crate) struct UiTooltips {
+    // with the time the hover started
+    hovered: Option<(Uuid, f64)>,
+    shown: Option<Uuid>,
+}
+
+impl UiTooltips {
+    pub(crate) fn update(&mut self, assets: &mut AssetLibrary, cursor: Vec2f, time: f64, scale: &UiScale) {
+        let target = assets.ui.iter()
+            .filter(|(uuid, x)| x.tooltip.is_some() && x.hit(cursor) && ui_visible(&assets.ui, **uuid))
+            .max_by_key(|(_, x)| x.depth())
+            .map(|(uuid, _)| *uuid);
+        if self.hovered.map(|x| x.0) != target {
+            self.hovered = target.map(|x| (x, time));
+            if let Some(shown) = self.shown.take() {
+                assets.ui_remove(shown);
+            }
+        }
+
+        let Some((hovered, start)) = self.hovered else {
+            return;
+        };
+        let tooltip = assets.ui[&hovered].tooltip.clone().unwrap();
+        if time - start < tooltip.delay as f64 {
+            return;
+        }
+
+        // pinned to the top left corner of the screen and moved along with the cursor
+        let rect = tooltip_rect(cursor, Vec2f::new([tooltip.width, tooltip.height]) * scale.unit, scale.texture_pixel * CURSOR_GAP);
+        let corner = Vec2f::new([1.0, 1.0]);
+        let anchors = UiAnchors {
+            anchor_min: Vec2f::new([0.0, 0.0]),
+            anchor_max: Vec2f::new([0.0, 0.0]),
+            offset_min: (rect.min + corner) / scale.unit,
+            offset_max: (rect.max + corner) / scale.unit,
+        };
+        match self.shown.and_then(|x| assets.ui.get_mut(&x)) {
+            Some(element) => element.set_anchors(anchors),
+            None => {
+                let element_type = match tooltip.content {
+                    UiTooltipContent::Text(text) => UiElementType::Text(text),
+                    UiTooltipContent::Sprite => UiElementType::None,
+                };
+                let name = format!("{}_tooltip", assets.ui[&hovered].name);
+                let mut element = UiElement::with_anchors(&name, element_type, tooltip.material, anchors);
+                element.overlay = true;
+                self.shown = Some(assets.ui_add(element));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{
+        asset_library::AssetLibrary,
+        types::vectors::Vec2f,
+        ui::{
+            ui_layout::{layout_ui, Anchor, UiElement, UiElementType},
+            ui_scale::UiScaleMode,
+        },
+    };
+
+    use super::{tooltip_rect, UiTooltip, UiTooltipContent, UiTooltips};
+
+    fn close(a: Vec2f, b: [f32; 2]) -> bool {
+        (a.x - b[0]).abs() < 1e-5 && (a.y - b[1]).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_tooltip_stays_on_screen_at_corners() {
+        let size = Vec2f::new([0.5, 0.2]);
+        let gap = Vec2f::new([0.1, 0.1]);
+
+        let top_left = tooltip_rect(Vec2f::new([-1.0, -1.0]), size, gap);
+        assert!(close(top_left.min, [-0.9, -0.9]));
+        // flipped above and left of the cursor
+        let bottom_right = tooltip_rect(Vec2f::new([1.0, 1.0]), size, gap);
+        assert!(close(bottom_right.min, [0.4, 0.7]));
+        assert!(close(bottom_right.max, [0.9, 0.9]));
+        let top_right = tooltip_rect(Vec2f::new([0.95, -1.0]), size, gap);
+        assert!(close(top_right.min, [0.35, -0.9]));
+        let bottom_left = tooltip_rect(Vec2f::new([-1.0, 0.95]), size, gap);
+        assert!(close(bottom_left.min, [-0.9, 0.65]));
+
+        // no room on either side, pushed against the edge
+        let wide = tooltip_rect(Vec2f::new([0.0, 0.0]), Vec2f::new([1.9, 0.2]), gap);
+        assert!(close(wide.min, [-0.9, 0.1]));
+        let huge = tooltip_rect(Vec2f::new([0.5, 0.5]), Vec2f::new([3.0, 3.0]), gap);
+        assert!(close(huge.min, [-1.0, -1.0]));
+    }
+
+    #[test]
+    fn test_tooltip_shows_after_delay_and_hides_on_leave() {
+        let scale = UiScaleMode::Ndc.scale(1000, 1000, 1.0);
+        let mut assets = AssetLibrary::default();
+        let mut icon = UiElement::new("icon", UiElementType::None, Uuid::nil(), Anchor::Center, Vec2f::new([0.0, 0.0]), 0.2, 0.2);
+        icon.tooltip = Some(UiTooltip {
+            content: UiTooltipContent::Sprite,
+            material: Uuid::nil(),
+            width: 0.4,
+            height: 0.1,
+            delay: 0.5,
+        });
+        assets.ui_add(icon);
+        layout_ui(&mut assets.ui, &scale);
+
+        let mut tooltips = UiTooltips::default();
+        let over = Vec2f::new([0.05, 0.0]);
+        tooltips.update(&mut assets, over, 10.0, &scale);
+        tooltips.update(&mut assets, over, 10.4, &scale);
+        assert_eq!(assets.ui.len(), 1);
+
+        tooltips.update(&mut assets, over, 10.5, &scale);
+        assert_eq!(assets.ui.len(), 2);
+        let tooltip = tooltips.shown.unwrap();
+        assert!(assets.ui[&tooltip].overlay);
+        assert!(assets.ui[&tooltip].name == "icon_tooltip");
+        // laid out with everything else next frame
+        layout_ui(&mut assets.ui, &scale);
+        let rect = assets.ui[&tooltip].rect().unwrap();
+        assert!(close(rect.min, [0.05 + 0.032, 0.032]));
+        assert!(close(rect.size(), [0.4, 0.1]));
+
+        tooltips.update(&mut assets, Vec2f::new([0.5, 0.5]), 10.6, &scale);
+        assert_eq!(assets.ui.len(), 1);
+        assert!(tooltips.shown.is_none() && tooltips.hovered.is_none());
+    }
+}
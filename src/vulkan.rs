@@ -1,4 +1,6 @@
 
 pub mod context;
+pub mod debug;
 pub mod memory;
+pub mod transfer;
 
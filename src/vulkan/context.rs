@@ -1,9 +1,21 @@
-use std::sync::Arc;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-use log::debug;
-use vulkano::{device::{physical::{PhysicalDevice, PhysicalDeviceType}, Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags}, instance::{Instance, InstanceCreateInfo}, swapchain::Surface, VulkanLibrary};
+use log::{debug, info};
+use vulkano::{device::{physical::{PhysicalDevice, PhysicalDeviceType}, Device, DeviceCreateInfo, DeviceExtensions, DeviceOwned, Features, Queue, QueueCreateInfo, QueueFlags}, image::Image, instance::{debug::DebugUtilsMessenger, Instance, InstanceCreateInfo, InstanceExtensions}, swapchain::Surface, Validated, VulkanError, VulkanLibrary, VulkanObject};
 
-use crate::rendering::Window;
+use crate::{
+    engine_config::{EngineConfig, GpuPreference},
+    engine_error::EngineError,
+    rendering::Window,
+};
+
+use super::debug::{create_messenger, validation_layers};
 
 pub struct VulkanContext {
     pub library: Arc<VulkanLibrary>,
@@ -13,6 +25,94 @@ pub struct VulkanContext {
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
     pub transfer_queue: Arc<Queue>,
+    // logs validation messages while it's alive
+    debug_messenger: Option<DebugUtilsMessenger>,
+    // the frame validation messages are tagged with
+    debug_frame: Arc<AtomicU64>,
+}
+
+// an enumerated device as selection sees it
+#[derive(Debug, Clone)]
+struct Candidate {
+    // position in enumerate_physical_devices, what GpuPreference::ByIndex refers to
+    index: usize,
+    name: String,
+    device_type: PhysicalDeviceType,
+    api_version: String,
+    // why the engine can't render with it
+    unusable: Option<String>,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({:?}, vulkan {})", self.index, self.name, self.device_type, self.api_version)?;
+        match &self.unusable {
+            Some(reason) => write!(f, ", {}", reason),
+            None => Ok(()),
+        }
+    }
+}
+
+// lower is picked first
+fn type_rank(device_type: PhysicalDeviceType, preference: &GpuPreference) -> u32 {
+    let integrated_first = *preference == GpuPreference::PreferIntegrated;
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu if integrated_first => 1,
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu if integrated_first => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        _ => 4,
+    }
+}
+
+// position in `candidates` of the device to render with, the first one enumerated among equally good ones
+fn choose_device(candidates: &[Candidate], preference: &GpuPreference) -> Result<usize, EngineError> {
+    let usable = |x: &&Candidate| x.unusable.is_none();
+    let requested = match preference {
+        GpuPreference::ByName(name) => {
+            let name = name.to_lowercase();
+            let matching: Vec<&Candidate> = candidates.iter().filter(|x| x.name.to_lowercase().contains(&name)).collect();
+            matching.iter().copied().find(|x| x.unusable.is_none()).or(matching.first().copied())
+        }
+        GpuPreference::ByIndex(index) => candidates.get(*index),
+        _ => {
+            return candidates
+                .iter()
+                .filter(usable)
+                .min_by_key(|x| (type_rank(x.device_type, preference), x.index))
+                .map(|x| x.index)
+                .ok_or(EngineError::NoSuitableDevice);
+        }
+    };
+    match requested {
+        Some(candidate) if candidate.unusable.is_none() => Ok(candidate.index),
+        _ => Err(EngineError::GpuUnavailable {
+            requested: format!("{:?}", preference),
+            candidates: candidates.iter().map(|x| x.to_string()).collect(),
+        }),
+    }
+}
+
+// the graphics queue family that presents to `surface` and a separate transfer one if there is one
+fn select_queues(physical_device: &PhysicalDevice, surface: &Surface) -> Option<(u32, Option<u32>)> {
+    let families = physical_device.queue_family_properties();
+    let graphics = families
+        .iter()
+        .enumerate()
+        .position(|(i, q)| {
+            q.queue_flags.contains(QueueFlags::GRAPHICS)
+                && physical_device.surface_support(i as u32, surface).unwrap_or(false)
+        })
+        .map(|q| q as u32)?;
+    // devices without one transfer on the graphics queue
+    let transfer = families
+        .iter()
+        .enumerate()
+        .position(|(i, q)| q.queue_flags.contains(QueueFlags::TRANSFER) && i as u32 != graphics)
+        .map(|q| q as u32);
+    Some((graphics, transfer))
 }
 
 fn select_physical_device(
@@ -20,73 +120,91 @@ fn select_physical_device(
     surface: Arc<Surface>,
     device_extensions: &DeviceExtensions,
     features: &Features,
-) -> (Arc<PhysicalDevice>, u32, Option<u32>) {
-    instance
+    preference: &GpuPreference,
+) -> Result<(Arc<PhysicalDevice>, u32, Option<u32>), EngineError> {
+    let devices: Vec<Arc<PhysicalDevice>> = instance
         .enumerate_physical_devices()
-        .expect("failed to enumerate physical devices")
-        .filter(|p| p.supported_extensions().contains(device_extensions))
-        .filter(|p| p.supported_features().contains(features))
-        .filter_map(|p| {
-            let gq = p
-                .queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    q.queue_flags.contains(QueueFlags::GRAPHICS)
-                        && p.surface_support(i as u32, &surface.clone())
-                            .unwrap_or(false)
-                })
-                .map(|q| q as u32);
-            let tq = p
-                .queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    q.queue_flags.contains(QueueFlags::TRANSFER)
-                        && i as u32 != gq.expect("No graphics queue")
-                })
-                .map(|q| q as u32);
+        .map_err(|e| EngineError::VulkanInit { source: Box::new(e) })?
+        .collect();
 
-            debug!("Selected queues main:{:?}, transfer:{:?}", gq, tq);
+    let mut queues = Vec::with_capacity(devices.len());
+    let mut candidates = Vec::with_capacity(devices.len());
+    for (index, p) in devices.iter().enumerate() {
+        let missing_extensions = device_extensions.difference(p.supported_extensions());
+        let missing_features = features.difference(p.supported_features());
+        let device_queues = select_queues(p, &surface);
+        let unusable = if missing_extensions != DeviceExtensions::empty() {
+            Some(format!("missing extensions {:?}", missing_extensions))
+        } else if missing_features != Features::empty() {
+            Some(format!("missing features {:?}", missing_features))
+        } else if device_queues.is_none() {
+            Some(String::from("no graphics queue that can present to the window"))
+        } else {
+            None
+        };
+        let properties = p.properties();
+        let candidate = Candidate {
+            index,
+            name: properties.device_name.clone(),
+            device_type: properties.device_type,
+            api_version: properties.api_version.to_string(),
+            unusable,
+        };
+        debug!("Found device {}", candidate);
+        queues.push(device_queues);
+        candidates.push(candidate);
+    }
 
-            gq.map(|gq| (p, gq, tq))
-        })
-        .min_by_key(|(p, _, _)| match p.properties().device_type {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            _ => 4,
-        })
-        .expect("no device available")
+    let index = choose_device(&candidates, preference)?;
+    let (graphics, transfer) = queues[index].expect("Chose a device without a graphics queue");
+    info!("Using device {}", candidates[index]);
+    debug!("Selected queues main:{:?}, transfer:{:?}", graphics, transfer);
+    Ok((devices[index].clone(), graphics, transfer))
 }
 
 impl VulkanContext {
-    pub fn new(window: &Window) -> VulkanContext {
+    // renders with the device config.gpu or the OXIDE_GPU environment variable asks for,
+    // `shader_features` are the extra features the asset pack's shader stages need
+    pub fn new(window: &Window, config: &EngineConfig, shader_features: Features) -> Result<VulkanContext, EngineError> {
         let features = Features {
             shader_draw_parameters: true,
             sampler_anisotropy: true,
             fill_mode_non_solid: true,
             ..Features::empty()
-        };
+        }.union(&shader_features);
         let extensions = DeviceExtensions {
             khr_swapchain: true,
             ..Default::default()
         };
 
-        let library = VulkanLibrary::new().expect("Vulkan library not found");
+        let vulkan_init = |e: Validated<VulkanError>| EngineError::VulkanInit { source: Box::new(e) };
+        let library = VulkanLibrary::new().map_err(|e| EngineError::VulkanInit { source: Box::new(e) })?;
         let instance = Instance::new(
             library.clone(),
             InstanceCreateInfo {
-                enabled_extensions: Surface::required_extensions(&window.window_handle),
+                // debug utils whenever the loader has them, for object names in RenderDoc captures
+                enabled_extensions: Surface::required_extensions(&window.window_handle).union(&InstanceExtensions {
+                    ext_debug_utils: library.supported_extensions().ext_debug_utils,
+                    ..InstanceExtensions::empty()
+                }),
+                enabled_layers: validation_layers(&library, config.vulkan_validation),
                 ..Default::default()
             },
         )
-        .unwrap();
+        .map_err(vulkan_init)?;
+        let debug_frame = Arc::new(AtomicU64::new(0));
+        let debug_messenger = create_messenger(&instance, config.vulkan_validation, debug_frame.clone());
 
-        let surface = Surface::from_window(instance.clone(), window.window_handle.clone()).unwrap();
+        let surface = Surface::from_window(instance.clone(), window.window_handle.clone())
+            .map_err(|e| EngineError::SurfaceCreation { source: Box::new(e) })?;
         let (physical_device, queue_family_index, transfer_family_index) =
-            select_physical_device(instance.clone(), surface.clone(), &extensions, &features);
+            select_physical_device(
+            instance.clone(),
+            surface.clone(),
+            &extensions,
+            &features,
+            &config.gpu.with_env_override(),
+        )?;
 
         debug!("Vulkan version: {}", instance.api_version());
 
@@ -94,14 +212,14 @@ impl VulkanContext {
             physical_device.clone(),
             DeviceCreateInfo {
                 queue_create_infos: {
-                    if transfer_family_index.is_some() {
+                    if let Some(transfer_family_index) = transfer_family_index {
                         vec![
                             QueueCreateInfo {
                                 queue_family_index,
                                 ..Default::default()
                             },
                             QueueCreateInfo {
-                                queue_family_index: transfer_family_index.unwrap(),
+                                queue_family_index: transfer_family_index,
                                 ..Default::default()
                             },
                         ]
@@ -117,19 +235,96 @@ impl VulkanContext {
                 ..Default::default()
             },
         )
-        .unwrap();
+        .map_err(vulkan_init)?;
         let queue = queues.next().unwrap();
         let transfer_queue = queues.next().unwrap_or(queue.clone());
 
-        VulkanContext {
+        let context = VulkanContext {
             library, 
             instance,
             physical_device,
             device,
             render_surface: surface,
             queue,
-            transfer_queue
+            transfer_queue,
+            debug_messenger,
+            debug_frame,
+        };
+        context.set_object_name(&*context.queue, "graphics queue");
+        if !Arc::ptr_eq(&context.transfer_queue, &context.queue) {
+            context.set_object_name(&*context.transfer_queue, "transfer queue");
+        }
+        Ok(context)
+    }
+
+    // what validation messages from now on are tagged with
+    pub fn set_debug_frame(&self, frame: u64) {
+        self.debug_frame.store(frame, Ordering::Relaxed);
+    }
+
+    pub fn validation_enabled(&self) -> bool {
+        self.debug_messenger.is_some()
+    }
+
+    // shows up in validation messages and graphics debuggers, does nothing without debug utils
+    pub fn set_object_name<T: VulkanObject + DeviceOwned>(&self, object: &T, name: &str) {
+        if !self.instance.enabled_extensions().ext_debug_utils {
+            return;
+        }
+        if let Err(e) = self.device.set_debug_utils_object_name(object, Some(name)) {
+            debug!("Failed to name {}: {:?}", name, e);
         }
+    }
+
+    pub fn name_swapchain_images(&self, images: &[Arc<Image>]) {
+        for (i, image) in images.iter().enumerate() {
+            self.set_object_name(&**image, &format!("swapchain image {}", i));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::device::physical::PhysicalDeviceType;
+
+    use crate::{engine_config::GpuPreference, engine_error::EngineError};
+
+    use super::{choose_device, Candidate};
+
+    fn candidate(index: usize, name: &str, device_type: PhysicalDeviceType, usable: bool) -> Candidate {
+        Candidate {
+            index,
+            name: name.to_string(),
+            device_type,
+            api_version: String::from("1.3.0"),
+            unusable: (!usable).then(|| String::from("missing extensions [khr_swapchain]")),
+        }
+    }
+
+    #[test]
+    fn test_choose_device() {
+        let candidates = [
+            candidate(0, "llvmpipe", PhysicalDeviceType::Cpu, true),
+            candidate(1, "Intel UHD Graphics", PhysicalDeviceType::IntegratedGpu, true),
+            candidate(2, "NVIDIA GeForce RTX 3060", PhysicalDeviceType::DiscreteGpu, true),
+            candidate(3, "NVIDIA GeForce RTX 3060", PhysicalDeviceType::DiscreteGpu, true),
+            candidate(4, "Broken GPU", PhysicalDeviceType::DiscreteGpu, false),
+        ];
+        // the first of two identical discrete gpus
+        assert_eq!(choose_device(&candidates, &GpuPreference::Default).unwrap(), 2);
+        assert_eq!(choose_device(&candidates, &GpuPreference::PreferDiscrete).unwrap(), 2);
+        assert_eq!(choose_device(&candidates, &GpuPreference::PreferIntegrated).unwrap(), 1);
+        assert_eq!(choose_device(&candidates, &GpuPreference::ByIndex(3)).unwrap(), 3);
+        assert_eq!(choose_device(&candidates, &GpuPreference::ByName(String::from("rtx"))).unwrap(), 2);
 
+        // asking for one that can't render lists what's there
+        let error = choose_device(&candidates, &GpuPreference::ByName(String::from("broken"))).unwrap_err();
+        assert!(matches!(&error, EngineError::GpuUnavailable { candidates, .. } if candidates.len() == 5));
+        assert!(error.to_string().contains("4: Broken GPU (DiscreteGpu, vulkan 1.3.0), missing extensions"));
+        assert!(matches!(
+            choose_device(&candidates, &GpuPreference::ByIndex(7)),
+            Err(EngineError::GpuUnavailable { .. })
+        ));
+        assert!(matches!(choose_device(&candidates[4..], &GpuPreference::Default), Err(EngineError::NoSuitableDevice)));
     }
 }
@@ -0,0 +1,138 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use log::{warn, Level};
+use vulkano::{
+    instance::{
+        debug::{
+            DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger, DebugUtilsMessengerCallback,
+            DebugUtilsMessengerCreateInfo,
+        },
+        Instance,
+    },
+    VulkanLibrary,
+};
+
+use crate::engine_config::ValidationMode;
+
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+// the layers to enable for `mode`, none when the validation layer isn't installed
+pub(crate) fn validation_layers(library: &VulkanLibrary, mode: ValidationMode) -> Vec<String> {
+    if mode == ValidationMode::Off {
+        return Vec::new();
+    }
+    let present = library
+        .layer_properties()
+        .map(|mut layers| layers.any(|x| x.name() == VALIDATION_LAYER))
+        .unwrap_or(false);
+    if !present {
+        warn!("Vulkan validation requested but {} isn't installed, running without it", VALIDATION_LAYER);
+        return Vec::new();
+    }
+    vec![String::from(VALIDATION_LAYER)]
+}
+
+// severities passed on to the log, nothing with validation off
+fn message_severity(mode: ValidationMode) -> DebugUtilsMessageSeverity {
+    match mode {
+        ValidationMode::Off => DebugUtilsMessageSeverity::empty(),
+        ValidationMode::Standard => DebugUtilsMessageSeverity::ERROR | DebugUtilsMessageSeverity::WARNING,
+        ValidationMode::Verbose => {
+            DebugUtilsMessageSeverity::ERROR
+                | DebugUtilsMessageSeverity::WARNING
+                | DebugUtilsMessageSeverity::INFO
+                | DebugUtilsMessageSeverity::VERBOSE
+        }
+    }
+}
+
+fn log_level(severity: DebugUtilsMessageSeverity) -> Level {
+    if severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+        Level::Error
+    } else if severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+        Level::Warn
+    } else if severity.intersects(DebugUtilsMessageSeverity::INFO) {
+        Level::Debug
+    } else {
+        Level::Trace
+    }
+}
+
+fn message_kind(message_type: DebugUtilsMessageType) -> &'static str {
+    if message_type.intersects(DebugUtilsMessageType::VALIDATION) {
+        "validation"
+    } else if message_type.intersects(DebugUtilsMessageType::PERFORMANCE) {
+        "performance"
+    } else {
+        "general"
+    }
+}
+
+// routes the driver's and the layers' messages into the log under the "vulkan" target, tagged with the frame
+// stored in `frame`, None with validation off or without debug utils on the instance
+pub(crate) fn create_messenger(
+    instance: &Arc<Instance>,
+    mode: ValidationMode,
+    frame: Arc<AtomicU64>,
+) -> Option<DebugUtilsMessenger> {
+    if mode == ValidationMode::Off || !instance.enabled_extensions().ext_debug_utils {
+        return None;
+    }
+    // safe as long as the callback doesn't call into vulkan, it only logs
+    let callback = unsafe {
+        DebugUtilsMessengerCallback::new(move |severity, message_type, data| {
+            log::log!(
+                target: "vulkan",
+                log_level(severity),
+                "[frame {}] {} {}: {}",
+                frame.load(Ordering::Relaxed),
+                message_kind(message_type),
+                data.message_id_name.unwrap_or("message"),
+                data.message
+            );
+        })
+    };
+    let messenger = DebugUtilsMessenger::new(
+        instance.clone(),
+        DebugUtilsMessengerCreateInfo {
+            message_severity: message_severity(mode),
+            message_type: DebugUtilsMessageType::GENERAL
+                | DebugUtilsMessageType::VALIDATION
+                | DebugUtilsMessageType::PERFORMANCE,
+            ..DebugUtilsMessengerCreateInfo::user_callback(callback)
+        },
+    );
+    match messenger {
+        Ok(messenger) => Some(messenger),
+        Err(e) => {
+            warn!("Failed to create the vulkan debug messenger: {:?}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+    use vulkano::instance::debug::{DebugUtilsMessageSeverity, DebugUtilsMessageType};
+
+    use crate::engine_config::ValidationMode;
+
+    use super::{log_level, message_kind, message_severity};
+
+    #[test]
+    fn test_message_severity_mapping() {
+        assert!(message_severity(ValidationMode::Off).is_empty());
+        assert!(!message_severity(ValidationMode::Standard).intersects(DebugUtilsMessageSeverity::INFO));
+        assert!(message_severity(ValidationMode::Verbose).contains(DebugUtilsMessageSeverity::VERBOSE));
+
+        assert_eq!(log_level(DebugUtilsMessageSeverity::ERROR), Level::Error);
+        assert_eq!(log_level(DebugUtilsMessageSeverity::WARNING), Level::Warn);
+        assert_eq!(log_level(DebugUtilsMessageSeverity::INFO), Level::Debug);
+        assert_eq!(log_level(DebugUtilsMessageSeverity::VERBOSE), Level::Trace);
+        assert_eq!(message_kind(DebugUtilsMessageType::PERFORMANCE), "performance");
+    }
+}
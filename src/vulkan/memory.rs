@@ -2,12 +2,13 @@ use std::sync::Arc;
 
 use vulkano::{command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo}, descriptor_set::allocator::StandardDescriptorSetAllocator, memory::allocator::StandardMemoryAllocator};
 
-use super::context::VulkanContext;
+use super::{context::VulkanContext, transfer::TransferManager};
 
 pub struct MemoryAllocators {
     pub standard_memory_allocator: Arc<StandardMemoryAllocator>,
     pub command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     pub descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    pub transfer: Arc<TransferManager>,
 }
 
 impl MemoryAllocators {
@@ -25,7 +26,8 @@ impl MemoryAllocators {
         MemoryAllocators {
             standard_memory_allocator,
             command_buffer_allocator, 
-            descriptor_set_allocator
+            descriptor_set_allocator,
+            transfer: Arc::new(TransferManager::new(context))
         }
     }
 }
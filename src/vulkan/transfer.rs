@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{AllocateBufferError, Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo,
+        PrimaryAutoCommandBuffer,
+    },
+    device::{Device, Queue},
+    image::ImageCreateInfo,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::{now, GpuFuture, Sharing},
+    Validated,
+};
+
+use super::context::VulkanContext;
+
+pub type TransferCommandBuilder =
+    AutoCommandBufferBuilder<PrimaryAutoCommandBuffer<StandardCommandBufferAllocator>, StandardCommandBufferAllocator>;
+
+// records uploads on the transfer queue, when the device has no separate transfer family
+// everything runs on the graphics queue like before
+pub struct TransferManager {
+    device: Arc<Device>,
+    graphics_queue: Arc<Queue>,
+    transfer_queue: Arc<Queue>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl TransferManager {
+    pub fn new(context: &VulkanContext) -> TransferManager {
+        TransferManager {
+            device: context.device.clone(),
+            graphics_queue: context.queue.clone(),
+            transfer_queue: context.transfer_queue.clone(),
+            command_buffer_allocator: StandardCommandBufferAllocator::new(context.device.clone(), Default::default()),
+        }
+    }
+
+    pub fn is_dedicated(&self) -> bool {
+        self.graphics_queue.queue_family_index() != self.transfer_queue.queue_family_index()
+    }
+
+    // resources written on the transfer queue and read on the graphics queue are shared by both families,
+    // vulkano doesn't record ownership transfer barriers for us
+    fn sharing<T: FromIterator<u32> + IntoIterator<Item = u32>>(&self) -> Sharing<T> {
+        match self.is_dedicated() {
+            true => Sharing::Concurrent(
+                [self.graphics_queue.queue_family_index(), self.transfer_queue.queue_family_index()]
+                    .into_iter()
+                    .collect(),
+            ),
+            false => Sharing::Exclusive,
+        }
+    }
+
+    pub fn buffer_info(&self, usage: BufferUsage) -> BufferCreateInfo {
+        BufferCreateInfo {
+            usage,
+            sharing: self.sharing(),
+            ..Default::default()
+        }
+    }
+
+    pub fn image_info(&self, create_info: ImageCreateInfo) -> ImageCreateInfo {
+        ImageCreateInfo {
+            sharing: self.sharing(),
+            ..create_info
+        }
+    }
+
+    fn builder(&self, queue: &Queue) -> TransferCommandBuilder {
+        AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap()
+    }
+
+    // copies only, transfer families can't blit or draw
+    pub fn copy_builder(&self) -> TransferCommandBuilder {
+        self.builder(&self.transfer_queue)
+    }
+
+    // for the part of an upload that needs the graphics queue, like blitting mip levels
+    pub fn graphics_builder(&self) -> TransferCommandBuilder {
+        self.builder(&self.graphics_queue)
+    }
+
+    // runs the copies on the transfer queue and `graphics` on the graphics queue once a semaphore says
+    // the copies are done, then waits for the fence of the whole batch
+    pub fn submit(&self, copies: TransferCommandBuilder, graphics: Option<TransferCommandBuilder>) {
+        let future = now(self.device.clone())
+            .then_execute(self.transfer_queue.clone(), copies.build().unwrap())
+            .unwrap();
+
+        match graphics {
+            Some(graphics) => future
+                .then_signal_semaphore()
+                .then_execute(self.graphics_queue.clone(), graphics.build().unwrap())
+                .unwrap()
+                .then_signal_fence_and_flush()
+                .unwrap()
+                .wait(None)
+                .unwrap(),
+            None => future.then_signal_fence_and_flush().unwrap().wait(None).unwrap(),
+        }
+    }
+
+    pub fn staging_buffer<T, I>(
+        &self,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        data: I,
+    ) -> Result<Subbuffer<[T]>, Validated<AllocateBufferError>>
+    where
+        T: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Buffer::from_iter(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            data,
+        )
+    }
+
+    // a device local buffer filled through a staging buffer, `usage` gets TRANSFER_DST on top
+    // so parts of it can be copied over later
+    pub fn upload_buffer<T, I>(
+        &self,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        usage: BufferUsage,
+        data: I,
+    ) -> Result<Subbuffer<[T]>, Validated<AllocateBufferError>>
+    where
+        T: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let staging = self.staging_buffer(memory_allocator.clone(), data)?;
+        let buffer = Buffer::new_slice::<T>(
+            memory_allocator,
+            self.buffer_info(usage | BufferUsage::TRANSFER_DST),
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            staging.len(),
+        )?;
+
+        let mut copies = self.copy_builder();
+        copies.copy_buffer(CopyBufferInfo::buffers(staging, buffer.clone())).unwrap();
+        self.submit(copies, None);
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,110 @@
+use std::{cell::Cell, rc::Rc};
+
+use oxide_engine::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    engine_config::HeadlessConfig,
+    hecs::Entity,
+    physics::{
+        collider::{Collider, ColliderShape},
+        rigidbody::{BodyType, Rigidbody},
+    },
+    run_headless,
+    state::State,
+    types::{position::Position, quaternion::Quat, transform::Transform, vectors::{Vec3d, Vec3f}},
+};
+
+fn transform(x: f64, y: f64) -> Transform {
+    Transform::new(Position::from(Vec3d::new([x, y, 0.0])), Vec3f::new([1.0, 1.0, 1.0]), Quat::new([1.0, 0.0, 0.0, 0.0]))
+}
+
+fn position(world: &World, entity: Entity) -> Vec3d {
+    Position::default().offset_to(&world.entities.borrow().get::<&Transform>(entity).unwrap().position)
+}
+
+// counts frames and checks the clock the systems see
+struct FrameCounter {
+    frames: Rc<Cell<u64>>,
+}
+
+impl System for FrameCounter {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        assert!(state.is_headless());
+    }
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        self.frames.set(self.frames.get() + 1);
+        assert_eq!(state.time_struct.delta, 1.0 / 60.0);
+        assert!((state.time_struct.elapsed - self.frames.get() as f64 / 60.0).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_headless_physics_scene() {
+    let mut world = World::new();
+    let frames = Rc::new(Cell::new(0));
+    world.add_system(FrameCounter { frames: frames.clone() });
+
+    let (ball, slider) = {
+        let mut entities = world.entities.borrow_mut();
+        // a huge static sphere whose top is at y = 0
+        let mut floor = Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        floor.body_type = BodyType::Static;
+        entities.spawn((transform(0.0, -1000.0), floor, Collider::new(ColliderShape::Sphere(1000.0))));
+        let ball = entities.spawn((
+            transform(0.0, 1.0),
+            Rigidbody::new(1.0, Vec3f::new([0.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0])),
+            Collider::new(ColliderShape::Sphere(0.5)),
+        ));
+        let mut rigidbody = Rigidbody::new(1.0, Vec3f::new([3.0, 0.0, 0.0]), Vec3f::new([0.0, 0.0, 0.0]));
+        rigidbody.use_gravity = false;
+        let slider = entities.spawn((transform(-10.0, 50.0), rigidbody));
+        (ball, slider)
+    };
+
+    let config = HeadlessConfig { max_frames: Some(100), fixed_delta: 1.0 / 60.0 };
+    let world = run_headless(world, AssetLibrary::default(), config);
+
+    assert_eq!(frames.get(), 100);
+    // fell half a meter and came to rest on the floor
+    let ball = position(&world, ball);
+    assert!((ball.y - 0.5).abs() < 0.01, "ball at {}", ball.y);
+    assert!(ball.x.abs() < 1e-6);
+    // a fixed step every frame, 100 of them at 3 units a second
+    let slider = position(&world, slider);
+    assert!((slider.x + 5.0).abs() < 1e-4, "slider at {}", slider.x);
+    assert_eq!(slider.y, 50.0);
+}
+
+// asks to quit in on_start or once it has seen `frames` updates
+struct Quitter {
+    at_start: bool,
+    frames: u64,
+    seen: Rc<Cell<u64>>,
+}
+
+impl System for Quitter {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        if self.at_start {
+            state.request_exit();
+        }
+    }
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        self.seen.set(self.seen.get() + 1);
+        if self.seen.get() == self.frames {
+            state.request_exit();
+        }
+    }
+}
+
+#[test]
+fn test_exit_requested() {
+    for (at_start, expected) in [(false, 3), (true, 0)] {
+        let mut world = World::new();
+        let seen = Rc::new(Cell::new(0));
+        world.add_system(Quitter { at_start, frames: 3, seen: seen.clone() });
+        run_headless(world, AssetLibrary::default(), HeadlessConfig { max_frames: Some(100), ..Default::default() });
+        assert_eq!(seen.get(), expected);
+    }
+}